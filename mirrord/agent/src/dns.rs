@@ -1,10 +1,25 @@
-use std::{future, path::PathBuf, time::Duration};
+use std::{
+    collections::HashMap,
+    future,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
 
 use futures::{stream::FuturesOrdered, StreamExt};
-use hickory_resolver::{system_conf::parse_resolv_conf, AsyncResolver, Hosts};
+use hickory_resolver::{
+    config::{LookupIpStrategy, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    proto::rr::RecordType,
+    system_conf::parse_resolv_conf,
+    AsyncResolver, Hosts, TokioAsyncResolver,
+};
 use mirrord_protocol::{
-    dns::{DnsLookup, GetAddrInfoRequest, GetAddrInfoResponse},
-    DnsLookupError, RemoteResult, ResolveErrorKindInternal, ResponseError,
+    dns::{
+        AddressFamily, DnsCacheStats, DnsLookup, DnsQueryRequest, DnsQueryResponse, DnsRecord,
+        GetAddrInfoRequestV2, GetAddrInfoResponse, ReverseLookupRequest, ReverseLookupResponse,
+    },
+    DaemonMessage, DnsLookupError, RemoteResult, ResolveErrorKindInternal, ResponseError,
 };
 use tokio::{
     fs,
@@ -21,10 +36,130 @@ use crate::{
     watched_task::TaskStatus,
 };
 
+/// Maximum number of answers kept in the [`DnsCache`].
+const MAX_CACHED_ANSWERS: usize = 1024;
+
+/// A request handled by the [`DnsWorker`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum DnsRequest {
+    AddrInfo(GetAddrInfoRequestV2),
+    Query(DnsQueryRequest),
+    ReverseLookup(ReverseLookupRequest),
+    CacheStats,
+}
+
+/// Result of a [`DnsRequest`], of the matching variant.
+#[derive(Debug, Clone)]
+enum DnsResponse {
+    AddrInfo(RemoteResult<DnsLookup>),
+    Query(RemoteResult<Vec<DnsRecord>>),
+    ReverseLookup(RemoteResult<Vec<String>>),
+    CacheStats(DnsCacheStats),
+}
+
+/// Configuration read from `resolv.conf` and `hosts`, valid for as long as the files keep their
+/// modification times.
+struct ResolverSetup {
+    modified: (SystemTime, SystemTime),
+    config: ResolverConfig,
+    options: ResolverOpts,
+    hosts_conf: Vec<u8>,
+    /// Resolvers built from this configuration so far, they keep their connections.
+    resolvers: Vec<(LookupIpStrategy, TokioAsyncResolver)>,
+}
+
+impl ResolverSetup {
+    /// Returns a resolver with the given [`LookupIpStrategy`], building it if needed.
+    fn resolver(&mut self, ip_strategy: LookupIpStrategy) -> TokioAsyncResolver {
+        if let Some((_, resolver)) = self
+            .resolvers
+            .iter()
+            .find(|(strategy, _)| *strategy == ip_strategy)
+        {
+            return resolver.clone();
+        }
+
+        let mut options = self.options.clone();
+        options.ip_strategy = ip_strategy;
+
+        let mut resolver = AsyncResolver::tokio(self.config.clone(), options);
+        // Parsed successfully when the setup was created.
+        let hosts = Hosts::default()
+            .read_hosts_conf(self.hosts_conf.as_slice())
+            .unwrap_or_default();
+        resolver.set_hosts(Some(hosts));
+
+        self.resolvers.push((ip_strategy, resolver.clone()));
+        resolver
+    }
+}
+
+/// State shared by the lookups of the [`DnsWorker`]: the [`ResolverSetup`], and the answers kept
+/// until their records expire.
+#[derive(Default)]
+struct DnsCache {
+    setup: Option<ResolverSetup>,
+    /// Answers with the time when they expire.
+    answers: HashMap<DnsRequest, (DnsResponse, Instant)>,
+    stats: DnsCacheStats,
+}
+
+impl DnsCache {
+    /// Returns the cached answer to the given [`DnsRequest`], if it did not expire yet.
+    fn get(&mut self, request: &DnsRequest) -> Option<DnsResponse> {
+        let now = Instant::now();
+
+        let answer = match self.answers.get(request) {
+            Some((answer, expires)) if *expires > now => Some(answer.clone()),
+            Some(..) => {
+                self.answers.remove(request);
+                None
+            }
+            None => None,
+        };
+
+        if answer.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+
+        answer
+    }
+
+    /// Caches the answer to the given [`DnsRequest`], until `expires`.
+    ///
+    /// When the cache is full, the expired answers are dropped first. If that's not enough, the
+    /// new answer is not cached.
+    fn insert(&mut self, request: DnsRequest, answer: DnsResponse, expires: Instant) {
+        if self.answers.len() >= MAX_CACHED_ANSWERS {
+            let now = Instant::now();
+            self.answers.retain(|_, (_, expires)| *expires > now);
+        }
+
+        if self.answers.len() < MAX_CACHED_ANSWERS {
+            self.answers.insert(request, (answer, expires));
+        }
+    }
+
+    /// Until when the given failed lookup can be cached, [`None`] if it should not be.
+    ///
+    /// Only the lookups that found no records are cached, for the negative TTL of the zone.
+    fn negative_expiry(fail: &ResolveError) -> Option<Instant> {
+        match fail.kind() {
+            ResolveErrorKind::NoRecordsFound {
+                negative_ttl: Some(ttl),
+                ..
+            } => Some(Instant::now() + Duration::from_secs((*ttl).into())),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct DnsCommand {
-    request: GetAddrInfoRequest,
-    response_tx: oneshot::Sender<RemoteResult<DnsLookup>>,
+    request: DnsRequest,
+    response_tx: oneshot::Sender<DnsResponse>,
 }
 
 /// Background task for resolving hostnames to IP addresses.
@@ -34,6 +169,7 @@ pub(crate) struct DnsWorker {
     request_rx: Receiver<DnsCommand>,
     attempts: usize,
     timeout: Duration,
+    cache: Arc<Mutex<DnsCache>>,
 }
 
 impl DnsWorker {
@@ -66,56 +202,214 @@ impl DnsWorker {
                 .ok()
                 .and_then(|attempts| attempts.parse().ok())
                 .unwrap_or(1),
+            cache: Default::default(),
         }
     }
 
-    /// Reads `/etc/resolv.conf` and `/etc/hosts` files, then uses [`AsyncResolver`] to resolve
-    /// address of the given `host`.
-    ///
-    /// # TODO
-    ///
-    /// We could probably cache results here.
-    /// We cannot cache the [`AsyncResolver`] itself, becaues the configuration in `etc` may change.
-    #[tracing::instrument(level = Level::TRACE, ret, err(level = Level::TRACE))]
-    async fn do_lookup(
-        etc_path: PathBuf,
-        host: String,
+    /// Locks the [`DnsCache`] shared by the lookups, ignoring the poisoning.
+    fn lock(cache: &Mutex<DnsCache>) -> MutexGuard<'_, DnsCache> {
+        cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reads `/etc/resolv.conf` and `/etc/hosts` files into a new [`ResolverSetup`].
+    async fn read_setup(
+        etc_path: &Path,
+        modified: (SystemTime, SystemTime),
         attempts: usize,
         timeout: Duration,
-    ) -> RemoteResult<DnsLookup> {
-        // Prepares the `AsyncResolver` after reading some `/etc` DNS files.
-        //
+    ) -> RemoteResult<ResolverSetup> {
         // We care about logging these errors, at an `error!` level.
-        let resolver: Result<_, ResponseError> = try {
-            let resolv_conf_path = etc_path.join("resolv.conf");
-            let hosts_path = etc_path.join("hosts");
-
-            let resolv_conf = fs::read(resolv_conf_path).await?;
-            let hosts_conf = fs::read(hosts_path).await?;
+        let setup: Result<_, ResponseError> = try {
+            let resolv_conf = fs::read(etc_path.join("resolv.conf")).await?;
+            let hosts_conf = fs::read(etc_path.join("hosts")).await?;
 
             let (config, mut options) = parse_resolv_conf(resolv_conf)?;
             options.server_ordering_strategy =
                 hickory_resolver::config::ServerOrderingStrategy::UserProvidedOrder;
             options.timeout = timeout;
             options.attempts = attempts;
-            options.ip_strategy = hickory_resolver::config::LookupIpStrategy::Ipv4Only;
 
-            let mut resolver = AsyncResolver::tokio(config, options);
+            Hosts::default().read_hosts_conf(hosts_conf.as_slice())?;
+
+            ResolverSetup {
+                modified,
+                config,
+                options,
+                hosts_conf,
+                resolvers: Default::default(),
+            }
+        };
+
+        setup.inspect_err(|fail| tracing::error!(?fail, "Failed to build DNS resolver"))
+    }
+
+    /// Prepares an [`AsyncResolver`] that uses `/etc/resolv.conf` and `/etc/hosts` files.
+    ///
+    /// The files are read again only when their modification times change, which also drops the
+    /// cached answers.
+    async fn resolver(
+        etc_path: &Path,
+        cache: &Mutex<DnsCache>,
+        ip_strategy: LookupIpStrategy,
+        attempts: usize,
+        timeout: Duration,
+    ) -> RemoteResult<TokioAsyncResolver> {
+        let modified = (
+            fs::metadata(etc_path.join("resolv.conf"))
+                .await?
+                .modified()?,
+            fs::metadata(etc_path.join("hosts")).await?.modified()?,
+        );
+
+        let resolver = Self::lock(cache)
+            .setup
+            .as_mut()
+            .filter(|setup| setup.modified == modified)
+            .map(|setup| setup.resolver(ip_strategy));
+        if let Some(resolver) = resolver {
+            return Ok(resolver);
+        }
+
+        let mut setup = Self::read_setup(etc_path, modified, attempts, timeout).await?;
+        let resolver = setup.resolver(ip_strategy);
+
+        let mut cache = Self::lock(cache);
+        cache.answers.clear();
+        cache.setup = Some(setup);
 
-            let hosts = Hosts::default().read_hosts_conf(hosts_conf.as_slice())?;
-            resolver.set_hosts(Some(hosts));
+        Ok(resolver)
+    }
 
-            resolver
+    /// Resolves address of the given `host`, see [`Self::resolver`].
+    ///
+    /// Answers are cached for as long as their records live.
+    #[tracing::instrument(level = Level::TRACE, skip(cache), ret, err(level = Level::TRACE))]
+    async fn do_lookup(
+        etc_path: PathBuf,
+        request: GetAddrInfoRequestV2,
+        cache: Arc<Mutex<DnsCache>>,
+        attempts: usize,
+        timeout: Duration,
+    ) -> RemoteResult<DnsLookup> {
+        let ip_strategy = match request.family {
+            AddressFamily::Ipv4Only => LookupIpStrategy::Ipv4Only,
+            AddressFamily::Ipv6Only => LookupIpStrategy::Ipv6Only,
+            AddressFamily::Both => LookupIpStrategy::Ipv4AndIpv6,
+            AddressFamily::Any => LookupIpStrategy::Ipv4thenIpv6,
         };
 
+        let resolver = Self::resolver(&etc_path, &cache, ip_strategy, attempts, timeout).await?;
+
+        let node = request.node.clone();
+        let request = DnsRequest::AddrInfo(request);
+        let cached = Self::lock(&cache).get(&request);
+        if let Some(DnsResponse::AddrInfo(cached)) = cached {
+            return cached;
+        }
+
         let lookup = resolver
-            .inspect_err(|fail| tracing::error!(?fail, "Failed to build DNS resolver"))?
-            .lookup_ip(host)
+            .lookup_ip(node)
             .await
-            .inspect(|lookup| tracing::trace!(?lookup, "Lookup finished"))?
-            .into();
+            .inspect(|lookup| tracing::trace!(?lookup, "Lookup finished"));
 
-        Ok(lookup)
+        let expires = match &lookup {
+            Ok(lookup) => Some(lookup.valid_until()),
+            Err(fail) => DnsCache::negative_expiry(fail),
+        };
+        let lookup = lookup.map(DnsLookup::from).map_err(ResponseError::from);
+
+        if let Some(expires) = expires {
+            Self::lock(&cache).insert(request, DnsResponse::AddrInfo(lookup.clone()), expires);
+        }
+
+        lookup
+    }
+
+    /// Resolves the records of the given `record_type` for `name`, see [`Self::resolver`].
+    ///
+    /// Answers are cached for as long as their records live.
+    #[tracing::instrument(level = Level::TRACE, skip(cache), ret, err(level = Level::TRACE))]
+    async fn do_query(
+        etc_path: PathBuf,
+        request: DnsQueryRequest,
+        cache: Arc<Mutex<DnsCache>>,
+        attempts: usize,
+        timeout: Duration,
+    ) -> RemoteResult<Vec<DnsRecord>> {
+        let resolver =
+            Self::resolver(&etc_path, &cache, Default::default(), attempts, timeout).await?;
+
+        let DnsQueryRequest { name, record_type } = request.clone();
+        let request = DnsRequest::Query(request);
+        let cached = Self::lock(&cache).get(&request);
+        if let Some(DnsResponse::Query(cached)) = cached {
+            return cached;
+        }
+
+        let lookup = resolver
+            .lookup(name, RecordType::from(record_type))
+            .await
+            .inspect(|lookup| tracing::trace!(?lookup, "Query finished"));
+
+        let expires = match &lookup {
+            Ok(lookup) => Some(lookup.valid_until()),
+            Err(fail) => DnsCache::negative_expiry(fail),
+        };
+        let records = lookup
+            .map(|lookup| DnsRecord::from_lookup(&lookup))
+            .map_err(ResponseError::from);
+
+        if let Some(expires) = expires {
+            Self::lock(&cache).insert(request, DnsResponse::Query(records.clone()), expires);
+        }
+
+        records
+    }
+
+    /// Resolves the names of the given `ip` from its `PTR` records, see [`Self::resolver`].
+    ///
+    /// Answers are cached for as long as their records live.
+    #[tracing::instrument(level = Level::TRACE, skip(cache), ret, err(level = Level::TRACE))]
+    async fn do_reverse_lookup(
+        etc_path: PathBuf,
+        request: ReverseLookupRequest,
+        cache: Arc<Mutex<DnsCache>>,
+        attempts: usize,
+        timeout: Duration,
+    ) -> RemoteResult<Vec<String>> {
+        let resolver =
+            Self::resolver(&etc_path, &cache, Default::default(), attempts, timeout).await?;
+
+        let ip = request.ip;
+        let request = DnsRequest::ReverseLookup(request);
+        let cached = Self::lock(&cache).get(&request);
+        if let Some(DnsResponse::ReverseLookup(cached)) = cached {
+            return cached;
+        }
+
+        let lookup = resolver
+            .reverse_lookup(ip)
+            .await
+            .inspect(|lookup| tracing::trace!(?lookup, "Reverse lookup finished"));
+
+        let expires = match &lookup {
+            Ok(lookup) => Some(lookup.valid_until()),
+            Err(fail) => DnsCache::negative_expiry(fail),
+        };
+        let names = lookup
+            .map(|lookup| {
+                lookup
+                    .iter()
+                    .map(|name| name.0.to_utf8())
+                    .collect::<Vec<_>>()
+            })
+            .map_err(ResponseError::from);
+
+        if let Some(expires) = expires {
+            Self::lock(&cache).insert(request, DnsResponse::ReverseLookup(names.clone()), expires);
+        }
+
+        names
     }
 
     /// Handles the given [`DnsCommand`] in a separate [`tokio::task`].
@@ -124,8 +418,20 @@ impl DnsWorker {
         let etc_path = self.etc_path.clone();
         let timeout = self.timeout;
         let attempts = self.attempts;
+        let cache = self.cache.clone();
         let lookup_future = async move {
-            let result = Self::do_lookup(etc_path, message.request.node, attempts, timeout).await;
+            let result = match message.request {
+                DnsRequest::AddrInfo(request) => DnsResponse::AddrInfo(
+                    Self::do_lookup(etc_path, request, cache, attempts, timeout).await,
+                ),
+                DnsRequest::Query(request) => DnsResponse::Query(
+                    Self::do_query(etc_path, request, cache, attempts, timeout).await,
+                ),
+                DnsRequest::ReverseLookup(request) => DnsResponse::ReverseLookup(
+                    Self::do_reverse_lookup(etc_path, request, cache, attempts, timeout).await,
+                ),
+                DnsRequest::CacheStats => DnsResponse::CacheStats(Self::lock(&cache).stats),
+            };
 
             if let Err(result) = message.response_tx.send(result) {
                 tracing::error!(?result, "Failed to send query response");
@@ -157,7 +463,7 @@ pub(crate) struct DnsApi {
     request_tx: Sender<DnsCommand>,
     /// [`DnsWorker`] processes all requests concurrently, so we use a combination of [`oneshot`]
     /// channels and [`FuturesOrdered`] to preserve order of responses.
-    responses: FuturesOrdered<oneshot::Receiver<RemoteResult<DnsLookup>>>,
+    responses: FuturesOrdered<oneshot::Receiver<DnsResponse>>,
 }
 
 impl DnsApi {
@@ -171,10 +477,7 @@ impl DnsApi {
 
     /// Schedules a new DNS request.
     /// Results of scheduled requests are available via [`Self::recv`] (order is preserved).
-    pub(crate) async fn make_request(
-        &mut self,
-        request: GetAddrInfoRequest,
-    ) -> Result<(), AgentError> {
+    pub(crate) async fn make_request(&mut self, request: DnsRequest) -> Result<(), AgentError> {
         let (response_tx, response_rx) = oneshot::channel();
 
         let command = DnsCommand {
@@ -190,15 +493,33 @@ impl DnsApi {
         Ok(())
     }
 
-    /// Returns the result of the oldest outstanding DNS request issued with this struct (see
+    /// Returns the response to the oldest outstanding DNS request issued with this struct (see
     /// [`Self::make_request`]).
     #[tracing::instrument(level = Level::TRACE, skip(self), ret, err)]
-    pub(crate) async fn recv(&mut self) -> Result<GetAddrInfoResponse, AgentError> {
+    pub(crate) async fn recv(&mut self) -> Result<DaemonMessage, AgentError> {
         let Some(response) = self.responses.next().await else {
             return future::pending().await;
         };
 
-        let response = response?.map_err(|fail| match fail {
+        let message = match response? {
+            DnsResponse::AddrInfo(response) => DaemonMessage::GetAddrInfoResponse(
+                GetAddrInfoResponse(response.map_err(Self::lookup_error)),
+            ),
+            DnsResponse::Query(response) => {
+                DaemonMessage::DnsQuery(DnsQueryResponse(response.map_err(Self::lookup_error)))
+            }
+            DnsResponse::ReverseLookup(response) => DaemonMessage::ReverseLookup(
+                ReverseLookupResponse(response.map_err(Self::lookup_error)),
+            ),
+            DnsResponse::CacheStats(stats) => DaemonMessage::DnsCacheStats(stats),
+        };
+
+        Ok(message)
+    }
+
+    /// Reports all failures of DNS requests as [`ResponseError::DnsLookup`].
+    fn lookup_error(fail: ResponseError) -> ResponseError {
+        match fail {
             ResponseError::RemoteIO(remote_ioerror) => ResponseError::DnsLookup(DnsLookupError {
                 kind: remote_ioerror.kind.into(),
             }),
@@ -206,8 +527,6 @@ impl DnsApi {
             _ => ResponseError::DnsLookup(DnsLookupError {
                 kind: ResolveErrorKindInternal::Unknown,
             }),
-        });
-
-        Ok(GetAddrInfoResponse(response))
+        }
     }
 }
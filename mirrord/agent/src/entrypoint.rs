@@ -10,9 +10,11 @@ use std::{
 };
 
 use client_connection::AgentTlsConnector;
-use dns::{DnsCommand, DnsWorker};
+use dns::{DnsCommand, DnsRequest, DnsWorker};
 use futures::TryFutureExt;
-use mirrord_protocol::{ClientMessage, DaemonMessage, GetEnvVarsRequest, LogMessage};
+use mirrord_protocol::{
+    ClientMessage, DaemonMessage, FileRequest, FileResponse, GetEnvVarsRequest, LogMessage,
+};
 use sniffer::tcp_capture::RawSocketTcpCapture;
 use tokio::{
     net::{TcpListener, TcpStream},
@@ -34,17 +36,20 @@ use crate::{
     dns::DnsApi,
     error::{AgentError, Result},
     file::FileManager,
+    file_watch::FileWatcher,
     outgoing::{TcpOutgoingApi, UdpOutgoingApi},
     runtime::get_container,
     sniffer::{api::TcpSnifferApi, messages::SnifferCommand, TcpConnectionSniffer},
     steal::{
         ip_tables::{
-            new_iptables, IPTablesWrapper, SafeIpTables, IPTABLE_IPV4_ROUTE_LOCALNET_ORIGINAL,
-            IPTABLE_IPV4_ROUTE_LOCALNET_ORIGINAL_ENV, IPTABLE_MESH, IPTABLE_MESH_ENV,
-            IPTABLE_PREROUTING, IPTABLE_PREROUTING_ENV, IPTABLE_STANDARD, IPTABLE_STANDARD_ENV,
+            new_iptables, redirect::Redirect, udp::UdpRedirect, IPTablesWrapper, SafeIpTables,
+            IPTABLE_IPV4_ROUTE_LOCALNET_ORIGINAL, IPTABLE_IPV4_ROUTE_LOCALNET_ORIGINAL_ENV,
+            IPTABLE_MESH, IPTABLE_MESH_ENV, IPTABLE_PREROUTING, IPTABLE_PREROUTING_ENV,
+            IPTABLE_STANDARD, IPTABLE_STANDARD_ENV, IPTABLE_UDP, IPTABLE_UDP_ENV,
         },
         StealerCommand, TcpConnectionStealer, TcpStealerApi,
     },
+    udp_incoming::{api::UdpIncomingApi, UdpIncomingCommand, UdpIncomingTask},
     util::{run_thread_in_namespace, ClientId},
     watched_task::{TaskStatus, WatchedTask},
     *,
@@ -188,15 +193,19 @@ struct BackgroundTasks {
     sniffer: BackgroundTask<SnifferCommand>,
     stealer: BackgroundTask<StealerCommand>,
     dns: BackgroundTask<DnsCommand>,
+    udp_incoming: BackgroundTask<UdpIncomingCommand>,
 }
 
 struct ClientConnectionHandler {
     id: ClientId,
     /// Handles mirrord's file operations, see [`FileManager`].
     file_manager: FileManager,
+    /// Handles the client's remote file watches, see [`FileWatcher`].
+    file_watcher: FileWatcher,
     connection: ClientConnection,
     tcp_sniffer_api: Option<TcpSnifferApi>,
     tcp_stealer_api: Option<TcpStealerApi>,
+    udp_incoming_api: Option<UdpIncomingApi>,
     tcp_outgoing_api: TcpOutgoingApi,
     udp_outgoing_api: UdpOutgoingApi,
     dns_api: DnsApi,
@@ -216,10 +225,12 @@ impl ClientConnectionHandler {
         let pid = state.container_pid();
 
         let file_manager = FileManager::new(pid.or_else(|| state.ephemeral.then_some(1)));
+        let file_watcher = FileWatcher::new(pid.or_else(|| state.ephemeral.then_some(1)));
 
         let tcp_sniffer_api = Self::create_sniffer_api(id, bg_tasks.sniffer, &mut connection).await;
         let tcp_stealer_api =
             Self::create_stealer_api(id, bg_tasks.stealer, &mut connection).await?;
+        let udp_incoming_api = Self::create_udp_incoming_api(id, bg_tasks.udp_incoming).await;
         let dns_api = Self::create_dns_api(bg_tasks.dns);
 
         let tcp_outgoing_api = TcpOutgoingApi::new(pid);
@@ -228,9 +239,11 @@ impl ClientConnectionHandler {
         let client_handler = Self {
             id,
             file_manager,
+            file_watcher,
             connection,
             tcp_sniffer_api,
             tcp_stealer_api,
+            udp_incoming_api,
             tcp_outgoing_api,
             udp_outgoing_api,
             dns_api,
@@ -300,6 +313,20 @@ impl ClientConnectionHandler {
         }
     }
 
+    async fn create_udp_incoming_api(
+        id: ClientId,
+        task: BackgroundTask<UdpIncomingCommand>,
+    ) -> Option<UdpIncomingApi> {
+        if let BackgroundTask::Running(task_status, task_sender) = task {
+            UdpIncomingApi::new(id, task_sender, task_status)
+                .await
+                .inspect_err(|error| warn!(%error, "Failed to create UdpIncomingApi"))
+                .ok()
+        } else {
+            None
+        }
+    }
+
     fn create_dns_api(task: BackgroundTask<DnsCommand>) -> DnsApi {
         match task {
             BackgroundTask::Running(task_status, task_sender) => {
@@ -360,6 +387,16 @@ impl ClientConnectionHandler {
                     Ok(message) => self.respond(DaemonMessage::TcpSteal(message)).await?,
                     Err(e) => break e,
                 },
+                message = async {
+                    if let Some(ref mut udp_incoming_api) = self.udp_incoming_api {
+                        udp_incoming_api.recv().await
+                    } else {
+                        unreachable!()
+                    }
+                }, if self.udp_incoming_api.is_some() => match message {
+                    Ok(message) => self.respond(DaemonMessage::UdpIncoming(message)).await?,
+                    Err(e) => break e,
+                },
                 message = self.tcp_outgoing_api.recv_from_task() => match message {
                     Ok(message) => self.respond(DaemonMessage::TcpOutgoing(message)).await?,
                     Err(e) => break e,
@@ -368,8 +405,11 @@ impl ClientConnectionHandler {
                     Ok(message) => self.respond(DaemonMessage::UdpOutgoing(message)).await?,
                     Err(e) => break e,
                 },
+                event = self.file_watcher.next_event() => {
+                    self.respond(DaemonMessage::FileWatchEvent(event)).await?
+                }
                 message = self.dns_api.recv() => match message {
-                    Ok(message) => self.respond(DaemonMessage::GetAddrInfoResponse(message)).await?,
+                    Ok(message) => self.respond(message).await?,
                     Err(e) => break e,
                 },
                 // message = self.vpn_api.daemon_message() => match message{
@@ -399,6 +439,14 @@ impl ClientConnectionHandler {
     #[tracing::instrument(level = "trace", skip(self))]
     async fn handle_client_message(&mut self, message: ClientMessage) -> Result<bool> {
         match message {
+            ClientMessage::FileRequest(FileRequest::AddWatch(request)) => {
+                let response = self.file_watcher.add_watch(request);
+                self.respond(DaemonMessage::File(FileResponse::AddWatch(response)))
+                    .await?
+            }
+            ClientMessage::FileRequest(FileRequest::RemoveWatch(request)) => {
+                self.file_watcher.remove_watch(request)
+            }
             ClientMessage::FileRequest(req) => {
                 if let Some(response) = self.file_manager.handle_message(req)? {
                     self.respond(DaemonMessage::File(response))
@@ -433,7 +481,27 @@ impl ClientConnectionHandler {
                     .await?
             }
             ClientMessage::GetAddrInfoRequest(request) => {
-                self.dns_api.make_request(request).await?;
+                self.dns_api
+                    .make_request(DnsRequest::AddrInfo(request.into()))
+                    .await?;
+            }
+            ClientMessage::GetAddrInfoRequestV2(request) => {
+                self.dns_api
+                    .make_request(DnsRequest::AddrInfo(request))
+                    .await?;
+            }
+            ClientMessage::DnsQuery(request) => {
+                self.dns_api
+                    .make_request(DnsRequest::Query(request))
+                    .await?;
+            }
+            ClientMessage::ReverseLookup(request) => {
+                self.dns_api
+                    .make_request(DnsRequest::ReverseLookup(request))
+                    .await?;
+            }
+            ClientMessage::GetDnsCacheStats => {
+                self.dns_api.make_request(DnsRequest::CacheStats).await?;
             }
             ClientMessage::Ping => self.respond(DaemonMessage::Pong).await?,
             ClientMessage::Tcp(message) => {
@@ -452,6 +520,14 @@ impl ClientConnectionHandler {
                     Err(AgentError::StealerNotRunning)?
                 }
             }
+            ClientMessage::UdpIncoming(message) => {
+                if let Some(udp_incoming_api) = self.udp_incoming_api.as_mut() {
+                    udp_incoming_api.handle_client_message(message).await?
+                } else {
+                    warn!("received incoming udp request while not available");
+                    Err(AgentError::UdpIncomingNotRunning)?
+                }
+            }
             ClientMessage::Close => {
                 return Ok(false);
             }
@@ -477,6 +553,10 @@ impl ClientConnectionHandler {
             ClientMessage::ReadyForLogs => {
                 self.ready_for_logs = true;
             }
+            ClientMessage::SwitchCompression(compression) => {
+                self.respond(DaemonMessage::SwitchCompressionResponse(compression))
+                    .await?;
+            }
             ClientMessage::Vpn(_message) => {
                 unreachable!("VPN is not supported");
                 // self.vpn_api.layer_message(message).await?;
@@ -508,6 +588,8 @@ async fn start_agent(args: Args) -> Result<()> {
     let (sniffer_command_tx, sniffer_command_rx) = mpsc::channel::<SnifferCommand>(1000);
     let (stealer_command_tx, stealer_command_rx) = mpsc::channel::<StealerCommand>(1000);
     let (dns_command_tx, dns_command_rx) = mpsc::channel::<DnsCommand>(1000);
+    let (udp_incoming_command_tx, udp_incoming_command_rx) =
+        mpsc::channel::<UdpIncomingCommand>(1000);
 
     let (sniffer_task, sniffer_status) = if args.mode.is_targetless() {
         (None, None)
@@ -517,7 +599,7 @@ async fn start_agent(args: Args) -> Result<()> {
 
         let watched_task = WatchedTask::new(
             TcpConnectionSniffer::<RawSocketTcpCapture>::TASK_NAME,
-            TcpConnectionSniffer::new(sniffer_command_rx, args.network_interface, is_mesh)
+            TcpConnectionSniffer::new(sniffer_command_rx, args.network_interface.clone(), is_mesh)
                 .and_then(|sniffer| async move {
                     let res = sniffer.start(cancellation_token).await;
                     if let Err(err) = res.as_ref() {
@@ -562,6 +644,31 @@ async fn start_agent(args: Args) -> Result<()> {
         (Some(task), Some(status))
     };
 
+    let (udp_incoming_task, udp_incoming_status) = if args.mode.is_targetless() {
+        (None, None)
+    } else {
+        let cancellation_token = cancellation_token.clone();
+        let watched_task = WatchedTask::new(
+            UdpIncomingTask::TASK_NAME,
+            UdpIncomingTask::new(
+                udp_incoming_command_rx,
+                args.network_interface.clone(),
+                args.is_mesh(),
+            )
+            .run(cancellation_token)
+            .inspect_err(|err| error!("UDP incoming task failed: {err}")),
+        );
+        let status = watched_task.status();
+        let task = run_thread_in_namespace(
+            watched_task.start(),
+            UdpIncomingTask::TASK_NAME.to_string(),
+            state.container_pid(),
+            "net",
+        );
+
+        (Some(task), Some(status))
+    };
+
     let (dns_task, dns_status) = {
         let cancellation_token = cancellation_token.clone();
         let watched_task = WatchedTask::new(
@@ -587,6 +694,9 @@ async fn start_agent(args: Args) -> Result<()> {
             .map(|status| BackgroundTask::Running(status, stealer_command_tx))
             .unwrap_or(BackgroundTask::Disabled),
         dns: BackgroundTask::Running(dns_status, dns_command_tx),
+        udp_incoming: udp_incoming_status
+            .map(|status| BackgroundTask::Running(status, udp_incoming_command_tx))
+            .unwrap_or(BackgroundTask::Disabled),
     };
 
     // WARNING: `wait_for_agent_startup` in `mirrord/kube/src/api/container.rs` expects a line
@@ -668,6 +778,7 @@ async fn start_agent(args: Args) -> Result<()> {
         sniffer,
         stealer,
         dns,
+        udp_incoming,
     } = bg_tasks;
 
     if let (Some(sniffer_task), BackgroundTask::Running(mut sniffer_status, _)) =
@@ -688,6 +799,18 @@ async fn start_agent(args: Args) -> Result<()> {
         }
     }
 
+    if let (Some(udp_incoming_task), BackgroundTask::Running(mut udp_incoming_status, _)) =
+        (udp_incoming_task, udp_incoming)
+    {
+        udp_incoming_task.join().map_err(|_| AgentError::JoinTask)?;
+        if let Some(err) = udp_incoming_status.err().await {
+            error!(
+                "start_agent -> udp incoming task failed with error: {}",
+                err
+            );
+        }
+    }
+
     if let BackgroundTask::Running(mut dns_status, _) = dns {
         dns_task.join().map_err(|_| AgentError::JoinTask)?;
         if let Some(err) = dns_status.err().await {
@@ -703,10 +826,14 @@ async fn start_agent(args: Args) -> Result<()> {
 async fn clear_iptable_chain() -> Result<()> {
     let ipt = new_iptables();
 
-    SafeIpTables::load(IPTablesWrapper::from(ipt), false)
-        .await?
-        .cleanup()
-        .await?;
+    let ipt = IPTablesWrapper::from(ipt);
+
+    // The UDP chain exists only if some UDP port was stolen.
+    if let Ok(udp) = UdpRedirect::load(Arc::new(ipt.clone())) {
+        udp.unmount_entrypoint().await?;
+    }
+
+    SafeIpTables::load(ipt, false).await?.cleanup().await?;
 
     Ok(())
 }
@@ -744,6 +871,7 @@ async fn start_iptable_guard(args: Args) -> Result<()> {
     std::env::set_var(IPTABLE_PREROUTING_ENV, IPTABLE_PREROUTING.as_str());
     std::env::set_var(IPTABLE_MESH_ENV, IPTABLE_MESH.as_str());
     std::env::set_var(IPTABLE_STANDARD_ENV, IPTABLE_STANDARD.as_str());
+    std::env::set_var(IPTABLE_UDP_ENV, IPTABLE_UDP.as_str());
     std::env::set_var(
         IPTABLE_IPV4_ROUTE_LOCALNET_ORIGINAL_ENV,
         IPTABLE_IPV4_ROUTE_LOCALNET_ORIGINAL.as_str(),
@@ -812,7 +940,8 @@ pub async fn main() -> Result<()> {
     let agent_result = if args.mode.is_targetless()
         || (std::env::var(IPTABLE_PREROUTING_ENV).is_ok()
             && std::env::var(IPTABLE_MESH_ENV).is_ok()
-            && std::env::var(IPTABLE_STANDARD_ENV).is_ok())
+            && std::env::var(IPTABLE_STANDARD_ENV).is_ok()
+            && std::env::var(IPTABLE_UDP_ENV).is_ok())
     {
         start_agent(args).await
     } else {
@@ -58,6 +58,9 @@ pub(crate) enum AgentError {
     #[error("Couldn't send message to stealer (steal) api, stealer probably not running.")]
     StealerNotRunning,
 
+    #[error("Couldn't send message to incoming UDP api, the task is probably not running.")]
+    UdpIncomingNotRunning,
+
     #[error("Background task `{task}` failed with `{cause}`")]
     BackgroundTaskFailed { task: &'static str, cause: String },
 
@@ -1,17 +1,23 @@
 use std::{
     self,
     collections::{hash_map::Entry, HashMap, VecDeque},
-    fs::{read_link, File, OpenOptions, ReadDir},
+    ffi::CString,
+    fs::{read_link, DirBuilder, File, OpenOptions, Permissions, ReadDir},
     io::{self, prelude::*, BufReader, SeekFrom},
     iter::{Enumerate, Peekable},
     ops::RangeInclusive,
-    os::unix::{fs::MetadataExt, prelude::FileExt},
-    path::{Path, PathBuf},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+        prelude::FileExt,
+    },
+    path::{Component, Path, PathBuf},
 };
 
 use faccess::{AccessMode, PathExt};
 use libc::DT_DIR;
 use mirrord_protocol::{file::*, FileRequest, FileResponse, RemoteResult, ResponseError};
+use nix::sys::statfs::Statfs;
 use tracing::{error, trace, Level};
 
 use crate::error::Result;
@@ -223,9 +229,31 @@ impl FileManager {
                 Some(FileResponse::Xstat(xstat_result))
             }
             FileRequest::XstatFs(XstatFsRequest { fd }) => {
-                let xstat_result = self.xstatfs(fd);
+                let xstat_result = self.xstatfs(fd).map(|statfs| XstatFsResponse {
+                    metadata: statfs.into(),
+                });
                 Some(FileResponse::XstatFs(xstat_result))
             }
+            FileRequest::StatFs(StatFsRequest { path }) => {
+                Some(FileResponse::XstatFs(self.statfs(path).map(|statfs| {
+                    XstatFsResponse {
+                        metadata: statfs.into(),
+                    }
+                })))
+            }
+            FileRequest::XstatFsV2(XstatFsRequestV2 { fd }) => {
+                let xstat_result = self.xstatfs(fd).map(|statfs| XstatFsResponseV2 {
+                    metadata: statfs.into(),
+                });
+                Some(FileResponse::XstatFsV2(xstat_result))
+            }
+            FileRequest::StatFsV2(StatFsRequestV2 { path }) => {
+                Some(FileResponse::XstatFsV2(self.statfs(path).map(|statfs| {
+                    XstatFsResponseV2 {
+                        metadata: statfs.into(),
+                    }
+                })))
+            }
 
             // dir operations
             FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd }) => {
@@ -250,6 +278,62 @@ impl FileManager {
             }) => Some(FileResponse::GetDEnts64(
                 self.getdents64(remote_fd, buffer_size),
             )),
+
+            // file system mutations
+            FileRequest::MakeDir(MakeDirRequest { path, mode }) => {
+                Some(FileResponse::MakeDir(self.make_dir(path, mode)))
+            }
+            FileRequest::RemoveDir(RemoveDirRequest { path }) => {
+                Some(FileResponse::RemoveDir(self.remove_dir(path)))
+            }
+            FileRequest::Unlink(UnlinkRequest { path }) => {
+                Some(FileResponse::Unlink(self.unlink(path)))
+            }
+            FileRequest::Rename(RenameRequest { old_path, new_path }) => {
+                Some(FileResponse::Rename(self.rename(old_path, new_path)))
+            }
+            FileRequest::Chmod(ChmodRequest { path, mode }) => {
+                Some(FileResponse::Chmod(self.chmod(path, mode)))
+            }
+            FileRequest::Fchmod(FchmodRequest { fd, mode }) => {
+                Some(FileResponse::Chmod(self.fchmod(fd, mode)))
+            }
+            FileRequest::Chown(ChownRequest {
+                path,
+                user_id,
+                group_id,
+                follow_symlink,
+            }) => Some(FileResponse::Chown(self.chown(
+                path,
+                user_id,
+                group_id,
+                follow_symlink,
+            ))),
+            FileRequest::Truncate(TruncateRequest { path, length }) => {
+                Some(FileResponse::Truncate(self.truncate(path, length)))
+            }
+            FileRequest::Ftruncate(FtruncateRequest { fd, length }) => {
+                Some(FileResponse::Truncate(self.ftruncate(fd, length)))
+            }
+            FileRequest::Symlink(SymlinkRequest { target, link_path }) => {
+                Some(FileResponse::Symlink(self.symlink(target, link_path)))
+            }
+            FileRequest::Utimes(UtimesRequest {
+                path,
+                access_time,
+                modification_time,
+                follow_symlink,
+            }) => Some(FileResponse::Utimes(self.utimes(
+                path,
+                access_time,
+                modification_time,
+                follow_symlink,
+            ))),
+            // Handled by the `FileWatcher`, which needs to push the events to the client.
+            FileRequest::AddWatch(..) => {
+                Some(FileResponse::AddWatch(Err(ResponseError::NotImplemented)))
+            }
+            FileRequest::RemoveWatch(..) => None,
         })
     }
 
@@ -446,6 +530,180 @@ impl FileManager {
             .map_err(ResponseError::from)
     }
 
+    /// Resolves the parent of `path` with [`resolve_path`] and joins it with the last component,
+    /// without resolving it.
+    ///
+    /// Used by the operations that must not follow a symbolic link in the last component, e.g.
+    /// `unlink` removes the link itself. Paths ending with `.` or `..` are rejected.
+    fn unresolved_path(&self, path: PathBuf) -> RemoteResult<PathBuf> {
+        let Some(Component::Normal(file_name)) = path.components().next_back() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "path must end with a file name",
+            )
+            .into());
+        };
+        let parent = path.parent().unwrap_or(Path::new("/"));
+
+        Ok(resolve_path(parent, &self.root_path)?.join(file_name))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn make_dir(&mut self, path: PathBuf, mode: u32) -> RemoteResult<()> {
+        let path = self.unresolved_path(path)?;
+
+        DirBuilder::new()
+            .mode(mode)
+            .create(path)
+            .map_err(ResponseError::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn remove_dir(&mut self, path: PathBuf) -> RemoteResult<()> {
+        let path = self.unresolved_path(path)?;
+
+        std::fs::remove_dir(path).map_err(ResponseError::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn unlink(&mut self, path: PathBuf) -> RemoteResult<()> {
+        let path = self.unresolved_path(path)?;
+
+        std::fs::remove_file(path).map_err(ResponseError::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn rename(&mut self, old_path: PathBuf, new_path: PathBuf) -> RemoteResult<()> {
+        let old_path = self.unresolved_path(old_path)?;
+        let new_path = self.unresolved_path(new_path)?;
+
+        std::fs::rename(old_path, new_path).map_err(ResponseError::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn chmod(&mut self, path: PathBuf, mode: u32) -> RemoteResult<()> {
+        let path = resolve_path(path, &self.root_path)?;
+
+        std::fs::set_permissions(path, Permissions::from_mode(mode)).map_err(ResponseError::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn fchmod(&mut self, fd: u64, mode: u32) -> RemoteResult<()> {
+        let permissions = Permissions::from_mode(mode);
+
+        match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => file.set_permissions(permissions)?,
+            RemoteFile::Directory(path) => std::fs::set_permissions(path, permissions)?,
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn chown(
+        &mut self,
+        path: PathBuf,
+        user_id: Option<u32>,
+        group_id: Option<u32>,
+        follow_symlink: bool,
+    ) -> RemoteResult<()> {
+        if follow_symlink {
+            std::os::unix::fs::chown(resolve_path(path, &self.root_path)?, user_id, group_id)?;
+        } else {
+            std::os::unix::fs::lchown(self.unresolved_path(path)?, user_id, group_id)?;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn truncate(&mut self, path: PathBuf, length: u64) -> RemoteResult<()> {
+        let path = resolve_path(path, &self.root_path)?;
+
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(length)
+            .map_err(ResponseError::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn ftruncate(&mut self, fd: u64, length: u64) -> RemoteResult<()> {
+        match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => file.set_len(length).map_err(ResponseError::from),
+            RemoteFile::Directory(..) => Err(ResponseError::NotFile(fd)),
+        }
+    }
+
+    /// Creates the symbolic link at `link_path`. The `target` is stored verbatim, so absolute
+    /// targets are interpreted relative to the target's root, just like they would be by the
+    /// target itself.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn symlink(&mut self, target: PathBuf, link_path: PathBuf) -> RemoteResult<()> {
+        let link_path = self.unresolved_path(link_path)?;
+
+        std::os::unix::fs::symlink(target, link_path).map_err(ResponseError::from)
+    }
+
+    /// Handles `utimensat` and friends with [`libc::utimensat`], as [`std`] has no way of
+    /// changing the timestamps of a symbolic link or setting them to `UTIME_NOW`.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn utimes(
+        &mut self,
+        path: PathBuf,
+        access_time: FileTimeUpdate,
+        modification_time: FileTimeUpdate,
+        follow_symlink: bool,
+    ) -> RemoteResult<()> {
+        let path = if follow_symlink {
+            resolve_path(path, &self.root_path)?
+        } else {
+            self.unresolved_path(path)?
+        };
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(std::io::Error::from)?;
+
+        let to_timespec = |update| match update {
+            FileTimeUpdate::Omit => libc::timespec {
+                tv_sec: 0,
+                tv_nsec: libc::UTIME_OMIT,
+            },
+            FileTimeUpdate::Now => libc::timespec {
+                tv_sec: 0,
+                tv_nsec: libc::UTIME_NOW,
+            },
+            FileTimeUpdate::Set {
+                seconds,
+                nanoseconds,
+            } => libc::timespec {
+                tv_sec: seconds,
+                tv_nsec: nanoseconds.into(),
+            },
+        };
+        let times = [to_timespec(access_time), to_timespec(modification_time)];
+        let flags = if follow_symlink {
+            0
+        } else {
+            libc::AT_SYMLINK_NOFOLLOW
+        };
+
+        // SAFETY: `path` is a valid C string and `times` has exactly 2 elements.
+        let result =
+            unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), flags) };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error().into())
+        }
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     pub(crate) fn write_limited(
         &mut self,
@@ -623,7 +881,7 @@ impl FileManager {
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
-    pub(crate) fn xstatfs(&mut self, fd: u64) -> RemoteResult<XstatFsResponse> {
+    pub(crate) fn xstatfs(&mut self, fd: u64) -> RemoteResult<Statfs> {
         let target = self
             .open_files
             .get(&fd)
@@ -636,9 +894,15 @@ impl FileManager {
                 .map_err(|err| std::io::Error::from_raw_os_error(err as i32))?,
         };
 
-        Ok(XstatFsResponse {
-            metadata: statfs.into(),
-        })
+        Ok(statfs)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn statfs(&mut self, path: PathBuf) -> RemoteResult<Statfs> {
+        let path = resolve_path(path, &self.root_path)?;
+
+        nix::sys::statfs::statfs(&path)
+            .map_err(|err| std::io::Error::from_raw_os_error(err as i32).into())
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
@@ -0,0 +1,191 @@
+//! Watches remote files for the client with inotify, see [`FileWatcher`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    os::fd::{AsFd, AsRawFd, RawFd},
+    path::PathBuf,
+};
+
+use mirrord_protocol::{
+    file::{AddWatchRequest, AddWatchResponse, FileWatchEvent, RemoveWatchRequest},
+    RemoteResult,
+};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use tokio::io::unix::AsyncFd;
+use tracing::Level;
+
+use crate::{
+    error::Result,
+    file::{get_root_path_from_optional_pid, resolve_path},
+};
+
+/// [`Inotify`] does not implement [`AsRawFd`], which is required by [`AsyncFd`].
+struct InotifyFd(Inotify);
+
+impl AsRawFd for InotifyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_fd().as_raw_fd()
+    }
+}
+
+/// Handles [`AddWatchRequest`]s and [`RemoveWatchRequest`]s of a single client, and produces the
+/// [`FileWatchEvent`]s of its watches.
+///
+/// The paths are resolved in the target's root, like in [`FileManager`](crate::file::FileManager),
+/// and watched with a single inotify instance, created on the first request.
+pub(crate) struct FileWatcher {
+    root_path: PathBuf,
+    inotify: Option<AsyncFd<InotifyFd>>,
+    /// Ids of the active watches, by their inotify descriptors.
+    watch_ids: HashMap<WatchDescriptor, u64>,
+    next_watch_id: u64,
+    /// Events read from inotify and not yet returned from [`FileWatcher::next_event`].
+    pending: VecDeque<FileWatchEvent>,
+}
+
+impl FileWatcher {
+    pub(crate) fn new(pid: Option<u64>) -> Self {
+        Self {
+            root_path: get_root_path_from_optional_pid(pid),
+            inotify: None,
+            watch_ids: Default::default(),
+            next_watch_id: 0,
+            pending: Default::default(),
+        }
+    }
+
+    /// Starts watching the given path.
+    ///
+    /// Watches of the same file share the inotify descriptor, so a repeated request extends the
+    /// mask of the existing watch (`IN_MASK_ADD`) and returns its id. `IN_ONESHOT` is ignored, as
+    /// it would remove the watch for all of its users.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub(crate) fn add_watch(
+        &mut self,
+        AddWatchRequest { path, mask }: AddWatchRequest,
+    ) -> RemoteResult<AddWatchResponse> {
+        let path = resolve_path(path, &self.root_path)?;
+
+        let inotify = match &mut self.inotify {
+            Some(inotify) => inotify,
+            inotify @ None => {
+                let fd = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+                    .map_err(io::Error::from)?;
+                inotify.insert(AsyncFd::new(InotifyFd(fd))?)
+            }
+        };
+
+        let flags = (AddWatchFlags::from_bits_truncate(mask) - AddWatchFlags::IN_ONESHOT)
+            | AddWatchFlags::from_bits_retain(libc::IN_MASK_ADD);
+        let descriptor = inotify
+            .get_ref()
+            .0
+            .add_watch(&path, flags)
+            .map_err(io::Error::from)?;
+
+        let watch_id = *self.watch_ids.entry(descriptor).or_insert_with(|| {
+            self.next_watch_id += 1;
+            self.next_watch_id
+        });
+
+        Ok(AddWatchResponse { watch_id })
+    }
+
+    /// Stops the watch with the given id.
+    ///
+    /// The watch is forgotten when inotify reports `IN_IGNORED` for it, which is passed on to the
+    /// client as the last event of the watch.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn remove_watch(&mut self, RemoveWatchRequest { watch_id }: RemoveWatchRequest) {
+        let (Some(inotify), Some(descriptor)) = (
+            &self.inotify,
+            self.watch_ids
+                .iter()
+                .find_map(|(descriptor, id)| (*id == watch_id).then_some(*descriptor)),
+        ) else {
+            return;
+        };
+
+        if let Err(error) = inotify.get_ref().0.rm_watch(descriptor) {
+            tracing::warn!(%error, watch_id, "Failed to remove an inotify watch");
+        }
+    }
+
+    /// Returns the next event of the active watches.
+    ///
+    /// Never returns when there are no watches, so it can be used in a `select!` loop.
+    ///
+    /// Failing to read from inotify ends the watches of this client only, each of them gets
+    /// `IN_IGNORED` like after a removal.
+    pub(crate) async fn next_event(&mut self) -> FileWatchEvent {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return event;
+            }
+
+            let Some(inotify) = &self.inotify else {
+                return std::future::pending().await;
+            };
+
+            match Self::read_events(inotify).await {
+                Ok(Some(events)) => {
+                    for event in events {
+                        self.event_received(event);
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => {
+                    tracing::warn!(%error, "Failed to read inotify events, removing the watches");
+                    self.inotify = None;
+                    self.pending
+                        .extend(self.watch_ids.drain().map(|(_, watch_id)| FileWatchEvent {
+                            watch_id,
+                            mask: AddWatchFlags::IN_IGNORED.bits(),
+                            cookie: 0,
+                            name: None,
+                        }));
+                }
+            }
+        }
+    }
+
+    /// Waits for the inotify instance to become readable and reads the events.
+    ///
+    /// Returns [`None`] if there was nothing to read after all.
+    async fn read_events(inotify: &AsyncFd<InotifyFd>) -> Result<Option<Vec<InotifyEvent>>> {
+        let mut guard = inotify.readable().await?;
+        match guard.try_io(|fd| fd.get_ref().0.read_events().map_err(Into::into)) {
+            Ok(events) => Ok(Some(events?)),
+            Err(_would_block) => Ok(None),
+        }
+    }
+
+    /// Translates an [`InotifyEvent`] into [`FileWatchEvent`]s.
+    fn event_received(&mut self, event: InotifyEvent) {
+        let InotifyEvent {
+            wd,
+            mask,
+            cookie,
+            name,
+        } = event;
+        let name = name.map(|name| name.to_string_lossy().into_owned());
+
+        // Overflow is not tied to any watch, but concerns all of them.
+        let watch_ids = if mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+            self.watch_ids.values().copied().collect::<Vec<_>>()
+        } else if mask.contains(AddWatchFlags::IN_IGNORED) {
+            self.watch_ids.remove(&wd).into_iter().collect()
+        } else {
+            self.watch_ids.get(&wd).copied().into_iter().collect()
+        };
+
+        self.pending
+            .extend(watch_ids.into_iter().map(|watch_id| FileWatchEvent {
+                watch_id,
+                mask: mask.bits(),
+                cookie,
+                name: name.clone(),
+            }));
+    }
+}
@@ -22,6 +22,8 @@ mod error;
 #[cfg(target_os = "linux")]
 mod file;
 #[cfg(target_os = "linux")]
+mod file_watch;
+#[cfg(target_os = "linux")]
 mod http;
 #[cfg(target_os = "linux")]
 mod namespace;
@@ -34,6 +36,8 @@ mod sniffer;
 #[cfg(target_os = "linux")]
 mod steal;
 #[cfg(target_os = "linux")]
+mod udp_incoming;
+#[cfg(target_os = "linux")]
 mod util;
 #[cfg(target_os = "linux")]
 mod vpn;
@@ -2,7 +2,7 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     fmt,
     hash::{Hash, Hasher},
-    net::Ipv4Addr,
+    net::IpAddr,
 };
 
 use futures::{stream::FuturesUnordered, StreamExt};
@@ -21,6 +21,7 @@ use tracing::Level;
 
 use self::{
     messages::{SniffedConnection, SnifferCommand, SnifferCommandInner},
+    reassembly::TcpStreamReassembler,
     tcp_capture::RawSocketTcpCapture,
 };
 use crate::{
@@ -30,8 +31,11 @@ use crate::{
 };
 
 pub(crate) mod api;
+mod bpf;
 pub(crate) mod messages;
+mod reassembly;
 pub(crate) mod tcp_capture;
+pub(crate) mod udp_capture;
 
 #[derive(Debug, Eq, Copy, Clone)]
 pub(crate) struct TcpSessionIdentifier {
@@ -41,7 +45,7 @@ pub(crate) struct TcpSessionIdentifier {
     ///
     /// If you were to `curl {impersonated_pod_ip}:{port}`, this would be the address of whoever
     /// is making the request.
-    pub(crate) source_addr: Ipv4Addr,
+    pub(crate) source_addr: IpAddr,
 
     /// Local address of the impersonated pod.
     ///
@@ -54,7 +58,7 @@ pub(crate) struct TcpSessionIdentifier {
     /// NAME        READY   STATUS    IP
     /// happy-pod   1/1     Running   1.2.3.4   
     /// ```
-    pub(crate) dest_addr: Ipv4Addr,
+    pub(crate) dest_addr: IpAddr,
     pub(crate) source_port: u16,
     pub(crate) dest_port: u16,
 }
@@ -92,7 +96,29 @@ impl Hash for TcpSessionIdentifier {
     }
 }
 
-type TCPSessionMap = HashMap<TcpSessionIdentifier, broadcast::Sender<Vec<u8>>>;
+/// State of a sniffed TCP session.
+#[derive(Debug)]
+struct TcpSession {
+    /// Identifier of the session as seen in the packets sent by the remote peer.
+    ///
+    /// Packets going in the other direction match the same [`TcpSessionIdentifier`], but they are
+    /// not part of the mirrored stream.
+    identifier: TcpSessionIdentifier,
+    /// Used to distribute the mirrored stream between the clients.
+    data_tx: broadcast::Sender<Vec<u8>>,
+    /// Puts payloads of the sniffed packets in order.
+    reassembler: TcpStreamReassembler,
+}
+
+impl TcpSession {
+    /// Checks whether the packet with the given `identifier` was sent by the remote peer.
+    fn is_incoming(&self, identifier: &TcpSessionIdentifier) -> bool {
+        self.identifier.source_addr == identifier.source_addr
+            && self.identifier.source_port == identifier.source_port
+    }
+}
+
+type TCPSessionMap = HashMap<TcpSessionIdentifier, TcpSession>;
 
 const fn is_new_connection(flags: u8) -> bool {
     0 != (flags & TcpFlags::SYN) && 0 == (flags & (TcpFlags::ACK | TcpFlags::RST | TcpFlags::FIN))
@@ -106,6 +132,21 @@ fn is_closed_connection(flags: u8) -> bool {
 pub(crate) struct TcpPacketData {
     bytes: Vec<u8>,
     flags: u8,
+    /// Sequence number of the TCP segment.
+    sequence: u32,
+}
+
+impl TcpPacketData {
+    /// Returns the sequence number of the first payload byte.
+    ///
+    /// `SYN` consumes one sequence number, so in a `SYN` segment the payload starts right after it.
+    fn payload_sequence(&self) -> u32 {
+        if 0 != (self.flags & TcpFlags::SYN) {
+            self.sequence.wrapping_add(1)
+        } else {
+            self.sequence
+        }
+    }
 }
 
 /// Main struct implementing incoming traffic mirroring feature.
@@ -252,7 +293,7 @@ where
             tracing::trace!("No ports subscribed, setting dummy bpf");
             rawsocket::filter::build_drop_always()
         } else {
-            rawsocket::filter::build_tcp_port_filter(&ports)
+            bpf::build_tcp_port_filter(&ports)
         };
 
         self.tcp_capture.set_filter(filter)?;
@@ -326,7 +367,7 @@ where
         identifier: TcpSessionIdentifier,
         tcp_packet: TcpPacketData,
     ) -> Result<(), AgentError> {
-        let data_tx = match self.sessions.entry(identifier) {
+        let mut session = match self.sessions.entry(identifier) {
             Entry::Occupied(e) => e,
             Entry::Vacant(e) => {
                 // Performs a check on the `tcp_flags` and on the packet contents to see if this
@@ -394,21 +435,45 @@ where
                     }
                 }
 
-                e.insert_entry(data_tx)
+                e.insert_entry(TcpSession {
+                    identifier,
+                    data_tx,
+                    reassembler: TcpStreamReassembler::new(tcp_packet.payload_sequence()),
+                })
             }
         };
 
         tracing::trace!("Resolved data broadcast channel");
 
-        if !tcp_packet.bytes.is_empty() && data_tx.get().send(tcp_packet.bytes).is_err() {
+        let is_closed = is_closed_connection(tcp_packet.flags);
+        let is_incoming = session.get().is_incoming(&identifier);
+
+        let TcpSession {
+            data_tx,
+            reassembler,
+            ..
+        } = session.get_mut();
+
+        let bytes = if is_incoming {
+            let mut bytes = reassembler.push(tcp_packet.payload_sequence(), tcp_packet.bytes);
+            if is_closed {
+                bytes.extend(reassembler.flush());
+            }
+            bytes
+        } else {
+            tracing::trace!("TCP packet was sent by the local peer, ignoring its payload");
+            Vec::new()
+        };
+
+        if !bytes.is_empty() && data_tx.send(bytes).is_err() {
             tracing::trace!("All data receivers are dead, dropping data broadcast sender");
-            data_tx.remove();
+            session.remove();
             return Ok(());
         }
 
-        if is_closed_connection(tcp_packet.flags) {
+        if is_closed {
             tracing::trace!("TCP packet closes connection, dropping data broadcast channel");
-            data_tx.remove();
+            session.remove();
         }
 
         Ok(())
@@ -518,6 +583,7 @@ mod test {
                     TcpPacketData {
                         bytes: b"hello_1".into(),
                         flags: TcpFlags::SYN,
+                        sequence: 0,
                     },
                 ))
                 .await
@@ -535,6 +601,7 @@ mod test {
                     TcpPacketData {
                         bytes: b"hello_2".into(),
                         flags: TcpFlags::FIN,
+                        sequence: 8,
                     },
                 ))
                 .await
@@ -692,6 +759,7 @@ mod test {
                 TcpPacketData {
                     bytes: b"hello".into(),
                     flags: TcpFlags::SYN,
+                    sequence: 0,
                 },
             ))
             .await
@@ -702,10 +770,10 @@ mod test {
             message,
             DaemonTcp::NewConnection(NewTcpConnection {
                 connection_id: 0,
-                remote_address: session_id.source_addr.into(),
+                remote_address: session_id.source_addr,
                 destination_port: session_id.dest_port,
                 source_port: session_id.source_port,
-                local_address: session_id.dest_addr.into(),
+                local_address: session_id.dest_addr,
             }),
         );
         assert_eq!(log, None);
@@ -720,7 +788,7 @@ mod test {
         );
         assert_eq!(log, None);
 
-        for _ in 0..TcpConnectionSniffer::<TcpPacketsChannel>::CONNECTION_DATA_CHANNEL_CAPACITY + 2
+        for i in 0..TcpConnectionSniffer::<TcpPacketsChannel>::CONNECTION_DATA_CHANNEL_CAPACITY + 2
         {
             setup
                 .packet_tx
@@ -729,6 +797,7 @@ mod test {
                     TcpPacketData {
                         bytes: vec![0],
                         flags: 0,
+                        sequence: 6 + i as u32,
                     },
                 ))
                 .await
@@ -783,6 +852,7 @@ mod test {
                     TcpPacketData {
                         bytes: Default::default(),
                         flags: TcpFlags::SYN,
+                        sequence: 0,
                     },
                 ))
                 .await
@@ -805,10 +875,10 @@ mod test {
                 msg,
                 DaemonTcp::NewConnection(NewTcpConnection {
                     connection_id: i as ConnectionId,
-                    remote_address: source_addr.into(),
+                    remote_address: source_addr,
                     destination_port: 80,
                     source_port: 3000 + i as u16,
-                    local_address: dest_addr.into(),
+                    local_address: dest_addr,
                 })
             )
         }
@@ -826,6 +896,7 @@ mod test {
                 TcpPacketData {
                     bytes: Default::default(),
                     flags: TcpFlags::SYN,
+                    sequence: 0,
                 },
             ))
             .await
@@ -838,11 +909,84 @@ mod test {
             msg,
             DaemonTcp::NewConnection(NewTcpConnection {
                 connection_id: TcpSnifferApi::CONNECTION_CHANNEL_SIZE as ConnectionId,
-                remote_address: source_addr.into(),
+                remote_address: source_addr,
                 destination_port: 80,
                 source_port: 3222,
-                local_address: dest_addr.into(),
+                local_address: dest_addr,
             }),
         );
     }
+
+    /// Simulates retransmitted and reordered packets, the client should receive an in-order
+    /// stream without duplicates.
+    #[tokio::test]
+    async fn reordered_and_retransmitted_packets() {
+        let mut setup = TestSnifferSetup::new();
+        let mut api = setup.get_api().await;
+
+        api.handle_client_message(LayerTcp::PortSubscribe(80))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            api.recv().await.unwrap(),
+            (DaemonTcp::SubscribeResult(Ok(80)), None),
+        );
+
+        let session_id = TcpSessionIdentifier {
+            source_addr: "1.1.1.1".parse().unwrap(),
+            dest_addr: "127.0.0.1".parse().unwrap(),
+            source_port: 3133,
+            dest_port: 80,
+        };
+        let response_id = TcpSessionIdentifier {
+            source_addr: session_id.dest_addr,
+            dest_addr: session_id.source_addr,
+            source_port: session_id.dest_port,
+            dest_port: session_id.source_port,
+        };
+
+        let packets: [(TcpSessionIdentifier, &[u8], u8, u32); 7] = [
+            (session_id, b"", TcpFlags::SYN, 99),
+            (session_id, b"hello ", TcpFlags::ACK, 100),
+            (session_id, b"world", TcpFlags::ACK, 106),
+            (session_id, b"hello ", TcpFlags::ACK, 100),
+            (response_id, b"response", TcpFlags::ACK, 5000),
+            (session_id, b"!", TcpFlags::FIN, 115),
+            (session_id, b" :) ", TcpFlags::ACK, 111),
+        ];
+        for (identifier, bytes, flags, sequence) in packets {
+            setup
+                .packet_tx
+                .send((
+                    identifier,
+                    TcpPacketData {
+                        bytes: bytes.into(),
+                        flags,
+                        sequence,
+                    },
+                ))
+                .await
+                .unwrap();
+        }
+
+        let (message, log) = api.recv().await.unwrap();
+        assert!(matches!(message, DaemonTcp::NewConnection(..)));
+        assert_eq!(log, None);
+
+        let mut received = Vec::new();
+        loop {
+            let (message, log) = api.recv().await.unwrap();
+            assert_eq!(log, None);
+
+            match message {
+                DaemonTcp::Data(TcpData { bytes, .. }) => received.extend(bytes),
+                DaemonTcp::Close(..) => break,
+                other => panic!("unexpected message {other:?}"),
+            }
+        }
+
+        // The FIN packet came before the ` :) ` segment, so the gap could not be filled.
+        assert_eq!(received, b"hello world!");
+    }
 }
@@ -105,8 +105,8 @@ impl TcpSnifferApi {
                     Ok((
                         DaemonTcp::NewConnection(NewTcpConnection {
                             connection_id: id,
-                            remote_address: conn.session_id.source_addr.into(),
-                            local_address: conn.session_id.dest_addr.into(),
+                            remote_address: conn.session_id.source_addr,
+                            local_address: conn.session_id.dest_addr,
                             source_port: conn.session_id.source_port,
                             destination_port: conn.session_id.dest_port,
                         }),
@@ -0,0 +1,376 @@
+//! Generation of the BPF programs attached to the sniffer's raw sockets.
+//!
+//! We don't use [`rawsocket::filter::build_tcp_port_filter`], because it only accepts IPv4
+//! packets.
+
+use libc::sock_filter;
+use rawsocket::filter::SocketFilterProgram;
+
+use super::tcp_capture::{ETHERNET_HEADER_LEN, IPV6_EXTENSION_HEADERS, IPV6_HEADER_LEN};
+
+/// `ldh [k]`
+const LD_H_ABS: u16 = 0x28;
+/// `ldb [k]`
+const LD_B_ABS: u16 = 0x30;
+/// `ldh [x + k]`
+const LD_H_IND: u16 = 0x48;
+/// `ldxb 4 * ([k] & 0xf)`
+const LDX_B_MSH: u16 = 0xb1;
+/// `jeq #k`
+const JMP_JEQ_K: u16 = 0x15;
+/// `jset #k`
+const JMP_JSET_K: u16 = 0x45;
+/// `ret #k`
+const RET_K: u16 = 0x06;
+
+/// How many bytes of an accepted packet are passed to the socket.
+const ACCEPT_SNAP_LEN: u32 = 0x0004_0000;
+
+const ETHER_TYPE_IPV4: u32 = 0x0800;
+const ETHER_TYPE_IPV6: u32 = 0x86dd;
+const IP_PROTOCOL_TCP: u32 = 6;
+const IP_PROTOCOL_UDP: u32 = 17;
+
+/// Offset of the ethertype in the Ethernet frame.
+const ETHER_TYPE_OFFSET: u32 = 12;
+/// Offset of the protocol field in the Ethernet frame carrying an IPv4 packet.
+const IPV4_PROTOCOL_OFFSET: u32 = ETHERNET_HEADER_LEN as u32 + 9;
+/// Offset of the flags and fragment offset fields in the Ethernet frame carrying an IPv4 packet.
+const IPV4_FRAGMENT_OFFSET: u32 = ETHERNET_HEADER_LEN as u32 + 6;
+/// Mask for the fragment offset (without the flags).
+const IPV4_FRAGMENT_OFFSET_MASK: u32 = 0x1fff;
+/// Offset of the next header field in the Ethernet frame carrying an IPv6 packet.
+const IPV6_NEXT_HEADER_OFFSET: u32 = ETHERNET_HEADER_LEN as u32 + 6;
+/// Offset of the TCP/UDP header in the Ethernet frame carrying an IPv6 packet without extension
+/// headers.
+const IPV6_TRANSPORT_OFFSET: u32 = (ETHERNET_HEADER_LEN + IPV6_HEADER_LEN) as u32;
+
+/// Jump target of a [`Instruction`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    Next,
+    Accept,
+    Drop,
+    Ipv6,
+    Ipv6ExtensionHeaders,
+}
+
+struct Instruction {
+    code: u16,
+    k: u32,
+    jt: Jump,
+    jf: Jump,
+}
+
+impl Instruction {
+    fn stmt(code: u16, k: u32) -> Self {
+        Self {
+            code,
+            k,
+            jt: Jump::Next,
+            jf: Jump::Next,
+        }
+    }
+
+    fn jump(code: u16, k: u32, jt: Jump, jf: Jump) -> Self {
+        Self { code, k, jt, jf }
+    }
+}
+
+/// Builds a BPF program that accepts only Ethernet frames carrying TCP segments with source or
+/// destination port in `ports`, over both IPv4 and IPv6.
+///
+/// IPv6 packets with extension headers are accepted regardless of the ports (classic BPF cannot
+/// loop over the header chain), and are filtered later in
+/// [`RawSocketTcpCapture`](super::tcp_capture::RawSocketTcpCapture). Non-initial IPv4 fragments
+/// are dropped.
+///
+/// If the program would be too long for the 8-bit jump offsets (very large number of ports),
+/// falls back to accepting all TCP traffic.
+pub(crate) fn build_tcp_port_filter(ports: &[u16]) -> SocketFilterProgram {
+    build_port_filter(IP_PROTOCOL_TCP, ports)
+}
+
+/// Same as [`build_tcp_port_filter`], but accepts UDP datagrams.
+pub(crate) fn build_udp_port_filter(ports: &[u16]) -> SocketFilterProgram {
+    build_port_filter(IP_PROTOCOL_UDP, ports)
+}
+
+fn build_port_filter(protocol: u32, ports: &[u16]) -> SocketFilterProgram {
+    let instructions = assemble(&port_filter(protocol, Some(ports)))
+        .or_else(|| assemble(&port_filter(protocol, None)))
+        .expect("BPF program without port checks should always fit");
+
+    SocketFilterProgram::new(instructions)
+}
+
+/// Returns the instructions of the program described in [`build_tcp_port_filter`], for the given
+/// IP `protocol`.
+///
+/// Passing [`None`] as `ports` skips port checks.
+fn port_filter(protocol: u32, ports: Option<&[u16]>) -> Vec<(Option<Jump>, Instruction)> {
+    let port_checks =
+        |load: u16, offset: u32| {
+            let mut instructions = Vec::new();
+            if let Some(ports) = ports {
+                instructions.push(Instruction::stmt(load, offset));
+                instructions.extend(ports.iter().map(|port| {
+                    Instruction::jump(JMP_JEQ_K, *port as u32, Jump::Accept, Jump::Next)
+                }));
+            }
+            instructions
+        };
+
+    let mut program: Vec<(Option<Jump>, Instruction)> = vec![
+        (None, Instruction::stmt(LD_H_ABS, ETHER_TYPE_OFFSET)),
+        (
+            None,
+            Instruction::jump(JMP_JEQ_K, ETHER_TYPE_IPV4, Jump::Next, Jump::Ipv6),
+        ),
+        // IPv4
+        (None, Instruction::stmt(LD_B_ABS, IPV4_PROTOCOL_OFFSET)),
+        (
+            None,
+            Instruction::jump(JMP_JEQ_K, protocol, Jump::Next, Jump::Drop),
+        ),
+        (None, Instruction::stmt(LD_H_ABS, IPV4_FRAGMENT_OFFSET)),
+        (
+            None,
+            Instruction::jump(
+                JMP_JSET_K,
+                IPV4_FRAGMENT_OFFSET_MASK,
+                Jump::Drop,
+                Jump::Next,
+            ),
+        ),
+        (
+            None,
+            Instruction::stmt(LDX_B_MSH, ETHERNET_HEADER_LEN as u32),
+        ),
+    ];
+    program.extend(
+        port_checks(LD_H_IND, ETHERNET_HEADER_LEN as u32)
+            .into_iter()
+            .chain(port_checks(LD_H_IND, ETHERNET_HEADER_LEN as u32 + 2))
+            .map(|instruction| (None, instruction)),
+    );
+    program.push((
+        None,
+        Instruction::stmt(RET_K, if ports.is_some() { 0 } else { ACCEPT_SNAP_LEN }),
+    ));
+
+    // IPv6, the ethertype is still loaded.
+    program.push((
+        Some(Jump::Ipv6),
+        Instruction::jump(JMP_JEQ_K, ETHER_TYPE_IPV6, Jump::Next, Jump::Drop),
+    ));
+    program.push((None, Instruction::stmt(LD_B_ABS, IPV6_NEXT_HEADER_OFFSET)));
+    program.push((
+        None,
+        Instruction::jump(JMP_JEQ_K, protocol, Jump::Next, Jump::Ipv6ExtensionHeaders),
+    ));
+    program.extend(
+        port_checks(LD_H_ABS, IPV6_TRANSPORT_OFFSET)
+            .into_iter()
+            .chain(port_checks(LD_H_ABS, IPV6_TRANSPORT_OFFSET + 2))
+            .map(|instruction| (None, instruction)),
+    );
+    program.push((
+        None,
+        Instruction::stmt(RET_K, if ports.is_some() { 0 } else { ACCEPT_SNAP_LEN }),
+    ));
+
+    // IPv6 extension headers, the next header is still loaded.
+    for (i, next_header) in IPV6_EXTENSION_HEADERS.iter().enumerate() {
+        program.push((
+            (i == 0).then_some(Jump::Ipv6ExtensionHeaders),
+            Instruction::jump(JMP_JEQ_K, *next_header as u32, Jump::Accept, Jump::Next),
+        ));
+    }
+
+    program.push((Some(Jump::Drop), Instruction::stmt(RET_K, 0)));
+    program.push((
+        Some(Jump::Accept),
+        Instruction::stmt(RET_K, ACCEPT_SNAP_LEN),
+    ));
+
+    program
+}
+
+/// Resolves jump targets in the given labeled instructions.
+///
+/// Returns [`None`] if any of the jumps does not fit in the 8-bit offset.
+fn assemble(program: &[(Option<Jump>, Instruction)]) -> Option<Vec<sock_filter>> {
+    let position = |label: Jump| {
+        program
+            .iter()
+            .position(|(instruction_label, _)| *instruction_label == Some(label))
+            .expect("jump target should be present in the program")
+    };
+
+    program
+        .iter()
+        .enumerate()
+        .map(|(i, (_, instruction))| {
+            let offset = |jump: Jump| match jump {
+                Jump::Next => Some(0),
+                label => u8::try_from(position(label).checked_sub(i + 1)?).ok(),
+            };
+
+            Some(sock_filter {
+                code: instruction.code,
+                jt: offset(instruction.jt)?,
+                jf: offset(instruction.jf)?,
+                k: instruction.k,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Minimal interpreter of the instructions used in [`port_filter`].
+    fn run(program: &[sock_filter], packet: &[u8]) -> u32 {
+        let load = |offset: usize, size: usize| {
+            packet
+                .get(offset..offset + size)
+                .map(|bytes| bytes.iter().fold(0_u32, |acc, b| (acc << 8) | *b as u32))
+        };
+
+        let (mut a, mut x, mut pc) = (0_u32, 0_u32, 0_usize);
+        loop {
+            let instruction = program.get(pc).expect("program counter out of bounds");
+            pc += 1;
+
+            match instruction.code {
+                LD_H_ABS => match load(instruction.k as usize, 2) {
+                    Some(value) => a = value,
+                    None => return 0,
+                },
+                LD_B_ABS => match load(instruction.k as usize, 1) {
+                    Some(value) => a = value,
+                    None => return 0,
+                },
+                LD_H_IND => match load((x + instruction.k) as usize, 2) {
+                    Some(value) => a = value,
+                    None => return 0,
+                },
+                LDX_B_MSH => match load(instruction.k as usize, 1) {
+                    Some(value) => x = 4 * (value & 0xf),
+                    None => return 0,
+                },
+                JMP_JEQ_K | JMP_JSET_K => {
+                    let matched = if instruction.code == JMP_JEQ_K {
+                        a == instruction.k
+                    } else {
+                        a & instruction.k != 0
+                    };
+
+                    pc += if matched {
+                        instruction.jt
+                    } else {
+                        instruction.jf
+                    } as usize;
+                }
+                RET_K => return instruction.k,
+                other => panic!("unexpected instruction {other:#x}"),
+            }
+        }
+    }
+
+    fn ethernet(ether_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; ETHER_TYPE_OFFSET as usize];
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn tcp(source_port: u16, dest_port: u16) -> Vec<u8> {
+        [
+            &source_port.to_be_bytes()[..],
+            &dest_port.to_be_bytes(),
+            &[0; 16],
+        ]
+        .concat()
+    }
+
+    fn ipv4(protocol: u8, fragment: u16, payload: &[u8]) -> Vec<u8> {
+        [
+            &[0x45, 0, 0, 0, 0, 0][..],
+            &fragment.to_be_bytes(),
+            &[64, protocol],
+            &[0; 10],
+            payload,
+        ]
+        .concat()
+    }
+
+    fn ipv6(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        [
+            &[0x60, 0, 0, 0, 0, 0, next_header][..],
+            &[0; IPV6_HEADER_LEN - 7],
+            payload,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn filters_ipv4_and_ipv6() {
+        let program = assemble(&port_filter(IP_PROTOCOL_TCP, Some(&[80, 8080]))).unwrap();
+        let accepted = |packet: Vec<u8>| run(&program, &packet) != 0;
+
+        assert!(accepted(ethernet(0x0800, &ipv4(6, 0, &tcp(4000, 80)))));
+        assert!(accepted(ethernet(0x0800, &ipv4(6, 0, &tcp(8080, 4000)))));
+        assert!(!accepted(ethernet(0x0800, &ipv4(6, 0, &tcp(4000, 81)))));
+        assert!(!accepted(ethernet(0x0800, &ipv4(17, 0, &tcp(4000, 80)))));
+        assert!(!accepted(ethernet(
+            0x0800,
+            &ipv4(6, 0x0010, &tcp(4000, 80))
+        )));
+
+        assert!(accepted(ethernet(0x86dd, &ipv6(6, &tcp(4000, 8080)))));
+        assert!(accepted(ethernet(0x86dd, &ipv6(6, &tcp(80, 4000)))));
+        assert!(!accepted(ethernet(0x86dd, &ipv6(6, &tcp(4000, 81)))));
+        assert!(!accepted(ethernet(0x86dd, &ipv6(17, &tcp(4000, 80)))));
+        // Hop-by-hop options, checked later.
+        assert!(accepted(ethernet(0x86dd, &ipv6(0, &tcp(4000, 81)))));
+
+        assert!(!accepted(ethernet(0x0806, &[0; 28])));
+    }
+
+    #[test]
+    fn too_many_ports() {
+        let ports = (1..=1024).collect::<Vec<u16>>();
+        assert!(assemble(&port_filter(IP_PROTOCOL_TCP, Some(&ports))).is_none());
+
+        let program = assemble(&port_filter(IP_PROTOCOL_TCP, None)).unwrap();
+        assert_ne!(
+            run(&program, &ethernet(0x0800, &ipv4(6, 0, &tcp(4000, 5000)))),
+            0
+        );
+        assert_ne!(
+            run(&program, &ethernet(0x86dd, &ipv6(6, &tcp(4000, 5000)))),
+            0
+        );
+        assert_eq!(
+            run(&program, &ethernet(0x86dd, &ipv6(17, &tcp(4000, 5000)))),
+            0
+        );
+    }
+
+    #[test]
+    fn filters_udp() {
+        let program = assemble(&port_filter(IP_PROTOCOL_UDP, Some(&[53]))).unwrap();
+        let accepted = |packet: Vec<u8>| run(&program, &packet) != 0;
+
+        // UDP header starts with the ports, just like TCP.
+        assert!(accepted(ethernet(0x0800, &ipv4(17, 0, &tcp(4000, 53)))));
+        assert!(!accepted(ethernet(0x0800, &ipv4(17, 0, &tcp(4000, 54)))));
+        assert!(!accepted(ethernet(0x0800, &ipv4(6, 0, &tcp(4000, 53)))));
+
+        assert!(accepted(ethernet(0x86dd, &ipv6(17, &tcp(53, 4000)))));
+        assert!(!accepted(ethernet(0x86dd, &ipv6(6, &tcp(53, 4000)))));
+    }
+}
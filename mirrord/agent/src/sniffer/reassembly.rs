@@ -0,0 +1,219 @@
+//! Turns payloads of the sniffed TCP segments into an in-order, de-duplicated byte stream.
+
+use std::collections::BTreeMap;
+
+use tracing::Level;
+
+/// Reassembles one direction of a sniffed TCP session.
+///
+/// The raw socket gives us the segments exactly as they appeared on the wire, so we see
+/// retransmissions and we may see segments out of order. This struct tracks sequence numbers and
+/// buffers segments that arrive ahead of time, so that only the missing bytes are released.
+///
+/// Positions in the stream are kept as [`u64`] offsets from the first byte we've seen, so we don't
+/// have to deal with sequence number wrap-around when ordering the buffered segments.
+#[derive(Debug)]
+pub(crate) struct TcpStreamReassembler {
+    /// Sequence number of the first byte in the stream (offset `0`).
+    initial_sequence: u32,
+    /// Offset of the next byte we expect.
+    next_offset: u64,
+    /// Segments received ahead of [`Self::next_offset`], keyed by their offsets.
+    pending: BTreeMap<u64, Vec<u8>>,
+    /// Total size of [`Self::pending`] segments.
+    pending_bytes: usize,
+}
+
+impl TcpStreamReassembler {
+    /// Maximum amount of out-of-order bytes buffered per session.
+    ///
+    /// When this is exceeded, we stop waiting for the missing segments and skip the gap.
+    pub(crate) const MAX_PENDING_BYTES: usize = 256 * 1024;
+
+    /// Creates a new reassembler for a stream that starts with the byte with the given `sequence`
+    /// number.
+    pub(crate) fn new(sequence: u32) -> Self {
+        Self {
+            initial_sequence: sequence,
+            next_offset: 0,
+            pending: Default::default(),
+            pending_bytes: 0,
+        }
+    }
+
+    /// Returns the offset of the byte with the given `sequence` number, or [`None`] if the byte
+    /// lies before the beginning of the stream.
+    ///
+    /// The sequence number is interpreted relative to [`Self::next_offset`], within the 2^31
+    /// window allowed by TCP.
+    fn offset_of(&self, sequence: u32) -> Option<u64> {
+        let next_sequence = self.initial_sequence.wrapping_add(self.next_offset as u32);
+        let distance = sequence.wrapping_sub(next_sequence) as i32;
+
+        self.next_offset.checked_add_signed(distance.into())
+    }
+
+    /// Handles the `payload` of a segment, `sequence` is the sequence number of its first byte.
+    ///
+    /// Returns the bytes that can be released in order (possibly none).
+    #[tracing::instrument(level = Level::TRACE, skip(self, payload), fields(bytes = payload.len()))]
+    pub(crate) fn push(&mut self, sequence: u32, payload: Vec<u8>) -> Vec<u8> {
+        if payload.is_empty() {
+            return Vec::new();
+        }
+
+        let Some(offset) = self.offset_of(sequence) else {
+            tracing::trace!("Segment lies before the beginning of the stream, dropping");
+            return Vec::new();
+        };
+
+        let end = offset + payload.len() as u64;
+        if end <= self.next_offset {
+            tracing::trace!(offset, "Retransmitted segment, dropping");
+            return Vec::new();
+        }
+
+        self.insert_pending(offset, payload);
+
+        let mut released = self.release();
+
+        while self.pending_bytes > Self::MAX_PENDING_BYTES {
+            released.extend(self.skip_gap());
+        }
+
+        released
+    }
+
+    /// Releases all buffered bytes, skipping any gaps that were not filled.
+    ///
+    /// Used when the session is being closed.
+    pub(crate) fn flush(&mut self) -> Vec<u8> {
+        let mut released = Vec::new();
+
+        while !self.pending.is_empty() {
+            released.extend(self.skip_gap());
+        }
+
+        released
+    }
+
+    /// Buffers the segment, unless we already have a segment with the same offset that is at least
+    /// as long.
+    fn insert_pending(&mut self, offset: u64, payload: Vec<u8>) {
+        let replaced = match self.pending.get(&offset) {
+            Some(existing) if existing.len() >= payload.len() => return,
+            Some(existing) => existing.len(),
+            None => 0,
+        };
+
+        self.pending_bytes = self.pending_bytes - replaced + payload.len();
+        self.pending.insert(offset, payload);
+    }
+
+    /// Releases the buffered segments that continue the stream from [`Self::next_offset`],
+    /// trimming the bytes that were already released.
+    fn release(&mut self) -> Vec<u8> {
+        let mut released = Vec::new();
+
+        while let Some(entry) = self.pending.first_entry() {
+            let offset = *entry.key();
+            if offset > self.next_offset {
+                break;
+            }
+
+            let segment = entry.remove();
+            self.pending_bytes -= segment.len();
+
+            let already_released = (self.next_offset - offset) as usize;
+            if let Some(new_bytes) = segment.get(already_released..) {
+                released.extend_from_slice(new_bytes);
+                self.next_offset += new_bytes.len() as u64;
+            }
+        }
+
+        released
+    }
+
+    /// Gives up on the bytes missing before the first buffered segment, and releases what follows.
+    fn skip_gap(&mut self) -> Vec<u8> {
+        if let Some(&offset) = self.pending.keys().next() {
+            if offset > self.next_offset {
+                tracing::warn!(
+                    missing_bytes = offset - self.next_offset,
+                    buffered_bytes = self.pending_bytes,
+                    "Failed to fill a gap in a sniffed TCP stream, skipping the missing bytes",
+                );
+
+                self.next_offset = offset;
+            }
+        }
+
+        self.release()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn in_order() {
+        let mut reassembler = TcpStreamReassembler::new(101);
+
+        assert_eq!(reassembler.push(101, b"hello ".to_vec()), b"hello ");
+        assert_eq!(reassembler.push(107, b"world".to_vec()), b"world");
+    }
+
+    #[test]
+    fn retransmissions() {
+        let mut reassembler = TcpStreamReassembler::new(100);
+
+        assert_eq!(reassembler.push(100, b"hello ".to_vec()), b"hello ");
+        assert!(reassembler.push(100, b"hello ".to_vec()).is_empty());
+        assert!(reassembler.push(102, b"llo".to_vec()).is_empty());
+
+        // Retransmission that carries some new bytes.
+        assert_eq!(reassembler.push(103, b"lo world".to_vec()), b"world");
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut reassembler = TcpStreamReassembler::new(100);
+
+        assert!(reassembler.push(105, b"world".to_vec()).is_empty());
+        assert!(reassembler.push(110, b"!".to_vec()).is_empty());
+        assert_eq!(reassembler.push(100, b"hello".to_vec()), b"helloworld!");
+        assert_eq!(reassembler.pending_bytes, 0);
+    }
+
+    #[test]
+    fn sequence_wrap_around() {
+        let mut reassembler = TcpStreamReassembler::new(u32::MAX - 2);
+
+        assert!(reassembler.push(2, b"world".to_vec()).is_empty());
+        assert_eq!(
+            reassembler.push(u32::MAX - 2, b"hello".to_vec()),
+            b"helloworld"
+        );
+    }
+
+    #[test]
+    fn gap_not_filled() {
+        let mut reassembler = TcpStreamReassembler::new(0);
+
+        let segment = vec![1; TcpStreamReassembler::MAX_PENDING_BYTES / 2];
+        let far_sequence = 10 + segment.len() as u32;
+
+        assert!(reassembler.push(10, segment.clone()).is_empty());
+        assert!(reassembler.push(far_sequence, segment.clone()).is_empty());
+
+        // Buffer is full, the first 10 bytes are skipped.
+        let released = reassembler.push(far_sequence * 2, vec![2]);
+        assert_eq!(released.len(), segment.len() * 2);
+
+        // Bytes from before the skipped gap are too late now.
+        assert!(reassembler.push(0, vec![0; 10]).is_empty());
+
+        assert_eq!(reassembler.flush(), vec![2]);
+    }
+}
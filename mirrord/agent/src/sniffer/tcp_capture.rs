@@ -1,10 +1,14 @@
-use std::{io, net::SocketAddr};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
 
 use nix::sys::socket::SockaddrStorage;
 use pnet::packet::{
     ethernet::{EtherTypes, EthernetPacket},
-    ip::IpNextHeaderProtocols,
+    ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
     ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet,
     tcp::TcpPacket,
     Packet,
 };
@@ -15,6 +19,23 @@ use tracing::Level;
 use super::{TcpPacketData, TcpSessionIdentifier};
 use crate::error::AgentError;
 
+/// Length of the Ethernet frame header.
+pub(crate) const ETHERNET_HEADER_LEN: usize = 14;
+
+/// Length of the fixed IPv6 header.
+pub(crate) const IPV6_HEADER_LEN: usize = 40;
+
+/// IPv6 extension headers that we skip when looking for the TCP/UDP header: hop-by-hop options,
+/// routing, destination options and authentication header.
+///
+/// Fragment headers are not here on purpose, we don't reassemble fragmented packets.
+pub(crate) const IPV6_EXTENSION_HEADERS: [u8; 4] = [
+    IpNextHeaderProtocols::Hopopt.0,
+    IpNextHeaderProtocols::Ipv6Route.0,
+    IpNextHeaderProtocols::Ipv6Opts.0,
+    IpNextHeaderProtocols::Ah.0,
+];
+
 /// Trait for structs that are able to sniff incoming Ethernet packets and filter TCP packets.
 pub trait TcpCapture {
     /// Sets a filter for incoming Ethernet packets.
@@ -35,8 +56,21 @@ impl RawSocketTcpCapture {
     /// network interface for the raw OS socket.
     ///
     /// Returned instance initially uses a BPF filter that drops every packet.
-    #[tracing::instrument(level = Level::DEBUG, err)]
     pub async fn new(network_interface: Option<String>, is_mesh: bool) -> Result<Self, AgentError> {
+        let capture = Self::raw_capture(network_interface, is_mesh).await?;
+        Ok(Self { inner: capture })
+    }
+
+    /// Opens the raw OS socket used by [`Self::new`], also used by the
+    /// [`RawSocketUdpCapture`](super::udp_capture::RawSocketUdpCapture).
+    ///
+    /// Returned socket initially uses a BPF filter that drops every packet, and ignores outgoing
+    /// packets.
+    #[tracing::instrument(level = Level::DEBUG, err)]
+    pub(crate) async fn raw_capture(
+        network_interface: Option<String>,
+        is_mesh: bool,
+    ) -> Result<RawCapture, AgentError> {
         // Priority is whatever the user set as an option to mirrord, then we check if we're in a
         // mesh to use `lo` interface, otherwise we try to get the appropriate interface.
         let interface = match network_interface.or_else(|| is_mesh.then(|| "lo".to_string())) {
@@ -56,10 +90,11 @@ impl RawSocketTcpCapture {
         capture
             .ignore_outgoing()
             .map_err(AgentError::PacketIgnoreOutgoing)?;
-        Ok(Self { inner: capture })
+        Ok(capture)
     }
 
-    /// Connects to a remote address (`8.8.8.8:53`) so we can find which network interface to use.
+    /// Connects to a remote address (`8.8.8.8:53`, or `[2001:4860:4860::8888]:53` on IPv6-only
+    /// clusters) so we can find which network interface to use.
     ///
     /// Used when no `user_interface` is specified in [`Self::new`] to prevent mirrord from
     /// defaulting to the wrong network interface (`eth0`), as sometimes the user's machine doesn't
@@ -67,8 +102,14 @@ impl RawSocketTcpCapture {
     #[tracing::instrument(level = Level::DEBUG, err)]
     async fn resolve_interface() -> io::Result<Option<String>> {
         // Connect to a remote address so we can later get the default network interface.
-        let temporary_socket = UdpSocket::bind("0.0.0.0:0").await?;
-        temporary_socket.connect("8.8.8.8:53").await?;
+        let temporary_socket = match Self::connect_temporary_socket("0.0.0.0:0", "8.8.8.8:53").await
+        {
+            Ok(socket) => socket,
+            Err(error) => {
+                tracing::debug!(%error, "Failed to find an IPv4 route, trying IPv6");
+                Self::connect_temporary_socket("[::]:0", "[2001:4860:4860::8888]:53").await?
+            }
+        };
 
         // Create comparison address here with `port: 0`, to match the network interface's address
         // of `sin_port: 0`.
@@ -83,29 +124,97 @@ impl RawSocketTcpCapture {
         Ok(usable_interface_name)
     }
 
+    /// Binds a [`UdpSocket`] to `bind` and connects it to `connect` (no traffic is sent).
+    async fn connect_temporary_socket(bind: &str, connect: &str) -> io::Result<UdpSocket> {
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(connect).await?;
+        Ok(socket)
+    }
+
     /// Extracts TCP packet from the raw Ethernet packet given as bytes.
-    /// If the given Ethernet packet is not TCP, returns [`None`].
+    /// If the given Ethernet packet is not TCP over IPv4 or IPv6, returns [`None`].
     #[tracing::instrument(skip(eth_packet), level = Level::TRACE, fields(bytes = %eth_packet.len()))]
     fn get_tcp_packet(eth_packet: Vec<u8>) -> Option<(TcpSessionIdentifier, TcpPacketData)> {
         let eth_packet = EthernetPacket::new(&eth_packet[..])?;
-        let ip_packet = match eth_packet.get_ethertype() {
-            EtherTypes::Ipv4 => Ipv4Packet::new(eth_packet.payload())?,
-            _ => return None,
-        };
 
-        let tcp_packet = match ip_packet.get_next_level_protocol() {
-            IpNextHeaderProtocols::Tcp => TcpPacket::new(ip_packet.payload())?,
-            _ => return None,
-        };
+        match eth_packet.get_ethertype() {
+            EtherTypes::Ipv4 => {
+                let ip_packet = Ipv4Packet::new(eth_packet.payload())?;
+                if ip_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+                    return None;
+                }
+
+                Self::get_tcp_session(
+                    ip_packet.get_source().into(),
+                    ip_packet.get_destination().into(),
+                    ip_packet.payload(),
+                )
+            }
 
-        let dest_port = tcp_packet.get_destination();
-        let source_port = tcp_packet.get_source();
+            EtherTypes::Ipv6 => {
+                let ip_packet = Ipv6Packet::new(eth_packet.payload())?;
+                let (IpNextHeaderProtocols::Tcp, tcp_segment) = Self::skip_ipv6_extension_headers(
+                    ip_packet.get_next_header(),
+                    ip_packet.payload(),
+                )?
+                else {
+                    return None;
+                };
+
+                Self::get_tcp_session(
+                    ip_packet.get_source().into(),
+                    ip_packet.get_destination().into(),
+                    tcp_segment,
+                )
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Walks the IPv6 extension headers chain starting with `next_header`.
+    /// Returns the first header that is not one of [`IPV6_EXTENSION_HEADERS`] along with its
+    /// payload, or [`None`] if the packet is truncated.
+    pub(crate) fn skip_ipv6_extension_headers(
+        mut next_header: IpNextHeaderProtocol,
+        mut payload: &[u8],
+    ) -> Option<(IpNextHeaderProtocol, &[u8])> {
+        loop {
+            match next_header {
+                // The length of the authentication header is in 4-octet units, not counting the
+                // first 2 units.
+                IpNextHeaderProtocols::Ah => {
+                    let length = (*payload.get(1)? as usize + 2) * 4;
+                    next_header = IpNextHeaderProtocol::new(*payload.first()?);
+                    payload = payload.get(length..)?;
+                }
+
+                // The length of other extension headers is in 8-octet units, not counting the
+                // first unit.
+                other if IPV6_EXTENSION_HEADERS.contains(&other.0) => {
+                    let length = (*payload.get(1)? as usize + 1) * 8;
+                    next_header = IpNextHeaderProtocol::new(*payload.first()?);
+                    payload = payload.get(length..)?;
+                }
+
+                _ => break Some((next_header, payload)),
+            }
+        }
+    }
+
+    /// Builds the session identifier and the packet data from the given TCP segment.
+    fn get_tcp_session(
+        source_addr: IpAddr,
+        dest_addr: IpAddr,
+        tcp_segment: &[u8],
+    ) -> Option<(TcpSessionIdentifier, TcpPacketData)> {
+        let tcp_packet = TcpPacket::new(tcp_segment)?;
 
         let identifier = TcpSessionIdentifier {
-            source_addr: ip_packet.get_source(),
-            dest_addr: ip_packet.get_destination(),
-            source_port,
-            dest_port,
+            source_addr,
+            dest_addr,
+            source_port: tcp_packet.get_source(),
+            dest_port: tcp_packet.get_destination(),
         };
 
         tracing::trace!(session_identifier = ?identifier, "Got TCP packet");
@@ -114,6 +223,7 @@ impl RawSocketTcpCapture {
             identifier,
             TcpPacketData {
                 flags: tcp_packet.get_flags(),
+                sequence: tcp_packet.get_sequence(),
                 bytes: tcp_packet.payload().to_vec(),
             },
         ))
@@ -138,15 +248,75 @@ impl TcpCapture for RawSocketTcpCapture {
 
 #[cfg(test)]
 pub mod test {
-    use std::sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+    use std::{
+        net::Ipv6Addr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
     };
 
+    use pnet::packet::tcp::TcpFlags;
     use tokio::sync::mpsc::Receiver;
 
     use super::*;
 
+    /// Builds an Ethernet frame with an IPv6 packet carrying a TCP segment from port 4000 to port
+    /// 80 with payload `hello`, preceded by the given extension headers.
+    fn ipv6_frame(first_header: u8, extension_headers: &[u8]) -> Vec<u8> {
+        let tcp = [
+            &4000_u16.to_be_bytes()[..],
+            &80_u16.to_be_bytes(),
+            &[0; 8],
+            &[0x50, TcpFlags::SYN as u8],
+            &[0; 6],
+            b"hello",
+        ]
+        .concat();
+        let payload_length = (extension_headers.len() + tcp.len()) as u16;
+
+        [
+            &[0; 12][..],
+            &[0x86, 0xdd],
+            &[0x60, 0, 0, 0],
+            &payload_length.to_be_bytes(),
+            &[first_header, 64],
+            &"fd00::1".parse::<Ipv6Addr>().unwrap().octets(),
+            &"fd00::2".parse::<Ipv6Addr>().unwrap().octets(),
+            extension_headers,
+            &tcp,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn ipv6_tcp_packet() {
+        let (identifier, data) = RawSocketTcpCapture::get_tcp_packet(ipv6_frame(6, &[])).unwrap();
+        assert_eq!(identifier.source_addr, "fd00::1".parse::<IpAddr>().unwrap());
+        assert_eq!(identifier.dest_addr, "fd00::2".parse::<IpAddr>().unwrap());
+        assert_eq!(identifier.source_port, 4000);
+        assert_eq!(identifier.dest_port, 80);
+        assert_eq!(data.flags, TcpFlags::SYN);
+        assert_eq!(data.bytes, b"hello");
+    }
+
+    #[test]
+    fn ipv6_tcp_packet_with_extension_headers() {
+        // Hop-by-hop options (8 bytes), followed by destination options (16 bytes).
+        let extension_headers = [[60, 0].as_slice(), &[0; 6], &[6, 1], &[0; 14]].concat();
+        let (identifier, data) =
+            RawSocketTcpCapture::get_tcp_packet(ipv6_frame(0, &extension_headers)).unwrap();
+        assert_eq!(identifier.dest_port, 80);
+        assert_eq!(data.bytes, b"hello");
+
+        // Fragment header.
+        let fragment_header = [[6, 0].as_slice(), &[0; 6]].concat();
+        assert!(RawSocketTcpCapture::get_tcp_packet(ipv6_frame(44, &fragment_header)).is_none());
+
+        // Truncated extension header.
+        assert!(RawSocketTcpCapture::get_tcp_packet(ipv6_frame(0, &[6, 200])).is_none());
+    }
+
     /// Implementor of [`TcpCapture`] that returns packets received from an
     /// [`mpsc`](tokio::sync::mpsc) channel.
     pub struct TcpPacketsChannel {
@@ -0,0 +1,207 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+use mirrord_protocol::{udp::IncomingDatagram, Port};
+use pnet::packet::{
+    ethernet::{EtherTypes, EthernetPacket},
+    ip::IpNextHeaderProtocols,
+    ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet,
+    udp::UdpPacket,
+    Packet,
+};
+use rawsocket::RawCapture;
+use tracing::Level;
+
+use super::{bpf, tcp_capture::RawSocketTcpCapture};
+use crate::error::AgentError;
+
+/// Sniffs incoming UDP datagrams with a raw OS socket and a BPF filter.
+///
+/// Used to mirror incoming UDP traffic, see
+/// [`UdpIncomingTask`](crate::udp_incoming::UdpIncomingTask).
+pub(crate) struct RawSocketUdpCapture {
+    /// Raw OS socket.
+    inner: RawCapture,
+}
+
+impl RawSocketUdpCapture {
+    /// Creates a new instance. `network_interface` and `mesh` are used the same way as in
+    /// [`RawSocketTcpCapture::new`].
+    ///
+    /// Returned instance initially uses a BPF filter that drops every packet.
+    pub(crate) async fn new(
+        network_interface: Option<String>,
+        is_mesh: bool,
+    ) -> Result<Self, AgentError> {
+        let capture = RawSocketTcpCapture::raw_capture(network_interface, is_mesh).await?;
+        Ok(Self { inner: capture })
+    }
+
+    /// Sets a BPF filter that accepts only datagrams from or to the given ports.
+    #[tracing::instrument(level = Level::TRACE, skip(self), err)]
+    pub(crate) fn set_ports(&mut self, ports: &[Port]) -> io::Result<()> {
+        let filter = if ports.is_empty() {
+            rawsocket::filter::build_drop_always()
+        } else {
+            bpf::build_udp_port_filter(ports)
+        };
+
+        self.inner.set_filter(filter)
+    }
+
+    /// Returns the next sniffed UDP datagram.
+    pub(crate) async fn next(&mut self) -> io::Result<IncomingDatagram> {
+        loop {
+            let raw = self.inner.next().await?;
+
+            if let Some(datagram) = Self::get_udp_datagram(raw) {
+                break Ok(datagram);
+            }
+        }
+    }
+
+    /// Extracts UDP datagram from the raw Ethernet packet given as bytes.
+    /// If the given Ethernet packet is not UDP over IPv4 or IPv6, returns [`None`].
+    #[tracing::instrument(skip(eth_packet), level = Level::TRACE, fields(bytes = %eth_packet.len()))]
+    fn get_udp_datagram(eth_packet: Vec<u8>) -> Option<IncomingDatagram> {
+        let eth_packet = EthernetPacket::new(&eth_packet[..])?;
+
+        match eth_packet.get_ethertype() {
+            EtherTypes::Ipv4 => {
+                let ip_packet = Ipv4Packet::new(eth_packet.payload())?;
+                if ip_packet.get_next_level_protocol() != IpNextHeaderProtocols::Udp {
+                    return None;
+                }
+
+                Self::build_datagram(
+                    ip_packet.get_source().into(),
+                    ip_packet.get_destination().into(),
+                    ip_packet.payload(),
+                )
+            }
+
+            EtherTypes::Ipv6 => {
+                let ip_packet = Ipv6Packet::new(eth_packet.payload())?;
+                let (IpNextHeaderProtocols::Udp, udp_datagram) =
+                    RawSocketTcpCapture::skip_ipv6_extension_headers(
+                        ip_packet.get_next_header(),
+                        ip_packet.payload(),
+                    )?
+                else {
+                    return None;
+                };
+
+                Self::build_datagram(
+                    ip_packet.get_source().into(),
+                    ip_packet.get_destination().into(),
+                    udp_datagram,
+                )
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Builds the [`IncomingDatagram`] from the given UDP datagram bytes.
+    fn build_datagram(
+        source_addr: IpAddr,
+        dest_addr: IpAddr,
+        udp_datagram: &[u8],
+    ) -> Option<IncomingDatagram> {
+        let udp_packet = UdpPacket::new(udp_datagram)?;
+
+        // The length field covers the header, trailing bytes may be Ethernet padding.
+        let payload_len =
+            (udp_packet.get_length() as usize).checked_sub(UdpPacket::minimum_packet_size())?;
+        let bytes = udp_packet.payload().get(..payload_len)?.to_vec();
+
+        let datagram = IncomingDatagram {
+            source: SocketAddr::new(source_addr, udp_packet.get_source()),
+            destination: SocketAddr::new(dest_addr, udp_packet.get_destination()),
+            bytes,
+        };
+
+        tracing::trace!(?datagram, "Got UDP datagram");
+
+        Some(datagram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    /// Builds a UDP datagram from port 4000 to port 53 with the given payload.
+    fn udp(payload: &[u8]) -> Vec<u8> {
+        let length = (8 + payload.len()) as u16;
+
+        [
+            &4000_u16.to_be_bytes()[..],
+            &53_u16.to_be_bytes(),
+            &length.to_be_bytes(),
+            &[0, 0],
+            payload,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn ipv4_udp_datagram_with_padding() {
+        let udp = udp(b"hi");
+        let total_length = (20 + udp.len()) as u16;
+
+        let frame = [
+            &[0; 12][..],
+            &[0x08, 0x00],
+            &[0x45, 0],
+            &total_length.to_be_bytes(),
+            &[0, 0, 0, 0, 64, 17, 0, 0],
+            &[10, 0, 0, 1],
+            &[10, 0, 0, 2],
+            &udp,
+            // Ethernet padding up to the minimal frame size.
+            &[0; 8],
+        ]
+        .concat();
+
+        let datagram = RawSocketUdpCapture::get_udp_datagram(frame).unwrap();
+        assert_eq!(datagram.source, "10.0.0.1:4000".parse().unwrap());
+        assert_eq!(datagram.destination, "10.0.0.2:53".parse().unwrap());
+        assert_eq!(datagram.bytes, b"hi");
+    }
+
+    #[test]
+    fn ipv6_udp_datagram() {
+        let udp = udp(b"hello");
+        let payload_length = udp.len() as u16;
+
+        let frame = [
+            &[0; 12][..],
+            &[0x86, 0xdd],
+            &[0x60, 0, 0, 0],
+            &payload_length.to_be_bytes(),
+            &[17, 64],
+            &"fd00::1".parse::<Ipv6Addr>().unwrap().octets(),
+            &"fd00::2".parse::<Ipv6Addr>().unwrap().octets(),
+            &udp,
+        ]
+        .concat();
+
+        let datagram = RawSocketUdpCapture::get_udp_datagram(frame.clone()).unwrap();
+        assert_eq!(datagram.source, "[fd00::1]:4000".parse().unwrap());
+        assert_eq!(datagram.destination, "[fd00::2]:53".parse().unwrap());
+        assert_eq!(datagram.bytes, b"hello");
+
+        // TCP is not a UDP datagram.
+        let mut tcp_frame = frame;
+        if let Some(next_header) = tcp_frame.get_mut(20) {
+            *next_header = 6;
+        }
+        assert!(RawSocketUdpCapture::get_udp_datagram(tcp_frame).is_none());
+    }
+}
@@ -7,10 +7,7 @@ use fancy_regex::Regex;
 use futures::{stream::FuturesUnordered, StreamExt};
 use http::Request;
 use http_body_util::BodyExt;
-use hyper::{
-    body::Incoming,
-    http::{header::UPGRADE, request::Parts},
-};
+use hyper::http::{header::UPGRADE, request::Parts};
 use mirrord_protocol::{
     body_chunks::{BodyExt as _, Frames},
     tcp::{
@@ -35,9 +32,10 @@ use crate::{
     error::{AgentError, Result},
     steal::{
         connections::{
-            ConnectionMessageIn, ConnectionMessageOut, StolenConnection, StolenConnections,
+            ConnectionMessageIn, ConnectionMessageOut, DynamicBody, StolenConnection,
+            StolenConnections,
         },
-        http::HttpFilter,
+        http::{ClientFilter, HttpFilter},
         orig_dst,
         subscriptions::{IpTablesRedirector, PortSubscriptions},
         Command, StealerCommand,
@@ -51,7 +49,7 @@ struct MatchedHttpRequest {
     connection_id: ConnectionId,
     port: Port,
     request_id: RequestId,
-    request: Request<Incoming>,
+    request: Request<DynamicBody>,
 }
 
 impl MatchedHttpRequest {
@@ -143,7 +141,7 @@ impl Client {
     ///
     /// # Why async?
     ///
-    /// This method spawns a [`tokio::task`] to read the [`Incoming`] body od the request without
+    /// This method spawns a [`tokio::task`] to read the [`DynamicBody`] of the request without
     /// blocking the main [`TcpConnectionStealer`] loop.
     fn send_request_async(&self, request: MatchedHttpRequest) -> bool {
         if request.request.headers().contains_key(UPGRADE)
@@ -542,10 +540,19 @@ impl TcpConnectionStealer {
         let spec = match port_steal {
             StealType::All(port) => Ok((port, None)),
             StealType::FilteredHttp(port, filter) => Regex::new(&format!("(?i){filter}"))
-                .map(|regex| (port, Some(HttpFilter::Header(regex))))
+                .map(|regex| (port, Some(HttpFilter::Header(regex).into())))
                 .map_err(|err| BadHttpFilterRegex(filter, err.to_string())),
             StealType::FilteredHttpEx(port, filter) => HttpFilter::try_from(&filter)
-                .map(|filter| (port, Some(filter)))
+                .map(|filter| (port, Some(filter.into())))
+                .map_err(|err| BadHttpFilterExRegex(filter, err.to_string())),
+            StealType::FilteredHttpPassthrough(port, filter) => HttpFilter::try_from(&filter)
+                .map(|filter| {
+                    let filter = ClientFilter {
+                        filter,
+                        passthrough_on_failure: true,
+                    };
+                    (port, Some(filter))
+                })
                 .map_err(|err| BadHttpFilterExRegex(filter, err.to_string())),
         };
 
@@ -695,7 +702,7 @@ mod test {
     use bytes::Bytes;
     use futures::{future::BoxFuture, FutureExt};
     use http::{Method, Request, Response, Version};
-    use http_body_util::{Empty, StreamBody};
+    use http_body_util::{combinators::BoxBody, Empty, StreamBody};
     use hyper::{
         body::{Frame, Incoming},
         service::Service,
@@ -799,7 +806,7 @@ mod test {
             connection_id: 0,
             port: 80,
             request_id: 0,
-            request,
+            request: request.map(BoxBody::new),
         });
 
         // Verify that single-framed ChunkedRequest::Start requests are as expected, containing any
@@ -868,7 +875,7 @@ mod test {
             connection_id: 0,
             port: 80,
             request_id: 0,
-            request,
+            request: request.map(BoxBody::new),
         });
 
         // Verify that ChunkedRequest::Start request is as expected
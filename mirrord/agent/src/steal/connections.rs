@@ -3,7 +3,7 @@
 
 use std::{collections::HashMap, fmt, io, net::SocketAddr, time::Duration};
 
-use hyper::{body::Incoming, Request, Response};
+use hyper::{Request, Response};
 use mirrord_protocol::{tcp::NewTcpConnection, ConnectionId, Port, RequestId};
 use thiserror::Error;
 use tokio::{
@@ -12,7 +12,8 @@ use tokio::{
     task::JoinSet,
 };
 
-use self::{filtered::DynamicBody, unfiltered::UnfilteredStealTask};
+pub use self::filtered::DynamicBody;
+use self::unfiltered::UnfilteredStealTask;
 use super::{http::DefaultReversibleStream, subscriptions::PortSubscription};
 use crate::{http::HttpVersion, steal::connections::filtered::FilteredStealTask, util::ClientId};
 
@@ -130,7 +131,7 @@ pub enum ConnectionMessageOut {
     Request {
         client_id: ClientId,
         connection_id: ConnectionId,
-        request: Request<Incoming>,
+        request: Request<DynamicBody>,
         id: RequestId,
         port: Port,
     },
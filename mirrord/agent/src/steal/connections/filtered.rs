@@ -1,13 +1,19 @@
 use std::{
-    collections::HashMap, future::Future, marker::PhantomData, net::SocketAddr, pin::Pin, sync::Arc,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    marker::PhantomData,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
 };
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use dashmap::DashMap;
-use http::Version;
-use http_body_util::{combinators::BoxBody, BodyExt};
+use http::{HeaderMap, Method, Uri, Version};
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
 use hyper::{
-    body::Incoming,
+    body::{Body, Frame, Incoming, SizeHint},
     client::conn::{http1, http2},
     http::{Request, StatusCode},
     service::Service,
@@ -31,17 +37,150 @@ use tracing::Level;
 use super::{ConnectionMessageIn, ConnectionMessageOut, ConnectionTaskError};
 use crate::{
     http::HttpVersion,
-    steal::{connections::unfiltered::UnfilteredStealTask, http::HttpFilter},
+    steal::{
+        connections::unfiltered::UnfilteredStealTask,
+        http::{BodyPrefix, ClientFilter},
+    },
     util::ClientId,
 };
 
-/// [`Body`](hyper::body::Body) type used in [`FilteredStealTask`].
+/// [`Body`] type used in [`FilteredStealTask`].
 pub type DynamicBody = BoxBody<Bytes, hyper::Error>;
 
+/// [`Incoming`] body with some of its frames already read (when buffering the [`BodyPrefix`]).
+///
+/// Yields the buffered frames first, then the rest of the [`Incoming`] body.
+struct PrefixedBody {
+    buffered: VecDeque<Frame<Bytes>>,
+    rest: Incoming,
+}
+
+impl Body for PrefixedBody {
+    type Data = Bytes;
+
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+
+        match this.buffered.pop_front() {
+            Some(frame) => Poll::Ready(Some(Ok(frame))),
+            None => Pin::new(&mut this.rest).poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.buffered.is_empty() && self.rest.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self
+            .buffered
+            .iter()
+            .filter_map(Frame::data_ref)
+            .map(|data| data.len() as u64)
+            .sum::<u64>();
+
+        let rest = self.rest.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(rest.lower() + buffered);
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(upper + buffered);
+        }
+
+        hint
+    }
+}
+
+/// Frames read from an [`Incoming`] body when buffering the [`BodyPrefix`].
+#[derive(Default)]
+struct BufferedFrames {
+    frames: VecDeque<Frame<Bytes>>,
+    bytes: BytesMut,
+    /// Whether the whole body was read.
+    complete: bool,
+}
+
+impl BufferedFrames {
+    /// Reads frames from the `body` until at least `size` bytes are buffered or the body ends.
+    async fn fill(&mut self, body: &mut Incoming, size: usize) -> Result<(), hyper::Error> {
+        while !self.complete && self.bytes.len() < size {
+            match body.frame().await.transpose()? {
+                Some(frame) => {
+                    if let Some(data) = frame.data_ref() {
+                        self.bytes.extend_from_slice(data);
+                    }
+
+                    self.frames.push_back(frame);
+                }
+                None => self.complete = true,
+            }
+        }
+
+        self.complete |= body.is_end_stream();
+
+        Ok(())
+    }
+
+    fn prefix(&self) -> BodyPrefix {
+        BodyPrefix {
+            bytes: self.bytes.clone().freeze(),
+            complete: self.complete,
+        }
+    }
+}
+
+/// Copy of an incoming [`Request`] with the whole body, made in the [`FilteringService`] when some
+/// client wants its failed requests passed through to the original destination
+/// ([`ClientFilter::passthrough_on_failure`]).
+struct RequestCopy {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl RequestCopy {
+    /// Copies the given [`Request`], if its whole body was buffered in the [`BodyPrefix`].
+    fn new<B>(request: &Request<B>) -> Option<Self> {
+        let prefix = request
+            .extensions()
+            .get::<BodyPrefix>()
+            .filter(|prefix| prefix.complete)?;
+
+        Some(Self {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+            version: request.version(),
+            headers: request.headers().clone(),
+            body: prefix.bytes.clone(),
+        })
+    }
+
+    fn into_request(self) -> Request<DynamicBody> {
+        let mut request = Request::new(BoxBody::new(
+            Full::new(self.body).map_err(|_| unreachable!()),
+        ));
+        *request.method_mut() = self.method;
+        *request.uri_mut() = self.uri;
+        *request.version_mut() = self.version;
+        *request.headers_mut() = self.headers;
+
+        request
+    }
+}
+
 /// Incoming [`Request`] extracted from the HTTP connection in the [`FilteringService`].
 struct ExtractedRequest {
-    request: Request<Incoming>,
+    request: Request<DynamicBody>,
     response_tx: oneshot::Sender<RequestHandling>,
+    /// Whether the [`FilteringService`] holds a [`RequestCopy`] of this request and can handle
+    /// [`RequestHandling::PassThrough`].
+    can_pass_through: bool,
 }
 
 /// Response instruction for [`FilteringService`].
@@ -50,7 +189,7 @@ enum RequestHandling {
     /// The [`Request`] should be handled by the HTTP server running at the given address.
     LetThrough {
         to: SocketAddr,
-        unchanged: Request<Incoming>,
+        unchanged: Request<DynamicBody>,
     },
     /// The [`FilteringService`] should respond immediately with the given [`Response`]
     /// on behalf of the given stealer client.
@@ -58,16 +197,27 @@ enum RequestHandling {
         response: Response<DynamicBody>,
         for_client: ClientId,
     },
+    /// The stealer client failed to handle the [`Request`], it should be handled by the HTTP
+    /// server running at the given address, using the [`RequestCopy`].
+    PassThrough { to: SocketAddr },
+}
+
+/// Request blocked in [`FilteredStealTask`] on a stealer client's response.
+struct BlockedRequest {
+    response_tx: oneshot::Sender<RequestHandling>,
+    /// Whether the request should be passed through to the original destination if the client
+    /// fails to handle it.
+    pass_through_on_failure: bool,
 }
 
 /// HTTP server side of an upgraded connection retrieved from [`FilteringService`].
 pub enum UpgradedServerSide {
-    /// Stealer client. Their [`HttpFilter`] matched the upgrade request.
+    /// Stealer client. Their [`ClientFilter`] matched the upgrade request.
     /// The rest of the connection should be proxied between the HTTP client and this stealer
     /// client (which acts as an HTTP server).
     MatchedClient(ClientId),
     /// TCP connection with the HTTP server that was the original destination of the HTTP client
-    /// (no [`HttpFilter`] matched the upgrade request). The rest of the connection should be
+    /// (no [`ClientFilter`] matched the upgrade request). The rest of the connection should be
     /// proxied between the HTTP client and this HTTP server.
     OriginalDestination(Upgraded),
 }
@@ -87,6 +237,10 @@ struct FilteringService {
     /// For sending incoming requests to the [`FilteredStealTask`].
     requests_tx: Sender<ExtractedRequest>,
 
+    /// Same as [`FilteredStealTask::filters`].
+    /// Used to determine how much of the request body should be buffered in [`BodyPrefix`].
+    filters: Arc<DashMap<ClientId, ClientFilter>>,
+
     /// For recovering the upgraded connection in [`FilteredStealTask`].
     ///
     /// # Note
@@ -98,6 +252,11 @@ struct FilteringService {
 }
 
 impl FilteringService {
+    /// How much of the request body is buffered when the request matches the filter of a client
+    /// that uses [`ClientFilter::passthrough_on_failure`]. Requests with larger bodies cannot be
+    /// passed through after the client fails to handle them.
+    const PASSTHROUGH_BODY_LIMIT: usize = 8 * 1024 * 1024;
+
     /// Produces a new [`StatusCode::BAD_GATEWAY`] [`Response`] with the given [`Version`] and the
     /// given `error` in body.
     fn bad_gateway(version: Version, error: &str) -> Response<DynamicBody> {
@@ -118,7 +277,7 @@ impl FilteringService {
     /// Also, it does not retry the request upon failure.
     async fn send_request(
         to: SocketAddr,
-        mut request: Request<Incoming>,
+        mut request: Request<DynamicBody>,
     ) -> Result<Response<Incoming>, Box<dyn std::error::Error>> {
         let tcp_stream = TcpStream::connect(to).await.inspect_err(|error| {
             tracing::error!(?error, address = %to, "Failed connecting to request destination");
@@ -191,7 +350,7 @@ impl FilteringService {
     )]
     async fn let_through(
         &self,
-        request: Request<Incoming>,
+        request: Request<DynamicBody>,
         on_upgrade: OnUpgrade,
         to: SocketAddr,
     ) -> Response<DynamicBody> {
@@ -271,6 +430,60 @@ impl FilteringService {
         }
     }
 
+    /// If any of the [`ClientFilter`]s inspects the request body, reads the beginning of the body
+    /// and stores it in the [`Request::extensions`] as [`BodyPrefix`].
+    ///
+    /// If the request matches the filter of a client that uses
+    /// [`ClientFilter::passthrough_on_failure`], reads up to [`Self::PASSTHROUGH_BODY_LIMIT`]
+    /// bytes, so that the request can be copied.
+    ///
+    /// The frames read here are not lost, the returned [`Request`] yields them again.
+    async fn buffer_body_prefix(
+        &self,
+        request: Request<Incoming>,
+    ) -> Result<Request<DynamicBody>, hyper::Error> {
+        let size_needed = self
+            .filters
+            .iter()
+            .map(|entry| entry.value().body_size_needed())
+            .max()
+            .unwrap_or_default();
+        let any_passthrough = self
+            .filters
+            .iter()
+            .any(|entry| entry.value().passthrough_on_failure);
+        if size_needed == 0 && !any_passthrough {
+            return Ok(request.map(BoxBody::new));
+        }
+
+        let (parts, mut body) = request.into_parts();
+        let mut buffered = BufferedFrames::default();
+        buffered.fill(&mut body, size_needed).await?;
+
+        // Body is not needed for matching, only the head and the prefix.
+        let mut head = Request::from_parts(parts, ());
+        head.extensions_mut().insert(buffered.prefix());
+
+        let passthrough_matched = !buffered.complete
+            && self.filters.iter().any(|entry| {
+                entry.value().passthrough_on_failure && entry.value().matches(&mut head)
+            });
+        if passthrough_matched {
+            buffered
+                .fill(&mut body, Self::PASSTHROUGH_BODY_LIMIT)
+                .await?;
+            head.extensions_mut().insert(buffered.prefix());
+        }
+
+        let (parts, ()) = head.into_parts();
+        let body = PrefixedBody {
+            buffered: buffered.frames,
+            rest: body,
+        };
+
+        Ok(Request::from_parts(parts, BoxBody::new(body)))
+    }
+
     /// Extracts [`OnUpgrade`] from the given [`Request`] and sends it to [`FilteredStealTask`].
     /// Waits on a dynamically created [`oneshot::channel`] for [`RequestHandling`] instruction.
     async fn handle_request(
@@ -280,11 +493,30 @@ impl FilteringService {
         let version = request.version();
         let on_upgrade = hyper::upgrade::on(&mut request);
 
+        let request = match self.buffer_body_prefix(request).await {
+            Ok(request) => request,
+            Err(error) => {
+                tracing::warn!(?error, "Failed to read the body of an incoming request");
+                return Ok(Self::bad_gateway(
+                    version,
+                    "failed to read the body of the request",
+                ));
+            }
+        };
+
+        let request_copy = self
+            .filters
+            .iter()
+            .any(|entry| entry.value().passthrough_on_failure)
+            .then(|| RequestCopy::new(&request))
+            .flatten();
+
         let (response_tx, response_rx) = oneshot::channel();
         self.requests_tx
             .send(ExtractedRequest {
                 request,
                 response_tx,
+                can_pass_through: request_copy.is_some(),
             })
             .await?;
 
@@ -300,6 +532,13 @@ impl FilteringService {
                     .await;
                 response
             }
+            Ok(RequestHandling::PassThrough { to }) => match request_copy {
+                Some(copy) => self.let_through(copy.into_request(), on_upgrade, to).await,
+                None => Self::bad_gateway(
+                    version,
+                    "failed to pass the request to its original destination",
+                ),
+            },
             Err(..) => Self::bad_gateway(
                 version,
                 "failed to receive a response from the connected mirrord session",
@@ -334,14 +573,14 @@ pub struct FilteredStealTask<T> {
     /// don't not match any filter in [`Self::filters`].
     original_destination: SocketAddr,
 
-    /// Stealer client to [`HttpFilter`] mapping. Allows for routing HTTP requests to correct
+    /// Stealer client to [`ClientFilter`] mapping. Allows for routing HTTP requests to correct
     /// stealer clients.
     ///
     /// # Note
     ///
     /// This mapping is shared via [`Arc`], allowing for dynamic updates from the outside.
     /// This allows for *injecting* new stealer clients into exisiting connections.
-    filters: Arc<DashMap<ClientId, HttpFilter>>,
+    filters: Arc<DashMap<ClientId, ClientFilter>>,
 
     /// Stealer client to subscription state mapping.
     /// 1. `true` -> client is subscribed
@@ -361,7 +600,7 @@ pub struct FilteredStealTask<T> {
     hyper_conn_task: Option<(JoinHandle<Option<UpgradedConnection>>, DropGuard)>,
 
     /// Requests blocked on stealer clients' responses.
-    blocked_requests: HashMap<(ClientId, RequestId), oneshot::Sender<RequestHandling>>,
+    blocked_requests: HashMap<(ClientId, RequestId), BlockedRequest>,
 
     /// Id of the next HTTP request that will be intercepted.
     next_request_id: RequestId,
@@ -388,7 +627,7 @@ where
     )]
     pub fn new(
         connection_id: ConnectionId,
-        filters: Arc<DashMap<ClientId, HttpFilter>>,
+        filters: Arc<DashMap<ClientId, ClientFilter>>,
         original_destination: SocketAddr,
         http_version: HttpVersion,
         io: T,
@@ -398,6 +637,7 @@ where
 
         let service = FilteringService {
             requests_tx,
+            filters: filters.clone(),
             upgrade_tx,
         };
 
@@ -496,7 +736,7 @@ where
         request_id: RequestId,
         response: Response<DynamicBody>,
     ) {
-        let Some(tx) = self.blocked_requests.remove(&(client_id, request_id)) else {
+        let Some(blocked) = self.blocked_requests.remove(&(client_id, request_id)) else {
             tracing::warn!(
                 client_id,
                 request_id,
@@ -508,13 +748,25 @@ where
             return;
         };
 
-        if tx
-            .send(RequestHandling::RespondWith {
+        let handling = if blocked.pass_through_on_failure && response.status().is_server_error() {
+            tracing::trace!(
+                client_id,
+                request_id,
+                connection_id = self.connection_id,
+                "Client responded with a server error, passing the request through",
+            );
+
+            RequestHandling::PassThrough {
+                to: self.original_destination,
+            }
+        } else {
+            RequestHandling::RespondWith {
                 response,
                 for_client: client_id,
-            })
-            .is_err()
-        {
+            }
+        };
+
+        if blocked.response_tx.send(handling).is_err() {
             tracing::warn!(
                 client_id,
                 request_id,
@@ -526,7 +778,8 @@ where
 
     /// Notifies the [`FilteringService`] that the client failed to provide a [`Response`] for the
     /// request with the given id. The [`FilteringService`] is notified by dropping a
-    /// [`oneshot::Sender`] from [`Self::blocked_requests`].
+    /// [`oneshot::Sender`] from [`Self::blocked_requests`], or with
+    /// [`RequestHandling::PassThrough`] if the client wants its failed requests passed through.
     ///
     /// If there is no blocked request for the given ([`ClientId`], [`RequestId`]) combination or
     /// the HTTP connection is dead, does nothing.
@@ -540,17 +793,21 @@ where
         )
     )]
     fn handle_response_failure(&mut self, client_id: ClientId, request_id: RequestId) {
-        let removed = self
-            .blocked_requests
-            .remove(&(client_id, request_id))
-            .is_some();
-        if !removed {
+        let Some(blocked) = self.blocked_requests.remove(&(client_id, request_id)) else {
             tracing::warn!(
                 client_id,
                 request_id,
                 connection_id = self.connection_id,
                 "Received a response failure for an unexpected (client_id, request_id) combination",
             );
+
+            return;
+        };
+
+        if blocked.pass_through_on_failure {
+            let _ = blocked.response_tx.send(RequestHandling::PassThrough {
+                to: self.original_destination,
+            });
         }
     }
 
@@ -596,8 +853,18 @@ where
         })
         .await?;
 
-        self.blocked_requests
-            .insert((client_id, id), request.response_tx);
+        let pass_through_on_failure = request.can_pass_through
+            && self
+                .filters
+                .get(&client_id)
+                .is_some_and(|filter| filter.passthrough_on_failure);
+        self.blocked_requests.insert(
+            (client_id, id),
+            BlockedRequest {
+                response_tx: request.response_tx,
+                pass_through_on_failure,
+            },
+        );
 
         Ok(())
     }
@@ -637,7 +904,15 @@ where
                     ConnectionMessageIn::Unsubscribed { client_id } => {
                         queued_raw_data.remove(&client_id);
                         self.subscribed.insert(client_id, false);
-                        self.blocked_requests.retain(|key, _| key.0 != client_id);
+                        let blocked = self
+                            .blocked_requests
+                            .keys()
+                            .filter(|key| key.0 == client_id)
+                            .map(|key| key.1)
+                            .collect::<Vec<_>>();
+                        for request_id in blocked {
+                            self.handle_response_failure(client_id, request_id);
+                        }
                     },
                 },
 
@@ -815,16 +1090,18 @@ mod test {
         header::{CONNECTION, UPGRADE},
         HeaderValue, Method,
     };
-    use http_body_util::Empty;
+    use http_body_util::{Empty, StreamBody};
     use hyper::{client::conn::http1::SendRequest, service::service_fn};
+    use rstest::rstest;
     use tokio::{io::AsyncReadExt, net::TcpListener, task::JoinSet};
 
     use super::*;
+    use crate::steal::http::HttpFilter;
 
     /// Full setup for [`FilteredStealTask`] tests.
     struct TestSetup {
-        /// [`HttpFilter`]s mapping used by the task.
-        filters: Arc<DashMap<ClientId, HttpFilter>>,
+        /// [`ClientFilter`]s mapping used by the task.
+        filters: Arc<DashMap<ClientId, ClientFilter>>,
         /// Address of the original HTTP server (the one we steal from).
         original_address: SocketAddr,
         /// Stolen connection wrapped into HTTP.
@@ -958,7 +1235,7 @@ mod test {
                 tasks.shutdown().await;
             });
 
-            let filters: Arc<DashMap<ClientId, HttpFilter>> = Default::default();
+            let filters: Arc<DashMap<ClientId, ClientFilter>> = Default::default();
             let filters_clone = filters.clone();
 
             let (in_tx, mut in_rx) = mpsc::channel(8);
@@ -1020,7 +1297,7 @@ mod test {
                 builder = builder.header("x-client", &client_id.to_string());
                 self.filters.insert(
                     client_id,
-                    HttpFilter::Header(format!("x-client: {client_id}").parse().unwrap()),
+                    HttpFilter::Header(format!("x-client: {client_id}").parse().unwrap()).into(),
                 );
             }
 
@@ -1570,6 +1847,135 @@ mod test {
         assert!(rx.recv().await.is_none());
     }
 
+    /// The stolen connection receives a request that matches the filter of a client with
+    /// [`ClientFilter::passthrough_on_failure`]. The client responds with
+    /// [`StatusCode::INTERNAL_SERVER_ERROR`] and the request sender gets the response from the
+    /// original HTTP server.
+    #[tokio::test]
+    async fn client_server_error_passed_through() {
+        let mut setup = TestSetup::new().await;
+
+        let request = setup.prepare_request(Some(0), false);
+        setup.filters.get_mut(&0).unwrap().passthrough_on_failure = true;
+
+        tokio::join!(
+            async {
+                let response = setup.request_sender.send_request(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            },
+            async {
+                match setup.task_out_rx.recv().await.unwrap() {
+                    ConnectionMessageOut::SubscribedHttp {
+                        client_id: 0,
+                        connection_id: TestSetup::CONNECTION_ID,
+                    } => {}
+                    other => unreachable!("unexpected message: {other:?}"),
+                };
+
+                let request_id = match setup.task_out_rx.recv().await.unwrap() {
+                    ConnectionMessageOut::Request {
+                        client_id: 0,
+                        connection_id: TestSetup::CONNECTION_ID,
+                        id,
+                        ..
+                    } => id,
+                    other => unreachable!("unexpected message: {other:?}"),
+                };
+
+                let mut response = Response::new(Empty::new().map_err(|_| unreachable!()).boxed());
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                setup
+                    .task_in_tx
+                    .send(ConnectionMessageIn::Response {
+                        client_id: 0,
+                        request_id,
+                        response,
+                    })
+                    .await
+                    .unwrap();
+            }
+        );
+
+        setup
+            .task_in_tx
+            .send(ConnectionMessageIn::Unsubscribed { client_id: 0 })
+            .await
+            .unwrap();
+
+        let mut rx = setup.shutdown().await;
+        // The task should not produce the `Closed` message - the client has unsubscribed.
+        assert!(rx.recv().await.is_none());
+    }
+
+    /// The stolen connection receives a request with a chunked body, that matches the filter of a
+    /// client with [`ClientFilter::passthrough_on_failure`]. The client fails to provide a
+    /// response (or unsubscribes before providing it) and the request sender gets the response
+    /// from the original HTTP server.
+    #[rstest]
+    #[case::response_failed(false)]
+    #[case::unsubscribed(true)]
+    #[tokio::test]
+    async fn client_failure_passed_through(#[case] unsubscribe: bool) {
+        let mut setup = TestSetup::new().await;
+
+        // Body without a size hint is sent with the chunked transfer encoding.
+        let request = setup.prepare_request(Some(0), false).map(|_| {
+            let frames = ["hello", " world"].map(|chunk| {
+                Ok::<_, hyper::Error>(Frame::data(Bytes::from_static(chunk.as_bytes())))
+            });
+            BoxBody::new(StreamBody::new(futures::stream::iter(frames)))
+        });
+        setup.filters.get_mut(&0).unwrap().passthrough_on_failure = true;
+
+        tokio::join!(
+            async {
+                let response = setup.request_sender.send_request(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            },
+            async {
+                match setup.task_out_rx.recv().await.unwrap() {
+                    ConnectionMessageOut::SubscribedHttp {
+                        client_id: 0,
+                        connection_id: TestSetup::CONNECTION_ID,
+                    } => {}
+                    other => unreachable!("unexpected message: {other:?}"),
+                };
+
+                let request_id = match setup.task_out_rx.recv().await.unwrap() {
+                    ConnectionMessageOut::Request {
+                        client_id: 0,
+                        connection_id: TestSetup::CONNECTION_ID,
+                        id,
+                        ..
+                    } => id,
+                    other => unreachable!("unexpected message: {other:?}"),
+                };
+
+                let message = if unsubscribe {
+                    ConnectionMessageIn::Unsubscribed { client_id: 0 }
+                } else {
+                    ConnectionMessageIn::ResponseFailed {
+                        client_id: 0,
+                        request_id,
+                    }
+                };
+                setup.task_in_tx.send(message).await.unwrap();
+            }
+        );
+
+        if !unsubscribe {
+            setup
+                .task_in_tx
+                .send(ConnectionMessageIn::Unsubscribed { client_id: 0 })
+                .await
+                .unwrap();
+        }
+
+        let mut rx = setup.shutdown().await;
+        // The task should not produce the `Closed` message - the client has unsubscribed.
+        assert!(rx.recv().await.is_none());
+    }
+
     /// The stolen connection receives a request that matches some client's filter.
     /// The client unsubscribes before providing a response and the request sender gets a
     /// [`StatusCode::BAD_GATEWAY`] response.
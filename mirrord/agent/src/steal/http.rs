@@ -5,7 +5,7 @@ use crate::http::HttpVersion;
 mod filter;
 mod reversible_stream;
 
-pub use filter::HttpFilter;
+pub use filter::{BodyPrefix, ClientFilter, HttpFilter};
 
 pub(crate) use self::reversible_stream::ReversibleStream;
 
@@ -1,7 +1,24 @@
+use std::{ops::Deref, str::FromStr};
+
+use bytes::Bytes;
 use fancy_regex::Regex;
 use hyper::Request;
+use jsonpath_rust::{path::config::JsonPathConfig, JsonPathInst};
+use mirrord_protocol::tcp::BodyFilter;
+use thiserror::Error;
 use tracing::Level;
 
+/// Errors that can occur when converting a [`mirrord_protocol::tcp::HttpFilter`] into a
+/// [`HttpFilter`].
+#[derive(Debug, Error)]
+pub enum HttpFilterError {
+    #[error(transparent)]
+    Regex(#[from] fancy_regex::Error),
+
+    #[error("invalid JSONPath query `{0}`: {1}")]
+    JsonPath(String, String),
+}
+
 /// Currently supported filtering criterias.
 #[derive(Debug)]
 pub enum HttpFilter {
@@ -17,10 +34,31 @@ pub enum HttpFilter {
         /// Filters to use.
         filters: Vec<HttpFilter>,
     },
+    /// Method based filter.
+    /// This [`Regex`] should match the whole method name.
+    Method(Regex),
+    /// Query string based filter.
+    Query(Regex),
+    /// Body based filter.
+    /// Inspects only the [`BodyPrefix`] of the request.
+    Body {
+        /// JSONPath query, if the body should be parsed as JSON first.
+        json_path: Option<JsonPathInst>,
+        /// Matched against the body or against the values selected by `json_path`.
+        filter: Regex,
+        /// How many bytes of the body this filter needs.
+        max_size: usize,
+    },
+}
+
+impl HttpFilter {
+    /// Maximum number of body bytes that any [`HttpFilter::Body`] can inspect, regardless of the
+    /// size requested by the client.
+    pub const BODY_MAX_SIZE_LIMIT: usize = 1024 * 1024;
 }
 
 impl TryFrom<&mirrord_protocol::tcp::HttpFilter> for HttpFilter {
-    type Error = fancy_regex::Error;
+    type Error = HttpFilterError;
 
     fn try_from(filter: &mirrord_protocol::tcp::HttpFilter) -> Result<Self, Self::Error> {
         match filter {
@@ -38,6 +76,33 @@ impl TryFrom<&mirrord_protocol::tcp::HttpFilter> for HttpFilter {
                     .collect::<Result<Vec<_>, _>>()?;
                 Ok(Self::Composite { all, filters })
             }
+            mirrord_protocol::tcp::HttpFilter::Method(method) => {
+                Ok(Self::Method(Regex::new(&format!("(?i)^(?:{method})$"))?))
+            }
+            mirrord_protocol::tcp::HttpFilter::Query(query) => {
+                Ok(Self::Query(Regex::new(&format!("(?i){query}"))?))
+            }
+            mirrord_protocol::tcp::HttpFilter::Body(body) => {
+                let max_size = (body.max_size() as usize).min(Self::BODY_MAX_SIZE_LIMIT);
+
+                match body {
+                    BodyFilter::Regex { filter, .. } => Ok(Self::Body {
+                        json_path: None,
+                        filter: Regex::new(&format!("(?s){filter}"))?,
+                        max_size,
+                    }),
+                    BodyFilter::Json { query, filter, .. } => {
+                        let json_path = JsonPathInst::from_str(query)
+                            .map_err(|error| HttpFilterError::JsonPath(query.clone(), error))?;
+
+                        Ok(Self::Body {
+                            json_path: Some(json_path),
+                            filter: Regex::new(&format!("^(?:{filter})$"))?,
+                            max_size,
+                        })
+                    }
+                }
+            }
         }
     }
 }
@@ -110,10 +175,137 @@ impl HttpFilter {
                 all: false,
                 filters,
             } => filters.iter().any(|f| f.matches(request)),
+
+            Self::Method(filter) => {
+                let method = request.method().as_str();
+                filter
+                    .is_match(method)
+                    .inspect_err(|error| {
+                        tracing::error!(method, ?error, "Error while matching method");
+                    })
+                    .unwrap_or(false)
+            }
+
+            Self::Query(filter) => {
+                let query = request.uri().query().unwrap_or_default();
+                filter
+                    .is_match(query)
+                    .inspect_err(|error| {
+                        tracing::error!(query, ?error, "Error while matching query");
+                    })
+                    .unwrap_or(false)
+            }
+
+            Self::Body {
+                json_path,
+                filter,
+                max_size,
+            } => {
+                let Some(BodyPrefix { bytes, complete }) = request.extensions().get() else {
+                    tracing::warn!("Body filter used, but the request body was not buffered");
+                    return false;
+                };
+                let truncated = !complete || bytes.len() > *max_size;
+                let bytes = bytes.slice(..bytes.len().min(*max_size));
+
+                match json_path {
+                    None => {
+                        let body = String::from_utf8_lossy(&bytes);
+                        filter
+                            .is_match(&body)
+                            .inspect_err(|error| {
+                                tracing::error!(?error, "Error while matching body");
+                            })
+                            .unwrap_or(false)
+                    }
+
+                    // A truncated body cannot be parsed as JSON.
+                    Some(..) if truncated => false,
+
+                    Some(json_path) => {
+                        let Ok(json) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+                            return false;
+                        };
+
+                        json_path
+                            .find_slice(&json, JsonPathConfig::default())
+                            .iter()
+                            .any(|value| {
+                                let value = match &**value {
+                                    serde_json::Value::String(value) => value.clone(),
+                                    other => other.to_string(),
+                                };
+
+                                filter
+                                    .is_match(&value)
+                                    .inspect_err(|error| {
+                                        tracing::error!(
+                                            value,
+                                            ?error,
+                                            "Error while matching JSON body value"
+                                        );
+                                    })
+                                    .unwrap_or(false)
+                            })
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns how many bytes of the request body are needed to check this filter.
+    ///
+    /// `0` means that this filter does not inspect the body at all.
+    pub fn body_size_needed(&self) -> usize {
+        match self {
+            Self::Body { max_size, .. } => *max_size,
+            Self::Composite { filters, .. } => filters
+                .iter()
+                .map(Self::body_size_needed)
+                .max()
+                .unwrap_or_default(),
+            Self::Header(..) | Self::Path(..) | Self::Method(..) | Self::Query(..) => 0,
+        }
+    }
+}
+
+/// [`HttpFilter`] of a stealer client, together with the client's policy for the matched requests
+/// that it fails to handle.
+#[derive(Debug)]
+pub struct ClientFilter {
+    pub filter: HttpFilter,
+    /// Whether the matched requests that the client fails to handle (no response or a 5xx
+    /// response) should be sent to their original destination.
+    pub passthrough_on_failure: bool,
+}
+
+impl From<HttpFilter> for ClientFilter {
+    fn from(filter: HttpFilter) -> Self {
+        Self {
+            filter,
+            passthrough_on_failure: false,
         }
     }
 }
 
+impl Deref for ClientFilter {
+    type Target = HttpFilter;
+
+    fn deref(&self) -> &Self::Target {
+        &self.filter
+    }
+}
+
+/// Beginning of the request body, buffered before matching the request against
+/// [`HttpFilter::Body`] filters. Stored in [`Request::extensions`].
+#[derive(Clone, Debug)]
+pub struct BodyPrefix {
+    /// The buffered bytes.
+    pub bytes: Bytes,
+    /// Whether [`BodyPrefix::bytes`] contain the whole body.
+    pub complete: bool,
+}
+
 /// [`HeaderMap`](hyper::http::header::HeaderMap) entries formatted like `k: v` (format expected by
 /// [`HttpFilter::Header`]). Computed and cached in [`Request::extensions`] the first time
 /// [`HttpFilter::matches`] is called on the [`Request`].
@@ -137,10 +329,11 @@ impl NormalizedHeaders {
 
 #[cfg(test)]
 mod test {
+    use bytes::Bytes;
     use hyper::Request;
-    use mirrord_protocol::tcp::{self, Filter};
+    use mirrord_protocol::tcp::{self, BodyFilter, Filter};
 
-    use crate::steal::http::HttpFilter;
+    use crate::steal::http::{BodyPrefix, HttpFilter};
 
     #[test]
     fn matching_all_filter() {
@@ -200,4 +393,122 @@ mod test {
         let filter: HttpFilter = TryFrom::try_from(&tcp_filter).unwrap();
         assert!(!filter.matches(&mut input));
     }
+
+    #[test]
+    fn matching_method_and_query_filter() {
+        let tcp_filter = tcp::HttpFilter::Composite {
+            all: true,
+            filters: vec![
+                tcp::HttpFilter::Method(Filter::new("post".to_string()).unwrap()),
+                tcp::HttpFilter::Query(Filter::new("tenant=alice".to_string()).unwrap()),
+            ],
+        };
+        let filter: HttpFilter = TryFrom::try_from(&tcp_filter).unwrap();
+
+        // should match
+        let mut input = Request::builder()
+            .method("POST")
+            .uri("https://www.balconia.gov/api/path/to/v1?tenant=alice&limit=10")
+            .body(())
+            .unwrap();
+        assert!(filter.matches(&mut input));
+
+        // should fail, method must match as a whole
+        let mut input = Request::builder()
+            .method("POSTPONE")
+            .uri("https://www.balconia.gov/api/path/to/v1?tenant=alice")
+            .body(())
+            .unwrap();
+        assert!(!filter.matches(&mut input));
+
+        // should fail, no query
+        let mut input = Request::builder()
+            .method("POST")
+            .uri("https://www.balconia.gov/api/tenant=alice")
+            .body(())
+            .unwrap();
+        assert!(!filter.matches(&mut input));
+    }
+
+    #[test]
+    fn matching_body_filter() {
+        let tcp_filter = tcp::HttpFilter::Body(BodyFilter::Regex {
+            filter: Filter::new("\"user_id\":\\s*42".to_string()).unwrap(),
+            max_size: 16,
+        });
+        let filter: HttpFilter = TryFrom::try_from(&tcp_filter).unwrap();
+        assert_eq!(filter.body_size_needed(), 16);
+
+        // should match
+        let mut input = Request::builder()
+            .extension(BodyPrefix {
+                bytes: Bytes::from_static(br#"{"user_id": 42, "name": "alice"}"#),
+                complete: true,
+            })
+            .body(())
+            .unwrap();
+        assert!(filter.matches(&mut input));
+
+        // should fail, the match is past the inspected part of the body
+        let mut input = Request::builder()
+            .extension(BodyPrefix {
+                bytes: Bytes::from_static(br#"{"name": "alice", "user_id": 42}"#),
+                complete: true,
+            })
+            .body(())
+            .unwrap();
+        assert!(!filter.matches(&mut input));
+
+        // should fail, body was not buffered
+        let mut input = Request::builder().body(()).unwrap();
+        assert!(!filter.matches(&mut input));
+    }
+
+    #[test]
+    fn matching_json_body_filter() {
+        let tcp_filter = tcp::HttpFilter::Body(BodyFilter::Json {
+            query: "$.user.id".to_string(),
+            filter: Filter::new("4[0-9]".to_string()).unwrap(),
+            max_size: 1024,
+        });
+        let filter: HttpFilter = TryFrom::try_from(&tcp_filter).unwrap();
+
+        // should match
+        let mut input = Request::builder()
+            .extension(BodyPrefix {
+                bytes: Bytes::from_static(br#"{"user": {"id": 42}}"#),
+                complete: true,
+            })
+            .body(())
+            .unwrap();
+        assert!(filter.matches(&mut input));
+
+        // should fail, the whole value must match
+        let mut input = Request::builder()
+            .extension(BodyPrefix {
+                bytes: Bytes::from_static(br#"{"user": {"id": 420}}"#),
+                complete: true,
+            })
+            .body(())
+            .unwrap();
+        assert!(!filter.matches(&mut input));
+
+        // should fail, truncated body
+        let mut input = Request::builder()
+            .extension(BodyPrefix {
+                bytes: Bytes::from_static(br#"{"user": {"id": 42}"#),
+                complete: false,
+            })
+            .body(())
+            .unwrap();
+        assert!(!filter.matches(&mut input));
+
+        // invalid JSONPath
+        let tcp_filter = tcp::HttpFilter::Body(BodyFilter::Json {
+            query: "user.id[".to_string(),
+            filter: Filter::new("42".to_string()).unwrap(),
+            max_size: 1024,
+        });
+        assert!(HttpFilter::try_from(&tcp_filter).is_err());
+    }
 }
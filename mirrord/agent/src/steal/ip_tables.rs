@@ -56,6 +56,7 @@ pub(crate) mod output;
 pub(crate) mod prerouting;
 pub(crate) mod redirect;
 pub(crate) mod standard;
+pub(crate) mod udp;
 
 pub static IPTABLE_PREROUTING_ENV: &str = "MIRRORD_IPTABLE_PREROUTING_NAME";
 pub static IPTABLE_PREROUTING: LazyLock<String> = LazyLock::new(|| {
@@ -87,6 +88,16 @@ pub(crate) static IPTABLE_STANDARD: LazyLock<String> = LazyLock::new(|| {
     })
 });
 
+pub(crate) static IPTABLE_UDP_ENV: &str = "MIRRORD_IPTABLE_UDP_NAME";
+pub(crate) static IPTABLE_UDP: LazyLock<String> = LazyLock::new(|| {
+    std::env::var(IPTABLE_UDP_ENV).unwrap_or_else(|_| {
+        format!(
+            "MIRRORD_UDP_{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 5)
+        )
+    })
+});
+
 pub static IPTABLE_INPUT_ENV: &str = "MIRRORD_IPTABLE_INPUT_NAME";
 pub static IPTABLE_INPUT: LazyLock<String> = LazyLock::new(|| {
     std::env::var(IPTABLE_INPUT_ENV).unwrap_or_else(|_| {
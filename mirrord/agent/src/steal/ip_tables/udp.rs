@@ -0,0 +1,140 @@
+use std::{ops::Deref, sync::Arc};
+
+use async_trait::async_trait;
+use mirrord_protocol::Port;
+
+use crate::{
+    error::Result,
+    steal::ip_tables::{chain::IPTableChain, IPTables, Redirect, IPTABLE_UDP},
+};
+
+/// Redirects incoming UDP datagrams, used to steal UDP traffic.
+///
+/// Unlike TCP redirects, this one has a dedicated chain, so that it can be created and removed
+/// independently of the [`SafeIpTables`](super::SafeIpTables) used by the TCP stealer.
+pub(crate) struct UdpRedirect<IPT: IPTables> {
+    managed: IPTableChain<IPT>,
+}
+
+impl<IPT> UdpRedirect<IPT>
+where
+    IPT: IPTables,
+{
+    const ENTRYPOINT: &'static str = "PREROUTING";
+
+    pub fn create(ipt: Arc<IPT>) -> Result<Self> {
+        let managed = IPTableChain::create(ipt, IPTABLE_UDP.to_string())?;
+
+        Ok(UdpRedirect { managed })
+    }
+
+    pub fn load(ipt: Arc<IPT>) -> Result<Self> {
+        let managed = IPTableChain::load(ipt, IPTABLE_UDP.to_string())?;
+
+        Ok(UdpRedirect { managed })
+    }
+}
+
+#[async_trait]
+impl<IPT> Redirect for UdpRedirect<IPT>
+where
+    IPT: IPTables + Send + Sync,
+{
+    async fn mount_entrypoint(&self) -> Result<()> {
+        self.managed.inner().add_rule(
+            Self::ENTRYPOINT,
+            &format!("-j {}", self.managed.chain_name()),
+        )?;
+
+        Ok(())
+    }
+
+    async fn unmount_entrypoint(&self) -> Result<()> {
+        self.managed.inner().remove_rule(
+            Self::ENTRYPOINT,
+            &format!("-j {}", self.managed.chain_name()),
+        )?;
+
+        Ok(())
+    }
+
+    async fn add_redirect(&self, redirected_port: Port, target_port: Port) -> Result<()> {
+        let redirect_rule =
+            format!("-m udp -p udp --dport {redirected_port} -j REDIRECT --to-ports {target_port}");
+
+        self.managed.add_rule(&redirect_rule)?;
+
+        Ok(())
+    }
+
+    async fn remove_redirect(&self, redirected_port: Port, target_port: Port) -> Result<()> {
+        let redirect_rule =
+            format!("-m udp -p udp --dport {redirected_port} -j REDIRECT --to-ports {target_port}");
+
+        self.managed.remove_rule(&redirect_rule)?;
+
+        Ok(())
+    }
+}
+
+impl<IPT> Deref for UdpRedirect<IPT>
+where
+    IPT: IPTables,
+{
+    type Target = IPTableChain<IPT>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.managed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::*;
+
+    use super::*;
+    use crate::steal::ip_tables::MockIPTables;
+
+    #[tokio::test]
+    async fn add_and_remove_redirect() {
+        let mut mock = MockIPTables::new();
+
+        mock.expect_create_chain()
+            .with(eq(IPTABLE_UDP.as_str()))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        mock.expect_add_rule()
+            .with(eq("PREROUTING"), str::starts_with("-j MIRRORD_UDP_"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        mock.expect_insert_rule()
+            .with(
+                eq(IPTABLE_UDP.as_str()),
+                eq("-m udp -p udp --dport 53 -j REDIRECT --to-ports 4200"),
+                eq(1),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        mock.expect_remove_rule()
+            .with(
+                eq(IPTABLE_UDP.as_str()),
+                eq("-m udp -p udp --dport 53 -j REDIRECT --to-ports 4200"),
+            )
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        mock.expect_remove_chain()
+            .with(eq(IPTABLE_UDP.as_str()))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let udp = UdpRedirect::create(Arc::new(mock)).expect("Unable to create");
+
+        assert!(udp.mount_entrypoint().await.is_ok());
+        assert!(udp.add_redirect(53, 4200).await.is_ok());
+        assert!(udp.remove_redirect(53, 4200).await.is_ok());
+    }
+}
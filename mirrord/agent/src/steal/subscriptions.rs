@@ -9,7 +9,7 @@ use mirrord_protocol::{Port, RemoteResult, ResponseError};
 use tokio::net::{TcpListener, TcpStream};
 
 use super::{
-    http::HttpFilter,
+    http::ClientFilter,
     ip_tables::{new_iptables, IPTablesWrapper, SafeIpTables},
 };
 use crate::{error::AgentError, util::ClientId};
@@ -169,7 +169,7 @@ impl<R: PortRedirector> PortSubscriptions<R> {
     ///
     /// * `client_id` - identifier of the client that issued the subscription
     /// * `port` - number of the port to steal from
-    /// * `filter` - optional [`HttpFilter`]
+    /// * `filter` - optional [`ClientFilter`]
     ///
     /// # Warning
     ///
@@ -180,7 +180,7 @@ impl<R: PortRedirector> PortSubscriptions<R> {
         &mut self,
         client_id: ClientId,
         port: Port,
-        filter: Option<HttpFilter>,
+        filter: Option<ClientFilter>,
     ) -> Result<RemoteResult<Port>, R::Error> {
         let add_redirect = match self.subscriptions.entry(port) {
             Entry::Occupied(mut e) => {
@@ -297,16 +297,16 @@ pub enum PortSubscription {
     ///
     /// Belongs to a single client.
     Unfiltered(ClientId),
-    /// Only HTTP requests matching one of the [`HttpFilter`]s should be stolen (on behalf of the
+    /// Only HTTP requests matching one of the [`ClientFilter`]s should be stolen (on behalf of the
     /// filter owner).
     ///
     /// Can be shared by multiple clients.
-    Filtered(Arc<DashMap<ClientId, HttpFilter>>),
+    Filtered(Arc<DashMap<ClientId, ClientFilter>>),
 }
 
 impl PortSubscription {
     /// Create a new instance. Variant is picked based on the optional `filter`.
-    fn new(client_id: ClientId, filter: Option<HttpFilter>) -> Self {
+    fn new(client_id: ClientId, filter: Option<ClientFilter>) -> Self {
         match filter {
             Some(filter) => Self::Filtered(Arc::new([(client_id, filter)].into_iter().collect())),
             None => Self::Unfiltered(client_id),
@@ -315,7 +315,7 @@ impl PortSubscription {
 
     /// Try extending this subscription with a new subscription request.
     /// Return whether extension was successful.
-    fn try_extend(&mut self, client_id: ClientId, filter: Option<HttpFilter>) -> bool {
+    fn try_extend(&mut self, client_id: ClientId, filter: Option<ClientFilter>) -> bool {
         match (self, filter) {
             (_, None) => false,
 
@@ -345,6 +345,7 @@ mod test {
     use std::collections::HashSet;
 
     use super::*;
+    use crate::steal::http::HttpFilter;
 
     /// Implementation of [`PortRedirector`] that stores redirections in memory.
     /// Disallows duplicate redirections or removing a non-existent redirection.
@@ -411,8 +412,8 @@ mod test {
         }
     }
 
-    fn dummy_filter() -> HttpFilter {
-        HttpFilter::Header(".*".parse().unwrap())
+    fn dummy_filter() -> ClientFilter {
+        HttpFilter::Header(".*".parse().unwrap()).into()
     }
 
     #[tokio::test]
@@ -0,0 +1,484 @@
+//! Mirroring and stealing of the incoming UDP traffic.
+//!
+//! Mirrored datagrams are picked up with a raw socket ([`RawSocketUdpCapture`]), stolen datagrams
+//! are redirected with iptables ([`UdpRedirect`]) to a socket owned by the [`UdpIncomingTask`].
+
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use mirrord_protocol::{
+    udp::{DaemonUdpIncoming, IncomingDatagram, UdpIncomingMode, UdpPortSubscription, UdpReply},
+    Port, RemoteResult, ResponseError,
+};
+use socket2::{Domain, Socket, Type};
+use tokio::{
+    net::UdpSocket,
+    select,
+    sync::{
+        mpsc::{error::TrySendError, Receiver, Sender},
+        oneshot,
+    },
+};
+use tokio_stream::StreamMap;
+use tokio_util::{codec::BytesCodec, sync::CancellationToken, udp::UdpFramed};
+use tracing::Level;
+
+use crate::{
+    error::AgentError,
+    sniffer::udp_capture::RawSocketUdpCapture,
+    steal::ip_tables::{new_iptables, redirect::Redirect, udp::UdpRedirect, IPTablesWrapper},
+    util::{ChannelClosedFuture, ClientId, Subscriptions},
+};
+
+pub(crate) mod api;
+
+/// Commmand for [`UdpIncomingTask`].
+#[derive(Debug)]
+pub(crate) enum UdpIncomingCommandInner {
+    /// New client wants to use the task.
+    NewClient(
+        /// For sending the datagrams to the client.
+        Sender<DaemonUdpIncoming>,
+    ),
+    /// Client wants to start receiving datagrams incoming to a specific port.
+    Subscribe(
+        UdpPortSubscription,
+        /// Channel to notify with the result when the operation is done.
+        oneshot::Sender<RemoteResult<()>>,
+    ),
+    /// Client no longer wants to receive datagrams incoming to a specific port.
+    Unsubscribe(Port),
+    /// Client wants to reply to a stolen datagram.
+    Reply(UdpReply),
+}
+
+/// Client's command for [`UdpIncomingTask`].
+#[derive(Debug)]
+pub(crate) struct UdpIncomingCommand {
+    /// Id of the client.
+    pub client_id: ClientId,
+    /// Actual command.
+    pub command: UdpIncomingCommandInner,
+}
+
+/// Port stolen by one of the clients.
+struct StolenPort {
+    client_id: ClientId,
+    /// Receives the datagrams redirected from the stolen port, also used to send the replies.
+    socket: Arc<UdpSocket>,
+    /// Port of [`Self::socket`].
+    redirected_to: Port,
+}
+
+/// Task responsible for mirroring and stealing incoming UDP datagrams.
+///
+/// Many clients can mirror the same port, but only one client can steal it.
+pub(crate) struct UdpIncomingTask {
+    command_rx: Receiver<UdpIncomingCommand>,
+    /// Used to create the [`RawSocketUdpCapture`].
+    network_interface: Option<String>,
+    /// Used to create the [`RawSocketUdpCapture`].
+    is_mesh: bool,
+    /// For sending datagrams to the clients.
+    clients: HashMap<ClientId, Sender<DaemonUdpIncoming>>,
+    clients_closed: FuturesUnordered<ChannelClosedFuture<DaemonUdpIncoming>>,
+    /// Ports mirrored by the clients.
+    mirrored_ports: Subscriptions<Port, ClientId>,
+    /// Created with the first mirror subscription.
+    capture: Option<RawSocketUdpCapture>,
+    stolen_ports: HashMap<Port, StolenPort>,
+    /// Datagrams received on [`StolenPort::socket`]s.
+    stolen_datagrams: StreamMap<Port, UdpFramed<BytesCodec, Arc<UdpSocket>>>,
+    /// Created with the first steal subscription, removed when there are no more stolen ports.
+    redirect: Option<UdpRedirect<IPTablesWrapper>>,
+}
+
+impl fmt::Debug for UdpIncomingTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpIncomingTask")
+            .field("clients", &self.clients.keys())
+            .field("mirrored_ports", &self.mirrored_ports)
+            .field("stolen_ports", &self.stolen_ports.keys())
+            .finish()
+    }
+}
+
+impl UdpIncomingTask {
+    pub const TASK_NAME: &'static str = "UdpIncoming";
+
+    /// Capacity of the channels used to send datagrams to the clients.
+    pub const CLIENT_CHANNEL_CAPACITY: usize = 512;
+
+    /// Creates a new instance. `network_interface` and `is_mesh` are used to create the
+    /// [`RawSocketUdpCapture`] when needed.
+    pub(crate) fn new(
+        command_rx: Receiver<UdpIncomingCommand>,
+        network_interface: Option<String>,
+        is_mesh: bool,
+    ) -> Self {
+        Self {
+            command_rx,
+            network_interface,
+            is_mesh,
+            clients: Default::default(),
+            clients_closed: Default::default(),
+            mirrored_ports: Default::default(),
+            capture: None,
+            stolen_ports: Default::default(),
+            stolen_datagrams: Default::default(),
+            redirect: None,
+        }
+    }
+
+    /// Runs the task loop, removes the iptables redirect on exit.
+    #[tracing::instrument(level = Level::DEBUG, skip(cancel_token), err)]
+    pub(crate) async fn run(mut self, cancel_token: CancellationToken) -> Result<(), AgentError> {
+        let result = self.run_inner(cancel_token).await;
+
+        if let Some(redirect) = self.redirect.take() {
+            redirect.unmount_entrypoint().await?;
+        }
+
+        result
+    }
+
+    async fn run_inner(&mut self, cancel_token: CancellationToken) -> Result<(), AgentError> {
+        loop {
+            select! {
+                command = self.command_rx.recv() => {
+                    let Some(command) = command else {
+                        tracing::debug!("command channel closed, exiting");
+                        break;
+                    };
+
+                    self.handle_command(command).await?;
+                },
+
+                Some(client_id) = self.clients_closed.next() => {
+                    self.handle_client_closed(client_id).await?;
+                }
+
+                result = Self::next_mirrored_datagram(self.capture.as_mut()) => {
+                    self.handle_mirrored_datagram(result?);
+                }
+
+                Some((port, result)) = self.stolen_datagrams.next() => match result {
+                    Ok((bytes, source)) => self.handle_stolen_datagram(port, source, bytes.to_vec()),
+                    Err(error) => {
+                        tracing::warn!(port, %error, "Failed to receive a stolen UDP datagram");
+                    }
+                },
+
+                _ = cancel_token.cancelled() => {
+                    tracing::debug!("token cancelled, exiting");
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next datagram from the capture, never resolves if there is no capture yet.
+    async fn next_mirrored_datagram(
+        capture: Option<&mut RawSocketUdpCapture>,
+    ) -> io::Result<IncomingDatagram> {
+        match capture {
+            Some(capture) => capture.next().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    #[tracing::instrument(level = Level::TRACE, skip(self), err)]
+    async fn handle_command(&mut self, command: UdpIncomingCommand) -> Result<(), AgentError> {
+        let UdpIncomingCommand { client_id, command } = command;
+
+        match command {
+            UdpIncomingCommandInner::NewClient(sender) => {
+                self.clients_closed
+                    .push(ChannelClosedFuture::new(sender.clone(), client_id));
+                self.clients.insert(client_id, sender);
+            }
+
+            UdpIncomingCommandInner::Subscribe(subscription, result_tx) => {
+                let result = match subscription.mode {
+                    UdpIncomingMode::Mirror => self.mirror(client_id, subscription.port).await?,
+                    UdpIncomingMode::Steal => self.steal(client_id, subscription.port).await?,
+                };
+
+                let _ = result_tx.send(result);
+            }
+
+            UdpIncomingCommandInner::Unsubscribe(port) => {
+                if self.mirrored_ports.unsubscribe(client_id, port) {
+                    self.update_packet_filter()?;
+                }
+
+                if self
+                    .stolen_ports
+                    .get(&port)
+                    .is_some_and(|stolen| stolen.client_id == client_id)
+                {
+                    self.release_stolen_port(port).await?;
+                }
+            }
+
+            UdpIncomingCommandInner::Reply(UdpReply { port, peer, bytes }) => {
+                let Some(stolen) = self
+                    .stolen_ports
+                    .get(&port)
+                    .filter(|stolen| stolen.client_id == client_id)
+                else {
+                    tracing::trace!(port, "Port is not stolen by this client, dropping reply");
+                    return Ok(());
+                };
+
+                // Dual-stack sockets expect IPv4 peers as IPv4-mapped IPv6 addresses.
+                let peer = match peer {
+                    SocketAddr::V4(v4)
+                        if stolen
+                            .socket
+                            .local_addr()
+                            .is_ok_and(|local| local.is_ipv6()) =>
+                    {
+                        SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port())
+                    }
+                    peer => peer,
+                };
+
+                // Connection tracking rewrites the source port of the reply to the stolen port.
+                if let Err(error) = stolen.socket.send_to(&bytes, peer).await {
+                    tracing::warn!(port, %peer, %error, "Failed to send a UDP reply");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes all subscriptions of the client.
+    #[tracing::instrument(level = Level::TRACE, skip(self), err)]
+    async fn handle_client_closed(&mut self, client_id: ClientId) -> Result<(), AgentError> {
+        self.clients.remove(&client_id);
+
+        if self.mirrored_ports.remove_client(client_id) {
+            self.update_packet_filter()?;
+        }
+
+        let stolen_ports = self
+            .stolen_ports
+            .iter()
+            .filter(|(_, stolen)| stolen.client_id == client_id)
+            .map(|(port, _)| *port)
+            .collect::<Vec<_>>();
+        for port in stolen_ports {
+            self.release_stolen_port(port).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a mirror subscription, creating the [`RawSocketUdpCapture`] if needed.
+    async fn mirror(
+        &mut self,
+        client_id: ClientId,
+        port: Port,
+    ) -> Result<RemoteResult<()>, AgentError> {
+        if self.capture.is_none() {
+            match RawSocketUdpCapture::new(self.network_interface.clone(), self.is_mesh).await {
+                Ok(capture) => self.capture = Some(capture),
+                Err(error) => {
+                    tracing::error!(%error, "Failed to create UDP raw capture");
+                    return Ok(Err(io::Error::other(error.to_string()).into()));
+                }
+            }
+        }
+
+        if self.mirrored_ports.subscribe(client_id, port) {
+            self.update_packet_filter()?;
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// Adds a steal subscription: binds a socket and redirects the `port` to it.
+    async fn steal(
+        &mut self,
+        client_id: ClientId,
+        port: Port,
+    ) -> Result<RemoteResult<()>, AgentError> {
+        if let Some(stolen) = self.stolen_ports.get(&port) {
+            return Ok(if stolen.client_id == client_id {
+                Ok(())
+            } else {
+                Err(ResponseError::PortAlreadyStolen(port))
+            });
+        }
+
+        let socket = match Self::bind_stolen_socket().await {
+            Ok(socket) => socket,
+            Err(error) => return Ok(Err(error.into())),
+        };
+        let redirected_to = socket.local_addr()?.port();
+
+        if let Err(error) = self.add_redirect(port, redirected_to).await {
+            tracing::error!(%error, port, "Failed to redirect UDP port");
+            return Ok(Err(io::Error::other(error.to_string()).into()));
+        }
+
+        let socket = Arc::new(socket);
+        self.stolen_datagrams
+            .insert(port, UdpFramed::new(socket.clone(), BytesCodec::new()));
+        self.stolen_ports.insert(
+            port,
+            StolenPort {
+                client_id,
+                socket,
+                redirected_to,
+            },
+        );
+
+        Ok(Ok(()))
+    }
+
+    /// Binds the socket that receives the datagrams redirected from a stolen port.
+    ///
+    /// The socket is dual-stack, so that it works with both IPv4 and IPv6 targets. Falls back to
+    /// IPv4 when IPv6 is disabled in the target's network namespace.
+    async fn bind_stolen_socket() -> io::Result<UdpSocket> {
+        let dual_stack = || {
+            let socket = Socket::new(Domain::IPV6, Type::DGRAM, None)?;
+            socket.set_only_v6(false)?;
+            socket.set_nonblocking(true)?;
+            socket.bind(&SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0).into())?;
+            UdpSocket::from_std(socket.into())
+        };
+
+        match dual_stack() {
+            Ok(socket) => Ok(socket),
+            Err(error) => {
+                tracing::debug!(%error, "Failed to bind a dual-stack UDP socket, using IPv4");
+                UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await
+            }
+        }
+    }
+
+    /// Redirects the `port` to `redirected_to`, mounting the [`UdpRedirect`] if needed.
+    ///
+    /// If this was supposed to be the first stolen port and the redirect fails, the
+    /// [`UdpRedirect`] is unmounted.
+    async fn add_redirect(&mut self, port: Port, redirected_to: Port) -> Result<(), AgentError> {
+        let redirect = match self.redirect.take() {
+            Some(redirect) => redirect,
+            None => {
+                let redirect =
+                    UdpRedirect::create(Arc::new(IPTablesWrapper::from(new_iptables())))?;
+                redirect.mount_entrypoint().await?;
+                redirect
+            }
+        };
+
+        let result = redirect.add_redirect(port, redirected_to).await;
+        if result.is_err() && self.stolen_ports.is_empty() {
+            redirect.unmount_entrypoint().await?;
+        } else {
+            self.redirect = Some(redirect);
+        }
+
+        result
+    }
+
+    /// Stops stealing the `port`, removes the iptables redirect if this was the last stolen port.
+    async fn release_stolen_port(&mut self, port: Port) -> Result<(), AgentError> {
+        let Some(stolen) = self.stolen_ports.remove(&port) else {
+            return Ok(());
+        };
+        self.stolen_datagrams.remove(&port);
+
+        if let Some(redirect) = self.redirect.as_ref() {
+            redirect.remove_redirect(port, stolen.redirected_to).await?;
+        }
+
+        if self.stolen_ports.is_empty()
+            && let Some(redirect) = self.redirect.take()
+        {
+            redirect.unmount_entrypoint().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Updates BPF filter used by [`Self::capture`] to match state of [`Self::mirrored_ports`].
+    fn update_packet_filter(&mut self) -> Result<(), AgentError> {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.set_ports(&self.mirrored_ports.get_subscribed_topics())?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a copy of the datagram to every client mirroring its destination port.
+    fn handle_mirrored_datagram(&self, datagram: IncomingDatagram) {
+        let Some(client_ids) = self
+            .mirrored_ports
+            .get_topic_subscribers(datagram.destination.port())
+        else {
+            return;
+        };
+
+        for client_id in client_ids {
+            if let Some(sender) = self.clients.get(client_id) {
+                Self::send_datagram(*client_id, sender, datagram.clone());
+            }
+        }
+    }
+
+    /// Sends the datagram to the client stealing the `port`.
+    fn handle_stolen_datagram(&self, port: Port, source: SocketAddr, bytes: Vec<u8>) {
+        let Some(client_id) = self.stolen_ports.get(&port).map(|stolen| stolen.client_id) else {
+            return;
+        };
+        let Some(sender) = self.clients.get(&client_id) else {
+            return;
+        };
+
+        // IPv4 peers are seen as IPv4-mapped IPv6 addresses on the dual-stack socket.
+        let source = SocketAddr::new(source.ip().to_canonical(), source.port());
+        // The original destination address is lost with the redirect, only the port is known.
+        let destination = match source.ip() {
+            IpAddr::V4(..) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+            IpAddr::V6(..) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port),
+        };
+        let datagram = IncomingDatagram {
+            source,
+            destination,
+            bytes,
+        };
+        Self::send_datagram(client_id, sender, datagram);
+    }
+
+    /// Datagrams are dropped if the client is not fast enough to pick them up, we can't block the
+    /// capture.
+    fn send_datagram(
+        client_id: ClientId,
+        sender: &Sender<DaemonUdpIncoming>,
+        datagram: IncomingDatagram,
+    ) {
+        match sender.try_send(DaemonUdpIncoming::Datagram(datagram)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(..)) => {
+                tracing::warn!(
+                    client_id,
+                    "Client's channel is full, dropping a UDP datagram"
+                );
+            }
+            // State will be cleaned up when `self.clients_closed` picks it up.
+            Err(TrySendError::Closed(..)) => {}
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use mirrord_protocol::udp::{DaemonUdpIncoming, LayerUdpIncoming};
+use tokio::sync::{
+    mpsc::{self, Receiver, Sender},
+    oneshot,
+};
+
+use super::{UdpIncomingCommand, UdpIncomingCommandInner, UdpIncomingTask};
+use crate::{error::AgentError, util::ClientId, watched_task::TaskStatus};
+
+/// Interface used by clients to interact with the [`UdpIncomingTask`]. Multiple instances of this
+/// struct operate on a single task instance.
+pub(crate) struct UdpIncomingApi {
+    /// Id of the client using this struct.
+    client_id: ClientId,
+    /// Channel used to send commands to the [`UdpIncomingTask`].
+    sender: Sender<UdpIncomingCommand>,
+    /// Channel used to receive datagrams from the [`UdpIncomingTask`].
+    receiver: Receiver<DaemonUdpIncoming>,
+    /// View on the task's status.
+    task_status: TaskStatus,
+    /// [`LayerUdpIncoming::PortSubscribe`] requests in progress, resolve to [`None`] if the task
+    /// dropped the result channel.
+    subscriptions_in_progress: FuturesUnordered<BoxFuture<'static, Option<DaemonUdpIncoming>>>,
+}
+
+impl UdpIncomingApi {
+    /// Creates a new instance of this struct and connects it to the [`UdpIncomingTask`].
+    /// * `client_id` - id of the client using this struct
+    /// * `task_sender` - channel used to send commands to the [`UdpIncomingTask`]
+    /// * `task_status` - handle to the [`UdpIncomingTask`] exit status
+    pub(crate) async fn new(
+        client_id: ClientId,
+        task_sender: Sender<UdpIncomingCommand>,
+        mut task_status: TaskStatus,
+    ) -> Result<Self, AgentError> {
+        let (sender, receiver) = mpsc::channel(UdpIncomingTask::CLIENT_CHANNEL_CAPACITY);
+
+        let command = UdpIncomingCommand {
+            client_id,
+            command: UdpIncomingCommandInner::NewClient(sender),
+        };
+        if task_sender.send(command).await.is_err() {
+            return Err(task_status.unwrap_err().await);
+        }
+
+        Ok(Self {
+            client_id,
+            sender: task_sender,
+            receiver,
+            task_status,
+            subscriptions_in_progress: Default::default(),
+        })
+    }
+
+    /// Sends the given command to the connected [`UdpIncomingTask`].
+    async fn send_command(&mut self, command: UdpIncomingCommandInner) -> Result<(), AgentError> {
+        let command = UdpIncomingCommand {
+            client_id: self.client_id,
+            command,
+        };
+
+        if self.sender.send(command).await.is_ok() {
+            Ok(())
+        } else {
+            Err(self.task_status.unwrap_err().await)
+        }
+    }
+
+    /// Returns the next message from the connected [`UdpIncomingTask`].
+    pub(crate) async fn recv(&mut self) -> Result<DaemonUdpIncoming, AgentError> {
+        tokio::select! {
+            message = self.receiver.recv() => match message {
+                Some(message) => Ok(message),
+                None => Err(self.task_status.unwrap_err().await),
+            },
+
+            Some(result) = self.subscriptions_in_progress.next() => match result {
+                Some(message) => Ok(message),
+                None => Err(self.task_status.unwrap_err().await),
+            },
+        }
+    }
+
+    /// Transforms the given message into a [`UdpIncomingCommand`] and passes it to the connected
+    /// [`UdpIncomingTask`].
+    pub(crate) async fn handle_client_message(
+        &mut self,
+        message: LayerUdpIncoming,
+    ) -> Result<(), AgentError> {
+        match message {
+            LayerUdpIncoming::PortSubscribe(subscription) => {
+                let (tx, rx) = oneshot::channel();
+                self.send_command(UdpIncomingCommandInner::Subscribe(subscription, tx))
+                    .await?;
+
+                let port = subscription.port;
+                self.subscriptions_in_progress.push(
+                    rx.map(move |result| {
+                        result
+                            .ok()
+                            .map(|result| DaemonUdpIncoming::SubscribeResult(port, result))
+                    })
+                    .boxed(),
+                );
+
+                Ok(())
+            }
+
+            LayerUdpIncoming::PortUnsubscribe(port) => {
+                self.send_command(UdpIncomingCommandInner::Unsubscribe(port))
+                    .await
+            }
+
+            LayerUdpIncoming::Reply(reply) => {
+                self.send_command(UdpIncomingCommandInner::Reply(reply))
+                    .await
+            }
+        }
+    }
+}
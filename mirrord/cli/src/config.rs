@@ -10,7 +10,7 @@ use std::{
 
 use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_complete::Shell;
-use mirrord_config::MIRRORD_CONFIG_FILE_ENV;
+use mirrord_config::{internal_proxy::MIRRORD_INTPROXY_HTTP_RECORD_ENV, MIRRORD_CONFIG_FILE_ENV};
 use mirrord_operator::setup::OperatorNamespace;
 use thiserror::Error;
 
@@ -88,6 +88,16 @@ pub(super) enum Commands {
     /// Run mirrord vpn
     #[command(hide = true)]
     Vpn(Box<VpnArgs>),
+
+    /// Replay HTTP traffic recorded with `mirrord exec --record` against a local process.
+    Replay(Box<ReplayArgs>),
+
+    /// Browse and copy the files of the target, as seen by the mirrord agent.
+    Fs(Box<FsArgs>),
+
+    /// Mount the file system of the target at a local directory, through FUSE.
+    #[cfg(target_os = "linux")]
+    Mount(Box<MountArgs>),
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
@@ -101,6 +111,9 @@ pub enum FsMode {
     Local,
     /// Read & Write from local, apart from overrides (hardcoded and configured in file)
     LocalWithOverrides,
+    /// Read from remote, Write to local copies of the remote files, apart from overrides
+    /// (hardcoded and configured in file)
+    Cow,
 }
 
 impl core::fmt::Display for FsMode {
@@ -110,6 +123,7 @@ impl core::fmt::Display for FsMode {
             FsMode::LocalWithOverrides => "localwithoverrides",
             FsMode::Read => "read",
             FsMode::Write => "write",
+            FsMode::Cow => "cow",
         })
     }
 }
@@ -173,6 +187,12 @@ pub(super) struct ExecParams {
     #[arg(long = "steal")]
     pub tcp_steal: bool,
 
+    /// Record stolen HTTP requests and responses to this file, can be replayed with
+    /// `mirrord replay`. Uses the HAR format if the file extension is `.har`, JSON Lines
+    /// otherwise.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub record: Option<PathBuf>,
+
     /// Disable tcp/udp outgoing traffic
     #[arg(long)]
     pub no_outgoing: bool,
@@ -285,6 +305,16 @@ impl ExecParams {
             envs.insert("MIRRORD_AGENT_TCP_STEAL_TRAFFIC".into(), "true".into());
         };
 
+        if let Some(record) = &self.record {
+            // Intproxy runs in a different working directory.
+            let full_path = std::path::absolute(record)
+                .map_err(|e| CliError::AbsoluteRecordPathFailed(record.clone(), e))?;
+            envs.insert(
+                MIRRORD_INTPROXY_HTTP_RECORD_ENV.into(),
+                full_path.into_os_string(),
+            );
+        }
+
         if self.no_outgoing || self.no_tcp_outgoing {
             envs.insert("MIRRORD_TCP_OUTGOING".into(), "false".into());
         }
@@ -706,6 +736,125 @@ pub(super) struct VerifyConfigArgs {
     pub(super) path: PathBuf,
 }
 
+#[derive(Args, Debug)]
+pub(super) struct ReplayArgs {
+    /// Recording file, in HAR or JSON Lines format.
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub(super) path: PathBuf,
+
+    /// Send the requests to this address, instead of localhost and the recorded remote port.
+    #[arg(long)]
+    pub(super) to: Option<SocketAddr>,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct FsArgs {
+    #[command(subcommand)]
+    pub command: FsCommand,
+
+    /// Target whose files to browse.
+    /// Valid formats: deployment/name, pod/name, pod/name/container/name
+    #[arg(short = 't', long, global = true)]
+    pub target: Option<String>,
+
+    /// Namespace of the target. Defaults to "default".
+    #[arg(short = 'n', long, global = true)]
+    pub target_namespace: Option<String>,
+
+    /// Specify config file to use
+    #[arg(short = 'f', long, global = true, value_hint = ValueHint::FilePath)]
+    pub config_file: Option<PathBuf>,
+
+    /// Specify the format of the output.
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "FORMAT",
+        value_enum,
+        global = true,
+        default_value_t = FsOutput::Plain
+    )]
+    pub output: FsOutput,
+}
+
+#[derive(Subcommand, Debug)]
+/// Commands for browsing the remote file system with `mirrord fs ...`.
+pub(super) enum FsCommand {
+    /// List a remote directory.
+    Ls {
+        /// Remote path.
+        path: PathBuf,
+    },
+    /// Print the content of a remote file.
+    Cat {
+        /// Remote path.
+        path: PathBuf,
+    },
+    /// Print the metadata of a remote file.
+    Stat {
+        /// Remote path.
+        path: PathBuf,
+
+        /// Follow symbolic links.
+        #[arg(short = 'L', long)]
+        dereference: bool,
+    },
+    /// Copy a remote file or directory to the local machine.
+    Cp {
+        /// Remote path.
+        remote: PathBuf,
+
+        /// Local path. If it's an existing directory, the remote file is copied into it.
+        #[arg(value_hint = ValueHint::AnyPath)]
+        local: PathBuf,
+
+        /// Copy directories recursively.
+        #[arg(short = 'r', long)]
+        recursive: bool,
+    },
+    /// Print a remote directory tree.
+    Tree {
+        /// Remote path.
+        path: PathBuf,
+
+        /// Descend at most this many levels below the given directory.
+        #[arg(short = 'L', long)]
+        max_depth: Option<usize>,
+    },
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Args, Debug)]
+pub(super) struct MountArgs {
+    /// Target whose file system to mount, or `targetless`.
+    /// Valid formats: deployment/name, pod/name, pod/name/container/name
+    pub target: String,
+
+    /// Existing empty local directory to mount the file system at.
+    #[arg(value_hint = ValueHint::DirPath)]
+    pub mountpoint: PathBuf,
+
+    /// Namespace of the target. Defaults to "default".
+    #[arg(short = 'n', long)]
+    pub target_namespace: Option<String>,
+
+    /// Specify config file to use
+    #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+    pub config_file: Option<PathBuf>,
+
+    /// Allow modifying the remote files through the mount. Read-only by default.
+    #[arg(short = 'w', long)]
+    pub read_write: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// Output formats of the `mirrord fs` commands.
+pub(super) enum FsOutput {
+    /// Human readable, similar to the coreutils.
+    Plain,
+    Json,
+}
+
 #[derive(Args, Debug)]
 pub(super) struct CompletionsArgs {
     pub(super) shell: Shell,
@@ -726,6 +875,25 @@ pub(super) enum DiagnoseCommand {
         #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
         config_file: Option<PathBuf>,
     },
+    /// Resolve the given names repeatedly through the internal proxy and the agent, and provide
+    /// the hit rates of their DNS caches.
+    Dns {
+        /// Names to resolve.
+        #[arg(required = true)]
+        names: Vec<String>,
+
+        /// How many times each name is resolved.
+        #[arg(short, long, default_value_t = 10)]
+        iterations: u32,
+
+        /// Milliseconds to wait between the iterations.
+        #[arg(long, default_value_t = 1000)]
+        interval: u64,
+
+        /// Specify config file to use
+        #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+        config_file: Option<PathBuf>,
+    },
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
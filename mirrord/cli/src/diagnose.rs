@@ -1,13 +1,37 @@
-use std::{path::Path, time::Duration};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    time::Duration,
+};
 
 use mirrord_analytics::NullReporter;
 use mirrord_config::{
     config::{ConfigContext, MirrordConfig},
-    LayerFileConfig,
+    LayerConfig, LayerFileConfig,
+};
+use mirrord_intproxy::{
+    agent_conn::AgentConnection as IntProxyAgentConnection,
+    proxies::{incoming::header_rules::HttpHeaderRules, simple::DnsCache},
+    IntProxy,
+};
+use mirrord_intproxy_protocol::{
+    codec::{self, AsyncDecoder, AsyncEncoder, CodecError},
+    DnsCacheStatsRequest, LayerToProxyMessage, LocalMessage, MessageId, NewSessionRequest,
+    ProcessInfo, ProxyToLayerMessage,
 };
 use mirrord_progress::{Progress, ProgressTracker};
-use mirrord_protocol::{ClientMessage, DaemonMessage};
-use tokio::{sync::mpsc, time::Instant};
+use mirrord_protocol::{
+    dns::{AddressFamily, DnsCacheStats, GetAddrInfoRequestV2},
+    ClientMessage, DaemonMessage,
+};
+use tokio::{
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::mpsc,
+    time::Instant,
+};
 use tracing::Level;
 
 use crate::{
@@ -45,11 +69,8 @@ async fn ping(
     }
 }
 
-/// Create a targetless session and run pings to diagnose network latency.
-#[tracing::instrument(level = Level::TRACE, ret)]
-async fn diagnose_latency(config: Option<&Path>) -> Result<()> {
-    let mut progress = ProgressTracker::from_env("mirrord network diagnosis");
-
+/// Generates the [`LayerConfig`] for a diagnosis session, from the given file if any.
+fn diagnose_config(config: Option<&Path>) -> Result<LayerConfig> {
     let mut cfg_context = ConfigContext::default();
     let config = if let Some(path) = config {
         LayerFileConfig::from_path(path)?.generate_config(&mut cfg_context)
@@ -61,6 +82,16 @@ async fn diagnose_latency(config: Option<&Path>) -> Result<()> {
         remove_proxy_env();
     }
 
+    Ok(config)
+}
+
+/// Create a targetless session and run pings to diagnose network latency.
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn diagnose_latency(config: Option<&Path>) -> Result<()> {
+    let mut progress = ProgressTracker::from_env("mirrord network diagnosis");
+
+    let config = diagnose_config(config)?;
+
     let mut analytics = NullReporter::default();
     let (_, mut connection) = create_and_connect(&config, &mut progress, &mut analytics).await?;
 
@@ -97,9 +128,246 @@ async fn diagnose_latency(config: Option<&Path>) -> Result<()> {
     Ok(())
 }
 
+/// Connection with the internal proxy started by [`diagnose_dns`], through which the diagnosis
+/// acts like a layer.
+struct ProxyConnection {
+    encoder: AsyncEncoder<LocalMessage<LayerToProxyMessage>, OwnedWriteHalf>,
+    decoder: AsyncDecoder<LocalMessage<ProxyToLayerMessage>, OwnedReadHalf>,
+    next_message_id: MessageId,
+}
+
+impl ProxyConnection {
+    /// Connects to the internal proxy listening on the given address and starts a new layer
+    /// session.
+    async fn connect(address: SocketAddr) -> Result<Self> {
+        let stream = TcpStream::connect(address).await.map_err(|error| {
+            CliError::DnsDiagnosisFailed(format!(
+                "failed to connect to the internal proxy: {error}"
+            ))
+        })?;
+        let (encoder, decoder) = codec::make_async_framed(stream);
+
+        let mut connection = Self {
+            encoder,
+            decoder,
+            next_message_id: 0,
+        };
+
+        let process_info = ProcessInfo {
+            pid: std::process::id(),
+            name: "mirrord".to_string(),
+            cmdline: std::env::args().collect(),
+            loaded: false,
+        };
+        match connection
+            .request(LayerToProxyMessage::NewSession(NewSessionRequest::New(
+                process_info,
+            )))
+            .await?
+        {
+            ProxyToLayerMessage::NewSession(..) => Ok(connection),
+            other => Err(unexpected_message(other)),
+        }
+    }
+
+    /// Sends the given message to the internal proxy and returns its response.
+    async fn request(&mut self, message: LayerToProxyMessage) -> Result<ProxyToLayerMessage> {
+        let message_id = self.next_message_id;
+        self.next_message_id += 1;
+
+        self.encoder
+            .send(&LocalMessage {
+                message_id,
+                inner: message,
+            })
+            .await
+            .map_err(proxy_failed)?;
+        self.encoder.flush().await.map_err(proxy_failed)?;
+
+        match self.decoder.receive().await.map_err(proxy_failed)? {
+            Some(LocalMessage { inner, .. }) => Ok(inner),
+            None => Err(CliError::DnsDiagnosisFailed(
+                "internal proxy unexpectedly closed connection".to_string(),
+            )),
+        }
+    }
+}
+
+fn proxy_failed(error: CodecError) -> CliError {
+    CliError::DnsDiagnosisFailed(format!(
+        "failed to communicate with the internal proxy: {error}"
+    ))
+}
+
+fn unexpected_message(message: ProxyToLayerMessage) -> CliError {
+    CliError::DnsDiagnosisFailed(format!(
+        "internal proxy sent an unexpected message: {message:?}"
+    ))
+}
+
+/// Describes the hit rate of a DNS cache, e.g. `75.0% (3/4)`.
+fn hit_rate(stats: DnsCacheStats) -> String {
+    match stats.hit_rate() {
+        Some(rate) => format!("{rate:.1}% ({}/{})", stats.hits, stats.hits + stats.misses),
+        None => "no lookups".to_string(),
+    }
+}
+
+/// Resolves the given `names` repeatedly through the internal proxy listening on `address`, and
+/// reports the hit rates of its DNS cache and of the agent DNS cache.
+async fn resolve_through_proxy<P: Progress>(
+    progress: &mut P,
+    address: SocketAddr,
+    names: &[String],
+    iterations: u32,
+    interval: Duration,
+) -> Result<()> {
+    let mut proxy = ProxyConnection::connect(address).await?;
+    let mut statistics: Vec<Duration> = Vec::new();
+
+    for i in 0..iterations {
+        for name in names {
+            let request = GetAddrInfoRequestV2 {
+                node: name.clone(),
+                family: AddressFamily::Both,
+            };
+
+            let start = Instant::now();
+            let response = match proxy
+                .request(LayerToProxyMessage::GetAddrInfo(request))
+                .await?
+            {
+                ProxyToLayerMessage::GetAddrInfo(response) => response,
+                other => return Err(unexpected_message(other)),
+            };
+            statistics.push(start.elapsed());
+
+            if let Err(error) = &response.0 {
+                progress.warning(format!("failed to resolve `{name}`: {error}").as_str());
+            }
+        }
+
+        progress.info(format!("{}/{iterations} iterations completed", i + 1).as_str());
+
+        if i + 1 < iterations {
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    let stats = match proxy
+        .request(LayerToProxyMessage::DnsCacheStats(DnsCacheStatsRequest))
+        .await?
+    {
+        ProxyToLayerMessage::DnsCacheStats(stats) => stats,
+        other => return Err(unexpected_message(other)),
+    };
+
+    let proxy_hit_rate = stats
+        .proxy
+        .map(hit_rate)
+        .unwrap_or_else(|| "disabled".to_string());
+    let agent_hit_rate = stats
+        .agent
+        .map(hit_rate)
+        .unwrap_or_else(|| "not reported by the agent".to_string());
+
+    let avg = if statistics.is_empty() {
+        0
+    } else {
+        (statistics.iter().sum::<Duration>() / statistics.len() as u32).as_millis()
+    };
+
+    progress.success(Some(
+        format!(
+            "DNS cache statistics: internal proxy hit rate={proxy_hit_rate}, \
+            agent hit rate={agent_hit_rate}, avg lookup={avg}ms"
+        )
+        .as_str(),
+    ));
+
+    Ok(())
+}
+
+/// Create a targetless session and resolve the given `names` repeatedly, to diagnose the DNS
+/// caches.
+///
+/// Like in `mirrord exec`, an [`IntProxy`] is started with its own connection to the agent, and
+/// the lookups are sent to it the same way the layer does. Both the internal proxy and the agent
+/// report the statistics of their DNS caches.
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn diagnose_dns(
+    config: Option<&Path>,
+    names: &[String],
+    iterations: u32,
+    interval: Duration,
+) -> Result<()> {
+    let mut progress = ProgressTracker::from_env("mirrord DNS diagnosis");
+
+    let config = diagnose_config(config)?;
+
+    let mut analytics = NullReporter::default();
+    let (connect_info, connection) =
+        create_and_connect(&config, &mut progress, &mut analytics).await?;
+
+    // The internal proxy opens its own connection with the agent before ours is dropped, so that
+    // the agent does not exit in between.
+    let agent_conn = IntProxyAgentConnection::new(&config, Some(connect_info), &mut analytics)
+        .await
+        .map_err(|error| {
+            CliError::DnsDiagnosisFailed(format!(
+                "internal proxy failed to connect to the agent: {error}"
+            ))
+        })?;
+    drop(connection);
+
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+        .await
+        .and_then(|listener| Ok((listener.local_addr()?, listener)));
+    let (address, listener) = listener.map_err(|error| {
+        CliError::DnsDiagnosisFailed(format!("failed to set up the internal proxy: {error}"))
+    })?;
+
+    let proxy = IntProxy::new_with_connection(
+        agent_conn,
+        listener,
+        HttpHeaderRules::default(),
+        None,
+        None,
+        DnsCache::new(&config.feature.network.dns.cache),
+    );
+
+    tokio::select! {
+        result = proxy.run(
+            Duration::from_secs(config.internal_proxy.start_idle_timeout),
+            Duration::from_secs(config.internal_proxy.idle_timeout),
+        ) => {
+            let fail = match result {
+                Ok(()) => "internal proxy exited unexpectedly".to_string(),
+                Err(error) => format!("internal proxy failed: {error}"),
+            };
+            Err(CliError::DnsDiagnosisFailed(fail))
+        }
+        result = resolve_through_proxy(&mut progress, address, names, iterations, interval) => result,
+    }
+}
+
 /// Handle commands related to the operator `mirrord diagnose ...`
 pub(crate) async fn diagnose_command(args: DiagnoseArgs) -> Result<()> {
     match args.command {
         DiagnoseCommand::Latency { config_file } => diagnose_latency(config_file.as_deref()).await,
+        DiagnoseCommand::Dns {
+            names,
+            iterations,
+            interval,
+            config_file,
+        } => {
+            diagnose_dns(
+                config_file.as_deref(),
+                &names,
+                iterations,
+                Duration::from_millis(interval),
+            )
+            .await
+        }
     }
 }
@@ -4,9 +4,13 @@ use kube::core::ErrorResponse;
 use miette::Diagnostic;
 use mirrord_config::config::ConfigError;
 use mirrord_console::error::ConsoleError;
-use mirrord_intproxy::{agent_conn::ConnectionTlsError, error::IntProxyError};
+use mirrord_intproxy::{
+    agent_conn::ConnectionTlsError, error::IntProxyError,
+    proxies::incoming::header_rules::InvalidHeaderRule,
+};
 use mirrord_kube::error::KubeApiError;
 use mirrord_operator::client::error::{HttpError, OperatorApiError, OperatorOperation};
+use mirrord_protocol::ResponseError;
 use mirrord_vpn::error::VpnError;
 use reqwest::StatusCode;
 use thiserror::Error;
@@ -145,6 +149,18 @@ pub(crate) enum InternalProxyError {
     #[error("Initial ping pong with the agent failed: {0}")]
     #[diagnostic(help("{GENERAL_BUG}"))]
     InitialPingPongFailed(String),
+
+    #[error("Failed to create HTTP recording file at `{}`: {1}", .0.display())]
+    #[diagnostic(help("{GENERAL_HELP}"))]
+    HttpRecordFile(PathBuf, std::io::Error),
+
+    #[error("Failed to parse `feature.network.incoming.http`: {0}")]
+    #[diagnostic(help("Please check that the header names and values are valid.{GENERAL_HELP}"))]
+    HttpHeaderRules(InvalidHeaderRule),
+
+    #[error("Failed to build the file cache from `feature.fs.read_only`: {0}")]
+    #[diagnostic(help("Please check that the patterns are valid regexes.{GENERAL_HELP}"))]
+    FileCachePatterns(regex::Error),
 }
 
 /// Errors that can occur when executing the `mirrord operator setup` command.
@@ -244,6 +260,45 @@ pub(crate) enum CliError {
     #[diagnostic(help("Please check that the path is correct and that you have permissions to read it.{GENERAL_HELP}"))]
     CanonicalizeConfigPathFailed(PathBuf, std::io::Error),
 
+    #[error("Failed to get absolute path to HTTP recording file at `{}`: {1}", .0.display())]
+    #[diagnostic(help("Please check that the path is correct.{GENERAL_HELP}"))]
+    AbsoluteRecordPathFailed(PathBuf, std::io::Error),
+
+    #[error("Failed to read HTTP recording from `{}`: {1}", .0.display())]
+    #[diagnostic(help("The file should be created with `mirrord exec --record`.{GENERAL_HELP}"))]
+    ReplayReadFailed(PathBuf, std::io::Error),
+
+    #[error("Failed to build HTTP client for replay: {0}")]
+    #[diagnostic(help("{GENERAL_BUG}"))]
+    ReplayClientFailed(reqwest::Error),
+
+    #[error("{0} out of {1} replayed requests did not get the recorded response status")]
+    #[diagnostic(help("Check the local application logs for details."))]
+    ReplayMismatch(usize, usize),
+
+    #[error("Remote file operation on `{}` failed: {1}", .0.display())]
+    #[diagnostic(help("Please check that the path exists in the target and that the agent can access it.{GENERAL_HELP}"))]
+    RemoteFsFailed(PathBuf, ResponseError),
+
+    #[error("`{}` is a remote directory, use `--recursive` to copy it", .0.display())]
+    RemoteFsIsDirectory(PathBuf),
+
+    #[error("Failed to write local copy at `{}`: {1}", .0.display())]
+    #[diagnostic(help("Please check that the path is correct and that you have permissions to write it.{GENERAL_HELP}"))]
+    LocalFsFailed(PathBuf, std::io::Error),
+
+    #[error("Communication with the agent failed while browsing remote files: {0}")]
+    #[diagnostic(help("{GENERAL_HELP}"))]
+    RemoteFsConnectionFailed(String),
+
+    #[error("Failed to mount the remote file system at `{}`: {1}", .0.display())]
+    #[diagnostic(help("Please check that FUSE is installed (`fusermount3` or `fusermount` is in PATH) and that the mountpoint is an existing empty directory.{GENERAL_HELP}"))]
+    MountFailed(PathBuf, std::io::Error),
+
+    #[error("The agent (protocol version {0}) does not support modifying remote files")]
+    #[diagnostic(help("Mount without `--read-write`, or use a newer mirrord agent."))]
+    MountReadWriteNotSupported(semver::Version),
+
     #[cfg(target_os = "macos")]
     #[error("SIP Error: `{0:#?}`")]
     #[diagnostic(help(
@@ -349,6 +404,10 @@ pub(crate) enum CliError {
     ))]
     PingPongFailed(String),
 
+    #[error("DNS diagnosis failed: {0}")]
+    #[diagnostic(help("{GENERAL_HELP}"))]
+    DnsDiagnosisFailed(String),
+
     #[error("Failed to prepare mirrord operator client certificate: {0}")]
     #[diagnostic(help("{GENERAL_BUG}"))]
     OperatorClientCertError(String),
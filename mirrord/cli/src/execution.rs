@@ -6,14 +6,18 @@ use std::{
 
 use mirrord_analytics::{AnalyticsError, AnalyticsReporter, Reporter};
 use mirrord_config::{
-    config::ConfigError, internal_proxy::MIRRORD_INTPROXY_CONNECT_TCP_ENV, LayerConfig,
+    config::ConfigError, feature::network::incoming::OnLocalFailure,
+    internal_proxy::MIRRORD_INTPROXY_CONNECT_TCP_ENV, LayerConfig,
 };
 use mirrord_intproxy::agent_conn::AgentConnectInfo;
 use mirrord_operator::client::OperatorSession;
 use mirrord_progress::Progress;
 use mirrord_protocol::{
-    tcp::HTTP_COMPOSITE_FILTER_VERSION, ClientMessage, DaemonMessage, EnvVars, GetEnvVarsRequest,
-    LogLevel,
+    tcp::{
+        HTTP_COMPOSITE_FILTER_VERSION, HTTP_FILTER_PASSTHROUGH_VERSION,
+        HTTP_METHOD_QUERY_BODY_FILTER_VERSION,
+    },
+    ClientMessage, DaemonMessage, EnvVars, GetEnvVarsRequest, LogLevel,
 };
 #[cfg(target_os = "macos")]
 use mirrord_sip::sip_patch;
@@ -183,7 +187,12 @@ impl MirrordExecution {
             .await
             .inspect_err(|_| analytics.set_error(AnalyticsError::AgentConnection))?;
 
-        if config.feature.network.incoming.http_filter.is_composite() {
+        let incoming = &config.feature.network.incoming;
+        let http_filter = &incoming.http_filter;
+        let passthrough = incoming.is_steal()
+            && http_filter.is_filter_set()
+            && matches!(incoming.on_local_failure, OnLocalFailure::Passthrough);
+        if http_filter.is_composite() || passthrough {
             let version = match &connect_info {
                 AgentConnectInfo::Operator(OperatorSession {
                     operator_protocol_version: Some(version),
@@ -194,15 +203,41 @@ impl MirrordExecution {
                 }
                 _ => None,
             };
-            if !version
-                .map(|version| HTTP_COMPOSITE_FILTER_VERSION.matches(&version))
-                .unwrap_or(false)
+            if http_filter.is_composite()
+                && !version
+                    .as_ref()
+                    .map(|version| HTTP_COMPOSITE_FILTER_VERSION.matches(version))
+                    .unwrap_or(false)
             {
                 Err(ConfigError::Conflict(format!(
                     "Cannot use 'any_of' or 'all_of' HTTP filter types, protocol version used by mirrord-agent must match {}. Consider using a newer version of mirrord-agent",
                     *HTTP_COMPOSITE_FILTER_VERSION
                 )))?
             }
+
+            if http_filter.has_method_query_or_body_filter()
+                && !version
+                    .as_ref()
+                    .map(|version| HTTP_METHOD_QUERY_BODY_FILTER_VERSION.matches(version))
+                    .unwrap_or(false)
+            {
+                Err(ConfigError::Conflict(format!(
+                    "Cannot use 'method', 'query' or 'body' HTTP filter types, protocol version used by mirrord-agent must match {}. Consider using a newer version of mirrord-agent",
+                    *HTTP_METHOD_QUERY_BODY_FILTER_VERSION
+                )))?
+            }
+
+            if passthrough
+                && !version
+                    .as_ref()
+                    .map(|version| HTTP_FILTER_PASSTHROUGH_VERSION.matches(version))
+                    .unwrap_or(false)
+            {
+                Err(ConfigError::Conflict(format!(
+                    "Cannot use 'on_local_failure: passthrough', protocol version used by mirrord-agent must match {}. Consider using a newer version of mirrord-agent",
+                    *HTTP_FILTER_PASSTHROUGH_VERSION
+                )))?
+            }
         }
 
         let mut env_vars = if config.feature.env.load_from_process.unwrap_or(false) {
@@ -36,7 +36,7 @@ use local_ip_address::local_ip;
 use mirrord_analytics::{AnalyticsReporter, CollectAnalytics, Reporter};
 use mirrord_config::LayerConfig;
 use mirrord_intproxy::agent_conn::{AgentConnection, ConnectionTlsError};
-use mirrord_protocol::DaemonCodec;
+use mirrord_protocol::{ClientMessage, DaemonCodec, DaemonMessage};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::server::TlsStream;
 use tokio_util::{either::Either, sync::CancellationToken};
@@ -254,6 +254,15 @@ async fn handle_connection(
         tokio::select! {
             client_message = stream.next() => {
                 match client_message {
+                    // Compression is negotiated separately on each connection.
+                    Some(Ok(ClientMessage::SwitchCompression(compression))) => {
+                        let response = DaemonMessage::SwitchCompressionResponse(compression);
+                        if let Err(error) = stream.send(response).await {
+                            tracing::error!(?peer_addr, %error, "unable to send message to intproxy");
+
+                            break;
+                        }
+                    }
                     Some(Ok(client_message)) => {
                         if let Err(error) = agent_conn.agent_tx.send(client_message).await {
                             tracing::error!(?peer_addr, %error, "unable to send message to agent");
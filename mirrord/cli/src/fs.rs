@@ -0,0 +1,774 @@
+//! `mirrord fs {ls|cat|stat|cp|tree}` browses the file system of the target through the agent,
+//! without relying on tools (or a shell) being present in the target's image.
+//!
+//! The commands use the same [`FileRequest`]s as the layer, so they see exactly what an
+//! application running with mirrord would see.
+
+use std::{
+    fs::{self, File, Permissions},
+    io::{self, Write},
+    os::unix::fs::{symlink, PermissionsExt},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use mirrord_analytics::NullReporter;
+use mirrord_config::{LayerConfig, MIRRORD_CONFIG_FILE_ENV};
+use mirrord_progress::{Progress, ProgressTracker};
+use mirrord_protocol::{
+    file::{
+        CloseDirRequest, CloseFileRequest, DirEntryInternal, FdOpenDirRequest, MetadataInternal,
+        OpenFileRequest, OpenOptionsInternal, ReadDirBatchRequest, ReadDirRequest,
+        ReadLimitedFileRequest, ReadLinkFileRequest, XstatRequest, READDIR_BATCH_VERSION,
+    },
+    ClientMessage, DaemonMessage, FileRequest, FileResponse, RemoteResult,
+};
+use semver::Version;
+use serde::Serialize;
+
+use crate::{
+    config::{FsArgs, FsCommand, FsOutput},
+    connection::{create_and_connect, AgentConnection},
+    error::{CliError, Result},
+    util::remove_proxy_env,
+};
+
+/// How many bytes we request from the agent in a single [`ReadLimitedFileRequest`].
+const READ_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// How many entries we request from the agent in a single [`ReadDirBatchRequest`].
+const READ_DIR_BATCH_SIZE: usize = 128;
+
+/// Bit mask for the file type in [`MetadataInternal::mode`].
+const S_IFMT: u32 = 0o170000;
+
+/// Type of a remote file, from [`MetadataInternal::mode`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FileType {
+    File,
+    Directory,
+    Symlink,
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+    Unknown,
+}
+
+impl FileType {
+    fn from_mode(mode: u32) -> Self {
+        match mode & S_IFMT {
+            0o100000 => Self::File,
+            0o040000 => Self::Directory,
+            0o120000 => Self::Symlink,
+            0o010000 => Self::Fifo,
+            0o140000 => Self::Socket,
+            0o020000 => Self::CharDevice,
+            0o060000 => Self::BlockDevice,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// First character of the `ls -l` mode string.
+    fn as_char(self) -> char {
+        match self {
+            Self::File => '-',
+            Self::Directory => 'd',
+            Self::Symlink => 'l',
+            Self::Fifo => 'p',
+            Self::Socket => 's',
+            Self::CharDevice => 'c',
+            Self::BlockDevice => 'b',
+            Self::Unknown => '?',
+        }
+    }
+}
+
+/// A remote file, as printed by `mirrord fs ls` and `mirrord fs tree`.
+#[derive(Serialize, Debug)]
+struct Entry {
+    name: String,
+    #[serde(rename = "type")]
+    file_type: FileType,
+    /// Permission bits.
+    mode: u32,
+    size: u64,
+    user_id: u32,
+    group_id: u32,
+    modified: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link_target: Option<PathBuf>,
+    /// Only filled by `mirrord fs tree`, for directories.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<Entry>>,
+}
+
+impl Entry {
+    fn new(name: String, metadata: &MetadataInternal, link_target: Option<PathBuf>) -> Self {
+        Self {
+            name,
+            file_type: FileType::from_mode(metadata.mode),
+            mode: metadata.mode & !S_IFMT,
+            size: metadata.size,
+            user_id: metadata.user_id,
+            group_id: metadata.group_id,
+            modified: format_time(metadata.modification_time, false),
+            link_target,
+            children: None,
+        }
+    }
+
+    /// The name, with the target of the symlink.
+    fn display_name(&self) -> String {
+        match &self.link_target {
+            Some(target) => format!("{} -> {}", self.name, target.display()),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Full metadata of a remote file, as printed by `mirrord fs stat`.
+#[derive(Serialize, Debug)]
+struct Stat {
+    path: PathBuf,
+    #[serde(rename = "type")]
+    file_type: FileType,
+    /// Permission bits.
+    mode: u32,
+    size: u64,
+    user_id: u32,
+    group_id: u32,
+    inode: u64,
+    device_id: u64,
+    hard_links: u64,
+    block_size: u64,
+    blocks: u64,
+    accessed: String,
+    modified: String,
+    changed: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link_target: Option<PathBuf>,
+}
+
+/// Summary of `mirrord fs cp`.
+#[derive(Serialize, Debug, Default)]
+struct CopySummary {
+    files: usize,
+    directories: usize,
+    symlinks: usize,
+    bytes: u64,
+    /// Remote files that can't be copied, e.g. sockets or devices.
+    skipped: Vec<PathBuf>,
+}
+
+/// Formats a timestamp in nanoseconds since the epoch, as found in [`MetadataInternal`].
+fn format_time(nanos: i64, precise: bool) -> String {
+    let time = UNIX_EPOCH + Duration::from_nanos(nanos.try_into().unwrap_or_default());
+
+    if precise {
+        humantime::format_rfc3339_nanos(time).to_string()
+    } else {
+        humantime::format_rfc3339_seconds(time).to_string()
+    }
+}
+
+/// Formats the file type and permission bits like `ls -l`, e.g. `drwxr-xr-x`.
+fn format_mode(file_type: FileType, mode: u32) -> String {
+    let mut formatted = String::with_capacity(10);
+    formatted.push(file_type.as_char());
+
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        formatted.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        formatted.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        formatted.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+
+    formatted
+}
+
+/// Prints the value as a single line of JSON.
+fn print_json<T: Serialize>(value: &T) {
+    let json = serde_json::to_string(value).expect("output types always serialize");
+    println!("{json}");
+}
+
+/// Client of the agent's file system, sends [`FileRequest`]s over an [`AgentConnection`].
+///
+/// Also used by `mirrord mount`.
+pub(crate) struct RemoteFs {
+    connection: AgentConnection,
+    /// Protocol version of the agent.
+    version: Version,
+}
+
+impl RemoteFs {
+    /// Connects to the agent, as configured in the [`LayerConfig`].
+    pub(crate) async fn connect<P>(config: &LayerConfig, progress: &mut P) -> Result<Self>
+    where
+        P: Progress + Send + Sync,
+    {
+        if !config.use_proxy {
+            remove_proxy_env();
+        }
+
+        let mut analytics = NullReporter::default();
+        let (_, connection) = create_and_connect(config, progress, &mut analytics).await?;
+
+        Self::new(connection).await
+    }
+
+    /// Negotiates the protocol version with the agent.
+    async fn new(mut connection: AgentConnection) -> Result<Self> {
+        connection
+            .sender
+            .send(ClientMessage::SwitchProtocolVersion(
+                mirrord_protocol::VERSION.clone(),
+            ))
+            .await
+            .map_err(|_| {
+                CliError::RemoteFsConnectionFailed(
+                    "failed to send protocol version request".to_string(),
+                )
+            })?;
+
+        let version = loop {
+            match connection.receiver.recv().await {
+                Some(DaemonMessage::SwitchProtocolVersionResponse(version)) => break version,
+                Some(DaemonMessage::LogMessage(..)) => continue,
+                other => return Err(Self::unexpected(other)),
+            }
+        };
+
+        Ok(Self {
+            connection,
+            version,
+        })
+    }
+
+    pub(crate) fn version(&self) -> &Version {
+        &self.version
+    }
+
+    pub(crate) fn unexpected(message: Option<DaemonMessage>) -> CliError {
+        CliError::RemoteFsConnectionFailed(match message {
+            Some(DaemonMessage::Close(message)) => {
+                format!("agent closed connection with message: {message}")
+            }
+            Some(message) => format!("agent sent an unexpected message: {message:?}"),
+            None => "agent unexpectedly closed connection".to_string(),
+        })
+    }
+
+    async fn send_message(&mut self, message: ClientMessage) -> Result<()> {
+        self.connection.sender.send(message).await.map_err(|_| {
+            CliError::RemoteFsConnectionFailed("agent unexpectedly closed connection".to_string())
+        })
+    }
+
+    /// Sends a request that the agent does not answer, e.g. [`FileRequest::Close`].
+    pub(crate) async fn send(&mut self, request: FileRequest) -> Result<()> {
+        self.send_message(ClientMessage::FileRequest(request)).await
+    }
+
+    /// Keeps the connection alive while idle, the pong is skipped by [`RemoteFs::request`].
+    pub(crate) async fn ping(&mut self) -> Result<()> {
+        self.send_message(ClientMessage::Ping).await
+    }
+
+    /// Sends the request and waits for the agent's response.
+    pub(crate) async fn request(&mut self, request: FileRequest) -> Result<FileResponse> {
+        self.send(request).await?;
+
+        loop {
+            match self.connection.receiver.recv().await {
+                Some(DaemonMessage::File(response)) => break Ok(response),
+                Some(DaemonMessage::LogMessage(..) | DaemonMessage::Pong) => continue,
+                other => break Err(Self::unexpected(other)),
+            }
+        }
+    }
+
+    async fn stat(&mut self, path: &Path, follow_symlink: bool) -> Result<MetadataInternal> {
+        let response = self
+            .request(FileRequest::Xstat(XstatRequest {
+                path: Some(path.to_path_buf()),
+                fd: None,
+                follow_symlink,
+            }))
+            .await?;
+
+        match response {
+            FileResponse::Xstat(result) => Ok(Self::check(path, result)?.metadata),
+            other => Err(Self::unexpected(Some(DaemonMessage::File(other)))),
+        }
+    }
+
+    async fn read_link(&mut self, path: &Path) -> Result<PathBuf> {
+        let response = self
+            .request(FileRequest::ReadLink(ReadLinkFileRequest {
+                path: path.to_path_buf(),
+            }))
+            .await?;
+
+        match response {
+            FileResponse::ReadLink(result) => Ok(Self::check(path, result)?.path),
+            other => Err(Self::unexpected(Some(DaemonMessage::File(other)))),
+        }
+    }
+
+    /// Opens the remote path for reading, returns the agent's descriptor.
+    async fn open(&mut self, path: &Path) -> Result<u64> {
+        let response = self
+            .request(FileRequest::Open(OpenFileRequest {
+                path: path.to_path_buf(),
+                open_options: OpenOptionsInternal {
+                    read: true,
+                    ..Default::default()
+                },
+            }))
+            .await?;
+
+        match response {
+            FileResponse::Open(result) => Ok(Self::check(path, result)?.fd),
+            other => Err(Self::unexpected(Some(DaemonMessage::File(other)))),
+        }
+    }
+
+    async fn close(&mut self, fd: u64) -> Result<()> {
+        self.send(FileRequest::Close(CloseFileRequest { fd })).await
+    }
+
+    /// Returns the entries of the remote directory, sorted by name, without `.` and `..`.
+    async fn read_dir(&mut self, path: &Path) -> Result<Vec<DirEntryInternal>> {
+        let fd = self.open(path).await?;
+        let entries = self.read_dir_fd(path, fd).await;
+        self.close(fd).await?;
+
+        let mut entries = entries?;
+        entries.retain(|entry| entry.name != "." && entry.name != "..");
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(entries)
+    }
+
+    async fn read_dir_fd(&mut self, path: &Path, fd: u64) -> Result<Vec<DirEntryInternal>> {
+        let dir_fd = match self
+            .request(FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd: fd }))
+            .await?
+        {
+            FileResponse::OpenDir(result) => Self::check(path, result)?.fd,
+            other => return Err(Self::unexpected(Some(DaemonMessage::File(other)))),
+        };
+
+        let mut entries = Vec::new();
+        let result = loop {
+            let batch = if READDIR_BATCH_VERSION.matches(&self.version) {
+                match self
+                    .request(FileRequest::ReadDirBatch(ReadDirBatchRequest {
+                        remote_fd: dir_fd,
+                        amount: READ_DIR_BATCH_SIZE,
+                    }))
+                    .await?
+                {
+                    FileResponse::ReadDirBatch(result) => {
+                        result.map(|response| response.dir_entries)
+                    }
+                    other => break Err(Self::unexpected(Some(DaemonMessage::File(other)))),
+                }
+            } else {
+                match self
+                    .request(FileRequest::ReadDir(ReadDirRequest { remote_fd: dir_fd }))
+                    .await?
+                {
+                    FileResponse::ReadDir(result) => {
+                        result.map(|response| response.direntry.into_iter().collect())
+                    }
+                    other => break Err(Self::unexpected(Some(DaemonMessage::File(other)))),
+                }
+            };
+
+            match Self::check(path, batch) {
+                Ok(batch) if batch.is_empty() => break Ok(entries),
+                Ok(batch) => entries.extend(batch),
+                Err(error) => break Err(error),
+            }
+        };
+
+        self.send(FileRequest::CloseDir(CloseDirRequest { remote_fd: dir_fd }))
+            .await?;
+
+        result
+    }
+
+    /// Reads the whole remote file into the writer, returns the number of bytes read.
+    async fn read_file<W: Write>(&mut self, path: &Path, writer: &mut W) -> Result<u64> {
+        let fd = self.open(path).await?;
+        let result = self.read_fd(path, fd, writer).await;
+        self.close(fd).await?;
+
+        result
+    }
+
+    async fn read_fd<W: Write>(&mut self, path: &Path, fd: u64, writer: &mut W) -> Result<u64> {
+        let mut offset = 0;
+
+        loop {
+            let response = self
+                .request(FileRequest::ReadLimited(ReadLimitedFileRequest {
+                    remote_fd: fd,
+                    buffer_size: READ_CHUNK_SIZE,
+                    start_from: offset,
+                }))
+                .await?;
+
+            let read = match response {
+                FileResponse::ReadLimited(result) => Self::check(path, result)?,
+                other => return Err(Self::unexpected(Some(DaemonMessage::File(other)))),
+            };
+
+            if read.read_amount == 0 {
+                break Ok(offset);
+            }
+
+            writer
+                .write_all(&read.bytes)
+                .map_err(|error| CliError::LocalFsFailed(path.to_path_buf(), error))?;
+            offset += read.read_amount;
+        }
+    }
+
+    /// Builds the [`Entry`] of a remote path, without following symlinks.
+    async fn entry(&mut self, path: &Path, name: String) -> Result<Entry> {
+        let metadata = self.stat(path, false).await?;
+        let link_target = if FileType::from_mode(metadata.mode) == FileType::Symlink {
+            Some(self.read_link(path).await?)
+        } else {
+            None
+        };
+
+        Ok(Entry::new(name, &metadata, link_target))
+    }
+
+    fn check<T>(path: &Path, result: RemoteResult<T>) -> Result<T> {
+        result.map_err(|error| CliError::RemoteFsFailed(path.to_path_buf(), error))
+    }
+}
+
+/// Name of the remote path as shown in the output, the whole path for the root of the listing.
+fn entry_name(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+async fn ls(client: &mut RemoteFs, path: &Path, output: FsOutput) -> Result<()> {
+    let metadata = client.stat(path, true).await?;
+
+    let entries = if FileType::from_mode(metadata.mode) == FileType::Directory {
+        let mut entries = Vec::new();
+        for dir_entry in client.read_dir(path).await? {
+            entries.push(
+                client
+                    .entry(&path.join(&dir_entry.name), dir_entry.name)
+                    .await?,
+            );
+        }
+        entries
+    } else {
+        vec![client.entry(path, entry_name(path)).await?]
+    };
+
+    match output {
+        FsOutput::Json => print_json(&entries),
+        FsOutput::Plain => {
+            let size_width = entries
+                .iter()
+                .map(|entry| entry.size.to_string().len())
+                .max()
+                .unwrap_or_default();
+
+            for entry in &entries {
+                println!(
+                    "{} {:>5} {:>5} {:>size_width$} {} {}",
+                    format_mode(entry.file_type, entry.mode),
+                    entry.user_id,
+                    entry.group_id,
+                    entry.size,
+                    entry.modified,
+                    entry.display_name(),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn cat(client: &mut RemoteFs, path: &Path) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    client.read_file(path, &mut stdout).await?;
+    stdout
+        .flush()
+        .map_err(|error| CliError::LocalFsFailed(path.to_path_buf(), error))
+}
+
+async fn stat(
+    client: &mut RemoteFs,
+    path: &Path,
+    dereference: bool,
+    output: FsOutput,
+) -> Result<()> {
+    let metadata = client.stat(path, dereference).await?;
+    let file_type = FileType::from_mode(metadata.mode);
+    let link_target = if file_type == FileType::Symlink {
+        Some(client.read_link(path).await?)
+    } else {
+        None
+    };
+
+    let stat = Stat {
+        path: path.to_path_buf(),
+        file_type,
+        mode: metadata.mode & !S_IFMT,
+        size: metadata.size,
+        user_id: metadata.user_id,
+        group_id: metadata.group_id,
+        inode: metadata.inode,
+        device_id: metadata.device_id,
+        hard_links: metadata.hard_links,
+        block_size: metadata.block_size,
+        blocks: metadata.blocks,
+        accessed: format_time(metadata.access_time, true),
+        modified: format_time(metadata.modification_time, true),
+        changed: format_time(metadata.creation_time, true),
+        link_target,
+    };
+
+    match output {
+        FsOutput::Json => print_json(&stat),
+        FsOutput::Plain => {
+            match &stat.link_target {
+                Some(target) => println!("  File: {} -> {}", path.display(), target.display()),
+                None => println!("  File: {}", path.display()),
+            }
+            println!(
+                "  Size: {}\tBlocks: {}\tIO Block: {}\t{:?}",
+                stat.size, stat.blocks, stat.block_size, stat.file_type
+            );
+            println!(
+                "Device: {}\tInode: {}\tLinks: {}",
+                stat.device_id, stat.inode, stat.hard_links
+            );
+            println!(
+                "Access: ({:04o}/{})\tUid: {}\tGid: {}",
+                stat.mode,
+                format_mode(stat.file_type, stat.mode),
+                stat.user_id,
+                stat.group_id
+            );
+            println!("Access: {}", stat.accessed);
+            println!("Modify: {}", stat.modified);
+            println!("Change: {}", stat.changed);
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies the remote path to the local path, the same way `cp -r` would.
+///
+/// Symlinks are copied as symlinks, files keep their permission bits. Remote files that can't be
+/// copied (sockets, devices, etc.) are skipped and reported in the summary.
+async fn cp(
+    client: &mut RemoteFs,
+    remote: &Path,
+    local: &Path,
+    recursive: bool,
+    output: FsOutput,
+) -> Result<()> {
+    let local = match remote.file_name() {
+        Some(name) if local.is_dir() => local.join(name),
+        _ => local.to_path_buf(),
+    };
+
+    let mut summary = CopySummary::default();
+    let mut pending = vec![(remote.to_path_buf(), local)];
+
+    while let Some((remote, local)) = pending.pop() {
+        let metadata = client.stat(&remote, false).await?;
+
+        match FileType::from_mode(metadata.mode) {
+            FileType::Directory if !recursive => return Err(CliError::RemoteFsIsDirectory(remote)),
+            FileType::Directory => {
+                fs::create_dir_all(&local)
+                    .map_err(|error| CliError::LocalFsFailed(local.clone(), error))?;
+
+                for entry in client.read_dir(&remote).await?.into_iter().rev() {
+                    pending.push((remote.join(&entry.name), local.join(&entry.name)));
+                }
+
+                summary.directories += 1;
+            }
+            FileType::Symlink => {
+                let target = client.read_link(&remote).await?;
+                symlink(target, &local)
+                    .map_err(|error| CliError::LocalFsFailed(local.clone(), error))?;
+
+                summary.symlinks += 1;
+            }
+            FileType::File => {
+                let mut file = File::create(&local)
+                    .map_err(|error| CliError::LocalFsFailed(local.clone(), error))?;
+                summary.bytes += client.read_file(&remote, &mut file).await?;
+                file.set_permissions(Permissions::from_mode(metadata.mode & 0o7777))
+                    .map_err(|error| CliError::LocalFsFailed(local.clone(), error))?;
+
+                summary.files += 1;
+            }
+            _ => summary.skipped.push(remote),
+        }
+    }
+
+    match output {
+        FsOutput::Json => print_json(&summary),
+        FsOutput::Plain => {
+            println!(
+                "Copied {} files ({} bytes), {} directories and {} symlinks",
+                summary.files, summary.bytes, summary.directories, summary.symlinks
+            );
+            for skipped in &summary.skipped {
+                println!("Skipped {}", skipped.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn tree(
+    client: &mut RemoteFs,
+    path: &Path,
+    max_depth: Option<usize>,
+    output: FsOutput,
+) -> Result<()> {
+    let mut root = client.entry(path, entry_name(path)).await?;
+    if root.file_type == FileType::Symlink {
+        let metadata = client.stat(path, true).await?;
+        root.file_type = FileType::from_mode(metadata.mode);
+    }
+
+    fill_children(client, path, &mut root, 0, max_depth).await?;
+
+    match output {
+        FsOutput::Json => print_json(&root),
+        FsOutput::Plain => {
+            println!("{}", root.display_name());
+            print_children(&root, "");
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the directories of the tree, depth first.
+///
+/// Symlinks to directories are not followed, so the tree can't contain cycles.
+async fn fill_children(
+    client: &mut RemoteFs,
+    path: &Path,
+    entry: &mut Entry,
+    depth: usize,
+    max_depth: Option<usize>,
+) -> Result<()> {
+    if entry.file_type != FileType::Directory || max_depth.is_some_and(|max| depth >= max) {
+        return Ok(());
+    }
+
+    let mut children = Vec::new();
+    for dir_entry in client.read_dir(path).await? {
+        let child_path = path.join(&dir_entry.name);
+        let mut child = client.entry(&child_path, dir_entry.name).await?;
+        Box::pin(fill_children(
+            client,
+            &child_path,
+            &mut child,
+            depth + 1,
+            max_depth,
+        ))
+        .await?;
+        children.push(child);
+    }
+
+    entry.children = Some(children);
+    Ok(())
+}
+
+fn print_children(entry: &Entry, prefix: &str) {
+    let children = entry.children.as_deref().unwrap_or_default();
+
+    for (index, child) in children.iter().enumerate() {
+        let last = index + 1 == children.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        println!("{prefix}{branch}{}", child.display_name());
+        print_children(child, &format!("{prefix}{indent}"));
+    }
+}
+
+/// Handle commands related to the remote file system `mirrord fs ...`
+pub(crate) async fn fs_command(args: FsArgs) -> Result<()> {
+    let mut progress = ProgressTracker::from_env("mirrord fs");
+
+    if let Some(config_file) = &args.config_file {
+        std::env::set_var(MIRRORD_CONFIG_FILE_ENV, config_file);
+    }
+    if let Some(target) = &args.target {
+        std::env::set_var("MIRRORD_IMPERSONATED_TARGET", target);
+    }
+    if let Some(namespace) = &args.target_namespace {
+        std::env::set_var("MIRRORD_TARGET_NAMESPACE", namespace);
+    }
+
+    let config = LayerConfig::from_env()?;
+    let mut client = RemoteFs::connect(&config, &mut progress).await?;
+    progress.success(None);
+
+    match args.command {
+        FsCommand::Ls { path } => ls(&mut client, &path, args.output).await,
+        FsCommand::Cat { path } => cat(&mut client, &path).await,
+        FsCommand::Stat { path, dereference } => {
+            stat(&mut client, &path, dereference, args.output).await
+        }
+        FsCommand::Cp {
+            remote,
+            local,
+            recursive,
+        } => cp(&mut client, &remote, &local, recursive, args.output).await,
+        FsCommand::Tree { path, max_depth } => {
+            tree(&mut client, &path, max_depth, args.output).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0o100644, "-rw-r--r--")]
+    #[case(0o040755, "drwxr-xr-x")]
+    #[case(0o120777, "lrwxrwxrwx")]
+    #[case(0o020600, "crw-------")]
+    fn mode_formatted_like_ls(#[case] mode: u32, #[case] expected: &str) {
+        assert_eq!(
+            format_mode(FileType::from_mode(mode), mode & !S_IFMT),
+            expected
+        );
+    }
+}
@@ -24,6 +24,10 @@ use mirrord_config::LayerConfig;
 use mirrord_intproxy::{
     agent_conn::{AgentConnectInfo, AgentConnection},
     error::IntProxyError,
+    proxies::{
+        incoming::{header_rules::HttpHeaderRules, record::HttpRecorder},
+        simple::{DnsCache, FileCache},
+    },
     IntProxy,
 };
 use mirrord_protocol::{ClientMessage, DaemonMessage, LogLevel, LogMessage};
@@ -138,13 +142,37 @@ pub(crate) async fn proxy(listen_port: u16, watch: drain::Watch) -> Result<(), I
     let first_connection_timeout = Duration::from_secs(config.internal_proxy.start_idle_timeout);
     let consecutive_connection_timeout = Duration::from_secs(config.internal_proxy.idle_timeout);
 
-    IntProxy::new_with_connection(agent_conn, listener)
-        .run(first_connection_timeout, consecutive_connection_timeout)
-        .await
-        .map_err(InternalProxyError::from)
-        .inspect_err(|error| {
-            tracing::error!(%error, "Internal proxy encountered an error, exiting");
+    let http_header_rules = HttpHeaderRules::try_from(&config.feature.network.incoming.http)
+        .map_err(InternalProxyError::HttpHeaderRules)?;
+
+    let http_recorder = config
+        .internal_proxy
+        .http_record
+        .as_ref()
+        .map(|path| {
+            HttpRecorder::new(path)
+                .map_err(|error| InternalProxyError::HttpRecordFile(path.clone(), error))
         })
+        .transpose()?;
+
+    let file_cache =
+        FileCache::new(&config.feature.fs).map_err(InternalProxyError::FileCachePatterns)?;
+    let dns_cache = DnsCache::new(&config.feature.network.dns.cache);
+
+    IntProxy::new_with_connection(
+        agent_conn,
+        listener,
+        http_header_rules,
+        http_recorder,
+        file_cache,
+        dns_cache,
+    )
+    .run(first_connection_timeout, consecutive_connection_timeout)
+    .await
+    .map_err(InternalProxyError::from)
+    .inspect_err(|error| {
+        tracing::error!(%error, "Internal proxy encountered an error, exiting");
+    })
 }
 
 /// Creates a connection with the agent and handles one round of ping pong.
@@ -56,9 +56,13 @@ mod execution;
 mod extension;
 mod external_proxy;
 mod extract;
+mod fs;
 mod internal_proxy;
+#[cfg(target_os = "linux")]
+mod mount;
 mod operator;
 pub mod port_forward;
+mod replay;
 mod teams;
 mod util;
 mod verify_config;
@@ -218,6 +222,7 @@ fn print_config<P>(
     let fs_info = match config.feature.fs.mode {
         FsModeConfig::Read => "read only from the remote",
         FsModeConfig::Write => "read and write from the remote",
+        FsModeConfig::Cow => "read from the remote, write to local copies",
         _ => "read and write locally",
     };
     messages.push(format!("fs: file operations will default to {}", fs_info));
@@ -687,6 +692,10 @@ fn main() -> miette::Result<()> {
             Commands::ExternalProxy { port } => external_proxy::proxy(port, watch).await?,
             Commands::PortForward(args) => port_forward(&args, watch).await?,
             Commands::Vpn(args) => vpn::vpn_command(*args).await?,
+            Commands::Replay(args) => replay::replay_command(*args).await?,
+            Commands::Fs(args) => fs::fs_command(*args).await?,
+            #[cfg(target_os = "linux")]
+            Commands::Mount(args) => mount::mount_command(*args).await?,
         };
 
         Ok(())
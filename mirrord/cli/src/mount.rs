@@ -0,0 +1,942 @@
+//! `mirrord mount {target} {mountpoint}` exposes the file system of the target through FUSE, so
+//! that tools running outside of mirrord (IDEs, linters, language servers) can see it.
+//!
+//! The kernel's FUSE operations are translated to the [`FileRequest`]s sent by the layer, and
+//! forwarded to the agent with [`RemoteFs`]. The mount is read-only, unless `--read-write` is
+//! given.
+
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, KernelConfig, MountOption, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request, Session, TimeOrNow,
+    FUSE_ROOT_ID,
+};
+use mirrord_config::{LayerConfig, MIRRORD_CONFIG_FILE_ENV};
+use mirrord_progress::{Progress, ProgressTracker};
+use mirrord_protocol::{
+    file::{
+        ChmodRequest, ChownRequest, CloseDirRequest, CloseFileRequest, DirEntryInternal,
+        FdOpenDirRequest, FileTimeUpdate, FtruncateRequest, MakeDirRequest, MetadataInternal,
+        OpenFileRequest, OpenOptionsInternal, ReadDirBatchRequest, ReadDirRequest,
+        ReadLimitedFileRequest, ReadLinkFileRequest, RemoveDirRequest, RenameRequest,
+        SymlinkRequest, TruncateRequest, UnlinkRequest, UtimesRequest, WriteLimitedFileRequest,
+        XstatRequest, FS_MUTATIONS_VERSION, READDIR_BATCH_VERSION,
+    },
+    FileRequest, FileResponse, ResponseError,
+};
+use nix::libc::{
+    c_int, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_SOCK, EACCES, EBADF, EINVAL, EIO, EISDIR,
+    ENOENT, ENOSYS, ENOTDIR, EROFS, O_ACCMODE, O_APPEND, O_CREAT, O_EXCL, O_RDONLY, O_TRUNC,
+    O_WRONLY,
+};
+use tokio::{
+    signal,
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    config::MountArgs,
+    error::{CliError, Result},
+    fs::RemoteFs,
+};
+
+/// For how long the kernel may cache the attributes and directory entries we return.
+const ATTR_TTL: Duration = Duration::from_secs(1);
+
+/// How often we ping the agent while the mount is idle, so that the connection stays open.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How many entries we request from the agent in a single [`ReadDirBatchRequest`].
+const READ_DIR_BATCH_SIZE: usize = 128;
+
+/// Bit mask for the file type in [`MetadataInternal::mode`].
+const S_IFMT: u32 = 0o170000;
+
+/// A [`FileRequest`] from the FUSE thread, with the channel for the response if the agent sends
+/// one.
+type AgentRequest = (FileRequest, Option<oneshot::Sender<FileResponse>>);
+
+/// Takes the result out of the expected [`FileResponse`] variant, mapping the errors to `errno`.
+macro_rules! response {
+    ($response:expr, $variant:ident) => {
+        match $response {
+            FileResponse::$variant(result) => result.map_err(errno),
+            other => {
+                tracing::warn!(?other, "Agent sent an unexpected file response");
+                Err(EIO)
+            }
+        }
+    };
+}
+
+/// Maps the agent's error to the `errno` returned to the kernel.
+fn errno(error: ResponseError) -> c_int {
+    match error {
+        ResponseError::RemoteIO(io_fail) => io_fail.raw_os_error.unwrap_or(EIO),
+        ResponseError::NotFound(_) => ENOENT,
+        ResponseError::NotDirectory(_) => ENOTDIR,
+        ResponseError::NotFile(_) => EISDIR,
+        ResponseError::NotImplemented => ENOSYS,
+        ResponseError::Forbidden { .. } => EACCES,
+        _ => EIO,
+    }
+}
+
+fn system_time(nanos: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(nanos.try_into().unwrap_or_default())
+}
+
+fn file_time_update(time: Option<TimeOrNow>) -> FileTimeUpdate {
+    match time {
+        None => FileTimeUpdate::Omit,
+        Some(TimeOrNow::Now) => FileTimeUpdate::Now,
+        Some(TimeOrNow::SpecificTime(time)) => {
+            let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            FileTimeUpdate::Set {
+                seconds: since_epoch.as_secs().try_into().unwrap_or(i64::MAX),
+                nanoseconds: since_epoch.subsec_nanos(),
+            }
+        }
+    }
+}
+
+fn file_type(mode: u32) -> FileType {
+    match mode & S_IFMT {
+        0o040000 => FileType::Directory,
+        0o120000 => FileType::Symlink,
+        0o010000 => FileType::NamedPipe,
+        0o140000 => FileType::Socket,
+        0o020000 => FileType::CharDevice,
+        0o060000 => FileType::BlockDevice,
+        _ => FileType::RegularFile,
+    }
+}
+
+/// Maps the `d_type` of [`DirEntryInternal::file_type`].
+fn dir_entry_type(d_type: u8) -> FileType {
+    match d_type {
+        DT_DIR => FileType::Directory,
+        DT_LNK => FileType::Symlink,
+        DT_FIFO => FileType::NamedPipe,
+        DT_SOCK => FileType::Socket,
+        DT_CHR => FileType::CharDevice,
+        DT_BLK => FileType::BlockDevice,
+        _ => FileType::RegularFile,
+    }
+}
+
+fn open_options(flags: i32) -> OpenOptionsInternal {
+    let access = flags & O_ACCMODE;
+
+    OpenOptionsInternal {
+        read: access != O_WRONLY,
+        write: access != O_RDONLY,
+        append: flags & O_APPEND != 0,
+        truncate: flags & O_TRUNC != 0,
+        create: flags & O_CREAT != 0,
+        create_new: flags & O_CREAT != 0 && flags & O_EXCL != 0,
+    }
+}
+
+/// Sends [`AgentRequest`]s from the FUSE thread to the task that owns the [`RemoteFs`].
+struct Agent {
+    requests: mpsc::Sender<AgentRequest>,
+}
+
+impl Agent {
+    /// Blocks the FUSE thread until the agent responds.
+    fn request(&self, request: FileRequest) -> Result<FileResponse, c_int> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.requests
+            .blocking_send((request, Some(response_tx)))
+            .map_err(|_| EIO)?;
+
+        response_rx.blocking_recv().map_err(|_| EIO)
+    }
+
+    /// Sends a request that the agent does not answer.
+    fn send(&self, request: FileRequest) {
+        let _ = self.requests.blocking_send((request, None));
+    }
+}
+
+/// The remote file system, as seen by the kernel.
+///
+/// Remote paths get inode numbers on the first lookup, and keep them for the lifetime of the
+/// mount. The remote inodes are not used, as they're only unique within a single remote device.
+struct MountedFs {
+    agent: Agent,
+    read_write: bool,
+    /// Whether the agent supports [`ReadDirBatchRequest`].
+    batched_read_dir: bool,
+    paths: HashMap<u64, PathBuf>,
+    inodes: HashMap<PathBuf, u64>,
+    next_inode: u64,
+    /// Listings of the open directories, by their handles.
+    listings: HashMap<u64, Vec<(u64, FileType, OsString)>>,
+    next_listing: u64,
+}
+
+impl MountedFs {
+    fn new(agent: Agent, read_write: bool, batched_read_dir: bool) -> Self {
+        Self {
+            agent,
+            read_write,
+            batched_read_dir,
+            paths: HashMap::from([(FUSE_ROOT_ID, PathBuf::from("/"))]),
+            inodes: HashMap::from([(PathBuf::from("/"), FUSE_ROOT_ID)]),
+            next_inode: FUSE_ROOT_ID + 1,
+            listings: Default::default(),
+            next_listing: 0,
+        }
+    }
+
+    fn path(&self, ino: u64) -> Result<PathBuf, c_int> {
+        self.paths.get(&ino).cloned().ok_or(ENOENT)
+    }
+
+    fn child_path(&self, parent: u64, name: &OsStr) -> Result<PathBuf, c_int> {
+        Ok(self.path(parent)?.join(name))
+    }
+
+    /// Returns the inode number of the remote path, allocates one if needed.
+    fn inode(&mut self, path: &Path) -> u64 {
+        if let Some(ino) = self.inodes.get(path) {
+            return *ino;
+        }
+
+        let ino = self.next_inode;
+        self.next_inode += 1;
+        self.paths.insert(ino, path.to_path_buf());
+        self.inodes.insert(path.to_path_buf(), ino);
+
+        ino
+    }
+
+    fn writable(&self) -> Result<(), c_int> {
+        if self.read_write {
+            Ok(())
+        } else {
+            Err(EROFS)
+        }
+    }
+
+    /// Returns the attributes of the remote path, without following symlinks.
+    fn attr(&mut self, path: &Path) -> Result<FileAttr, c_int> {
+        let response = self.agent.request(FileRequest::Xstat(XstatRequest {
+            path: Some(path.to_path_buf()),
+            fd: None,
+            follow_symlink: false,
+        }))?;
+        let metadata = response!(response, Xstat)?.metadata;
+
+        Ok(self.file_attr(path, &metadata))
+    }
+
+    fn file_attr(&mut self, path: &Path, metadata: &MetadataInternal) -> FileAttr {
+        FileAttr {
+            ino: self.inode(path),
+            size: metadata.size,
+            blocks: metadata.blocks,
+            atime: system_time(metadata.access_time),
+            mtime: system_time(metadata.modification_time),
+            ctime: system_time(metadata.creation_time),
+            crtime: system_time(metadata.creation_time),
+            kind: file_type(metadata.mode),
+            perm: (metadata.mode & 0o7777) as u16,
+            nlink: metadata.hard_links.try_into().unwrap_or(u32::MAX),
+            uid: metadata.user_id,
+            gid: metadata.group_id,
+            rdev: metadata.rdevice_id as u32,
+            blksize: metadata.block_size.try_into().unwrap_or(u32::MAX),
+            flags: 0,
+        }
+    }
+
+    fn open_remote(&self, path: &Path, open_options: OpenOptionsInternal) -> Result<u64, c_int> {
+        let response = self.agent.request(FileRequest::Open(OpenFileRequest {
+            path: path.to_path_buf(),
+            open_options,
+        }))?;
+
+        Ok(response!(response, Open)?.fd)
+    }
+
+    /// Returns all entries of the remote directory.
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntryInternal>, c_int> {
+        let fd = self.open_remote(
+            path,
+            OpenOptionsInternal {
+                read: true,
+                ..Default::default()
+            },
+        )?;
+        let entries = self.read_dir_fd(fd);
+        self.agent.send(FileRequest::Close(CloseFileRequest { fd }));
+
+        entries
+    }
+
+    fn read_dir_fd(&self, fd: u64) -> Result<Vec<DirEntryInternal>, c_int> {
+        let response = self
+            .agent
+            .request(FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd: fd }))?;
+        let dir_fd = response!(response, OpenDir)?.fd;
+
+        let mut entries = Vec::new();
+        let result = loop {
+            let batch = if self.batched_read_dir {
+                self.agent
+                    .request(FileRequest::ReadDirBatch(ReadDirBatchRequest {
+                        remote_fd: dir_fd,
+                        amount: READ_DIR_BATCH_SIZE,
+                    }))
+                    .and_then(|response| response!(response, ReadDirBatch))
+                    .map(|response| response.dir_entries)
+            } else {
+                self.agent
+                    .request(FileRequest::ReadDir(ReadDirRequest { remote_fd: dir_fd }))
+                    .and_then(|response| response!(response, ReadDir))
+                    .map(|response| response.direntry.into_iter().collect())
+            };
+
+            match batch {
+                Ok(batch) if batch.is_empty() => break Ok(entries),
+                Ok(batch) => entries.extend(batch),
+                Err(error) => break Err(error),
+            }
+        };
+
+        self.agent
+            .send(FileRequest::CloseDir(CloseDirRequest { remote_fd: dir_fd }));
+
+        result
+    }
+
+    /// Updates the paths of the renamed file, and of everything below it.
+    fn renamed(&mut self, old_path: &Path, new_path: &Path) {
+        if let Some(replaced) = self.inodes.remove(new_path) {
+            self.paths.remove(&replaced);
+        }
+
+        let moved = self
+            .paths
+            .iter()
+            .filter_map(|(ino, path)| {
+                let relative = path.strip_prefix(old_path).ok()?;
+                let path = if relative.as_os_str().is_empty() {
+                    new_path.to_path_buf()
+                } else {
+                    new_path.join(relative)
+                };
+
+                Some((*ino, path))
+            })
+            .collect::<Vec<_>>();
+
+        for (ino, path) in moved {
+            if let Some(previous) = self.paths.insert(ino, path.clone()) {
+                self.inodes.remove(&previous);
+            }
+            self.inodes.insert(path, ino);
+        }
+    }
+
+    fn lookup_path(&mut self, parent: u64, name: &OsStr) -> Result<FileAttr, c_int> {
+        let path = self.child_path(parent, name)?;
+        self.attr(&path)
+    }
+
+    fn open_dir(&mut self, ino: u64) -> Result<u64, c_int> {
+        let path = self.path(ino)?;
+        let parent = path
+            .parent()
+            .map(|parent| self.inode(parent))
+            .unwrap_or(ino);
+
+        let mut listing = vec![
+            (ino, FileType::Directory, OsString::from(".")),
+            (parent, FileType::Directory, OsString::from("..")),
+        ];
+        for entry in self.read_dir(&path)? {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+
+            let entry_ino = self.inode(&path.join(&entry.name));
+            listing.push((
+                entry_ino,
+                dir_entry_type(entry.file_type),
+                entry.name.into(),
+            ));
+        }
+
+        let handle = self.next_listing;
+        self.next_listing += 1;
+        self.listings.insert(handle, listing);
+
+        Ok(handle)
+    }
+
+    fn read_remote(&self, fh: u64, offset: i64, size: u32) -> Result<Vec<u8>, c_int> {
+        let response = self
+            .agent
+            .request(FileRequest::ReadLimited(ReadLimitedFileRequest {
+                remote_fd: fh,
+                buffer_size: size.into(),
+                start_from: offset.try_into().map_err(|_| EINVAL)?,
+            }))?;
+
+        Ok(response!(response, ReadLimited)?.bytes)
+    }
+
+    fn write_remote(&self, fh: u64, offset: i64, data: &[u8]) -> Result<u32, c_int> {
+        self.writable()?;
+
+        let response = self
+            .agent
+            .request(FileRequest::WriteLimited(WriteLimitedFileRequest {
+                remote_fd: fh,
+                start_from: offset.try_into().map_err(|_| EINVAL)?,
+                write_bytes: data.to_vec(),
+            }))?;
+        let written = response!(response, WriteLimited)?.written_amount;
+
+        Ok(written.try_into().unwrap_or(u32::MAX))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn set_attr(
+        &mut self,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        fh: Option<u64>,
+    ) -> Result<FileAttr, c_int> {
+        let path = self.path(ino)?;
+
+        if mode.is_some() || uid.is_some() || gid.is_some() || size.is_some() {
+            self.writable()?;
+        }
+
+        if let Some(length) = size {
+            let request = match fh {
+                Some(fd) => FileRequest::Ftruncate(FtruncateRequest { fd, length }),
+                None => FileRequest::Truncate(TruncateRequest {
+                    path: path.clone(),
+                    length,
+                }),
+            };
+            response!(self.agent.request(request)?, Truncate)?;
+        }
+
+        if let Some(mode) = mode {
+            let request = FileRequest::Chmod(ChmodRequest {
+                path: path.clone(),
+                mode: mode & 0o7777,
+            });
+            response!(self.agent.request(request)?, Chmod)?;
+        }
+
+        if uid.is_some() || gid.is_some() {
+            let request = FileRequest::Chown(ChownRequest {
+                path: path.clone(),
+                user_id: uid,
+                group_id: gid,
+                follow_symlink: false,
+            });
+            response!(self.agent.request(request)?, Chown)?;
+        }
+
+        if atime.is_some() || mtime.is_some() {
+            self.writable()?;
+
+            let request = FileRequest::Utimes(UtimesRequest {
+                path: path.clone(),
+                access_time: file_time_update(atime),
+                modification_time: file_time_update(mtime),
+                follow_symlink: true,
+            });
+            response!(self.agent.request(request)?, Utimes)?;
+        }
+
+        self.attr(&path)
+    }
+
+    fn make_dir(
+        &mut self,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+    ) -> Result<FileAttr, c_int> {
+        self.writable()?;
+
+        let path = self.child_path(parent, name)?;
+        let request = FileRequest::MakeDir(MakeDirRequest {
+            path: path.clone(),
+            mode: mode & !umask & 0o7777,
+        });
+        response!(self.agent.request(request)?, MakeDir)?;
+
+        self.attr(&path)
+    }
+
+    fn remove(&mut self, parent: u64, name: &OsStr, dir: bool) -> Result<(), c_int> {
+        self.writable()?;
+
+        let path = self.child_path(parent, name)?;
+        if dir {
+            let request = FileRequest::RemoveDir(RemoveDirRequest { path: path.clone() });
+            response!(self.agent.request(request)?, RemoveDir)?;
+        } else {
+            let request = FileRequest::Unlink(UnlinkRequest { path: path.clone() });
+            response!(self.agent.request(request)?, Unlink)?;
+        }
+
+        if let Some(ino) = self.inodes.remove(&path) {
+            self.paths.remove(&ino);
+        }
+
+        Ok(())
+    }
+
+    fn make_symlink(
+        &mut self,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+    ) -> Result<FileAttr, c_int> {
+        self.writable()?;
+
+        let link_path = self.child_path(parent, link_name)?;
+        let request = FileRequest::Symlink(SymlinkRequest {
+            target: target.to_path_buf(),
+            link_path: link_path.clone(),
+        });
+        response!(self.agent.request(request)?, Symlink)?;
+
+        self.attr(&link_path)
+    }
+
+    fn rename_path(
+        &mut self,
+        parent: u64,
+        name: &OsStr,
+        new_parent: u64,
+        new_name: &OsStr,
+    ) -> Result<(), c_int> {
+        self.writable()?;
+
+        let old_path = self.child_path(parent, name)?;
+        let new_path = self.child_path(new_parent, new_name)?;
+        let request = FileRequest::Rename(RenameRequest {
+            old_path: old_path.clone(),
+            new_path: new_path.clone(),
+        });
+        response!(self.agent.request(request)?, Rename)?;
+
+        self.renamed(&old_path, &new_path);
+        Ok(())
+    }
+
+    fn create_file(
+        &mut self,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+    ) -> Result<(FileAttr, u64), c_int> {
+        self.writable()?;
+
+        let path = self.child_path(parent, name)?;
+        let fd = self.open_remote(
+            &path,
+            OpenOptionsInternal {
+                create: true,
+                ..open_options(flags)
+            },
+        )?;
+
+        // The agent creates the file with the default permissions.
+        let request = FileRequest::Chmod(ChmodRequest {
+            path: path.clone(),
+            mode: mode & !umask & 0o7777,
+        });
+        if let Err(error) = response!(self.agent.request(request)?, Chmod) {
+            tracing::debug!(error, ?path, "Failed to set the mode of a created file");
+        }
+
+        Ok((self.attr(&path)?, fd))
+    }
+}
+
+impl Filesystem for MountedFs {
+    fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
+        Ok(())
+    }
+
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self.lookup_path(parent, name) {
+            Ok(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.path(ino).and_then(|path| self.attr(&path)) {
+            Ok(attr) => reply.attr(&ATTR_TTL, &attr),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        match self.set_attr(ino, mode, uid, gid, size, atime, mtime, fh) {
+            Ok(attr) => reply.attr(&ATTR_TTL, &attr),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        let result = self.path(ino).and_then(|path| {
+            let response = self
+                .agent
+                .request(FileRequest::ReadLink(ReadLinkFileRequest { path }))?;
+            response!(response, ReadLink)
+        });
+
+        match result {
+            Ok(link) => reply.data(link.path.as_os_str().as_encoded_bytes()),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        match self.make_dir(parent, name, mode, umask) {
+            Ok(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match self.remove(parent, name, false) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match self.remove(parent, name, true) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+        reply: ReplyEntry,
+    ) {
+        match self.make_symlink(parent, link_name, target) {
+            Ok(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        match self.rename_path(parent, name, newparent, newname) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        let open_options = open_options(flags);
+        let result = if open_options.is_read_only() {
+            Ok(())
+        } else {
+            self.writable()
+        }
+        .and_then(|()| self.path(ino))
+        .and_then(|path| self.open_remote(&path, open_options));
+
+        match result {
+            Ok(fd) => reply.opened(fd, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.read_remote(fh, offset, size) {
+            Ok(bytes) => reply.data(&bytes),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        match self.write_remote(fh, offset, data) {
+            Ok(written) => reply.written(written),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.agent
+            .send(FileRequest::Close(CloseFileRequest { fd: fh }));
+        reply.ok();
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.open_dir(ino) {
+            Ok(handle) => reply.opened(handle, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(listing) = self.listings.get(&fh) else {
+            reply.error(EBADF);
+            return;
+        };
+
+        let skip = usize::try_from(offset).unwrap_or_default();
+        for (index, (ino, kind, name)) in listing.iter().enumerate().skip(skip) {
+            // The offset is the one of the next entry.
+            if reply.add(*ino, (index + 1) as i64, *kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: ReplyEmpty,
+    ) {
+        self.listings.remove(&fh);
+        reply.ok();
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        match self.create_file(parent, name, mode, umask, flags) {
+            Ok((attr, fd)) => reply.created(&ATTR_TTL, &attr, 0, fd, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+}
+
+/// Forwards the requests of the FUSE thread to the agent, until the mount is gone.
+async fn serve(client: &mut RemoteFs, mut requests: mpsc::Receiver<AgentRequest>) -> Result<()> {
+    let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            request = requests.recv() => match request {
+                Some((request, Some(response_tx))) => {
+                    let response = client.request(request).await?;
+                    let _ = response_tx.send(response);
+                }
+                Some((request, None)) => client.send(request).await?,
+                None => break Ok(()),
+            },
+
+            _ = keep_alive.tick() => client.ping().await?,
+        }
+    }
+}
+
+/// Handle the `mirrord mount ...` command.
+///
+/// Runs until interrupted, or until the file system is unmounted with `umount`.
+pub(crate) async fn mount_command(args: MountArgs) -> Result<()> {
+    let mut progress = ProgressTracker::from_env("mirrord mount");
+
+    if let Some(config_file) = &args.config_file {
+        std::env::set_var(MIRRORD_CONFIG_FILE_ENV, config_file);
+    }
+    std::env::set_var("MIRRORD_IMPERSONATED_TARGET", &args.target);
+    if let Some(namespace) = &args.target_namespace {
+        std::env::set_var("MIRRORD_TARGET_NAMESPACE", namespace);
+    }
+
+    let config = LayerConfig::from_env()?;
+    let mut client = RemoteFs::connect(&config, &mut progress).await?;
+
+    if args.read_write && !FS_MUTATIONS_VERSION.matches(client.version()) {
+        return Err(CliError::MountReadWriteNotSupported(
+            client.version().clone(),
+        ));
+    }
+
+    let (requests_tx, requests_rx) = mpsc::channel(64);
+    let filesystem = MountedFs::new(
+        Agent {
+            requests: requests_tx,
+        },
+        args.read_write,
+        READDIR_BATCH_VERSION.matches(client.version()),
+    );
+
+    let options = [
+        MountOption::FSName("mirrord".to_string()),
+        if args.read_write {
+            MountOption::RW
+        } else {
+            MountOption::RO
+        },
+    ];
+    let mut session = Session::new(filesystem, &args.mountpoint, &options)
+        .map_err(|error| CliError::MountFailed(args.mountpoint.clone(), error))?;
+    let mut unmounter = session.unmount_callable();
+    let mut session_task = tokio::task::spawn_blocking(move || session.run());
+
+    progress.success(Some(&format!(
+        "mounted {} at {}, press Ctrl+C to unmount",
+        args.target,
+        args.mountpoint.display()
+    )));
+
+    let result = tokio::select! {
+        result = serve(&mut client, requests_rx) => result,
+        _ = signal::ctrl_c() => Ok(()),
+        // Unmounted from the outside, or the session failed.
+        session = &mut session_task => {
+            return session
+                .unwrap_or_else(|error| Err(io::Error::other(error)))
+                .map_err(|error| CliError::MountFailed(args.mountpoint.clone(), error));
+        }
+    };
+
+    // Pending requests of the FUSE thread fail now that `serve` is gone, so the unmount can't get
+    // stuck on them.
+    unmounter
+        .unmount()
+        .map_err(|error| CliError::MountFailed(args.mountpoint.clone(), error))?;
+    let _ = session_task.await;
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_moves_descendants() {
+        let (requests, _) = mpsc::channel(1);
+        let mut fs = MountedFs::new(Agent { requests }, true, true);
+
+        let dir = fs.inode(Path::new("/app/config"));
+        let file = fs.inode(Path::new("/app/config/settings.yaml"));
+        let sibling = fs.inode(Path::new("/app/configs"));
+        let replaced = fs.inode(Path::new("/app/old"));
+
+        fs.renamed(Path::new("/app/config"), Path::new("/app/old"));
+
+        assert_eq!(fs.path(dir), Ok(PathBuf::from("/app/old")));
+        assert_eq!(fs.path(file), Ok(PathBuf::from("/app/old/settings.yaml")));
+        assert_eq!(fs.path(sibling), Ok(PathBuf::from("/app/configs")));
+        assert_eq!(fs.path(replaced), Err(ENOENT));
+        assert_eq!(fs.inode(Path::new("/app/old/settings.yaml")), file);
+    }
+}
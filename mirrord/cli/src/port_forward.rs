@@ -908,7 +908,7 @@ impl IncomingMode {
                 all_of: Some(filters),
                 any_of: None,
                 ports: _ports,
-            } => StealHttpFilter::Filter(Self::make_composite_filter(true, filters)),
+            } => StealHttpFilter::Filter(InnerFilter::composite(true, filters)),
 
             HttpFilterConfig {
                 path_filter: None,
@@ -916,7 +916,7 @@ impl IncomingMode {
                 all_of: None,
                 any_of: Some(filters),
                 ports: _ports,
-            } => StealHttpFilter::Filter(Self::make_composite_filter(false, filters)),
+            } => StealHttpFilter::Filter(InnerFilter::composite(false, filters)),
 
             HttpFilterConfig {
                 path_filter: None,
@@ -932,22 +932,6 @@ impl IncomingMode {
         Self::Steal(StealHttpSettings { filter, ports })
     }
 
-    fn make_composite_filter(all: bool, filters: &[InnerFilter]) -> HttpFilter {
-        let filters = filters
-            .iter()
-            .map(|filter| match filter {
-                InnerFilter::Path { path } => {
-                    HttpFilter::Path(Filter::new(path.clone()).expect("invalid filter expression"))
-                }
-                InnerFilter::Header { header } => HttpFilter::Header(
-                    Filter::new(header.clone()).expect("invalid filter expression"),
-                ),
-            })
-            .collect();
-
-        HttpFilter::Composite { all, filters }
-    }
-
     /// Returns [`PortSubscription`] request to be used for the given port.
     fn subscription(&self, port: Port) -> PortSubscription {
         let Self::Steal(steal) = self else {
@@ -0,0 +1,102 @@
+//! `mirrord replay {path}` re-sends HTTP requests recorded with `mirrord exec --record` to a local
+//! process, without connecting to the cluster.
+//!
+//! Each response status is compared with the recorded one, and the command fails if any of them
+//! differ.
+
+use mirrord_intproxy::proxies::incoming::record::{read_recording, HarEntry};
+use mirrord_progress::{Progress, ProgressTracker};
+use reqwest::{
+    header::{HeaderName, HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING},
+    redirect::Policy,
+    Client, Method, Url,
+};
+
+use crate::{
+    config::ReplayArgs,
+    error::{CliError, Result},
+};
+
+pub(crate) async fn replay_command(args: ReplayArgs) -> Result<()> {
+    let mut progress = ProgressTracker::from_env("mirrord replay");
+
+    let entries =
+        read_recording(&args.path).map_err(|e| CliError::ReplayReadFailed(args.path.clone(), e))?;
+
+    let client = Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .map_err(CliError::ReplayClientFailed)?;
+
+    let mut mismatched = 0;
+    for entry in &entries {
+        let recorded = entry.response.status;
+        match replay_entry(&client, entry, &args).await {
+            Ok(status) if status == recorded => {
+                progress.info(&format!(
+                    "{} {} -> {status}",
+                    entry.request.method, entry.request.url
+                ));
+            }
+            Ok(status) => {
+                mismatched += 1;
+                progress.warning(&format!(
+                    "{} {} -> {status}, recorded {recorded}",
+                    entry.request.method, entry.request.url
+                ));
+            }
+            Err(error) => {
+                mismatched += 1;
+                progress.warning(&format!(
+                    "{} {} failed: {error}",
+                    entry.request.method, entry.request.url
+                ));
+            }
+        }
+    }
+
+    if mismatched > 0 {
+        progress.failure(Some("responses differ from the recording"));
+        return Err(CliError::ReplayMismatch(mismatched, entries.len()));
+    }
+
+    progress.success(Some(&format!("replayed {} requests", entries.len())));
+
+    Ok(())
+}
+
+/// Sends the recorded request and returns the status of the response.
+async fn replay_entry(
+    client: &Client,
+    entry: &HarEntry,
+    args: &ReplayArgs,
+) -> Result<u16, Box<dyn std::error::Error>> {
+    let mut url = Url::parse(&entry.request.url)?;
+    if let Some(to) = args.to {
+        url.set_ip_host(to.ip())
+            .map_err(|()| format!("cannot set host of {url}"))?;
+        url.set_port(Some(to.port()))
+            .map_err(|()| format!("cannot set port of {url}"))?;
+    }
+
+    let method = Method::from_bytes(entry.request.method.as_bytes())?;
+    let mut request = client.request(method, url);
+
+    for header in &entry.request.headers {
+        let name = HeaderName::from_bytes(header.name.as_bytes())?;
+        // Set by the client, based on the body.
+        if name == CONTENT_LENGTH || name == TRANSFER_ENCODING {
+            continue;
+        }
+
+        request = request.header(name, HeaderValue::from_str(&header.value)?);
+    }
+
+    if let Some(post_data) = &entry.request.post_data {
+        request = request.body(post_data.bytes()?);
+    }
+
+    let response = request.send().await?;
+
+    Ok(response.status().as_u16())
+}
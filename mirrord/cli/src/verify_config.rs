@@ -5,7 +5,7 @@
 use error::Result;
 use mirrord_config::{
     config::{ConfigContext, MirrordConfig},
-    feature::FeatureConfig,
+    feature::{fs::PathMapping, FeatureConfig},
     target::{
         cron_job::CronJobTarget, deployment::DeploymentTarget, job::JobTarget, pod::PodTarget,
         rollout::RolloutTarget, stateful_set::StatefulSetTarget, Target, TargetConfig,
@@ -158,6 +158,9 @@ enum VerifiedConfig {
         /// Target types compatible with the source config.
         /// Meant to be used by IDE plugins for customizing target selection.
         compatible_target_types: Vec<TargetType>,
+        /// The `feature.fs.mapping` rules, with the `local:` and `remote:` sides of their targets
+        /// resolved.
+        fs_mappings: Vec<PathMapping>,
     },
     /// Invalid config was detected, mirrord cannot run.
     ///
@@ -188,7 +191,14 @@ enum VerifiedConfig {
 ///     "namespace": null
 ///   },
 ///   "warnings": [],
-///   "compatible_target_types": ["targetless", "deployment", "rollout", "pod"]
+///   "compatible_target_types": ["targetless", "deployment", "rollout", "pod"],
+///   "fs_mappings": [
+///     {
+///       "pattern": "^/etc/app/secret\\.json$",
+///       "side": "local",
+///       "replacement": "./dev/secret.json"
+///     }
+///   ]
 /// }
 /// ```
 ///
@@ -218,6 +228,7 @@ pub(super) async fn verify_config(VerifyConfigArgs { ide, path }: VerifyConfigAr
             compatible_target_types: TargetType::all()
                 .filter(|tt| tt.compatible_with(&config.feature))
                 .collect(),
+            fs_mappings: config.feature.fs.mappings(),
         },
         Err(fail) => VerifiedConfig::Fail {
             errors: vec![fail.to_string()],
@@ -10,7 +10,7 @@
 use schemars::JsonSchema;
 use serde::Deserialize;
 
-pub use self::{advanced::*, mode::*};
+pub use self::{advanced::*, cache::*, mapping::*, mode::*};
 use crate::{
     config::{
         from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError, MirrordConfig,
@@ -19,6 +19,8 @@ use crate::{
 };
 
 pub mod advanced;
+pub mod cache;
+pub mod mapping;
 pub mod mode;
 
 /// ## feature.fs {#fs}
@@ -64,7 +66,7 @@ pub enum FsUserConfig {
     /// <!--${internal}-->
     /// Allows the user to specify both [`FsModeConfig`] (as above), and configuration for the
     /// overrides.
-    Advanced(AdvancedFsUserConfig),
+    Advanced(Box<AdvancedFsUserConfig>),
 }
 
 impl Default for FsUserConfig {
@@ -91,6 +93,10 @@ impl MirrordConfig for FsUserConfig {
                     .transpose()?,
                 not_found: None,
                 mapping: None,
+                cache: FsCacheFileConfig::default().generate_config(context)?,
+                overlay: FromEnv::new("MIRRORD_FILE_OVERLAY")
+                    .source_value(context)
+                    .transpose()?,
             },
             FsUserConfig::Advanced(advanced) => advanced.generate_config(context)?,
         };
@@ -111,6 +117,9 @@ impl MirrordToggleableConfig for FsUserConfig {
         let local = FromEnv::new("MIRRORD_FILE_LOCAL_PATTERN")
             .source_value(context)
             .transpose()?;
+        let overlay = FromEnv::new("MIRRORD_FILE_OVERLAY")
+            .source_value(context)
+            .transpose()?;
 
         Ok(FsConfig {
             mode,
@@ -119,6 +128,8 @@ impl MirrordToggleableConfig for FsUserConfig {
             local,
             not_found: None,
             mapping: None,
+            cache: FsCacheFileConfig::default().generate_config(context)?,
+            overlay,
         })
     }
 }
@@ -1,13 +1,15 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use mirrord_analytics::{AnalyticValue, CollectAnalytics};
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::Serialize;
 
-use super::{FsModeConfig, FsUserConfig};
+use super::{FsCacheConfig, FsCacheFileConfig, FsModeConfig, FsUserConfig, PathMapping};
 use crate::{
-    config::{from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError},
+    config::{
+        from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError, MirrordConfig,
+    },
     util::{MirrordToggleableConfig, VecOrSingle},
 };
 
@@ -22,6 +24,7 @@ use crate::{
 /// 3. `"local"` - Read from the local file system.
 /// 4. `"localwithoverrides"` - perform fs operation locally, unless the path matches a pre-defined
 ///    or user-specified exception.
+/// 5. `"cow"` - Read from the remote file system, write to local copies of the remote files.
 ///
 /// > Note: by default, some paths are read locally or remotely, regardless of the selected FS mode.
 /// > This is described in further detail below.
@@ -40,7 +43,10 @@ use crate::{
 ///
 ///
 /// 1. Check agains "mapping" if path needs to be replaced, if matched then continue to next step
-///    with new path after replacements otherwise continue as usual.
+///    with new path after replacements otherwise continue as usual. If several mappings match, the
+///    one matching the longest part of the path is used. If the target of the matched mapping
+///    starts with `local:` or `remote:`, the new path is handled on that side, and the next steps
+///    are skipped.
 /// 2. Check if one of the patterns match the file path, do the corresponding action. There's no
 ///    specified order if two lists match the same path, we will use the first one (and we do not
 ///    guarantee what is first).
@@ -131,7 +137,45 @@ pub struct FsConfig {
     ///
     /// `/home/johndoe/dev/tomcat/context.xml` => `/etc/tomcat/context.xml`
     /// `/home/johndoe/dev/config/api/app.conf` => `/mnt/configs/johndoe-api/app.conf`
+    ///
+    /// The mapped path is handled according to the other settings, unless the target starts with
+    /// `local:` or `remote:`. Then the mapped path is always accessed in the local or the remote
+    /// file system, so one rule can move a single path to the other machine:
+    /// ```json
+    /// {
+    ///   "^/etc/app/secret\\.json$": "local:./dev/secret.json",
+    ///   "^/home/(?<user>\\S+)/dev/data": "remote:/var/lib/app/data"
+    /// }
+    /// ```
+    /// Here `/etc/app/secret.json` is read from `./dev/secret.json` on the local machine, while
+    /// the rest of `/etc/app` follows the fs mode.
+    ///
+    /// When more than one pattern matches a path, only the most specific one is applied: the one
+    /// that matches the longest part of the path. If they match equally long parts, the pattern
+    /// that comes first alphabetically wins. So in
+    /// ```json
+    /// {
+    ///   "^/etc/app": "remote:/srv/app",
+    ///   "^/etc/app/secret\\.json$": "local:./dev/secret.json"
+    /// }
+    /// ```
+    /// `/etc/app/secret.json` is still read locally, whatever the order of the rules.
     pub mapping: Option<HashMap<String, String>>,
+
+    /// ### feature.fs.cache {#feature-fs-cache}
+    #[config(nested)]
+    pub cache: FsCacheConfig,
+
+    /// ### feature.fs.overlay {#feature-fs-overlay}
+    ///
+    /// Local directory where the [`"cow"`](#feature-fs-mode-cow) mode keeps the copies of the
+    /// remote files, under their remote paths, e.g. `/app/config.yaml` is copied to
+    /// `<overlay>/app/config.yaml`.
+    ///
+    /// Defaults to a `mirrord-overlay-<port>` directory in the system temporary directory, where
+    /// `<port>` is the port of the internal proxy, so all processes of a session share it.
+    #[config(env = "MIRRORD_FILE_OVERLAY")]
+    pub overlay: Option<PathBuf>,
 }
 
 impl MirrordToggleableConfig for AdvancedFsUserConfig {
@@ -146,6 +190,9 @@ impl MirrordToggleableConfig for AdvancedFsUserConfig {
         let local = FromEnv::new("MIRRORD_FILE_LOCAL_PATTERN")
             .source_value(context)
             .transpose()?;
+        let overlay = FromEnv::new("MIRRORD_FILE_OVERLAY")
+            .source_value(context)
+            .transpose()?;
 
         Ok(Self::Generated {
             mode,
@@ -154,6 +201,8 @@ impl MirrordToggleableConfig for AdvancedFsUserConfig {
             local,
             not_found: None,
             mapping: None,
+            cache: FsCacheFileConfig::default().generate_config(context)?,
+            overlay,
         })
     }
 }
@@ -167,6 +216,24 @@ impl FsConfig {
         self.mode.is_write()
     }
 
+    pub fn is_cow(&self) -> bool {
+        self.mode.is_cow()
+    }
+
+    /// Returns the [`mapping`](Self::mapping) rules, with the sides of their targets, ordered by
+    /// their patterns, which is the order used to break ties between equally specific rules.
+    pub fn mappings(&self) -> Vec<PathMapping> {
+        let mut mappings: Vec<_> = self
+            .mapping
+            .iter()
+            .flatten()
+            .map(|(pattern, target)| PathMapping::new(pattern.clone(), target))
+            .collect();
+        mappings.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+
+        mappings
+    }
+
     /// Checks if fs operations are active
     pub fn is_active(&self) -> bool {
         !matches!(self.mode, FsModeConfig::Local)
@@ -180,6 +247,7 @@ impl From<FsModeConfig> for AnalyticValue {
             FsModeConfig::LocalWithOverrides => Self::Number(1),
             FsModeConfig::Read => Self::Number(2),
             FsModeConfig::Write => Self::Number(3),
+            FsModeConfig::Cow => Self::Number(4),
         }
     }
 }
@@ -212,6 +280,7 @@ impl CollectAnalytics for &FsConfig {
                 .map(<[_]>::len)
                 .unwrap_or_default(),
         );
+        analytics.add("cache", &self.cache);
     }
 }
 
@@ -0,0 +1,78 @@
+use mirrord_analytics::CollectAnalytics;
+use mirrord_config_derive::MirrordConfig;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::config::source::MirrordConfigSource;
+
+/// Default value of [`FsCacheConfig::max_size`], 64 MiB.
+const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Caches the content, metadata and directory listings of remote files matched by
+/// [`read_only`](#feature-fs-read_only) in the internal proxy.
+///
+/// Saves round trips to the agent when the application reads or stats the same files over and
+/// over again, e.g. interpreters loading their libraries on startup.
+///
+/// Cached content is used for as long as the remote file keeps its modification time and size.
+/// Cached metadata is trusted for `ttl` seconds before it's refreshed from the remote.
+///
+/// Disabled by default.
+///
+/// ```json
+/// {
+///   "feature": {
+///     "fs": {
+///       "read_only": [ "^/usr/lib/python3", "^/app/config" ],
+///       "cache": {
+///         "enabled": true,
+///         "max_size": 67108864,
+///         "ttl": 10
+///       }
+///     }
+///   }
+/// }
+/// ```
+#[derive(MirrordConfig, Clone, Debug, PartialEq, Eq, Serialize)]
+#[config(map_to = "FsCacheFileConfig", derive = "PartialEq,Eq,JsonSchema")]
+pub struct FsCacheConfig {
+    /// #### feature.fs.cache.enabled {#feature-fs-cache-enabled}
+    ///
+    /// Enables the cache of remote read-only files.
+    #[config(env = "MIRRORD_FILE_CACHE", default = false)]
+    pub enabled: bool,
+
+    /// #### feature.fs.cache.max_size {#feature-fs-cache-max_size}
+    ///
+    /// Maximum total size (in bytes) of the cached file content. Files bigger than this are never
+    /// cached.
+    ///
+    /// Defaults to 64 MiB.
+    #[config(env = "MIRRORD_FILE_CACHE_MAX_SIZE", default = DEFAULT_MAX_SIZE)]
+    pub max_size: u64,
+
+    /// #### feature.fs.cache.ttl {#feature-fs-cache-ttl}
+    ///
+    /// For how many seconds cached metadata and directory listings are used before they're fetched
+    /// again from the remote.
+    ///
+    /// Defaults to 10 seconds.
+    #[config(env = "MIRRORD_FILE_CACHE_TTL", default = 10)]
+    pub ttl: u64,
+}
+
+impl Default for FsCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size: DEFAULT_MAX_SIZE,
+            ttl: 10,
+        }
+    }
+}
+
+impl CollectAnalytics for &FsCacheConfig {
+    fn collect_analytics(&self, analytics: &mut mirrord_analytics::Analytics) {
+        analytics.add("enabled", self.enabled);
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Serialize;
+
+/// Prefix of the [`mapping`](super::FsConfig::mapping) targets that are accessed locally.
+const LOCAL_PREFIX: &str = "local:";
+
+/// Prefix of the [`mapping`](super::FsConfig::mapping) targets that are accessed remotely.
+const REMOTE_PREFIX: &str = "remote:";
+
+/// Where the path produced by a [`PathMapping`] is accessed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathMappingSide {
+    /// In the local file system, regardless of the fs mode and filters.
+    Local,
+    /// In the remote file system, regardless of the fs mode and filters.
+    Remote,
+}
+
+/// A [`mapping`](super::FsConfig::mapping) rule, with its target split into the side and the
+/// replacement.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PathMapping {
+    /// Regex matched against the path.
+    pub pattern: String,
+    /// Set by the `local:` or `remote:` prefix of the target. Without a prefix, the mapped path is
+    /// handled according to the fs mode and filters, like any other path.
+    pub side: Option<PathMappingSide>,
+    /// Replacement of the matched part of the path, may refer to the capture groups.
+    pub replacement: String,
+}
+
+impl PathMapping {
+    pub fn new(pattern: String, target: &str) -> Self {
+        let (side, replacement) = if let Some(replacement) = target.strip_prefix(LOCAL_PREFIX) {
+            (Some(PathMappingSide::Local), replacement)
+        } else if let Some(replacement) = target.strip_prefix(REMOTE_PREFIX) {
+            (Some(PathMappingSide::Remote), replacement)
+        } else {
+            (None, target)
+        };
+
+        Self {
+            pattern,
+            side,
+            replacement: replacement.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("/etc/tomcat", None, "/etc/tomcat")]
+    #[case(
+        "local:./dev/secret.json",
+        Some(PathMappingSide::Local),
+        "./dev/secret.json"
+    )]
+    #[case(
+        "remote:/mnt/configs/$1",
+        Some(PathMappingSide::Remote),
+        "/mnt/configs/$1"
+    )]
+    #[case("remote:", Some(PathMappingSide::Remote), "")]
+    fn target_side(
+        #[case] target: &str,
+        #[case] side: Option<PathMappingSide>,
+        #[case] replacement: &str,
+    ) {
+        let mapping = PathMapping::new("^/etc/app".to_string(), target);
+
+        assert_eq!(mapping.side, side);
+        assert_eq!(mapping.replacement, replacement);
+    }
+}
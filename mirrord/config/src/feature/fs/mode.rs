@@ -19,7 +19,7 @@ use crate::{
 /// can be read/write remotely based on our default/user specified.
 /// Default option for general file configuration.
 ///
-/// The accepted values are: `"local"`, `"localwithoverrides`, `"read"`, or `"write`.
+/// The accepted values are: `"local"`, `"localwithoverrides`, `"read"`, `"write"`, or `"cow"`.
 #[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug, Copy, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "lowercase")]
 pub enum FsModeConfig {
@@ -42,7 +42,20 @@ pub enum FsModeConfig {
     /// #### feature.fs.mode.write {#feature-fs-mode-write}
     ///
     /// mirrord will read/write from the remote.
+    ///
+    /// Directories and file metadata are also changed remotely, e.g. `mkdir`, `rename`, `unlink`
+    /// or `chmod` (requires an up to date agent).
     Write,
+
+    /// #### feature.fs.mode.cow {#feature-fs-mode-cow}
+    ///
+    /// mirrord will read files from the remote, and write to them copy-on-write.
+    ///
+    /// The first write to a remote file copies it into a local overlay directory (see
+    /// [`feature.fs.overlay`](#feature-fs-overlay)), and the following operations on that path use
+    /// the copy. Directory listings merge the overlay with the remote directory. The remote file
+    /// system is never changed.
+    Cow,
 }
 
 impl FsModeConfig {
@@ -57,6 +70,10 @@ impl FsModeConfig {
     pub fn is_write(self) -> bool {
         self == FsModeConfig::Write
     }
+
+    pub fn is_cow(self) -> bool {
+        self == FsModeConfig::Cow
+    }
 }
 
 impl FromStr for FsModeConfig {
@@ -68,6 +85,7 @@ impl FromStr for FsModeConfig {
             "localwithoverrides" => Ok(FsModeConfig::LocalWithOverrides),
             "read" => Ok(FsModeConfig::Read),
             "write" => Ok(FsModeConfig::Write),
+            "cow" => Ok(FsModeConfig::Cow),
             _ => Err(ConfigError::InvalidFsMode(s.to_string())),
         }
     }
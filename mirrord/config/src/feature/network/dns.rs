@@ -1,16 +1,21 @@
-use std::ops::Deref;
+use std::{collections::HashMap, net::IpAddr, ops::Deref};
 
 use mirrord_analytics::CollectAnalytics;
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+pub use self::cache::*;
 use super::filter::AddressFilter;
 use crate::{
-    config::{from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError},
+    config::{
+        from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError, MirrordConfig,
+    },
     util::{MirrordToggleableConfig, VecOrSingle},
 };
 
+pub mod cache;
+
 /// List of addresses/ports/subnets that should be resolved through either the remote pod or local
 /// app, depending how you set this up with either `remote` or `local`.
 ///
@@ -62,7 +67,15 @@ use crate::{
 /// }
 /// ```
 ///
-/// Valid values follow this pattern: `[name|address|subnet/mask][:port]`.
+/// - Only queries for hostnames under `svc.cluster.local` will go through the remote pod.
+///
+/// ```json
+/// {
+///   "remote": ["*.svc.cluster.local"]
+/// }
+/// ```
+///
+/// Valid values follow this pattern: `[name|address|subnet/mask|wildcard|/regex/][:port]`.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "lowercase")]
 pub enum DnsFilterConfig {
@@ -99,10 +112,37 @@ pub struct DnsConfig {
     /// Unstable: the precise syntax of this config is subject to change.
     #[config(default, unstable)]
     pub filter: Option<DnsFilterConfig>,
+
+    /// #### feature.network.dns.cache {#feature-network-dns-cache}
+    #[config(nested)]
+    pub cache: DnsCacheConfig,
+
+    /// #### feature.network.dns.hosts {#feature-network-dns-hosts}
+    ///
+    /// Static DNS answers, like entries in a `hosts` file, but without touching the local
+    /// `/etc/hosts`.
+    ///
+    /// Maps a hostname, or a glob pattern where `*` matches any sequence of characters and `?`
+    /// matches a single one, to one or more addresses:
+    ///
+    /// ```json
+    /// {
+    ///   "payments.prod.svc": "127.0.0.1",
+    ///   "*.mock.svc": ["10.0.0.1", "fd00::1"]
+    /// }
+    /// ```
+    ///
+    /// Matching names are resolved by mirrord itself, neither locally nor in the remote pod,
+    /// regardless of [`filter`](#feature-network-dns-filter). Exact names take precedence over
+    /// patterns, and longer patterns over shorter ones.
+    #[config(default)]
+    pub hosts: Option<HashMap<String, VecOrSingle<String>>>,
 }
 
 impl DnsConfig {
     pub fn verify(&self, context: &mut ConfigContext) -> Result<(), ConfigError> {
+        self.verify_hosts(context)?;
+
         let filters = match &self.filter {
             Some(..) if !self.enabled => {
                 context.add_warning(
@@ -144,6 +184,38 @@ impl DnsConfig {
 
         Ok(())
     }
+
+    fn verify_hosts(&self, context: &mut ConfigContext) -> Result<(), ConfigError> {
+        let Some(hosts) = self.hosts.as_ref().filter(|hosts| !hosts.is_empty()) else {
+            return Ok(());
+        };
+
+        if !self.enabled {
+            context.add_warning(
+                "Remote DNS resolution is disabled, provided DNS hosts will be ignored".to_string(),
+            );
+        }
+
+        for (host, addresses) in hosts {
+            if addresses.is_empty() {
+                context.add_warning(format!(
+                    "DNS host `{host}` has no addresses, it will not resolve to anything"
+                ));
+            }
+
+            for address in addresses.iter() {
+                if let Err(error) = address.parse::<IpAddr>() {
+                    return Err(ConfigError::InvalidValue {
+                        name: "feature.network.dns.hosts",
+                        provided: format!("{host}: {address}"),
+                        error: Box::new(error),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl MirrordToggleableConfig for DnsFileConfig {
@@ -152,7 +224,9 @@ impl MirrordToggleableConfig for DnsFileConfig {
             enabled: FromEnv::new("MIRRORD_REMOTE_DNS")
                 .source_value(context)
                 .unwrap_or(Ok(false))?,
-            ..Default::default()
+            filter: None,
+            hosts: None,
+            cache: DnsCacheFileConfig::default().generate_config(context)?,
         })
     }
 }
@@ -160,6 +234,11 @@ impl MirrordToggleableConfig for DnsFileConfig {
 impl CollectAnalytics for &DnsConfig {
     fn collect_analytics(&self, analytics: &mut mirrord_analytics::Analytics) {
         analytics.add("enabled", self.enabled);
+        analytics.add("cache", &self.cache);
+        analytics.add(
+            "hosts",
+            self.hosts.as_ref().map(HashMap::len).unwrap_or_default(),
+        );
 
         if let Some(filter) = self.filter.as_ref() {
             match filter {
@@ -170,3 +249,45 @@ impl CollectAnalytics for &DnsConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn dns_config(host: &str, address: &str) -> DnsConfig {
+        DnsConfig {
+            enabled: true,
+            hosts: Some(HashMap::from([(
+                host.to_string(),
+                VecOrSingle::Single(address.to_string()),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case("payments.prod.svc", "127.0.0.1")]
+    #[case("*.mock.svc", "fd00::1")]
+    fn valid_hosts(#[case] host: &str, #[case] address: &str) {
+        let mut context = ConfigContext::default();
+
+        dns_config(host, address).verify(&mut context).unwrap();
+    }
+
+    #[rstest]
+    #[case("payments.prod.svc", "localhost")]
+    #[case("*.mock.svc", "10.0.0.256")]
+    fn invalid_hosts(#[case] host: &str, #[case] address: &str) {
+        let mut context = ConfigContext::default();
+
+        assert!(matches!(
+            dns_config(host, address).verify(&mut context),
+            Err(ConfigError::InvalidValue {
+                name: "feature.network.dns.hosts",
+                ..
+            })
+        ));
+    }
+}
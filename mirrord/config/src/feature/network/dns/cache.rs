@@ -0,0 +1,84 @@
+use mirrord_analytics::CollectAnalytics;
+use mirrord_config_derive::MirrordConfig;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::config::source::MirrordConfigSource;
+
+/// Caches the answers of remote DNS lookups in the internal proxy.
+///
+/// Saves round trips to the agent when the application keeps resolving the same names, e.g. when
+/// it opens a new connection for every request. The agent keeps its own cache, which follows the
+/// TTLs of the DNS records.
+///
+/// Successful answers are kept for `ttl` seconds (or less, if the records expire sooner), failed
+/// lookups for `negative_ttl` seconds.
+///
+/// Enabled by default.
+///
+/// ```json
+/// {
+///   "feature": {
+///     "network": {
+///       "dns": {
+///         "cache": {
+///           "enabled": true,
+///           "ttl": 5,
+///           "negative_ttl": 1,
+///           "max_entries": 256
+///         }
+///       }
+///     }
+///   }
+/// }
+/// ```
+#[derive(MirrordConfig, Clone, Debug, PartialEq, Eq, Serialize)]
+#[config(map_to = "DnsCacheFileConfig", derive = "PartialEq,Eq,JsonSchema")]
+pub struct DnsCacheConfig {
+    /// #### feature.network.dns.cache.enabled {#feature-network-dns-cache-enabled}
+    ///
+    /// Enables the cache of remote DNS answers in the internal proxy.
+    #[config(env = "MIRRORD_DNS_CACHE", default = true)]
+    pub enabled: bool,
+
+    /// #### feature.network.dns.cache.ttl {#feature-network-dns-cache-ttl}
+    ///
+    /// For how many seconds successful answers are used before they're resolved again.
+    ///
+    /// Defaults to 5 seconds.
+    #[config(env = "MIRRORD_DNS_CACHE_TTL", default = 5)]
+    pub ttl: u64,
+
+    /// #### feature.network.dns.cache.negative_ttl {#feature-network-dns-cache-negative_ttl}
+    ///
+    /// For how many seconds failed lookups are remembered before they're tried again.
+    ///
+    /// Defaults to 1 second.
+    #[config(env = "MIRRORD_DNS_CACHE_NEGATIVE_TTL", default = 1)]
+    pub negative_ttl: u64,
+
+    /// #### feature.network.dns.cache.max_entries {#feature-network-dns-cache-max_entries}
+    ///
+    /// Maximum number of cached answers.
+    ///
+    /// Defaults to 256.
+    #[config(env = "MIRRORD_DNS_CACHE_MAX_ENTRIES", default = 256)]
+    pub max_entries: usize,
+}
+
+impl Default for DnsCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl: 5,
+            negative_ttl: 1,
+            max_entries: 256,
+        }
+    }
+}
+
+impl CollectAnalytics for &DnsCacheConfig {
+    fn collect_analytics(&self, analytics: &mut mirrord_analytics::Analytics) {
+        analytics.add("enabled", self.enabled);
+    }
+}
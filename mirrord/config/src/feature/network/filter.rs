@@ -1,9 +1,12 @@
 use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
     net::{IpAddr, SocketAddr},
     num::ParseIntError,
     str::FromStr,
 };
 
+use fancy_regex::Regex;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_until},
@@ -65,6 +68,76 @@ pub enum AddressFilter {
 
     /// Just a plain old subnet and a port, specified as `a.b.c.d/e:f`.
     Subnet(ipnet::IpNet, u16),
+
+    /// A pattern for names and a port, specified either as a wildcard `*.name:a`, or as a
+    /// regular expression between slashes `/^name-[0-9]+$/:a`.
+    ///
+    /// Unlike [`AddressFilter::Name`], it's not resolved, but matched against the names that the
+    /// addresses were resolved from.
+    Hostname(HostnamePattern, u16),
+}
+
+/// <!--${internal}-->
+/// The pattern of an [`AddressFilter::Hostname`], compiled to a [`Regex`].
+///
+/// Compared by its regular expression.
+#[derive(Debug, Clone)]
+pub struct HostnamePattern(Regex);
+
+impl HostnamePattern {
+    /// A case-insensitive wildcard, where `*` matches any sequence of characters and `?` matches
+    /// a single one.
+    pub fn wildcard(wildcard: &str) -> Result<Self, AddressFilterError> {
+        let pattern = fancy_regex::escape(wildcard)
+            .replace(r"\*", ".*")
+            .replace(r"\?", ".");
+
+        Self::regex(&format!("(?i)^{pattern}$"))
+    }
+
+    /// A regular expression, in the [`fancy_regex`] syntax.
+    pub fn regex(regex: &str) -> Result<Self, AddressFilterError> {
+        Regex::new(regex)
+            .map(Self)
+            .map_err(|error| AddressFilterError::HostnamePattern(Box::new(error)))
+    }
+
+    /// Matches `hostname`, ignoring the trailing `.` of fully qualified names.
+    pub fn is_match(&self, hostname: &str) -> bool {
+        self.0
+            .is_match(hostname.trim_end_matches('.'))
+            .unwrap_or_default()
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for HostnamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for HostnamePattern {}
+
+impl PartialOrd for HostnamePattern {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HostnamePattern {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for HostnamePattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
 }
 
 impl AddressFilter {
@@ -74,6 +147,7 @@ impl AddressFilter {
             Self::Name(_, port) => *port,
             Self::Socket(socket) => socket.port(),
             Self::Subnet(_, port) => *port,
+            Self::Hostname(_, port) => *port,
         }
     }
 }
@@ -98,6 +172,9 @@ pub enum AddressFilterError {
     #[error("invalid subnet: {0}")]
     SubnetPrefixLen(#[from] ipnet::PrefixLenError),
 
+    #[error("invalid hostname pattern: {0}")]
+    HostnamePattern(Box<fancy_regex::Error>),
+
     #[error("provided empty string")]
     Empty,
 }
@@ -112,6 +189,22 @@ impl FromStr for AddressFilter {
     type Err = AddressFilterError;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
+        // Regular expressions may contain any of the characters below, so they go first.
+        if let (rest, Some(regex)) = hostname_regex(input)? {
+            let (rest, port) = port(rest)?;
+            if !rest.is_empty() {
+                return Err(Self::Err::TrailingValue(rest.to_string()));
+            }
+
+            let port = port
+                .map(u16::from_str)
+                .transpose()
+                .map_err(AddressFilterError::ParsePort)?
+                .unwrap_or(0);
+
+            return Ok(Self::Hostname(HostnamePattern::regex(regex)?, port));
+        }
+
         // Perform the basic parsing.
         let (rest, address) = address(input)?;
         let (rest, subnet) = subnet(rest)?;
@@ -148,7 +241,7 @@ impl FromStr for AddressFilter {
                 Ok(Self::Subnet(ip_net, port))
             }
 
-            // Subnet not specified. Address can be a name or an IP.
+            // Subnet not specified. Address can be a wildcard, a name or an IP.
             (Some(address), None, _) => {
                 let port = port
                     .map(u16::from_str)
@@ -156,6 +249,10 @@ impl FromStr for AddressFilter {
                     .map_err(AddressFilterError::ParsePort)?
                     .unwrap_or(0);
 
+                if address.contains(['*', '?']) {
+                    return Ok(Self::Hostname(HostnamePattern::wildcard(&address)?, port));
+                }
+
                 let result = address
                     .parse::<IpAddr>()
                     .map(|ip| Self::Socket(SocketAddr::new(ip, port)))
@@ -229,7 +326,7 @@ fn protocol(input: &str) -> IResult<&str, &str> {
 ///
 /// We try to parse 3 different kinds of values here:
 ///
-/// 1. `name.with.dots`;
+/// 1. `name.with.dots`, or a wildcard like `*.with.dots`;
 /// 2. `1.2.3.4.5.6`;
 /// 3. `[dad:1337:fa57::0]`
 ///
@@ -242,7 +339,14 @@ fn address(input: &str) -> IResult<&str, Option<String>> {
     let ipv6 = many1(alt((alphanumeric1, tag(":"))));
     let ipv6_host = delimited(tag("["), ipv6, tag("]"));
 
-    let host_char = alt((alphanumeric1, tag("-"), tag("_"), tag(".")));
+    let host_char = alt((
+        alphanumeric1,
+        tag("-"),
+        tag("_"),
+        tag("."),
+        tag("*"),
+        tag("?"),
+    ));
     let dotted_address = many1(host_char);
 
     let (rest, address) = opt(alt((dotted_address, ipv6_host)))(input)?;
@@ -252,6 +356,17 @@ fn address(input: &str) -> IResult<&str, Option<String>> {
     Ok((rest, address))
 }
 
+/// <!--${internal}-->
+///
+/// Parses `/^name-[0-9]+$/`, extracting the regular expression between the slashes, which can't
+/// appear in names.
+fn hostname_regex(input: &str) -> IResult<&str, Option<&str>> {
+    let regex_parser = delimited(tag("/"), take_until("/"), tag("/"));
+    let (rest, regex) = opt(regex_parser)(input)?;
+
+    Ok((rest, regex))
+}
+
 /// <!--${internal}-->
 ///
 /// Parses `/24`, extracting the `24` part, and discarding the `/`.
@@ -414,6 +529,38 @@ mod tests {
         }
     }
 
+    #[fixture]
+    fn wildcard() -> &'static str {
+        "tcp://*.internal.corp:443"
+    }
+
+    #[fixture]
+    fn wildcard_converted() -> ProtocolAndAddressFilter {
+        ProtocolAndAddressFilter {
+            protocol: ProtocolFilter::Tcp,
+            address: AddressFilter::Hostname(
+                HostnamePattern::wildcard("*.internal.corp").unwrap(),
+                443,
+            ),
+        }
+    }
+
+    #[fixture]
+    fn regex() -> &'static str {
+        r"/^api-(?:eu|us)-[0-9]+\.corp$/:8080"
+    }
+
+    #[fixture]
+    fn regex_converted() -> ProtocolAndAddressFilter {
+        ProtocolAndAddressFilter {
+            protocol: ProtocolFilter::Any,
+            address: AddressFilter::Hostname(
+                HostnamePattern::regex(r"^api-(?:eu|us)-[0-9]+\.corp$").unwrap(),
+                8080,
+            ),
+        }
+    }
+
     // Bad configs.
     #[fixture]
     fn name_with_subnet() -> &'static str {
@@ -430,6 +577,16 @@ mod tests {
         "meow://"
     }
 
+    #[fixture]
+    fn bad_regex() -> &'static str {
+        "tcp:///^api-(eu$/:443"
+    }
+
+    #[fixture]
+    fn unclosed_regex() -> &'static str {
+        "/^api-[0-9]+:443"
+    }
+
     #[rstest]
     #[case(full(), full_converted())]
     #[case(ipv6(), ipv6_converted())]
@@ -441,6 +598,8 @@ mod tests {
     #[case(subnet_only(), subnet_only_converted())]
     #[case(protocol_port(), protocol_port_converted())]
     #[case(port_only(), port_only_converted())]
+    #[case(wildcard(), wildcard_converted())]
+    #[case(regex(), regex_converted())]
     fn valid_filters(#[case] input: &'static str, #[case] converted: ProtocolAndAddressFilter) {
         assert_eq!(
             ProtocolAndAddressFilter::from_str(input).unwrap(),
@@ -452,8 +611,22 @@ mod tests {
     #[case(name_with_subnet())]
     #[case(port_protocol())]
     #[case(fake_protocol())]
+    #[case(bad_regex())]
+    #[case(unclosed_regex())]
     #[should_panic]
     fn invalid_filters(#[case] input: &'static str) {
         ProtocolAndAddressFilter::from_str(input).unwrap();
     }
+
+    #[rstest]
+    #[case("*.internal.corp", "db.internal.corp.", true)]
+    #[case("*.internal.corp", "DB.Internal.Corp", true)]
+    #[case("*.internal.corp", "internal.corp", false)]
+    #[case("db-?.corp", "db-1.corp", true)]
+    #[case("db-?.corp", "db-10.corp", false)]
+    fn wildcard_matches(#[case] wildcard: &str, #[case] hostname: &str, #[case] matches: bool) {
+        let pattern = HostnamePattern::wildcard(wildcard).unwrap();
+
+        assert_eq!(pattern.is_match(hostname), matches);
+    }
 }
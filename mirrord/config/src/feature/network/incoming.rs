@@ -14,8 +14,10 @@ use crate::{
     util::{MirrordToggleableConfig, ToggleableConfig},
 };
 
+pub mod http;
 pub mod http_filter;
 
+use http::HttpConfig;
 use http_filter::*;
 
 /// ## incoming (network)
@@ -136,6 +138,9 @@ impl MirrordConfig for IncomingFileConfig {
                     .transpose()?
                     .unwrap_or_default(),
                 ports: advanced.ports.map(|ports| ports.into_iter().collect()),
+                udp: advanced.udp.unwrap_or_default(),
+                http: advanced.http.unwrap_or_default(),
+                on_local_failure: advanced.on_local_failure.unwrap_or_default(),
             },
         };
 
@@ -308,6 +313,25 @@ pub struct IncomingAdvancedFileConfig {
     ///
     /// Mutually exclusive with [`ignore_ports`](###ignore_ports).
     pub ports: Option<Vec<u16>>,
+
+    /// ### udp
+    ///
+    /// Mirror/steal the incoming UDP traffic as well.
+    pub udp: Option<bool>,
+
+    /// ### http
+    ///
+    /// Header rewriting rules for the stolen HTTP traffic.
+    ///
+    /// See [`http`](##http) for details.
+    pub http: Option<HttpConfig>,
+
+    /// ### on_local_failure
+    ///
+    /// What to do with a stolen HTTP request when the local application fails to handle it.
+    ///
+    /// See [`on_local_failure`](##on_local_failure) for details.
+    pub on_local_failure: Option<OnLocalFailure>,
 }
 
 fn serialize_bi_map<S>(map: &BiMap<u16, u16>, serializer: S) -> Result<S::Ok, S::Error>
@@ -451,6 +475,25 @@ pub struct IncomingConfig {
     /// Mutually exclusive with
     /// [`feature.network.incoming.ignore_ports`](#feature-network-ignore_ports).
     pub ports: Option<HashSet<u16>>,
+
+    /// #### feature.network.incoming.udp {#feature-network-incoming-udp}
+    ///
+    /// Mirror/steal the incoming UDP traffic as well, using the same
+    /// [`mode`](#feature-network-incoming-mode), `port_mapping`, `ignore_ports` and `ports`
+    /// settings as the TCP traffic.
+    ///
+    /// Whenever the local application binds a UDP socket, datagrams incoming to the same port in
+    /// the target are delivered to that socket. When stealing, replies sent from the socket to the
+    /// sender of the datagram are sent back from the target.
+    ///
+    /// Defaults to `false`.
+    pub udp: bool,
+
+    /// #### feature.network.incoming.http {#feature-network-incoming-http}
+    pub http: HttpConfig,
+
+    /// #### feature.network.incoming.on_local_failure {#feature-network-incoming-on_local_failure}
+    pub on_local_failure: OnLocalFailure,
 }
 
 impl IncomingConfig {
@@ -586,6 +629,35 @@ impl fmt::Display for ConcurrentSteal {
     }
 }
 
+/// What to do with a stolen HTTP request when the local application fails to handle it, i.e.
+/// it's not listening, the connection breaks or it responds with a 5xx status.
+///
+/// Only does something when [`feature.network.incoming.mode`](#feature-network-incoming-mode) is
+/// set as `"steal"` and an [`http_filter`](#feature-network-incoming-http-filter) is in use,
+/// ignored otherwise.
+///
+/// Can be set to either `"error"` (default) or `"passthrough"`.
+///
+/// - `"error"`: The agent responds to the request with the failure (or with `502 Bad Gateway`).
+/// - `"passthrough"`: The agent sends the request to its original destination in the target, and
+///   responds with the response from there. Requires the whole request body to be buffered in the
+///   agent, requests with bodies larger than 8 MiB get the `"error"` behavior.
+#[derive(Default, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "lowercase")]
+pub enum OnLocalFailure {
+    /// <!--${internal}-->
+    /// ### error
+    ///
+    /// Respond with the failure.
+    #[default]
+    Error,
+    /// <!--${internal}-->
+    /// ### passthrough
+    ///
+    /// Send the request to its original destination.
+    Passthrough,
+}
+
 impl From<&IncomingMode> for AnalyticValue {
     fn from(value: &IncomingMode) -> Self {
         match value {
@@ -615,5 +687,11 @@ impl CollectAnalytics for &IncomingConfig {
         analytics.add("ignore_localhost", self.ignore_localhost);
         analytics.add("ignore_ports_count", self.ignore_ports.len());
         analytics.add("http", &self.http_filter);
+        analytics.add("udp", self.udp);
+        analytics.add("http_header_rules", !self.http.is_empty());
+        analytics.add(
+            "on_local_failure_passthrough",
+            matches!(self.on_local_failure, OnLocalFailure::Passthrough),
+        );
     }
 }
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Header rewriting rules for the stolen HTTP traffic.
+///
+/// Request rules are applied to the stolen requests before they reach the local application.
+/// Response rules are applied to the responses of the local application before they're sent back
+/// by the agent.
+///
+/// Only does something when [`feature.network.incoming.mode`](#feature-network-incoming-mode) is
+/// set as `"steal"`, ignored otherwise.
+///
+/// For example, to swap the authorization header for a development token and mark the responses
+/// as served by mirrord:
+/// ```json
+/// {
+///   "request_headers": {
+///     "replace": { "authorization": "Bearer dev-token" }
+///   },
+///   "response_headers": {
+///     "add": { "x-mirrord-served-by": "alice" }
+///   }
+/// }
+/// ```
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// ##### feature.network.incoming.http.request_headers {#feature-network-incoming-http-request_headers}
+    ///
+    /// Rules applied to the stolen requests.
+    #[serde(default)]
+    pub request_headers: HeaderRules,
+
+    /// ##### feature.network.incoming.http.response_headers {#feature-network-incoming-http-response_headers}
+    ///
+    /// Rules applied to the responses of the local application.
+    #[serde(default)]
+    pub response_headers: HeaderRules,
+}
+
+impl HttpConfig {
+    /// Returns whether there are no rules at all.
+    pub fn is_empty(&self) -> bool {
+        self.request_headers.is_empty() && self.response_headers.is_empty()
+    }
+}
+
+/// Header rules, applied in order: `remove`, `replace`, `add`.
+///
+/// Header names are case insensitive.
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderRules {
+    /// ###### remove
+    ///
+    /// Names of the headers to remove.
+    #[serde(default)]
+    pub remove: Vec<String>,
+
+    /// ###### replace
+    ///
+    /// Headers to replace, only if already present.
+    #[serde(default)]
+    pub replace: BTreeMap<String, String>,
+
+    /// ###### add
+    ///
+    /// Headers to add, existing headers with the same name are kept.
+    #[serde(default)]
+    pub add: BTreeMap<String, String>,
+}
+
+impl HeaderRules {
+    /// Returns whether there are no rules at all.
+    pub fn is_empty(&self) -> bool {
+        self.remove.is_empty() && self.replace.is_empty() && self.add.is_empty()
+    }
+}
@@ -2,6 +2,7 @@ use std::{collections::HashSet, ops::Deref, str::FromStr};
 
 use mirrord_analytics::CollectAnalytics;
 use mirrord_config_derive::MirrordConfig;
+use mirrord_protocol::tcp::{BodyFilter, Filter, HttpFilter};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -55,6 +56,19 @@ use crate::{
 /// ```
 /// Setting this filter will make mirrord only steal requests to URIs that do not start with
 /// "/health/".
+///
+/// Filtering by method, query string or body is available only through
+/// [`all_of`](#feature-network-incoming-http_filter-all_of) and
+/// [`any_of`](#feature-network-incoming-http_filter-any_of).
+/// For example, to steal only `POST` requests for a specific tenant:
+/// ```json
+/// {
+///   "all_of": [
+///     { "method": "POST" },
+///     { "query": "tenant=alice" }
+///   ]
+/// }
+/// ```
 #[derive(MirrordConfig, Default, PartialEq, Eq, Clone, Debug, Serialize)]
 #[config(map_to = "HttpFilterFileConfig", derive = "JsonSchema")]
 #[cfg_attr(test, config(derive = "PartialEq, Eq"))]
@@ -117,6 +131,23 @@ impl HttpFilterConfig {
         self.all_of.is_some() || self.any_of.is_some()
     }
 
+    /// Whether any of the composite filters uses [`InnerFilter::Method`], [`InnerFilter::Query`]
+    /// or [`InnerFilter::Body`].
+    pub fn has_method_query_or_body_filter(&self) -> bool {
+        [self.all_of.as_ref(), self.any_of.as_ref()]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .any(|filter| {
+                matches!(
+                    filter,
+                    InnerFilter::Method { .. }
+                        | InnerFilter::Query { .. }
+                        | InnerFilter::Body { .. }
+                )
+            })
+    }
+
     pub fn get_filtered_ports(&self) -> Option<&[u16]> {
         self.is_filter_set().then(|| &*self.ports.0)
     }
@@ -144,6 +175,98 @@ pub enum InnerFilter {
     /// Case-insensitive. Tries to find match in the path (without query) and path+query.
     /// If any of the two matches, the request is stolen.
     Path { path: String },
+
+    /// ##### feature.network.incoming.inner_filter.method_filter {#feature-network-incoming-inner-method-filter}
+    ///
+    ///
+    /// Supports regexes validated by the
+    /// [`fancy-regex`](https://docs.rs/fancy-regex/latest/fancy_regex/) crate.
+    ///
+    /// Case-insensitive. The whole method name must match, e.g. `"POST|PUT"`.
+    Method { method: String },
+
+    /// ##### feature.network.incoming.inner_filter.query_filter {#feature-network-incoming-inner-query-filter}
+    ///
+    ///
+    /// Supports regexes validated by the
+    /// [`fancy-regex`](https://docs.rs/fancy-regex/latest/fancy_regex/) crate.
+    ///
+    /// Case-insensitive. Tries to find match in the query string (without the leading `?`), e.g.
+    /// `"tenant=alice"`.
+    Query { query: String },
+
+    /// ##### feature.network.incoming.inner_filter.body_filter {#feature-network-incoming-inner-body-filter}
+    ///
+    ///
+    /// Supports regexes validated by the
+    /// [`fancy-regex`](https://docs.rs/fancy-regex/latest/fancy_regex/) crate.
+    ///
+    /// Only the first `max_size` bytes of the body are inspected (64 KiB by default, at most 1
+    /// MiB).
+    ///
+    /// Without `json_path`, tries to find match in the inspected bytes, decoded as UTF-8 (invalid
+    /// sequences are replaced). Case-sensitive, and `.` also matches newlines. A longer body
+    /// matches if its first `max_size` bytes do.
+    ///
+    /// With `json_path`, the body is parsed as JSON and the regex must match the whole value of
+    /// any field selected by the [JSONPath](https://goessner.net/articles/JsonPath/) query.
+    /// Bodies that are not valid JSON or are longer than `max_size` never match.
+    ///
+    /// ```json
+    /// { "body": "alice", "json_path": "$.user_id" }
+    /// ```
+    Body {
+        body: String,
+        json_path: Option<String>,
+        max_size: Option<u32>,
+    },
+}
+
+impl InnerFilter {
+    /// Converts this filter into the [`HttpFilter`] sent to the agent.
+    ///
+    /// Panics on invalid regexes, they should be verified in the CLI.
+    pub fn to_http_filter(&self) -> HttpFilter {
+        match self {
+            InnerFilter::Path { path } => {
+                HttpFilter::Path(Filter::new(path.clone()).expect("invalid filter expression"))
+            }
+            InnerFilter::Header { header } => {
+                HttpFilter::Header(Filter::new(header.clone()).expect("invalid filter expression"))
+            }
+            InnerFilter::Method { method } => {
+                HttpFilter::Method(Filter::new(method.clone()).expect("invalid filter expression"))
+            }
+            InnerFilter::Query { query } => {
+                HttpFilter::Query(Filter::new(query.clone()).expect("invalid filter expression"))
+            }
+            InnerFilter::Body {
+                body,
+                json_path,
+                max_size,
+            } => {
+                let filter = Filter::new(body.clone()).expect("invalid filter expression");
+                let max_size = max_size.unwrap_or(BodyFilter::DEFAULT_MAX_SIZE);
+
+                HttpFilter::Body(match json_path {
+                    Some(query) => BodyFilter::Json {
+                        query: query.clone(),
+                        filter,
+                        max_size,
+                    },
+                    None => BodyFilter::Regex { filter, max_size },
+                })
+            }
+        }
+    }
+
+    /// Converts the `all_of` (`all`) or `any_of` `filters` into a [`HttpFilter::Composite`], see
+    /// [`Self::to_http_filter`].
+    pub fn composite(all: bool, filters: &[Self]) -> HttpFilter {
+        let filters = filters.iter().map(Self::to_http_filter).collect();
+
+        HttpFilter::Composite { all, filters }
+    }
 }
 
 /// <!--${internal}-->
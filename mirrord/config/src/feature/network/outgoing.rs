@@ -52,7 +52,20 @@ use crate::{
 /// }
 /// ```
 ///
-/// Valid values follow this pattern: `[protocol]://[name|address|subnet/mask]:[port]`.
+/// - Only TCP traffic to hosts under `internal.corp` on port `443`, and traffic to hosts like
+///   `api-7.corp`, will go through the local app.
+/// ```json
+/// {
+///   "local": ["tcp://*.internal.corp:443", "/^api-[0-9]+\\.corp$/"]
+/// }
+/// ```
+///
+/// Wildcards (`*` and `?`) and regular expressions (between slashes) are not resolved, they match
+/// the hostname that the address was resolved from through the remote pod, even when the app
+/// connects to an address it resolved earlier.
+///
+/// Valid values follow this pattern:
+/// `[protocol]://[name|address|subnet/mask|wildcard|/regex/]:[port]`.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "lowercase")]
 pub enum OutgoingFilterConfig {
@@ -11,6 +11,7 @@ pub static MIRRORD_INTPROXY_CONTAINER_MODE_ENV: &str = "MIRRORD_INTPROXY_CONTAIN
 pub static MIRRORD_INTPROXY_CLIENT_TLS_CERTIFICATE_ENV: &str =
     "MIRRORD_INTPROXY_CLIENT_TLS_CERTIFICATE";
 pub static MIRRORD_INTPROXY_CLIENT_TLS_KEY_ENV: &str = "MIRRORD_INTPROXY_CLIENT_TLS_KEY";
+pub static MIRRORD_INTPROXY_HTTP_RECORD_ENV: &str = "MIRRORD_INTPROXY_HTTP_RECORD";
 
 /// Configuration for the internal proxy mirrord spawns for each local mirrord session
 /// that local layers use to connect to the remote agent
@@ -118,4 +119,23 @@ pub struct InternalProxyConfig {
     /// This informs the intproxy that it's running inside a continer and should not detach io
     #[config(default = false, env = MIRRORD_INTPROXY_CONTAINER_MODE_ENV)]
     pub container_mode: bool,
+
+    /// ### internal_proxy.http_record {#internal_proxy-http_record}
+    ///
+    /// Record every stolen HTTP request, together with the response sent by the local
+    /// application, to the given file.
+    ///
+    /// Files with the `.har` extension are written in the HAR format, any other file gets one
+    /// HAR entry per line (JSON Lines). The recording can be replayed against a local process
+    /// with `mirrord replay`.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "http_record": "/tmp/session.har"
+    ///   }
+    /// }
+    /// ```
+    #[config(env = MIRRORD_INTPROXY_HTTP_RECORD_ENV)]
+    pub http_record: Option<PathBuf>,
 }
@@ -20,7 +20,7 @@ use std::{collections::HashSet, ops::Not, path::Path};
 
 use config::{ConfigContext, ConfigError, MirrordConfig};
 use experimental::ExperimentalConfig;
-use feature::network::outgoing::OutgoingFilterConfig;
+use feature::{fs::PathMappingSide, network::outgoing::OutgoingFilterConfig};
 use mirrord_analytics::CollectAnalytics;
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
@@ -366,6 +366,21 @@ impl LayerConfig {
             );
         }
 
+        if !self.feature.fs.is_active()
+            && self
+                .feature
+                .fs
+                .mappings()
+                .iter()
+                .any(|mapping| mapping.side == Some(PathMappingSide::Remote))
+        {
+            context.add_warning(
+                "The fs mapping includes rules with `remote:` targets, but the fs mode is \
+                `local`, so these rules are ignored."
+                    .to_string(),
+            );
+        }
+
         let http_filter = &self.feature.network.incoming.http_filter;
         let used_filters = [
             http_filter.path_filter.is_some(),
@@ -856,6 +871,9 @@ mod tests {
                             listen_ports: None,
                             on_concurrent_steal: None,
                             ports: None,
+                            udp: None,
+                            http: None,
+                            on_local_failure: None,
                         }),
                     ))),
                     outgoing: Some(ToggleableConfig::Config(OutgoingFileConfig {
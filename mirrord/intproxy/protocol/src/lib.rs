@@ -10,10 +10,14 @@ use std::{
 
 use bincode::{Decode, Encode};
 use mirrord_protocol::{
-    dns::{GetAddrInfoRequest, GetAddrInfoResponse},
+    dns::{
+        DnsCacheStats, DnsQueryRequest, DnsQueryResponse, GetAddrInfoRequestV2,
+        GetAddrInfoResponse, ReverseLookupRequest, ReverseLookupResponse,
+    },
     file::*,
     outgoing::SocketAddress,
     tcp::StealType,
+    udp::UdpPortSubscription,
     FileRequest, FileResponse, GetEnvVarsRequest, Port, RemoteResult,
 };
 
@@ -44,13 +48,21 @@ pub enum LayerToProxyMessage {
     /// A file operation request.
     File(FileRequest),
     /// A DNS request.
-    GetAddrInfo(GetAddrInfoRequest),
+    GetAddrInfo(GetAddrInfoRequestV2),
     /// A request to initiate a new outgoing connection.
     OutgoingConnect(OutgoingConnectRequest),
     /// Requests related to incoming connections.
     Incoming(IncomingRequest),
     /// Fetch environment variables from the target.
     GetEnv(GetEnvVarsRequest),
+    /// Requests related to the watches of remote files.
+    FileWatch(FileWatchRequest),
+    /// A DNS request for records of any type.
+    DnsQuery(DnsQueryRequest),
+    /// A DNS request for the names of an address.
+    ReverseLookup(ReverseLookupRequest),
+    /// A request for the counters of the DNS caches, sent by `mirrord diagnose dns`.
+    DnsCacheStats(DnsCacheStatsRequest),
 }
 
 /// Layer process information
@@ -89,6 +101,10 @@ pub enum NewSessionRequest {
     /// Layer re-initialized from a [`fork`](https://man7.org/linux/man-pages/man2/fork.2.html) detour.
     /// It inherits state from its parent.
     Forked(LayerId),
+    /// A separate connection opened by the layer with the given id, to receive the
+    /// [`ProxyToLayerMessage::FileWatchEvent`]s of its inotify instances. The layer sends no
+    /// requests in this session.
+    FileWatchEvents(LayerId),
 }
 
 /// Supported network protocols when intercepting outgoing connections.
@@ -137,6 +153,11 @@ pub enum IncomingRequest {
     /// A request made by the layer when it accepts a connection on the socket that is listening
     /// for mirrored connections.
     ConnMetadata(ConnMetadataRequest),
+    /// A request made by the layer when it binds a UDP socket.
+    UdpPortSubscribe(UdpPortSubscribe),
+    /// A request made by the layer when it closes a UDP socket subscribed with
+    /// [`UdpPortSubscribe`].
+    UdpPortUnsubscribe(UdpPortUnsubscribe),
 }
 
 /// A request for additional metadata for accepted connection.
@@ -202,6 +223,92 @@ pub struct PortUnsubscribe {
     pub listening_on: SocketAddr,
 }
 
+/// A request to start proxying incoming UDP traffic.
+///
+/// For each remote peer sending datagrams to the remote port, the internal proxy will use a
+/// separate UDP socket to forward them to the local address specified in `bound_on`.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct UdpPortSubscribe {
+    /// Local address to which the layer's UDP socket is bound.
+    pub bound_on: SocketAddr,
+    /// Remote port and how to handle its traffic.
+    pub subscription: UdpPortSubscription,
+}
+
+/// A request to stop proxying incoming UDP traffic.
+#[derive(Encode, Decode, Debug)]
+pub struct UdpPortUnsubscribe {
+    /// Remote port that the layer subscribed to.
+    pub port: Port,
+    /// Local address to which the layer's UDP socket was bound.
+    pub bound_on: SocketAddr,
+}
+
+/// Requests related to the watches of remote files.
+///
+/// The layer emulates an inotify instance with a local socket pair. The internal proxy sends the
+/// events of the instance's watches as `inotify_event` records, in a separate session opened by
+/// the layer (see [`NewSessionRequest::FileWatchEvents`]), and the layer writes them to the socket
+/// pair.
+#[derive(Encode, Decode, Debug)]
+pub enum FileWatchRequest {
+    /// A request made by the layer when the application adds a watch of a remote path.
+    Subscribe(WatchSubscribe),
+    /// A request made by the layer when the application removes a watch of a remote path, or
+    /// closes the inotify instance.
+    Unsubscribe(WatchUnsubscribe),
+}
+
+/// Identifies an inotify instance emulated by the layer.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchInstance {
+    /// Session in which the layer receives the events of the instance.
+    pub events_to: LayerId,
+    /// Id of the instance, unique within the layer.
+    pub id: u64,
+}
+
+/// A request to start watching a remote path for an inotify instance emulated by the layer.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct WatchSubscribe {
+    pub request: AddWatchRequest,
+    pub instance: WatchInstance,
+    /// Watch descriptor allocated by the layer, used in the events of the watch.
+    pub wd: i32,
+}
+
+/// A request to stop watching a remote path for an inotify instance emulated by the layer.
+#[derive(Encode, Decode, Debug)]
+pub struct WatchUnsubscribe {
+    pub instance: WatchInstance,
+    /// Watch descriptor returned in the response to [`WatchSubscribe`].
+    pub wd: i32,
+}
+
+/// An event of a remote watch, sent in the session opened with
+/// [`NewSessionRequest::FileWatchEvents`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// [`WatchInstance::id`] of the instance that should get the event.
+    pub instance: u64,
+    /// Encoded `struct inotify_event`, with the name padded like the kernel does.
+    pub record: Vec<u8>,
+}
+
+/// A request for the counters of the DNS caches of the internal proxy and of the agent.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsCacheStatsRequest;
+
+/// A response to [`DnsCacheStatsRequest`].
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsCacheStatsResponse {
+    /// Counters of the internal proxy cache, [`None`] if the cache is disabled.
+    pub proxy: Option<DnsCacheStats>,
+    /// Counters of the agent cache, [`None`] if the agent does not match
+    /// [`DNS_CACHE_STATS_VERSION`](mirrord_protocol::dns::DNS_CACHE_STATS_VERSION).
+    pub agent: Option<DnsCacheStats>,
+}
+
 /// Messages sent by the internal proxy and handled by the layer.
 #[derive(Encode, Decode, Debug)]
 pub enum ProxyToLayerMessage {
@@ -210,7 +317,7 @@ pub enum ProxyToLayerMessage {
     NewSession(LayerId),
     /// A response to layer's [`FileRequest`].
     File(FileResponse),
-    /// A response to layer's [`GetAddrInfoRequest`].
+    /// A response to layer's [`GetAddrInfoRequestV2`].
     GetAddrInfo(GetAddrInfoResponse),
     /// A response to layer's [`OutgoingConnectRequest`].
     OutgoingConnect(RemoteResult<OutgoingConnectResponse>),
@@ -218,6 +325,17 @@ pub enum ProxyToLayerMessage {
     Incoming(IncomingResponse),
     /// A response to layer's [`LayerToProxyMessage::GetEnv`].
     GetEnv(RemoteResult<HashMap<String, String>>),
+    /// A response to layer's [`WatchSubscribe`]. Contains the watch descriptor, which is the
+    /// already used one if the inotify instance watches the same remote file.
+    FileWatch(RemoteResult<i32>),
+    /// Pushed for the watches of the inotify instances emulated by the layer, see [`WatchEvent`].
+    FileWatchEvent(WatchEvent),
+    /// A response to layer's [`DnsQueryRequest`].
+    DnsQuery(DnsQueryResponse),
+    /// A response to layer's [`ReverseLookupRequest`].
+    ReverseLookup(ReverseLookupResponse),
+    /// A response to [`DnsCacheStatsRequest`].
+    DnsCacheStats(DnsCacheStatsResponse),
 }
 
 /// A response to layer's [`IncomingRequest`].
@@ -231,6 +349,8 @@ pub enum IncomingResponse {
     PortSubscribe(RemoteResult<()>),
     /// A response to layers' [`ConnMetadataRequest`].
     ConnMetadata(ConnMetadataResponse),
+    /// A response to layer's [`UdpPortSubscribe`].
+    UdpPortSubscribe(RemoteResult<()>),
 }
 
 /// A response to layer's [`OutgoingConnectRequest`].
@@ -352,6 +472,27 @@ impl_request!(
     res_path = ProxyToLayerMessage::File => FileResponse::XstatFs,
 );
 
+impl_request!(
+    req = StatFsRequest,
+    res = RemoteResult<XstatFsResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::StatFs,
+    res_path = ProxyToLayerMessage::File => FileResponse::XstatFs,
+);
+
+impl_request!(
+    req = XstatFsRequestV2,
+    res = RemoteResult<XstatFsResponseV2>,
+    req_path = LayerToProxyMessage::File => FileRequest::XstatFsV2,
+    res_path = ProxyToLayerMessage::File => FileResponse::XstatFsV2,
+);
+
+impl_request!(
+    req = StatFsRequestV2,
+    res = RemoteResult<XstatFsResponseV2>,
+    req_path = LayerToProxyMessage::File => FileRequest::StatFsV2,
+    res_path = ProxyToLayerMessage::File => FileResponse::XstatFsV2,
+);
+
 impl_request!(
     req = FdOpenDirRequest,
     res = RemoteResult<OpenDirResponse>,
@@ -382,6 +523,83 @@ impl_request!(
     res_path = ProxyToLayerMessage::File => FileResponse::GetDEnts64,
 );
 
+impl_request!(
+    req = MakeDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::MakeDir,
+    res_path = ProxyToLayerMessage::File => FileResponse::MakeDir,
+);
+
+impl_request!(
+    req = RemoveDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::RemoveDir,
+    res_path = ProxyToLayerMessage::File => FileResponse::RemoveDir,
+);
+
+impl_request!(
+    req = UnlinkRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Unlink,
+    res_path = ProxyToLayerMessage::File => FileResponse::Unlink,
+);
+
+impl_request!(
+    req = RenameRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Rename,
+    res_path = ProxyToLayerMessage::File => FileResponse::Rename,
+);
+
+impl_request!(
+    req = ChmodRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Chmod,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chmod,
+);
+
+impl_request!(
+    req = FchmodRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Fchmod,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chmod,
+);
+
+impl_request!(
+    req = ChownRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Chown,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chown,
+);
+
+impl_request!(
+    req = TruncateRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Truncate,
+    res_path = ProxyToLayerMessage::File => FileResponse::Truncate,
+);
+
+impl_request!(
+    req = FtruncateRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Ftruncate,
+    res_path = ProxyToLayerMessage::File => FileResponse::Truncate,
+);
+
+impl_request!(
+    req = SymlinkRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Symlink,
+    res_path = ProxyToLayerMessage::File => FileResponse::Symlink,
+);
+
+impl_request!(
+    req = UtimesRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Utimes,
+    res_path = ProxyToLayerMessage::File => FileResponse::Utimes,
+);
+
 impl_request!(
     req = CloseFileRequest,
     req_path = LayerToProxyMessage::File => FileRequest::Close,
@@ -393,12 +611,33 @@ impl_request!(
 );
 
 impl_request!(
-    req = GetAddrInfoRequest,
+    req = GetAddrInfoRequestV2,
     res = GetAddrInfoResponse,
     req_path = LayerToProxyMessage::GetAddrInfo,
     res_path = ProxyToLayerMessage::GetAddrInfo,
 );
 
+impl_request!(
+    req = DnsQueryRequest,
+    res = DnsQueryResponse,
+    req_path = LayerToProxyMessage::DnsQuery,
+    res_path = ProxyToLayerMessage::DnsQuery,
+);
+
+impl_request!(
+    req = ReverseLookupRequest,
+    res = ReverseLookupResponse,
+    req_path = LayerToProxyMessage::ReverseLookup,
+    res_path = ProxyToLayerMessage::ReverseLookup,
+);
+
+impl_request!(
+    req = DnsCacheStatsRequest,
+    res = DnsCacheStatsResponse,
+    req_path = LayerToProxyMessage::DnsCacheStats,
+    res_path = ProxyToLayerMessage::DnsCacheStats,
+);
+
 impl_request!(
     req = OutgoingConnectRequest,
     res = RemoteResult<OutgoingConnectResponse>,
@@ -425,9 +664,33 @@ impl_request!(
     res_path = ProxyToLayerMessage::Incoming => IncomingResponse::ConnMetadata,
 );
 
+impl_request!(
+    req = UdpPortSubscribe,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::Incoming => IncomingRequest::UdpPortSubscribe,
+    res_path = ProxyToLayerMessage::Incoming => IncomingResponse::UdpPortSubscribe,
+);
+
+impl_request!(
+    req = UdpPortUnsubscribe,
+    req_path = LayerToProxyMessage::Incoming => IncomingRequest::UdpPortUnsubscribe,
+);
+
 impl_request!(
     req = GetEnvVarsRequest,
     res = RemoteResult<HashMap<String, String>>,
     req_path = LayerToProxyMessage::GetEnv,
     res_path = ProxyToLayerMessage::GetEnv,
 );
+
+impl_request!(
+    req = WatchSubscribe,
+    res = RemoteResult<i32>,
+    req_path = LayerToProxyMessage::FileWatch => FileWatchRequest::Subscribe,
+    res_path = ProxyToLayerMessage::FileWatch,
+);
+
+impl_request!(
+    req = WatchUnsubscribe,
+    req_path = LayerToProxyMessage::FileWatch => FileWatchRequest::Unsubscribe,
+);
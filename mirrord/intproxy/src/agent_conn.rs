@@ -8,9 +8,11 @@ use std::{
     net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
-use mirrord_analytics::Reporter;
+use exponential_backoff::Backoff;
+use mirrord_analytics::{NullReporter, Reporter};
 use mirrord_config::LayerConfig;
 use mirrord_kube::{
     api::{
@@ -29,12 +31,14 @@ use tokio::{
         mpsc,
         mpsc::{Receiver, Sender},
     },
+    time,
 };
 use tokio_rustls::TlsConnector;
 use tracing::Level;
 
 use crate::{
     background_tasks::{BackgroundTask, MessageBus},
+    main_tasks::ConnectionRefresh,
     ProxyMessage,
 };
 
@@ -97,8 +101,21 @@ pub enum AgentConnectInfo {
     DirectKubernetes(AgentKubernetesConnectInfo),
 }
 
+/// Describes how the [`AgentConnection`] should be re-established when it breaks.
+enum ReconnectFlow {
+    /// Connect again using the same [`LayerConfig`] and [`AgentConnectInfo`].
+    ConnectInfo {
+        config: Box<LayerConfig>,
+        connect_info: Option<AgentConnectInfo>,
+    },
+    /// Do not reconnect, fail instead.
+    Break,
+}
+
 /// Handles logic of the `proxy <-> agent` connection as a [`BackgroundTask`].
 ///
+/// When the connection breaks, this task tries to re-establish it (see [`ConnectionRefresh`]).
+///
 /// # Note
 /// The raw IO is managed in a separate [`tokio::task`] created in a different mirrord crate.
 /// This differs from the [`LayerConnection`](crate::layer_conn::LayerConnection) implementation,
@@ -107,9 +124,17 @@ pub enum AgentConnectInfo {
 pub struct AgentConnection {
     pub agent_tx: Sender<ClientMessage>,
     pub agent_rx: Receiver<DaemonMessage>,
+    reconnect: ReconnectFlow,
 }
 
 impl AgentConnection {
+    /// How many times we retry re-establishing a broken connection before giving up.
+    const RECONNECT_RETRIES: u32 = 10;
+    /// Lower limit for the delay between reconnection attempts.
+    const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(500);
+    /// Upper limit for the delay between reconnection attempts.
+    const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
     /// Creates a new agent connection based on the provided [`LayerConfig`] and optional
     /// [`AgentConnectInfo`].
     pub async fn new<R: Reporter>(
@@ -117,6 +142,25 @@ impl AgentConnection {
         connect_info: Option<AgentConnectInfo>,
         analytics: &mut R,
     ) -> Result<Self, AgentConnectionError> {
+        let (agent_tx, agent_rx) = Self::connect(config, connect_info.clone(), analytics).await?;
+
+        Ok(Self {
+            agent_tx,
+            agent_rx,
+            reconnect: ReconnectFlow::ConnectInfo {
+                config: Box::new(config.clone()),
+                connect_info,
+            },
+        })
+    }
+
+    /// Establishes a new connection with the agent, returns channels for communicating with the
+    /// task that handles raw IO.
+    async fn connect<R: Reporter>(
+        config: &LayerConfig,
+        connect_info: Option<AgentConnectInfo>,
+        analytics: &mut R,
+    ) -> Result<(Sender<ClientMessage>, Receiver<DaemonMessage>), AgentConnectionError> {
         let (agent_tx, agent_rx) = match connect_info {
             Some(AgentConnectInfo::Operator(session)) => {
                 let connection =
@@ -178,20 +222,105 @@ impl AgentConnection {
             }
         };
 
-        Ok(Self { agent_tx, agent_rx })
+        Ok((agent_tx, agent_rx))
     }
 
+    /// Creates a new agent connection to the given raw address.
+    ///
+    /// This connection is not re-established when it breaks.
     pub async fn new_for_raw_address(address: SocketAddr) -> Result<Self, AgentConnectionError> {
         let stream = TcpStream::connect(address).await?;
         let (agent_tx, agent_rx) = wrap_raw_connection(stream);
 
-        Ok(Self { agent_tx, agent_rx })
+        Ok(Self {
+            agent_tx,
+            agent_rx,
+            reconnect: ReconnectFlow::Break,
+        })
     }
 
     #[tracing::instrument(level = Level::TRACE, name = "send_agent_message", skip(self), ret)]
     async fn send(&self, msg: ClientMessage) -> Result<(), AgentChannelError> {
         self.agent_tx.send(msg).await.map_err(|_| AgentChannelError)
     }
+
+    /// Tries to establish a new connection with the agent, retrying using a [`Backoff`] until we
+    /// reach [`Self::RECONNECT_RETRIES`].
+    async fn connect_with_backoff(
+        config: &LayerConfig,
+        connect_info: &Option<AgentConnectInfo>,
+    ) -> Result<(Sender<ClientMessage>, Receiver<DaemonMessage>), AgentConnectionError> {
+        let mut backoffs = Backoff::new(
+            Self::RECONNECT_RETRIES,
+            Self::RECONNECT_MIN_BACKOFF,
+            Self::RECONNECT_MAX_BACKOFF,
+        )
+        .into_iter()
+        .flatten();
+
+        let mut reporter = NullReporter::default();
+
+        loop {
+            match Self::connect(config, connect_info.clone(), &mut reporter).await {
+                Ok(channels) => break Ok(channels),
+                Err(error) => match backoffs.next() {
+                    Some(backoff) => {
+                        tracing::warn!(%error, ?backoff, "failed to reconnect to the agent, retrying");
+                        time::sleep(backoff).await;
+                    }
+                    None => break Err(error),
+                },
+            }
+        }
+    }
+
+    /// Replaces the broken connection with a new one.
+    ///
+    /// Messages received from the [`MessageBus`] in the meantime are dropped.
+    /// Returns `false` if the [`MessageBus`] was closed.
+    #[tracing::instrument(level = Level::TRACE, skip_all, err)]
+    async fn reconnect(
+        &mut self,
+        message_bus: &mut MessageBus<Self>,
+    ) -> Result<bool, AgentChannelError> {
+        let ReconnectFlow::ConnectInfo {
+            config,
+            connect_info,
+        } = &self.reconnect
+        else {
+            return Err(AgentChannelError);
+        };
+
+        message_bus.send(ConnectionRefresh::Start).await;
+
+        let connect = Self::connect_with_backoff(config, connect_info);
+        tokio::pin!(connect);
+
+        let (agent_tx, agent_rx) = loop {
+            tokio::select! {
+                result = &mut connect => match result {
+                    Ok(channels) => break channels,
+                    Err(error) => {
+                        tracing::error!(%error, "failed to reconnect to the agent, giving up");
+                        return Err(AgentChannelError);
+                    }
+                },
+
+                msg = message_bus.recv() => match msg {
+                    None => return Ok(false),
+                    Some(msg) => tracing::trace!(?msg, "agent connection is down, dropping message"),
+                },
+            }
+        };
+
+        tracing::info!("reconnected to the agent");
+
+        self.agent_tx = agent_tx;
+        self.agent_rx = agent_rx;
+        message_bus.send(ConnectionRefresh::End).await;
+
+        Ok(true)
+    }
 }
 
 /// This error occurs when the [`AgentConnection`] fails to communicate with the inner
@@ -206,17 +335,29 @@ impl BackgroundTask for AgentConnection {
     type MessageOut = ProxyMessage;
 
     async fn run(mut self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        // Whether we're waiting for the first message sent to the new connection.
+        let mut awaiting_refresh = false;
+
         loop {
-            tokio::select! {
+            let connection_lost = tokio::select! {
                 msg = message_bus.recv() => match msg {
                     None => {
                         tracing::trace!("message bus closed, exiting");
                         break Ok(());
                     },
+                    Some(msg) if awaiting_refresh && !matches!(msg, ClientMessage::SwitchProtocolVersion(..)) => {
+                        tracing::trace!(?msg, "message meant for the previous connection, dropping");
+                        false
+                    }
                     Some(msg) => {
-                        if let Err(error) = self.send(msg).await {
-                            tracing::error!(%error, "failed to send message to the agent");
-                            break Err(error);
+                        awaiting_refresh = false;
+
+                        match self.send(msg).await {
+                            Ok(()) => false,
+                            Err(error) => {
+                                tracing::error!(%error, "failed to send message to the agent");
+                                true
+                            }
                         }
                     }
                 },
@@ -224,10 +365,22 @@ impl BackgroundTask for AgentConnection {
                 msg = self.agent_rx.recv() => match msg {
                     None => {
                         tracing::error!("failed to receive message from the agent, inner task down");
-                        break Err(AgentChannelError);
+                        true
+                    }
+                    Some(msg) => {
+                        message_bus.send(ProxyMessage::FromAgent(msg)).await;
+                        false
                     }
-                    Some(msg) => message_bus.send(ProxyMessage::FromAgent(msg)).await,
                 }
+            };
+
+            if connection_lost {
+                if !self.reconnect(message_bus).await? {
+                    tracing::trace!("message bus closed, exiting");
+                    break Ok(());
+                }
+
+                awaiting_refresh = true;
             }
         }
     }
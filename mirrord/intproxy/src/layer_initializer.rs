@@ -64,6 +64,10 @@ impl LayerInitializer {
                 None
             }
             LayerToProxyMessage::NewSession(NewSessionRequest::Forked(parent)) => Some(parent),
+            LayerToProxyMessage::NewSession(NewSessionRequest::FileWatchEvents(layer_id)) => {
+                info!(?layer_id, "new file watch events session");
+                None
+            }
             other => return Err(LayerInitializerError::UnexpectedMessage(other)),
         };
 
@@ -1,19 +1,24 @@
 #![feature(map_try_insert, let_chains)]
 #![warn(clippy::indexing_slicing)]
 
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use background_tasks::{BackgroundTasks, TaskSender, TaskUpdate};
 use layer_conn::LayerConnection;
 use layer_initializer::LayerInitializer;
-use main_tasks::{FromLayer, LayerForked, MainTaskId, ProxyMessage, ToLayer};
+use main_tasks::{ConnectionRefresh, FromLayer, LayerForked, MainTaskId, ProxyMessage, ToLayer};
 use mirrord_intproxy_protocol::{LayerId, LayerToProxyMessage, LocalMessage};
 use mirrord_protocol::{ClientMessage, DaemonMessage, LogLevel, CLIENT_READY_FOR_LOGS};
-use ping_pong::{AgentSentPong, PingPong};
+use ping_pong::{AgentSentPong, PingPong, PingPongMessage};
 use proxies::{
-    incoming::{IncomingProxy, IncomingProxyMessage},
+    incoming::{
+        header_rules::HttpHeaderRules, record::HttpRecorder, IncomingProxy, IncomingProxyMessage,
+    },
     outgoing::{OutgoingProxy, OutgoingProxyMessage},
-    simple::{SimpleProxy, SimpleProxyMessage},
+    simple::{DnsCache, FileCache, SimpleProxy, SimpleProxyMessage},
 };
 use tokio::{net::TcpListener, time};
 use tracing::Level;
@@ -54,6 +59,9 @@ pub struct IntProxy {
     any_connection_accepted: bool,
     background_tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
     task_txs: TaskTxs,
+    /// Main tasks that have not yet refreshed their state after the agent connection was
+    /// re-established. See [`ConnectionRefresh`].
+    refreshing_tasks: HashSet<MainTaskId>,
 }
 
 impl IntProxy {
@@ -65,7 +73,20 @@ impl IntProxy {
     /// Creates a new [`IntProxy`] using existing [`AgentConnection`].
     /// The returned instance will accept connections from the layers using the given
     /// [`TcpListener`].
-    pub fn new_with_connection(agent_conn: AgentConnection, listener: TcpListener) -> Self {
+    ///
+    /// Headers of the stolen HTTP traffic are rewritten with the given [`HttpHeaderRules`], and the
+    /// traffic is recorded with the given [`HttpRecorder`], if any.
+    ///
+    /// Read-only remote files are served from the given [`FileCache`], and remote DNS answers from
+    /// the given [`DnsCache`], if any.
+    pub fn new_with_connection(
+        agent_conn: AgentConnection,
+        listener: TcpListener,
+        http_header_rules: HttpHeaderRules,
+        http_recorder: Option<HttpRecorder>,
+        file_cache: Option<FileCache>,
+        dns_cache: Option<DnsCache>,
+    ) -> Self {
         let mut background_tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> =
             Default::default();
 
@@ -82,7 +103,7 @@ impl IntProxy {
             Self::CHANNEL_SIZE,
         );
         let simple = background_tasks.register(
-            SimpleProxy::default(),
+            SimpleProxy::new(file_cache, dns_cache),
             MainTaskId::SimpleProxy,
             Self::CHANNEL_SIZE,
         );
@@ -92,7 +113,7 @@ impl IntProxy {
             Self::CHANNEL_SIZE,
         );
         let incoming = background_tasks.register(
-            IncomingProxy::default(),
+            IncomingProxy::new(http_header_rules, http_recorder),
             MainTaskId::IncomingProxy,
             Self::CHANNEL_SIZE,
         );
@@ -109,6 +130,7 @@ impl IntProxy {
                 incoming,
                 ping_pong,
             },
+            refreshing_tasks: Default::default(),
         }
     }
 
@@ -158,9 +180,15 @@ impl IntProxy {
         Ok(())
     }
 
-    /// Routes a [`ProxyMessage`] to the correct background task.
-    /// [`ProxyMessage::NewLayer`] is handled here, as an exception.
-    async fn handle(&mut self, msg: ProxyMessage) -> Result<(), IntProxyError> {
+    /// Routes a [`ProxyMessage`] from the task with the given [`MainTaskId`] to the correct
+    /// background task.
+    /// [`ProxyMessage::NewLayer`] and [`ProxyMessage::ConnectionRefresh`] are handled here, as an
+    /// exception.
+    async fn handle(
+        &mut self,
+        task_id: MainTaskId,
+        msg: ProxyMessage,
+    ) -> Result<(), IntProxyError> {
         match msg {
             ProxyMessage::NewLayer(new_layer) => {
                 self.any_connection_accepted = true;
@@ -190,7 +218,13 @@ impl IntProxy {
             }
             ProxyMessage::FromAgent(msg) => self.handle_agent_message(msg).await?,
             ProxyMessage::FromLayer(msg) => self.handle_layer_message(msg).await?,
+            ProxyMessage::ToAgent(msg) if self.refreshing_tasks.contains(&task_id) => {
+                tracing::trace!(%task_id, ?msg, "message meant for the previous connection, dropping");
+            }
             ProxyMessage::ToAgent(msg) => self.task_txs.agent.send(msg).await,
+            ProxyMessage::ConnectionRefresh(refresh) => {
+                self.handle_connection_refresh(task_id, refresh).await
+            }
             ProxyMessage::ToLayer(msg) => {
                 let ToLayer {
                     message,
@@ -248,12 +282,66 @@ impl IntProxy {
                     return Err(IntProxyError::TaskPanic(task_id));
                 }
             },
-            (_, TaskUpdate::Message(msg)) => self.handle(msg).await?,
+            (task_id, TaskUpdate::Message(msg)) => self.handle(task_id, msg).await?,
         }
 
         Ok(())
     }
 
+    /// Handles a [`ConnectionRefresh`] notification from one of the main tasks.
+    ///
+    /// When the [`AgentConnection`] re-establishes the connection, we negotiate the protocol
+    /// version again and notify the proxies, so that they drop the state tied to the previous
+    /// connection.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    async fn handle_connection_refresh(&mut self, task_id: MainTaskId, refresh: ConnectionRefresh) {
+        match (task_id, refresh) {
+            (MainTaskId::AgentConnection, ConnectionRefresh::Start) => {
+                tracing::warn!("lost connection with the agent, reconnecting");
+
+                self.task_txs
+                    .ping_pong
+                    .send(PingPongMessage::ConnectionRefresh(refresh))
+                    .await;
+            }
+            (MainTaskId::AgentConnection, ConnectionRefresh::End) => {
+                self.task_txs
+                    .agent
+                    .send(ClientMessage::SwitchProtocolVersion(
+                        mirrord_protocol::VERSION.clone(),
+                    ))
+                    .await;
+
+                self.refreshing_tasks.extend([
+                    MainTaskId::SimpleProxy,
+                    MainTaskId::OutgoingProxy,
+                    MainTaskId::IncomingProxy,
+                ]);
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::ConnectionRefresh)
+                    .await;
+                self.task_txs
+                    .outgoing
+                    .send(OutgoingProxyMessage::ConnectionRefresh)
+                    .await;
+                self.task_txs
+                    .incoming
+                    .send(IncomingProxyMessage::ConnectionRefresh)
+                    .await;
+
+                self.task_txs
+                    .ping_pong
+                    .send(PingPongMessage::ConnectionRefresh(refresh))
+                    .await;
+            }
+            (task_id, _) => {
+                tracing::trace!(%task_id, "task refreshed its state");
+                self.refreshing_tasks.remove(&task_id);
+            }
+        }
+    }
+
     /// Routes most messages from the agent to the correct background task.
     /// Some messages are handled here.
     #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
@@ -297,6 +385,12 @@ impl IntProxy {
                     .send(IncomingProxyMessage::AgentSteal(msg))
                     .await
             }
+            DaemonMessage::UdpIncoming(msg) => {
+                self.task_txs
+                    .incoming
+                    .send(IncomingProxyMessage::AgentUdp(msg))
+                    .await
+            }
             DaemonMessage::SwitchProtocolVersionResponse(protocol_version) => {
                 if CLIENT_READY_FOR_LOGS.matches(&protocol_version) {
                     self.task_txs.agent.send(ClientMessage::ReadyForLogs).await;
@@ -324,6 +418,30 @@ impl IntProxy {
                     .send(SimpleProxyMessage::GetEnvRes(res))
                     .await
             }
+            DaemonMessage::FileWatchEvent(event) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::FileWatchEvent(event))
+                    .await
+            }
+            DaemonMessage::DnsQuery(res) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::DnsQueryRes(res))
+                    .await
+            }
+            DaemonMessage::ReverseLookup(res) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::ReverseLookupRes(res))
+                    .await
+            }
+            DaemonMessage::DnsCacheStats(stats) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::DnsCacheStatsRes(stats))
+                    .await
+            }
             other => {
                 return Err(IntProxyError::UnexpectedAgentMessage(other));
             }
@@ -376,6 +494,32 @@ impl IntProxy {
                     .send(SimpleProxyMessage::GetEnvReq(message_id, layer_id, req))
                     .await
             }
+            LayerToProxyMessage::FileWatch(req) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::FileWatchReq(message_id, layer_id, req))
+                    .await
+            }
+            LayerToProxyMessage::DnsQuery(req) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::DnsQueryReq(message_id, layer_id, req))
+                    .await
+            }
+            LayerToProxyMessage::ReverseLookup(req) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::ReverseLookupReq(
+                        message_id, layer_id, req,
+                    ))
+                    .await
+            }
+            LayerToProxyMessage::DnsCacheStats(..) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::DnsCacheStatsReq(message_id, layer_id))
+                    .await
+            }
             other => return Err(IntProxyError::UnexpectedLayerMessage(other)),
         }
 
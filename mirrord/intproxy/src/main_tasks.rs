@@ -18,6 +18,8 @@ pub enum ProxyMessage {
     FromLayer(FromLayer),
     /// New layer instance to serve.
     NewLayer(NewLayer),
+    /// Progress of re-establishing the agent connection.
+    ConnectionRefresh(ConnectionRefresh),
 }
 
 #[derive(Debug)]
@@ -72,6 +74,12 @@ impl From<NewLayer> for ProxyMessage {
     }
 }
 
+impl From<ConnectionRefresh> for ProxyMessage {
+    fn from(value: ConnectionRefresh) -> Self {
+        Self::ConnectionRefresh(value)
+    }
+}
+
 /// Enumerated ids of main [`BackgroundTask`](crate::background_tasks::BackgroundTask)s used by
 /// [`IntProxy`](crate::IntProxy).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -111,3 +119,25 @@ pub struct LayerForked {
 pub struct LayerClosed {
     pub id: LayerId,
 }
+
+/// Notification about the connection with the agent being re-established.
+///
+/// # Flow
+///
+/// 1. The [`AgentConnection`](crate::agent_conn::AgentConnection) loses the connection and sends
+///    [`ConnectionRefresh::Start`]. While reconnecting, it drops all messages meant for the agent.
+/// 2. The [`AgentConnection`](crate::agent_conn::AgentConnection) establishes a new connection and
+///    sends [`ConnectionRefresh::End`]. It keeps dropping messages meant for the agent until it
+///    receives [`ClientMessage::SwitchProtocolVersion`].
+/// 3. The [`IntProxy`](crate::IntProxy) sends [`ClientMessage::SwitchProtocolVersion`] and notifies
+///    the proxies. Each proxy drops the state tied to the previous connection and responds with
+///    [`ConnectionRefresh::End`]. Until then, its messages to the agent are considered stale and
+///    dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionRefresh {
+    /// Connection with the agent was lost.
+    Start,
+    /// Connection with the agent was re-established, or a background task finished refreshing its
+    /// state.
+    End,
+}
@@ -13,6 +13,7 @@ use tokio::time::{self, Interval, MissedTickBehavior};
 
 use crate::{
     background_tasks::{BackgroundTask, MessageBus},
+    main_tasks::ConnectionRefresh,
     ProxyMessage,
 };
 
@@ -31,6 +32,20 @@ pub enum PingPongError {
 /// from the agent.
 pub struct AgentSentPong;
 
+/// Messages consumed by [`PingPong`] running as a [`BackgroundTask`].
+pub enum PingPongMessage {
+    AgentSentPong(AgentSentPong),
+    /// The agent connection is being re-established. We should not expect pongs from the previous
+    /// connection, nor ping while there is no connection.
+    ConnectionRefresh(ConnectionRefresh),
+}
+
+impl From<AgentSentPong> for PingPongMessage {
+    fn from(value: AgentSentPong) -> Self {
+        Self::AgentSentPong(value)
+    }
+}
+
 /// Encapsulates logic of the ping pong mechanism on the proxy side.
 /// Run as a [`BackgroundTask`].
 pub struct PingPong {
@@ -38,6 +53,8 @@ pub struct PingPong {
     ticker: Interval,
     /// Whether this struct awaits for a pong from the agent.
     awaiting_pong: bool,
+    /// Whether the agent connection is being re-established.
+    refreshing: bool,
 }
 
 impl PingPong {
@@ -53,13 +70,14 @@ impl PingPong {
         Self {
             ticker,
             awaiting_pong: false,
+            refreshing: false,
         }
     }
 }
 
 impl BackgroundTask for PingPong {
     type Error = PingPongError;
-    type MessageIn = AgentSentPong;
+    type MessageIn = PingPongMessage;
     type MessageOut = ProxyMessage;
 
     /// Pings the agent with a frequency configured in [`PingPong::new`].
@@ -70,7 +88,9 @@ impl BackgroundTask for PingPong {
         loop {
             tokio::select! {
                 _ = self.ticker.tick() => {
-                    if self.awaiting_pong {
+                    if self.refreshing {
+                        tracing::trace!("agent connection is being refreshed, not sending ping");
+                    } else if self.awaiting_pong {
                         tracing::error!("pong timeout");
                         break Err(PingPongError::PongTimeout);
                     } else {
@@ -85,14 +105,20 @@ impl BackgroundTask for PingPong {
                         tracing::trace!("message bus closed, exiting");
                         break Ok(())
                     },
-                    (Some(AgentSentPong), true) => {
+                    (Some(PingPongMessage::AgentSentPong(AgentSentPong)), true) => {
                         tracing::trace!("agent responded to ping");
                         self.awaiting_pong = false;
                     },
-                    (Some(AgentSentPong), false) => {
+                    (Some(PingPongMessage::AgentSentPong(AgentSentPong)), false) => {
                         tracing::error!("agent sent an unexpected pong");
                         break Err(PingPongError::UnmatchedPong)
                     },
+                    (Some(PingPongMessage::ConnectionRefresh(refresh)), _) => {
+                        tracing::trace!(?refresh, "agent connection refresh");
+                        self.refreshing = refresh == ConnectionRefresh::Start;
+                        self.awaiting_pong = false;
+                        self.ticker.reset();
+                    },
                 },
             }
         }
@@ -23,6 +23,7 @@ use mirrord_protocol::{
         InternalHttpRequest, InternalHttpResponse, LayerTcpSteal, NewTcpConnection,
         ReceiverStreamBody, StreamingBody, TcpData,
     },
+    udp::DaemonUdpIncoming,
     ClientMessage, ConnectionId, RequestId, ResponseError,
 };
 use thiserror::Error;
@@ -34,20 +35,26 @@ use tokio_stream::{wrappers::ReceiverStream, StreamMap, StreamNotifyClose};
 use tracing::{debug, Level};
 
 use self::{
+    header_rules::HttpHeaderRules,
     interceptor::{Interceptor, InterceptorError, MessageOut},
     port_subscription_ext::PortSubscriptionExt,
+    record::HttpRecorder,
     subscriptions::SubscriptionsManager,
+    udp::UdpSubscriptions,
 };
 use crate::{
     background_tasks::{BackgroundTask, BackgroundTasks, MessageBus, TaskSender, TaskUpdate},
-    main_tasks::{LayerClosed, LayerForked, ToLayer},
+    main_tasks::{ConnectionRefresh, LayerClosed, LayerForked, ToLayer},
     ProxyMessage,
 };
 
+pub mod header_rules;
 mod http;
 mod interceptor;
 pub mod port_subscription_ext;
+pub mod record;
 mod subscriptions;
+mod udp;
 
 /// Creates and binds a new [`TcpSocket`].
 /// The socket has the same IP version and address as the given `addr`.
@@ -108,8 +115,11 @@ pub enum IncomingProxyMessage {
     LayerClosed(LayerClosed),
     AgentMirror(DaemonTcp),
     AgentSteal(DaemonTcp),
+    AgentUdp(DaemonUdpIncoming),
     /// Agent responded to [`ClientMessage::SwitchProtocolVersion`].
     AgentProtocolVersion(semver::Version),
+    /// Connection with the agent was re-established.
+    ConnectionRefresh,
 }
 
 /// Handle for an [`Interceptor`].
@@ -160,6 +170,11 @@ impl MetadataStore {
 ///
 /// Incoming connections are created by the agent either explicitly ([`NewTcpConnection`] message)
 /// or implicitly ([`HttpRequest`]).
+///
+/// Incoming UDP traffic is handled separately, with [`UdpSubscriptions`].
+///
+/// Headers of the stolen HTTP traffic are rewritten with [`HttpHeaderRules`], and the traffic can
+/// be recorded with an optional [`HttpRecorder`].
 #[derive(Default)]
 pub struct IncomingProxy {
     /// Active port subscriptions for all layers.
@@ -176,6 +191,12 @@ pub struct IncomingProxy {
     response_body_rxs: StreamMap<(ConnectionId, RequestId), StreamNotifyClose<ReceiverStreamBody>>,
     /// Version of [`mirrord_protocol`] negotiated with the agent.
     agent_protocol_version: Option<semver::Version>,
+    /// Active UDP port subscriptions for all layers.
+    udp_subscriptions: UdpSubscriptions,
+    /// Records stolen HTTP requests and responses, if enabled.
+    http_recorder: Option<HttpRecorder>,
+    /// Rewrites headers of stolen HTTP requests and responses.
+    http_header_rules: HttpHeaderRules,
 }
 
 impl IncomingProxy {
@@ -184,6 +205,34 @@ impl IncomingProxy {
     // TODO: Update outdated documentation. RawInterceptor, HttpInterceptor do not exist
     const CHANNEL_SIZE: usize = 512;
 
+    /// Creates a new instance that rewrites headers of stolen HTTP traffic with the given
+    /// [`HttpHeaderRules`] and records the traffic with the given [`HttpRecorder`].
+    pub fn new(http_header_rules: HttpHeaderRules, http_recorder: Option<HttpRecorder>) -> Self {
+        Self {
+            http_header_rules,
+            http_recorder,
+            ..Default::default()
+        }
+    }
+
+    /// Sends the given message to the agent. If it's an HTTP response, rewrites its headers and
+    /// records it first.
+    async fn send_to_agent(
+        &mut self,
+        mut message: ClientMessage,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        self.http_header_rules.rewrite_response(&mut message);
+
+        if let (Some(recorder), ClientMessage::TcpSteal(steal)) =
+            (self.http_recorder.as_mut(), &message)
+        {
+            recorder.handle_response(steal);
+        }
+
+        message_bus.send(message).await;
+    }
+
     /// Tries to register the new subscription in the [`SubscriptionsManager`].
     #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
     async fn handle_port_subscribe(
@@ -427,19 +476,72 @@ impl IncomingProxy {
         Ok(())
     }
 
+    /// Handles agent messages related to the incoming UDP traffic.
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
+    async fn handle_agent_udp_message(
+        &mut self,
+        message: DaemonUdpIncoming,
+        message_bus: &mut MessageBus<Self>,
+    ) -> Result<(), IncomingProxyError> {
+        match message {
+            DaemonUdpIncoming::SubscribeResult(port, result) => {
+                for msg in self.udp_subscriptions.agent_responded(port, result) {
+                    message_bus.send(msg).await;
+                }
+            }
+            DaemonUdpIncoming::Datagram(datagram) => {
+                self.udp_subscriptions.agent_datagram(datagram).await?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn handle_layer_fork(&mut self, msg: LayerForked) {
         let LayerForked { child, parent } = msg;
         self.subscriptions.layer_forked(parent, child);
+        self.udp_subscriptions.layer_forked(parent, child);
     }
 
     async fn handle_layer_close(&mut self, msg: LayerClosed, message_bus: &MessageBus<Self>) {
-        let msgs = self.subscriptions.layer_closed(msg.id);
+        let msgs = self
+            .subscriptions
+            .layer_closed(msg.id)
+            .into_iter()
+            .chain(self.udp_subscriptions.layer_closed(msg.id));
 
         for msg in msgs {
             message_bus.send(msg).await;
         }
     }
 
+    /// Drops the state tied to the previous agent connection and resumes all port subscriptions.
+    ///
+    /// Connections intercepted so far are closed, as the new agent does not know about them.
+    #[tracing::instrument(level = Level::TRACE, skip_all)]
+    async fn handle_connection_refresh(&mut self, message_bus: &mut MessageBus<Self>) {
+        message_bus.send(ConnectionRefresh::End).await;
+
+        self.interceptors.clear();
+        // New agent will reuse connection ids, so we can't wait for the old interceptors to exit.
+        self.background_tasks = Default::default();
+        self.metadata_store = Default::default();
+        self.request_body_txs.clear();
+        self.response_body_rxs = Default::default();
+        if let Some(recorder) = self.http_recorder.as_mut() {
+            recorder.clear_pending();
+        }
+
+        let msgs = self
+            .subscriptions
+            .agent_reconnected()
+            .into_iter()
+            .chain(self.udp_subscriptions.agent_reconnected());
+        for msg in msgs {
+            message_bus.send(msg).await;
+        }
+    }
+
     fn get_subscription(&self, interceptor_id: InterceptorId) -> Option<&PortSubscription> {
         self.interceptors
             .get(&interceptor_id)
@@ -465,20 +567,20 @@ impl BackgroundTask for IncomingProxy {
                             connection_id,
                             request_id,
                         });
-                        message_bus
-                            .send(ClientMessage::TcpSteal(LayerTcpSteal::HttpResponseChunked(
-                                res,
-                            )))
-                            .await;
+                        self.send_to_agent(
+                            ClientMessage::TcpSteal(LayerTcpSteal::HttpResponseChunked(res)),
+                            message_bus,
+                        )
+                        .await;
                     },
                     Some(Err(error)) => {
                         debug!(%error, "Error while reading streamed response body");
                         let res = ChunkedResponse::Error(ChunkedHttpError {connection_id, request_id});
-                        message_bus
-                            .send(ClientMessage::TcpSteal(LayerTcpSteal::HttpResponseChunked(
-                                res,
-                            )))
-                            .await;
+                        self.send_to_agent(
+                            ClientMessage::TcpSteal(LayerTcpSteal::HttpResponseChunked(res)),
+                            message_bus,
+                        )
+                        .await;
                         self.response_body_rxs.remove(&(connection_id, request_id));
                     },
                     None => {
@@ -488,11 +590,11 @@ impl BackgroundTask for IncomingProxy {
                             connection_id,
                             request_id,
                         });
-                        message_bus
-                            .send(ClientMessage::TcpSteal(LayerTcpSteal::HttpResponseChunked(
-                                res,
-                            )))
-                            .await;
+                        self.send_to_agent(
+                            ClientMessage::TcpSteal(LayerTcpSteal::HttpResponseChunked(res)),
+                            message_bus,
+                        )
+                        .await;
                         self.response_body_rxs.remove(&(connection_id, request_id));
                     }
                 },
@@ -509,18 +611,43 @@ impl BackgroundTask for IncomingProxy {
                             let res = self.metadata_store.get(req);
                             message_bus.send(ToLayer { message_id, layer_id, message: ProxyToLayerMessage::Incoming(IncomingResponse::ConnMetadata(res))  }).await;
                         }
+                        IncomingRequest::UdpPortSubscribe(subscribe) => {
+                            let msg = self.udp_subscriptions.layer_subscribed(layer_id, message_id, subscribe, self.agent_protocol_version.as_ref());
+                            if let Some(msg) = msg {
+                                message_bus.send(msg).await;
+                            }
+                        }
+                        IncomingRequest::UdpPortUnsubscribe(unsubscribe) => {
+                            if let Some(msg) = self.udp_subscriptions.layer_unsubscribed(layer_id, unsubscribe) {
+                                message_bus.send(msg).await;
+                            }
+                        }
                     },
                     Some(IncomingProxyMessage::AgentMirror(msg)) => {
                         self.handle_agent_message(msg, message_bus).await?;
                     }
-                    Some(IncomingProxyMessage::AgentSteal(msg)) => {
+                    Some(IncomingProxyMessage::AgentSteal(mut msg)) => {
+                        self.http_header_rules.rewrite_request(&mut msg);
+                        if let Some(recorder) = self.http_recorder.as_mut() {
+                            recorder.handle_request(&msg);
+                        }
                         self.handle_agent_message(msg, message_bus).await?;
                     }
+                    Some(IncomingProxyMessage::AgentUdp(msg)) => {
+                        self.handle_agent_udp_message(msg, message_bus).await?;
+                    }
                     Some(IncomingProxyMessage::LayerClosed(msg)) => self.handle_layer_close(msg, message_bus).await,
                     Some(IncomingProxyMessage::LayerForked(msg)) => self.handle_layer_fork(msg),
                     Some(IncomingProxyMessage::AgentProtocolVersion(version)) => {
                         self.agent_protocol_version.replace(version);
                     }
+                    Some(IncomingProxyMessage::ConnectionRefresh) => self.handle_connection_refresh(message_bus).await,
+                },
+
+                Some((id, update)) = self.udp_subscriptions.next() => {
+                    if let Some(msg) = self.udp_subscriptions.forwarder_update(id, update) {
+                        message_bus.send(msg).await;
+                    }
                 },
 
                 Some(task_update) = self.background_tasks.next() => match task_update {
@@ -561,7 +688,7 @@ impl BackgroundTask for IncomingProxy {
                                 }
                             }
                         };
-                        message_bus.send(msg).await;
+                        self.send_to_agent(msg, message_bus).await;
                     },
                 },
             }
@@ -0,0 +1,196 @@
+//! Rewriting headers of the stolen HTTP traffic in the [`IncomingProxy`](super::IncomingProxy).
+
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    HeaderMap,
+};
+use mirrord_config::feature::network::incoming::http::{HeaderRules, HttpConfig};
+use mirrord_protocol::{
+    tcp::{ChunkedRequest, ChunkedResponse, DaemonTcp, LayerTcpSteal},
+    ClientMessage,
+};
+use thiserror::Error;
+
+/// Header rule from the [`HttpConfig`] is not a valid header.
+#[derive(Error, Debug)]
+#[error("invalid HTTP header rule for `{0}`: {1}")]
+pub struct InvalidHeaderRule(String, hyper::http::Error);
+
+/// Parsed [`HeaderRules`].
+#[derive(Default, Debug)]
+struct HeaderRewrite {
+    remove: Vec<HeaderName>,
+    replace: Vec<(HeaderName, HeaderValue)>,
+    add: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl HeaderRewrite {
+    fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+
+        for (name, value) in &self.replace {
+            if headers.contains_key(name) {
+                headers.insert(name, value.clone());
+            }
+        }
+
+        for (name, value) in &self.add {
+            headers.append(name, value.clone());
+        }
+    }
+}
+
+impl TryFrom<&HeaderRules> for HeaderRewrite {
+    type Error = InvalidHeaderRule;
+
+    fn try_from(rules: &HeaderRules) -> Result<Self, Self::Error> {
+        let parse_name = |name: &String| {
+            HeaderName::try_from(name.as_str())
+                .map_err(|error| InvalidHeaderRule(name.clone(), error.into()))
+        };
+        let parse_header = |(name, value): (&String, &String)| {
+            let value = HeaderValue::try_from(value.as_str())
+                .map_err(|error| InvalidHeaderRule(name.clone(), error.into()))?;
+            Ok((parse_name(name)?, value))
+        };
+
+        Ok(Self {
+            remove: rules
+                .remove
+                .iter()
+                .map(parse_name)
+                .collect::<Result<_, _>>()?,
+            replace: rules
+                .replace
+                .iter()
+                .map(parse_header)
+                .collect::<Result<_, _>>()?,
+            add: rules
+                .add
+                .iter()
+                .map(parse_header)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Rewrites headers of the stolen HTTP requests and of the responses sent back to the agent,
+/// according to the [`HttpConfig`].
+///
+/// Works on the whole messages, so it handles all kinds of bodies (fallback, framed and chunked).
+#[derive(Default, Debug)]
+pub struct HttpHeaderRules {
+    request: HeaderRewrite,
+    response: HeaderRewrite,
+}
+
+impl HttpHeaderRules {
+    /// Rewrites the headers of the request in the given message received from the agent.
+    pub(crate) fn rewrite_request(&self, message: &mut DaemonTcp) {
+        let headers = match message {
+            DaemonTcp::HttpRequest(request) => &mut request.internal_request.headers,
+            DaemonTcp::HttpRequestFramed(request) => &mut request.internal_request.headers,
+            DaemonTcp::HttpRequestChunked(ChunkedRequest::Start(request)) => {
+                &mut request.internal_request.headers
+            }
+            _ => return,
+        };
+
+        self.request.apply(headers);
+    }
+
+    /// Rewrites the headers of the response in the given message sent to the agent.
+    pub(crate) fn rewrite_response(&self, message: &mut ClientMessage) {
+        let ClientMessage::TcpSteal(message) = message else {
+            return;
+        };
+
+        let headers = match message {
+            LayerTcpSteal::HttpResponse(response) => &mut response.internal_response.headers,
+            LayerTcpSteal::HttpResponseFramed(response) => &mut response.internal_response.headers,
+            LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Start(response)) => {
+                &mut response.internal_response.headers
+            }
+            _ => return,
+        };
+
+        self.response.apply(headers);
+    }
+}
+
+impl TryFrom<&HttpConfig> for HttpHeaderRules {
+    type Error = InvalidHeaderRule;
+
+    fn try_from(config: &HttpConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            request: (&config.request_headers).try_into()?,
+            response: (&config.response_headers).try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mirrord_protocol::tcp::{HttpRequest, InternalHttpRequest};
+
+    use super::*;
+
+    #[test]
+    fn rewrite_request_headers() {
+        let config: HttpConfig = serde_json::from_value(serde_json::json!({
+            "request_headers": {
+                "remove": ["x-debug"],
+                "replace": { "authorization": "Bearer dev", "cookie": "a=b" },
+                "add": { "x-mirrord": "yes", "accept": "text/html" }
+            }
+        }))
+        .unwrap();
+        let rules = HttpHeaderRules::try_from(&config).unwrap();
+
+        let mut message = DaemonTcp::HttpRequest(HttpRequest {
+            internal_request: InternalHttpRequest {
+                method: hyper::Method::GET,
+                uri: "/".parse().unwrap(),
+                headers: [
+                    ("x-debug", "1"),
+                    ("authorization", "Bearer prod"),
+                    ("accept", "application/json"),
+                ]
+                .into_iter()
+                .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+                .collect(),
+                version: hyper::Version::HTTP_11,
+                body: vec![],
+            },
+            connection_id: 0,
+            request_id: 0,
+            port: 80,
+        });
+        rules.rewrite_request(&mut message);
+
+        let DaemonTcp::HttpRequest(request) = message else {
+            unreachable!()
+        };
+        let headers = request.internal_request.headers;
+        assert!(!headers.contains_key("x-debug"));
+        assert!(!headers.contains_key("cookie"));
+        assert_eq!(headers.get("authorization").unwrap(), "Bearer dev");
+        assert_eq!(headers.get("x-mirrord").unwrap(), "yes");
+        assert_eq!(
+            headers.get_all("accept").iter().collect::<Vec<_>>(),
+            ["application/json", "text/html"]
+        );
+    }
+
+    #[test]
+    fn invalid_header_rule() {
+        let config: HttpConfig = serde_json::from_value(serde_json::json!({
+            "response_headers": { "add": { "bad header": "value" } }
+        }))
+        .unwrap();
+
+        assert!(HttpHeaderRules::try_from(&config).is_err());
+    }
+}
@@ -12,6 +12,7 @@ fn get_port(steal_type: &StealType) -> Port {
         StealType::All(port) => *port,
         StealType::FilteredHttp(port, _) => *port,
         StealType::FilteredHttpEx(port, _) => *port,
+        StealType::FilteredHttpPassthrough(port, _) => *port,
     }
 }
 
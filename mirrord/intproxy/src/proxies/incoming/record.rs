@@ -0,0 +1,714 @@
+//! Recording of the stolen HTTP traffic in the [`IncomingProxy`](super::IncomingProxy).
+//!
+//! Recorded request/response pairs are stored as [HAR](http://www.softwareishard.com/blog/har-12-spec/)
+//! entries, either in a complete HAR file or one entry per line (JSON Lines).
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{SecondsFormat, Utc};
+use futures::FutureExt;
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, StatusCode, Uri, Version};
+use mirrord_protocol::{
+    tcp::{
+        ChunkedRequest, ChunkedResponse, DaemonTcp, HttpRequest, HttpResponse, InternalHttpBody,
+        InternalHttpBodyFrame, LayerTcpSteal,
+    },
+    ConnectionId, Port, RequestId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::Level;
+
+/// Root object of a HAR file.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Har {
+    pub log: HarLog,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HarLog {
+    pub version: String,
+    pub creator: HarCreator,
+    pub entries: Vec<HarEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HarCreator {
+    pub name: String,
+    pub version: String,
+}
+
+/// Single recorded request/response pair.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarEntry {
+    /// When the request was received from the agent, in RFC 3339 format.
+    pub started_date_time: String,
+    /// Milliseconds between receiving the request and sending the whole response.
+    pub time: f64,
+    pub request: HarRequest,
+    pub response: HarResponse,
+    #[serde(default)]
+    pub cache: serde_json::Map<String, serde_json::Value>,
+    pub timings: HarTimings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarRequest {
+    pub method: String,
+    /// Uses `localhost` and the remote port that the request was stolen from.
+    pub url: String,
+    pub http_version: String,
+    pub headers: Vec<HarHeader>,
+    #[serde(default)]
+    pub query_string: Vec<HarHeader>,
+    #[serde(default)]
+    pub cookies: Vec<serde_json::Value>,
+    pub headers_size: i64,
+    pub body_size: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<HarContent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub http_version: String,
+    pub headers: Vec<HarHeader>,
+    #[serde(default)]
+    pub cookies: Vec<serde_json::Value>,
+    pub content: HarContent,
+    #[serde(rename = "redirectURL", default)]
+    pub redirect_url: String,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+/// Name/value pair, used both for headers and query parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HarHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// HTTP body, used both as `postData` in requests and `content` in responses.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarContent {
+    #[serde(default)]
+    pub size: i64,
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default)]
+    pub text: String,
+    /// `base64` if the body is not valid UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+}
+
+impl HarContent {
+    fn new(body: Vec<u8>, headers: &HeaderMap) -> Self {
+        let mime_type = headers
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let size = body.len() as i64;
+
+        match String::from_utf8(body) {
+            Ok(text) => Self {
+                size,
+                mime_type,
+                text,
+                encoding: None,
+            },
+            Err(error) => Self {
+                size,
+                mime_type,
+                text: STANDARD.encode(error.into_bytes()),
+                encoding: Some("base64".into()),
+            },
+        }
+    }
+
+    /// Returns the raw bytes of this body.
+    pub fn bytes(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        match self.encoding.as_deref() {
+            Some("base64") => STANDARD.decode(&self.text),
+            _ => Ok(self.text.clone().into_bytes()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HarTimings {
+    pub send: f64,
+    pub wait: f64,
+    pub receive: f64,
+}
+
+impl HarHeader {
+    fn from_map(headers: &HeaderMap) -> Vec<Self> {
+        headers
+            .iter()
+            .map(|(name, value)| Self {
+                name: name.to_string(),
+                value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            })
+            .collect()
+    }
+
+    fn from_query(uri: &Uri) -> Vec<Self> {
+        uri.query()
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (name, value) = param.split_once('=').unwrap_or((param, ""));
+                Self {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Head of a recorded request or response.
+struct RecordedHead<H> {
+    head: H,
+    version: Version,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    /// Whether the whole body was received.
+    complete: bool,
+}
+
+impl<H> RecordedHead<H> {
+    fn extend(&mut self, frames: &[InternalHttpBodyFrame]) {
+        for frame in frames {
+            if let InternalHttpBodyFrame::Data(data) = frame {
+                self.body.extend_from_slice(data);
+            }
+        }
+    }
+}
+
+/// Request/response pair that is not yet complete.
+struct PendingEntry {
+    started_at: chrono::DateTime<Utc>,
+    started: Instant,
+    port: Port,
+    request: RecordedHead<(hyper::Method, Uri)>,
+    response: Option<RecordedHead<StatusCode>>,
+}
+
+impl PendingEntry {
+    fn new<B>(request: &HttpRequest<B>, body: Vec<u8>, complete: bool) -> Self {
+        Self {
+            started_at: Utc::now(),
+            started: Instant::now(),
+            port: request.port,
+            request: RecordedHead {
+                head: (
+                    request.internal_request.method.clone(),
+                    request.internal_request.uri.clone(),
+                ),
+                version: request.internal_request.version,
+                headers: request.internal_request.headers.clone(),
+                body,
+                complete,
+            },
+            response: None,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.request.complete && self.response.as_ref().is_some_and(|res| res.complete)
+    }
+
+    fn into_har(self) -> Option<HarEntry> {
+        let response = self.response?;
+        let time = self.started.elapsed().as_secs_f64() * 1000.0;
+
+        let (method, uri) = self.request.head;
+        let path = uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+        let post_data = (!self.request.body.is_empty())
+            .then(|| HarContent::new(self.request.body, &self.request.headers));
+
+        let request = HarRequest {
+            method: method.to_string(),
+            url: format!("http://localhost:{}{path}", self.port),
+            http_version: format!("{:?}", self.request.version),
+            headers: HarHeader::from_map(&self.request.headers),
+            query_string: HarHeader::from_query(&uri),
+            cookies: Default::default(),
+            headers_size: -1,
+            body_size: post_data.as_ref().map(|data| data.size).unwrap_or_default(),
+            post_data,
+        };
+
+        let content = HarContent::new(response.body, &response.headers);
+        let response = HarResponse {
+            status: response.head.as_u16(),
+            status_text: response
+                .head
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_string(),
+            http_version: format!("{:?}", response.version),
+            headers: HarHeader::from_map(&response.headers),
+            cookies: Default::default(),
+            body_size: content.size,
+            content,
+            redirect_url: Default::default(),
+            headers_size: -1,
+        };
+
+        Some(HarEntry {
+            started_date_time: self.started_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            time,
+            request,
+            response,
+            cache: Default::default(),
+            timings: HarTimings {
+                send: 0.0,
+                wait: time,
+                receive: 0.0,
+            },
+        })
+    }
+}
+
+/// Format of the recording file.
+#[derive(Clone, Copy)]
+enum RecordFormat {
+    /// Complete HAR file, every new entry is inserted before [`RecordWriter::HAR_TRAILER`].
+    Har,
+    /// One [`HarEntry`] per line, appended with every new entry.
+    JsonLines,
+}
+
+/// Writes [`HarEntry`]s to the recording file.
+///
+/// Runs on a dedicated thread, so that the file operations don't block the
+/// [`IncomingProxy`](super::IncomingProxy). The file is valid after every written entry.
+struct RecordWriter {
+    path: PathBuf,
+    format: RecordFormat,
+    file: File,
+    /// How many entries were written to the file.
+    written: usize,
+}
+
+impl RecordWriter {
+    /// Closes the `entries` array and the root object of a HAR file.
+    const HAR_TRAILER: &'static [u8] = b"\n]}}\n";
+
+    /// Truncates the file at the given path and writes an empty recording.
+    fn create(path: PathBuf, format: RecordFormat) -> io::Result<Self> {
+        let mut file = File::create(&path)?;
+
+        if let RecordFormat::Har = format {
+            let creator = serde_json::to_string(&HarCreator {
+                name: "mirrord".into(),
+                version: env!("CARGO_PKG_VERSION").into(),
+            })?;
+            write!(
+                file,
+                r#"{{"log":{{"version":"1.2","creator":{creator},"entries":["#
+            )?;
+            file.write_all(Self::HAR_TRAILER)?;
+        }
+
+        Ok(Self {
+            path,
+            format,
+            file,
+            written: 0,
+        })
+    }
+
+    /// Writes entries until the [`HttpRecorder`] is dropped. Failing to write an entry is logged.
+    fn run(mut self, entries: mpsc::Receiver<HarEntry>) {
+        for entry in entries {
+            if let Err(error) = self.write(&entry) {
+                tracing::warn!(%error, path = %self.path.display(), "Failed to record HTTP traffic");
+            }
+        }
+    }
+
+    fn write(&mut self, entry: &HarEntry) -> io::Result<()> {
+        let mut bytes = vec![];
+
+        match self.format {
+            RecordFormat::Har => {
+                if self.written > 0 {
+                    bytes.push(b',');
+                }
+                bytes.push(b'\n');
+                serde_json::to_writer_pretty(&mut bytes, entry)?;
+                bytes.extend_from_slice(Self::HAR_TRAILER);
+
+                // Overwrites the trailer, the new content is always longer.
+                self.file
+                    .seek(SeekFrom::End(-(Self::HAR_TRAILER.len() as i64)))?;
+            }
+            RecordFormat::JsonLines => {
+                serde_json::to_writer(&mut bytes, entry)?;
+                bytes.push(b'\n');
+            }
+        }
+
+        self.file.write_all(&bytes)?;
+        self.written += 1;
+
+        Ok(())
+    }
+}
+
+/// Records stolen HTTP requests and the responses sent by the user application.
+///
+/// Inspects [`DaemonTcp`] messages received from the agent and [`LayerTcpSteal`] responses sent
+/// back to it, and passes a [`HarEntry`] to the [`RecordWriter`] once both the request and the
+/// response are complete. Failing to write the file is logged and does not break the traffic.
+///
+/// Dropping the recorder waits until all entries are written.
+pub struct HttpRecorder {
+    pending: HashMap<(ConnectionId, RequestId), PendingEntry>,
+    /// Dropped before joining [`Self::writer`], which ends the [`RecordWriter::run`] loop.
+    entries_tx: Option<mpsc::Sender<HarEntry>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl HttpRecorder {
+    /// Creates a new recorder, truncating the file at the given path.
+    ///
+    /// Files with the `.har` extension are written in the HAR format, other files in the JSON
+    /// Lines format.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let is_har = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("har"));
+        let format = if is_har {
+            RecordFormat::Har
+        } else {
+            RecordFormat::JsonLines
+        };
+
+        let writer = RecordWriter::create(path, format)?;
+        let (entries_tx, entries_rx) = mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("mirrord-http-recorder".into())
+            .spawn(move || writer.run(entries_rx))?;
+
+        Ok(Self {
+            pending: Default::default(),
+            entries_tx: Some(entries_tx),
+            writer: Some(writer),
+        })
+    }
+
+    /// Inspects a message received from the agent in the steal mode.
+    #[tracing::instrument(level = Level::TRACE, skip_all)]
+    pub(crate) fn handle_request(&mut self, message: &DaemonTcp) {
+        match message {
+            DaemonTcp::HttpRequest(request) => {
+                self.request_started(request, request.internal_request.body.clone(), true);
+            }
+            DaemonTcp::HttpRequestFramed(request) => {
+                let body = Self::collect_body(&request.internal_request.body);
+                self.request_started(request, body, true);
+            }
+            DaemonTcp::HttpRequestChunked(ChunkedRequest::Start(request)) => {
+                let mut entry = PendingEntry::new(request, vec![], false);
+                entry.request.extend(&request.internal_request.body);
+                self.pending
+                    .insert((request.connection_id, request.request_id), entry);
+            }
+            DaemonTcp::HttpRequestChunked(ChunkedRequest::Body(body)) => {
+                let key = (body.connection_id, body.request_id);
+                if let Some(entry) = self.pending.get_mut(&key) {
+                    entry.request.extend(&body.frames);
+                    entry.request.complete |= body.is_last;
+                }
+                self.flush(key);
+            }
+            DaemonTcp::HttpRequestChunked(ChunkedRequest::Error(error)) => {
+                self.pending
+                    .remove(&(error.connection_id, error.request_id));
+            }
+            DaemonTcp::Close(close) => {
+                self.pending
+                    .retain(|(connection_id, _), _| *connection_id != close.connection_id);
+            }
+            DaemonTcp::NewConnection(..) | DaemonTcp::Data(..) | DaemonTcp::SubscribeResult(..) => {
+            }
+        }
+    }
+
+    /// Inspects a message sent to the agent in the steal mode.
+    #[tracing::instrument(level = Level::TRACE, skip_all)]
+    pub(crate) fn handle_response(&mut self, message: &LayerTcpSteal) {
+        match message {
+            LayerTcpSteal::HttpResponse(response) => {
+                self.response_started(response, response.internal_response.body.clone(), true);
+            }
+            LayerTcpSteal::HttpResponseFramed(response) => {
+                let body = Self::collect_body(&response.internal_response.body);
+                self.response_started(response, body, true);
+            }
+            LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Start(response)) => {
+                let key = (response.connection_id, response.request_id);
+                self.response_started(response, vec![], false);
+                if let Some(response_head) = self
+                    .pending
+                    .get_mut(&key)
+                    .and_then(|entry| entry.response.as_mut())
+                {
+                    response_head.extend(&response.internal_response.body);
+                }
+            }
+            LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Body(body)) => {
+                let key = (body.connection_id, body.request_id);
+                if let Some(response_head) = self
+                    .pending
+                    .get_mut(&key)
+                    .and_then(|entry| entry.response.as_mut())
+                {
+                    response_head.extend(&body.frames);
+                    response_head.complete |= body.is_last;
+                }
+                self.flush(key);
+            }
+            LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Error(error)) => {
+                self.pending
+                    .remove(&(error.connection_id, error.request_id));
+            }
+            LayerTcpSteal::PortSubscribe(..)
+            | LayerTcpSteal::ConnectionUnsubscribe(..)
+            | LayerTcpSteal::PortUnsubscribe(..)
+            | LayerTcpSteal::Data(..) => {}
+        }
+    }
+
+    /// Drops all incomplete entries, used when the agent connection is re-established.
+    pub(crate) fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    fn request_started<B>(&mut self, request: &HttpRequest<B>, body: Vec<u8>, complete: bool) {
+        self.pending.insert(
+            (request.connection_id, request.request_id),
+            PendingEntry::new(request, body, complete),
+        );
+    }
+
+    fn response_started<B>(&mut self, response: &HttpResponse<B>, body: Vec<u8>, complete: bool) {
+        let key = (response.connection_id, response.request_id);
+        let Some(entry) = self.pending.get_mut(&key) else {
+            return;
+        };
+
+        entry.response = Some(RecordedHead {
+            head: response.internal_response.status,
+            version: response.internal_response.version,
+            headers: response.internal_response.headers.clone(),
+            body,
+            complete,
+        });
+
+        self.flush(key);
+    }
+
+    /// [`InternalHttpBody`] is always ready, so we can collect it synchronously.
+    fn collect_body(body: &InternalHttpBody) -> Vec<u8> {
+        body.clone()
+            .collect()
+            .now_or_never()
+            .and_then(Result::ok)
+            .map(|collected| collected.to_bytes().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Passes the entry with the given key to the [`RecordWriter`] if it's complete.
+    fn flush(&mut self, key: (ConnectionId, RequestId)) {
+        if !self
+            .pending
+            .get(&key)
+            .is_some_and(PendingEntry::is_complete)
+        {
+            return;
+        }
+
+        let Some(entry) = self.pending.remove(&key).and_then(PendingEntry::into_har) else {
+            return;
+        };
+
+        if let Some(entries_tx) = self.entries_tx.as_ref()
+            && entries_tx.send(entry).is_err()
+        {
+            tracing::warn!("HTTP recording writer is gone, dropping an entry");
+        }
+    }
+}
+
+impl Drop for HttpRecorder {
+    fn drop(&mut self) {
+        self.entries_tx.take();
+
+        if let Some(writer) = self.writer.take()
+            && writer.join().is_err()
+        {
+            tracing::error!("HTTP recording writer panicked");
+        }
+    }
+}
+
+/// Reads entries recorded by [`HttpRecorder`] from the given file, either in HAR or JSON Lines
+/// format.
+pub fn read_recording<P: AsRef<Path>>(path: P) -> io::Result<Vec<HarEntry>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    if let Ok(har) = serde_json::from_str::<Har>(&contents) {
+        return Ok(har.log.entries);
+    }
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use mirrord_protocol::tcp::{ChunkedHttpBody, InternalHttpRequest, InternalHttpResponse};
+
+    use super::*;
+
+    fn request(request_id: RequestId) -> HttpRequest<Vec<u8>> {
+        HttpRequest {
+            internal_request: InternalHttpRequest {
+                method: hyper::Method::POST,
+                uri: "/api/items?id=1&full".parse().unwrap(),
+                headers: [(hyper::header::HOST, "example.com".parse().unwrap())]
+                    .into_iter()
+                    .collect(),
+                version: Version::HTTP_11,
+                body: b"hello".to_vec(),
+            },
+            connection_id: 0,
+            request_id,
+            port: 80,
+        }
+    }
+
+    fn response_start(request_id: RequestId) -> HttpResponse<Vec<InternalHttpBodyFrame>> {
+        HttpResponse {
+            port: 80,
+            connection_id: 0,
+            request_id,
+            internal_response: InternalHttpResponse {
+                status: StatusCode::CREATED,
+                version: Version::HTTP_11,
+                headers: Default::default(),
+                body: vec![InternalHttpBodyFrame::Data(vec![0xff])],
+            },
+        }
+    }
+
+    #[test]
+    fn records_chunked_response_as_json_lines() {
+        let path =
+            std::env::temp_dir().join(format!("mirrord-record-{}.jsonl", std::process::id()));
+        let mut recorder = HttpRecorder::new(&path).unwrap();
+
+        recorder.handle_request(&DaemonTcp::HttpRequest(request(0)));
+        recorder.handle_request(&DaemonTcp::HttpRequest(request(1)));
+        recorder.handle_response(&LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Start(
+            response_start(0),
+        )));
+        assert!(read_recording(&path).unwrap().is_empty());
+
+        recorder.handle_response(&LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Body(
+            ChunkedHttpBody {
+                frames: vec![InternalHttpBodyFrame::Data(vec![0xfe])],
+                is_last: true,
+                connection_id: 0,
+                request_id: 0,
+            },
+        )));
+        // Request 1 is dropped together with its connection.
+        recorder.handle_request(&DaemonTcp::Close(mirrord_protocol::tcp::TcpClose {
+            connection_id: 0,
+        }));
+        recorder.handle_response(&LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Start(
+            response_start(1),
+        )));
+        drop(recorder);
+
+        let entries = read_recording(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        let entry = entries.first().unwrap();
+        assert_eq!(entry.request.url, "http://localhost:80/api/items?id=1&full");
+        assert_eq!(entry.request.query_string.len(), 2);
+        assert_eq!(
+            entry.request.post_data.as_ref().unwrap().bytes().unwrap(),
+            b"hello"
+        );
+        assert_eq!(entry.response.status, 201);
+        assert_eq!(entry.response.content.encoding.as_deref(), Some("base64"));
+        assert_eq!(entry.response.content.bytes().unwrap(), [0xff, 0xfe]);
+    }
+
+    #[test]
+    fn records_framed_response_as_har() {
+        let path = std::env::temp_dir().join(format!("mirrord-record-{}.har", std::process::id()));
+        let mut recorder = HttpRecorder::new(&path).unwrap();
+        assert!(read_recording(&path).unwrap().is_empty());
+
+        for request_id in 0..2 {
+            recorder.handle_request(&DaemonTcp::HttpRequest(request(request_id)));
+            let response = response_start(request_id);
+            recorder.handle_response(&LayerTcpSteal::HttpResponseFramed(HttpResponse {
+                port: response.port,
+                connection_id: response.connection_id,
+                request_id: response.request_id,
+                internal_response: response
+                    .internal_response
+                    .map_body(|_| InternalHttpBody::from_bytes(b"created")),
+            }));
+        }
+        drop(recorder);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let har = serde_json::from_str::<Har>(&contents).unwrap();
+        assert_eq!(har.log.version, "1.2");
+        assert_eq!(har.log.entries.len(), 2);
+        for entry in har.log.entries {
+            assert_eq!(entry.response.content.text, "created");
+            assert!(entry.response.content.encoding.is_none());
+        }
+    }
+}
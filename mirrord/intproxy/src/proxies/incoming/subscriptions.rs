@@ -96,9 +96,9 @@ impl Subscription {
     /// Rejects this subscription with the given `reason`.
     /// Returns messages to be sent to the layers.
     /// Returns [`Err`] if this subscription was already confirmed.
-    fn reject(self, reason: ResponseError) -> Result<Vec<ToLayer>, Self> {
+    fn reject(self, reason: ResponseError) -> Result<Vec<ToLayer>, Box<Self>> {
         if self.confirmed {
-            return Err(self);
+            return Err(Box::new(self));
         }
 
         let responses = self
@@ -250,7 +250,7 @@ impl SubscriptionsManager {
                 match subscription.reject(ResponseError::PortAlreadyStolen(port)) {
                     Ok(responses) => Ok(responses),
                     Err(subscription) => {
-                        self.subscriptions.insert(port, subscription);
+                        self.subscriptions.insert(port, *subscription);
                         Ok(vec![])
                     }
                 }
@@ -294,6 +294,21 @@ impl SubscriptionsManager {
             .collect()
     }
 
+    /// Notifies this struct about the agent connection being re-established.
+    /// Returns messages to be sent to the new agent, so that it resumes all subscriptions.
+    pub fn agent_reconnected(&self) -> Vec<ClientMessage> {
+        self.subscriptions
+            .values()
+            .map(|subscription| {
+                subscription
+                    .active_source
+                    .request
+                    .subscription
+                    .agent_subscribe()
+            })
+            .collect()
+    }
+
     /// Notifies this struct about layer forking.
     pub fn layer_forked(&mut self, parent: LayerId, child: LayerId) {
         self.remote_ports.clone_all(parent, child);
@@ -453,6 +468,38 @@ mod test {
         assert!(manager.get(80).is_none());
     }
 
+    #[test]
+    fn with_reconnect() {
+        let listening_on = "127.0.0.1:1111".parse().unwrap();
+
+        let mut manager = SubscriptionsManager::default();
+
+        manager.layer_subscribed(
+            LayerId(0),
+            0,
+            PortSubscribe {
+                listening_on,
+                subscription: PortSubscription::Mirror(80),
+            },
+        );
+        let responses = manager.agent_responded(Ok(80)).unwrap();
+        assert_eq!(responses.len(), 1, "{responses:?}");
+
+        let messages = manager.agent_reconnected();
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [ClientMessage::Tcp(LayerTcp::PortSubscribe(80))]
+            ),
+            "{messages:?}"
+        );
+
+        // Subscription was already confirmed to the layer.
+        let responses = manager.agent_responded(Ok(80)).unwrap();
+        assert!(responses.is_empty(), "{responses:?}");
+        assert_eq!(manager.get(80).unwrap().listening_on, listening_on);
+    }
+
     #[test]
     fn with_double_response() {
         let listening_on = "127.0.0.1:1111".parse().unwrap();
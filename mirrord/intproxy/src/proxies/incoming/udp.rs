@@ -0,0 +1,534 @@
+//! Handles the incoming UDP traffic in the [`IncomingProxy`](super::IncomingProxy).
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use mirrord_intproxy_protocol::{
+    IncomingResponse, LayerId, MessageId, ProxyToLayerMessage, UdpPortSubscribe, UdpPortUnsubscribe,
+};
+use mirrord_protocol::{
+    udp::{IncomingDatagram, LayerUdpIncoming, UdpIncomingMode, UdpReply, UDP_INCOMING_VERSION},
+    ClientMessage, Port, RemoteResult, ResponseError,
+};
+use tokio::net::UdpSocket;
+use tracing::Level;
+
+use crate::{
+    background_tasks::{BackgroundTask, BackgroundTasks, MessageBus, TaskSender, TaskUpdate},
+    main_tasks::{ProxyMessage, ToLayer},
+};
+
+/// Id of a single [`UdpForwarder`] task. Used to manage forwarder tasks with the
+/// [`BackgroundTasks`] struct.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct UdpForwarderId {
+    /// Makes the id unique, a new forwarder for the same peer can be created before we learn that
+    /// the previous one finished.
+    id: u64,
+    /// Remote port that the datagrams were sent to.
+    port: Port,
+    /// Remote peer that sent the datagrams.
+    peer: SocketAddr,
+}
+
+impl fmt::Display for UdpForwarderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "incoming UDP forwarder {} ({} -> port {})",
+            self.id, self.peer, self.port
+        )
+    }
+}
+
+/// Forwards the datagrams sent by a single remote peer to the layer's UDP socket, and passes back
+/// the datagrams that the socket sends in reply.
+///
+/// Each remote peer gets a separate forwarder, so that the user application can reply with a
+/// simple `sendto` to the source address of the received datagram.
+///
+/// Exits after [`UdpForwarder::IDLE_TIMEOUT`] without any traffic.
+pub struct UdpForwarder {
+    /// Socket connected to the layer's socket.
+    socket: UdpSocket,
+}
+
+impl UdpForwarder {
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Creates a new forwarder with a socket connected to the given `bound_on` address.
+    ///
+    /// The socket is bound to the same IP address, or to localhost if the address is unspecified.
+    #[tracing::instrument(level = Level::TRACE, err)]
+    async fn new(bound_on: SocketAddr) -> io::Result<Self> {
+        let ip = match bound_on.ip() {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED) => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED) => Ipv6Addr::LOCALHOST.into(),
+            ip => ip,
+        };
+
+        let socket = UdpSocket::bind(SocketAddr::new(ip, 0)).await?;
+        socket.connect(SocketAddr::new(ip, bound_on.port())).await?;
+
+        Ok(Self { socket })
+    }
+}
+
+impl BackgroundTask for UdpForwarder {
+    type Error = io::Error;
+    type MessageIn = Vec<u8>;
+    type MessageOut = Vec<u8>;
+
+    async fn run(self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        let mut buffer = vec![0; u16::MAX as usize];
+
+        loop {
+            tokio::select! {
+                message = message_bus.recv() => match message {
+                    None => break Ok(()),
+                    Some(bytes) => match self.socket.send(&bytes).await {
+                        Ok(..) => {}
+                        // The layer's socket is not there (yet or anymore), datagram is lost.
+                        Err(error) if error.kind() == io::ErrorKind::ConnectionRefused => {
+                            tracing::trace!(%error, "Failed to forward a datagram to the layer");
+                        }
+                        Err(error) => break Err(error),
+                    },
+                },
+
+                result = self.socket.recv(&mut buffer) => match result {
+                    Ok(read) => {
+                        message_bus.send(buffer.get(..read).unwrap_or_default().to_vec()).await;
+                    }
+                    Err(error) if error.kind() == io::ErrorKind::ConnectionRefused => {
+                        tracing::trace!(%error, "Failed to forward a datagram to the layer");
+                    }
+                    Err(error) => break Err(error),
+                },
+
+                _ = tokio::time::sleep(Self::IDLE_TIMEOUT) => break Ok(()),
+            }
+        }
+    }
+}
+
+/// Represents a UDP port subscription in the agent.
+#[derive(Debug)]
+struct UdpSubscription {
+    /// Latest request from the layer.
+    request: UdpPortSubscribe,
+    /// Layers that own the subscribed socket.
+    owners: HashSet<LayerId>,
+    /// Requests waiting for the agent's confirmation.
+    pending: Vec<(LayerId, MessageId)>,
+    /// Whether this subscription is confirmed.
+    confirmed: bool,
+}
+
+/// Manages the UDP port subscriptions of all layers, and the [`UdpForwarder`]s that handle the
+/// incoming datagrams.
+///
+/// Unlike TCP subscriptions, at most one local socket receives the traffic from a remote port.
+/// Subscribing a port that is already subscribed switches the traffic to the new socket.
+#[derive(Default)]
+pub struct UdpSubscriptions {
+    /// Active subscriptions, by the remote port.
+    subscriptions: HashMap<Port, UdpSubscription>,
+    /// [`TaskSender`]s for active [`UdpForwarder`]s, by the remote port and peer.
+    forwarders: HashMap<(Port, SocketAddr), (UdpForwarderId, TaskSender<UdpForwarder>)>,
+    /// For receiving updates from [`UdpForwarder`]s.
+    tasks: BackgroundTasks<UdpForwarderId, Vec<u8>, io::Error>,
+    /// Used to make [`UdpForwarderId`]s unique.
+    next_forwarder_id: u64,
+}
+
+impl UdpSubscriptions {
+    /// Size of [`mpsc`](tokio::sync::mpsc) channels used to communicate with [`UdpForwarder`]s.
+    const CHANNEL_SIZE: usize = 512;
+
+    /// Registers the subscription of the layer.
+    ///
+    /// Returns a message to be sent either to the agent or to the layer.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub fn layer_subscribed(
+        &mut self,
+        layer_id: LayerId,
+        message_id: MessageId,
+        request: UdpPortSubscribe,
+        agent_protocol_version: Option<&semver::Version>,
+    ) -> Option<ProxyMessage> {
+        let supported =
+            agent_protocol_version.is_some_and(|version| UDP_INCOMING_VERSION.matches(version));
+        if !supported {
+            return Some(
+                Self::response(layer_id, message_id, Err(ResponseError::NotImplemented)).into(),
+            );
+        }
+
+        let port = request.subscription.port;
+
+        match self.subscriptions.get_mut(&port) {
+            Some(subscription) => {
+                if subscription.request.bound_on != request.bound_on {
+                    self.forwarders
+                        .retain(|(forwarded_port, _), _| *forwarded_port != port);
+                }
+
+                subscription.request = request;
+                subscription.owners.insert(layer_id);
+
+                if subscription.confirmed {
+                    Some(Self::response(layer_id, message_id, Ok(())).into())
+                } else {
+                    subscription.pending.push((layer_id, message_id));
+                    None
+                }
+            }
+
+            None => {
+                let message = Self::agent_subscribe(&request);
+
+                self.subscriptions.insert(
+                    port,
+                    UdpSubscription {
+                        request,
+                        owners: HashSet::from([layer_id]),
+                        pending: vec![(layer_id, message_id)],
+                        confirmed: false,
+                    },
+                );
+
+                Some(message.into())
+            }
+        }
+    }
+
+    /// Removes the subscription, if the layer's socket is still the one receiving the traffic.
+    ///
+    /// Returns a message to be sent to the agent.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub fn layer_unsubscribed(
+        &mut self,
+        layer_id: LayerId,
+        request: UdpPortUnsubscribe,
+    ) -> Option<ClientMessage> {
+        let subscription = self.subscriptions.get(&request.port)?;
+        if subscription.request.bound_on != request.bound_on
+            || !subscription.owners.contains(&layer_id)
+        {
+            return None;
+        }
+
+        self.remove(request.port)
+    }
+
+    /// Handles the agent's response to [`LayerUdpIncoming::PortSubscribe`].
+    ///
+    /// Returns messages to be sent to the layers.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub fn agent_responded(&mut self, port: Port, result: RemoteResult<()>) -> Vec<ToLayer> {
+        let Some(subscription) = self.subscriptions.get_mut(&port) else {
+            return vec![];
+        };
+
+        let responses = subscription
+            .pending
+            .drain(..)
+            .map(|(layer_id, message_id)| Self::response(layer_id, message_id, result.clone()))
+            .collect();
+
+        match result {
+            Ok(()) => subscription.confirmed = true,
+            Err(error) => {
+                tracing::warn!(%error, port, "Agent failed to subscribe to an incoming UDP port");
+                self.subscriptions.remove(&port);
+                self.forwarders
+                    .retain(|(forwarded_port, _), _| *forwarded_port != port);
+            }
+        }
+
+        responses
+    }
+
+    /// Passes the datagram to the [`UdpForwarder`] of its source, creating the forwarder if needed.
+    #[tracing::instrument(level = Level::TRACE, skip(self), err)]
+    pub async fn agent_datagram(&mut self, datagram: IncomingDatagram) -> io::Result<()> {
+        let port = datagram.destination.port();
+        let Some(subscription) = self.subscriptions.get(&port) else {
+            tracing::trace!("Received a datagram for port {port} that is no longer subscribed");
+            return Ok(());
+        };
+
+        let key = (port, datagram.source);
+        let tx = match self.forwarders.get(&key) {
+            Some((_, tx)) => tx,
+            None => {
+                let forwarder = UdpForwarder::new(subscription.request.bound_on).await?;
+                let id = UdpForwarderId {
+                    id: self.next_forwarder_id,
+                    port,
+                    peer: datagram.source,
+                };
+                self.next_forwarder_id += 1;
+
+                let tx = self.tasks.register(forwarder, id, Self::CHANNEL_SIZE);
+                &self.forwarders.entry(key).or_insert((id, tx)).1
+            }
+        };
+
+        tx.send(datagram.bytes).await;
+
+        Ok(())
+    }
+
+    /// Returns the next update from one of the [`UdpForwarder`]s.
+    pub async fn next(&mut self) -> Option<(UdpForwarderId, TaskUpdate<Vec<u8>, io::Error>)> {
+        self.tasks.next().await
+    }
+
+    /// Handles an update from one of the [`UdpForwarder`]s.
+    ///
+    /// Returns a message to be sent to the agent.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub fn forwarder_update(
+        &mut self,
+        id: UdpForwarderId,
+        update: TaskUpdate<Vec<u8>, io::Error>,
+    ) -> Option<ClientMessage> {
+        let key = (id.port, id.peer);
+        let is_current = self
+            .forwarders
+            .get(&key)
+            .is_some_and(|(current_id, _)| *current_id == id);
+
+        match update {
+            TaskUpdate::Finished(result) => {
+                tracing::trace!("{id} finished: {result:?}");
+                if is_current {
+                    self.forwarders.remove(&key);
+                }
+
+                None
+            }
+
+            TaskUpdate::Message(bytes) => {
+                let steal = self
+                    .subscriptions
+                    .get(&id.port)
+                    .is_some_and(|subscription| {
+                        subscription.request.subscription.mode == UdpIncomingMode::Steal
+                    });
+
+                (is_current && steal).then(|| {
+                    ClientMessage::UdpIncoming(LayerUdpIncoming::Reply(UdpReply {
+                        port: id.port,
+                        peer: id.peer,
+                        bytes,
+                    }))
+                })
+            }
+        }
+    }
+
+    /// Gives the child layer ownership over the parent's subscriptions.
+    pub fn layer_forked(&mut self, parent: LayerId, child: LayerId) {
+        for subscription in self.subscriptions.values_mut() {
+            if subscription.owners.contains(&parent) {
+                subscription.owners.insert(child);
+            }
+        }
+    }
+
+    /// Removes the subscriptions that are no longer owned by any layer.
+    ///
+    /// Returns messages to be sent to the agent.
+    pub fn layer_closed(&mut self, layer_id: LayerId) -> Vec<ClientMessage> {
+        let ports = self
+            .subscriptions
+            .iter_mut()
+            .filter_map(|(port, subscription)| {
+                subscription
+                    .pending
+                    .retain(|(pending_layer, _)| *pending_layer != layer_id);
+                (subscription.owners.remove(&layer_id) && subscription.owners.is_empty())
+                    .then_some(*port)
+            })
+            .collect::<Vec<_>>();
+
+        ports
+            .into_iter()
+            .filter_map(|port| self.remove(port))
+            .collect()
+    }
+
+    /// Drops the forwarders tied to the previous agent connection.
+    ///
+    /// Returns messages to be sent to the agent, in order to resume all subscriptions.
+    pub fn agent_reconnected(&mut self) -> Vec<ClientMessage> {
+        self.forwarders.clear();
+        self.tasks = Default::default();
+
+        self.subscriptions
+            .values()
+            .map(|subscription| Self::agent_subscribe(&subscription.request))
+            .collect()
+    }
+
+    /// Removes the subscription and its forwarders.
+    ///
+    /// Returns a message to be sent to the agent.
+    fn remove(&mut self, port: Port) -> Option<ClientMessage> {
+        self.subscriptions.remove(&port)?;
+        self.forwarders
+            .retain(|(forwarded_port, _), _| *forwarded_port != port);
+
+        Some(ClientMessage::UdpIncoming(
+            LayerUdpIncoming::PortUnsubscribe(port),
+        ))
+    }
+
+    fn agent_subscribe(request: &UdpPortSubscribe) -> ClientMessage {
+        ClientMessage::UdpIncoming(LayerUdpIncoming::PortSubscribe(request.subscription))
+    }
+
+    fn response(layer_id: LayerId, message_id: MessageId, result: RemoteResult<()>) -> ToLayer {
+        ToLayer {
+            message_id,
+            layer_id,
+            message: ProxyToLayerMessage::Incoming(IncomingResponse::UdpPortSubscribe(result)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn subscribe(port: Port, bound_on: &str, mode: UdpIncomingMode) -> UdpPortSubscribe {
+        UdpPortSubscribe {
+            bound_on: bound_on.parse().unwrap(),
+            subscription: mirrord_protocol::udp::UdpPortSubscription { port, mode },
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_forward_reply() {
+        let version = "1.14.0".parse().unwrap();
+        let mut subscriptions = UdpSubscriptions::default();
+
+        let local = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bound_on = local.local_addr().unwrap();
+
+        let message = subscriptions.layer_subscribed(
+            LayerId(0),
+            0,
+            subscribe(53, &bound_on.to_string(), UdpIncomingMode::Steal),
+            Some(&version),
+        );
+        assert!(matches!(
+            message,
+            Some(ProxyMessage::ToAgent(ClientMessage::UdpIncoming(
+                LayerUdpIncoming::PortSubscribe(..)
+            )))
+        ));
+
+        let responses = subscriptions.agent_responded(53, Ok(()));
+        assert_eq!(responses.len(), 1);
+
+        let peer: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        subscriptions
+            .agent_datagram(IncomingDatagram {
+                source: peer,
+                destination: "10.0.0.2:53".parse().unwrap(),
+                bytes: b"hello".to_vec(),
+            })
+            .await
+            .unwrap();
+
+        let mut buffer = [0; 16];
+        let (read, forwarder) = local.recv_from(&mut buffer).await.unwrap();
+        assert_eq!(buffer.get(..read), Some(b"hello".as_slice()));
+
+        local.send_to(b"hi", forwarder).await.unwrap();
+        let (id, update) = subscriptions.next().await.unwrap();
+        let message = subscriptions.forwarder_update(id, update);
+        assert_eq!(
+            message,
+            Some(ClientMessage::UdpIncoming(LayerUdpIncoming::Reply(
+                UdpReply {
+                    port: 53,
+                    peer,
+                    bytes: b"hi".to_vec(),
+                }
+            )))
+        );
+
+        assert_eq!(
+            subscriptions.layer_closed(LayerId(0)),
+            vec![ClientMessage::UdpIncoming(
+                LayerUdpIncoming::PortUnsubscribe(53)
+            )],
+        );
+    }
+
+    #[test]
+    fn agent_not_supported() {
+        let version = "1.13.0".parse().unwrap();
+        let mut subscriptions = UdpSubscriptions::default();
+
+        let message = subscriptions.layer_subscribed(
+            LayerId(0),
+            0,
+            subscribe(53, "127.0.0.1:5353", UdpIncomingMode::Mirror),
+            Some(&version),
+        );
+        assert!(matches!(
+            message,
+            Some(ProxyMessage::ToLayer(ToLayer {
+                message: ProxyToLayerMessage::Incoming(IncomingResponse::UdpPortSubscribe(Err(
+                    ResponseError::NotImplemented
+                ))),
+                ..
+            }))
+        ));
+        assert!(subscriptions.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn ownership() {
+        let version = "1.14.0".parse().unwrap();
+        let mut subscriptions = UdpSubscriptions::default();
+
+        subscriptions.layer_subscribed(
+            LayerId(0),
+            0,
+            subscribe(53, "127.0.0.1:5353", UdpIncomingMode::Mirror),
+            Some(&version),
+        );
+        subscriptions.layer_forked(LayerId(0), LayerId(1));
+        assert_eq!(subscriptions.agent_responded(53, Ok(())).len(), 1);
+
+        // Socket was bound somewhere else in the meantime.
+        assert!(subscriptions
+            .layer_unsubscribed(
+                LayerId(1),
+                UdpPortUnsubscribe {
+                    port: 53,
+                    bound_on: "127.0.0.1:5354".parse().unwrap(),
+                },
+            )
+            .is_none());
+
+        assert!(subscriptions.layer_closed(LayerId(0)).is_empty());
+        assert_eq!(
+            subscriptions.layer_closed(LayerId(1)),
+            vec![ClientMessage::UdpIncoming(
+                LayerUdpIncoming::PortUnsubscribe(53)
+            )],
+        );
+    }
+}
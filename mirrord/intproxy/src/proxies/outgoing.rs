@@ -16,7 +16,7 @@ use tracing::Level;
 use self::interceptor::Interceptor;
 use crate::{
     background_tasks::{BackgroundTask, BackgroundTasks, MessageBus, TaskSender, TaskUpdate},
-    main_tasks::ToLayer,
+    main_tasks::{ConnectionRefresh, ToLayer},
     proxies::outgoing::net_protocol_ext::NetProtocolExt,
     request_queue::{RequestQueue, RequestQueueEmpty},
     ProxyMessage,
@@ -208,6 +208,36 @@ impl OutgoingProxy {
         let msg = request.protocol.wrap_agent_connect(request.remote_address);
         message_bus.send(ProxyMessage::ToAgent(msg)).await;
     }
+
+    /// Drops the state tied to the previous agent connection.
+    ///
+    /// Pending connection requests are failed, and all intercepted connections are closed.
+    #[tracing::instrument(level = Level::TRACE, skip_all)]
+    async fn handle_connection_refresh(&mut self, message_bus: &mut MessageBus<Self>) {
+        message_bus.send(ConnectionRefresh::End).await;
+
+        self.txs.clear();
+        // New agent will reuse connection ids, so we can't wait for the old interceptors to exit.
+        self.background_tasks = Default::default();
+
+        let pending = self
+            .datagrams_reqs
+            .drain()
+            .chain(self.stream_reqs.drain())
+            .collect::<Vec<_>>();
+
+        for (message_id, layer_id, ()) in pending {
+            message_bus
+                .send(ToLayer {
+                    message: ProxyToLayerMessage::OutgoingConnect(Err(
+                        ResponseError::AgentReconnected,
+                    )),
+                    message_id,
+                    layer_id,
+                })
+                .await;
+        }
+    }
 }
 
 /// Messages consumed by the [`OutgoingProxy`] running as a [`BackgroundTask`].
@@ -215,6 +245,7 @@ pub enum OutgoingProxyMessage {
     AgentStream(DaemonTcpOutgoing),
     AgentDatagrams(DaemonUdpOutgoing),
     LayerConnect(OutgoingConnectRequest, MessageId, LayerId),
+    ConnectionRefresh,
 }
 
 impl BackgroundTask for OutgoingProxy {
@@ -252,6 +283,7 @@ impl BackgroundTask for OutgoingProxy {
                         req,
                         message_bus
                     ).await,
+                    Some(OutgoingProxyMessage::ConnectionRefresh) => self.handle_connection_refresh(message_bus).await,
                 },
 
                 Some(task_update) = self.background_tasks.next() => match task_update {
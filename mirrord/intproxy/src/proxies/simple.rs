@@ -1,40 +1,69 @@
 //! The most basic proxying logic. Handles cases when the only job to do in the internal proxy is to
 //! pass requests and responses between the layer and the agent.
 
-use std::{collections::HashMap, vec::IntoIter};
+use std::{collections::HashMap, io, path::PathBuf, sync::Arc, vec::IntoIter};
 
-use mirrord_intproxy_protocol::{LayerId, MessageId, ProxyToLayerMessage};
+use mirrord_intproxy_protocol::{
+    DnsCacheStatsResponse, FileWatchRequest, LayerId, MessageId, ProxyToLayerMessage,
+    WatchSubscribe,
+};
 use mirrord_protocol::{
-    dns::{GetAddrInfoRequest, GetAddrInfoResponse},
+    dns::{
+        DnsCacheStats, DnsQueryRequest, DnsQueryResponse, GetAddrInfoRequest, GetAddrInfoRequestV2,
+        GetAddrInfoResponse, ReverseLookupRequest, ReverseLookupResponse, ADDRINFO_V2_VERSION,
+        DNS_CACHE_STATS_VERSION, DNS_QUERY_VERSION, REVERSE_LOOKUP_VERSION,
+    },
     file::{
-        CloseDirRequest, CloseFileRequest, DirEntryInternal, OpenDirResponse, OpenFileResponse,
+        AddWatchResponse, CloseDirRequest, CloseFileRequest, DirEntryInternal, FdOpenDirRequest,
+        FileWatchEvent, MetadataInternal, OpenDirResponse, OpenFileRequest, OpenFileResponse,
         ReadDirBatchRequest, ReadDirBatchResponse, ReadDirRequest, ReadDirResponse,
-        READDIR_BATCH_VERSION,
+        ReadFileRequest, ReadFileResponse, ReadLimitedFileRequest, RemoveWatchRequest,
+        SeekFileRequest, SeekFileResponse, SeekFromInternal, XstatRequest, XstatResponse,
+        FS_MUTATIONS_VERSION, READDIR_BATCH_VERSION, STATFS_V2_VERSION, STATFS_VERSION,
+        WATCH_VERSION,
     },
     ClientMessage, FileRequest, FileResponse, GetEnvVarsRequest, RemoteResult, ResponseError,
 };
-use semver::Version;
+use semver::{Version, VersionReq};
 use thiserror::Error;
 
+use self::file_watch::FileWatches;
+pub use self::{dns_cache::DnsCache, file_cache::FileCache};
 use crate::{
     background_tasks::{BackgroundTask, MessageBus},
-    main_tasks::{LayerClosed, LayerForked, ToLayer},
+    main_tasks::{ConnectionRefresh, LayerClosed, LayerForked, ToLayer},
     remote_resources::RemoteResources,
     request_queue::{RequestQueue, RequestQueueEmpty},
     ProxyMessage,
 };
 
+mod dns_cache;
+mod file_cache;
+mod file_watch;
+
+/// Size of the chunks in which the content of a remote file is read into the [`FileCache`].
+const CACHE_READ_SIZE: u64 = 1024 * 1024;
+
 #[derive(Debug)]
 pub enum SimpleProxyMessage {
     FileReq(MessageId, LayerId, FileRequest),
     FileRes(FileResponse),
-    AddrInfoReq(MessageId, LayerId, GetAddrInfoRequest),
+    AddrInfoReq(MessageId, LayerId, GetAddrInfoRequestV2),
     AddrInfoRes(GetAddrInfoResponse),
     LayerForked(LayerForked),
     LayerClosed(LayerClosed),
     GetEnvReq(MessageId, LayerId, GetEnvVarsRequest),
     GetEnvRes(RemoteResult<HashMap<String, String>>),
     ProtocolVersion(Version),
+    ConnectionRefresh,
+    FileWatchReq(MessageId, LayerId, FileWatchRequest),
+    FileWatchEvent(FileWatchEvent),
+    DnsQueryReq(MessageId, LayerId, DnsQueryRequest),
+    DnsQueryRes(DnsQueryResponse),
+    ReverseLookupReq(MessageId, LayerId, ReverseLookupRequest),
+    ReverseLookupRes(ReverseLookupResponse),
+    DnsCacheStatsReq(MessageId, LayerId),
+    DnsCacheStatsRes(DnsCacheStats),
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -49,6 +78,12 @@ pub(crate) enum FileResource {
     Dir {
         dirs_iter: IntoIter<DirEntryInternal>,
     },
+    /// File served from the [`FileCache`], unknown to the agent.
+    Cached {
+        content: Arc<[u8]>,
+        metadata: MetadataInternal,
+        position: u64,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -58,6 +93,9 @@ enum FileError {
 
     #[error("Dir operation called on file `{0}`!")]
     DirOnFile(u64),
+
+    #[error("Resource `{0}` belongs to a previous agent connection!")]
+    StaleResource(u64),
 }
 
 impl From<FileError> for ResponseError {
@@ -65,6 +103,7 @@ impl From<FileError> for ResponseError {
         match file_fail {
             FileError::MissingResource(remote_fd) => ResponseError::NotFound(remote_fd),
             FileError::DirOnFile(remote_fd) => ResponseError::NotDirectory(remote_fd),
+            FileError::StaleResource(remote_fd) => ResponseError::StaleResource(remote_fd),
         }
     }
 }
@@ -73,26 +112,221 @@ impl FileResource {
     fn next_dir(&mut self, remote_fd: u64) -> Result<Option<DirEntryInternal>, FileError> {
         match self {
             FileResource::Dir { dirs_iter } => dirs_iter.next().map(Ok).transpose(),
-            FileResource::File => Err(FileError::DirOnFile(remote_fd)),
+            FileResource::File | FileResource::Cached { .. } => {
+                Err(FileError::DirOnFile(remote_fd))
+            }
+        }
+    }
+}
+
+/// Builds an error [`FileResponse`] of the kind that the layer expects for some [`FileRequest`].
+type FileErrorResponse = fn(ResponseError) -> FileResponse;
+
+/// Returns a [`FileErrorResponse`] for the given [`FileRequest`], or [`None`] if the request does
+/// not get a response.
+fn file_error_response(request: &FileRequest) -> Option<FileErrorResponse> {
+    let error_response: FileErrorResponse = match request {
+        FileRequest::Open(..) | FileRequest::OpenRelative(..) => {
+            |error| FileResponse::Open(Err(error))
+        }
+        FileRequest::Read(..) => |error| FileResponse::Read(Err(error)),
+        FileRequest::ReadLimited(..) => |error| FileResponse::ReadLimited(Err(error)),
+        FileRequest::Seek(..) => |error| FileResponse::Seek(Err(error)),
+        FileRequest::Write(..) => |error| FileResponse::Write(Err(error)),
+        FileRequest::WriteLimited(..) => |error| FileResponse::WriteLimited(Err(error)),
+        FileRequest::Access(..) => |error| FileResponse::Access(Err(error)),
+        FileRequest::Xstat(..) => |error| FileResponse::Xstat(Err(error)),
+        FileRequest::XstatFs(..) | FileRequest::StatFs(..) => {
+            |error| FileResponse::XstatFs(Err(error))
+        }
+        FileRequest::XstatFsV2(..) | FileRequest::StatFsV2(..) => {
+            |error| FileResponse::XstatFsV2(Err(error))
+        }
+        FileRequest::FdOpenDir(..) => |error| FileResponse::OpenDir(Err(error)),
+        // `ReadDirBatch` is only sent by this proxy when the layer asks for `ReadDir`.
+        FileRequest::ReadDir(..) | FileRequest::ReadDirBatch(..) => {
+            |error| FileResponse::ReadDir(Err(error))
+        }
+        FileRequest::GetDEnts64(..) => |error| FileResponse::GetDEnts64(Err(error)),
+        FileRequest::ReadLink(..) => |error| FileResponse::ReadLink(Err(error)),
+        FileRequest::MakeDir(..) => |error| FileResponse::MakeDir(Err(error)),
+        FileRequest::RemoveDir(..) => |error| FileResponse::RemoveDir(Err(error)),
+        FileRequest::Unlink(..) => |error| FileResponse::Unlink(Err(error)),
+        FileRequest::Rename(..) => |error| FileResponse::Rename(Err(error)),
+        FileRequest::Chmod(..) | FileRequest::Fchmod(..) => |error| FileResponse::Chmod(Err(error)),
+        FileRequest::Chown(..) => |error| FileResponse::Chown(Err(error)),
+        FileRequest::Truncate(..) | FileRequest::Ftruncate(..) => {
+            |error| FileResponse::Truncate(Err(error))
         }
+        FileRequest::Symlink(..) => |error| FileResponse::Symlink(Err(error)),
+        FileRequest::Utimes(..) => |error| FileResponse::Utimes(Err(error)),
+        FileRequest::AddWatch(..) => |error| FileResponse::AddWatch(Err(error)),
+        FileRequest::Close(..) | FileRequest::CloseDir(..) | FileRequest::RemoveWatch(..) => {
+            return None
+        }
+    };
+
+    Some(error_response)
+}
+
+/// What to do with the agent's response to a [`FileRequest`].
+enum PendingFileRequest {
+    /// Pass the response to the layer. Holds the [`FileErrorResponse`] used when the request
+    /// fails in the internal proxy, if the request gets a response.
+    Layer(Option<FileErrorResponse>),
+    /// The layer stats a cacheable path, pass the response and cache the metadata.
+    Xstat(PathBuf),
+    /// The layer opens a cacheable directory, pass the response and start collecting the listing.
+    OpenDir(PathBuf),
+    /// The layer opens a cacheable file for reading. If we manage to cache its content, the layer
+    /// gets a descriptor served from the [`FileCache`].
+    Open(PathBuf),
+    /// Internal stat of the file opened for [`PendingFileRequest::Open`], the layer still waits
+    /// for the [`FileResponse::Open`].
+    OpenXstat { path: PathBuf, fd: u64 },
+    /// Internal read of the file opened for [`PendingFileRequest::Open`], the layer still waits
+    /// for the [`FileResponse::Open`].
+    OpenRead {
+        path: PathBuf,
+        fd: u64,
+        metadata: MetadataInternal,
+        bytes: Vec<u8>,
+    },
+}
+
+impl PendingFileRequest {
+    /// Returns the [`FileErrorResponse`] for the request that the layer waits for, if any.
+    fn error_response(&self) -> Option<FileErrorResponse> {
+        match self {
+            Self::Layer(error_response) => *error_response,
+            Self::Xstat(..) => Some(|error| FileResponse::Xstat(Err(error))),
+            Self::OpenDir(..) => Some(|error| FileResponse::OpenDir(Err(error))),
+            Self::Open(..) | Self::OpenXstat { .. } | Self::OpenRead { .. } => {
+                Some(|error| FileResponse::Open(Err(error)))
+            }
+        }
+    }
+}
+
+/// Returns the remote descriptor used by the given [`FileRequest`], if any.
+fn request_fd_mut(request: &mut FileRequest) -> Option<&mut u64> {
+    match request {
+        FileRequest::OpenRelative(req) => Some(&mut req.relative_fd),
+        FileRequest::Read(req) => Some(&mut req.remote_fd),
+        FileRequest::ReadLimited(req) => Some(&mut req.remote_fd),
+        FileRequest::Seek(req) => Some(&mut req.fd),
+        FileRequest::Write(req) => Some(&mut req.fd),
+        FileRequest::WriteLimited(req) => Some(&mut req.remote_fd),
+        FileRequest::Close(req) => Some(&mut req.fd),
+        FileRequest::Xstat(req) => req.fd.as_mut(),
+        FileRequest::XstatFs(req) => Some(&mut req.fd),
+        FileRequest::XstatFsV2(req) => Some(&mut req.fd),
+        FileRequest::FdOpenDir(req) => Some(&mut req.remote_fd),
+        FileRequest::ReadDir(req) => Some(&mut req.remote_fd),
+        FileRequest::CloseDir(req) => Some(&mut req.remote_fd),
+        FileRequest::GetDEnts64(req) => Some(&mut req.remote_fd),
+        FileRequest::ReadDirBatch(req) => Some(&mut req.remote_fd),
+        FileRequest::Fchmod(req) => Some(&mut req.fd),
+        FileRequest::Ftruncate(req) => Some(&mut req.fd),
+        FileRequest::Open(..)
+        | FileRequest::Access(..)
+        | FileRequest::ReadLink(..)
+        | FileRequest::MakeDir(..)
+        | FileRequest::RemoveDir(..)
+        | FileRequest::Unlink(..)
+        | FileRequest::Rename(..)
+        | FileRequest::Chmod(..)
+        | FileRequest::Chown(..)
+        | FileRequest::Truncate(..)
+        | FileRequest::Symlink(..)
+        | FileRequest::Utimes(..)
+        | FileRequest::AddWatch(..)
+        | FileRequest::RemoveWatch(..)
+        | FileRequest::StatFs(..)
+        | FileRequest::StatFsV2(..) => None,
+    }
+}
+
+/// Returns the minimal protocol version that the agent must match to handle the given
+/// [`FileRequest`], e.g. [`FS_MUTATIONS_VERSION`] for the requests that modify the remote file
+/// system.
+fn required_version(request: &FileRequest) -> Option<&'static VersionReq> {
+    match request {
+        FileRequest::MakeDir(..)
+        | FileRequest::RemoveDir(..)
+        | FileRequest::Unlink(..)
+        | FileRequest::Rename(..)
+        | FileRequest::Chmod(..)
+        | FileRequest::Fchmod(..)
+        | FileRequest::Chown(..)
+        | FileRequest::Truncate(..)
+        | FileRequest::Ftruncate(..)
+        | FileRequest::Symlink(..)
+        | FileRequest::Utimes(..) => Some(&FS_MUTATIONS_VERSION),
+        FileRequest::StatFs(..) => Some(&STATFS_VERSION),
+        FileRequest::XstatFsV2(..) | FileRequest::StatFsV2(..) => Some(&STATFS_V2_VERSION),
+        _ => None,
     }
 }
 
 /// For passing messages between the layer and the agent without custom internal logic.
 /// Run as a [`BackgroundTask`].
+///
+/// # Remote descriptors
+///
+/// When the agent connection is re-established, the new agent knows nothing about the files and
+/// directories opened so far, and starts allocating descriptors from scratch. To keep the
+/// descriptors unique from the layer's point of view, the ones returned by the current agent are
+/// shifted by [`SimpleProxy::fd_offset`]. Descriptors below the offset are stale, and operations
+/// on them fail with [`ResponseError::StaleResource`].
+///
+/// # Cache
+///
+/// With a [`FileCache`], the read-only remote files can be served without reaching the agent.
+/// Such files and directories get descriptors that the agent knows nothing about, see
+/// [`FileResource::Cached`].
 #[derive(Default)]
 pub struct SimpleProxy {
     /// Remote descriptors for open files and directories. Allows tracking across layer forks.
     remote_fds: RemoteResources<RemoteFd, FileResource>,
     /// For [`FileRequest`]s.
-    file_reqs: RequestQueue,
-    /// For [`GetAddrInfoRequest`]s.
-    addr_info_reqs: RequestQueue,
+    file_reqs: RequestQueue<PendingFileRequest>,
+    /// For [`GetAddrInfoRequestV2`]s, kept for the [`DnsCache`].
+    addr_info_reqs: RequestQueue<GetAddrInfoRequestV2>,
+    /// For [`DnsQueryRequest`]s, kept for the [`DnsCache`].
+    dns_query_reqs: RequestQueue<DnsQueryRequest>,
+    /// For [`ReverseLookupRequest`]s, kept for the [`DnsCache`].
+    reverse_lookup_reqs: RequestQueue<ReverseLookupRequest>,
     /// For [`GetEnvVarsRequest`]s.
     get_env_reqs: RequestQueue,
+    /// For the [`ClientMessage::GetDnsCacheStats`] sent on behalf of the layer.
+    dns_cache_stats_reqs: RequestQueue,
+    /// For [`WatchSubscribe`]s, answered with [`FileResponse::AddWatch`].
+    watch_reqs: RequestQueue<WatchSubscribe>,
+    /// Difference between the remote descriptors seen by the layer and the ones used by the
+    /// current agent.
+    fd_offset: u64,
+    /// Lowest remote descriptor not yet seen by the layer.
+    next_fd_offset: u64,
+    /// Cache of the read-only remote files, if enabled.
+    file_cache: Option<FileCache>,
+    /// Cache of the remote DNS answers, if enabled.
+    dns_cache: Option<DnsCache>,
+    /// Remote file watches of the inotify instances emulated by the layer.
+    file_watches: FileWatches,
 }
 
 impl SimpleProxy {
+    /// Creates a new proxy, which serves the read-only remote files from the given [`FileCache`],
+    /// and the remote DNS answers from the given [`DnsCache`], if any.
+    pub fn new(file_cache: Option<FileCache>, dns_cache: Option<DnsCache>) -> Self {
+        Self {
+            file_cache,
+            dns_cache,
+            ..Default::default()
+        }
+    }
+
     /// `readdir` works by keeping an iterator of all the `dir`s, and a call to it is
     /// equivalent to doing `iterator.next()`.
     ///
@@ -107,6 +341,10 @@ impl SimpleProxy {
         protocol_version: Option<&Version>,
         message_bus: &mut MessageBus<SimpleProxy>,
     ) -> Result<(), FileError> {
+        let agent_fd = self
+            .agent_fd(remote_fd)
+            .ok_or(FileError::StaleResource(remote_fd))?;
+
         let resource = self
             .remote_fds
             .get_mut(&layer_id, &RemoteFd::Dir(remote_fd))
@@ -124,17 +362,33 @@ impl SimpleProxy {
                     layer_id,
                 })
                 .await;
+        } else if FileCache::is_cached_fd(remote_fd) {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
+                        ReadDirResponse { direntry: None },
+                    ))),
+                    layer_id,
+                })
+                .await;
         } else {
-            self.file_reqs.insert(message_id, layer_id);
+            self.file_reqs.insert_with(
+                message_id,
+                layer_id,
+                PendingFileRequest::Layer(Some(|error| FileResponse::ReadDir(Err(error)))),
+            );
 
             let request =
                 if protocol_version.is_some_and(|version| READDIR_BATCH_VERSION.matches(version)) {
                     FileRequest::ReadDirBatch(ReadDirBatchRequest {
-                        remote_fd,
+                        remote_fd: agent_fd,
                         amount: 128,
                     })
                 } else {
-                    FileRequest::ReadDir(ReadDirRequest { remote_fd })
+                    FileRequest::ReadDir(ReadDirRequest {
+                        remote_fd: agent_fd,
+                    })
                 };
 
             // Convert it into a `ReadDirBatch` for the agent.
@@ -145,366 +399,2049 @@ impl SimpleProxy {
 
         Ok(())
     }
-}
 
-impl BackgroundTask for SimpleProxy {
-    type Error = RequestQueueEmpty;
-    type MessageIn = SimpleProxyMessage;
-    type MessageOut = ProxyMessage;
+    /// Combines the counters of our [`DnsCache`], if any, with the ones of the agent.
+    fn dns_cache_stats(&self, agent: Option<DnsCacheStats>) -> DnsCacheStatsResponse {
+        DnsCacheStatsResponse {
+            proxy: self.dns_cache.as_ref().map(DnsCache::stats),
+            agent,
+        }
+    }
 
-    async fn run(mut self, message_bus: &mut MessageBus<Self>) -> Result<(), RequestQueueEmpty> {
-        let mut protocol_version = None;
+    /// Translates the remote descriptor seen by the layer into the one used by the current agent.
+    /// Returns [`None`] if the descriptor belongs to a previous agent connection.
+    fn agent_fd(&self, fd: u64) -> Option<u64> {
+        fd.checked_sub(self.fd_offset)
+    }
 
-        while let Some(msg) = message_bus.recv().await {
-            tracing::trace!(?msg, "new message in message_bus");
+    /// Translates the remote descriptor used by the current agent into the one seen by the layer.
+    fn layer_fd(&mut self, fd: u64) -> u64 {
+        let fd = fd + self.fd_offset;
+        self.next_fd_offset = self.next_fd_offset.max(fd + 1);
+        fd
+    }
 
-            match msg {
-                SimpleProxyMessage::ProtocolVersion(new_protocol_version) => {
-                    protocol_version = Some(new_protocol_version);
-                }
-                SimpleProxyMessage::FileReq(
-                    _,
+    /// Responds to the given [`FileRequest`] from the [`FileCache`], if possible.
+    fn cached_response(
+        &mut self,
+        layer_id: LayerId,
+        request: &FileRequest,
+    ) -> Option<FileResponse> {
+        let file_cache = self.file_cache.as_mut()?;
+
+        match request {
+            FileRequest::Open(OpenFileRequest { path, open_options })
+                if open_options.is_read_only() && file_cache.is_cacheable(path) =>
+            {
+                let metadata = *file_cache.metadata(path)?;
+                let content = file_cache.content(path, &metadata)?;
+                let fd = file_cache.next_fd();
+
+                self.remote_fds.add(
                     layer_id,
-                    FileRequest::Close(CloseFileRequest { fd }),
-                ) => {
-                    let do_close = self.remote_fds.remove(layer_id, RemoteFd::File(fd));
-                    if do_close {
-                        message_bus
-                            .send(ClientMessage::FileRequest(FileRequest::Close(
-                                CloseFileRequest { fd },
-                            )))
-                            .await;
-                    }
-                }
-                SimpleProxyMessage::FileReq(
-                    _,
+                    RemoteFd::File(fd),
+                    FileResource::Cached {
+                        content,
+                        metadata,
+                        position: 0,
+                    },
+                );
+
+                Some(FileResponse::Open(Ok(OpenFileResponse { fd })))
+            }
+            FileRequest::Xstat(XstatRequest {
+                path: Some(path),
+                fd: None,
+                follow_symlink: true,
+            }) if file_cache.is_cacheable(path) => {
+                let metadata = *file_cache.metadata(path)?;
+                Some(FileResponse::Xstat(Ok(XstatResponse { metadata })))
+            }
+            FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd })
+                if !FileCache::is_cached_fd(*remote_fd) =>
+            {
+                let dir_entries = file_cache.dir_entries(file_cache.open_path(*remote_fd)?)?;
+                let fd = file_cache.next_fd();
+
+                self.remote_fds.add(
                     layer_id,
-                    FileRequest::CloseDir(CloseDirRequest { remote_fd }),
-                ) => {
-                    let do_close = self.remote_fds.remove(layer_id, RemoteFd::Dir(remote_fd));
-                    if do_close {
-                        message_bus
-                            .send(ClientMessage::FileRequest(FileRequest::CloseDir(
-                                CloseDirRequest { remote_fd },
-                            )))
-                            .await;
-                    }
+                    RemoteFd::Dir(fd),
+                    FileResource::Dir {
+                        dirs_iter: dir_entries.into_iter(),
+                    },
+                );
+
+                Some(FileResponse::OpenDir(Ok(OpenDirResponse { fd })))
+            }
+            request => {
+                let mut request = request.clone();
+                let fd = request_fd_mut(&mut request).copied()?;
+                if !FileCache::is_cached_fd(fd) {
+                    return None;
                 }
-                SimpleProxyMessage::FileReq(
-                    message_id,
-                    layer_id,
-                    FileRequest::ReadDir(ReadDirRequest { remote_fd }),
-                ) => {
-                    if let Err(fail) = self
-                        .handle_readdir(
-                            layer_id,
-                            remote_fd,
-                            message_id,
-                            protocol_version.as_ref(),
-                            message_bus,
-                        )
-                        .await
-                    {
-                        // Send local failure to layer.
-                        message_bus
-                            .send(ToLayer {
-                                message_id,
-                                message: ProxyToLayerMessage::File(FileResponse::ReadDir(Err(
-                                    fail.into(),
-                                ))),
-                                layer_id,
-                            })
-                            .await;
+
+                Some(self.handle_cached_file_request(layer_id, fd, request))
+            }
+        }
+    }
+
+    /// Handles a [`FileRequest`] on a file or directory served from the [`FileCache`].
+    fn handle_cached_file_request(
+        &mut self,
+        layer_id: LayerId,
+        fd: u64,
+        request: FileRequest,
+    ) -> FileResponse {
+        let resource = self.remote_fds.get_mut(&layer_id, &RemoteFd::File(fd));
+        let Some(FileResource::Cached {
+            content,
+            metadata,
+            position,
+        }) = resource
+        else {
+            return file_error_response(&request)
+                .map(|error_response| error_response(ResponseError::NotFound(fd)))
+                .unwrap_or_else(|| FileResponse::Read(Err(ResponseError::NotFound(fd))));
+        };
+
+        let read = |start: u64, buffer_size: u64| {
+            let start = start.min(content.len() as u64) as usize;
+            let end = start
+                .saturating_add(buffer_size as usize)
+                .min(content.len());
+            let bytes = content.get(start..end).unwrap_or_default().to_vec();
+
+            ReadFileResponse {
+                read_amount: bytes.len() as u64,
+                bytes,
+            }
+        };
+
+        match request {
+            FileRequest::Read(ReadFileRequest { buffer_size, .. }) => {
+                let response = read(*position, buffer_size);
+                *position += response.read_amount;
+                FileResponse::Read(Ok(response))
+            }
+            FileRequest::ReadLimited(ReadLimitedFileRequest {
+                buffer_size,
+                start_from,
+                ..
+            }) => FileResponse::ReadLimited(Ok(read(start_from, buffer_size))),
+            FileRequest::Seek(SeekFileRequest { seek_from, .. }) => {
+                let result_offset = match seek_from {
+                    SeekFromInternal::Start(offset) => Some(offset),
+                    SeekFromInternal::End(offset) => {
+                        (content.len() as u64).checked_add_signed(offset)
                     }
-                }
-                // TODO(alex): We can remove this case when users are up-to-date for `readlink`.
-                SimpleProxyMessage::FileReq(
-                    message_id,
-                    layer_id,
-                    req @ FileRequest::ReadLink(_),
-                ) => {
-                    if protocol_version
-                        .as_ref()
-                        .is_some_and(|version| READDIR_BATCH_VERSION.matches(version))
-                    {
-                        self.file_reqs.insert(message_id, layer_id);
-                        message_bus
-                            .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(req)))
-                            .await;
-                    } else {
-                        message_bus
-                            .send(ToLayer {
-                                message_id,
-                                message: ProxyToLayerMessage::File(FileResponse::ReadLink(Err(
-                                    ResponseError::NotImplemented,
-                                ))),
-                                layer_id,
-                            })
-                            .await;
+                    SeekFromInternal::Current(offset) => position.checked_add_signed(offset),
+                };
+
+                match result_offset {
+                    Some(result_offset) => {
+                        *position = result_offset;
+                        FileResponse::Seek(Ok(SeekFileResponse { result_offset }))
+                    }
+                    None => {
+                        FileResponse::Seek(Err(io::Error::from_raw_os_error(libc::EINVAL).into()))
                     }
                 }
-                SimpleProxyMessage::FileReq(message_id, layer_id, req) => {
-                    self.file_reqs.insert(message_id, layer_id);
-                    message_bus
-                        .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(req)))
-                        .await;
-                }
-                SimpleProxyMessage::FileRes(FileResponse::Open(Ok(OpenFileResponse { fd }))) => {
-                    let (message_id, layer_id) = self.file_reqs.get()?;
+            }
+            FileRequest::FdOpenDir(..) => {
+                FileResponse::OpenDir(Err(ResponseError::NotDirectory(fd)))
+            }
+            FileRequest::Xstat(XstatRequest { path: None, .. }) => {
+                FileResponse::Xstat(Ok(XstatResponse {
+                    metadata: *metadata,
+                }))
+            }
+            request => file_error_response(&request)
+                .map(|error_response| error_response(ResponseError::NotImplemented))
+                .unwrap_or_else(|| FileResponse::Read(Err(ResponseError::NotImplemented))),
+        }
+    }
 
-                    self.remote_fds
-                        .add(layer_id, RemoteFd::File(fd), FileResource::File);
+    /// Returns what to do with the agent's response to the given [`FileRequest`].
+    fn pending_request(&self, request: &FileRequest) -> PendingFileRequest {
+        let Some(file_cache) = self.file_cache.as_ref() else {
+            return PendingFileRequest::Layer(file_error_response(request));
+        };
 
-                    message_bus
-                        .send(ToLayer {
-                            message_id,
-                            message: ProxyToLayerMessage::File(FileResponse::Open(Ok(
-                                OpenFileResponse { fd },
-                            ))),
-                            layer_id,
-                        })
-                        .await;
+        match request {
+            FileRequest::Open(OpenFileRequest { path, open_options })
+                if open_options.is_read_only() && file_cache.is_cacheable(path) =>
+            {
+                PendingFileRequest::Open(path.clone())
+            }
+            FileRequest::Xstat(XstatRequest {
+                path: Some(path),
+                fd: None,
+                follow_symlink: true,
+            }) if file_cache.is_cacheable(path) => PendingFileRequest::Xstat(path.clone()),
+            FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd }) => {
+                match file_cache.open_path(*remote_fd) {
+                    Some(path) => PendingFileRequest::OpenDir(path.to_owned()),
+                    None => PendingFileRequest::Layer(file_error_response(request)),
                 }
-                SimpleProxyMessage::FileRes(FileResponse::OpenDir(Ok(OpenDirResponse { fd }))) => {
-                    let (message_id, layer_id) = self.file_reqs.get()?;
-
-                    self.remote_fds.add(
-                        layer_id,
-                        RemoteFd::Dir(fd),
-                        FileResource::Dir {
-                            dirs_iter: IntoIter::default(),
-                        },
-                    );
+            }
+            request => PendingFileRequest::Layer(file_error_response(request)),
+        }
+    }
 
-                    message_bus
-                        .send(ToLayer {
-                            message_id,
-                            message: ProxyToLayerMessage::File(FileResponse::OpenDir(Ok(
-                                OpenDirResponse { fd },
-                            ))),
-                            layer_id,
-                        })
-                        .await;
+    /// Handles the agent's response to a [`FileRequest`] that involves the [`FileCache`].
+    async fn handle_cache_response(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        pending: PendingFileRequest,
+        response: FileResponse,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        match (pending, response) {
+            (PendingFileRequest::Layer(..), response) => {
+                self.handle_file_response(message_id, layer_id, response, message_bus)
+                    .await;
+            }
+            (PendingFileRequest::Xstat(path), response) => {
+                if let FileResponse::Xstat(Ok(XstatResponse { metadata })) = &response
+                    && let Some(file_cache) = self.file_cache.as_mut()
+                {
+                    file_cache.store_metadata(path, *metadata);
                 }
-                SimpleProxyMessage::FileRes(FileResponse::ReadDirBatch(Ok(
-                    ReadDirBatchResponse { fd, dir_entries },
-                ))) => {
-                    let (message_id, layer_id) = self.file_reqs.get()?;
 
-                    let mut entries_iter = dir_entries.into_iter();
-                    let direntry = entries_iter.next();
+                self.handle_file_response(message_id, layer_id, response, message_bus)
+                    .await;
+            }
+            (PendingFileRequest::OpenDir(path), response) => {
+                if let FileResponse::OpenDir(Ok(OpenDirResponse { fd })) = &response
+                    && let Some(file_cache) = self.file_cache.as_mut()
+                {
+                    file_cache.listing_started(fd + self.fd_offset, path);
+                }
 
-                    message_bus
-                        .send(ToLayer {
-                            message_id,
-                            message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
-                                ReadDirResponse { direntry },
-                            ))),
-                            layer_id,
-                        })
-                        .await;
+                self.handle_file_response(message_id, layer_id, response, message_bus)
+                    .await;
+            }
+            (PendingFileRequest::Open(path), FileResponse::Open(Ok(OpenFileResponse { fd }))) => {
+                let metadata = self
+                    .file_cache
+                    .as_ref()
+                    .and_then(|file_cache| file_cache.metadata(&path))
+                    .copied();
 
-                    if let Some(FileResource::Dir { dirs_iter }) =
-                        self.remote_fds.get_mut(&layer_id, &RemoteFd::Dir(fd))
-                    {
-                        *dirs_iter = entries_iter;
+                match metadata {
+                    Some(metadata) => {
+                        self.fill_cache(message_id, layer_id, path, fd, metadata, message_bus)
+                            .await;
                     }
-                }
-                SimpleProxyMessage::FileRes(res) => {
-                    let (message_id, layer_id) = self.file_reqs.get()?;
-                    message_bus
-                        .send(ToLayer {
-                            message_id,
-                            message: ProxyToLayerMessage::File(res),
-                            layer_id,
-                        })
-                        .await;
-                }
-                SimpleProxyMessage::AddrInfoReq(message_id, session_id, req) => {
-                    self.addr_info_reqs.insert(message_id, session_id);
-                    message_bus
-                        .send(ProxyMessage::ToAgent(ClientMessage::GetAddrInfoRequest(
-                            req,
-                        )))
-                        .await;
-                }
-                SimpleProxyMessage::AddrInfoRes(res) => {
-                    let (message_id, layer_id) = self.addr_info_reqs.get()?;
-                    message_bus
-                        .send(ToLayer {
+                    None => {
+                        self.file_reqs.insert_with(
                             message_id,
-                            message: ProxyToLayerMessage::GetAddrInfo(res),
                             layer_id,
-                        })
-                        .await;
-                }
-                SimpleProxyMessage::LayerClosed(LayerClosed { id }) => {
-                    for to_close in self.remote_fds.remove_all(id) {
-                        let req = match to_close {
-                            RemoteFd::Dir(remote_fd) => {
-                                FileRequest::CloseDir(CloseDirRequest { remote_fd })
-                            }
-                            RemoteFd::File(fd) => FileRequest::Close(CloseFileRequest { fd }),
-                        };
-
-                        message_bus.send(ClientMessage::FileRequest(req)).await;
+                            PendingFileRequest::OpenXstat { path, fd },
+                        );
+                        message_bus
+                            .send(ClientMessage::FileRequest(FileRequest::Xstat(
+                                XstatRequest {
+                                    path: None,
+                                    fd: Some(fd),
+                                    follow_symlink: true,
+                                },
+                            )))
+                            .await;
                     }
                 }
-                SimpleProxyMessage::LayerForked(LayerForked { child, parent }) => {
-                    self.remote_fds.clone_all(parent, child);
+            }
+            (PendingFileRequest::Open(..), response) => {
+                self.handle_file_response(message_id, layer_id, response, message_bus)
+                    .await;
+            }
+            (
+                PendingFileRequest::OpenXstat { path, fd },
+                FileResponse::Xstat(Ok(XstatResponse { metadata })),
+            ) => {
+                if let Some(file_cache) = self.file_cache.as_mut() {
+                    file_cache.store_metadata(path.clone(), metadata);
                 }
-                SimpleProxyMessage::GetEnvReq(message_id, layer_id, req) => {
-                    self.get_env_reqs.insert(message_id, layer_id);
-                    message_bus
-                        .send(ProxyMessage::ToAgent(ClientMessage::GetEnvVarsRequest(req)))
+
+                self.fill_cache(message_id, layer_id, path, fd, metadata, message_bus)
+                    .await;
+            }
+            (
+                PendingFileRequest::OpenRead {
+                    path,
+                    fd,
+                    metadata,
+                    mut bytes,
+                },
+                FileResponse::ReadLimited(Ok(response)),
+            ) if response.read_amount > 0 => {
+                bytes.extend(response.bytes);
+
+                if (bytes.len() as u64) < metadata.size {
+                    self.read_into_cache(
+                        message_id,
+                        layer_id,
+                        path,
+                        fd,
+                        metadata,
+                        bytes,
+                        message_bus,
+                    )
+                    .await;
+                } else if bytes.len() as u64 == metadata.size
+                    && let Some(file_cache) = self.file_cache.as_mut()
+                {
+                    let content = file_cache.store_content(path, &metadata, bytes);
+                    let cached_fd = file_cache.next_fd();
+                    self.open_cached(
+                        message_id,
+                        layer_id,
+                        fd,
+                        cached_fd,
+                        content,
+                        metadata,
+                        message_bus,
+                    )
+                    .await;
+                } else {
+                    self.open_remote(message_id, layer_id, fd, path, message_bus)
                         .await;
                 }
-                SimpleProxyMessage::GetEnvRes(res) => {
-                    let (message_id, layer_id) = self.get_env_reqs.get()?;
-                    message_bus
-                        .send(ToLayer {
-                            message_id,
-                            message: ProxyToLayerMessage::GetEnv(res),
-                            layer_id,
+            }
+            // We failed to cache the content, the layer gets the file opened in the agent.
+            (
+                PendingFileRequest::OpenXstat { path, fd }
+                | PendingFileRequest::OpenRead { path, fd, .. },
+                _,
+            ) => {
+                self.open_remote(message_id, layer_id, fd, path, message_bus)
+                    .await;
+            }
+        }
+    }
+
+    /// Serves the file opened in the agent with `fd` from the [`FileCache`], reading its content
+    /// first if needed. Directories and files too big for the cache stay in the agent.
+    async fn fill_cache(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        path: PathBuf,
+        fd: u64,
+        metadata: MetadataInternal,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let cached = match self.file_cache.as_mut() {
+            Some(file_cache) if file_cache.can_cache_content(&metadata) => file_cache
+                .content(&path, &metadata)
+                .map(|content| (file_cache.next_fd(), content)),
+            _ => {
+                self.open_remote(message_id, layer_id, fd, path, message_bus)
+                    .await;
+                return;
+            }
+        };
+
+        if let Some((cached_fd, content)) = cached {
+            self.open_cached(
+                message_id,
+                layer_id,
+                fd,
+                cached_fd,
+                content,
+                metadata,
+                message_bus,
+            )
+            .await;
+        } else {
+            self.read_into_cache(
+                message_id,
+                layer_id,
+                path,
+                fd,
+                metadata,
+                Vec::new(),
+                message_bus,
+            )
+            .await;
+        }
+    }
+
+    /// Requests the next chunk of the file opened in the agent with `fd`, `bytes` holds the
+    /// content read so far.
+    #[allow(clippy::too_many_arguments)]
+    async fn read_into_cache(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        path: PathBuf,
+        fd: u64,
+        metadata: MetadataInternal,
+        bytes: Vec<u8>,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let start_from = bytes.len() as u64;
+        let buffer_size = (metadata.size - start_from).min(CACHE_READ_SIZE);
+
+        self.file_reqs.insert_with(
+            message_id,
+            layer_id,
+            PendingFileRequest::OpenRead {
+                path,
+                fd,
+                metadata,
+                bytes,
+            },
+        );
+        message_bus
+            .send(ClientMessage::FileRequest(FileRequest::ReadLimited(
+                ReadLimitedFileRequest {
+                    remote_fd: fd,
+                    buffer_size,
+                    start_from,
+                },
+            )))
+            .await;
+    }
+
+    /// Closes the file opened in the agent with `fd`, and responds to the layer with `cached_fd`,
+    /// served from the [`FileCache`].
+    #[allow(clippy::too_many_arguments)]
+    async fn open_cached(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        fd: u64,
+        cached_fd: u64,
+        content: Arc<[u8]>,
+        metadata: MetadataInternal,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        message_bus
+            .send(ClientMessage::FileRequest(FileRequest::Close(
+                CloseFileRequest { fd },
+            )))
+            .await;
+
+        self.remote_fds.add(
+            layer_id,
+            RemoteFd::File(cached_fd),
+            FileResource::Cached {
+                content,
+                metadata,
+                position: 0,
+            },
+        );
+
+        message_bus
+            .send(ToLayer {
+                message_id,
+                message: ProxyToLayerMessage::File(FileResponse::Open(Ok(OpenFileResponse {
+                    fd: cached_fd,
+                }))),
+                layer_id,
+            })
+            .await;
+    }
+
+    /// Responds to the layer with the file opened in the agent with `fd`.
+    async fn open_remote(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        fd: u64,
+        path: PathBuf,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        if let Some(file_cache) = self.file_cache.as_mut() {
+            file_cache.file_opened(fd + self.fd_offset, path);
+        }
+
+        self.handle_file_response(
+            message_id,
+            layer_id,
+            FileResponse::Open(Ok(OpenFileResponse { fd })),
+            message_bus,
+        )
+        .await;
+    }
+
+    /// Handles the agent's response to a [`FileRequest`] made by the layer.
+    async fn handle_file_response(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        response: FileResponse,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        match response {
+            FileResponse::Open(Ok(OpenFileResponse { fd })) => {
+                let fd = self.layer_fd(fd);
+
+                self.remote_fds
+                    .add(layer_id, RemoteFd::File(fd), FileResource::File);
+
+                message_bus
+                    .send(ToLayer {
+                        message_id,
+                        message: ProxyToLayerMessage::File(FileResponse::Open(Ok(
+                            OpenFileResponse { fd },
+                        ))),
+                        layer_id,
+                    })
+                    .await;
+            }
+            FileResponse::OpenDir(Ok(OpenDirResponse { fd })) => {
+                let fd = self.layer_fd(fd);
+
+                self.remote_fds.add(
+                    layer_id,
+                    RemoteFd::Dir(fd),
+                    FileResource::Dir {
+                        dirs_iter: IntoIter::default(),
+                    },
+                );
+
+                message_bus
+                    .send(ToLayer {
+                        message_id,
+                        message: ProxyToLayerMessage::File(FileResponse::OpenDir(Ok(
+                            OpenDirResponse { fd },
+                        ))),
+                        layer_id,
+                    })
+                    .await;
+            }
+            FileResponse::ReadDirBatch(Ok(ReadDirBatchResponse { fd, dir_entries })) => {
+                let fd = fd + self.fd_offset;
+
+                if let Some(file_cache) = self.file_cache.as_mut() {
+                    file_cache.listing_progress(fd, &dir_entries);
+                }
+
+                let mut entries_iter = dir_entries.into_iter();
+                let direntry = entries_iter.next();
+
+                message_bus
+                    .send(ToLayer {
+                        message_id,
+                        message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
+                            ReadDirResponse { direntry },
+                        ))),
+                        layer_id,
+                    })
+                    .await;
+
+                if let Some(FileResource::Dir { dirs_iter }) =
+                    self.remote_fds.get_mut(&layer_id, &RemoteFd::Dir(fd))
+                {
+                    *dirs_iter = entries_iter;
+                }
+            }
+            response => {
+                message_bus
+                    .send(ToLayer {
+                        message_id,
+                        message: ProxyToLayerMessage::File(response),
+                        layer_id,
+                    })
+                    .await;
+            }
+        }
+    }
+
+    /// Drops the state tied to the previous agent connection.
+    ///
+    /// Pending requests won't get their responses, so we fail them. Open files and directories
+    /// become stale.
+    async fn handle_connection_refresh(&mut self, message_bus: &mut MessageBus<Self>) {
+        message_bus.send(ConnectionRefresh::End).await;
+
+        self.fd_offset = self.next_fd_offset;
+
+        if let Some(file_cache) = self.file_cache.as_mut() {
+            file_cache.connection_refreshed();
+        }
+
+        for (message_id, layer_id, pending) in self.file_reqs.drain() {
+            let Some(error_response) = pending.error_response() else {
+                continue;
+            };
+
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::File(error_response(
+                        ResponseError::AgentReconnected,
+                    )),
+                    layer_id,
+                })
+                .await;
+        }
+
+        for (message_id, layer_id, _) in self.addr_info_reqs.drain() {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::GetAddrInfo(GetAddrInfoResponse(Err(
+                        ResponseError::AgentReconnected,
+                    ))),
+                    layer_id,
+                })
+                .await;
+        }
+
+        for (message_id, layer_id, _) in self.dns_query_reqs.drain() {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::DnsQuery(DnsQueryResponse(Err(
+                        ResponseError::AgentReconnected,
+                    ))),
+                    layer_id,
+                })
+                .await;
+        }
+
+        for (message_id, layer_id, _) in self.reverse_lookup_reqs.drain() {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::ReverseLookup(ReverseLookupResponse(Err(
+                        ResponseError::AgentReconnected,
+                    ))),
+                    layer_id,
+                })
+                .await;
+        }
+
+        for (message_id, layer_id, ()) in self.get_env_reqs.drain() {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::GetEnv(Err(ResponseError::AgentReconnected)),
+                    layer_id,
+                })
+                .await;
+        }
+
+        for (message_id, layer_id, _) in self.watch_reqs.drain() {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::FileWatch(Err(ResponseError::AgentReconnected)),
+                    layer_id,
+                })
+                .await;
+        }
+
+        // The counters of the previous agent are gone, only ours are reported.
+        let dns_cache_stats = self.dns_cache_stats(None);
+        for (message_id, layer_id, ()) in self.dns_cache_stats_reqs.drain() {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::DnsCacheStats(dns_cache_stats),
+                    layer_id,
+                })
+                .await;
+        }
+
+        for event in self.file_watches.connection_refreshed() {
+            message_bus.send(event).await;
+        }
+    }
+}
+
+impl BackgroundTask for SimpleProxy {
+    type Error = RequestQueueEmpty;
+    type MessageIn = SimpleProxyMessage;
+    type MessageOut = ProxyMessage;
+
+    async fn run(mut self, message_bus: &mut MessageBus<Self>) -> Result<(), RequestQueueEmpty> {
+        let mut protocol_version = None;
+
+        while let Some(msg) = message_bus.recv().await {
+            tracing::trace!(?msg, "new message in message_bus");
+
+            match msg {
+                SimpleProxyMessage::ProtocolVersion(new_protocol_version) => {
+                    protocol_version = Some(new_protocol_version);
+                }
+                SimpleProxyMessage::ConnectionRefresh => {
+                    self.handle_connection_refresh(message_bus).await;
+                }
+                SimpleProxyMessage::FileReq(
+                    _,
+                    layer_id,
+                    FileRequest::Close(CloseFileRequest { fd }),
+                ) => {
+                    let do_close = self.remote_fds.remove(layer_id, RemoteFd::File(fd));
+                    if do_close && let Some(file_cache) = self.file_cache.as_mut() {
+                        file_cache.file_closed(fd);
+                    }
+
+                    if do_close
+                        && !FileCache::is_cached_fd(fd)
+                        && let Some(fd) = self.agent_fd(fd)
+                    {
+                        message_bus
+                            .send(ClientMessage::FileRequest(FileRequest::Close(
+                                CloseFileRequest { fd },
+                            )))
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::FileReq(
+                    _,
+                    layer_id,
+                    FileRequest::CloseDir(CloseDirRequest { remote_fd }),
+                ) => {
+                    let do_close = self.remote_fds.remove(layer_id, RemoteFd::Dir(remote_fd));
+                    if do_close && let Some(file_cache) = self.file_cache.as_mut() {
+                        file_cache.listing_closed(remote_fd);
+                    }
+
+                    if do_close
+                        && !FileCache::is_cached_fd(remote_fd)
+                        && let Some(remote_fd) = self.agent_fd(remote_fd)
+                    {
+                        message_bus
+                            .send(ClientMessage::FileRequest(FileRequest::CloseDir(
+                                CloseDirRequest { remote_fd },
+                            )))
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::FileReq(
+                    message_id,
+                    layer_id,
+                    FileRequest::ReadDir(ReadDirRequest { remote_fd }),
+                ) => {
+                    if let Err(fail) = self
+                        .handle_readdir(
+                            layer_id,
+                            remote_fd,
+                            message_id,
+                            protocol_version.as_ref(),
+                            message_bus,
+                        )
+                        .await
+                    {
+                        // Send local failure to layer.
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::File(FileResponse::ReadDir(Err(
+                                    fail.into(),
+                                ))),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                // TODO(alex): We can remove this case when users are up-to-date for `readlink`.
+                SimpleProxyMessage::FileReq(
+                    message_id,
+                    layer_id,
+                    req @ FileRequest::ReadLink(_),
+                ) => {
+                    if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| READDIR_BATCH_VERSION.matches(version))
+                    {
+                        self.file_reqs.insert_with(
+                            message_id,
+                            layer_id,
+                            PendingFileRequest::Layer(file_error_response(&req)),
+                        );
+                        message_bus
+                            .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(req)))
+                            .await;
+                    } else {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::File(FileResponse::ReadLink(Err(
+                                    ResponseError::NotImplemented,
+                                ))),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::FileReq(message_id, layer_id, req)
+                    if required_version(&req).is_some_and(|required| {
+                        !protocol_version
+                            .as_ref()
+                            .is_some_and(|version| required.matches(version))
+                    }) =>
+                {
+                    if let Some(error_response) = file_error_response(&req) {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::File(error_response(
+                                    ResponseError::NotImplemented,
+                                )),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::FileReq(message_id, layer_id, mut req) => {
+                    if let Some(response) = self.cached_response(layer_id, &req) {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::File(response),
+                                layer_id,
+                            })
+                            .await;
+
+                        continue;
+                    }
+
+                    let pending = self.pending_request(&req);
+
+                    if let Some(fd) = request_fd_mut(&mut req) {
+                        match self.agent_fd(*fd) {
+                            Some(agent_fd) => *fd = agent_fd,
+                            None => {
+                                if let Some(error_response) = pending.error_response() {
+                                    message_bus
+                                        .send(ToLayer {
+                                            message_id,
+                                            message: ProxyToLayerMessage::File(error_response(
+                                                ResponseError::StaleResource(*fd),
+                                            )),
+                                            layer_id,
+                                        })
+                                        .await;
+                                }
+
+                                continue;
+                            }
+                        }
+                    }
+
+                    self.file_reqs.insert_with(message_id, layer_id, pending);
+                    message_bus
+                        .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(req)))
+                        .await;
+                }
+                SimpleProxyMessage::FileRes(FileResponse::AddWatch(result)) => {
+                    let (message_id, layer_id, subscribe) = self.watch_reqs.get_with()?;
+                    let result = result.map(|AddWatchResponse { watch_id }| {
+                        self.file_watches.subscribed(layer_id, subscribe, watch_id)
+                    });
+
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::FileWatch(result),
+                            layer_id,
+                        })
+                        .await;
+                }
+                SimpleProxyMessage::FileRes(response) => {
+                    let (message_id, layer_id, pending) = self.file_reqs.get_with()?;
+                    self.handle_cache_response(
+                        message_id,
+                        layer_id,
+                        pending,
+                        response,
+                        message_bus,
+                    )
+                    .await;
+                }
+                SimpleProxyMessage::AddrInfoReq(message_id, layer_id, req) => {
+                    if let Some(res) = self
+                        .dns_cache
+                        .as_mut()
+                        .and_then(|dns_cache| dns_cache.get_addr_info(&req))
+                    {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::GetAddrInfo(res),
+                                layer_id,
+                            })
+                            .await;
+                        continue;
+                    }
+
+                    self.addr_info_reqs
+                        .insert_with(message_id, layer_id, req.clone());
+
+                    // Older agents can only resolve IPv4 addresses.
+                    let message = if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| ADDRINFO_V2_VERSION.matches(version))
+                    {
+                        ClientMessage::GetAddrInfoRequestV2(req)
+                    } else {
+                        ClientMessage::GetAddrInfoRequest(GetAddrInfoRequest::from(req))
+                    };
+
+                    message_bus.send(ProxyMessage::ToAgent(message)).await;
+                }
+                SimpleProxyMessage::AddrInfoRes(res) => {
+                    let (message_id, layer_id, req) = self.addr_info_reqs.get_with()?;
+                    if let Some(dns_cache) = self.dns_cache.as_mut() {
+                        dns_cache.insert_addr_info(&req, &res);
+                    }
+
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::GetAddrInfo(res),
+                            layer_id,
+                        })
+                        .await;
+                }
+                SimpleProxyMessage::DnsQueryReq(message_id, layer_id, req) => {
+                    if let Some(res) = self
+                        .dns_cache
+                        .as_mut()
+                        .and_then(|dns_cache| dns_cache.get_query(&req))
+                    {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::DnsQuery(res),
+                                layer_id,
+                            })
+                            .await;
+                    } else if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| DNS_QUERY_VERSION.matches(version))
+                    {
+                        self.dns_query_reqs
+                            .insert_with(message_id, layer_id, req.clone());
+                        message_bus
+                            .send(ProxyMessage::ToAgent(ClientMessage::DnsQuery(req)))
+                            .await;
+                    } else {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::DnsQuery(DnsQueryResponse(Err(
+                                    ResponseError::NotImplemented,
+                                ))),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::DnsQueryRes(res) => {
+                    let (message_id, layer_id, req) = self.dns_query_reqs.get_with()?;
+                    if let Some(dns_cache) = self.dns_cache.as_mut() {
+                        dns_cache.insert_query(&req, &res);
+                    }
+
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::DnsQuery(res),
+                            layer_id,
+                        })
+                        .await;
+                }
+                SimpleProxyMessage::ReverseLookupReq(message_id, layer_id, req) => {
+                    if let Some(res) = self
+                        .dns_cache
+                        .as_mut()
+                        .and_then(|dns_cache| dns_cache.get_reverse_lookup(&req))
+                    {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::ReverseLookup(res),
+                                layer_id,
+                            })
+                            .await;
+                    } else if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| REVERSE_LOOKUP_VERSION.matches(version))
+                    {
+                        self.reverse_lookup_reqs
+                            .insert_with(message_id, layer_id, req.clone());
+                        message_bus
+                            .send(ProxyMessage::ToAgent(ClientMessage::ReverseLookup(req)))
+                            .await;
+                    } else {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::ReverseLookup(ReverseLookupResponse(
+                                    Err(ResponseError::NotImplemented),
+                                )),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::DnsCacheStatsReq(message_id, layer_id) => {
+                    if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| DNS_CACHE_STATS_VERSION.matches(version))
+                    {
+                        self.dns_cache_stats_reqs.insert(message_id, layer_id);
+                        message_bus
+                            .send(ProxyMessage::ToAgent(ClientMessage::GetDnsCacheStats))
+                            .await;
+                    } else {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::DnsCacheStats(
+                                    self.dns_cache_stats(None),
+                                ),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::DnsCacheStatsRes(stats) => {
+                    let (message_id, layer_id) = self.dns_cache_stats_reqs.get()?;
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::DnsCacheStats(
+                                self.dns_cache_stats(Some(stats)),
+                            ),
+                            layer_id,
+                        })
+                        .await;
+                }
+                SimpleProxyMessage::ReverseLookupRes(res) => {
+                    let (message_id, layer_id, req) = self.reverse_lookup_reqs.get_with()?;
+                    if let Some(dns_cache) = self.dns_cache.as_mut() {
+                        dns_cache.insert_reverse_lookup(&req, &res);
+                    }
+
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::ReverseLookup(res),
+                            layer_id,
+                        })
+                        .await;
+                }
+                SimpleProxyMessage::LayerClosed(LayerClosed { id }) => {
+                    let fd_offset = self.fd_offset;
+
+                    for to_close in self.remote_fds.remove_all(id) {
+                        if let Some(file_cache) = self.file_cache.as_mut() {
+                            match to_close {
+                                RemoteFd::File(fd) => file_cache.file_closed(fd),
+                                RemoteFd::Dir(remote_fd) => file_cache.listing_closed(remote_fd),
+                            }
+                        }
+
+                        // Stale resources were already closed together with the previous agent
+                        // connection, and the cached ones were never open in the agent.
+                        let req = match to_close {
+                            RemoteFd::Dir(remote_fd) | RemoteFd::File(remote_fd)
+                                if FileCache::is_cached_fd(remote_fd) =>
+                            {
+                                continue
+                            }
+                            RemoteFd::Dir(remote_fd) if remote_fd >= fd_offset => {
+                                FileRequest::CloseDir(CloseDirRequest {
+                                    remote_fd: remote_fd - fd_offset,
+                                })
+                            }
+                            RemoteFd::File(fd) if fd >= fd_offset => {
+                                FileRequest::Close(CloseFileRequest { fd: fd - fd_offset })
+                            }
+                            _ => continue,
+                        };
+
+                        message_bus.send(ClientMessage::FileRequest(req)).await;
+                    }
+
+                    for watch_id in self.file_watches.layer_closed(id) {
+                        message_bus
+                            .send(ClientMessage::FileRequest(FileRequest::RemoveWatch(
+                                RemoveWatchRequest { watch_id },
+                            )))
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::FileWatchReq(
+                    message_id,
+                    layer_id,
+                    FileWatchRequest::Subscribe(subscribe),
+                ) => {
+                    if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| WATCH_VERSION.matches(version))
+                    {
+                        let request = FileRequest::AddWatch(subscribe.request.clone());
+                        self.watch_reqs.insert_with(message_id, layer_id, subscribe);
+                        message_bus
+                            .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(request)))
+                            .await;
+                    } else {
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::FileWatch(Err(
+                                    ResponseError::NotImplemented,
+                                )),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::FileWatchReq(
+                    _,
+                    _,
+                    FileWatchRequest::Unsubscribe(unsubscribe),
+                ) => {
+                    let Some((ignored, unused)) = self.file_watches.unsubscribe(unsubscribe) else {
+                        continue;
+                    };
+
+                    message_bus.send(ignored).await;
+                    if let Some(watch_id) = unused {
+                        message_bus
+                            .send(ClientMessage::FileRequest(FileRequest::RemoveWatch(
+                                RemoveWatchRequest { watch_id },
+                            )))
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::FileWatchEvent(event) => {
+                    for event in self.file_watches.event_received(event) {
+                        message_bus.send(event).await;
+                    }
+                }
+                SimpleProxyMessage::LayerForked(LayerForked { child, parent }) => {
+                    self.remote_fds.clone_all(parent, child);
+                }
+                SimpleProxyMessage::GetEnvReq(message_id, layer_id, req) => {
+                    self.get_env_reqs.insert(message_id, layer_id);
+                    message_bus
+                        .send(ProxyMessage::ToAgent(ClientMessage::GetEnvVarsRequest(req)))
+                        .await;
+                }
+                SimpleProxyMessage::GetEnvRes(res) => {
+                    let (message_id, layer_id) = self.get_env_reqs.get()?;
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::GetEnv(res),
+                            layer_id,
                         })
                         .await
                 }
             }
-        }
+        }
+
+        if let Some(dns_cache) = self.dns_cache.as_ref() {
+            tracing::debug!(stats = ?dns_cache.stats(), "DNS cache statistics");
+        }
+
+        tracing::trace!("message bus closed, exiting");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use mirrord_config::{
+        feature::{
+            fs::{FsCacheConfig, FsConfig},
+            network::dns::DnsCacheConfig,
+        },
+        util::VecOrSingle,
+    };
+    use mirrord_intproxy_protocol::{
+        DnsCacheStatsResponse, FileWatchRequest, LayerId, ProxyToLayerMessage, WatchEvent,
+        WatchInstance, WatchSubscribe, WatchUnsubscribe,
+    };
+    use mirrord_protocol::{
+        dns::{
+            AddressFamily, DnsCacheStats, DnsLookup, DnsQueryRequest, DnsQueryResponse,
+            GetAddrInfoRequestV2, GetAddrInfoResponse, LookupRecord, ReverseLookupRequest,
+            ReverseLookupResponse,
+        },
+        file::{
+            AddWatchRequest, AddWatchResponse, CloseFileRequest, FdOpenDirRequest, FileWatchEvent,
+            MetadataInternal, OpenDirResponse, OpenFileRequest, OpenFileResponse,
+            OpenOptionsInternal, ReadDirBatchRequest, ReadDirBatchResponse, ReadDirRequest,
+            ReadDirResponse, ReadFileRequest, ReadFileResponse, ReadLimitedFileRequest,
+            RemoveWatchRequest, StatFsRequest, StatFsRequestV2, XstatFsRequest, XstatRequest,
+            XstatResponse,
+        },
+        ClientMessage, DnsLookupError, FileRequest, FileResponse, ResolveErrorKindInternal,
+        ResponseError,
+    };
+    use semver::Version;
+
+    use super::{DnsCache, FileCache, SimpleProxy};
+    use crate::{
+        background_tasks::{BackgroundTasks, TaskSender, TaskUpdate},
+        error::IntProxyError,
+        main_tasks::{ConnectionRefresh, MainTaskId, ProxyMessage, ToLayer},
+        proxies::simple::SimpleProxyMessage,
+    };
+
+    /// Sets up a [`TaskSender`] and [`BackgroundTasks`] for a functioning [`SimpleProxy`].
+    ///
+    /// - `protocol_version`: allows specifying the version of the protocol to use for testing out
+    ///   potential mismatches in messages.
+    async fn setup_proxy(
+        protocol_version: Version,
+    ) -> (
+        TaskSender<SimpleProxy>,
+        BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
+    ) {
+        let mut tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> =
+            Default::default();
+
+        let proxy = tasks.register(SimpleProxy::default(), MainTaskId::SimpleProxy, 32);
+
+        proxy
+            .send(SimpleProxyMessage::ProtocolVersion(protocol_version))
+            .await;
+
+        (proxy, tasks)
+    }
+
+    /// Convenience for opening a dir.
+    async fn prepare_dir(
+        proxy: &TaskSender<SimpleProxy>,
+        tasks: &mut BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
+    ) {
+        let request = FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd: 0xdad });
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::FdOpenDir(FdOpenDirRequest { .. }),)
+                )))
+            ),
+            "Mismatched message for `FdOpenDirRequest` {update:?}!"
+        );
+
+        let response = FileResponse::OpenDir(Ok(OpenDirResponse { fd: 0xdad }));
+        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        let (_, update) = tasks.next().await.unzip();
+
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::File(FileResponse::OpenDir(Ok(
+                        OpenDirResponse { .. }
+                    )))
+                })))
+            ),
+            "Mismatched message for `OpenDirResponse` {update:?}!"
+        );
+    }
+
+    #[tokio::test]
+    async fn old_protocol_uses_read_dir_request() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(0, 1, 0)).await;
+
+        prepare_dir(&proxy, &mut tasks).await;
+
+        let readdir_request = FileRequest::ReadDir(ReadDirRequest { remote_fd: 0xdad });
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                0xbad,
+                LayerId(0xa55),
+                readdir_request,
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::ReadDir(ReadDirRequest { .. }))
+                )))
+            ),
+            "Mismatched message for `ReadDirRequest` {update:?}!"
+        );
+
+        let readdir_response = FileResponse::ReadDir(Ok(ReadDirResponse { direntry: None }));
+        proxy
+            .send(SimpleProxyMessage::FileRes(readdir_response))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
+                        ReadDirResponse { .. }
+                    )))
+                })))
+            ),
+            "Mismatched message for `ReadDirResponse` {update:?}!"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn new_protocol_uses_read_dir_batch_request() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 8, 3)).await;
+
+        prepare_dir(&proxy, &mut tasks).await;
+
+        let request = FileRequest::ReadDirBatch(ReadDirBatchRequest {
+            remote_fd: 0xdad,
+            amount: 0xca7,
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::ReadDirBatch(ReadDirBatchRequest {
+                        remote_fd: 0xdad,
+                        amount: 0xca7
+                    }))
+                )))
+            ),
+            "Mismatched message for `ReadDirBatchRequest` {update:?}!"
+        );
+
+        let response = FileResponse::ReadDirBatch(Ok(ReadDirBatchResponse {
+            fd: 0xdad,
+            dir_entries: Vec::new(),
+        }));
+        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        let (_, update) = tasks.next().await.unzip();
+
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
+                        ReadDirResponse { .. }
+                    )))
+                })))
+            ),
+            "Mismatched message for `ReadDirBatchResponse` {update:?}!"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// Opens a file, the agent responds with the given `agent_fd`.
+    /// Returns the remote fd that the layer got.
+    async fn open_file(
+        proxy: &TaskSender<SimpleProxy>,
+        tasks: &mut BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
+        agent_fd: u64,
+    ) -> u64 {
+        let request = FileRequest::Open(OpenFileRequest {
+            path: "/etc/hosts".into(),
+            open_options: OpenOptionsInternal::default(),
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::Open(..))
+                )))
+            ),
+            "Mismatched message for `OpenFileRequest` {update:?}!"
+        );
+
+        let response = FileResponse::Open(Ok(OpenFileResponse { fd: agent_fd }));
+        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        let (_, update) = tasks.next().await.unzip();
+
+        match update {
+            Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                message: ProxyToLayerMessage::File(FileResponse::Open(Ok(OpenFileResponse { fd }))),
+                ..
+            }))) => fd,
+            other => panic!("Mismatched message for `OpenFileResponse` {other:?}!"),
+        }
+    }
+
+    #[tokio::test]
+    async fn statfs_requires_protocol_version() {
+        for (protocol_version, supported) in [
+            (Version::new(1, 18, 0), false),
+            (Version::new(1, 19, 0), true),
+        ] {
+            let (proxy, mut tasks) = setup_proxy(protocol_version).await;
+
+            let request = FileRequest::StatFs(StatFsRequest {
+                path: "/var/lib/kafka".into(),
+            });
+            proxy
+                .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+
+            if supported {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::FileRequest(FileRequest::StatFs(..))
+                        )))
+                    ),
+                    "Mismatched message for `StatFsRequest` {update:?}!"
+                );
+            } else {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                            message_id: 0xbad,
+                            layer_id: LayerId(0xa55),
+                            message: ProxyToLayerMessage::File(FileResponse::XstatFs(Err(
+                                ResponseError::NotImplemented
+                            ))),
+                        })))
+                    ),
+                    "Mismatched message for unsupported `StatFsRequest` {update:?}!"
+                );
+            }
+
+            drop(proxy);
+            let results = tasks.results().await;
+            for (_, result) in results {
+                assert!(result.is_ok(), "{result:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn statfs_v2_requires_protocol_version() {
+        for (protocol_version, supported) in [
+            (Version::new(1, 22, 0), false),
+            (Version::new(1, 23, 0), true),
+        ] {
+            let (proxy, mut tasks) = setup_proxy(protocol_version).await;
+
+            let request = FileRequest::StatFsV2(StatFsRequestV2 {
+                path: "/var/lib/kafka".into(),
+            });
+            proxy
+                .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+
+            if supported {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::FileRequest(FileRequest::StatFsV2(..))
+                        )))
+                    ),
+                    "Mismatched message for `StatFsRequestV2` {update:?}!"
+                );
+            } else {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                            message_id: 0xbad,
+                            layer_id: LayerId(0xa55),
+                            message: ProxyToLayerMessage::File(FileResponse::XstatFsV2(Err(
+                                ResponseError::NotImplemented
+                            ))),
+                        })))
+                    ),
+                    "Mismatched message for unsupported `StatFsRequestV2` {update:?}!"
+                );
+            }
+
+            drop(proxy);
+            let results = tasks.results().await;
+            for (_, result) in results {
+                assert!(result.is_ok(), "{result:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dns_query_requires_protocol_version() {
+        for (protocol_version, supported) in [
+            (Version::new(1, 19, 0), false),
+            (Version::new(1, 20, 0), true),
+        ] {
+            let (proxy, mut tasks) = setup_proxy(protocol_version).await;
+
+            let request = DnsQueryRequest {
+                name: "_mongodb._tcp.db.svc.cluster.local.".to_string(),
+                record_type: 33,
+            };
+            proxy
+                .send(SimpleProxyMessage::DnsQueryReq(
+                    0xbad,
+                    LayerId(0xa55),
+                    request,
+                ))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+
+            if supported {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::DnsQuery(..)
+                        )))
+                    ),
+                    "Mismatched message for `DnsQueryRequest` {update:?}!"
+                );
+            } else {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                            message_id: 0xbad,
+                            layer_id: LayerId(0xa55),
+                            message: ProxyToLayerMessage::DnsQuery(DnsQueryResponse(Err(
+                                ResponseError::NotImplemented
+                            ))),
+                        })))
+                    ),
+                    "Mismatched message for unsupported `DnsQueryRequest` {update:?}!"
+                );
+            }
 
-        tracing::trace!("message bus closed, exiting");
-        Ok(())
+            drop(proxy);
+            let results = tasks.results().await;
+            for (_, result) in results {
+                assert!(result.is_ok(), "{result:?}");
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Repeated lookups are answered from the [`DnsCache`], unless the previous lookup failed for
+    /// a reason other than missing records.
+    #[tokio::test]
+    async fn reverse_lookup_requires_protocol_version() {
+        for (protocol_version, supported) in [
+            (Version::new(1, 21, 0), false),
+            (Version::new(1, 22, 0), true),
+        ] {
+            let (proxy, mut tasks) = setup_proxy(protocol_version).await;
 
-    use mirrord_intproxy_protocol::{LayerId, ProxyToLayerMessage};
-    use mirrord_protocol::{
-        file::{
-            FdOpenDirRequest, OpenDirResponse, ReadDirBatchRequest, ReadDirBatchResponse,
-            ReadDirRequest, ReadDirResponse,
-        },
-        ClientMessage, FileRequest, FileResponse,
-    };
-    use semver::Version;
+            let request = ReverseLookupRequest {
+                ip: "10.0.0.1".parse().unwrap(),
+            };
+            proxy
+                .send(SimpleProxyMessage::ReverseLookupReq(
+                    0xbad,
+                    LayerId(0xa55),
+                    request,
+                ))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
 
-    use super::SimpleProxy;
-    use crate::{
-        background_tasks::{BackgroundTasks, TaskSender, TaskUpdate},
-        error::IntProxyError,
-        main_tasks::{MainTaskId, ProxyMessage, ToLayer},
-        proxies::simple::SimpleProxyMessage,
-    };
+            if supported {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::ReverseLookup(..)
+                        )))
+                    ),
+                    "Mismatched message for `ReverseLookupRequest` {update:?}!"
+                );
+            } else {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                            message_id: 0xbad,
+                            layer_id: LayerId(0xa55),
+                            message: ProxyToLayerMessage::ReverseLookup(ReverseLookupResponse(
+                                Err(ResponseError::NotImplemented)
+                            )),
+                        })))
+                    ),
+                    "Mismatched message for unsupported `ReverseLookupRequest` {update:?}!"
+                );
+            }
 
-    /// Sets up a [`TaskSender`] and [`BackgroundTasks`] for a functioning [`SimpleProxy`].
-    ///
-    /// - `protocol_version`: allows specifying the version of the protocol to use for testing out
-    ///   potential mismatches in messages.
-    async fn setup_proxy(
-        protocol_version: Version,
-    ) -> (
-        TaskSender<SimpleProxy>,
-        BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
-    ) {
+            drop(proxy);
+            let results = tasks.results().await;
+            for (_, result) in results {
+                assert!(result.is_ok(), "{result:?}");
+            }
+        }
+    }
+
+    /// Repeated lookups are answered from the [`DnsCache`], unless the previous lookup failed for
+    /// a reason other than missing records.
+    #[tokio::test]
+    async fn dns_cache_answers_repeated_lookups() {
         let mut tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> =
             Default::default();
-
-        let proxy = tasks.register(SimpleProxy::default(), MainTaskId::SimpleProxy, 32);
-
+        let dns_cache = DnsCache::new(&DnsCacheConfig::default());
+        let proxy = tasks.register(
+            SimpleProxy::new(None, dns_cache),
+            MainTaskId::SimpleProxy,
+            32,
+        );
         proxy
-            .send(SimpleProxyMessage::ProtocolVersion(protocol_version))
+            .send(SimpleProxyMessage::ProtocolVersion(Version::new(1, 21, 0)))
             .await;
 
-        (proxy, tasks)
+        let found = GetAddrInfoResponse(Ok(DnsLookup(vec![LookupRecord {
+            name: "db.svc.cluster.local.".to_string(),
+            ip: "10.0.0.1".parse().unwrap(),
+        }])));
+        let not_found = GetAddrInfoResponse(Err(ResponseError::DnsLookup(DnsLookupError {
+            kind: ResolveErrorKindInternal::NoRecordsFound(3),
+        })));
+        let timeout = GetAddrInfoResponse(Err(ResponseError::DnsLookup(DnsLookupError {
+            kind: ResolveErrorKindInternal::Timeout,
+        })));
+
+        for (node, response, cached) in [
+            ("db", found, true),
+            ("missing", not_found, true),
+            ("slow", timeout, false),
+        ] {
+            let request = GetAddrInfoRequestV2 {
+                node: node.to_string(),
+                family: AddressFamily::Both,
+            };
+
+            proxy
+                .send(SimpleProxyMessage::AddrInfoReq(
+                    1,
+                    LayerId(0xa55),
+                    request.clone(),
+                ))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                        ClientMessage::GetAddrInfoRequestV2(..)
+                    )))
+                ),
+                "Mismatched message for `GetAddrInfoRequestV2` {update:?}!"
+            );
+
+            proxy
+                .send(SimpleProxyMessage::AddrInfoRes(response.clone()))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    &update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                        message_id: 1,
+                        message: ProxyToLayerMessage::GetAddrInfo(res),
+                        ..
+                    }))) if *res == response
+                ),
+                "Mismatched message for `GetAddrInfoResponse` {update:?}!"
+            );
+
+            proxy
+                .send(SimpleProxyMessage::AddrInfoReq(2, LayerId(0xa55), request))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            if cached {
+                assert!(
+                    matches!(
+                        &update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                            message_id: 2,
+                            message: ProxyToLayerMessage::GetAddrInfo(res),
+                            ..
+                        }))) if *res == response
+                    ),
+                    "Lookup was not answered from the cache {update:?}!"
+                );
+            } else {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::GetAddrInfoRequestV2(..)
+                        )))
+                    ),
+                    "Failed lookup was cached {update:?}!"
+                );
+                proxy.send(SimpleProxyMessage::AddrInfoRes(response)).await;
+                tasks.next().await;
+            }
+        }
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
     }
 
-    /// Convenience for opening a dir.
-    async fn prepare_dir(
-        proxy: &TaskSender<SimpleProxy>,
-        tasks: &mut BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
-    ) {
-        let request = FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd: 0xdad });
+    #[tokio::test]
+    async fn dns_cache_stats_include_agent() {
+        let agent_stats = DnsCacheStats { hits: 3, misses: 1 };
+
+        for (protocol_version, supported) in [
+            (Version::new(1, 20, 0), false),
+            (Version::new(1, 21, 0), true),
+        ] {
+            let mut tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> =
+                Default::default();
+            let dns_cache = DnsCache::new(&DnsCacheConfig::default());
+            let proxy = tasks.register(
+                SimpleProxy::new(None, dns_cache),
+                MainTaskId::SimpleProxy,
+                32,
+            );
+            proxy
+                .send(SimpleProxyMessage::ProtocolVersion(protocol_version))
+                .await;
+
+            proxy
+                .send(SimpleProxyMessage::DnsCacheStatsReq(0xbad, LayerId(0xa55)))
+                .await;
+            let (_, mut update) = tasks.next().await.unzip();
+
+            if supported {
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::GetDnsCacheStats
+                        )))
+                    ),
+                    "Mismatched message for `GetDnsCacheStats` {update:?}!"
+                );
+
+                proxy
+                    .send(SimpleProxyMessage::DnsCacheStatsRes(agent_stats))
+                    .await;
+                (_, update) = tasks.next().await.unzip();
+            }
+
+            let expected = DnsCacheStatsResponse {
+                proxy: Some(DnsCacheStats::default()),
+                agent: supported.then_some(agent_stats),
+            };
+            assert!(
+                matches!(
+                    &update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                        message_id: 0xbad,
+                        layer_id: LayerId(0xa55),
+                        message: ProxyToLayerMessage::DnsCacheStats(res),
+                    }))) if *res == expected
+                ),
+                "Mismatched message for `DnsCacheStatsResponse` {update:?}!"
+            );
+
+            drop(proxy);
+            let results = tasks.results().await;
+            for (_, result) in results {
+                assert!(result.is_ok(), "{result:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_refresh() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 13, 1)).await;
+
+        let stale_fd = open_file(&proxy, &mut tasks, 1).await;
+        assert_eq!(stale_fd, 1);
+
+        // Request that won't get a response from the previous agent.
+        let request = FileRequest::XstatFs(XstatFsRequest { fd: stale_fd });
         proxy
             .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
             .await;
         let (_, update) = tasks.next().await.unzip();
-
         assert!(
             matches!(
                 update,
                 Some(TaskUpdate::Message(ProxyMessage::ToAgent(
-                    ClientMessage::FileRequest(FileRequest::FdOpenDir(FdOpenDirRequest { .. }),)
+                    ClientMessage::FileRequest(FileRequest::XstatFs(..))
                 )))
             ),
-            "Mismatched message for `FdOpenDirRequest` {update:?}!"
+            "Mismatched message for `XstatFsRequest` {update:?}!"
         );
 
-        let response = FileResponse::OpenDir(Ok(OpenDirResponse { fd: 0xdad }));
-        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        proxy.send(SimpleProxyMessage::ConnectionRefresh).await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ConnectionRefresh(
+                    ConnectionRefresh::End
+                )))
+            ),
+            "Mismatched message for `ConnectionRefresh` {update:?}!"
+        );
         let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message: ProxyToLayerMessage::File(FileResponse::XstatFs(Err(
+                        ResponseError::AgentReconnected
+                    ))),
+                    ..
+                })))
+            ),
+            "Mismatched message for failed `XstatFsRequest` {update:?}!"
+        );
+
+        // New agent allocates descriptors from scratch.
+        let fresh_fd = open_file(&proxy, &mut tasks, 1).await;
+        assert_ne!(fresh_fd, stale_fd);
 
+        let request = FileRequest::Read(ReadFileRequest {
+            remote_fd: stale_fd,
+            buffer_size: 1,
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
         assert!(
             matches!(
                 update,
                 Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
-                    message_id: 0xbad,
-                    layer_id: LayerId(0xa55),
-                    message: ProxyToLayerMessage::File(FileResponse::OpenDir(Ok(
-                        OpenDirResponse { .. }
-                    )))
+                    message: ProxyToLayerMessage::File(FileResponse::Read(Err(
+                        ResponseError::StaleResource(1)
+                    ))),
+                    ..
                 })))
             ),
-            "Mismatched message for `OpenDirResponse` {update:?}!"
+            "Mismatched message for stale `ReadFileRequest` {update:?}!"
+        );
+
+        let request = FileRequest::Read(ReadFileRequest {
+            remote_fd: fresh_fd,
+            buffer_size: 1,
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::Read(ReadFileRequest {
+                        remote_fd: 1,
+                        ..
+                    }))
+                )))
+            ),
+            "Mismatched message for `ReadFileRequest` {update:?}!"
         );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
     }
 
     #[tokio::test]
-    async fn old_protocol_uses_read_dir_request() {
-        let (proxy, mut tasks) = setup_proxy(Version::new(0, 1, 0)).await;
+    async fn read_only_file_served_from_cache() {
+        let fs_config = FsConfig {
+            read_only: Some(VecOrSingle::Single("^/etc/hosts$".to_string())),
+            cache: FsCacheConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let file_cache = FileCache::new(&fs_config).unwrap();
+        assert!(file_cache.is_some());
 
-        prepare_dir(&proxy, &mut tasks).await;
+        let mut tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> =
+            Default::default();
+        let proxy = tasks.register(
+            SimpleProxy::new(file_cache, None),
+            MainTaskId::SimpleProxy,
+            32,
+        );
+        proxy
+            .send(SimpleProxyMessage::ProtocolVersion(Version::new(1, 17, 0)))
+            .await;
 
-        let readdir_request = FileRequest::ReadDir(ReadDirRequest { remote_fd: 0xdad });
+        let content = b"127.0.0.1 localhost\n".to_vec();
+        let metadata = MetadataInternal {
+            mode: 0o100644,
+            size: content.len() as u64,
+            modification_time: 1,
+            ..Default::default()
+        };
+        let open_request = FileRequest::Open(OpenFileRequest {
+            path: "/etc/hosts".into(),
+            open_options: OpenOptionsInternal {
+                read: true,
+                ..Default::default()
+            },
+        });
+
+        // First open goes to the agent, and the proxy fetches the content.
         proxy
             .send(SimpleProxyMessage::FileReq(
                 0xbad,
                 LayerId(0xa55),
-                readdir_request,
+                open_request.clone(),
             ))
             .await;
         let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::Open(..))
+                )))
+            ),
+            "Mismatched message for `OpenFileRequest` {update:?}!"
+        );
 
+        let response = FileResponse::Open(Ok(OpenFileResponse { fd: 1 }));
+        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        let (_, update) = tasks.next().await.unzip();
         assert!(
             matches!(
                 update,
                 Some(TaskUpdate::Message(ProxyMessage::ToAgent(
-                    ClientMessage::FileRequest(FileRequest::ReadDir(ReadDirRequest { .. }))
+                    ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+                        fd: Some(1),
+                        ..
+                    }))
                 )))
             ),
-            "Mismatched message for `ReadDirRequest` {update:?}!"
+            "Mismatched message for internal `XstatRequest` {update:?}!"
         );
 
-        let readdir_response = FileResponse::ReadDir(Ok(ReadDirResponse { direntry: None }));
-        proxy
-            .send(SimpleProxyMessage::FileRes(readdir_response))
-            .await;
+        let response = FileResponse::Xstat(Ok(XstatResponse { metadata }));
+        proxy.send(SimpleProxyMessage::FileRes(response)).await;
         let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::ReadLimited(ReadLimitedFileRequest {
+                        remote_fd: 1,
+                        start_from: 0,
+                        ..
+                    }))
+                )))
+            ),
+            "Mismatched message for internal `ReadLimitedFileRequest` {update:?}!"
+        );
 
+        let response = FileResponse::ReadLimited(Ok(ReadFileResponse {
+            read_amount: content.len() as u64,
+            bytes: content.clone(),
+        }));
+        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        let (_, update) = tasks.next().await.unzip();
         assert!(
             matches!(
                 update,
-                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
-                    message_id: 0xbad,
-                    layer_id: LayerId(0xa55),
-                    message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
-                        ReadDirResponse { .. }
-                    )))
-                })))
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::Close(CloseFileRequest { fd: 1 }))
+                )))
             ),
-            "Mismatched message for `ReadDirResponse` {update:?}!"
+            "Mismatched message for internal `CloseFileRequest` {update:?}!"
         );
+        let (_, update) = tasks.next().await.unzip();
+        let cached_fd = match update {
+            Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                message_id: 0xbad,
+                message: ProxyToLayerMessage::File(FileResponse::Open(Ok(OpenFileResponse { fd }))),
+                ..
+            }))) => fd,
+            other => panic!("Mismatched message for `OpenFileResponse` {other:?}!"),
+        };
+
+        // Now everything is served without reaching the agent.
+        let requests = [
+            FileRequest::Read(ReadFileRequest {
+                remote_fd: cached_fd,
+                buffer_size: 9,
+            }),
+            FileRequest::Xstat(XstatRequest {
+                path: Some("/etc/hosts".into()),
+                fd: None,
+                follow_symlink: true,
+            }),
+            open_request,
+        ];
+        for request in requests {
+            proxy
+                .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+
+            match update {
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message: ProxyToLayerMessage::File(response),
+                    ..
+                }))) => match response {
+                    FileResponse::Read(Ok(ReadFileResponse { bytes, .. })) => {
+                        assert_eq!(bytes, b"127.0.0.1")
+                    }
+                    FileResponse::Xstat(Ok(XstatResponse { metadata: cached })) => {
+                        assert_eq!(cached, metadata)
+                    }
+                    FileResponse::Open(Ok(OpenFileResponse { fd })) => {
+                        assert_ne!(fd, cached_fd)
+                    }
+                    other => panic!("Unexpected response from the cache {other:?}!"),
+                },
+                other => panic!("Request was not served from the cache {other:?}!"),
+            }
+        }
 
         drop(proxy);
         let results = tasks.results().await;
@@ -514,58 +2451,135 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn new_protocol_uses_read_dir_batch_request() {
-        let (proxy, mut tasks) = setup_proxy(Version::new(1, 8, 3)).await;
+    async fn watch_events_sent_to_layer_session() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 18, 0)).await;
+        let instance = WatchInstance {
+            events_to: LayerId(0xe7),
+            id: 3,
+        };
 
-        prepare_dir(&proxy, &mut tasks).await;
-
-        let request = FileRequest::ReadDirBatch(ReadDirBatchRequest {
-            remote_fd: 0xdad,
-            amount: 0xca7,
-        });
+        let subscribe = WatchSubscribe {
+            request: AddWatchRequest {
+                path: "/app/config".into(),
+                mask: 0x2,
+            },
+            instance,
+            wd: 1 << 24,
+        };
         proxy
-            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+            .send(SimpleProxyMessage::FileWatchReq(
+                0xbad,
+                LayerId(0xa55),
+                FileWatchRequest::Subscribe(subscribe),
+            ))
             .await;
         let (_, update) = tasks.next().await.unzip();
-
         assert!(
             matches!(
                 update,
                 Some(TaskUpdate::Message(ProxyMessage::ToAgent(
-                    ClientMessage::FileRequest(FileRequest::ReadDirBatch(ReadDirBatchRequest {
-                        remote_fd: 0xdad,
-                        amount: 0xca7
+                    ClientMessage::FileRequest(FileRequest::AddWatch(AddWatchRequest {
+                        mask: 0x2,
+                        ..
                     }))
                 )))
             ),
-            "Mismatched message for `ReadDirBatchRequest` {update:?}!"
+            "{update:?}"
         );
 
-        let response = FileResponse::ReadDirBatch(Ok(ReadDirBatchResponse {
-            fd: 0xdad,
-            dir_entries: Vec::new(),
-        }));
-        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        proxy
+            .send(SimpleProxyMessage::FileRes(FileResponse::AddWatch(Ok(
+                AddWatchResponse { watch_id: 7 },
+            ))))
+            .await;
         let (_, update) = tasks.next().await.unzip();
-
         assert!(
             matches!(
                 update,
                 Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
                     message_id: 0xbad,
                     layer_id: LayerId(0xa55),
-                    message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
-                        ReadDirResponse { .. }
-                    )))
+                    message: ProxyToLayerMessage::FileWatch(Ok(0x0100_0000)),
                 })))
             ),
-            "Mismatched message for `ReadDirBatchResponse` {update:?}!"
+            "{update:?}"
         );
 
-        drop(proxy);
-        let results = tasks.results().await;
-        for (_, result) in results {
-            assert!(result.is_ok(), "{result:?}");
-        }
+        // Not in the subscribed mask.
+        proxy
+            .send(SimpleProxyMessage::FileWatchEvent(FileWatchEvent {
+                watch_id: 7,
+                mask: 0x100,
+                cookie: 0,
+                name: Some("new.yaml".to_string()),
+            }))
+            .await;
+        proxy
+            .send(SimpleProxyMessage::FileWatchEvent(FileWatchEvent {
+                watch_id: 7,
+                mask: 0x2,
+                cookie: 0,
+                name: Some("config.yaml".to_string()),
+            }))
+            .await;
+
+        let (_, update) = tasks.next().await.unzip();
+        let Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+            layer_id: LayerId(0xe7),
+            message:
+                ProxyToLayerMessage::FileWatchEvent(WatchEvent {
+                    instance: 3,
+                    record,
+                }),
+            ..
+        }))) = update
+        else {
+            panic!("unexpected update {update:?}");
+        };
+        let record: [u8; 32] = record.try_into().unwrap();
+        assert_eq!(record[0..4], (1_i32 << 24).to_ne_bytes());
+        assert_eq!(record[4..8], 0x2_u32.to_ne_bytes());
+        assert_eq!(record[12..16], 16_u32.to_ne_bytes());
+        assert_eq!(&record[16..28], b"config.yaml\0");
+
+        proxy
+            .send(SimpleProxyMessage::FileWatchReq(
+                0xbad,
+                LayerId(0xa55),
+                FileWatchRequest::Unsubscribe(WatchUnsubscribe {
+                    instance,
+                    wd: 1 << 24,
+                }),
+            ))
+            .await;
+
+        let (_, update) = tasks.next().await.unzip();
+        let Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+            layer_id: LayerId(0xe7),
+            message:
+                ProxyToLayerMessage::FileWatchEvent(WatchEvent {
+                    instance: 3,
+                    record,
+                }),
+            ..
+        }))) = update
+        else {
+            panic!("unexpected update {update:?}");
+        };
+        let record: [u8; 16] = record.try_into().unwrap();
+        assert_eq!(record[4..8], 0x8000_u32.to_ne_bytes());
+
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::RemoveWatch(RemoveWatchRequest {
+                        watch_id: 7
+                    }))
+                )))
+            ),
+            "{update:?}"
+        );
     }
 }
@@ -0,0 +1,329 @@
+//! Client-side cache of the remote DNS answers, see [`DnsCache`].
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use mirrord_config::feature::network::dns::DnsCacheConfig;
+use mirrord_protocol::{
+    dns::{
+        DnsCacheStats, DnsQueryRequest, DnsQueryResponse, GetAddrInfoRequestV2,
+        GetAddrInfoResponse, ReverseLookupRequest, ReverseLookupResponse,
+    },
+    DnsLookupError, RemoteResult, ResolveErrorKindInternal, ResponseError,
+};
+
+/// Identifies the requests that share their answers.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum CacheKey {
+    AddrInfo(GetAddrInfoRequestV2),
+    Query(DnsQueryRequest),
+    ReverseLookup(ReverseLookupRequest),
+}
+
+#[derive(Clone, Debug)]
+enum CachedAnswer {
+    AddrInfo(GetAddrInfoResponse),
+    Query(DnsQueryResponse),
+    ReverseLookup(ReverseLookupResponse),
+}
+
+/// Caches the answers of [`GetAddrInfoRequestV2`]s, [`DnsQueryRequest`]s and
+/// [`ReverseLookupRequest`]s.
+///
+/// Spares the round trips to the agent when the application keeps resolving the same names.
+///
+/// Successful answers are trusted for the configured TTL (or until the first of their records
+/// expires). Only the lookups that found no records are cached as failures, errors like timeouts
+/// are never cached. When the cache is full, the answer closest to its expiration is evicted.
+pub struct DnsCache {
+    ttl: Duration,
+    negative_ttl: Duration,
+    max_entries: usize,
+    /// Answers with the time when they expire.
+    entries: HashMap<CacheKey, (CachedAnswer, Instant)>,
+    stats: DnsCacheStats,
+}
+
+impl DnsCache {
+    /// Creates a new cache with the given [`DnsCacheConfig`].
+    ///
+    /// Returns [`None`] if the cache is disabled.
+    pub fn new(config: &DnsCacheConfig) -> Option<Self> {
+        if !config.enabled || config.max_entries == 0 {
+            return None;
+        }
+
+        Some(Self {
+            ttl: Duration::from_secs(config.ttl),
+            negative_ttl: Duration::from_secs(config.negative_ttl),
+            max_entries: config.max_entries,
+            entries: Default::default(),
+            stats: Default::default(),
+        })
+    }
+
+    /// Hits and misses of this cache so far.
+    pub fn stats(&self) -> DnsCacheStats {
+        self.stats
+    }
+
+    /// Returns the cached answer to the given [`GetAddrInfoRequestV2`], if any.
+    pub fn get_addr_info(&mut self, request: &GetAddrInfoRequestV2) -> Option<GetAddrInfoResponse> {
+        match self.get(&CacheKey::AddrInfo(request.clone()))? {
+            CachedAnswer::AddrInfo(response) => Some(response),
+            CachedAnswer::Query(..) | CachedAnswer::ReverseLookup(..) => None,
+        }
+    }
+
+    /// Caches the answer to the given [`GetAddrInfoRequestV2`].
+    pub fn insert_addr_info(
+        &mut self,
+        request: &GetAddrInfoRequestV2,
+        response: &GetAddrInfoResponse,
+    ) {
+        if let Some(ttl) = self.answer_ttl(&response.0, None) {
+            self.insert(
+                CacheKey::AddrInfo(request.clone()),
+                CachedAnswer::AddrInfo(response.clone()),
+                ttl,
+            );
+        }
+    }
+
+    /// Returns the cached answer to the given [`DnsQueryRequest`], if any.
+    pub fn get_query(&mut self, request: &DnsQueryRequest) -> Option<DnsQueryResponse> {
+        match self.get(&CacheKey::Query(request.clone()))? {
+            CachedAnswer::Query(response) => Some(response),
+            CachedAnswer::AddrInfo(..) | CachedAnswer::ReverseLookup(..) => None,
+        }
+    }
+
+    /// Caches the answer to the given [`DnsQueryRequest`], for no longer than its records live.
+    pub fn insert_query(&mut self, request: &DnsQueryRequest, response: &DnsQueryResponse) {
+        let records_ttl = response
+            .as_ref()
+            .ok()
+            .and_then(|records| records.iter().map(|record| record.ttl).min())
+            .map(|ttl| Duration::from_secs(ttl.into()));
+
+        if let Some(ttl) = self.answer_ttl(&response.0, records_ttl) {
+            self.insert(
+                CacheKey::Query(request.clone()),
+                CachedAnswer::Query(response.clone()),
+                ttl,
+            );
+        }
+    }
+
+    /// Returns the cached answer to the given [`ReverseLookupRequest`], if any.
+    pub fn get_reverse_lookup(
+        &mut self,
+        request: &ReverseLookupRequest,
+    ) -> Option<ReverseLookupResponse> {
+        match self.get(&CacheKey::ReverseLookup(request.clone()))? {
+            CachedAnswer::ReverseLookup(response) => Some(response),
+            CachedAnswer::AddrInfo(..) | CachedAnswer::Query(..) => None,
+        }
+    }
+
+    /// Caches the answer to the given [`ReverseLookupRequest`].
+    pub fn insert_reverse_lookup(
+        &mut self,
+        request: &ReverseLookupRequest,
+        response: &ReverseLookupResponse,
+    ) {
+        if let Some(ttl) = self.answer_ttl(&response.0, None) {
+            self.insert(
+                CacheKey::ReverseLookup(request.clone()),
+                CachedAnswer::ReverseLookup(response.clone()),
+                ttl,
+            );
+        }
+    }
+
+    /// For how long the given answer can be cached, [`None`] if it should not be cached.
+    fn answer_ttl<T>(
+        &self,
+        answer: &RemoteResult<T>,
+        records_ttl: Option<Duration>,
+    ) -> Option<Duration> {
+        let ttl = match answer {
+            Ok(..) => records_ttl.map_or(self.ttl, |records_ttl| records_ttl.min(self.ttl)),
+            Err(ResponseError::DnsLookup(DnsLookupError {
+                kind: ResolveErrorKindInternal::NoRecordsFound(..),
+            })) => self.negative_ttl,
+            Err(..) => return None,
+        };
+
+        (!ttl.is_zero()).then_some(ttl)
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<CachedAnswer> {
+        let now = Instant::now();
+
+        let answer = match self.entries.get(key) {
+            Some((answer, expires)) if *expires > now => Some(answer.clone()),
+            Some(..) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+
+        if answer.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+
+        answer
+    }
+
+    fn insert(&mut self, key: CacheKey, answer: CachedAnswer, ttl: Duration) {
+        let now = Instant::now();
+
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&key) {
+            self.entries.retain(|_, (_, expires)| *expires > now);
+        }
+
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&key) {
+            let closest_to_expire = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, expires))| *expires)
+                .map(|(key, _)| key.clone());
+
+            if let Some(closest_to_expire) = closest_to_expire {
+                self.entries.remove(&closest_to_expire);
+            }
+        }
+
+        self.entries.insert(key, (answer, now + ttl));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use mirrord_protocol::dns::{AddressFamily, DnsLookup, DnsRecord, LookupRecord};
+
+    use super::*;
+
+    fn cache(ttl: Duration, negative_ttl: Duration, max_entries: usize) -> DnsCache {
+        DnsCache {
+            ttl,
+            negative_ttl,
+            max_entries,
+            entries: Default::default(),
+            stats: Default::default(),
+        }
+    }
+
+    fn addr_info_request(node: &str) -> GetAddrInfoRequestV2 {
+        GetAddrInfoRequestV2 {
+            node: node.to_string(),
+            family: AddressFamily::Both,
+        }
+    }
+
+    fn addr_info_response(node: &str) -> GetAddrInfoResponse {
+        GetAddrInfoResponse(Ok(DnsLookup(vec![LookupRecord {
+            name: node.to_string(),
+            ip: "10.0.0.1".parse().unwrap(),
+        }])))
+    }
+
+    fn query_request(name: &str) -> DnsQueryRequest {
+        DnsQueryRequest {
+            name: name.to_string(),
+            record_type: 16,
+        }
+    }
+
+    fn query_response(name: &str, ttl: u32) -> DnsQueryResponse {
+        DnsQueryResponse(Ok(vec![DnsRecord {
+            name: name.to_string(),
+            record_type: 16,
+            ttl,
+            rdata: b"\x02ok".to_vec(),
+        }]))
+    }
+
+    fn not_found<T>() -> RemoteResult<T> {
+        Err(ResponseError::DnsLookup(DnsLookupError {
+            kind: ResolveErrorKindInternal::NoRecordsFound(3),
+        }))
+    }
+
+    #[test]
+    fn answers_expire() {
+        let mut cache = cache(Duration::from_millis(50), Duration::from_secs(60), 8);
+        let request = addr_info_request("app.svc");
+
+        cache.insert_addr_info(&request, &addr_info_response("app.svc"));
+        assert_eq!(
+            cache.get_addr_info(&request),
+            Some(addr_info_response("app.svc"))
+        );
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(cache.get_addr_info(&request).is_none());
+
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn records_ttl_limits_query_answers() {
+        let mut cache = cache(Duration::from_secs(60), Duration::from_secs(60), 8);
+        let request = query_request("_http._tcp.app.svc.");
+
+        cache.insert_query(&request, &query_response("_http._tcp.app.svc.", 0));
+        assert!(cache.get_query(&request).is_none());
+
+        cache.insert_query(&request, &query_response("_http._tcp.app.svc.", 30));
+        assert_eq!(
+            cache.get_query(&request),
+            Some(query_response("_http._tcp.app.svc.", 30))
+        );
+    }
+
+    #[test]
+    fn only_missing_records_cached_as_failures() {
+        let request = addr_info_request("missing.svc");
+
+        let mut negative = cache(Duration::from_secs(60), Duration::from_secs(60), 8);
+        negative.insert_addr_info(
+            &request,
+            &GetAddrInfoResponse(Err(ResponseError::AgentReconnected)),
+        );
+        assert!(negative.get_addr_info(&request).is_none());
+
+        negative.insert_addr_info(&request, &GetAddrInfoResponse(not_found()));
+        assert_eq!(
+            negative.get_addr_info(&request),
+            Some(GetAddrInfoResponse(not_found()))
+        );
+
+        let mut positive_only = cache(Duration::from_secs(60), Duration::ZERO, 8);
+        positive_only.insert_addr_info(&request, &GetAddrInfoResponse(not_found()));
+        assert!(positive_only.get_addr_info(&request).is_none());
+    }
+
+    #[test]
+    fn closest_to_expiration_evicted() {
+        let mut cache = cache(Duration::from_secs(600), Duration::from_secs(60), 2);
+        let [short, long, new] = ["short.svc.", "long.svc.", "new.svc."].map(query_request);
+
+        cache.insert_query(&short, &query_response("short.svc.", 10));
+        cache.insert_query(&long, &query_response("long.svc.", 100));
+        cache.insert_query(&new, &query_response("new.svc.", 50));
+
+        assert!(cache.get_query(&short).is_none());
+        assert!(cache.get_query(&long).is_some());
+        assert!(cache.get_query(&new).is_some());
+    }
+}
@@ -0,0 +1,362 @@
+//! Client-side cache of the remote files matched by `feature.fs.read_only`, see [`FileCache`].
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mirrord_config::feature::fs::FsConfig;
+use mirrord_protocol::file::{DirEntryInternal, MetadataInternal};
+use regex::{RegexSet, RegexSetBuilder};
+
+/// Descriptors of the files and directories served from the [`FileCache`] are allocated from
+/// here up, so that they never collide with the descriptors allocated by the agent.
+const CACHED_FD_BASE: u64 = 1 << 62;
+
+/// Bit mask for the file type in [`MetadataInternal::mode`].
+const S_IFMT: u32 = 0o170000;
+
+/// Regular file type in [`MetadataInternal::mode`].
+const S_IFREG: u32 = 0o100000;
+
+/// Content of a remote file, valid for as long as the file keeps its modification time and size.
+struct CachedContent {
+    modification_time: i64,
+    bytes: Arc<[u8]>,
+    last_used: Instant,
+}
+
+/// Everything we know about a single remote path.
+#[derive(Default)]
+struct CacheEntry {
+    /// Metadata with symlinks followed, and the time when it was fetched.
+    metadata: Option<(MetadataInternal, Instant)>,
+    content: Option<CachedContent>,
+    /// Complete listing of the directory, and the time when it was fetched.
+    dir_entries: Option<(Vec<DirEntryInternal>, Instant)>,
+}
+
+/// Caches the content, metadata and directory listings of the remote paths matched by
+/// `feature.fs.read_only`.
+///
+/// Spares the round trips to the agent when the application keeps reading and stating the same
+/// files.
+///
+/// Content is keyed by the path, and is used only while the remote file keeps the same
+/// modification time and size. Metadata and directory listings are trusted for the configured
+/// TTL. The total size of the cached content is limited, least recently used content is evicted
+/// first.
+pub struct FileCache {
+    read_only: RegexSet,
+    max_size: u64,
+    ttl: Duration,
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// Total size of the cached content.
+    content_size: u64,
+    /// Next descriptor to allocate for a file or directory served from the cache.
+    next_fd: u64,
+    /// Cacheable files and directories opened in the agent by the layer, by their descriptors.
+    open_paths: HashMap<u64, PathBuf>,
+    /// Cacheable directories being listed by the layer, by their descriptors, with the entries
+    /// received so far.
+    listings: HashMap<u64, (PathBuf, Vec<DirEntryInternal>)>,
+}
+
+impl FileCache {
+    /// Creates a new cache for the paths matched by [`FsConfig::read_only`].
+    ///
+    /// Returns [`None`] if the cache is disabled, or there is nothing to cache.
+    pub fn new(config: &FsConfig) -> Result<Option<Self>, regex::Error> {
+        let patterns = config.read_only.as_deref().unwrap_or_default();
+
+        if !config.cache.enabled || !config.is_active() || patterns.is_empty() {
+            return Ok(None);
+        }
+
+        let read_only = RegexSetBuilder::new(patterns)
+            .case_insensitive(true)
+            .build()?;
+
+        Ok(Some(Self {
+            read_only,
+            max_size: config.cache.max_size,
+            ttl: Duration::from_secs(config.cache.ttl),
+            entries: Default::default(),
+            content_size: 0,
+            next_fd: CACHED_FD_BASE,
+            open_paths: Default::default(),
+            listings: Default::default(),
+        }))
+    }
+
+    /// Whether the given descriptor belongs to a file or directory served from the cache.
+    pub(crate) fn is_cached_fd(fd: u64) -> bool {
+        fd >= CACHED_FD_BASE
+    }
+
+    /// Whether the given remote path should be cached.
+    pub(crate) fn is_cacheable(&self, path: &Path) -> bool {
+        path.is_absolute()
+            && path
+                .to_str()
+                .is_some_and(|path| self.read_only.is_match(path))
+    }
+
+    /// Whether we can cache the content of the file with the given metadata.
+    ///
+    /// Files reporting no size are skipped, as their content is usually generated on read (e.g.
+    /// under `/proc`).
+    pub(crate) fn can_cache_content(&self, metadata: &MetadataInternal) -> bool {
+        metadata.mode & S_IFMT == S_IFREG && metadata.size > 0 && metadata.size <= self.max_size
+    }
+
+    /// Allocates a descriptor for a file or directory served from the cache.
+    pub(crate) fn next_fd(&mut self) -> u64 {
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        fd
+    }
+
+    /// Returns the metadata of the given path, if it was fetched within the TTL.
+    pub(crate) fn metadata(&self, path: &Path) -> Option<&MetadataInternal> {
+        self.entries
+            .get(path)?
+            .metadata
+            .as_ref()
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+            .map(|(metadata, _)| metadata)
+    }
+
+    pub(crate) fn store_metadata(&mut self, path: PathBuf, metadata: MetadataInternal) {
+        self.entries.entry(path).or_default().metadata = Some((metadata, Instant::now()));
+    }
+
+    /// Returns the content of the given path, if it was cached when the file had the same
+    /// modification time and size.
+    pub(crate) fn content(
+        &mut self,
+        path: &Path,
+        metadata: &MetadataInternal,
+    ) -> Option<Arc<[u8]>> {
+        let content = self.entries.get_mut(path)?.content.as_mut()?;
+
+        if content.modification_time != metadata.modification_time
+            || content.bytes.len() as u64 != metadata.size
+        {
+            return None;
+        }
+
+        content.last_used = Instant::now();
+        Some(content.bytes.clone())
+    }
+
+    /// Stores the content of the given path, evicting the least recently used content to stay
+    /// within the size limit.
+    pub(crate) fn store_content(
+        &mut self,
+        path: PathBuf,
+        metadata: &MetadataInternal,
+        bytes: Vec<u8>,
+    ) -> Arc<[u8]> {
+        let bytes: Arc<[u8]> = bytes.into();
+        let size = bytes.len() as u64;
+
+        if let Some(previous) = self
+            .entries
+            .get_mut(&path)
+            .and_then(|entry| entry.content.take())
+        {
+            self.content_size -= previous.bytes.len() as u64;
+        }
+
+        while self.content_size + size > self.max_size {
+            let Some(evicted) = self
+                .entries
+                .values_mut()
+                .filter(|entry| entry.content.is_some())
+                .min_by_key(|entry| entry.content.as_ref().map(|content| content.last_used))
+                .and_then(|entry| entry.content.take())
+            else {
+                break;
+            };
+
+            self.content_size -= evicted.bytes.len() as u64;
+        }
+
+        self.content_size += size;
+        self.entries.entry(path).or_default().content = Some(CachedContent {
+            modification_time: metadata.modification_time,
+            bytes: bytes.clone(),
+            last_used: Instant::now(),
+        });
+
+        bytes
+    }
+
+    /// Returns the listing of the given directory, if it was fetched within the TTL.
+    pub(crate) fn dir_entries(&self, path: &Path) -> Option<Vec<DirEntryInternal>> {
+        self.entries
+            .get(path)?
+            .dir_entries
+            .as_ref()
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+            .map(|(entries, _)| entries.clone())
+    }
+
+    /// Remembers the path of a cacheable file or directory opened in the agent.
+    pub(crate) fn file_opened(&mut self, fd: u64, path: PathBuf) {
+        self.open_paths.insert(fd, path);
+    }
+
+    /// Returns the path of a cacheable file or directory opened in the agent.
+    pub(crate) fn open_path(&self, fd: u64) -> Option<&Path> {
+        self.open_paths.get(&fd).map(PathBuf::as_path)
+    }
+
+    pub(crate) fn file_closed(&mut self, fd: u64) {
+        self.open_paths.remove(&fd);
+    }
+
+    /// Starts collecting the listing of a cacheable directory opened in the agent.
+    pub(crate) fn listing_started(&mut self, dir_fd: u64, path: PathBuf) {
+        self.listings.insert(dir_fd, (path, Vec::new()));
+    }
+
+    /// Adds a batch of entries read by the layer to the listing of the directory.
+    ///
+    /// An empty batch means that the layer has seen the whole directory, so we cache the listing.
+    pub(crate) fn listing_progress(&mut self, dir_fd: u64, entries: &[DirEntryInternal]) {
+        if !entries.is_empty() {
+            if let Some((_, listing)) = self.listings.get_mut(&dir_fd) {
+                listing.extend_from_slice(entries);
+            }
+
+            return;
+        }
+
+        if let Some((path, listing)) = self.listings.remove(&dir_fd) {
+            self.entries.entry(path).or_default().dir_entries = Some((listing, Instant::now()));
+        }
+    }
+
+    pub(crate) fn listing_closed(&mut self, dir_fd: u64) {
+        self.listings.remove(&dir_fd);
+    }
+
+    /// Forgets the files and directories opened in the previous agent, keeps the cached data.
+    pub(crate) fn connection_refreshed(&mut self) {
+        self.open_paths.clear();
+        self.listings.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cache(max_size: u64, ttl: Duration) -> FileCache {
+        FileCache {
+            read_only: RegexSetBuilder::new(["^/etc/"]).build().unwrap(),
+            max_size,
+            ttl,
+            entries: Default::default(),
+            content_size: 0,
+            next_fd: CACHED_FD_BASE,
+            open_paths: Default::default(),
+            listings: Default::default(),
+        }
+    }
+
+    fn file_metadata(size: u64, modification_time: i64) -> MetadataInternal {
+        MetadataInternal {
+            mode: S_IFREG | 0o644,
+            size,
+            modification_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn content_invalidated_on_change() {
+        let mut cache = cache(1024, Duration::from_secs(60));
+        let path = PathBuf::from("/etc/hosts");
+        let metadata = file_metadata(5, 1);
+
+        cache.store_content(path.clone(), &metadata, b"hello".to_vec());
+        assert_eq!(
+            cache.content(&path, &metadata).as_deref(),
+            Some(b"hello".as_slice())
+        );
+
+        assert!(cache.content(&path, &file_metadata(5, 2)).is_none());
+        assert!(cache.content(&path, &file_metadata(6, 1)).is_none());
+        assert!(cache.content(Path::new("/etc/passwd"), &metadata).is_none());
+    }
+
+    #[test]
+    fn metadata_and_listing_expire() {
+        let path = PathBuf::from("/etc");
+        let entry = DirEntryInternal {
+            inode: 1,
+            position: 0,
+            name: "hosts".into(),
+            file_type: 0,
+        };
+
+        for (ttl, fresh) in [(Duration::from_secs(60), true), (Duration::ZERO, false)] {
+            let mut cache = cache(1024, ttl);
+
+            cache.store_metadata(path.clone(), file_metadata(0, 1));
+            cache.listing_started(1, path.clone());
+            cache.listing_progress(1, &[entry.clone()]);
+            assert!(
+                cache.dir_entries(&path).is_none(),
+                "listing not complete yet"
+            );
+            cache.listing_progress(1, &[]);
+
+            assert_eq!(cache.metadata(&path).is_some(), fresh);
+            assert_eq!(cache.dir_entries(&path), fresh.then(|| vec![entry.clone()]));
+        }
+    }
+
+    #[test]
+    fn least_recently_used_evicted() {
+        let mut cache = cache(10, Duration::from_secs(60));
+        let metadata = file_metadata(4, 1);
+        let [first, second, third] = ["/etc/a", "/etc/b", "/etc/c"].map(PathBuf::from);
+
+        cache.store_content(first.clone(), &metadata, b"aaaa".to_vec());
+        cache.store_content(second.clone(), &metadata, b"bbbb".to_vec());
+        // Makes `second` the least recently used.
+        assert!(cache.content(&first, &metadata).is_some());
+
+        cache.store_content(third.clone(), &metadata, b"cccc".to_vec());
+        assert!(cache.content(&first, &metadata).is_some());
+        assert!(cache.content(&second, &metadata).is_none());
+        assert!(cache.content(&third, &metadata).is_some());
+        assert_eq!(cache.content_size, 8);
+
+        // Replacing the content of a path doesn't count the previous content.
+        cache.store_content(third.clone(), &file_metadata(2, 2), b"cc".to_vec());
+        assert!(cache.content(&first, &metadata).is_some());
+        assert_eq!(cache.content_size, 6);
+    }
+
+    #[test]
+    fn cached_fds_out_of_agent_range() {
+        let mut cache = cache(1024, Duration::from_secs(60));
+
+        let first = cache.next_fd();
+        let second = cache.next_fd();
+        assert_eq!(first, CACHED_FD_BASE);
+        assert_eq!(second, CACHED_FD_BASE + 1);
+        assert!(FileCache::is_cached_fd(first));
+        assert!(FileCache::is_cached_fd(second));
+
+        assert!(!FileCache::is_cached_fd(0));
+        assert!(!FileCache::is_cached_fd(CACHED_FD_BASE - 1));
+    }
+}
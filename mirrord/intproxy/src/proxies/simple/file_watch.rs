@@ -0,0 +1,244 @@
+//! Delivery of the remote file watch events to the inotify instances emulated by the layer, see
+//! [`FileWatches`].
+
+use std::collections::HashMap;
+
+use mirrord_intproxy_protocol::{
+    LayerId, ProxyToLayerMessage, WatchEvent, WatchInstance, WatchSubscribe, WatchUnsubscribe,
+};
+use mirrord_protocol::file::FileWatchEvent;
+
+use crate::main_tasks::ToLayer;
+
+/// Events that can be requested in the watch mask.
+const IN_ALL_EVENTS: u32 = 0x0fff;
+
+/// Extends the mask of an existing watch, instead of replacing it.
+const IN_MASK_ADD: u32 = 0x2000_0000;
+
+/// Filesystem containing the watched file was unmounted.
+const IN_UNMOUNT: u32 = 0x2000;
+
+/// Event queue overflowed.
+const IN_Q_OVERFLOW: u32 = 0x4000;
+
+/// Watch was removed.
+const IN_IGNORED: u32 = 0x8000;
+
+/// Size of `struct inotify_event` without the name. The names are padded to its multiple.
+const INOTIFY_EVENT_SIZE: usize = 16;
+
+/// A remote watch requested by an inotify instance emulated by the layer.
+#[derive(Debug)]
+struct Subscription {
+    layer_id: LayerId,
+    instance: WatchInstance,
+    wd: i32,
+    mask: u32,
+}
+
+impl Subscription {
+    /// Whether the event with the given mask should be delivered to this subscription.
+    fn wants(&self, event_mask: u32) -> bool {
+        event_mask & IN_ALL_EVENTS & self.mask != 0
+            || event_mask & (IN_UNMOUNT | IN_Q_OVERFLOW | IN_IGNORED) != 0
+    }
+}
+
+/// Tracks which inotify instances emulated by the layer use which remote watches.
+///
+/// The agent keeps a single watch per remote file, so the inotify instances share the watches.
+/// Each instance gets the events matching its own mask, with its own watch descriptor, encoded as
+/// `inotify_event` records. The methods return the [`ToLayer`] messages with the events, to be
+/// sent in the layer's events session.
+#[derive(Default)]
+pub(crate) struct FileWatches {
+    /// Subscriptions by the ids of the agent's watches.
+    subscriptions: HashMap<u64, Vec<Subscription>>,
+}
+
+impl FileWatches {
+    /// Registers a subscription after the agent started the watch.
+    ///
+    /// Returns the watch descriptor for the layer, which is the existing one if the inotify
+    /// instance already watches the same remote file.
+    pub(crate) fn subscribed(
+        &mut self,
+        layer_id: LayerId,
+        subscribe: WatchSubscribe,
+        watch_id: u64,
+    ) -> i32 {
+        let WatchSubscribe {
+            request,
+            instance,
+            wd,
+        } = subscribe;
+        let subscriptions = self.subscriptions.entry(watch_id).or_default();
+
+        if let Some(existing) = subscriptions
+            .iter_mut()
+            .find(|subscription| subscription.instance == instance)
+        {
+            if request.mask & IN_MASK_ADD != 0 {
+                existing.mask |= request.mask;
+            } else {
+                existing.mask = request.mask;
+            }
+
+            return existing.wd;
+        }
+
+        subscriptions.push(Subscription {
+            layer_id,
+            instance,
+            wd,
+            mask: request.mask,
+        });
+
+        wd
+    }
+
+    /// Removes a subscription, and notifies the inotify instance with `IN_IGNORED`.
+    ///
+    /// Returns the notification, and the id of the agent's watch if it's no longer used.
+    pub(crate) fn unsubscribe(
+        &mut self,
+        unsubscribe: WatchUnsubscribe,
+    ) -> Option<(ToLayer, Option<u64>)> {
+        let WatchUnsubscribe { instance, wd } = unsubscribe;
+
+        let (watch_id, subscription) =
+            self.subscriptions
+                .iter_mut()
+                .find_map(|(watch_id, subscriptions)| {
+                    let position = subscriptions.iter().position(|subscription| {
+                        subscription.instance == instance && subscription.wd == wd
+                    })?;
+
+                    Some((*watch_id, subscriptions.swap_remove(position)))
+                })?;
+
+        let ignored = event_to_layer(&subscription, IN_IGNORED, 0, None);
+
+        Some((ignored, self.forget_if_unused(watch_id)))
+    }
+
+    /// Removes the subscriptions of the given layer.
+    ///
+    /// Returns the ids of the agent's watches that are no longer used.
+    pub(crate) fn layer_closed(&mut self, layer_id: LayerId) -> Vec<u64> {
+        let mut unused = Vec::new();
+
+        self.subscriptions.retain(|watch_id, subscriptions| {
+            subscriptions.retain(|subscription| subscription.layer_id != layer_id);
+
+            if subscriptions.is_empty() {
+                unused.push(*watch_id);
+            }
+
+            !subscriptions.is_empty()
+        });
+
+        unused
+    }
+
+    /// Delivers an event of the agent's watch to the subscribed inotify instances.
+    pub(crate) fn event_received(&mut self, event: FileWatchEvent) -> Vec<ToLayer> {
+        let FileWatchEvent {
+            watch_id,
+            mask,
+            cookie,
+            name,
+        } = event;
+
+        // The agent's watch is gone, so are the subscriptions.
+        let subscriptions = if mask & IN_IGNORED != 0 {
+            self.subscriptions.remove(&watch_id).unwrap_or_default()
+        } else {
+            self.subscriptions
+                .get_mut(&watch_id)
+                .map(std::mem::take)
+                .unwrap_or_default()
+        };
+
+        let events = subscriptions
+            .iter()
+            .filter(|subscription| subscription.wants(mask))
+            .map(|subscription| event_to_layer(subscription, mask, cookie, name.as_deref()))
+            .collect();
+
+        if mask & IN_IGNORED == 0 && !subscriptions.is_empty() {
+            self.subscriptions.insert(watch_id, subscriptions);
+        }
+
+        events
+    }
+
+    /// The watches of the previous agent are gone, so we remove all subscriptions and notify the
+    /// inotify instances with `IN_IGNORED`.
+    pub(crate) fn connection_refreshed(&mut self) -> Vec<ToLayer> {
+        std::mem::take(&mut self.subscriptions)
+            .values()
+            .flatten()
+            .map(|subscription| event_to_layer(subscription, IN_IGNORED, 0, None))
+            .collect()
+    }
+
+    fn forget_if_unused(&mut self, watch_id: u64) -> Option<u64> {
+        if self
+            .subscriptions
+            .get(&watch_id)
+            .is_some_and(|subscriptions| !subscriptions.is_empty())
+        {
+            return None;
+        }
+
+        self.subscriptions.remove(&watch_id);
+        Some(watch_id)
+    }
+}
+
+/// Prepares a single `inotify_event` for the inotify instance of the subscription.
+///
+/// The layer drops the events of the instances that were closed in the meantime.
+fn event_to_layer(
+    subscription: &Subscription,
+    mask: u32,
+    cookie: u32,
+    name: Option<&str>,
+) -> ToLayer {
+    let wd = if mask & IN_Q_OVERFLOW != 0 {
+        -1
+    } else {
+        subscription.wd
+    };
+
+    ToLayer {
+        message_id: 0,
+        layer_id: subscription.instance.events_to,
+        message: ProxyToLayerMessage::FileWatchEvent(WatchEvent {
+            instance: subscription.instance.id,
+            record: encode_event(wd, mask, cookie, name),
+        }),
+    }
+}
+
+/// Encodes a `struct inotify_event`, with the name NUL terminated and padded like the kernel does.
+fn encode_event(wd: i32, mask: u32, cookie: u32, name: Option<&str>) -> Vec<u8> {
+    let name = name.unwrap_or_default().as_bytes();
+    let name_len = if name.is_empty() {
+        0
+    } else {
+        (name.len() + 1).next_multiple_of(INOTIFY_EVENT_SIZE)
+    };
+
+    let mut event = Vec::with_capacity(INOTIFY_EVENT_SIZE + name_len);
+    event.extend_from_slice(&wd.to_ne_bytes());
+    event.extend_from_slice(&mask.to_ne_bytes());
+    event.extend_from_slice(&cookie.to_ne_bytes());
+    event.extend_from_slice(&(name_len as u32).to_ne_bytes());
+    event.extend_from_slice(name);
+    event.resize(INOTIFY_EVENT_SIZE + name_len, 0);
+
+    event
+}
@@ -27,17 +27,28 @@ pub struct RequestQueueEmpty;
 /// A queue used to match agent responses with layer requests.
 /// A single queue can be used for multiple types of requests only if the agent preserves order
 /// between them.
-#[derive(Default)]
-pub struct RequestQueue {
-    inner: VecDeque<(MessageId, LayerId)>,
+///
+/// Each request can carry some additional data `T`.
+pub struct RequestQueue<T = ()> {
+    inner: VecDeque<(MessageId, LayerId, T)>,
 }
 
-impl fmt::Debug for RequestQueue {
+impl<T> Default for RequestQueue<T> {
+    fn default() -> Self {
+        Self {
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for RequestQueue<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ids = |(message_id, layer_id, _): &(MessageId, LayerId, T)| (*message_id, *layer_id);
+
         f.debug_struct("RequestQueue")
             .field("queue_len", &self.inner.len())
-            .field("front", &self.inner.front().copied())
-            .field("back", &self.inner.back().copied())
+            .field("front", &self.inner.front().map(ids))
+            .field("back", &self.inner.back().map(ids))
             .finish()
     }
 }
@@ -46,12 +57,35 @@ impl RequestQueue {
     /// Save the request at the end of this queue.
     #[tracing::instrument(level = Level::TRACE)]
     pub fn insert(&mut self, message_id: MessageId, layer_id: LayerId) {
-        self.inner.push_back((message_id, layer_id));
+        self.insert_with(message_id, layer_id, ());
     }
 
     /// Retrieve and remove a request from the front of this queue.
     #[tracing::instrument(level = Level::TRACE)]
     pub fn get(&mut self) -> Result<(MessageId, LayerId), RequestQueueEmpty> {
+        self.get_with()
+            .map(|(message_id, layer_id, ())| (message_id, layer_id))
+    }
+}
+
+impl<T> RequestQueue<T> {
+    /// Save the request with its additional data at the end of this queue.
+    #[tracing::instrument(level = Level::TRACE, skip(data))]
+    pub fn insert_with(&mut self, message_id: MessageId, layer_id: LayerId, data: T) {
+        self.inner.push_back((message_id, layer_id, data));
+    }
+
+    /// Retrieve and remove a request with its additional data from the front of this queue.
+    #[tracing::instrument(level = Level::TRACE)]
+    pub fn get_with(&mut self) -> Result<(MessageId, LayerId, T), RequestQueueEmpty> {
         self.inner.pop_front().ok_or(RequestQueueEmpty)
     }
+
+    /// Removes all requests from this queue.
+    ///
+    /// Can be used when the agent connection is re-established, as the requests won't get their
+    /// responses.
+    pub fn drain(&mut self) -> impl '_ + Iterator<Item = (MessageId, LayerId, T)> {
+        self.inner.drain(..)
+    }
 }
@@ -1,6 +1,8 @@
 use actix_codec::{AsyncRead, AsyncWrite};
 use futures::{SinkExt, StreamExt};
-use mirrord_protocol::{ClientCodec, ClientMessage, DaemonMessage};
+use mirrord_protocol::{
+    ClientCodec, ClientMessage, Compression, DaemonMessage, COMPRESSION_VERSION,
+};
 use tokio::sync::mpsc;
 use tracing::Instrument;
 
@@ -12,6 +14,10 @@ const CONNECTION_CHANNEL_SIZE: usize = 1000;
 
 /// Creates the task that handles the messaging between layer/agent.
 /// It does the encoding/decoding of protocol.
+///
+/// When the agent responds to [`ClientMessage::SwitchProtocolVersion`] with a version that
+/// supports it, switches the connection to [`Compression::Zstd`]. The
+/// [`DaemonMessage::SwitchCompressionResponse`] is not passed to the returned receiver.
 #[tracing::instrument(level = "trace", skip_all)]
 pub fn wrap_raw_connection(
     stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
@@ -23,6 +29,8 @@ pub fn wrap_raw_connection(
 
     tokio::spawn(
         async move {
+            let mut compression = Compression::None;
+
             // Generally, this loop should not be exited early with any `return` statement.
             // We want the `close` below to happen.
             loop {
@@ -41,11 +49,29 @@ pub fn wrap_raw_connection(
                     },
 
                     msg = codec.next() => match msg {
+                        Some(Ok(DaemonMessage::SwitchCompressionResponse(switched))) => {
+                            tracing::debug!(?switched, "Agent switched compression");
+                        }
                         Some(Ok(msg)) => {
+                            let compress = matches!(
+                                &msg,
+                                DaemonMessage::SwitchProtocolVersionResponse(version)
+                                    if COMPRESSION_VERSION.matches(version)
+                            ) && compression == Compression::None;
+
                             if let Err(error) = out_tx.send(msg).await {
                                 tracing::error!(?error, "Failed to send agent message");
                                 break;
                             }
+
+                            if compress {
+                                compression = Compression::Zstd;
+                                let switch = ClientMessage::SwitchCompression(compression);
+                                if let Err(error) = codec.send(switch).await {
+                                    tracing::error!(?error, "Failed to send client message");
+                                    break;
+                                }
+                            }
                         }
                         Some(Err(error)) => {
                             tracing::error!(?error, "Failed to receive agent message");
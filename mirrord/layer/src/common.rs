@@ -1,7 +1,11 @@
 //! Shared place for a few types and functions that are used everywhere by the layer.
+#[cfg(target_os = "linux")]
+use std::net::TcpStream;
 use std::{ffi::CStr, fmt::Debug, ops::Not, path::PathBuf};
 
 use libc::c_char;
+#[cfg(target_os = "linux")]
+use mirrord_intproxy_protocol::LayerId;
 use mirrord_intproxy_protocol::{IsLayerRequest, IsLayerRequestWithResponse, MessageId};
 use mirrord_protocol::file::OpenOptionsInternal;
 use null_terminated::Nul;
@@ -50,6 +54,21 @@ pub fn make_proxy_request_no_response<T: IsLayerRequest + Debug>(
     }
 }
 
+/// Opens a separate session with the internal proxy for the events of the remote file watches,
+/// using global [`PROXY_CONNECTION`].
+#[cfg(target_os = "linux")]
+pub fn new_file_watch_session() -> HookResult<(LayerId, TcpStream)> {
+    // SAFETY: mutation happens only on initialization.
+    #[allow(static_mut_refs)]
+    unsafe {
+        PROXY_CONNECTION
+            .get()
+            .ok_or(HookError::CannotGetProxyConnection)?
+            .new_file_watch_session()
+            .map_err(Into::into)
+    }
+}
+
 /// Converts raw pointer values `P` to some other type.
 ///
 /// ## Usage
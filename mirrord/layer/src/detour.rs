@@ -157,6 +157,22 @@ pub(crate) enum Bypass {
     /// Some operations only handle absolute [`PathBuf`]s.
     RelativePath(CString),
 
+    /// The path has a local copy in the copy-on-write overlay, so the operation is done locally,
+    /// on the copy.
+    Overlay(CString),
+
+    /// The path was mapped to a local path by a `local:` rule of
+    /// [`FsConfig::mapping`](mirrord_config::feature::fs::FsConfig::mapping), so the operation is
+    /// done locally, on the mapped path.
+    LocalMapping(CString),
+
+    /// Both paths of a `rename` are accessed locally, each one as told by its own [`Bypass`], e.g.
+    /// a [`Bypass::LocalMapping`] replaces only the path it was produced for.
+    Rename {
+        old_path: Box<Bypass>,
+        new_path: Box<Bypass>,
+    },
+
     /// Started mirrord with [`FsModeConfig`](mirrord_config::feature::fs::mode::FsModeConfig) set
     /// to [`FsModeConfig::Read`](mirrord_config::feature::fs::FsModeConfig::Read), but
     /// operation requires more file permissions.
@@ -210,6 +226,9 @@ pub(crate) enum Bypass {
     /// DNS query should be done locally.
     LocalDns,
 
+    /// Called `getnameinfo` with `NI_NUMERICHOST`, so there is no name to resolve.
+    NumericHost,
+
     /// Operation is not implemented, but it should not be a hard error.
     ///
     /// Useful for operations that are version gated, and we want to bypass when the protocol
@@ -225,6 +244,18 @@ impl Bypass {
     pub fn ignored_file(path: impl Into<Vec<u8>>) -> Self {
         Bypass::IgnoredFile(CString::new(path).expect("Should be CStringable"))
     }
+
+    pub fn overlay(path: PathBuf) -> Self {
+        Bypass::Overlay(
+            CString::new(path.into_os_string().into_vec()).expect("Should be CStringable"),
+        )
+    }
+
+    pub fn local_mapping(path: PathBuf) -> Self {
+        Bypass::LocalMapping(
+            CString::new(path.into_os_string().into_vec()).expect("Should be CStringable"),
+        )
+    }
 }
 
 /// [`ControlFlow`](std::ops::ControlFlow)-like enum to be used by hooks.
@@ -2,7 +2,7 @@ use std::{env::VarError, net::SocketAddr, ptr, str::ParseBoolError};
 
 use errno::set_errno;
 use ignore_codes::*;
-use libc::{c_char, hostent, DIR, FILE};
+use libc::{c_char, c_int, hostent, DIR, FILE};
 use mirrord_config::config::ConfigError;
 use mirrord_protocol::{ResponseError, SerializationError};
 #[cfg(target_os = "macos")]
@@ -75,9 +75,6 @@ pub(crate) enum HookError {
     #[error("mirrord-layer: SIP patch failed with error `{0}`!")]
     FailedSipPatch(#[from] SipError),
 
-    #[error("mirrord-layer: IPv6 can't be used with mirrord")]
-    SocketUnsuportedIpv6,
-
     // `From` implemented below, not with `#[from]` so that when new variants of
     // `SerializationError` are added, they are mapped into different variants of
     // `LayerError`.
@@ -200,48 +197,15 @@ impl<T> From<std::sync::PoisonError<std::sync::MutexGuard<'_, T>>> for HookError
 pub(crate) type Result<T, E = LayerError> = std::result::Result<T, E>;
 pub(crate) type HookResult<T, E = HookError> = std::result::Result<T, E>;
 
-/// mapping based on - <https://man7.org/linux/man-pages/man3/errno.3.html>
-impl From<HookError> for i64 {
-    fn from(fail: HookError) -> Self {
-        match fail {
-            HookError::AddressAlreadyBound(_)
-            | HookError::ResponseError(
-                ResponseError::NotFound(_)
-                | ResponseError::NotFile(_)
-                | ResponseError::NotDirectory(_)
-                | ResponseError::Remote(_)
-                | ResponseError::RemoteIO(_)
-                | ResponseError::DnsLookup(_),
-            ) => {
-                info!("libc error (doesn't indicate a problem) >> {fail:#?}")
-            }
-            HookError::IO(ref e) if (is_ignored_code(e.raw_os_error())) => {
-                info!("libc error (doesn't indicate a problem) >> {fail:#?}")
-            }
-            HookError::FileNotFound => {
-                info!("mirrord file not found triggered")
-            }
-            HookError::SocketUnsuportedIpv6 => {
-                info!("{fail}")
-            }
-            HookError::ProxyError(ref err) => {
-                graceful_exit!(
-                    r"Proxy error, connectivity issue or a bug.
-                    Please report it to us on https://github.com/metalbear-co/mirrord/issues/new?assignees=&labels=bug&projects=&template=bug_report.yml
-                    You can find the `mirrord-intproxy` logs in {}.
-                    {err}",
-                    crate::setup()
-                        .layer_config()
-                        .internal_proxy
-                        .log_destination
-                        .clone()
-                        .unwrap_or("/tmp".to_string())
-                )
-            }
-            _ => error!("Error occured in Layer >> {fail:?}"),
-        };
-
-        let libc_error = match fail {
+impl HookError {
+    /// Returns the `errno` value that describes this error.
+    ///
+    /// mapping based on - <https://man7.org/linux/man-pages/man3/errno.3.html>
+    ///
+    /// [`ResponseError::DnsLookup`] is mapped to an `EAI_*` code, which is returned by the
+    /// `getaddrinfo` family instead of being set as `errno`.
+    pub(crate) fn errno(&self) -> c_int {
+        match self {
             HookError::Null(_) => libc::EINVAL,
             HookError::TryFromInt(_) => libc::EINVAL,
             HookError::CannotGetProxyConnection => libc::EINVAL,
@@ -260,18 +224,16 @@ impl From<HookError> for i64 {
                     mirrord_protocol::RemoteError::ConnectTimedOut(_) => libc::ENETUNREACH,
                     _ => libc::EINVAL,
                 },
-                ResponseError::DnsLookup(dns_fail) => {
-                    return match dns_fail.kind {
-                        mirrord_protocol::ResolveErrorKindInternal::Timeout => libc::EAI_AGAIN,
-                        // prevents an infinite loop that used to happen in some apps, don't know if
-                        // this is the correct mapping.
-                        mirrord_protocol::ResolveErrorKindInternal::NoRecordsFound(_) => {
-                            libc::EAI_NONAME
-                        }
-                        _ => libc::EAI_FAIL,
-                        // TODO: Add more error kinds, next time we break protocol compatibility.
-                    } as _;
-                }
+                ResponseError::DnsLookup(dns_fail) => match dns_fail.kind {
+                    mirrord_protocol::ResolveErrorKindInternal::Timeout => libc::EAI_AGAIN,
+                    // prevents an infinite loop that used to happen in some apps, don't know if
+                    // this is the correct mapping.
+                    mirrord_protocol::ResolveErrorKindInternal::NoRecordsFound(_) => {
+                        libc::EAI_NONAME
+                    }
+                    _ => libc::EAI_FAIL,
+                    // TODO: Add more error kinds, next time we break protocol compatibility.
+                },
                 // for listen, EINVAL means "socket is already connected."
                 // Will not happen, because this ResponseError is not return from any hook, so it
                 // never appears as HookError::ResponseError(PortAlreadyStolen(_)).
@@ -279,12 +241,9 @@ impl From<HookError> for i64 {
                 ResponseError::PortAlreadyStolen(_port) => libc::EINVAL,
                 ResponseError::NotImplemented => libc::EINVAL,
                 ResponseError::StripPrefix(_) => libc::EINVAL,
-                err @ ResponseError::Forbidden { .. } => {
-                    graceful_exit!(
-                        "Stopping mirrord run. Please adjust your mirrord configuration.\n{err}"
-                    );
-                    libc::EINVAL
-                }
+                ResponseError::AgentReconnected => libc::EIO,
+                ResponseError::StaleResource(_) => libc::EBADF,
+                ResponseError::Forbidden { .. } => libc::EINVAL,
             },
             HookError::DNSNoName => libc::EFAULT,
             HookError::Utf8(_) => libc::EINVAL,
@@ -292,7 +251,6 @@ impl From<HookError> for i64 {
             HookError::LocalFileCreation(_) => libc::EINVAL,
             #[cfg(target_os = "macos")]
             HookError::FailedSipPatch(_) => libc::EACCES,
-            HookError::SocketUnsuportedIpv6 => libc::EAFNOSUPPORT,
             HookError::UnsupportedSocketType => libc::EAFNOSUPPORT,
             HookError::BadPointer => libc::EFAULT,
             HookError::AddressAlreadyBound(_) => libc::EADDRINUSE,
@@ -304,8 +262,58 @@ impl From<HookError> for i64 {
             #[cfg(target_os = "linux")]
             HookError::EmptyPath => libc::ENOENT,
             HookError::InvalidBindAddressForDomain => libc::EINVAL,
+        }
+    }
+}
+
+/// Logs the error and sets `errno`, see [`HookError::errno`].
+impl From<HookError> for i64 {
+    fn from(fail: HookError) -> Self {
+        match fail {
+            HookError::AddressAlreadyBound(_)
+            | HookError::ResponseError(
+                ResponseError::NotFound(_)
+                | ResponseError::NotFile(_)
+                | ResponseError::NotDirectory(_)
+                | ResponseError::Remote(_)
+                | ResponseError::RemoteIO(_)
+                | ResponseError::DnsLookup(_),
+            ) => {
+                info!("libc error (doesn't indicate a problem) >> {fail:#?}")
+            }
+            HookError::IO(ref e) if (is_ignored_code(e.raw_os_error())) => {
+                info!("libc error (doesn't indicate a problem) >> {fail:#?}")
+            }
+            HookError::FileNotFound => {
+                info!("mirrord file not found triggered")
+            }
+            HookError::ProxyError(ref err) => {
+                graceful_exit!(
+                    r"Proxy error, connectivity issue or a bug.
+                    Please report it to us on https://github.com/metalbear-co/mirrord/issues/new?assignees=&labels=bug&projects=&template=bug_report.yml
+                    You can find the `mirrord-intproxy` logs in {}.
+                    {err}",
+                    crate::setup()
+                        .layer_config()
+                        .internal_proxy
+                        .log_destination
+                        .clone()
+                        .unwrap_or("/tmp".to_string())
+                )
+            }
+            HookError::ResponseError(ref err @ ResponseError::Forbidden { .. }) => {
+                graceful_exit!(
+                    "Stopping mirrord run. Please adjust your mirrord configuration.\n{err}"
+                );
+            }
+            _ => error!("Error occured in Layer >> {fail:?}"),
         };
 
+        let libc_error = fail.errno();
+        if let HookError::ResponseError(ResponseError::DnsLookup(..)) = fail {
+            return libc_error as _;
+        }
+
         set_errno(errno::Errno(libc_error));
 
         -1
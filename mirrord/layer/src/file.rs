@@ -24,9 +24,13 @@ use mirrord_protocol::file::{GetDEnts64Request, GetDEnts64Response};
 
 pub(crate) mod filter;
 pub(crate) mod hooks;
+#[cfg(target_os = "linux")]
+pub(crate) mod inotify;
 pub(crate) mod mapper;
+pub(crate) mod mmap;
 pub(crate) mod open_dirs;
 pub(crate) mod ops;
+pub(crate) mod overlay;
 
 type RemoteFd = u64;
 type LocalFd = RawFd;
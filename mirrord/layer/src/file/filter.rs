@@ -149,11 +149,20 @@ impl FileFilter {
             _ if self.default_remote_ro.is_match(text) && !write => Detour::Success(()),
             _ if self.default_local.is_match(text) => Detour::Bypass(op()),
             FsModeConfig::LocalWithOverrides => Detour::Bypass(op()),
-            FsModeConfig::Write => Detour::Success(()),
+            FsModeConfig::Write | FsModeConfig::Cow => Detour::Success(()),
             FsModeConfig::Read if write => Detour::Bypass(Bypass::ReadOnly(text.into())),
             FsModeConfig::Read => Detour::Success(()),
         }
     }
+
+    /// Checks if `text` is changed in the local overlay instead of the remote file system, which
+    /// is the default for the paths that [`continue_or_bypass_with`](Self::continue_or_bypass_with)
+    /// lets through in [`FsModeConfig::Cow`].
+    ///
+    /// Paths matching the `read_write` patterns are still changed remotely.
+    pub fn is_copy_on_write(&self, text: &str) -> bool {
+        self.mode.is_cow() && !self.read_write.is_match(text)
+    }
 }
 
 impl Default for FileFilter {
@@ -380,6 +389,19 @@ mod tests {
         false,
         DetourKind::Error
     )]
+    #[case(FsModeConfig::Cow, "/a/test.a", false, DetourKind::Success)]
+    #[case(FsModeConfig::Cow, "/a/test.a", true, DetourKind::Success)]
+    #[case(
+        FsModeConfig::Cow,
+        "/pain/read_write/test.a",
+        true,
+        DetourKind::Success
+    )]
+    #[case(FsModeConfig::Cow, "/pain/read_only/test.a", true, DetourKind::Bypass)]
+    #[case(FsModeConfig::Cow, "/pain/local/test.a", true, DetourKind::Bypass)]
+    #[case(FsModeConfig::Cow, "/opt/test.a", true, DetourKind::Bypass)]
+    #[case(FsModeConfig::Cow, "/etc/resolv.conf", true, DetourKind::Bypass)]
+    #[case(FsModeConfig::Cow, "/pain/not_found/test.a", false, DetourKind::Error)]
     fn include_complex_configuration(
         #[case] mode: FsModeConfig,
         #[case] path: &str,
@@ -401,6 +423,8 @@ mod tests {
             not_found,
             mode,
             mapping: None,
+            cache: Default::default(),
+            overlay: None,
         };
 
         let file_filter = FileFilter::new(fs_config);
@@ -410,6 +434,23 @@ mod tests {
         assert_eq!(res.kind(), expected);
     }
 
+    #[rstest]
+    #[case(FsModeConfig::Cow, "/a/test.a", true)]
+    #[case(FsModeConfig::Cow, "/pain/read_write/test.a", false)]
+    #[case(FsModeConfig::Write, "/a/test.a", false)]
+    #[case(FsModeConfig::Read, "/a/test.a", false)]
+    fn copy_on_write(#[case] mode: FsModeConfig, #[case] path: &str, #[case] expected: bool) {
+        let fs_config = FsConfig {
+            mode,
+            read_write: Some(VecOrSingle::Single(r"/pain/read_write.*\.a".to_string())),
+            ..Default::default()
+        };
+
+        let file_filter = FileFilter::new(fs_config);
+
+        assert_eq!(file_filter.is_copy_on_write(path), expected);
+    }
+
     #[rstest]
     #[case(FsModeConfig::Read, "/etc/resolv.conf", true, DetourKind::Bypass)]
     #[case(FsModeConfig::Write, "/etc/resolv.conf", true, DetourKind::Bypass)]
@@ -14,23 +14,30 @@ use std::{
 
 use errno::{set_errno, Errno};
 use libc::{
-    self, c_char, c_int, c_void, dirent, iovec, off_t, size_t, ssize_t, stat, statfs, AT_EACCESS,
-    AT_FDCWD, DIR, EINVAL, O_DIRECTORY, O_RDONLY,
+    self, c_char, c_int, c_void, dirent, gid_t, iovec, mode_t, off_t, size_t, ssize_t, stat,
+    statfs, statvfs, timespec, timeval, uid_t, AT_EACCESS, AT_FDCWD, AT_REMOVEDIR,
+    AT_SYMLINK_NOFOLLOW, DIR, EINVAL, MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED, O_DIRECTORY, O_RDONLY,
 };
 #[cfg(target_os = "linux")]
-use libc::{dirent64, stat64, statx, EBADF, ENOENT, ENOTDIR};
+use libc::{
+    c_uint, dirent64, loff_t, stat64, statfs64, statvfs64, statx, EBADF, ENOENT, ENOTDIR,
+    MREMAP_FIXED,
+};
 use mirrord_layer_macro::{hook_fn, hook_guard_fn};
 use mirrord_protocol::file::{
-    FsMetadataInternal, MetadataInternal, ReadFileResponse, ReadLinkFileResponse, WriteFileResponse,
+    FileTimeUpdate, FsMetadataInternalV2, MetadataInternal, ReadFileResponse, ReadLinkFileResponse,
+    WriteFileResponse,
 };
 #[cfg(target_os = "linux")]
 use mirrord_protocol::ResponseError::{NotDirectory, NotFound};
 use num_traits::Bounded;
-use tracing::trace;
+use tracing::{error, trace};
 #[cfg(target_os = "linux")]
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
-use super::{open_dirs, ops::*, OpenOptionsInternalExt};
+#[cfg(target_os = "linux")]
+use super::inotify;
+use super::{mmap::MAPPED_FILES, open_dirs, ops::*, OpenOptionsInternalExt};
 #[cfg(target_os = "linux")]
 use crate::error::HookError::ResponseError;
 use crate::{
@@ -61,11 +68,32 @@ fn update_ptr_from_bypass(ptr: *const c_char, bypass: &Bypass) -> *const c_char
         // path.
         #[cfg(target_os = "macos")]
         Bypass::FileOperationInMirrordBinTempDir(stripped_ptr) => *stripped_ptr,
-        Bypass::RelativePath(path) | Bypass::IgnoredFile(path) => path.as_ptr(),
+        Bypass::RelativePath(path)
+        | Bypass::IgnoredFile(path)
+        | Bypass::Overlay(path)
+        | Bypass::LocalMapping(path) => path.as_ptr(),
         _ => ptr,
     }
 }
 
+/// [`update_ptr_from_bypass`] for both paths of a `rename`, see [`Bypass::Rename`].
+fn update_rename_ptrs_from_bypass(
+    old_path: *const c_char,
+    new_path: *const c_char,
+    bypass: &Bypass,
+) -> (*const c_char, *const c_char) {
+    match bypass {
+        Bypass::Rename {
+            old_path: old_bypass,
+            new_path: new_bypass,
+        } => (
+            update_ptr_from_bypass(old_path, old_bypass),
+            update_ptr_from_bypass(new_path, new_bypass),
+        ),
+        _ => (old_path, new_path),
+    }
+}
+
 /// Implementation of open_detour, used in open_detour and openat_detour
 /// We ignore mode in case we don't bypass the call.
 #[mirrord_layer_macro::instrument(level = "trace", ret)]
@@ -439,6 +467,12 @@ pub(crate) unsafe extern "C" fn read_detour(
     out_buffer: *mut c_void,
     count: size_t,
 ) -> ssize_t {
+    #[cfg(target_os = "linux")]
+    if inotify::is_instance(fd) {
+        return inotify::read(fd, out_buffer, count)
+            .unwrap_or_bypass_with(|_| FN_READ(fd, out_buffer, count));
+    }
+
     read(fd, count as u64)
         .map(|read_file| {
             let ReadFileResponse { bytes, read_amount } = read_file;
@@ -659,6 +693,160 @@ pub(crate) unsafe extern "C" fn _write_nocancel_detour(
     write(fd, write_bytes).unwrap_or_bypass_with(|_| FN__WRITE_NOCANCEL(fd, buffer, count))
 }
 
+/// Writes the bytes copied by [`copy_from_remote`] to `fd`, at `offset` when given, moving the
+/// `offset` past them.
+///
+/// Remote `fd`s are written through the agent, local ones with the original `write` and `pwrite`.
+#[cfg(target_os = "linux")]
+unsafe fn write_copied(fd: RawFd, bytes: &[u8], offset: Option<&mut u64>) -> Detour<usize> {
+    let remote = match offset.as_deref() {
+        Some(&offset) => pwrite(fd, bytes, offset).map(|response| response.written_amount as usize),
+        None => write(fd, Some(bytes.to_vec())).map(|written| written as usize),
+    };
+
+    let written = match remote {
+        Detour::Bypass(_) => {
+            let written = match offset.as_deref() {
+                Some(&offset) => FN_PWRITE(fd, bytes.as_ptr().cast(), bytes.len(), offset as off_t),
+                None => FN_WRITE(fd, bytes.as_ptr().cast(), bytes.len()),
+            };
+            if written == -1 {
+                return Detour::Error(std::io::Error::last_os_error().into());
+            }
+
+            written as usize
+        }
+        remote => remote?,
+    };
+
+    if let Some(offset) = offset {
+        *offset += written as u64;
+    }
+
+    Detour::Success(written)
+}
+
+/// Implementation of [`sendfile_detour`] and [`sendfile64_detour`].
+///
+/// Only handles a remote `in_fd`, as the local file backing it is empty, see
+/// [`copy_from_remote`].
+#[cfg(target_os = "linux")]
+unsafe fn sendfile_logic(
+    out_fd: RawFd,
+    in_fd: RawFd,
+    offset: *mut off_t,
+    count: size_t,
+) -> Detour<ssize_t> {
+    let start = offset.as_ref().map(|offset| *offset as u64);
+    let copied = copy_from_remote(in_fd, start, count as u64, |bytes| {
+        write_copied(out_fd, bytes, None)
+    })?;
+
+    if let Some(offset) = offset.as_mut() {
+        *offset += copied as off_t;
+    }
+
+    Detour::Success(copied as ssize_t)
+}
+
+/// Hook for `libc::sendfile`.
+///
+/// **Bypassed** when `in_fd` is not managed by us (not found in `OPEN_FILES`).
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn sendfile_detour(
+    out_fd: RawFd,
+    in_fd: RawFd,
+    offset: *mut off_t,
+    count: size_t,
+) -> ssize_t {
+    sendfile_logic(out_fd, in_fd, offset, count)
+        .unwrap_or_bypass_with(|_| FN_SENDFILE(out_fd, in_fd, offset, count))
+}
+
+/// Hook for `libc::sendfile64`, used by programs built with `_FILE_OFFSET_BITS=64`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn sendfile64_detour(
+    out_fd: RawFd,
+    in_fd: RawFd,
+    offset: *mut off_t,
+    count: size_t,
+) -> ssize_t {
+    sendfile_logic(out_fd, in_fd, offset, count)
+        .unwrap_or_bypass_with(|_| FN_SENDFILE64(out_fd, in_fd, offset, count))
+}
+
+/// Implementation of [`copy_file_range_detour`] and [`splice_detour`], which only differ in the
+/// kinds of `fd`s they accept.
+///
+/// Only handles a remote `fd_in`, see [`sendfile_logic`].
+#[cfg(target_os = "linux")]
+unsafe fn copy_range_logic(
+    fd_in: RawFd,
+    off_in: *mut loff_t,
+    fd_out: RawFd,
+    off_out: *mut loff_t,
+    len: size_t,
+) -> Detour<ssize_t> {
+    let start = off_in.as_ref().map(|offset| *offset as u64);
+    let mut out_offset = off_out.as_ref().map(|offset| *offset as u64);
+    let copied = copy_from_remote(fd_in, start, len as u64, |bytes| {
+        write_copied(fd_out, bytes, out_offset.as_mut())
+    })?;
+
+    if let Some(off_in) = off_in.as_mut() {
+        *off_in += copied as loff_t;
+    }
+    if let (Some(off_out), Some(out_offset)) = (off_out.as_mut(), out_offset) {
+        *off_out = out_offset as loff_t;
+    }
+
+    Detour::Success(copied as ssize_t)
+}
+
+/// Hook for `libc::copy_file_range`.
+///
+/// No `flags` are defined yet, so any of them fail with `EINVAL` for a remote `fd_in`, just like
+/// they do in the kernel.
+///
+/// **Bypassed** when `fd_in` is not managed by us (not found in `OPEN_FILES`).
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn copy_file_range_detour(
+    fd_in: RawFd,
+    off_in: *mut loff_t,
+    fd_out: RawFd,
+    off_out: *mut loff_t,
+    len: size_t,
+    flags: c_uint,
+) -> ssize_t {
+    if flags != 0 && matches!(get_remote_fd(fd_in), Detour::Success(_)) {
+        set_errno(Errno(EINVAL));
+        return -1;
+    }
+
+    copy_range_logic(fd_in, off_in, fd_out, off_out, len)
+        .unwrap_or_bypass_with(|_| FN_COPY_FILE_RANGE(fd_in, off_in, fd_out, off_out, len, flags))
+}
+
+/// Hook for `libc::splice`, the `flags` are ignored for remote files.
+///
+/// **Bypassed** when `fd_in` is not managed by us (not found in `OPEN_FILES`).
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn splice_detour(
+    fd_in: RawFd,
+    off_in: *mut loff_t,
+    fd_out: RawFd,
+    off_out: *mut loff_t,
+    len: size_t,
+    flags: c_uint,
+) -> ssize_t {
+    copy_range_logic(fd_in, off_in, fd_out, off_out, len)
+        .unwrap_or_bypass_with(|_| FN_SPLICE(fd_in, off_in, fd_out, off_out, len, flags))
+}
+
 /// Implementation of access_detour, used in access_detour and faccessat_detour
 unsafe fn access_logic(raw_path: *const c_char, mode: c_int) -> c_int {
     access(raw_path.checked_into(), mode as u8).unwrap_or_bypass_with(|bypass| {
@@ -736,7 +924,7 @@ unsafe extern "C" fn fill_stat(out_stat: *mut stat64, metadata: &MetadataInterna
 }
 
 /// Fills the `statfs` struct with the metadata
-unsafe extern "C" fn fill_statfs(out_stat: *mut statfs, metadata: &FsMetadataInternal) {
+unsafe extern "C" fn fill_statfs(out_stat: *mut statfs, metadata: &FsMetadataInternalV2) {
     // Acording to linux documentation "Fields that are undefined for a particular file system are
     // set to 0."
     out_stat.write_bytes(0, 1);
@@ -749,6 +937,58 @@ unsafe extern "C" fn fill_statfs(out_stat: *mut statfs, metadata: &FsMetadataInt
     out.f_bavail = metadata.blocks_available;
     out.f_files = metadata.files;
     out.f_ffree = metadata.files_free;
+    #[cfg(target_os = "linux")]
+    {
+        out.f_namelen = best_effort_cast(metadata.name_len);
+    }
+}
+
+/// Fills the `statfs64` struct with the metadata
+#[cfg(target_os = "linux")]
+unsafe extern "C" fn fill_statfs64(out_stat: *mut statfs64, metadata: &FsMetadataInternalV2) {
+    out_stat.write_bytes(0, 1);
+    let out = &mut *out_stat;
+    out.f_type = best_effort_cast(metadata.filesystem_type);
+    out.f_bsize = best_effort_cast(metadata.block_size);
+    out.f_blocks = metadata.blocks;
+    out.f_bfree = metadata.blocks_free;
+    out.f_bavail = metadata.blocks_available;
+    out.f_files = metadata.files;
+    out.f_ffree = metadata.files_free;
+    out.f_namelen = best_effort_cast(metadata.name_len);
+}
+
+/// Fills the `statvfs` struct with the metadata, the fields we don't get from the agent (like
+/// `f_fsid`) are set to 0.
+unsafe extern "C" fn fill_statvfs(out_stat: *mut statvfs, metadata: &FsMetadataInternalV2) {
+    out_stat.write_bytes(0, 1);
+    let out = &mut *out_stat;
+    out.f_bsize = best_effort_cast(metadata.block_size);
+    // `statfs` has no fragment size, glibc uses the block size then.
+    out.f_frsize = best_effort_cast(metadata.block_size);
+    out.f_blocks = best_effort_cast(metadata.blocks);
+    out.f_bfree = best_effort_cast(metadata.blocks_free);
+    out.f_bavail = best_effort_cast(metadata.blocks_available);
+    out.f_files = best_effort_cast(metadata.files);
+    out.f_ffree = best_effort_cast(metadata.files_free);
+    out.f_favail = best_effort_cast(metadata.files_free);
+    out.f_namemax = best_effort_cast(metadata.name_len);
+}
+
+/// Fills the `statvfs64` struct with the metadata, see [`fill_statvfs`].
+#[cfg(target_os = "linux")]
+unsafe extern "C" fn fill_statvfs64(out_stat: *mut statvfs64, metadata: &FsMetadataInternalV2) {
+    out_stat.write_bytes(0, 1);
+    let out = &mut *out_stat;
+    out.f_bsize = best_effort_cast(metadata.block_size);
+    out.f_frsize = best_effort_cast(metadata.block_size);
+    out.f_blocks = metadata.blocks;
+    out.f_bfree = metadata.blocks_free;
+    out.f_bavail = metadata.blocks_available;
+    out.f_files = metadata.files;
+    out.f_ffree = metadata.files_free;
+    out.f_favail = metadata.files_free;
+    out.f_namemax = best_effort_cast(metadata.name_len);
 }
 
 fn stat_logic<const FOLLOW_SYMLINK: bool>(
@@ -905,7 +1145,7 @@ unsafe extern "C" fn fstatat_detour(
 }
 
 #[hook_guard_fn]
-unsafe extern "C" fn fstatfs_detour(fd: c_int, out_stat: *mut statfs) -> c_int {
+pub(crate) unsafe extern "C" fn fstatfs_detour(fd: c_int, out_stat: *mut statfs) -> c_int {
     if out_stat.is_null() {
         return HookError::BadPointer.into();
     }
@@ -919,6 +1159,99 @@ unsafe extern "C" fn fstatfs_detour(fd: c_int, out_stat: *mut statfs) -> c_int {
         .unwrap_or_bypass_with(|_| FN_FSTATFS(fd, out_stat))
 }
 
+/// Hook for [`libc::fstatvfs`], see [`fstatfs_detour`].
+#[hook_guard_fn]
+unsafe extern "C" fn fstatvfs_detour(fd: c_int, out_stat: *mut statvfs) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    xstatfs(fd)
+        .map(|res| {
+            fill_statvfs(out_stat, &res.metadata);
+            0
+        })
+        .unwrap_or_bypass_with(|_| FN_FSTATVFS(fd, out_stat))
+}
+
+/// Hook for [`libc::statfs`], the [`FileFilter`](super::filter::FileFilter) decides whether we
+/// check the remote file system.
+#[hook_guard_fn]
+unsafe extern "C" fn statfs_detour(raw_path: *const c_char, out_stat: *mut statfs) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    statfs_path(raw_path.checked_into())
+        .map(|res| {
+            fill_statfs(out_stat, &res.metadata);
+            0
+        })
+        .unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_STATFS(raw_path, out_stat)
+        })
+}
+
+/// Hook for `libc::statfs64`, see [`statfs_detour`].
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn statfs64_detour(
+    raw_path: *const c_char,
+    out_stat: *mut statfs64,
+) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    statfs_path(raw_path.checked_into())
+        .map(|res| {
+            fill_statfs64(out_stat, &res.metadata);
+            0
+        })
+        .unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_STATFS64(raw_path, out_stat)
+        })
+}
+
+/// Hook for [`libc::statvfs`], see [`statfs_detour`].
+#[hook_guard_fn]
+unsafe extern "C" fn statvfs_detour(raw_path: *const c_char, out_stat: *mut statvfs) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    statfs_path(raw_path.checked_into())
+        .map(|res| {
+            fill_statvfs(out_stat, &res.metadata);
+            0
+        })
+        .unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_STATVFS(raw_path, out_stat)
+        })
+}
+
+/// Hook for `libc::statvfs64`, see [`statfs_detour`].
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn statvfs64_detour(raw_path: *const c_char, out_stat: *mut statvfs64) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    statfs_path(raw_path.checked_into())
+        .map(|res| {
+            fill_statvfs64(out_stat, &res.metadata);
+            0
+        })
+        .unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_STATVFS64(raw_path, out_stat)
+        })
+}
+
 unsafe fn realpath_logic(
     source_path: *const c_char,
     output_path: *mut c_char,
@@ -1062,6 +1395,480 @@ pub(crate) unsafe extern "C" fn readlink_detour(
         })
 }
 
+/// Hook for `libc::mkdir`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn mkdir_detour(raw_path: *const c_char, mode: mode_t) -> c_int {
+    mkdir(raw_path.checked_into(), mode.into()).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_MKDIR(raw_path, mode)
+    })
+}
+
+/// Hook for `libc::mkdirat`.
+///
+/// Paths relative to a remote `dirfd` are resolved with [`path_at`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn mkdirat_detour(
+    dirfd: RawFd,
+    raw_path: *const c_char,
+    mode: mode_t,
+) -> c_int {
+    mkdir(path_at(dirfd, raw_path.checked_into()), mode.into()).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_MKDIRAT(dirfd, raw_path, mode)
+    })
+}
+
+/// Hook for `libc::rmdir`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn rmdir_detour(raw_path: *const c_char) -> c_int {
+    rmdir(raw_path.checked_into()).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_RMDIR(raw_path)
+    })
+}
+
+/// Hook for `libc::unlink`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn unlink_detour(raw_path: *const c_char) -> c_int {
+    unlink(raw_path.checked_into()).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_UNLINK(raw_path)
+    })
+}
+
+/// Hook for `libc::unlinkat`, which is also `rmdir` when `AT_REMOVEDIR` is set.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn unlinkat_detour(
+    dirfd: RawFd,
+    raw_path: *const c_char,
+    flags: c_int,
+) -> c_int {
+    let path = path_at(dirfd, raw_path.checked_into());
+    let result = if flags & AT_REMOVEDIR != 0 {
+        rmdir(path)
+    } else {
+        unlink(path)
+    };
+
+    result.unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_UNLINKAT(dirfd, raw_path, flags)
+    })
+}
+
+/// Hook for `libc::rename`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn rename_detour(
+    old_path: *const c_char,
+    new_path: *const c_char,
+) -> c_int {
+    rename(old_path.checked_into(), new_path.checked_into()).unwrap_or_bypass_with(|bypass| {
+        let (old_path, new_path) = update_rename_ptrs_from_bypass(old_path, new_path, &bypass);
+        FN_RENAME(old_path, new_path)
+    })
+}
+
+/// Hook for `libc::renameat`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn renameat_detour(
+    old_dirfd: RawFd,
+    old_path: *const c_char,
+    new_dirfd: RawFd,
+    new_path: *const c_char,
+) -> c_int {
+    rename(
+        path_at(old_dirfd, old_path.checked_into()),
+        path_at(new_dirfd, new_path.checked_into()),
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let (old_path, new_path) = update_rename_ptrs_from_bypass(old_path, new_path, &bypass);
+        FN_RENAMEAT(old_dirfd, old_path, new_dirfd, new_path)
+    })
+}
+
+/// Hook for `libc::chmod`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn chmod_detour(raw_path: *const c_char, mode: mode_t) -> c_int {
+    chmod(raw_path.checked_into(), mode.into()).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_CHMOD(raw_path, mode)
+    })
+}
+
+/// Hook for `libc::fchmod`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fchmod_detour(fd: RawFd, mode: mode_t) -> c_int {
+    fchmod(fd, mode.into()).unwrap_or_bypass_with(|_| FN_FCHMOD(fd, mode))
+}
+
+/// Hook for `libc::fchmodat`.
+///
+/// Changing the permissions of a symbolic link (`AT_SYMLINK_NOFOLLOW`) is always done locally.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fchmodat_detour(
+    dirfd: RawFd,
+    raw_path: *const c_char,
+    mode: mode_t,
+    flags: c_int,
+) -> c_int {
+    if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        return FN_FCHMODAT(dirfd, raw_path, mode, flags);
+    }
+
+    chmod(path_at(dirfd, raw_path.checked_into()), mode.into()).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_FCHMODAT(dirfd, raw_path, mode, flags)
+    })
+}
+
+/// Converts the `uid_t`/`gid_t` arguments of `chown`, where `-1` means "leave unchanged".
+fn owner_id(id: u32) -> Option<u32> {
+    (id != u32::MAX).then_some(id)
+}
+
+/// Hook for `libc::chown`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn chown_detour(
+    raw_path: *const c_char,
+    owner: uid_t,
+    group: gid_t,
+) -> c_int {
+    chown(
+        raw_path.checked_into(),
+        owner_id(owner),
+        owner_id(group),
+        true,
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_CHOWN(raw_path, owner, group)
+    })
+}
+
+/// Hook for `libc::lchown`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn lchown_detour(
+    raw_path: *const c_char,
+    owner: uid_t,
+    group: gid_t,
+) -> c_int {
+    chown(
+        raw_path.checked_into(),
+        owner_id(owner),
+        owner_id(group),
+        false,
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_LCHOWN(raw_path, owner, group)
+    })
+}
+
+/// Hook for `libc::fchownat`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fchownat_detour(
+    dirfd: RawFd,
+    raw_path: *const c_char,
+    owner: uid_t,
+    group: gid_t,
+    flags: c_int,
+) -> c_int {
+    let follow_symlink = flags & AT_SYMLINK_NOFOLLOW == 0;
+
+    chown(
+        path_at(dirfd, raw_path.checked_into()),
+        owner_id(owner),
+        owner_id(group),
+        follow_symlink,
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_FCHOWNAT(dirfd, raw_path, owner, group, flags)
+    })
+}
+
+/// Hook for `libc::truncate`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn truncate_detour(raw_path: *const c_char, length: off_t) -> c_int {
+    // Negative lengths are rejected by the original call.
+    let Ok(remote_length) = u64::try_from(length) else {
+        return FN_TRUNCATE(raw_path, length);
+    };
+
+    truncate(raw_path.checked_into(), remote_length).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_TRUNCATE(raw_path, length)
+    })
+}
+
+/// Hook for `libc::ftruncate`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn ftruncate_detour(fd: RawFd, length: off_t) -> c_int {
+    // Negative lengths are rejected by the original call.
+    let Ok(remote_length) = u64::try_from(length) else {
+        return FN_FTRUNCATE(fd, length);
+    };
+
+    ftruncate(fd, remote_length).unwrap_or_bypass_with(|_| FN_FTRUNCATE(fd, length))
+}
+
+/// Hook for `libc::symlink`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn symlink_detour(
+    target: *const c_char,
+    link_path: *const c_char,
+) -> c_int {
+    symlink(target.checked_into(), link_path.checked_into()).unwrap_or_bypass_with(|bypass| {
+        let link_path = update_ptr_from_bypass(link_path, &bypass);
+        FN_SYMLINK(target, link_path)
+    })
+}
+
+/// Hook for `libc::symlinkat`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn symlinkat_detour(
+    target: *const c_char,
+    new_dirfd: RawFd,
+    link_path: *const c_char,
+) -> c_int {
+    symlink(
+        target.checked_into(),
+        path_at(new_dirfd, link_path.checked_into()),
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let link_path = update_ptr_from_bypass(link_path, &bypass);
+        FN_SYMLINKAT(target, new_dirfd, link_path)
+    })
+}
+
+/// Converts a `timespec` argument of `utimensat`, including the special `UTIME_NOW` and
+/// `UTIME_OMIT` values.
+fn file_time_from_timespec(time: &timespec) -> FileTimeUpdate {
+    match time.tv_nsec {
+        libc::UTIME_NOW => FileTimeUpdate::Now,
+        libc::UTIME_OMIT => FileTimeUpdate::Omit,
+        nanoseconds => FileTimeUpdate::Set {
+            seconds: time.tv_sec,
+            nanoseconds: best_effort_cast(nanoseconds),
+        },
+    }
+}
+
+/// Converts a `timeval` argument of `utimes`.
+fn file_time_from_timeval(time: &timeval) -> FileTimeUpdate {
+    FileTimeUpdate::Set {
+        seconds: time.tv_sec,
+        nanoseconds: best_effort_cast(i64::from(time.tv_usec) * 1000),
+    }
+}
+
+/// Hook for `libc::utimes`.
+///
+/// A null `times` sets both timestamps to the current time.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn utimes_detour(
+    raw_path: *const c_char,
+    times: *const timeval,
+) -> c_int {
+    let (access_time, modification_time) = match times.as_ref() {
+        Some(access_time) => (
+            file_time_from_timeval(access_time),
+            file_time_from_timeval(&*times.add(1)),
+        ),
+        None => (FileTimeUpdate::Now, FileTimeUpdate::Now),
+    };
+
+    utimes(
+        raw_path.checked_into(),
+        access_time,
+        modification_time,
+        true,
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_UTIMES(raw_path, times)
+    })
+}
+
+/// Hook for `libc::utimensat`.
+///
+/// A null `times` sets both timestamps to the current time.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn utimensat_detour(
+    dirfd: RawFd,
+    raw_path: *const c_char,
+    times: *const timespec,
+    flags: c_int,
+) -> c_int {
+    let (access_time, modification_time) = match times.as_ref() {
+        Some(access_time) => (
+            file_time_from_timespec(access_time),
+            file_time_from_timespec(&*times.add(1)),
+        ),
+        None => (FileTimeUpdate::Now, FileTimeUpdate::Now),
+    };
+    let follow_symlink = flags & AT_SYMLINK_NOFOLLOW == 0;
+
+    utimes(
+        path_at(dirfd, raw_path.checked_into()),
+        access_time,
+        modification_time,
+        follow_symlink,
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_UTIMENSAT(dirfd, raw_path, times, flags)
+    })
+}
+
+/// Hook for `libc::mmap`.
+///
+/// Only mappings of remote files are handled, see [`super::mmap`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn mmap_detour(
+    address: *mut c_void,
+    length: size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: RawFd,
+    offset: off_t,
+) -> *mut c_void {
+    let mapped = if flags & MAP_ANONYMOUS != 0 || fd < 0 {
+        FN_MMAP(address, length, prot, flags, fd, offset)
+    } else {
+        match MAPPED_FILES.map(address, length, prot, flags, fd, offset) {
+            Detour::Success(mapped) => return mapped,
+            Detour::Bypass(_) => FN_MMAP(address, length, prot, flags, fd, offset),
+            Detour::Error(fail) => {
+                error!(%fail, "Failed to map a remote file!");
+                set_errno(Errno(fail.errno()));
+                return MAP_FAILED;
+            }
+        }
+    };
+
+    // A fixed mapping replaces whatever was mapped in its range, remote file mappings included.
+    if mapped != MAP_FAILED && flags & MAP_FIXED != 0 {
+        let _ = MAPPED_FILES.forget(mapped as usize, length);
+    }
+
+    mapped
+}
+
+/// Hook for `libc::msync`.
+///
+/// Writes the dirty pages of remote file mappings back to the remote files.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn msync_detour(
+    address: *mut c_void,
+    length: size_t,
+    flags: c_int,
+) -> c_int {
+    match MAPPED_FILES.sync(address as usize, length) {
+        Detour::Error(fail) => fail.into(),
+        _ => FN_MSYNC(address, length, flags),
+    }
+}
+
+/// Hook for `libc::munmap`.
+///
+/// Writes the dirty pages of remote file mappings back to the remote files before unmapping them.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn munmap_detour(address: *mut c_void, length: size_t) -> c_int {
+    if let Detour::Error(fail) = MAPPED_FILES.sync(address as usize, length) {
+        error!(%fail, "Failed to write back the dirty pages of a remote file mapping!");
+    }
+
+    let result = FN_MUNMAP(address, length);
+    if result == 0 {
+        let _ = MAPPED_FILES.forget(address as usize, length);
+    }
+
+    result
+}
+
+/// Hook for `libc::mremap`.
+///
+/// Remote file mappings can't be remapped, as the dirty pages are written back based on where the
+/// mapping was created, so this fails with `EINVAL` for them. A fixed remap replaces whatever was
+/// mapped at the destination, so we forget the remote file mappings there.
+#[cfg(target_os = "linux")]
+#[hook_fn]
+pub(crate) unsafe extern "C" fn mremap_detour(
+    old_address: *mut c_void,
+    old_size: size_t,
+    new_size: size_t,
+    flags: c_int,
+    mut args: ...
+) -> *mut c_void {
+    // Only meaningful with `MREMAP_FIXED`, passed along as is otherwise.
+    let new_address: *mut c_void = args.arg();
+    let guard = DetourGuard::new();
+    if guard.is_none() {
+        return FN_MREMAP(old_address, old_size, new_size, flags, new_address);
+    }
+
+    match MAPPED_FILES.is_mapped(old_address as usize, old_size) {
+        Detour::Success(false) | Detour::Bypass(_) => {}
+        Detour::Success(true) => {
+            warn!(
+                ?old_address,
+                old_size, "Refusing to remap a remote file mapping!"
+            );
+            set_errno(Errno(EINVAL));
+            return MAP_FAILED;
+        }
+        Detour::Error(fail) => {
+            error!(%fail, "Failed to check the remote file mappings!");
+            set_errno(Errno(fail.errno()));
+            return MAP_FAILED;
+        }
+    }
+
+    let remapped = FN_MREMAP(old_address, old_size, new_size, flags, new_address);
+    if remapped != MAP_FAILED && flags & MREMAP_FIXED != 0 {
+        let _ = MAPPED_FILES.forget(remapped as usize, new_size);
+    }
+
+    remapped
+}
+
+/// Hook for `libc::inotify_init`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn inotify_init_detour() -> c_int {
+    inotify::init(0).unwrap_or_bypass_with(|_| FN_INOTIFY_INIT())
+}
+
+/// Hook for `libc::inotify_init1`.
+///
+/// Returns an emulated instance that can watch remote files, see [`inotify`].
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn inotify_init1_detour(flags: c_int) -> c_int {
+    inotify::init(flags).unwrap_or_bypass_with(|_| FN_INOTIFY_INIT1(flags))
+}
+
+/// Hook for `libc::inotify_add_watch`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn inotify_add_watch_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    mask: u32,
+) -> c_int {
+    inotify::add_watch(fd, raw_path, mask)
+        .unwrap_or_bypass_with(|_| FN_INOTIFY_ADD_WATCH(fd, raw_path, mask))
+}
+
+/// Hook for `libc::inotify_rm_watch`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn inotify_rm_watch_detour(fd: RawFd, wd: c_int) -> c_int {
+    inotify::rm_watch(fd, wd).unwrap_or_bypass_with(|_| FN_INOTIFY_RM_WATCH(fd, wd))
+}
+
 /// Convenience function to setup file hooks (`x_detour`) with `frida_gum`.
 pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
     replace!(hook_manager, "open", open_detour, FnOpen, FN_OPEN);
@@ -1156,6 +1963,32 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         FN__PWRITE_NOCANCEL
     );
 
+    #[cfg(target_os = "linux")]
+    {
+        replace!(
+            hook_manager,
+            "sendfile",
+            sendfile_detour,
+            FnSendfile,
+            FN_SENDFILE
+        );
+        replace!(
+            hook_manager,
+            "sendfile64",
+            sendfile64_detour,
+            FnSendfile64,
+            FN_SENDFILE64
+        );
+        replace!(
+            hook_manager,
+            "copy_file_range",
+            copy_file_range_detour,
+            FnCopy_file_range,
+            FN_COPY_FILE_RANGE
+        );
+        replace!(hook_manager, "splice", splice_detour, FnSplice, FN_SPLICE);
+    }
+
     replace!(hook_manager, "access", access_detour, FnAccess, FN_ACCESS);
     replace!(
         hook_manager,
@@ -1174,6 +2007,123 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         FN_FDATASYNC
     );
 
+    replace!(hook_manager, "mmap", mmap_detour, FnMmap, FN_MMAP);
+    replace!(hook_manager, "msync", msync_detour, FnMsync, FN_MSYNC);
+    replace!(hook_manager, "munmap", munmap_detour, FnMunmap, FN_MUNMAP);
+
+    #[cfg(target_os = "linux")]
+    {
+        replace!(hook_manager, "mremap", mremap_detour, FnMremap, FN_MREMAP);
+        replace!(
+            hook_manager,
+            "inotify_init",
+            inotify_init_detour,
+            FnInotify_init,
+            FN_INOTIFY_INIT
+        );
+        replace!(
+            hook_manager,
+            "inotify_init1",
+            inotify_init1_detour,
+            FnInotify_init1,
+            FN_INOTIFY_INIT1
+        );
+        replace!(
+            hook_manager,
+            "inotify_add_watch",
+            inotify_add_watch_detour,
+            FnInotify_add_watch,
+            FN_INOTIFY_ADD_WATCH
+        );
+        replace!(
+            hook_manager,
+            "inotify_rm_watch",
+            inotify_rm_watch_detour,
+            FnInotify_rm_watch,
+            FN_INOTIFY_RM_WATCH
+        );
+    }
+
+    replace!(hook_manager, "mkdir", mkdir_detour, FnMkdir, FN_MKDIR);
+    replace!(
+        hook_manager,
+        "mkdirat",
+        mkdirat_detour,
+        FnMkdirat,
+        FN_MKDIRAT
+    );
+    replace!(hook_manager, "rmdir", rmdir_detour, FnRmdir, FN_RMDIR);
+    replace!(hook_manager, "unlink", unlink_detour, FnUnlink, FN_UNLINK);
+    replace!(
+        hook_manager,
+        "unlinkat",
+        unlinkat_detour,
+        FnUnlinkat,
+        FN_UNLINKAT
+    );
+    replace!(hook_manager, "rename", rename_detour, FnRename, FN_RENAME);
+    replace!(
+        hook_manager,
+        "renameat",
+        renameat_detour,
+        FnRenameat,
+        FN_RENAMEAT
+    );
+    replace!(hook_manager, "chmod", chmod_detour, FnChmod, FN_CHMOD);
+    replace!(hook_manager, "fchmod", fchmod_detour, FnFchmod, FN_FCHMOD);
+    replace!(
+        hook_manager,
+        "fchmodat",
+        fchmodat_detour,
+        FnFchmodat,
+        FN_FCHMODAT
+    );
+    replace!(hook_manager, "chown", chown_detour, FnChown, FN_CHOWN);
+    replace!(hook_manager, "lchown", lchown_detour, FnLchown, FN_LCHOWN);
+    replace!(
+        hook_manager,
+        "fchownat",
+        fchownat_detour,
+        FnFchownat,
+        FN_FCHOWNAT
+    );
+    replace!(
+        hook_manager,
+        "truncate",
+        truncate_detour,
+        FnTruncate,
+        FN_TRUNCATE
+    );
+    replace!(
+        hook_manager,
+        "ftruncate",
+        ftruncate_detour,
+        FnFtruncate,
+        FN_FTRUNCATE
+    );
+    replace!(
+        hook_manager,
+        "symlink",
+        symlink_detour,
+        FnSymlink,
+        FN_SYMLINK
+    );
+    replace!(
+        hook_manager,
+        "symlinkat",
+        symlinkat_detour,
+        FnSymlinkat,
+        FN_SYMLINKAT
+    );
+    replace!(hook_manager, "utimes", utimes_detour, FnUtimes, FN_UTIMES);
+    replace!(
+        hook_manager,
+        "utimensat",
+        utimensat_detour,
+        FnUtimensat,
+        FN_UTIMENSAT
+    );
+
     replace!(
         hook_manager,
         "realpath",
@@ -1250,6 +2200,37 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
             FnFstatfs,
             FN_FSTATFS
         );
+        replace!(hook_manager, "statfs", statfs_detour, FnStatfs, FN_STATFS);
+        #[cfg(target_os = "linux")]
+        replace!(
+            hook_manager,
+            "statfs64",
+            statfs64_detour,
+            FnStatfs64,
+            FN_STATFS64
+        );
+        replace!(
+            hook_manager,
+            "statvfs",
+            statvfs_detour,
+            FnStatvfs,
+            FN_STATVFS
+        );
+        #[cfg(target_os = "linux")]
+        replace!(
+            hook_manager,
+            "statvfs64",
+            statvfs64_detour,
+            FnStatvfs64,
+            FN_STATVFS64
+        );
+        replace!(
+            hook_manager,
+            "fstatvfs",
+            fstatvfs_detour,
+            FnFstatvfs,
+            FN_FSTATVFS
+        );
         replace!(
             hook_manager,
             "fdopendir",
@@ -1332,6 +2313,27 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
             FnFstatfs,
             FN_FSTATFS
         );
+        replace!(
+            hook_manager,
+            "statfs$INODE64",
+            statfs_detour,
+            FnStatfs,
+            FN_STATFS
+        );
+        replace!(
+            hook_manager,
+            "statvfs",
+            statvfs_detour,
+            FnStatvfs,
+            FN_STATVFS
+        );
+        replace!(
+            hook_manager,
+            "fstatvfs",
+            fstatvfs_detour,
+            FnFstatvfs,
+            FN_FSTATVFS
+        );
         replace!(
             hook_manager,
             "fdopendir$INODE64",
@@ -0,0 +1,478 @@
+//! Emulation of inotify instances that can watch remote files. Used in hooks like `inotify_init1`
+//! or `inotify_add_watch`.
+//!
+//! The descriptor returned to the application is one end of a local `AF_UNIX` stream socket pair,
+//! the layer writes `inotify_event` records to the other end. Reads from the descriptor are handled
+//! in [`read`], so that the application gets whole events, and `EINVAL` when its buffer is too
+//! small for the next one.
+//!
+//! The internal proxy sends the events of the remote watches in a separate session, opened with
+//! the first instance in the process. Local watches are kept in a real inotify instance, created
+//! with the first local watch. A single thread waits for both, see [`forward_events`].
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    net::TcpStream,
+    os::{
+        fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    path::PathBuf,
+    process, slice,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    thread,
+};
+
+use errno::{set_errno, Errno};
+use libc::{
+    c_char, c_int, c_void, epoll_event, ssize_t, EINTR, EINVAL, EPOLLIN, EPOLL_CLOEXEC,
+    EPOLL_CTL_ADD, EPOLL_CTL_DEL, F_SETFD, IN_CLOEXEC, IN_NONBLOCK, MSG_DONTWAIT, MSG_PEEK,
+};
+use mirrord_intproxy_protocol::{
+    codec::SyncDecoder, LayerId, LocalMessage, ProxyToLayerMessage, WatchEvent, WatchInstance,
+    WatchSubscribe, WatchUnsubscribe,
+};
+use mirrord_protocol::{file::AddWatchRequest, ResponseError};
+use tracing::Level;
+
+use super::{
+    hooks::{FN_INOTIFY_ADD_WATCH, FN_INOTIFY_INIT1, FN_INOTIFY_RM_WATCH},
+    ops::watched_path,
+    LocalFd,
+};
+use crate::{
+    common::{self, CheckedInto},
+    detour::{Bypass, Detour, DetourGuard},
+};
+
+/// Descriptors of the remote watches are allocated from here up, so that they don't collide with
+/// the descriptors of the local watches, allocated by the kernel from 1 up.
+const REMOTE_WD_BASE: c_int = 1 << 24;
+
+/// Size of `struct inotify_event` without the name.
+const INOTIFY_EVENT_SIZE: usize = 16;
+
+/// Local events are read in chunks of at most this size.
+const FORWARD_BUFFER_SIZE: usize = 4096;
+
+/// Marks the session with the remote events in the epoll instance of [`forward_events`]. The real
+/// inotify instances are marked with the ids of the emulated ones.
+const REMOTE_EVENTS: u64 = u64::MAX;
+
+/// Inotify instances emulated by the layer, by the descriptors returned to the application.
+pub(crate) static INOTIFY_INSTANCES: LazyLock<Mutex<HashMap<LocalFd, InotifyInstance>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Allocates the [`InotifyInstance::id`]s.
+static NEXT_INSTANCE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The thread forwarding the events, see [`Forwarder::get`].
+static FORWARDER: Mutex<Option<Forwarder>> = Mutex::new(None);
+
+/// An inotify instance emulated by the layer.
+#[derive(Debug)]
+pub(crate) struct InotifyInstance {
+    /// Identifies this instance in the remote watches and in the epoll instance of
+    /// [`forward_events`]. Unlike the descriptor, it's never reused.
+    id: u64,
+    /// The end of the socket pair kept by the layer, the events are written here.
+    events: UnixStream,
+    /// Real inotify instance for the local watches.
+    local: Option<OwnedFd>,
+    /// Descriptors of the remote watches, with the sessions in which they get the events.
+    remote_wds: HashMap<c_int, LayerId>,
+    next_remote_wd: c_int,
+}
+
+impl InotifyInstance {
+    fn add_remote_watch(&mut self, path: PathBuf, mask: u32) -> Detour<c_int> {
+        let (events_to, _) = Forwarder::get()?;
+        let request = WatchSubscribe {
+            request: AddWatchRequest { path, mask },
+            instance: WatchInstance {
+                events_to,
+                id: self.id,
+            },
+            wd: self.next_remote_wd,
+        };
+
+        match common::make_proxy_request_with_response(request)? {
+            Ok(wd) => {
+                if wd == self.next_remote_wd {
+                    self.next_remote_wd += 1;
+                }
+
+                self.remote_wds.insert(wd, events_to);
+                Detour::Success(wd)
+            }
+            Err(ResponseError::NotImplemented) => Detour::Bypass(Bypass::NotImplemented),
+            Err(fail) => Detour::Error(fail.into()),
+        }
+    }
+
+    fn add_local_watch(&mut self, raw_path: *const c_char, mask: u32) -> Detour<c_int> {
+        let local = match &self.local {
+            Some(local) => local.as_raw_fd(),
+            None => self.start_local()?,
+        };
+
+        Detour::Success(unsafe { FN_INOTIFY_ADD_WATCH(local, raw_path, mask) })
+    }
+
+    /// Creates the real inotify instance for the local watches, and adds it to the epoll instance
+    /// of [`forward_events`].
+    fn start_local(&mut self) -> Detour<RawFd> {
+        let (_, epoll) = Forwarder::get()?;
+
+        let fd = unsafe { FN_INOTIFY_INIT1(IN_NONBLOCK | IN_CLOEXEC) };
+        if fd == -1 {
+            return Detour::Error(io::Error::last_os_error().into());
+        }
+
+        // Closing the descriptor removes it from the epoll instance.
+        let local = unsafe { OwnedFd::from_raw_fd(fd) };
+        epoll_ctl(&epoll, EPOLL_CTL_ADD, fd, self.id)?;
+
+        Detour::Success(self.local.insert(local).as_raw_fd())
+    }
+
+    fn rm_watch(&mut self, wd: c_int) -> Detour<c_int> {
+        if let Some(events_to) = self.remote_wds.remove(&wd) {
+            common::make_proxy_request_no_response(WatchUnsubscribe {
+                instance: WatchInstance {
+                    events_to,
+                    id: self.id,
+                },
+                wd,
+            })?;
+
+            return Detour::Success(0);
+        }
+
+        match &self.local {
+            Some(local) => Detour::Success(unsafe { FN_INOTIFY_RM_WATCH(local.as_raw_fd(), wd) }),
+            None => Detour::Error(io::Error::from_raw_os_error(EINVAL).into()),
+        }
+    }
+
+    /// Writes whole `inotify_event` records to the application's end of the socket pair.
+    ///
+    /// The layer's end is non-blocking, and the records are written with a single call, so they
+    /// either fit in the socket buffer whole, or are dropped when the application doesn't read.
+    fn deliver(&self, records: &[u8]) {
+        if let Err(error) = (&self.events).write_all(records) {
+            tracing::debug!(%error, id = self.id, "Failed to deliver inotify events");
+        }
+    }
+
+    /// Removes the remote watches after the application closed the instance.
+    pub(crate) fn close(self) {
+        for (wd, events_to) in self.remote_wds {
+            let _ = common::make_proxy_request_no_response(WatchUnsubscribe {
+                instance: WatchInstance {
+                    events_to,
+                    id: self.id,
+                },
+                wd,
+            });
+        }
+    }
+}
+
+/// The thread that forwards the remote and local events to the inotify instances.
+#[derive(Debug)]
+struct Forwarder {
+    /// Process that started the thread, a forked child needs its own.
+    pid: u32,
+    /// Session in which the internal proxy sends the remote events.
+    events_to: LayerId,
+    /// The thread waits on this epoll instance.
+    epoll: Arc<OwnedFd>,
+}
+
+impl Forwarder {
+    /// Returns the events session and the epoll instance of the forwarding thread, starting the
+    /// thread if this process doesn't have one yet.
+    fn get() -> Detour<(LayerId, Arc<OwnedFd>)> {
+        let mut forwarder = FORWARDER.lock()?;
+
+        if let Some(forwarder) = forwarder.as_ref()
+            && forwarder.pid == process::id()
+        {
+            return Detour::Success((forwarder.events_to, forwarder.epoll.clone()));
+        }
+
+        let (events_to, session) = common::new_file_watch_session()?;
+
+        let epoll = unsafe { libc::epoll_create1(EPOLL_CLOEXEC) };
+        if epoll == -1 {
+            return Detour::Error(io::Error::last_os_error().into());
+        }
+        let epoll = Arc::new(unsafe { OwnedFd::from_raw_fd(epoll) });
+        epoll_ctl(&epoll, EPOLL_CTL_ADD, session.as_raw_fd(), REMOTE_EVENTS)?;
+
+        let thread_epoll = epoll.clone();
+        thread::Builder::new()
+            .name("mirrord-inotify".to_string())
+            .spawn(move || forward_events(thread_epoll, session))?;
+
+        *forwarder = Some(Self {
+            pid: process::id(),
+            events_to,
+            epoll: epoll.clone(),
+        });
+
+        Detour::Success((events_to, epoll))
+    }
+}
+
+fn epoll_ctl(epoll: &OwnedFd, op: c_int, fd: RawFd, token: u64) -> io::Result<()> {
+    let mut event = epoll_event {
+        events: EPOLLIN as u32,
+        u64: token,
+    };
+
+    if unsafe { libc::epoll_ctl(epoll.as_raw_fd(), op, fd, &mut event) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Waits for the events of the remote watches, sent by the internal proxy in the given session,
+/// and for the events of the real inotify instances. Writes them to the emulated instances.
+///
+/// Runs for as long as the process does.
+fn forward_events(epoll: Arc<OwnedFd>, session: TcpStream) {
+    // Everything this thread does is internal to the layer.
+    let _guard = DetourGuard::new();
+    let session_fd = session.as_raw_fd();
+    let mut session = Some(SyncDecoder::<LocalMessage<ProxyToLayerMessage>, _>::new(
+        session,
+    ));
+    let mut ready = [epoll_event { events: 0, u64: 0 }; 16];
+
+    loop {
+        let count = unsafe {
+            libc::epoll_wait(
+                epoll.as_raw_fd(),
+                ready.as_mut_ptr(),
+                ready.len() as c_int,
+                -1,
+            )
+        };
+        let Ok(count) = usize::try_from(count) else {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(EINTR) {
+                continue;
+            }
+
+            tracing::error!(%error, "Failed to wait for inotify events, stopping");
+            return;
+        };
+
+        for event in ready.iter().take(count) {
+            match event.u64 {
+                REMOTE_EVENTS => {
+                    let Some(decoder) = session.as_mut() else {
+                        continue;
+                    };
+
+                    match decoder.receive() {
+                        Ok(Some(LocalMessage {
+                            inner:
+                                ProxyToLayerMessage::FileWatchEvent(WatchEvent { instance, record }),
+                            ..
+                        })) => deliver_remote(instance, &record),
+                        Ok(Some(message)) => {
+                            tracing::debug!(?message, "Unexpected message in the events session")
+                        }
+                        Ok(None) | Err(..) => {
+                            tracing::debug!("Events session closed, remote events are gone");
+                            let _ = epoll_ctl(&epoll, EPOLL_CTL_DEL, session_fd, REMOTE_EVENTS);
+                            session = None;
+                        }
+                    }
+                }
+                instance => forward_local(instance),
+            }
+        }
+    }
+}
+
+/// Writes a remote event to the instance with the given id, if it's still open.
+fn deliver_remote(id: u64, record: &[u8]) {
+    let Ok(instances) = INOTIFY_INSTANCES.lock() else {
+        return;
+    };
+
+    if let Some(instance) = instances.values().find(|instance| instance.id == id) {
+        instance.deliver(record);
+    }
+}
+
+/// Moves the events from the real inotify instance to the emulated one with the given id.
+///
+/// The lock on [`INOTIFY_INSTANCES`] keeps the real instance open while we read from it.
+fn forward_local(id: u64) {
+    let Ok(instances) = INOTIFY_INSTANCES.lock() else {
+        return;
+    };
+    let Some((instance, local)) = instances
+        .values()
+        .find(|instance| instance.id == id)
+        .and_then(|instance| Some((instance, instance.local.as_ref()?)))
+    else {
+        return;
+    };
+
+    let mut buffer = [0_u8; FORWARD_BUFFER_SIZE];
+    loop {
+        // Non-blocking, and the kernel returns whole events only.
+        let read =
+            unsafe { libc::read(local.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+        let Ok(read @ 1..) = usize::try_from(read) else {
+            break;
+        };
+
+        if let Some(events) = buffer.get(..read) {
+            instance.deliver(events);
+        }
+    }
+}
+
+/// Creates an emulated inotify instance.
+///
+/// Unknown flags are left for the original call to report.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn init(flags: c_int) -> Detour<c_int> {
+    if flags & !(IN_NONBLOCK | IN_CLOEXEC) != 0 {
+        return Detour::Bypass(Bypass::NotImplemented);
+    }
+
+    Forwarder::get()?;
+
+    let (application_end, events) = UnixStream::pair()?;
+    events.set_nonblocking(true)?;
+    application_end.set_nonblocking(flags & IN_NONBLOCK != 0)?;
+    let fd = application_end.into_raw_fd();
+
+    // Sockets created by the standard library are always close-on-exec.
+    if flags & IN_CLOEXEC == 0 {
+        unsafe { libc::fcntl(fd, F_SETFD, 0) };
+    }
+
+    INOTIFY_INSTANCES.lock()?.insert(
+        fd,
+        InotifyInstance {
+            id: NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed),
+            events,
+            local: None,
+            remote_wds: Default::default(),
+            next_remote_wd: REMOTE_WD_BASE,
+        },
+    );
+
+    Detour::Success(fd)
+}
+
+/// Adds a watch to an emulated inotify instance.
+///
+/// Paths that are not handled remotely are watched locally, as are all paths when the agent does
+/// not support watches.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn add_watch(fd: LocalFd, raw_path: *const c_char, mask: u32) -> Detour<c_int> {
+    let mut instances = INOTIFY_INSTANCES.lock()?;
+    let instance = instances.get_mut(&fd).ok_or(Bypass::LocalFdNotFound(fd))?;
+
+    match watched_path(raw_path.checked_into())
+        .and_then(|path| instance.add_remote_watch(path, mask))
+    {
+        Detour::Bypass(..) => instance.add_local_watch(raw_path, mask),
+        remote => remote,
+    }
+}
+
+/// Removes a watch from an emulated inotify instance.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn rm_watch(fd: LocalFd, wd: c_int) -> Detour<c_int> {
+    INOTIFY_INSTANCES
+        .lock()?
+        .get_mut(&fd)
+        .ok_or(Bypass::LocalFdNotFound(fd))?
+        .rm_watch(wd)
+}
+
+/// Whether the given descriptor belongs to an emulated inotify instance.
+pub(crate) fn is_instance(fd: LocalFd) -> bool {
+    INOTIFY_INSTANCES
+        .lock()
+        .is_ok_and(|instances| instances.contains_key(&fd))
+}
+
+/// Reads whole events from an emulated inotify instance, like from a real one.
+///
+/// Fails with `EINVAL` if the buffer is too small for the next event. Blocks until there is an
+/// event, unless the instance is non-blocking.
+///
+/// # Safety
+///
+/// `buffer` must be valid for writes of `count` bytes.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, skip(buffer), ret)]
+pub(crate) unsafe fn read(fd: LocalFd, buffer: *mut c_void, count: usize) -> Detour<ssize_t> {
+    // The records are written whole, so the first one is complete once anything can be read.
+    let mut header = [0_u8; INOTIFY_EVENT_SIZE];
+    if libc::recv(fd, header.as_mut_ptr().cast(), header.len(), MSG_PEEK) == -1 {
+        // `errno` is set by `recv`.
+        return Detour::Success(-1);
+    }
+
+    // Expected by the applications that retry with a bigger buffer, not worth logging.
+    if count < record_size(&header) {
+        set_errno(Errno(EINVAL));
+        return Detour::Success(-1);
+    }
+
+    let buffer = slice::from_raw_parts_mut(buffer.cast::<u8>(), count);
+    let peeked = libc::recv(
+        fd,
+        buffer.as_mut_ptr().cast(),
+        count,
+        MSG_PEEK | MSG_DONTWAIT,
+    );
+    let Ok(peeked) = usize::try_from(peeked) else {
+        return Detour::Success(peeked);
+    };
+
+    // As many whole records as fit in the buffer.
+    let mut whole = 0;
+    while let Some(header) = buffer
+        .get(whole..peeked)
+        .and_then(|rest| rest.get(..INOTIFY_EVENT_SIZE))
+        && whole + record_size(header) <= peeked
+    {
+        whole += record_size(header);
+    }
+
+    Detour::Success(libc::recv(
+        fd,
+        buffer.as_mut_ptr().cast(),
+        whole,
+        MSG_DONTWAIT,
+    ))
+}
+
+/// Returns the size of the `inotify_event` record starting with the given header.
+fn record_size(header: &[u8]) -> usize {
+    let name_len = header
+        .get(12..INOTIFY_EVENT_SIZE)
+        .and_then(|name_len| name_len.try_into().ok())
+        .map(u32::from_ne_bytes)
+        .unwrap_or_default();
+
+    INOTIFY_EVENT_SIZE + name_len as usize
+}
@@ -1,25 +1,38 @@
-use std::{borrow::Cow, collections::HashMap, path::PathBuf};
+use std::{borrow::Cow, path::PathBuf};
 
+use mirrord_config::feature::fs::{PathMapping, PathMappingSide};
 use regex::{Regex, RegexSet, RegexSetBuilder};
 
 #[derive(Debug)]
 pub struct FileRemapper {
     filter: RegexSet,
-    mapping: Vec<(Regex, String)>,
+    mapping: Vec<(Regex, PathMapping)>,
+}
+
+/// A path after [`FileRemapper::change_path`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum MappedPath {
+    /// Not matched by any rule, or matched by a rule without a side, so it's handled according to
+    /// the file filter.
+    Filtered(PathBuf),
+    /// Matched by a `local:` rule, always accessed locally.
+    Local(PathBuf),
+    /// Matched by a `remote:` rule, always accessed remotely.
+    Remote(PathBuf),
 }
 
 impl FileRemapper {
-    pub fn new(mapping: HashMap<String, String>) -> Self {
-        let filter = RegexSetBuilder::new(mapping.keys())
+    pub fn new(mapping: Vec<PathMapping>) -> Self {
+        let filter = RegexSetBuilder::new(mapping.iter().map(|mapping| &mapping.pattern))
             .case_insensitive(true)
             .build()
             .expect("Building path mapping regex set failed");
         let mapping = mapping
             .into_iter()
-            .map(|(pattern, value)| {
+            .map(|mapping| {
                 (
-                    Regex::new(&pattern).expect("Building path mapping regex failed"),
-                    value,
+                    Regex::new(&mapping.pattern).expect("Building path mapping regex failed"),
+                    mapping,
                 )
             })
             .collect();
@@ -28,33 +41,63 @@ impl FileRemapper {
     }
 
     #[tracing::instrument(level = "trace", skip(self), ret)]
-    fn replace_path_str<'p>(&self, mapping_index: usize, path_str: &'p str) -> Cow<'p, str> {
-        let (pattern, value) = self
+    fn replace_path_str<'p>(
+        &self,
+        mapping_index: usize,
+        path_str: &'p str,
+    ) -> (Cow<'p, str>, Option<PathMappingSide>) {
+        let (pattern, mapping) = self
             .mapping
             .get(mapping_index)
             .expect("RegexSet matches returned an impossible index");
 
-        pattern.replace(path_str, value)
+        (
+            pattern.replace(path_str, mapping.replacement.as_str()),
+            mapping.side,
+        )
     }
 
-    // Don't instrument trace this or `change_path` because it spams a lot
-    pub fn change_path_str<'p>(&self, path_str: &'p str) -> Cow<'p, str> {
-        let matches = self.filter.matches(path_str);
+    /// Picks the most specific of the rules that match `path_str`, i.e. the one that matches the
+    /// longest part of it. Ties go to the rule that comes first in the [`PathMapping`] list.
+    fn most_specific(&self, path_str: &str) -> Option<usize> {
+        self.filter.matches(path_str).iter().max_by_key(|index| {
+            let matched = self
+                .mapping
+                .get(*index)
+                .and_then(|(pattern, _)| pattern.find(path_str))
+                .map(|found| found.len())
+                .unwrap_or_default();
+
+            (matched, std::cmp::Reverse(*index))
+        })
+    }
 
-        if let Some(index) = matches.iter().next() {
+    // Don't instrument trace this or `change_path` because it spams a lot
+    pub fn change_path_str<'p>(
+        &self,
+        path_str: &'p str,
+    ) -> (Cow<'p, str>, Option<PathMappingSide>) {
+        if let Some(index) = self.most_specific(path_str) {
             self.replace_path_str(index, path_str)
         } else {
-            Cow::Borrowed(path_str)
+            (Cow::Borrowed(path_str), None)
         }
     }
 
     // Don't instrument trace this or `change_path_str` because it spams a lot
-    pub fn change_path(&self, path: PathBuf) -> PathBuf {
+    pub fn change_path(&self, path: PathBuf) -> MappedPath {
         let path_str = path.to_str().unwrap_or_default();
 
-        match self.change_path_str(path_str) {
+        let (changed, side) = self.change_path_str(path_str);
+        let path = match changed {
             Cow::Borrowed(borrowed_path) if borrowed_path == path_str => path,
             updated_path => PathBuf::from(updated_path.as_ref()),
+        };
+
+        match side {
+            None => MappedPath::Filtered(path),
+            Some(PathMappingSide::Local) => MappedPath::Local(path),
+            Some(PathMappingSide::Remote) => MappedPath::Remote(path),
         }
     }
 }
@@ -65,13 +108,17 @@ mod tests {
 
     use super::*;
 
-    fn test_mapping() -> HashMap<String, String> {
+    fn test_mapping() -> Vec<PathMapping> {
         [
-            ("/foo".to_string(), "/bar".to_string()),
-            ("/(baz)".to_string(), "/tmp/mirrord-$1".to_string()),
-            ("^/Users/(?<user>.+)/Library/Caches/JetBrains/(?<intellij>.+)/tomcat/(?<uuid>.+)/static/manifest.xml".to_string(), "/opt/tomcat/static/manifest.xml".to_string())
+            ("/foo", "/bar"),
+            ("/(baz)", "/tmp/mirrord-$1"),
+            ("^/Users/(?<user>.+)/Library/Caches/JetBrains/(?<intellij>.+)/tomcat/(?<uuid>.+)/static/manifest.xml", "/opt/tomcat/static/manifest.xml"),
+            ("^/etc/app/secret\\.json$", "local:./dev/secret.json"),
+            ("^/home/(?<user>[^/]+)/data", "remote:/var/lib/$user"),
         ]
-        .into()
+        .into_iter()
+        .map(|(pattern, target)| PathMapping::new(pattern.to_string(), target))
+        .collect()
     }
 
     #[rstest]
@@ -83,6 +130,41 @@ mod tests {
     fn simple_mapping(#[case] input: PathBuf, #[case] expect: PathBuf) {
         let remapper = FileRemapper::new(test_mapping());
 
+        assert_eq!(remapper.change_path(input), MappedPath::Filtered(expect));
+    }
+
+    #[rstest]
+    #[case("/etc/app/secret.json", MappedPath::Local("./dev/secret.json".into()))]
+    #[case("/etc/app/config.json", MappedPath::Filtered("/etc/app/config.json".into()))]
+    #[case("/home/john/data/users.db", MappedPath::Remote("/var/lib/john/users.db".into()))]
+    fn mapping_side(#[case] input: PathBuf, #[case] expect: MappedPath) {
+        let remapper = FileRemapper::new(test_mapping());
+
+        assert_eq!(remapper.change_path(input), expect);
+    }
+
+    /// A broad rule must not shadow a narrower one, whatever their order in the list.
+    #[rstest]
+    #[case("/etc/app/secret.json", MappedPath::Local("./dev/secret.json".into()))]
+    #[case("/etc/app/config.json", MappedPath::Remote("/srv/app/config.json".into()))]
+    #[case("/etc/app", MappedPath::Remote("/srv/app".into()))]
+    fn most_specific_mapping(
+        #[case] input: PathBuf,
+        #[case] expect: MappedPath,
+        #[values(false, true)] reversed: bool,
+    ) {
+        let mut mapping: Vec<_> = [
+            ("^/etc/app", "remote:/srv/app"),
+            ("^/etc/app/secret\\.json$", "local:./dev/secret.json"),
+        ]
+        .into_iter()
+        .map(|(pattern, target)| PathMapping::new(pattern.to_string(), target))
+        .collect();
+        if reversed {
+            mapping.reverse();
+        }
+        let remapper = FileRemapper::new(mapping);
+
         assert_eq!(remapper.change_path(input), expect);
     }
 }
@@ -0,0 +1,350 @@
+//! Implementation of memory mappings of remote files. Used in hooks like `mmap`, `msync` or
+//! `munmap`.
+//!
+//! The local descriptor of a remote file points to an empty temporary file, so mapping it directly
+//! would give the application zeroes. Instead, the mapped range of the remote file is copied into a
+//! separate, unlinked backing file, and the backing file is mapped.
+//!
+//! Shared writable mappings are mapped privately, so that the backing file keeps the content last
+//! seen in the remote file. On `msync` and `munmap`, the mapped memory is compared with the backing
+//! file, and the dirty pages are written back to the remote file.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, File, OpenOptions},
+    os::unix::{fs::FileExt, io::AsRawFd},
+    slice,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use libc::{c_int, c_void, off_t, size_t, MAP_FAILED, MAP_PRIVATE, MAP_SHARED, PROT_WRITE};
+use mirrord_protocol::file::{ReadFileResponse, ReadLimitedFileRequest, WriteLimitedFileRequest};
+use rand::distributions::{Alphanumeric, DistString};
+use tracing::Level;
+
+use super::{
+    hooks::FN_MMAP,
+    ops::{RemoteFile, MAX_READ_SIZE},
+    LocalFd, OPEN_FILES,
+};
+use crate::{
+    common,
+    detour::{Bypass, Detour},
+};
+
+/// Granularity of the comparison between the mapped memory and the backing file.
+const DIRTY_CHUNK_SIZE: usize = 4096;
+
+/// Global instance of [`MappedFiles`]. Used in hooks.
+pub(crate) static MAPPED_FILES: LazyLock<MappedFiles> = LazyLock::new(MappedFiles::new);
+
+/// A memory mapping of a range of a remote file.
+struct MappedFile {
+    /// Keeps the remote file open for as long as it's mapped, even if the application closes the
+    /// descriptor.
+    remote_file: Arc<RemoteFile>,
+    /// Start address of the mapping.
+    address: usize,
+    /// Length of the mapping, as requested by the application.
+    length: usize,
+    /// Number of bytes at the start of the mapping that were present in the remote file.
+    content_length: usize,
+    /// Offset of the mapping in the remote file.
+    offset: u64,
+    /// Holds the content of the mapped range, as last seen in the remote file.
+    backing_file: Arc<File>,
+    /// Offset of the mapping in the backing file.
+    backing_offset: u64,
+    /// Whether changes in the mapped memory should be written back to the remote file.
+    write_back: bool,
+}
+
+impl MappedFile {
+    fn end(&self) -> usize {
+        self.address + self.length
+    }
+
+    /// Returns the part of this mapping in `[from, to)`, which must be contained in the mapping.
+    fn slice(&self, from: usize, to: usize) -> Self {
+        let skipped = from - self.address;
+
+        Self {
+            remote_file: self.remote_file.clone(),
+            address: from,
+            length: to - from,
+            content_length: self.content_length.saturating_sub(skipped).min(to - from),
+            offset: self.offset + skipped as u64,
+            backing_file: self.backing_file.clone(),
+            backing_offset: self.backing_offset + skipped as u64,
+            write_back: self.write_back,
+        }
+    }
+
+    /// Writes the dirty pages from `[from, to)` back to the remote file.
+    ///
+    /// # Safety
+    ///
+    /// `[from, to)` must be a readable part of this mapping.
+    unsafe fn write_back(&self, from: usize, to: usize) -> Detour<()> {
+        if !self.write_back {
+            return Detour::Success(());
+        }
+
+        // Writes past the end of the remote file are not persisted.
+        let to = to.min(self.address + self.content_length);
+        let mut dirty: Option<(usize, usize)> = None;
+
+        for chunk_start in (from..to).step_by(DIRTY_CHUNK_SIZE) {
+            let chunk_end = (chunk_start + DIRTY_CHUNK_SIZE).min(to);
+
+            let memory = slice::from_raw_parts(chunk_start as *const u8, chunk_end - chunk_start);
+            let mut original = vec![0; chunk_end - chunk_start];
+            self.backing_file
+                .read_exact_at(&mut original, self.backing_offset(chunk_start))?;
+
+            if memory == original {
+                if let Some((dirty_start, dirty_end)) = dirty.take() {
+                    self.write_range(dirty_start, dirty_end)?;
+                }
+
+                continue;
+            }
+
+            dirty = match dirty {
+                // Don't let a single write get too big.
+                Some((dirty_start, dirty_end))
+                    if dirty_end - dirty_start < MAX_READ_SIZE as usize =>
+                {
+                    Some((dirty_start, chunk_end))
+                }
+                Some((dirty_start, dirty_end)) => {
+                    self.write_range(dirty_start, dirty_end)?;
+                    Some((chunk_start, chunk_end))
+                }
+                None => Some((chunk_start, chunk_end)),
+            };
+        }
+
+        if let Some((dirty_start, dirty_end)) = dirty {
+            self.write_range(dirty_start, dirty_end)?;
+        }
+
+        Detour::Success(())
+    }
+
+    /// Writes the mapped memory from `[from, to)` to the remote file, and updates the backing
+    /// file.
+    ///
+    /// # Safety
+    ///
+    /// `[from, to)` must be a readable part of this mapping.
+    unsafe fn write_range(&self, from: usize, to: usize) -> Detour<()> {
+        let memory = slice::from_raw_parts(from as *const u8, to - from);
+
+        let request = WriteLimitedFileRequest {
+            remote_fd: self.remote_file.fd,
+            start_from: self.offset + (from - self.address) as u64,
+            write_bytes: memory.to_vec(),
+        };
+        common::make_proxy_request_with_response(request)??;
+
+        self.backing_file
+            .write_all_at(memory, self.backing_offset(from))?;
+
+        Detour::Success(())
+    }
+
+    fn backing_offset(&self, address: usize) -> u64 {
+        self.backing_offset + (address - self.address) as u64
+    }
+}
+
+/// State related to the memory mappings of remote files, keyed by the start address.
+pub(crate) struct MappedFiles {
+    inner: Mutex<BTreeMap<usize, MappedFile>>,
+}
+
+impl MappedFiles {
+    /// Creates an empty state.
+    fn new() -> Self {
+        Self {
+            inner: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Maps `length` bytes of the remote file paired with `local_fd`, starting from `offset`.
+    ///
+    /// Bypasses if `local_fd` is not a remote file.
+    #[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+    pub(crate) fn map(
+        &self,
+        address: *mut c_void,
+        length: size_t,
+        prot: c_int,
+        flags: c_int,
+        local_fd: LocalFd,
+        offset: off_t,
+    ) -> Detour<*mut c_void> {
+        let remote_file = OPEN_FILES
+            .lock()?
+            .get(&local_fd)
+            .cloned()
+            .ok_or(Bypass::LocalFdNotFound(local_fd))?;
+
+        // Let the original call report invalid arguments.
+        let (Ok(remote_offset), 1..) = (u64::try_from(offset), length) else {
+            return Detour::Success(unsafe {
+                FN_MMAP(address, length, prot, flags, local_fd, offset)
+            });
+        };
+
+        let backing_file = create_backing_file()?;
+        let content_length = fetch_content(&remote_file, remote_offset, length, &backing_file)?;
+
+        let write_back = prot & PROT_WRITE != 0 && flags & MAP_SHARED != 0;
+        let flags = if write_back {
+            (flags & !MAP_SHARED) | MAP_PRIVATE
+        } else {
+            flags
+        };
+
+        let mapped = unsafe { FN_MMAP(address, length, prot, flags, backing_file.as_raw_fd(), 0) };
+        if mapped == MAP_FAILED {
+            return Detour::Success(mapped);
+        }
+
+        let mapped_file = MappedFile {
+            remote_file,
+            address: mapped as usize,
+            length,
+            content_length,
+            offset: remote_offset,
+            backing_file: Arc::new(backing_file),
+            backing_offset: 0,
+            write_back,
+        };
+
+        let mut mappings = self.inner.lock()?;
+        // A fixed mapping might have replaced our previous mappings.
+        Self::remove_range(&mut mappings, mapped_file.address, mapped_file.end());
+        mappings.insert(mapped_file.address, mapped_file);
+
+        Detour::Success(mapped)
+    }
+
+    /// Writes the dirty pages of the remote file mappings in `[address, address + length)` back to
+    /// the remote files.
+    ///
+    /// # Safety
+    ///
+    /// The range must not have been unmapped by the application.
+    #[mirrord_layer_macro::instrument(level = Level::TRACE)]
+    pub(crate) unsafe fn sync(&self, address: usize, length: size_t) -> Detour<()> {
+        let mappings = self.inner.lock()?;
+        let end = address.saturating_add(length);
+
+        for mapping in mappings
+            .range(..end)
+            .map(|(_, mapping)| mapping)
+            .filter(|mapping| mapping.end() > address)
+        {
+            mapping.write_back(address.max(mapping.address), end.min(mapping.end()))?;
+        }
+
+        Detour::Success(())
+    }
+
+    /// Forgets about the remote file mappings in `[address, address + length)`, after they were
+    /// unmapped.
+    pub(crate) fn forget(&self, address: usize, length: size_t) -> Detour<()> {
+        let mut mappings = self.inner.lock()?;
+        Self::remove_range(&mut mappings, address, address.saturating_add(length));
+
+        Detour::Success(())
+    }
+
+    /// Returns whether any remote file mapping overlaps `[address, address + length)`.
+    pub(crate) fn is_mapped(&self, address: usize, length: size_t) -> Detour<bool> {
+        let mappings = self.inner.lock()?;
+        let end = address.saturating_add(length.max(1));
+
+        Detour::Success(
+            mappings
+                .range(..end)
+                .any(|(_, mapping)| mapping.end() > address),
+        )
+    }
+
+    /// Removes `[from, to)` from the tracked mappings, keeping the parts of partially covered
+    /// mappings that lie outside of the range.
+    fn remove_range(mappings: &mut BTreeMap<usize, MappedFile>, from: usize, to: usize) {
+        let overlapping = mappings
+            .range(..to)
+            .filter(|(_, mapping)| mapping.end() > from)
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+
+        for address in overlapping {
+            let Some(mapping) = mappings.remove(&address) else {
+                continue;
+            };
+
+            if mapping.address < from {
+                mappings.insert(mapping.address, mapping.slice(mapping.address, from));
+            }
+
+            if to < mapping.end() {
+                mappings.insert(to, mapping.slice(to, mapping.end()));
+            }
+        }
+    }
+}
+
+/// Creates an unlinked temporary file to back a mapping.
+fn create_backing_file() -> Detour<File> {
+    let random_string = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+    let path = env::temp_dir().join(format!("mirrord-mmap-{random_string}"));
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+
+    Detour::Success(file)
+}
+
+/// Copies `length` bytes of the remote file, starting from `offset`, into the start of the
+/// backing file.
+///
+/// Returns the number of bytes copied, which is smaller than `length` when the mapping goes past
+/// the end of the remote file.
+fn fetch_content(
+    remote_file: &RemoteFile,
+    offset: u64,
+    length: usize,
+    backing_file: &File,
+) -> Detour<usize> {
+    let mut content_length = 0;
+
+    while content_length < length {
+        let request = ReadLimitedFileRequest {
+            remote_fd: remote_file.fd,
+            buffer_size: ((length - content_length) as u64).min(MAX_READ_SIZE),
+            start_from: offset + content_length as u64,
+        };
+        let ReadFileResponse { bytes, read_amount } =
+            common::make_proxy_request_with_response(request)??;
+
+        if read_amount == 0 {
+            break;
+        }
+
+        backing_file.write_all_at(&bytes, content_length as u64)?;
+        content_length += bytes.len();
+    }
+
+    Detour::Success(content_length)
+}
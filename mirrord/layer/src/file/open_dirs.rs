@@ -10,7 +10,7 @@ use std::{
 use mirrord_protocol::file::{CloseDirRequest, DirEntryInternal, ReadDirRequest, ReadDirResponse};
 use tracing::Level;
 
-use super::{DirStreamFd, LocalFd, RemoteFd, OPEN_FILES};
+use super::{overlay::OverlayListing, DirStreamFd, LocalFd, RemoteFd, OPEN_FILES};
 use crate::{
     common,
     detour::{Bypass, Detour},
@@ -39,15 +39,17 @@ impl OpenDirs {
     ///
     /// * `local_dir_fd` - opaque identifier
     /// * `remote_fd` - descriptor of the remote directory (received from the agent)
+    /// * `overlay` - local entries to merge into the listing, in the copy-on-write file mode
     pub fn insert(
         &self,
         local_dir_fd: DirStreamFd,
         remote_fd: RemoteFd,
         base_fd: LocalFd,
+        overlay: Option<OverlayListing>,
     ) -> Detour<()> {
         self.inner.lock()?.insert(
             local_dir_fd,
-            Mutex::new(OpenDir::new(local_dir_fd, remote_fd, base_fd)).into(),
+            Mutex::new(OpenDir::new(local_dir_fd, remote_fd, base_fd, overlay)).into(),
         );
         Detour::Success(())
     }
@@ -61,7 +63,7 @@ impl OpenDirs {
             .ok_or(Bypass::LocalDirStreamNotFound(local_dir_fd))?
             .clone();
 
-        let guard = dir.lock().expect("lock poisoned");
+        let mut guard = dir.lock().expect("lock poisoned");
 
         guard.read_r()
     }
@@ -168,13 +170,19 @@ struct OpenDir {
     remote_fd: RemoteFd,
     // fd used for opening the dir originally
     base_fd: LocalFd,
+    overlay: Option<OverlayListing>,
     dirent: libc::dirent,
     #[cfg(target_os = "linux")]
     dirent64: libc::dirent64,
 }
 
 impl OpenDir {
-    fn new(local_fd: DirStreamFd, remote_fd: RemoteFd, base_fd: LocalFd) -> Self {
+    fn new(
+        local_fd: DirStreamFd,
+        remote_fd: RemoteFd,
+        base_fd: LocalFd,
+        overlay: Option<OverlayListing>,
+    ) -> Self {
         #[cfg(not(target_os = "macos"))]
         let dirent = libc::dirent {
             d_ino: 0,
@@ -199,6 +207,7 @@ impl OpenDir {
             local_fd,
             remote_fd,
             base_fd,
+            overlay,
             dirent,
             #[cfg(target_os = "linux")]
             dirent64: libc::dirent64 {
@@ -212,17 +221,26 @@ impl OpenDir {
     }
 
     #[tracing::instrument(level = Level::DEBUG, skip(self), ret)]
-    fn read_r(&self) -> Detour<Option<DirEntryInternal>> {
+    fn read_r(&mut self) -> Detour<Option<DirEntryInternal>> {
         if self.closed {
             // This thread got this struct from `OpenDirs` before `close` removed it.
             return Detour::Bypass(Bypass::LocalDirStreamNotFound(self.local_fd));
         }
 
-        let ReadDirResponse { direntry } =
-            common::make_proxy_request_with_response(ReadDirRequest {
-                remote_fd: self.remote_fd,
-            })??;
-        Detour::Success(direntry)
+        loop {
+            let ReadDirResponse { direntry } =
+                common::make_proxy_request_with_response(ReadDirRequest {
+                    remote_fd: self.remote_fd,
+                })??;
+
+            // Remote entries removed or copied in the overlay are skipped, the local entries
+            // follow the remote ones.
+            match (direntry, &mut self.overlay) {
+                (Some(entry), Some(overlay)) if !overlay.shows(&entry.name) => continue,
+                (None, Some(overlay)) => return Detour::Success(overlay.next_local()),
+                (direntry, _) => return Detour::Success(direntry),
+            }
+        }
     }
 
     fn get_base_fd(&self) -> LocalFd {
@@ -1,22 +1,41 @@
 #[cfg(target_os = "linux")]
 use std::time::Duration;
-use std::{env, ffi::CString, io::SeekFrom, os::unix::io::RawFd, path::PathBuf};
+use std::{
+    env,
+    ffi::CString,
+    fmt::Debug,
+    fs::{self, Permissions},
+    io::{self, SeekFrom, Write},
+    os::unix::{fs::PermissionsExt, io::RawFd},
+    path::{Path, PathBuf},
+};
 
 #[cfg(target_os = "linux")]
 use libc::{c_char, statx, statx_timestamp};
-use libc::{c_int, iovec, unlink, AT_FDCWD};
+use libc::{c_int, iovec, AT_FDCWD};
+use mirrord_intproxy_protocol::IsLayerRequestWithResponse;
 use mirrord_protocol::{
     file::{
-        OpenFileRequest, OpenFileResponse, OpenOptionsInternal, ReadFileResponse,
-        ReadLinkFileRequest, ReadLinkFileResponse, SeekFileResponse, WriteFileResponse,
-        XstatFsResponse, XstatResponse,
+        ChmodRequest, ChownRequest, CloseDirRequest, FchmodRequest, FileTimeUpdate,
+        FtruncateRequest, MakeDirRequest, MetadataInternal, OpenFileRequest, OpenFileResponse,
+        OpenOptionsInternal, ReadDirRequest, ReadDirResponse, ReadFileResponse,
+        ReadLinkFileRequest, ReadLinkFileResponse, RemoveDirRequest, RenameRequest,
+        SeekFileResponse, StatFsRequest, StatFsRequestV2, SymlinkRequest, TruncateRequest,
+        UnlinkRequest, UtimesRequest, WriteFileResponse, XstatFsRequestV2, XstatFsResponseV2,
+        XstatResponse,
     },
-    ResponseError,
+    ErrorKindInternal, RemoteIOError, RemoteResult, ResponseError,
 };
 use rand::distributions::{Alphanumeric, DistString};
 use tracing::{error, trace, Level};
 
-use super::{hooks::FN_OPEN, open_dirs::OPEN_DIRS, *};
+use super::{
+    hooks::FN_OPEN,
+    mapper::MappedPath,
+    open_dirs::OPEN_DIRS,
+    overlay::{FileOverlay, OverlayEntry},
+    *,
+};
 #[cfg(target_os = "linux")]
 use crate::common::CheckedInto;
 use crate::{
@@ -26,7 +45,16 @@ use crate::{
 };
 
 /// 1 Megabyte. Large read requests can lead to timeouts.
-const MAX_READ_SIZE: u64 = 1024 * 1024;
+pub(super) const MAX_READ_SIZE: u64 = 1024 * 1024;
+
+/// Bit mask for the file type in [`MetadataInternal::mode`].
+const S_IFMT: u32 = 0o170000;
+
+/// Directory type in [`MetadataInternal::mode`].
+const S_IFDIR: u32 = 0o040000;
+
+/// Symbolic link type in [`MetadataInternal::mode`].
+const S_IFLNK: u32 = 0o120000;
 
 /// Helper macro for checking if the given path should be handled remotely.
 /// Uses global [`crate::setup()`].
@@ -55,12 +83,40 @@ macro_rules! check_relative_paths {
     };
 }
 
+/// Helper macro for applying the path [`mapping`](mirrord_config::feature::fs::FsConfig::mapping)
+/// to the given path. Uses global [`crate::setup()`].
+///
+/// Should the path be mapped to the local side, this macro exits current context with
+/// [`Bypass::LocalMapping`].
+///
+/// Evaluates to the mapped path, and whether it was mapped to the remote side.
 macro_rules! remap_path {
     ($path:expr) => {
-        $crate::setup().file_remapper().change_path($path)
+        match $crate::setup().file_remapper().change_path($path) {
+            MappedPath::Filtered(path) => (path, false),
+            MappedPath::Remote(path) => (path, true),
+            MappedPath::Local(path) => return Detour::Bypass(Bypass::local_mapping(path)),
+        }
     };
 }
 
+/// Helper macro for applying the path mapping with `remap_path!`, and checking if the mapped
+/// path should be handled remotely with `ensure_not_ignored!`.
+///
+/// Paths mapped to the remote side are not checked.
+///
+/// Evaluates to the mapped path.
+macro_rules! remote_path {
+    ($path:expr, $write:expr) => {{
+        let (path, mapped_remote) = remap_path!($path);
+        if !mapped_remote {
+            ensure_not_ignored!(path, $write);
+        }
+
+        path
+    }};
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct RemoteFile {
     pub fd: u64,
@@ -127,7 +183,7 @@ impl Drop for RemoteFile {
 
 /// Helper function that retrieves the `remote_fd` (which is generated by
 /// `mirrord_agent::util::IndexAllocator`).
-fn get_remote_fd(local_fd: RawFd) -> Detour<u64> {
+pub(crate) fn get_remote_fd(local_fd: RawFd) -> Detour<u64> {
     // don't add a trace here since it causes deadlocks in some cases.
     Detour::Success(
         OPEN_FILES
@@ -153,7 +209,7 @@ fn create_local_fake_file(remote_fd: u64) -> Detour<RawFd> {
         close_remote_file_on_failure(remote_fd)?;
         Detour::Error(HookError::LocalFileCreation(remote_fd))
     } else {
-        unsafe { unlink(file_path_ptr) };
+        unsafe { libc::unlink(file_path_ptr) };
         Detour::Success(local_file_fd)
     }
 }
@@ -182,9 +238,11 @@ pub(crate) fn open(path: Detour<PathBuf>, open_options: OpenOptionsInternal) ->
 
     check_relative_paths!(path);
 
-    let path = remap_path!(path);
+    let path = remote_path!(path, open_options.is_write());
 
-    ensure_not_ignored!(path, open_options.is_write());
+    if let Some(overlay) = copy_on_write(&path) {
+        open_copy_on_write(overlay, &path, open_options)?;
+    }
 
     let OpenFileResponse { fd: remote_fd } = RemoteFile::remote_open(path.clone(), open_options)?;
 
@@ -206,11 +264,15 @@ pub(crate) fn open(path: Detour<PathBuf>, open_options: OpenOptionsInternal) ->
 pub(crate) fn fdopendir(fd: RawFd) -> Detour<usize> {
     // usize == ptr size
     // we don't return a pointer to an address that contains DIR
-    let remote_file_fd = OPEN_FILES
-        .lock()?
-        .get(&fd)
-        .ok_or(Bypass::LocalFdNotFound(fd))?
-        .fd;
+    let (remote_file_fd, path) = {
+        let open_files = OPEN_FILES.lock()?;
+        let remote_file = open_files.get(&fd).ok_or(Bypass::LocalFdNotFound(fd))?;
+        (remote_file.fd, PathBuf::from(&remote_file.path))
+    };
+
+    let overlay = copy_on_write(&path)
+        .map(|overlay| overlay.listing(&path))
+        .transpose()?;
 
     let open_dir_request = FdOpenDirRequest {
         remote_fd: remote_file_fd,
@@ -220,7 +282,7 @@ pub(crate) fn fdopendir(fd: RawFd) -> Detour<usize> {
         common::make_proxy_request_with_response(open_dir_request)??;
 
     let local_dir_fd = create_local_fake_file(remote_dir_fd)?;
-    OPEN_DIRS.insert(local_dir_fd as usize, remote_dir_fd, fd)?;
+    OPEN_DIRS.insert(local_dir_fd as usize, remote_dir_fd, fd, overlay)?;
 
     // Let it stay in OPEN_FILES, as some functions might use it in comibination with dirfd
 
@@ -238,12 +300,16 @@ pub(crate) fn openat(
     // `openat` behaves the same as `open` when the path is absolute. When called with AT_FDCWD, the
     // call is propagated to `open`.
     if path.is_absolute() || fd == AT_FDCWD {
-        let path = remap_path!(path);
         open(Detour::Success(path), open_options)
+    } else if crate::setup().file_overlay().is_some() {
+        // In the copy-on-write mode the file may have a local copy, which is found by the full
+        // path.
+        open(path_at(fd, Detour::Success(path)), open_options)
     } else {
         // Relative path requires special handling, we must identify the relative part (relative to
         // what).
         let remote_fd = get_remote_fd(fd)?;
+        let full_path = path_at(fd, Detour::Success(path.clone()))?;
 
         let requesting_file = OpenRelativeFileRequest {
             relative_fd: remote_fd,
@@ -258,13 +324,35 @@ pub(crate) fn openat(
 
         OPEN_FILES.lock()?.insert(
             local_file_fd,
-            Arc::new(RemoteFile::new(remote_fd, path.display().to_string())),
+            Arc::new(RemoteFile::new(remote_fd, full_path.display().to_string())),
         );
 
         Detour::Success(local_file_fd)
     }
 }
 
+/// Resolves the `path` argument of the `*at` functions (e.g. `mkdirat`) against the remote
+/// directory open as `dir_fd`, so that it can be used in a path based request.
+///
+/// Absolute paths and paths relative to [`AT_FDCWD`] are returned unchanged. Bypassed if `dir_fd`
+/// is not a remote directory.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn path_at(dir_fd: RawFd, path: Detour<PathBuf>) -> Detour<PathBuf> {
+    let path = path?;
+
+    if path.is_absolute() || dir_fd == AT_FDCWD {
+        return Detour::Success(path);
+    }
+
+    let base = OPEN_FILES
+        .lock()?
+        .get(&dir_fd)
+        .map(|remote_file| PathBuf::from(&remote_file.path))
+        .ok_or(Bypass::LocalFdNotFound(dir_fd))?;
+
+    Detour::Success(base.join(path))
+}
+
 /// Blocking wrapper around [`libc::read`] call.
 ///
 /// **Bypassed** when trying to load system files, and files from the current working directory, see
@@ -301,11 +389,13 @@ pub(crate) fn pread(local_fd: RawFd, buffer_size: u64, offset: u64) -> Detour<Re
 /// Resolves the symbolic link `path`.
 #[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
 pub(crate) fn read_link(path: Detour<PathBuf>) -> Detour<ReadLinkFileResponse> {
-    let path = remap_path!(path?);
+    let path = path?;
 
     check_relative_paths!(path);
 
-    ensure_not_ignored!(path, false);
+    let path = remote_path!(path, false);
+
+    overlaid(&path)?;
 
     let requesting_path = ReadLinkFileRequest { path };
 
@@ -374,15 +464,70 @@ pub(crate) fn write(local_fd: RawFd, write_bytes: Option<Vec<u8>>) -> Detour<isi
     Detour::Success(written_amount.try_into()?)
 }
 
+/// Copies up to `count` bytes of the remote file `local_fd` with `write_out`, for the `sendfile`,
+/// `copy_file_range` and `splice` hooks, which would copy the empty local fake file otherwise.
+///
+/// Reads from `offset` when given, leaving the file position untouched, otherwise from the file
+/// position, which is then moved past the copied bytes.
+///
+/// Large copies are read in chunks of [`MAX_READ_SIZE`], all within this call. Stops early on the
+/// end of the file or on a short write, and only fails if nothing was copied.
+#[mirrord_layer_macro::instrument(level = "trace", skip(write_out), ret)]
+pub(crate) fn copy_from_remote<W>(
+    local_fd: RawFd,
+    offset: Option<u64>,
+    count: u64,
+    mut write_out: W,
+) -> Detour<u64>
+where
+    W: FnMut(&[u8]) -> Detour<usize>,
+{
+    let start = match offset {
+        Some(offset) => offset,
+        None => lseek(local_fd, 0, libc::SEEK_CUR)?,
+    };
+
+    let mut copied = 0;
+    while copied < count {
+        let chunk_size = (count - copied).min(MAX_READ_SIZE);
+        let ReadFileResponse { bytes, .. } = pread(local_fd, chunk_size, start + copied)?;
+        if bytes.is_empty() {
+            break;
+        }
+
+        let mut written = 0;
+        while written < bytes.len() {
+            match write_out(&bytes[written..]) {
+                Detour::Success(0) => break,
+                Detour::Success(amount) => written += amount,
+                failed if copied + written as u64 == 0 => return failed.map(|_| 0),
+                // Reported by the next call, once the copied bytes are accounted for.
+                _ => break,
+            }
+        }
+
+        copied += written as u64;
+        if written < bytes.len() || (bytes.len() as u64) < chunk_size {
+            break;
+        }
+    }
+
+    if offset.is_none() {
+        lseek(local_fd, (start + copied) as i64, libc::SEEK_SET)?;
+    }
+
+    Detour::Success(copied)
+}
+
 #[mirrord_layer_macro::instrument(level = "trace")]
 pub(crate) fn access(path: Detour<PathBuf>, mode: u8) -> Detour<c_int> {
     let path = path?;
 
     check_relative_paths!(path);
 
-    let path = remap_path!(path);
+    let path = remote_path!(path, false);
 
-    ensure_not_ignored!(path, false);
+    overlaid(&path)?;
 
     let access = AccessFileRequest {
         pathname: path,
@@ -418,17 +563,15 @@ pub(crate) fn xstat(
         // fstatat
         (Some(path), Some(fd)) => {
             let path = path?;
-            let fd = {
-                if fd == AT_FDCWD {
-                    check_relative_paths!(path);
-
-                    ensure_not_ignored!(remap_path!(path.clone()), false);
-                    None
-                } else {
-                    Some(get_remote_fd(fd)?)
-                }
-            };
-            (Some(path), fd)
+            if fd == AT_FDCWD {
+                check_relative_paths!(path);
+
+                let path = remote_path!(path, false);
+                overlaid(&path)?;
+                (Some(path), None)
+            } else {
+                (Some(path), Some(get_remote_fd(fd)?))
+            }
         }
         // lstat/stat
         (Some(path), None) => {
@@ -436,9 +579,8 @@ pub(crate) fn xstat(
 
             check_relative_paths!(path);
 
-            let path = remap_path!(path);
-
-            ensure_not_ignored!(path, false);
+            let path = remote_path!(path, false);
+            overlaid(&path)?;
             (Some(path), None)
         }
         // fstat
@@ -447,6 +589,15 @@ pub(crate) fn xstat(
         (None, None) => return Detour::Error(HookError::NullPointer),
     };
 
+    remote_xstat(path, fd, follow_symlink)
+}
+
+/// Sends a [`XstatRequest`], for the remote file `path` or the remote descriptor `fd`.
+fn remote_xstat(
+    path: Option<PathBuf>,
+    fd: Option<u64>,
+    follow_symlink: bool,
+) -> Detour<XstatResponse> {
     let lstat = XstatRequest {
         fd,
         path,
@@ -495,7 +646,8 @@ pub(crate) fn statx_logic(
     }
 
     let (fd, path) = if path_name.is_absolute() {
-        ensure_not_ignored!(path_name, false);
+        let path_name = remote_path!(path_name, false);
+        overlaid(&path_name)?;
         (None, Some(path_name))
     } else if !path_name.as_os_str().is_empty() && dir_fd == libc::AT_FDCWD {
         return Detour::Bypass(Bypass::relative_path(
@@ -582,14 +734,44 @@ pub(crate) fn statx_logic(
 }
 
 #[mirrord_layer_macro::instrument(level = "trace")]
-pub(crate) fn xstatfs(fd: RawFd) -> Detour<XstatFsResponse> {
+pub(crate) fn xstatfs(fd: RawFd) -> Detour<XstatFsResponseV2> {
     let fd = get_remote_fd(fd)?;
 
-    let lstatfs = XstatFsRequest { fd };
+    // Agents that don't support `XstatFsRequestV2` get the old request, without the name length.
+    match common::make_proxy_request_with_response(XstatFsRequestV2 { fd })? {
+        Err(ResponseError::NotImplemented) => {
+            let response = common::make_proxy_request_with_response(XstatFsRequest { fd })??;
+            Detour::Success(response.into())
+        }
+        response => Detour::Success(response?),
+    }
+}
 
-    let response = common::make_proxy_request_with_response(lstatfs)??;
+/// Gets the metadata of the remote file system that contains `path`, for `statfs` and `statvfs`.
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(crate) fn statfs_path(path: Detour<PathBuf>) -> Detour<XstatFsResponseV2> {
+    let path = path?;
 
-    Detour::Success(response)
+    check_relative_paths!(path);
+
+    let path = remote_path!(path, false);
+
+    overlaid(&path)?;
+
+    let response =
+        match common::make_proxy_request_with_response(StatFsRequestV2 { path: path.clone() })? {
+            Err(ResponseError::NotImplemented) => {
+                common::make_proxy_request_with_response(StatFsRequest { path })?.map(Into::into)
+            }
+            response => response,
+        };
+
+    // `NotImplemented` error here means that the protocol doesn't support it.
+    match response {
+        Ok(response) => Detour::Success(response),
+        Err(ResponseError::NotImplemented) => Detour::Bypass(Bypass::NotImplemented),
+        Err(fail) => Detour::Error(fail.into()),
+    }
 }
 
 #[cfg(target_os = "linux")]
@@ -598,14 +780,59 @@ pub(crate) fn getdents64(fd: RawFd, buffer_size: u64) -> Detour<GetDEnts64Respon
     // We're only interested in files that are paired with mirrord-agent.
     let remote_fd = get_remote_fd(fd)?;
 
-    let getdents64 = GetDEnts64Request {
-        remote_fd,
-        buffer_size,
+    let request = || {
+        common::make_proxy_request_with_response(GetDEnts64Request {
+            remote_fd,
+            buffer_size,
+        })
     };
 
-    let response = common::make_proxy_request_with_response(getdents64)??;
+    let path = PathBuf::from(
+        &OPEN_FILES
+            .lock()?
+            .get(&fd)
+            .ok_or(Bypass::LocalFdNotFound(fd))?
+            .path,
+    );
+    let Some(overlay) = copy_on_write(&path) else {
+        return Detour::Success(request()??);
+    };
 
-    Detour::Success(response)
+    // The listing is taken out of the map for the duration of the proxy requests, so that other
+    // threads are not blocked on `DIR_LISTINGS` in the meantime.
+    let taken = super::overlay::DIR_LISTINGS.lock()?.remove(&fd);
+    let mut listing = match taken {
+        Some(listing) => listing,
+        None => overlay.listing(&path)?,
+    };
+
+    // An empty result means the end of the directory, so we keep asking until some remote
+    // entries are not hidden by the overlay.
+    let mut merge = || -> Detour<GetDEnts64Response> {
+        loop {
+            let response = request()??;
+            let remote_done = response.entries.is_empty();
+            let entries = listing.merge_batch(response.entries, buffer_size)?;
+
+            if !entries.is_empty() || remote_done {
+                let result_size = entries
+                    .iter()
+                    .map(|entry| u64::from(entry.get_d_reclen64()))
+                    .sum();
+
+                return Detour::Success(GetDEnts64Response {
+                    fd: response.fd,
+                    entries,
+                    result_size,
+                });
+            }
+        }
+    };
+    let result = merge();
+
+    super::overlay::DIR_LISTINGS.lock()?.insert(fd, listing);
+
+    result
 }
 
 /// Resolves ./ and ../ in the path, and returns an absolute path.
@@ -633,18 +860,529 @@ pub(crate) fn realpath(path: Detour<PathBuf>) -> Detour<PathBuf> {
 
     check_relative_paths!(path);
 
-    let path = remap_path!(path);
+    let (path, mapped_remote) = remap_path!(path);
 
     let realpath = absolute_path(path);
 
-    ensure_not_ignored!(realpath, false);
+    if !mapped_remote {
+        ensure_not_ignored!(realpath, false);
+    }
 
-    // check that file exists
-    xstat(Some(Detour::Success(realpath.clone())), None, true)?;
+    // check that file exists, the local copy in the copy-on-write overlay is not a path to return
+    match overlaid(&realpath) {
+        Detour::Bypass(Bypass::Overlay(..)) => {}
+        remote => {
+            remote?;
+            remote_xstat(Some(realpath.clone()), None, true)?;
+        }
+    }
 
     Detour::Success(realpath)
 }
 
+/// Checks that the file system entry at `path` should be modified remotely.
+fn mutated_path(path: Detour<PathBuf>) -> Detour<PathBuf> {
+    let path = path?;
+
+    check_relative_paths!(path);
+
+    let path = remote_path!(path, true);
+
+    Detour::Success(path)
+}
+
+/// Checks that the file system entry at `path` should be watched remotely.
+pub(crate) fn watched_path(path: Detour<PathBuf>) -> Detour<PathBuf> {
+    let path = path?;
+
+    check_relative_paths!(path);
+
+    let path = remote_path!(path, false);
+
+    Detour::Success(path)
+}
+
+/// Sends a request that modifies the remote file system, e.g. [`MakeDirRequest`].
+///
+/// `NotImplemented` error here means that the protocol doesn't support it, so the operation is
+/// done locally.
+fn remote_mutation<T>(request: T) -> Detour<c_int>
+where
+    T: IsLayerRequestWithResponse<Response = RemoteResult<()>> + Debug,
+{
+    match common::make_proxy_request_with_response(request)? {
+        Ok(()) => Detour::Success(0),
+        Err(ResponseError::NotImplemented) => Detour::Bypass(Bypass::NotImplemented),
+        Err(fail) => Detour::Error(fail.into()),
+    }
+}
+
+/// Returns the overlay if `path` is changed copy-on-write, see
+/// [`FileFilter::is_copy_on_write`](super::filter::FileFilter::is_copy_on_write).
+fn copy_on_write(path: &Path) -> Option<&'static FileOverlay> {
+    let setup = crate::setup();
+
+    setup.file_overlay().filter(|_| {
+        setup
+            .file_filter()
+            .is_copy_on_write(path.to_str().unwrap_or_default())
+    })
+}
+
+/// Redirects the operation on `path` to its local copy in the copy-on-write overlay, if there is
+/// one.
+fn overlaid(path: &Path) -> Detour<()> {
+    match copy_on_write(path).map(|overlay| overlay.entry(path)) {
+        Some(OverlayEntry::Copied(local)) => Detour::Bypass(Bypass::overlay(local)),
+        Some(OverlayEntry::Removed) => Detour::Error(HookError::FileNotFound),
+        Some(OverlayEntry::Remote) | None => Detour::Success(()),
+    }
+}
+
+/// Redirects the change of `path` to its local copy in the copy-on-write overlay, see
+/// [`local_copy`].
+fn copied(path: &Path, follow_symlink: bool) -> Detour<()> {
+    match local_copy(path, follow_symlink)? {
+        Some(local) => Detour::Bypass(Bypass::overlay(local)),
+        None => Detour::Success(()),
+    }
+}
+
+/// Returns the local copy of `path` in the copy-on-write overlay, copying the remote file first if
+/// needed.
+///
+/// [`None`] if `path` is not changed copy-on-write.
+fn local_copy(path: &Path, follow_symlink: bool) -> Detour<Option<PathBuf>> {
+    let Some(overlay) = copy_on_write(path) else {
+        return Detour::Success(None);
+    };
+
+    let local = match overlay.entry(path) {
+        OverlayEntry::Copied(local) => local,
+        OverlayEntry::Removed => return Detour::Error(HookError::FileNotFound),
+        OverlayEntry::Remote => copy_up(overlay, path, follow_symlink, true)?,
+    };
+
+    Detour::Success(Some(local))
+}
+
+/// Opens `path` in the copy-on-write mode.
+///
+/// Unchanged remote files are opened remotely, unless they're opened for writing. Then the remote
+/// file is copied into the overlay, and the open is redirected to the local copy, like the opens
+/// of files that were already copied.
+fn open_copy_on_write(
+    overlay: &FileOverlay,
+    path: &Path,
+    open_options: OpenOptionsInternal,
+) -> Detour<()> {
+    let create = open_options.create || open_options.create_new;
+
+    let local = match overlay.entry(path) {
+        // Directories are opened remotely, and their listings are merged with the local copies.
+        OverlayEntry::Copied(local) if local.is_dir() && remote_metadata(path, true)?.is_some() => {
+            return Detour::Success(())
+        }
+        OverlayEntry::Copied(local) => local,
+        OverlayEntry::Removed if create => create_in_overlay(overlay, path)?,
+        OverlayEntry::Removed => return Detour::Error(HookError::FileNotFound),
+        OverlayEntry::Remote if !open_options.is_write() => return Detour::Success(()),
+        OverlayEntry::Remote => match remote_metadata(path, true)? {
+            Some(..) if open_options.create_new => {
+                return Detour::Error(io::Error::from_raw_os_error(libc::EEXIST).into())
+            }
+            Some(..) => copy_up(overlay, path, true, !open_options.truncate)?,
+            None if create => create_in_overlay(overlay, path)?,
+            None => return Detour::Error(HookError::FileNotFound),
+        },
+    };
+
+    Detour::Bypass(Bypass::overlay(local))
+}
+
+/// Copies the remote `path` into the copy-on-write overlay, and returns the local copy.
+///
+/// With `follow_symlink`, a symbolic link is copied as the file it points to. Directories are
+/// copied without their entries, which are merged in the listings. The content of a file is
+/// copied only with `content`, so it's skipped when the copy is truncated anyway.
+fn copy_up(
+    overlay: &FileOverlay,
+    path: &Path,
+    follow_symlink: bool,
+    content: bool,
+) -> Detour<PathBuf> {
+    let metadata = remote_metadata(path, follow_symlink)?.ok_or(HookError::FileNotFound)?;
+    let local = overlay.prepare(path)?;
+
+    match metadata.mode & S_IFMT {
+        S_IFDIR => fs::create_dir_all(&local)?,
+        S_IFLNK => {
+            let ReadLinkFileResponse { path: target } =
+                common::make_proxy_request_with_response(ReadLinkFileRequest {
+                    path: path.to_path_buf(),
+                })??;
+            std::os::unix::fs::symlink(target, &local)?;
+
+            return Detour::Success(local);
+        }
+        _ => {
+            let mut file = fs::File::create(&local)?;
+            if content {
+                copy_remote_content(path, &mut file)?;
+            }
+        }
+    }
+
+    fs::set_permissions(&local, Permissions::from_mode(metadata.mode & !S_IFMT))?;
+
+    Detour::Success(local)
+}
+
+/// Reads the whole remote file `path` into `file`.
+fn copy_remote_content(path: &Path, file: &mut fs::File) -> Detour<()> {
+    let open_options = OpenOptionsInternal {
+        read: true,
+        ..Default::default()
+    };
+    let OpenFileResponse { fd } = RemoteFile::remote_open(path.to_path_buf(), open_options)?;
+
+    let copied = (|| -> Detour<()> {
+        loop {
+            let ReadFileResponse { bytes, .. } = RemoteFile::remote_read(fd, MAX_READ_SIZE)?;
+            if bytes.is_empty() {
+                return Detour::Success(());
+            }
+
+            file.write_all(&bytes)?;
+        }
+    })();
+
+    RemoteFile::remote_close(fd)?;
+    copied
+}
+
+/// Prepares the copy-on-write overlay for creating the new entry `path`, and returns its local
+/// path.
+///
+/// Fails with `ENOENT` when the parent directory does not exist.
+fn create_in_overlay(overlay: &FileOverlay, path: &Path) -> Detour<PathBuf> {
+    if let Some(parent) = path.parent() {
+        let parent_exists = match overlay.entry(parent) {
+            OverlayEntry::Copied(local) => local.is_dir(),
+            OverlayEntry::Removed => false,
+            OverlayEntry::Remote => remote_metadata(parent, true)?
+                .is_some_and(|metadata| metadata.mode & S_IFMT == S_IFDIR),
+        };
+
+        if !parent_exists {
+            return Detour::Error(HookError::FileNotFound);
+        }
+    }
+
+    Detour::Success(overlay.prepare(path)?)
+}
+
+/// Fetches the metadata of the remote `path`, [`None`] if it does not exist.
+fn remote_metadata(path: &Path, follow_symlink: bool) -> Detour<Option<MetadataInternal>> {
+    let request = XstatRequest {
+        path: Some(path.to_path_buf()),
+        fd: None,
+        follow_symlink,
+    };
+
+    match common::make_proxy_request_with_response(request)? {
+        Ok(XstatResponse { metadata }) => Detour::Success(Some(metadata)),
+        Err(ResponseError::RemoteIO(RemoteIOError {
+            kind: ErrorKindInternal::NotFound,
+            ..
+        })) => Detour::Success(None),
+        Err(fail) => Detour::Error(fail.into()),
+    }
+}
+
+/// Lists the names in the remote directory `path`, without `.` and `..`.
+fn remote_dir_names(path: &Path) -> Detour<Vec<String>> {
+    let open_options = OpenOptionsInternal {
+        read: true,
+        ..Default::default()
+    };
+    let OpenFileResponse { fd } = RemoteFile::remote_open(path.to_path_buf(), open_options)?;
+
+    let names = (|| -> Detour<Vec<String>> {
+        let OpenDirResponse { fd: dir_fd } =
+            common::make_proxy_request_with_response(FdOpenDirRequest { remote_fd: fd })??;
+
+        let mut names = Vec::new();
+        while let ReadDirResponse {
+            direntry: Some(entry),
+        } = common::make_proxy_request_with_response(ReadDirRequest { remote_fd: dir_fd })??
+        {
+            if entry.name != "." && entry.name != ".." {
+                names.push(entry.name);
+            }
+        }
+
+        common::make_proxy_request_no_response(CloseDirRequest { remote_fd: dir_fd })?;
+        Detour::Success(names)
+    })();
+
+    RemoteFile::remote_close(fd)?;
+    names
+}
+
+/// Creates the directory `path` in the agent.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn mkdir(path: Detour<PathBuf>, mode: u32) -> Detour<c_int> {
+    let path = mutated_path(path)?;
+
+    if let Some(overlay) = copy_on_write(&path) {
+        let exists = match overlay.entry(&path) {
+            OverlayEntry::Copied(..) => true,
+            OverlayEntry::Removed => false,
+            OverlayEntry::Remote => remote_metadata(&path, false)?.is_some(),
+        };
+        if exists {
+            return Detour::Error(io::Error::from_raw_os_error(libc::EEXIST).into());
+        }
+
+        let local = create_in_overlay(overlay, &path)?;
+        // The local copy of a removed remote directory is kept, see `FileOverlay::remove_dir`.
+        if local.is_dir() {
+            return Detour::Success(0);
+        }
+
+        return Detour::Bypass(Bypass::overlay(local));
+    }
+
+    remote_mutation(MakeDirRequest { path, mode })
+}
+
+/// Removes the empty directory `path` in the agent.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn rmdir(path: Detour<PathBuf>) -> Detour<c_int> {
+    let path = mutated_path(path)?;
+
+    if let Some(overlay) = copy_on_write(&path) {
+        let remote = remote_metadata(&path, false)?;
+        let is_dir = match overlay.entry(&path) {
+            OverlayEntry::Copied(local) => local.symlink_metadata()?.is_dir(),
+            OverlayEntry::Removed => return Detour::Error(HookError::FileNotFound),
+            OverlayEntry::Remote => {
+                remote.as_ref().ok_or(HookError::FileNotFound)?.mode & S_IFMT == S_IFDIR
+            }
+        };
+        if !is_dir {
+            return Detour::Error(io::Error::from_raw_os_error(libc::ENOTDIR).into());
+        }
+
+        let listing = overlay.listing(&path)?;
+        let remote = remote.is_some_and(|metadata| metadata.mode & S_IFMT == S_IFDIR);
+        let empty = listing.is_local_empty()
+            && (!remote
+                || !remote_dir_names(&path)?
+                    .iter()
+                    .any(|name| listing.shows(name)));
+        if !empty {
+            return Detour::Error(io::Error::from_raw_os_error(libc::ENOTEMPTY).into());
+        }
+
+        overlay.remove_dir(&path, remote)?;
+        return Detour::Success(0);
+    }
+
+    remote_mutation(RemoveDirRequest { path })
+}
+
+/// Removes the file `path` in the agent.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn unlink(path: Detour<PathBuf>) -> Detour<c_int> {
+    let path = mutated_path(path)?;
+
+    if let Some(overlay) = copy_on_write(&path) {
+        let remote = remote_metadata(&path, false)?;
+        let is_dir = match overlay.entry(&path) {
+            OverlayEntry::Copied(local) => local.symlink_metadata()?.is_dir(),
+            OverlayEntry::Removed => return Detour::Error(HookError::FileNotFound),
+            OverlayEntry::Remote => {
+                remote.as_ref().ok_or(HookError::FileNotFound)?.mode & S_IFMT == S_IFDIR
+            }
+        };
+        if is_dir {
+            return Detour::Error(io::Error::from_raw_os_error(libc::EISDIR).into());
+        }
+
+        overlay.remove_file(&path, remote.is_some())?;
+        return Detour::Success(0);
+    }
+
+    remote_mutation(UnlinkRequest { path })
+}
+
+/// Moves `old_path` to `new_path` in the agent.
+///
+/// Moving files between the local and the remote file system is reported as `EXDEV`, just like
+/// moving them between two mounts, so that the application can fall back to copying.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn rename(old_path: Detour<PathBuf>, new_path: Detour<PathBuf>) -> Detour<c_int> {
+    match (mutated_path(old_path), mutated_path(new_path)) {
+        (Detour::Success(old_path), Detour::Success(new_path)) => {
+            match (copy_on_write(&old_path), copy_on_write(&new_path)) {
+                (Some(overlay), Some(..)) => rename_copy_on_write(overlay, &old_path, &new_path),
+                (None, None) => remote_mutation(RenameRequest { old_path, new_path }),
+                _ => Detour::Error(io::Error::from_raw_os_error(libc::EXDEV).into()),
+            }
+        }
+        (Detour::Error(fail), _) | (_, Detour::Error(fail)) => Detour::Error(fail),
+        (Detour::Bypass(old_path), Detour::Bypass(new_path)) => Detour::Bypass(Bypass::Rename {
+            old_path: Box::new(old_path),
+            new_path: Box::new(new_path),
+        }),
+        _ => Detour::Error(io::Error::from_raw_os_error(libc::EXDEV).into()),
+    }
+}
+
+/// Moves `old_path` to `new_path` in the copy-on-write overlay, copying the remote file first.
+///
+/// Remote directories are not copied with all their entries, so moving them is reported as
+/// `EXDEV`, just like overlay file systems do.
+fn rename_copy_on_write(overlay: &FileOverlay, old_path: &Path, new_path: &Path) -> Detour<c_int> {
+    let remote = remote_metadata(old_path, false)?;
+
+    let old_local = match overlay.entry(old_path) {
+        OverlayEntry::Removed => return Detour::Error(HookError::FileNotFound),
+        OverlayEntry::Copied(local) if !local.symlink_metadata()?.is_dir() => local,
+        OverlayEntry::Copied(local) if remote.is_none() => local,
+        OverlayEntry::Copied(..) => {
+            return Detour::Error(io::Error::from_raw_os_error(libc::EXDEV).into())
+        }
+        OverlayEntry::Remote => match &remote {
+            None => return Detour::Error(HookError::FileNotFound),
+            Some(metadata) if metadata.mode & S_IFMT == S_IFDIR => {
+                return Detour::Error(io::Error::from_raw_os_error(libc::EXDEV).into())
+            }
+            Some(..) => copy_up(overlay, old_path, false, true)?,
+        },
+    };
+
+    let new_local = create_in_overlay(overlay, new_path)?;
+    fs::rename(old_local, new_local)?;
+    overlay.remove_file(old_path, remote.is_some())?;
+
+    Detour::Success(0)
+}
+
+/// Changes the permissions of `path` in the agent.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn chmod(path: Detour<PathBuf>, mode: u32) -> Detour<c_int> {
+    let path = mutated_path(path)?;
+
+    copied(&path, true)?;
+
+    remote_mutation(ChmodRequest { path, mode })
+}
+
+/// Changes the permissions of the remote file paired with `local_fd`.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn fchmod(local_fd: RawFd, mode: u32) -> Detour<c_int> {
+    let remote_file = OPEN_FILES
+        .lock()?
+        .get(&local_fd)
+        .cloned()
+        .ok_or(Bypass::LocalFdNotFound(local_fd))?;
+
+    // The remote file is only read in the copy-on-write mode, its local copy is changed instead.
+    if let Some(local) = local_copy(Path::new(&remote_file.path), true)? {
+        fs::set_permissions(local, Permissions::from_mode(mode))?;
+
+        return Detour::Success(0);
+    }
+
+    remote_mutation(FchmodRequest {
+        fd: remote_file.fd,
+        mode,
+    })
+}
+
+/// Changes the owner and group of `path` in the agent.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn chown(
+    path: Detour<PathBuf>,
+    user_id: Option<u32>,
+    group_id: Option<u32>,
+    follow_symlink: bool,
+) -> Detour<c_int> {
+    let path = mutated_path(path)?;
+
+    copied(&path, follow_symlink)?;
+
+    remote_mutation(ChownRequest {
+        path,
+        user_id,
+        group_id,
+        follow_symlink,
+    })
+}
+
+/// Truncates the file `path` in the agent.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn truncate(path: Detour<PathBuf>, length: u64) -> Detour<c_int> {
+    let path = mutated_path(path)?;
+
+    copied(&path, true)?;
+
+    remote_mutation(TruncateRequest { path, length })
+}
+
+/// Truncates the remote file paired with `local_fd`.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn ftruncate(local_fd: RawFd, length: u64) -> Detour<c_int> {
+    let fd = get_remote_fd(local_fd)?;
+
+    remote_mutation(FtruncateRequest { fd, length })
+}
+
+/// Creates the symbolic link `link_path` in the agent. The `target` is sent as is.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn symlink(target: Detour<PathBuf>, link_path: Detour<PathBuf>) -> Detour<c_int> {
+    let target = target?;
+    let link_path = mutated_path(link_path)?;
+
+    if let Some(overlay) = copy_on_write(&link_path) {
+        let exists = match overlay.entry(&link_path) {
+            OverlayEntry::Copied(..) => true,
+            OverlayEntry::Removed => false,
+            OverlayEntry::Remote => remote_metadata(&link_path, false)?.is_some(),
+        };
+        if exists {
+            return Detour::Error(io::Error::from_raw_os_error(libc::EEXIST).into());
+        }
+
+        return Detour::Bypass(Bypass::overlay(create_in_overlay(overlay, &link_path)?));
+    }
+
+    remote_mutation(SymlinkRequest { target, link_path })
+}
+
+/// Changes the access and modification times of `path` in the agent.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn utimes(
+    path: Detour<PathBuf>,
+    access_time: FileTimeUpdate,
+    modification_time: FileTimeUpdate,
+    follow_symlink: bool,
+) -> Detour<c_int> {
+    let path = mutated_path(path)?;
+
+    copied(&path, follow_symlink)?;
+
+    remote_mutation(UtimesRequest {
+        path,
+        access_time,
+        modification_time,
+        follow_symlink,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
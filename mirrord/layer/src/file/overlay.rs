@@ -0,0 +1,321 @@
+//! Local overlay of the remote file system, used in the copy-on-write file mode, see
+//! [`FileOverlay`].
+
+#[cfg(target_os = "linux")]
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    ffi::OsString,
+    fs, io,
+    os::unix::fs::DirEntryExt,
+    path::{Path, PathBuf},
+};
+
+use mirrord_protocol::file::DirEntryInternal;
+
+#[cfg(target_os = "linux")]
+use super::LocalFd;
+
+/// Prefix of the whiteout files, which mark the remote files removed in the overlay.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// Listings of the local directories merged into the `getdents64` results, by the local descriptors
+/// of the remote directories.
+#[cfg(target_os = "linux")]
+pub(crate) static DIR_LISTINGS: LazyLock<Mutex<HashMap<LocalFd, OverlayListing>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Local directory with the copies of the remote files changed by the application, kept under
+/// their remote paths, e.g. `/app/config.yaml` is copied to `<root>/app/config.yaml`.
+///
+/// Removed remote files are marked with empty whiteout files, next to where their copies would be,
+/// e.g. removing `/app/config.yaml` creates `<root>/app/.wh.config.yaml`.
+///
+/// The overlay lives on disk, so it's shared by all processes of the session.
+#[derive(Debug)]
+pub(crate) struct FileOverlay {
+    root: PathBuf,
+}
+
+/// Where the current version of a remote path is, see [`FileOverlay::entry`].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum OverlayEntry {
+    /// The path was not changed, it's only in the remote file system.
+    Remote,
+    /// The path was copied or created in the overlay, at the given local path.
+    Copied(PathBuf),
+    /// The path, or one of its parents, was removed.
+    Removed,
+}
+
+impl FileOverlay {
+    pub(crate) fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Returns the local path of the copy of the remote `path`, whether it exists or not.
+    pub(crate) fn local_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    fn whiteout_path(&self, path: &Path) -> Option<PathBuf> {
+        let mut whiteout = OsString::from(WHITEOUT_PREFIX);
+        whiteout.push(path.file_name()?);
+
+        Some(self.local_path(path.parent()?).join(whiteout))
+    }
+
+    /// Checks where the current version of the remote `path` is.
+    pub(crate) fn entry(&self, path: &Path) -> OverlayEntry {
+        let removed = path
+            .ancestors()
+            .filter_map(|ancestor| self.whiteout_path(ancestor))
+            .any(|whiteout| whiteout.symlink_metadata().is_ok());
+        if removed {
+            return OverlayEntry::Removed;
+        }
+
+        let local = self.local_path(path);
+        if local.symlink_metadata().is_ok() {
+            OverlayEntry::Copied(local)
+        } else {
+            OverlayEntry::Remote
+        }
+    }
+
+    /// Prepares the overlay for creating the copy of the remote `path`: creates the local parent
+    /// directories and removes the whiteout.
+    ///
+    /// Returns the local path of the copy.
+    pub(crate) fn prepare(&self, path: &Path) -> io::Result<PathBuf> {
+        let local = self.local_path(path);
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if let Some(whiteout) = self.whiteout_path(path) {
+            ignore_not_found(fs::remove_file(whiteout))?;
+        }
+
+        Ok(local)
+    }
+
+    /// Removes the copy of the file `path`, and hides the remote file, if there is one.
+    pub(crate) fn remove_file(&self, path: &Path, remote: bool) -> io::Result<()> {
+        ignore_not_found(fs::remove_file(self.local_path(path)))?;
+
+        if remote {
+            self.hide(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the copy of the empty directory `path`, and hides the remote directory, if there is
+    /// one.
+    ///
+    /// The local directory of a remote one is kept, as it holds the whiteouts of the remote
+    /// entries, which must stay hidden if the directory is created again.
+    pub(crate) fn remove_dir(&self, path: &Path, remote: bool) -> io::Result<()> {
+        if remote {
+            self.hide(path)
+        } else {
+            ignore_not_found(fs::remove_dir_all(self.local_path(path)))
+        }
+    }
+
+    fn hide(&self, path: &Path) -> io::Result<()> {
+        let Some(whiteout) = self.whiteout_path(path) else {
+            return Ok(());
+        };
+
+        if let Some(parent) = whiteout.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::File::create(whiteout).map(drop)
+    }
+
+    /// Lists the local directory of the remote directory `path`, to be merged with the remote
+    /// listing.
+    pub(crate) fn listing(&self, path: &Path) -> io::Result<OverlayListing> {
+        let mut listing = OverlayListing::default();
+
+        let entries = match fs::read_dir(self.local_path(path)) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(listing),
+            Err(error) => return Err(error),
+        };
+
+        for (position, entry) in entries.enumerate() {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if let Some(removed) = name.strip_prefix(WHITEOUT_PREFIX) {
+                listing.hidden.insert(removed.to_string());
+                continue;
+            }
+
+            let file_type = entry.file_type()?;
+            let file_type = if file_type.is_dir() {
+                libc::DT_DIR
+            } else if file_type.is_symlink() {
+                libc::DT_LNK
+            } else if file_type.is_file() {
+                libc::DT_REG
+            } else {
+                libc::DT_UNKNOWN
+            };
+
+            listing.hidden.insert(name.clone());
+            listing.local.push_back(DirEntryInternal {
+                inode: entry.ino(),
+                position: position as u64,
+                name,
+                file_type,
+            });
+        }
+
+        Ok(listing)
+    }
+}
+
+/// Local part of a merged directory listing, see [`FileOverlay::listing`].
+///
+/// The remote entries come first, without the ones that were removed or copied, followed by the
+/// local entries.
+#[derive(Debug, Default)]
+pub(crate) struct OverlayListing {
+    /// Names of the remote entries that should not be listed.
+    hidden: HashSet<String>,
+    /// Entries of the local directory, not listed yet.
+    local: VecDeque<DirEntryInternal>,
+}
+
+impl OverlayListing {
+    /// Checks whether the remote entry `name` should be listed.
+    pub(crate) fn shows(&self, name: &str) -> bool {
+        !self.hidden.contains(name)
+    }
+
+    /// Takes the next local entry, to be listed after all the remote ones.
+    pub(crate) fn next_local(&mut self) -> Option<DirEntryInternal> {
+        self.local.pop_front()
+    }
+
+    /// Checks whether the local directory has no entries, besides the whiteouts.
+    pub(crate) fn is_local_empty(&self) -> bool {
+        self.local.is_empty()
+    }
+
+    /// Merges a batch of remote entries returned by `getdents64`.
+    ///
+    /// Once the remote entries run out, returns the local ones, as many as fit in `buffer_size`.
+    /// Fails with `EINVAL` when not even the next local entry fits, like `getdents64` does.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn merge_batch(
+        &mut self,
+        remote: Vec<DirEntryInternal>,
+        buffer_size: u64,
+    ) -> io::Result<Vec<DirEntryInternal>> {
+        if !remote.is_empty() {
+            return Ok(remote
+                .into_iter()
+                .filter(|entry| self.shows(&entry.name))
+                .collect());
+        }
+
+        let mut entries = Vec::new();
+        let mut size = 0;
+        while let Some(entry) = self.local.front() {
+            size += u64::from(entry.get_d_reclen64());
+            if size > buffer_size {
+                break;
+            }
+
+            entries.extend(self.local.pop_front());
+        }
+
+        if entries.is_empty() && !self.local.is_empty() {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+
+        Ok(entries)
+    }
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_entries() {
+        let root = tempfile::tempdir().unwrap();
+        let overlay = FileOverlay::new(root.path().to_path_buf());
+        let path = Path::new("/app/config/settings.yaml");
+
+        assert_eq!(overlay.entry(path), OverlayEntry::Remote);
+
+        let local = overlay.prepare(path).unwrap();
+        fs::write(&local, "copy").unwrap();
+        assert_eq!(overlay.entry(path), OverlayEntry::Copied(local.clone()));
+
+        overlay.remove_file(path, true).unwrap();
+        assert!(!local.exists());
+        assert_eq!(overlay.entry(path), OverlayEntry::Removed);
+
+        overlay.prepare(path).unwrap();
+        assert_eq!(overlay.entry(path), OverlayEntry::Remote);
+
+        overlay.remove_dir(Path::new("/app/config"), true).unwrap();
+        assert_eq!(overlay.entry(path), OverlayEntry::Removed);
+    }
+
+    #[test]
+    fn listing_hides_removed_and_copied() {
+        let root = tempfile::tempdir().unwrap();
+        let overlay = FileOverlay::new(root.path().to_path_buf());
+
+        fs::write(overlay.prepare(Path::new("/app/copied")).unwrap(), "copy").unwrap();
+        overlay
+            .remove_file(Path::new("/app/removed"), true)
+            .unwrap();
+
+        let mut listing = overlay.listing(Path::new("/app")).unwrap();
+        assert!(listing.shows("unchanged"));
+        assert!(!listing.shows("copied"));
+        assert!(!listing.shows("removed"));
+        assert!(!listing.is_local_empty());
+
+        let local = listing.next_local().unwrap();
+        assert_eq!(local.name, "copied");
+        assert_eq!(local.file_type, libc::DT_REG);
+        assert!(listing.next_local().is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn merge_batch_too_small() {
+        let root = tempfile::tempdir().unwrap();
+        let overlay = FileOverlay::new(root.path().to_path_buf());
+        fs::write(overlay.prepare(Path::new("/app/copied")).unwrap(), "copy").unwrap();
+
+        let mut listing = overlay.listing(Path::new("/app")).unwrap();
+        let error = listing.merge_batch(Vec::new(), 8).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::EINVAL));
+
+        let entries = listing.merge_batch(Vec::new(), 1024).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(listing.merge_batch(Vec::new(), 1024).unwrap().is_empty());
+    }
+}
@@ -101,8 +101,30 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                         .into()
                 }
                 libc::SYS_fstat => fstat_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_statfs => statfs64_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_fstatfs => fstatfs_detour(param1 as _, param2 as _) as i64,
                 libc::SYS_fsync => fsync_detour(param1 as _) as i64,
                 libc::SYS_fdatasync => fsync_detour(param1 as _) as i64,
+                // Used by `io.Copy` from an `os.File` to a socket or another file.
+                libc::SYS_sendfile => {
+                    sendfile_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                libc::SYS_copy_file_range => copy_file_range_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                    param6 as _,
+                ) as i64,
+                libc::SYS_splice => splice_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                    param6 as _,
+                ) as i64,
                 libc::SYS_openat => {
                     openat_detour(param1 as _, param2 as _, param3 as _, param4 as libc::c_int)
                         as i64
@@ -110,6 +132,61 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                 libc::SYS_getdents64 => {
                     getdents64_detour(param1 as _, param2 as _, param3 as _) as i64
                 }
+                libc::SYS_mkdirat => mkdirat_detour(param1 as _, param2 as _, param3 as _) as i64,
+                libc::SYS_unlinkat => unlinkat_detour(param1 as _, param2 as _, param3 as _) as i64,
+                #[cfg(target_arch = "x86_64")]
+                libc::SYS_renameat => {
+                    renameat_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                // Go uses `renameat2` without flags on architectures that lack `renameat`.
+                libc::SYS_renameat2 if param5 == 0 => {
+                    renameat_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                // The kernel `fchmodat` takes no flags, see the note on `SYS_faccessat`.
+                libc::SYS_fchmodat => {
+                    fchmodat_detour(param1 as _, param2 as _, param3 as _, 0) as i64
+                }
+                libc::SYS_fchmod => fchmod_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_fchownat => fchownat_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                ) as i64,
+                libc::SYS_truncate => truncate_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_ftruncate => ftruncate_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_symlinkat => {
+                    symlinkat_detour(param1 as _, param2 as _, param3 as _) as i64
+                }
+                libc::SYS_mmap => mmap_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                    param6 as _,
+                ) as i64,
+                libc::SYS_mremap => mremap_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as *mut libc::c_void,
+                ) as i64,
+                libc::SYS_msync => msync_detour(param1 as _, param2 as _, param3 as _) as i64,
+                libc::SYS_inotify_init1 => inotify_init1_detour(param1 as _) as i64,
+                libc::SYS_inotify_add_watch => {
+                    inotify_add_watch_detour(param1 as _, param2 as _, param3 as _) as i64
+                }
+                libc::SYS_inotify_rm_watch => {
+                    inotify_rm_watch_detour(param1 as _, param2 as _) as i64
+                }
+                libc::SYS_munmap => munmap_detour(param1 as _, param2 as _) as i64,
+                // A null path means `futimens`, which works on a descriptor.
+                libc::SYS_utimensat if param2 != 0 => {
+                    utimensat_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
                 _ => {
                     let (Ok(result) | Err(result)) = syscalls::syscall!(
                         syscalls::Sysno::from(syscall as i32),
@@ -468,6 +468,8 @@ fn sip_only_layer_start(mut config: LayerConfig, patch_binaries: Vec<String>) {
         local: None,
         not_found: None,
         mapping: None,
+        cache: Default::default(),
+        overlay: None,
     };
     let debugger_ports = DebuggerPorts::from_env();
     let setup = LayerSetup::new(config, debugger_ports, true);
@@ -593,6 +595,21 @@ pub(crate) fn close_layer_fd(fd: c_int) {
             .lock()
             .expect("OPEN_FILES lock failed")
             .remove(&fd);
+
+        #[cfg(target_os = "linux")]
+        file::overlay::DIR_LISTINGS
+            .lock()
+            .expect("DIR_LISTINGS lock failed")
+            .remove(&fd);
+
+        #[cfg(target_os = "linux")]
+        if let Some(instance) = file::inotify::INOTIFY_INSTANCES
+            .lock()
+            .expect("INOTIFY_INSTANCES lock failed")
+            .remove(&fd)
+        {
+            instance.close();
+        }
     }
 }
 
@@ -124,6 +124,35 @@ impl ProxyConnection {
         self.send(request.wrap())
     }
 
+    /// Opens a separate session, in which the internal proxy sends the events of the remote file
+    /// watches, see [`NewSessionRequest::FileWatchEvents`].
+    ///
+    /// Returns the id of the new session and its connection, without a read timeout, as the
+    /// events can come at any time.
+    pub fn new_file_watch_session(&self) -> Result<(LayerId, TcpStream)> {
+        let connection = TcpStream::connect(self.proxy_addr)?;
+
+        let (mut sender, mut receiver) = codec::make_sync_framed::<
+            LocalMessage<LayerToProxyMessage>,
+            LocalMessage<ProxyToLayerMessage>,
+        >(connection)?;
+
+        sender.send(&LocalMessage {
+            message_id: 0,
+            inner: LayerToProxyMessage::NewSession(NewSessionRequest::FileWatchEvents(
+                self.layer_id,
+            )),
+        })?;
+
+        match receiver.receive()?.map(|message| message.inner) {
+            Some(ProxyToLayerMessage::NewSession(session_id)) => {
+                Ok((session_id, receiver.into_inner()))
+            }
+            Some(response) => Err(ProxyError::UnexpectedResponse(response)),
+            None => Err(ProxyError::ConnectionClosed),
+        }
+    }
+
     pub fn layer_id(&self) -> LayerId {
         self.layer_id
     }
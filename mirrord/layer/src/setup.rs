@@ -1,4 +1,4 @@
-use std::{collections::HashSet, net::SocketAddr};
+use std::{collections::HashSet, env, net::SocketAddr};
 
 use mirrord_config::{
     experimental::ExperimentalConfig,
@@ -8,7 +8,7 @@ use mirrord_config::{
         network::{
             incoming::{
                 http_filter::{HttpFilterConfig, InnerFilter},
-                IncomingConfig,
+                IncomingConfig, OnLocalFailure,
             },
             outgoing::OutgoingConfig,
         },
@@ -25,7 +25,7 @@ use regex::RegexSet;
 
 use crate::{
     debugger_ports::DebuggerPorts,
-    file::{filter::FileFilter, mapper::FileRemapper},
+    file::{filter::FileFilter, mapper::FileRemapper, overlay::FileOverlay},
     socket::{dns_selector::DnsSelector, OutgoingSelector},
 };
 
@@ -37,6 +37,8 @@ pub struct LayerSetup {
     config: LayerConfig,
     file_filter: FileFilter,
     file_remapper: FileRemapper,
+    /// Present only in the copy-on-write file mode.
+    file_overlay: Option<FileOverlay>,
     debugger_ports: DebuggerPorts,
     remote_unix_streams: RegexSet,
     outgoing_selector: OutgoingSelector,
@@ -52,8 +54,7 @@ pub struct LayerSetup {
 impl LayerSetup {
     pub fn new(config: LayerConfig, debugger_ports: DebuggerPorts, local_hostname: bool) -> Self {
         let file_filter = FileFilter::new(config.feature.fs.clone());
-        let file_remapper =
-            FileRemapper::new(config.feature.fs.mapping.clone().unwrap_or_default());
+        let file_remapper = FileRemapper::new(config.feature.fs.mappings());
 
         let remote_unix_streams = config
             .feature
@@ -77,6 +78,14 @@ impl LayerSetup {
             .parse()
             .expect("failed to parse internal proxy address");
 
+        let file_overlay = config.feature.fs.is_cow().then(|| {
+            let root = config.feature.fs.overlay.clone().unwrap_or_else(|| {
+                env::temp_dir().join(format!("mirrord-overlay-{}", proxy_address.port()))
+            });
+
+            FileOverlay::new(root)
+        });
+
         let incoming_mode = IncomingMode::new(&config.feature.network.incoming);
         #[cfg(target_os = "macos")]
         let env_backup = std::env::vars()
@@ -87,6 +96,7 @@ impl LayerSetup {
             config,
             file_filter,
             file_remapper,
+            file_overlay,
             debugger_ports,
             remote_unix_streams,
             outgoing_selector,
@@ -119,6 +129,10 @@ impl LayerSetup {
         &self.file_remapper
     }
 
+    pub(crate) fn file_overlay(&self) -> Option<&FileOverlay> {
+        self.file_overlay.as_ref()
+    }
+
     pub fn incoming_config(&self) -> &IncomingConfig {
         &self.config.feature.network.incoming
     }
@@ -203,6 +217,9 @@ pub struct StealHttpSettings {
     pub filter: StealHttpFilter,
     /// Ports to filter HTTP on.
     pub ports: HashSet<Port>,
+    /// Whether the filtered requests that the local application fails to handle should be sent
+    /// to their original destination.
+    pub passthrough: bool,
 }
 
 /// Operation mode for the `incoming` feature.
@@ -255,7 +272,7 @@ impl IncomingMode {
                 all_of: Some(filters),
                 any_of: None,
                 ports: _ports,
-            } => StealHttpFilter::Filter(Self::make_composite_filter(true, filters)),
+            } => StealHttpFilter::Filter(InnerFilter::composite(true, filters)),
 
             HttpFilterConfig {
                 path_filter: None,
@@ -263,7 +280,7 @@ impl IncomingMode {
                 all_of: None,
                 any_of: Some(filters),
                 ports: _ports,
-            } => StealHttpFilter::Filter(Self::make_composite_filter(false, filters)),
+            } => StealHttpFilter::Filter(InnerFilter::composite(false, filters)),
 
             HttpFilterConfig {
                 path_filter: None,
@@ -276,23 +293,13 @@ impl IncomingMode {
             _ => panic!("multiple HTTP filters specified, this is a bug"),
         };
 
-        Self::Steal(StealHttpSettings { filter, ports })
-    }
-
-    fn make_composite_filter(all: bool, filters: &[InnerFilter]) -> HttpFilter {
-        let filters = filters
-            .iter()
-            .map(|filter| match filter {
-                InnerFilter::Path { path } => {
-                    HttpFilter::Path(Filter::new(path.clone()).expect("invalid filter expression"))
-                }
-                InnerFilter::Header { header } => HttpFilter::Header(
-                    Filter::new(header.clone()).expect("invalid filter expression"),
-                ),
-            })
-            .collect();
+        let passthrough = matches!(config.on_local_failure, OnLocalFailure::Passthrough);
 
-        HttpFilter::Composite { all, filters }
+        Self::Steal(StealHttpSettings {
+            filter,
+            ports,
+            passthrough,
+        })
     }
 
     /// Returns [`PortSubscription`] request to be used for the given port.
@@ -304,6 +311,9 @@ impl IncomingMode {
         let steal_type = match &steal.filter {
             _ if !steal.ports.contains(&port) => StealType::All(port),
             StealHttpFilter::None => StealType::All(port),
+            StealHttpFilter::Filter(filter) if steal.passthrough => {
+                StealType::FilteredHttpPassthrough(port, filter.clone())
+            }
             StealHttpFilter::Filter(filter) => StealType::FilteredHttpEx(port, filter.clone()),
         };
 
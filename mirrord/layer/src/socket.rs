@@ -17,9 +17,10 @@ use mirrord_config::feature::network::{
     filter::{AddressFilter, ProtocolAndAddressFilter, ProtocolFilter},
     outgoing::{OutgoingConfig, OutgoingFilterConfig},
 };
-use mirrord_intproxy_protocol::{NetProtocol, PortUnsubscribe};
+use mirrord_intproxy_protocol::{NetProtocol, PortUnsubscribe, UdpPortUnsubscribe};
 use mirrord_protocol::{
-    outgoing::SocketAddress, DnsLookupError, ResolveErrorKindInternal, ResponseError,
+    dns::AddressFamily, outgoing::SocketAddress, DnsLookupError, ResolveErrorKindInternal,
+    ResponseError,
 };
 use socket2::SockAddr;
 use tracing::warn;
@@ -221,19 +222,36 @@ impl UserSocket {
         }
     }
 
-    /// Inform internal proxy about closing a listening port.
+    /// Inform internal proxy about closing a listening port, or a UDP socket subscribed to the
+    /// incoming traffic.
     #[mirrord_layer_macro::instrument(level = "trace", fields(pid = std::process::id()), ret)]
     pub(crate) fn close(&self) {
-        if let Self {
-            state: SocketState::Listening(bound),
-            kind: SocketKind::Tcp(..),
-            ..
-        } = self
-        {
-            let _ = common::make_proxy_request_no_response(PortUnsubscribe {
-                port: bound.requested_address.port(),
-                listening_on: bound.address,
-            });
+        match self {
+            Self {
+                state: SocketState::Listening(bound),
+                kind: SocketKind::Tcp(..),
+                ..
+            } => {
+                let _ = common::make_proxy_request_no_response(PortUnsubscribe {
+                    port: bound.requested_address.port(),
+                    listening_on: bound.address,
+                });
+            }
+
+            Self {
+                state: SocketState::Bound(bound),
+                kind: SocketKind::Udp(..),
+                ..
+            } => {
+                if let Some(port) = ops::incoming_udp_port(&bound.requested_address) {
+                    let _ = common::make_proxy_request_no_response(UdpPortUnsubscribe {
+                        port,
+                        bound_on: bound.address,
+                    });
+                }
+            }
+
+            _ => {}
         }
     }
 }
@@ -410,9 +428,13 @@ trait ProtocolAndAddressFilterExt {
     /// # Note on DNS resolution
     ///
     /// This method may require a DNS resolution (when [`ProtocolAndAddressFilter::address`] is
-    /// [`AddressFilter::Name`]). If remote DNS is disabled or `force_local_dns`
+    /// [`AddressFilter::Name`]), unless the address was resolved remotely from that name, see
+    /// [`REMOTE_DNS_REVERSE_MAPPING`]. If remote DNS is disabled or `force_local_dns`
     /// flag is used, the method uses local resolution [`ToSocketAddrs`]. Otherwise, it uses
     /// remote resolution [`remote_getaddrinfo`].
+    ///
+    /// [`AddressFilter::Hostname`] patterns are never resolved, they only match addresses in
+    /// [`REMOTE_DNS_REVERSE_MAPPING`].
     fn matches(
         &self,
         address: SocketAddr,
@@ -439,10 +461,28 @@ impl ProtocolAndAddressFilterExt for ProtocolAndAddressFilter {
             return Ok(false);
         }
 
+        let remote_hostname = REMOTE_DNS_REVERSE_MAPPING
+            .lock()?
+            .get(&address.ip())
+            .cloned();
+
         match &self.address {
+            AddressFilter::Name(name, _)
+                if remote_hostname
+                    .as_deref()
+                    .is_some_and(|hostname| hostname.eq_ignore_ascii_case(name)) =>
+            {
+                Ok(true)
+            }
             AddressFilter::Name(name, port) => {
                 let resolved_ips = if crate::setup().remote_dns_enabled() && !force_local_dns {
-                    match remote_getaddrinfo(name.to_string()) {
+                    let family = if address.is_ipv6() {
+                        AddressFamily::Ipv6Only
+                    } else {
+                        AddressFamily::Ipv4Only
+                    };
+
+                    match remote_getaddrinfo(name.to_string(), family) {
                         Ok(res) => res.into_iter().map(|(_, ip)| ip).collect(),
                         Err(HookError::ResponseError(ResponseError::DnsLookup(
                             DnsLookupError {
@@ -484,6 +524,9 @@ impl ProtocolAndAddressFilterExt for ProtocolAndAddressFilter {
             }
             AddressFilter::Subnet(net, _) => Ok(net.contains(&address.ip())),
             AddressFilter::Port(..) => Ok(true),
+            AddressFilter::Hostname(pattern, _) => Ok(remote_hostname
+                .as_deref()
+                .is_some_and(|hostname| pattern.is_match(hostname))),
         }
     }
 }
@@ -493,6 +536,30 @@ fn is_ignored_port(addr: &SocketAddr) -> bool {
     addr.port() == 0
 }
 
+/// Converts an IPv4-mapped IPv6 address (`::ffff:a.b.c.d`) to a plain IPv4 address.
+///
+/// [`libc::AF_INET6`] sockets use such addresses to talk to IPv4 peers, while the agent, the
+/// outgoing filters and our [`SOCKETS`] deal with plain IPv4 addresses.
+fn to_canonical(address: SockAddr) -> SockAddr {
+    match address.as_socket() {
+        Some(SocketAddr::V6(v6)) if v6.ip().to_ipv4_mapped().is_some() => {
+            SockAddr::from(SocketAddr::new(v6.ip().to_canonical(), v6.port()))
+        }
+        _ => address,
+    }
+}
+
+/// Inverse of [`to_canonical`], converts an IPv4 address to an IPv4-mapped IPv6 address when it's
+/// going to be used with a socket of the [`libc::AF_INET6`] `domain`.
+fn to_domain(address: SockAddr, domain: c_int) -> SockAddr {
+    match address.as_socket() {
+        Some(SocketAddr::V4(v4)) if domain == libc::AF_INET6 => {
+            SockAddr::from(SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port()))
+        }
+        _ => address,
+    }
+}
+
 /// Fill in the sockaddr structure for the given address.
 #[inline]
 fn fill_address(
@@ -552,3 +619,22 @@ impl SocketAddrExt for SocketAddr {
             .and_then(|address| address.as_socket().bypass(Bypass::AddressConversion))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ipv4_mapped_addresses() {
+        let v4 = SockAddr::from("10.0.0.1:80".parse::<SocketAddr>().unwrap());
+        let mapped = SockAddr::from("[::ffff:10.0.0.1]:80".parse::<SocketAddr>().unwrap());
+        let v6 = SockAddr::from("[fd00::1]:80".parse::<SocketAddr>().unwrap());
+
+        assert_eq!(to_canonical(mapped.clone()), v4);
+        assert_eq!(to_canonical(v6.clone()), v6);
+
+        assert_eq!(to_domain(v4.clone(), libc::AF_INET6), mapped);
+        assert_eq!(to_domain(v4.clone(), libc::AF_INET), v4);
+        assert_eq!(to_domain(v6.clone(), libc::AF_INET6), v6);
+    }
+}
@@ -4,6 +4,7 @@ use mirrord_config::feature::network::{
     dns::{DnsConfig, DnsFilterConfig},
     filter::AddressFilter,
 };
+use regex::{Regex, RegexBuilder};
 use tracing::Level;
 
 use crate::detour::{Bypass, Detour};
@@ -16,6 +17,40 @@ pub struct DnsSelector {
     filters: Vec<AddressFilter>,
     /// Whether a query matching one of [`Self::filters`] should be done locally.
     filter_is_local: bool,
+    /// Static answers from [`DnsConfig::hosts`], exact names first, then the glob patterns from
+    /// the longest. Patterns of the same length are in lexicographic order.
+    hosts: Vec<(HostPattern, Vec<IpAddr>)>,
+}
+
+/// A key of [`DnsConfig::hosts`].
+#[derive(Debug)]
+enum HostPattern {
+    Name(String),
+    Glob(Regex),
+}
+
+impl HostPattern {
+    fn new(host: &str) -> Self {
+        let host = host.trim_end_matches('.');
+        if !host.contains(['*', '?']) {
+            return Self::Name(host.to_ascii_lowercase());
+        }
+
+        let pattern = regex::escape(host).replace(r"\*", ".*").replace(r"\?", ".");
+        let glob = RegexBuilder::new(&format!("^{pattern}$"))
+            .case_insensitive(true)
+            .build()
+            .expect("escaped glob should be a valid regex");
+
+        Self::Glob(glob)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Name(host) => host.eq_ignore_ascii_case(name),
+            Self::Glob(glob) => glob.is_match(name),
+        }
+    }
 }
 
 impl DnsSelector {
@@ -32,6 +67,7 @@ impl DnsSelector {
             .any(|filter| match filter {
                 AddressFilter::Port(..) => true,
                 AddressFilter::Name(filter_name, _) => filter_name == node,
+                AddressFilter::Hostname(filter_pattern, _) => filter_pattern.is_match(node),
                 AddressFilter::Socket(filter_socket) => {
                     filter_socket.ip().is_unspecified()
                         || Some(filter_socket.ip()) == node.parse().ok()
@@ -51,6 +87,16 @@ impl DnsSelector {
             Detour::Success(())
         }
     }
+
+    /// Returns the addresses that [`DnsConfig::hosts`] has for `name`.
+    pub fn static_host(&self, name: &str) -> Option<&[IpAddr]> {
+        let name = name.trim_end_matches('.');
+
+        self.hosts
+            .iter()
+            .find(|(pattern, _)| pattern.matches(name))
+            .map(|(_, addresses)| addresses.as_slice())
+    }
 }
 
 impl From<&DnsConfig> for DnsSelector {
@@ -59,6 +105,7 @@ impl From<&DnsConfig> for DnsSelector {
             return Self {
                 filters: Default::default(),
                 filter_is_local: false,
+                hosts: Default::default(),
             };
         }
 
@@ -78,9 +125,127 @@ impl From<&DnsConfig> for DnsSelector {
             })
             .collect();
 
+        let mut hosts = value
+            .hosts
+            .iter()
+            .flatten()
+            .map(|(host, addresses)| {
+                let addresses = addresses
+                    .iter()
+                    .map(|address| {
+                        address
+                            .parse::<IpAddr>()
+                            .expect("bad DNS host address, should be verified in the CLI")
+                    })
+                    .collect::<Vec<_>>();
+
+                (host.as_str(), addresses)
+            })
+            .collect::<Vec<_>>();
+        // Exact names first, then the more specific patterns. The config is a `HashMap`, so ties
+        // are broken by the pattern itself, to pick the same answer on every run.
+        hosts.sort_by_key(|(host, _)| {
+            (
+                host.contains(['*', '?']),
+                std::cmp::Reverse(host.len()),
+                *host,
+            )
+        });
+
+        let hosts = hosts
+            .into_iter()
+            .map(|(host, addresses)| (HostPattern::new(host), addresses))
+            .collect();
+
         Self {
             filters,
             filter_is_local,
+            hosts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use mirrord_config::util::VecOrSingle;
+
+    use super::*;
+
+    #[test]
+    fn static_hosts() {
+        let config = DnsConfig {
+            enabled: true,
+            hosts: Some(HashMap::from([
+                (
+                    "payments.prod.svc".to_string(),
+                    VecOrSingle::Single("127.0.0.1".to_string()),
+                ),
+                (
+                    "*.svc".to_string(),
+                    VecOrSingle::Single("10.0.0.1".to_string()),
+                ),
+                (
+                    "*.prod.svc".to_string(),
+                    VecOrSingle::Multiple(vec!["10.0.0.2".to_string(), "fd00::2".to_string()]),
+                ),
+            ])),
+            ..Default::default()
+        };
+        let selector = DnsSelector::from(&config);
+
+        let static_host = |name| {
+            selector.static_host(name).map(|addresses| {
+                addresses
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            static_host("payments.prod.svc"),
+            Some(vec!["127.0.0.1".into()])
+        );
+        assert_eq!(
+            static_host("Payments.Prod.svc."),
+            Some(vec!["127.0.0.1".into()])
+        );
+        assert_eq!(
+            static_host("orders.prod.svc"),
+            Some(vec!["10.0.0.2".into(), "fd00::2".into()])
+        );
+        assert_eq!(static_host("orders.dev.svc"), Some(vec!["10.0.0.1".into()]));
+        assert_eq!(static_host("orders.dev.svc.local"), None);
+        assert_eq!(static_host("svc"), None);
+    }
+
+    /// Patterns of the same length that match the same name should always give the same answer,
+    /// whatever the order of [`DnsConfig::hosts`].
+    #[test]
+    fn static_hosts_same_length() {
+        for _ in 0..16 {
+            let config = DnsConfig {
+                enabled: true,
+                hosts: Some(HashMap::from([
+                    (
+                        "orders.*vc".to_string(),
+                        VecOrSingle::Single("10.0.0.1".to_string()),
+                    ),
+                    (
+                        "*.prod.svc".to_string(),
+                        VecOrSingle::Single("10.0.0.2".to_string()),
+                    ),
+                ])),
+                ..Default::default()
+            };
+            let selector = DnsSelector::from(&config);
+
+            assert_eq!(
+                selector.static_host("orders.prod.svc"),
+                Some(["10.0.0.2".parse().unwrap()].as_slice())
+            );
         }
     }
 }
@@ -3,6 +3,7 @@ use core::{cmp, ffi::CStr};
 use std::{
     collections::HashSet,
     os::unix::io::RawFd,
+    slice,
     sync::{LazyLock, Mutex},
 };
 
@@ -10,10 +11,13 @@ use errno::{set_errno, Errno};
 use libc::{c_char, c_int, c_void, hostent, size_t, sockaddr, socklen_t, ssize_t, EINVAL};
 use mirrord_config::experimental::ExperimentalConfig;
 use mirrord_layer_macro::{hook_fn, hook_guard_fn};
+use mirrord_protocol::dns::AddressFamily;
 
 #[cfg(target_os = "macos")]
 use super::apple_dnsinfo::*;
 use super::ops::*;
+#[cfg(target_os = "linux")]
+use crate::detour::Detour;
 use crate::{detour::DetourGuard, hooks::HookManager, replace};
 
 /// Here we keep addr infos that we allocated so we'll know when to use the original
@@ -123,7 +127,107 @@ pub(crate) unsafe extern "C" fn gethostname_detour(
 #[hook_guard_fn]
 unsafe extern "C" fn gethostbyname_detour(raw_name: *const c_char) -> *mut hostent {
     let rawish_name = (!raw_name.is_null()).then(|| CStr::from_ptr(raw_name));
-    gethostbyname(rawish_name).unwrap_or_bypass_with(|_| FN_GETHOSTBYNAME(raw_name))
+    gethostbyname(rawish_name, AddressFamily::Ipv4Only)
+        .unwrap_or_bypass_with(|_| FN_GETHOSTBYNAME(raw_name))
+}
+
+/// Hook for `gethostbyname2`, like [`gethostbyname_detour`], but for addresses of the given
+/// `family`.
+#[hook_guard_fn]
+unsafe extern "C" fn gethostbyname2_detour(raw_name: *const c_char, family: c_int) -> *mut hostent {
+    let rawish_name = (!raw_name.is_null()).then(|| CStr::from_ptr(raw_name));
+    let address_family = match family {
+        libc::AF_INET => AddressFamily::Ipv4Only,
+        libc::AF_INET6 => AddressFamily::Ipv6Only,
+        _ => return FN_GETHOSTBYNAME2(raw_name, family),
+    };
+
+    gethostbyname(rawish_name, address_family)
+        .unwrap_or_bypass_with(|_| FN_GETHOSTBYNAME2(raw_name, family))
+}
+
+/// Hook for `gethostbyname_r`, the reentrant [`gethostbyname_detour`] that fills the caller's
+/// `ret` and `buffer`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn gethostbyname_r_detour(
+    raw_name: *const c_char,
+    ret: *mut hostent,
+    buffer: *mut c_char,
+    buffer_length: size_t,
+    result: *mut *mut hostent,
+    h_errnop: *mut c_int,
+) -> c_int {
+    if ret.is_null() || buffer.is_null() || result.is_null() || h_errnop.is_null() {
+        return FN_GETHOSTBYNAME_R(raw_name, ret, buffer, buffer_length, result, h_errnop);
+    }
+
+    let rawish_name = (!raw_name.is_null()).then(|| CStr::from_ptr(raw_name));
+    let rawish_buffer = slice::from_raw_parts_mut(buffer.cast::<u8>(), buffer_length);
+    gethostbyname_r(
+        rawish_name,
+        &mut *ret,
+        rawish_buffer,
+        &mut *result,
+        &mut *h_errnop,
+    )
+    .unwrap_or_bypass_with(|_| {
+        FN_GETHOSTBYNAME_R(raw_name, ret, buffer, buffer_length, result, h_errnop)
+    })
+}
+
+/// Hook for `gethostbyaddr`, resolves the name of the address through the agent.
+///
+/// Fills the same `static` [`libc::hostent`] as [`gethostbyname_detour`].
+#[hook_guard_fn]
+unsafe extern "C" fn gethostbyaddr_detour(
+    raw_address: *const c_void,
+    address_length: socklen_t,
+    family: c_int,
+) -> *mut hostent {
+    if raw_address.is_null() {
+        return FN_GETHOSTBYADDR(raw_address, address_length, family);
+    }
+
+    let rawish_address = slice::from_raw_parts(raw_address.cast::<u8>(), address_length as usize);
+    gethostbyaddr(rawish_address, family)
+        .unwrap_or_bypass_with(|_| FN_GETHOSTBYADDR(raw_address, address_length, family))
+}
+
+/// Hook for `getnameinfo`, resolves the host name of the address through the agent.
+///
+/// See [`getnameinfo`] for how the service name and missing names are handled.
+#[hook_guard_fn]
+unsafe extern "C" fn getnameinfo_detour(
+    raw_address: *const sockaddr,
+    address_length: socklen_t,
+    host: *mut c_char,
+    host_length: socklen_t,
+    service: *mut c_char,
+    service_length: socklen_t,
+    flags: c_int,
+) -> c_int {
+    let rawish_host = (!host.is_null())
+        .then(|| slice::from_raw_parts_mut(host.cast::<u8>(), host_length as usize));
+    getnameinfo(
+        raw_address,
+        address_length,
+        rawish_host,
+        service,
+        service_length,
+        flags,
+    )
+    .unwrap_or_bypass_with(|_| {
+        FN_GETNAMEINFO(
+            raw_address,
+            address_length,
+            host,
+            host_length,
+            service,
+            service_length,
+            flags,
+        )
+    })
 }
 
 #[hook_guard_fn]
@@ -283,6 +387,92 @@ unsafe extern "C" fn getaddrinfo_detour(
         .unwrap_or_bypass_with(|_| FN_GETADDRINFO(raw_node, raw_service, raw_hints, out_addr_info))
 }
 
+/// Implementation of the `res_*` detours, see [`res_query`].
+#[cfg(target_os = "linux")]
+unsafe fn res_query_logic(
+    raw_name: *const c_char,
+    class: c_int,
+    record_type: c_int,
+    answer: *mut u8,
+    answer_length: c_int,
+    search: bool,
+) -> Detour<c_int> {
+    let rawish_name = (!raw_name.is_null()).then(|| CStr::from_ptr(raw_name));
+    let answer = if answer.is_null() || answer_length <= 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(answer, answer_length as usize)
+    };
+
+    res_query(rawish_name, class, record_type, answer, search)
+}
+
+/// Hook for `libc::res_query`, used for the DNS records other than addresses, e.g. `SRV`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn res_query_detour(
+    raw_name: *const c_char,
+    class: c_int,
+    record_type: c_int,
+    answer: *mut u8,
+    answer_length: c_int,
+) -> c_int {
+    res_query_logic(raw_name, class, record_type, answer, answer_length, false)
+        .unwrap_or_bypass_with(|_| {
+            FN_RES_QUERY(raw_name, class, record_type, answer, answer_length)
+        })
+}
+
+/// Hook for `libc::res_search`, like [`res_query_detour`], but applies the search domains.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn res_search_detour(
+    raw_name: *const c_char,
+    class: c_int,
+    record_type: c_int,
+    answer: *mut u8,
+    answer_length: c_int,
+) -> c_int {
+    res_query_logic(raw_name, class, record_type, answer, answer_length, true)
+        .unwrap_or_bypass_with(|_| {
+            FN_RES_SEARCH(raw_name, class, record_type, answer, answer_length)
+        })
+}
+
+/// Hook for `libc::res_nquery`, the resolver `state` is not used for remote queries.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn res_nquery_detour(
+    state: *mut c_void,
+    raw_name: *const c_char,
+    class: c_int,
+    record_type: c_int,
+    answer: *mut u8,
+    answer_length: c_int,
+) -> c_int {
+    res_query_logic(raw_name, class, record_type, answer, answer_length, false)
+        .unwrap_or_bypass_with(|_| {
+            FN_RES_NQUERY(state, raw_name, class, record_type, answer, answer_length)
+        })
+}
+
+/// Hook for `libc::res_nsearch`, the resolver `state` is not used for remote queries.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn res_nsearch_detour(
+    state: *mut c_void,
+    raw_name: *const c_char,
+    class: c_int,
+    record_type: c_int,
+    answer: *mut u8,
+    answer_length: c_int,
+) -> c_int {
+    res_query_logic(raw_name, class, record_type, answer, answer_length, true)
+        .unwrap_or_bypass_with(|_| {
+            FN_RES_NSEARCH(state, raw_name, class, record_type, answer, answer_length)
+        })
+}
+
 /// Deallocates a `*mut libc::addrinfo` that was previously allocated with `Box::new` in
 /// `getaddrinfo_detour` and converted into a raw pointer by `Box::into_raw`. Same thing must also
 /// be done for `addrinfo.ai_addr`.
@@ -609,6 +799,30 @@ pub(crate) unsafe fn enable_socket_hooks(
             FN_GETHOSTBYNAME
         );
 
+        replace!(
+            hook_manager,
+            "gethostbyname2",
+            gethostbyname2_detour,
+            FnGethostbyname2,
+            FN_GETHOSTBYNAME2
+        );
+
+        replace!(
+            hook_manager,
+            "gethostbyaddr",
+            gethostbyaddr_detour,
+            FnGethostbyaddr,
+            FN_GETHOSTBYADDR
+        );
+
+        replace!(
+            hook_manager,
+            "getnameinfo",
+            getnameinfo_detour,
+            FnGetnameinfo,
+            FN_GETNAMEINFO
+        );
+
         replace!(
             hook_manager,
             "getaddrinfo",
@@ -624,6 +838,44 @@ pub(crate) unsafe fn enable_socket_hooks(
             FnFreeaddrinfo,
             FN_FREEADDRINFO
         );
+        #[cfg(target_os = "linux")]
+        {
+            replace!(
+                hook_manager,
+                "gethostbyname_r",
+                gethostbyname_r_detour,
+                FnGethostbyname_r,
+                FN_GETHOSTBYNAME_R
+            );
+            replace!(
+                hook_manager,
+                "res_query",
+                res_query_detour,
+                FnRes_query,
+                FN_RES_QUERY
+            );
+            replace!(
+                hook_manager,
+                "res_search",
+                res_search_detour,
+                FnRes_search,
+                FN_RES_SEARCH
+            );
+            replace!(
+                hook_manager,
+                "res_nquery",
+                res_nquery_detour,
+                FnRes_nquery,
+                FN_RES_NQUERY
+            );
+            replace!(
+                hook_manager,
+                "res_nsearch",
+                res_nsearch_detour,
+                FnRes_nsearch,
+                FN_RES_NSEARCH
+            );
+        }
         #[cfg(target_os = "macos")]
         {
             replace!(
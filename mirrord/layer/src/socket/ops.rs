@@ -9,20 +9,31 @@ use std::{
         unix::io::RawFd,
     },
     path::PathBuf,
-    ptr,
+    ptr, slice,
     sync::{Arc, Mutex, OnceLock},
 };
 
 use errno::set_errno;
-use libc::{c_int, c_void, hostent, sockaddr, socklen_t, AF_UNIX};
+use hickory_proto::{
+    op::{Message, MessageType, OpCode, Query, ResponseCode},
+    rr::{
+        rdata::{A, AAAA},
+        DNSClass, Name, RData, Record, RecordType,
+    },
+};
+use libc::{c_int, c_void, hostent, sockaddr, socklen_t, AF_INET6, AF_UNIX};
 use mirrord_config::feature::network::incoming::{IncomingConfig, IncomingMode};
 use mirrord_intproxy_protocol::{
     ConnMetadataRequest, ConnMetadataResponse, NetProtocol, OutgoingConnectRequest,
-    OutgoingConnectResponse, PortSubscribe,
+    OutgoingConnectResponse, PortSubscribe, UdpPortSubscribe,
 };
 use mirrord_protocol::{
-    dns::{GetAddrInfoRequest, LookupRecord},
+    dns::{
+        AddressFamily, DnsQueryRequest, GetAddrInfoRequestV2, LookupRecord, ReverseLookupRequest,
+    },
     file::{OpenFileResponse, OpenOptionsInternal, ReadFileResponse},
+    udp::{UdpIncomingMode, UdpPortSubscription},
+    DnsLookupError, Port, ResolveErrorKindInternal, ResponseError,
 };
 use nix::sys::socket::{sockopt, SockaddrIn, SockaddrIn6, SockaddrLike, SockaddrStorage};
 use socket2::SockAddr;
@@ -39,11 +50,12 @@ use crate::{
     file::{self, OPEN_FILES},
 };
 
-/// Holds the pair of [`IpAddr`] with their hostnames, resolved remotely through
-/// [`remote_getaddrinfo`].
+/// Holds the pair of [`IpAddr`] with the hostnames they were resolved from remotely, through
+/// [`remote_getaddrinfo`], [`remote_dns_query`] or [`remote_reverse_lookup`].
 ///
 /// Used by [`connect_outgoing`] to retrieve the hostname from the address that the user called
-/// [`connect`] with, so we can resolve it locally when neccessary.
+/// [`connect`] with, so we can match it against the outgoing filter and resolve it locally when
+/// neccessary, even when the app cached the address.
 pub(super) static REMOTE_DNS_REVERSE_MAPPING: LazyLock<Mutex<HashMap<IpAddr, String>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
@@ -59,7 +71,7 @@ static mut GETHOSTBYNAME_ALIASES_STR: Option<Vec<CString>> = None;
 /// have `*const _`. As this is being filled to fulfill the contract of a deprecated function, I
 /// (alex) don't think we're going to hit this issue ever.
 static mut GETHOSTBYNAME_ALIASES_PTR: Option<Vec<*const i8>> = None;
-static mut GETHOSTBYNAME_ADDRESSES_VAL: Option<Vec<[u8; 4]>> = None;
+static mut GETHOSTBYNAME_ADDRESSES_VAL: Option<Vec<Vec<u8>>> = None;
 static mut GETHOSTBYNAME_ADDRESSES_PTR: Option<Vec<*mut u8>> = None;
 
 /// Global static that the user will receive when calling [`gethostbyname`].
@@ -75,6 +87,32 @@ static mut GETHOSTBYNAME_HOSTENT: hostent = hostent {
     h_addr_list: ptr::null_mut(),
 };
 
+/// `h_errno` values set by [`res_query`] and the `gethostby*` functions, from `netdb.h`.
+#[cfg(target_os = "linux")]
+const NETDB_INTERNAL: c_int = -1;
+const HOST_NOT_FOUND: c_int = 1;
+#[cfg(target_os = "linux")]
+const TRY_AGAIN: c_int = 2;
+#[cfg(target_os = "linux")]
+const NO_DATA: c_int = 4;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn __h_errno_location() -> *mut c_int;
+}
+
+/// Sets `h_errno`, which the legacy resolver functions use instead of `errno`.
+///
+/// Only supported on linux, where `h_errno` is thread local.
+fn set_h_errno(value: c_int) {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        *__h_errno_location() = value
+    };
+    #[cfg(not(target_os = "linux"))]
+    let _ = value;
+}
+
 /// Helper struct for connect results where we want to hold the original errno
 /// when result is -1 (error) because sometimes it's not a real error (EINPROGRESS/EINTR)
 /// and the caller should have the original value.
@@ -129,10 +167,6 @@ pub(super) fn socket(domain: c_int, type_: c_int, protocol: c_int) -> Detour<Raw
         Ok(())
     }?;
 
-    if domain == libc::AF_INET6 {
-        return Detour::Error(HookError::SocketUnsuportedIpv6);
-    }
-
     let socket_result = unsafe { FN_SOCKET(domain, type_, protocol) };
 
     let socket_fd = if socket_result == -1 {
@@ -205,6 +239,64 @@ fn is_ignored_tcp_port(addr: &SocketAddr, config: &IncomingConfig) -> bool {
     is_ignored_port(addr) || (not_stolen_with_filter && not_whitelisted)
 }
 
+/// Returns the remote port from which the incoming UDP traffic should be delivered to a socket
+/// bound to the given `requested_address`, or [`None`] if the traffic should remain local.
+///
+/// Uses the same `port_mapping` and `ports` logic as the incoming TCP traffic.
+pub(super) fn incoming_udp_port(requested_address: &SocketAddr) -> Option<Port> {
+    let setup = crate::setup();
+    let incoming_config = setup.incoming_config();
+
+    if !incoming_config.udp
+        || matches!(incoming_config.mode, IncomingMode::Off)
+        || setup.targetless()
+        || is_ignored_port(requested_address)
+    {
+        return None;
+    }
+
+    let mapped_port = incoming_config
+        .port_mapping
+        .get_by_left(&requested_address.port())
+        .copied()
+        .unwrap_or_else(|| requested_address.port());
+
+    incoming_config
+        .ports
+        .as_ref()
+        .map_or(true, |ports| ports.contains(&mapped_port))
+        .then_some(mapped_port)
+}
+
+/// Subscribes to the incoming UDP traffic on the given remote `port`, so that the datagrams are
+/// delivered to the socket bound to `address`.
+///
+/// The socket remains bound locally if the subscription fails.
+fn subscribe_udp(port: Port, address: SocketAddr) -> Detour<()> {
+    let mode = if crate::setup().incoming_config().is_steal() {
+        UdpIncomingMode::Steal
+    } else {
+        UdpIncomingMode::Mirror
+    };
+
+    let response = common::make_proxy_request_with_response(UdpPortSubscribe {
+        bound_on: address,
+        subscription: UdpPortSubscription { port, mode },
+    })?;
+
+    match response {
+        Ok(()) => tracing::debug!("daemon subscribed UDP port {port}"),
+        Err(error) => warn!(
+            %error,
+            port,
+            "Failed to subscribe to the incoming UDP traffic, the socket will receive only \
+            local traffic"
+        ),
+    }
+
+    Detour::Success(())
+}
+
 /// If the socket is not found in [`SOCKETS`], bypass.
 /// Otherwise, if it's not an ignored port, bind (possibly with a fallback to random port) and
 /// update socket state in [`SOCKETS`]. If it's an ignored port, remove the socket from [`SOCKETS`].
@@ -327,6 +419,12 @@ pub(super) fn bind(
     .and_then(|(_, address)| address.as_socket())
     .bypass(Bypass::AddressConversion)?;
 
+    if socket.kind.is_udp()
+        && let Some(port) = incoming_udp_port(&requested_address)
+    {
+        subscribe_udp(port, address)?;
+    }
+
     Arc::get_mut(&mut socket).unwrap().state = SocketState::Bound(Bound {
         requested_address,
         address,
@@ -468,6 +566,8 @@ fn connect_outgoing<const CALL_CONNECT: bool>(
     mut user_socket_info: Arc<UserSocket>,
     protocol: NetProtocol,
 ) -> Detour<ConnectResult> {
+    let domain = user_socket_info.domain;
+
     // Closure that performs the connection with mirrord messaging.
     let remote_connection = |remote_address: SockAddr| {
         // Prepare this socket to be intercepted.
@@ -486,7 +586,7 @@ fn connect_outgoing<const CALL_CONNECT: bool>(
 
         // Connect to the interceptor socket that is listening.
         let connect_result: ConnectResult = if CALL_CONNECT {
-            let layer_address = SockAddr::try_from(layer_address.clone())?;
+            let layer_address = to_domain(SockAddr::try_from(layer_address.clone())?, domain);
 
             unsafe { FN_CONNECT(sockfd, layer_address.as_ptr(), layer_address.len()) }.into()
         } else {
@@ -534,7 +634,7 @@ fn connect_outgoing<const CALL_CONNECT: bool>(
                 Detour::Success(connect_result)
             }
             ConnectionThrough::Local(addr) => {
-                let rawish_local_addr = SockAddr::from(addr);
+                let rawish_local_addr = to_domain(SockAddr::from(addr), domain);
 
                 let connect_result = ConnectResult::from(unsafe {
                     FN_CONNECT(sockfd, rawish_local_addr.as_ptr(), rawish_local_addr.len())
@@ -569,7 +669,7 @@ fn connect_to_local_address(
                         address,
                     }) => (requested_address.port() == ip_address.port()
                         && socket.protocol == user_socket_info.protocol)
-                        .then(|| SockAddr::from(address)),
+                        .then(|| to_domain(SockAddr::from(address), user_socket_info.domain)),
                     _ => None,
                 })
                 .map(|rawish_remote_address| unsafe {
@@ -606,7 +706,7 @@ pub(super) fn connect(
     raw_address: *const sockaddr,
     address_length: socklen_t,
 ) -> Detour<ConnectResult> {
-    let remote_address = SockAddr::try_from_raw(raw_address, address_length)?;
+    let remote_address = to_canonical(SockAddr::try_from_raw(raw_address, address_length)?);
     let optional_ip_address = remote_address.as_socket();
 
     let unix_streams = crate::setup().remote_unix_streams();
@@ -625,7 +725,7 @@ pub(super) fn connect(
                 .family()
                 .map(|family| family as i32)
                 .unwrap_or(-1);
-            if domain != libc::AF_INET && domain != libc::AF_UNIX {
+            if domain != libc::AF_INET && domain != libc::AF_INET6 && domain != libc::AF_UNIX {
                 return Detour::Bypass(Bypass::Domain(domain));
             }
             // I really hate it, but nix seems to really make this API bad :()
@@ -719,14 +819,14 @@ pub(super) fn getpeername(
     address: *mut sockaddr,
     address_len: *mut socklen_t,
 ) -> Detour<i32> {
-    let remote_address = {
+    let (domain, remote_address) = {
         SOCKETS
             .lock()?
             .get(&sockfd)
             .bypass(Bypass::LocalFdNotFound(sockfd))
             .and_then(|socket| match &socket.state {
                 SocketState::Connected(connected) => {
-                    Detour::Success(connected.remote_address.clone())
+                    Detour::Success((socket.domain, connected.remote_address.clone()))
                 }
                 _ => Detour::Bypass(Bypass::InvalidState(sockfd)),
             })?
@@ -734,7 +834,11 @@ pub(super) fn getpeername(
 
     trace!("getpeername -> remote_address {:#?}", remote_address);
 
-    fill_address(address, address_len, remote_address.try_into()?)
+    fill_address(
+        address,
+        address_len,
+        to_domain(remote_address.try_into()?, domain),
+    )
 }
 
 /// Resolve the fake local address to the real local address.
@@ -749,35 +853,39 @@ pub(super) fn getsockname(
     address: *mut sockaddr,
     address_len: *mut socklen_t,
 ) -> Detour<i32> {
-    let local_address = {
+    let (domain, local_address) = {
         SOCKETS
             .lock()?
             .get(&sockfd)
             .bypass(Bypass::LocalFdNotFound(sockfd))
-            .and_then(|socket| match &socket.state {
-                SocketState::Connected(connected) => {
-                    Detour::Success(connected.local_address.clone())
-                }
-                SocketState::Bound(Bound {
-                    requested_address,
-                    address,
-                }) => {
-                    if requested_address.port() == 0 {
-                        Detour::Success(
-                            SocketAddr::new(requested_address.ip(), address.port()).into(),
-                        )
-                    } else {
-                        Detour::Success((*requested_address).into())
+            .and_then(|socket| {
+                let local_address: SocketAddress = match &socket.state {
+                    SocketState::Connected(connected) => connected.local_address.clone(),
+                    SocketState::Bound(Bound {
+                        requested_address,
+                        address,
+                    }) => {
+                        if requested_address.port() == 0 {
+                            SocketAddr::new(requested_address.ip(), address.port()).into()
+                        } else {
+                            (*requested_address).into()
+                        }
                     }
-                }
-                SocketState::Listening(bound) => Detour::Success(bound.requested_address.into()),
-                _ => Detour::Bypass(Bypass::InvalidState(sockfd)),
+                    SocketState::Listening(bound) => bound.requested_address.into(),
+                    _ => return Detour::Bypass(Bypass::InvalidState(sockfd)),
+                };
+
+                Detour::Success((socket.domain, local_address))
             })?
     };
 
     trace!("getsockname -> local_address {:#?}", local_address);
 
-    fill_address(address, address_len, local_address.try_into()?)
+    fill_address(
+        address,
+        address_len,
+        to_domain(local_address.try_into()?, domain),
+    )
 }
 
 /// When the fd is "ours", we accept and use [`ConnMetadataRequest`] to retrieve peer address from
@@ -832,7 +940,11 @@ pub(super) fn accept(
 
     let new_socket = UserSocket::new(domain, type_, protocol, state, type_.try_into()?);
 
-    fill_address(address, address_len, remote_source.into())?;
+    fill_address(
+        address,
+        address_len,
+        to_domain(remote_source.into(), domain),
+    )?;
 
     SOCKETS.lock()?.insert(new_fd, Arc::new(new_socket));
 
@@ -889,13 +1001,17 @@ pub(super) fn dup<const SWITCH_MAP: bool>(fd: c_int, dup_fd: i32) -> Result<(),
 ///
 /// This function updates the mapping in [`REMOTE_DNS_REVERSE_MAPPING`].
 #[mirrord_layer_macro::instrument(level = Level::TRACE, ret, err)]
-pub(super) fn remote_getaddrinfo(node: String) -> HookResult<Vec<(String, IpAddr)>> {
-    let addr_info_list = common::make_proxy_request_with_response(GetAddrInfoRequest { node })?.0?;
-
-    let mut remote_dns_reverse_mapping = REMOTE_DNS_REVERSE_MAPPING.lock()?;
-    addr_info_list.iter().for_each(|lookup| {
-        remote_dns_reverse_mapping.insert(lookup.ip, lookup.name.clone());
-    });
+pub(super) fn remote_getaddrinfo(
+    node: String,
+    family: AddressFamily,
+) -> HookResult<Vec<(String, IpAddr)>> {
+    let addr_info_list = common::make_proxy_request_with_response(GetAddrInfoRequestV2 {
+        node: node.clone(),
+        family,
+    })?
+    .0?;
+
+    remember_remote_answers(&node, addr_info_list.iter().map(|lookup| lookup.ip))?;
 
     Ok(addr_info_list
         .into_iter()
@@ -903,6 +1019,41 @@ pub(super) fn remote_getaddrinfo(node: String) -> HookResult<Vec<(String, IpAddr
         .collect())
 }
 
+/// Stores the `ips` that the remote DNS answered for `name` in [`REMOTE_DNS_REVERSE_MAPPING`].
+///
+/// We keep the name that the app asked for, rather than the names of the records (which may be
+/// canonical names), as that's what the outgoing filters are written against.
+fn remember_remote_answers(name: &str, ips: impl IntoIterator<Item = IpAddr>) -> HookResult<()> {
+    let name = name.trim_end_matches('.');
+
+    let mut remote_dns_reverse_mapping = REMOTE_DNS_REVERSE_MAPPING.lock()?;
+    ips.into_iter().for_each(|ip| {
+        remote_dns_reverse_mapping.insert(ip, name.to_string());
+    });
+
+    Ok(())
+}
+
+/// Answers `node` with the addresses of the given `family` that
+/// [`DnsConfig::hosts`](mirrord_config::feature::network::dns::DnsConfig::hosts) has for it.
+///
+/// These are not remote answers, so [`REMOTE_DNS_REVERSE_MAPPING`] is left untouched.
+fn static_getaddrinfo(node: &str, family: AddressFamily) -> Option<Vec<(String, IpAddr)>> {
+    let addresses = crate::setup().dns_selector().static_host(node)?;
+
+    let addresses = addresses
+        .iter()
+        .filter(|address| match family {
+            AddressFamily::Ipv4Only => address.is_ipv4(),
+            AddressFamily::Ipv6Only => address.is_ipv6(),
+            AddressFamily::Both | AddressFamily::Any => true,
+        })
+        .map(|address| (node.to_string(), *address))
+        .collect();
+
+    Some(addresses)
+}
+
 /// Retrieves the result of calling `getaddrinfo` from a remote host (resolves remote DNS),
 /// converting the result into a `Box` allocated raw pointer of `libc::addrinfo` (which is basically
 /// a linked list of such type).
@@ -948,26 +1099,44 @@ pub(super) fn getaddrinfo(
         .and_then(|service| service.parse::<u16>().ok())
         .unwrap_or(0);
 
-    crate::setup().dns_selector().check_query(&node, service)?;
-
     let raw_hints = raw_hints
         .cloned()
         .unwrap_or_else(|| unsafe { mem::zeroed() });
 
     // TODO(alex): Use more fields from `raw_hints` to respect the user's `getaddrinfo` call.
     let libc::addrinfo {
+        ai_family,
         ai_socktype,
         ai_protocol,
         ..
     } = raw_hints;
 
-    // Some apps (gRPC on Python) use `::` to listen on all interfaces, and usually that just means
-    // resolve on unspecified. So we just return that in IpV4 because we don't support ipv6.
-    let resolved_addr = if node == "::" {
-        // name is "" because that's what happens in real flow.
-        vec![("".to_string(), IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
-    } else {
-        remote_getaddrinfo(node.clone())?
+    let family = match ai_family {
+        libc::AF_INET => AddressFamily::Ipv4Only,
+        libc::AF_INET6 => AddressFamily::Ipv6Only,
+        _ => AddressFamily::Any,
+    };
+
+    let resolved_addr = match static_getaddrinfo(&node, family) {
+        Some(static_addr) => static_addr,
+        None => {
+            crate::setup().dns_selector().check_query(&node, service)?;
+
+            // Some apps (gRPC on Python) use `::` to listen on all interfaces, and usually that
+            // just means resolve on unspecified.
+            if node == "::" {
+                let unspecified = if family == AddressFamily::Ipv4Only {
+                    IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+                } else {
+                    IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                };
+
+                // name is "" because that's what happens in real flow.
+                vec![("".to_string(), unspecified)]
+            } else {
+                remote_getaddrinfo(node.clone(), family)?
+            }
+        }
     };
 
     let mut managed_addr_info = MANAGED_ADDRINFO.lock()?;
@@ -1045,16 +1214,9 @@ fn remote_hostname_string() -> Detour<CString> {
     .map(Detour::Success)?
 }
 
-/// Resolves a hostname and set result to static global like the original `gethostbyname` does.
-///
-/// Used by erlang/elixir to resolve DNS.
-///
-/// **Safety**:
-/// See the [`GETHOSTBYNAME_ALIASES_PTR`] docs. If you see this function being called and some weird
-/// issue is going on, assume that you might've triggered the UB.
-#[mirrord_layer_macro::instrument(level = "trace", ret)]
-pub(super) fn gethostbyname(raw_name: Option<&CStr>) -> Detour<*mut hostent> {
-    let name: String = raw_name
+/// Converts the name given to the `gethostbyname` family of functions.
+fn host_name_from_raw(raw_name: Option<&CStr>) -> Detour<String> {
+    let name = raw_name
         .bypass(Bypass::NullNode)?
         .to_str()
         .map_err(|fail| {
@@ -1064,70 +1226,547 @@ pub(super) fn gethostbyname(raw_name: Option<&CStr>) -> Detour<*mut hostent> {
         })?
         .into();
 
-    crate::setup().dns_selector().check_query(&name, 0)?;
+    Detour::Success(name)
+}
+
+/// A remotely resolved host, ready to fill a [`hostent`].
+#[derive(Debug)]
+struct HostEntry {
+    name: CString,
+    aliases: Vec<CString>,
+    /// Octets of the addresses, all of the same family.
+    addresses: Vec<Vec<u8>>,
+    ipv6: bool,
+}
+
+impl HostEntry {
+    /// Resolves `name` with [`static_getaddrinfo`], or with [`remote_getaddrinfo`] when the
+    /// [`DnsSelector`](super::dns_selector::DnsSelector) lets it go through the agent.
+    ///
+    /// [`hostent`] can hold addresses of only one family, so with [`AddressFamily::Any`] we keep
+    /// IPv6 addresses only when there are no IPv4 ones.
+    ///
+    /// Returns [`None`] when there are no addresses for `name`.
+    fn resolve(name: String, family: AddressFamily) -> Detour<Option<Self>> {
+        let hosts_and_ips = match static_getaddrinfo(&name, family) {
+            Some(hosts_and_ips) => hosts_and_ips,
+            None => {
+                crate::setup().dns_selector().check_query(&name, 0)?;
+                remote_getaddrinfo(name.clone(), family)?
+            }
+        };
 
-    let hosts_and_ips = remote_getaddrinfo(name.clone())?;
+        // We could `unwrap` here, as this would have failed on the previous conversion.
+        let name = CString::new(name)?;
+
+        if hosts_and_ips.is_empty() {
+            return Detour::Success(None);
+        }
+
+        // IPv4 wins whenever there is any, wherever it is in the answer.
+        let ipv6 = !hosts_and_ips.iter().any(|(_, ip)| ip.is_ipv4());
+
+        let (aliases, addresses) = hosts_and_ips
+            .into_iter()
+            .filter_map(|(host, ip)| match ip {
+                // Only care about addresses of the family we picked and hosts that exist.
+                IpAddr::V4(ip) if !ipv6 => {
+                    let c_host = CString::new(host).ok()?;
+                    Some((c_host, ip.octets().to_vec()))
+                }
+                IpAddr::V6(ip) if ipv6 => {
+                    let c_host = CString::new(host).ok()?;
+                    Some((c_host, ip.octets().to_vec()))
+                }
+                other => {
+                    trace!("address of a different family received - ignoring - {other:?}");
+                    None
+                }
+            })
+            .unzip();
+
+        Detour::Success(Some(Self {
+            name,
+            aliases,
+            addresses,
+            ipv6,
+        }))
+    }
 
-    // We could `unwrap` here, as this would have failed on the previous conversion.
-    let host_name = CString::new(name)?;
+    fn address_type(&self) -> (c_int, c_int) {
+        if self.ipv6 {
+            (libc::AF_INET6, 16)
+        } else {
+            (libc::AF_INET, 4)
+        }
+    }
 
-    if hosts_and_ips.is_empty() {
+    /// Moves this entry to the static globals, like the original `gethostbyname` does.
+    ///
+    /// **Safety**:
+    /// See the [`GETHOSTBYNAME_ALIASES_PTR`] docs.
+    fn into_static(self) -> *mut hostent {
+        let (h_addrtype, h_length) = self.address_type();
+        let Self {
+            name,
+            aliases,
+            // We need `*mut _` at the end, so `addresses` has to be `mut`.
+            mut addresses,
+            ..
+        } = self;
+
+        let mut aliases_ptrs: Vec<*const i8> = aliases
+            .iter()
+            .map(|alias| alias.as_ptr().cast())
+            .collect::<Vec<_>>();
+        let mut ips_ptrs = addresses
+            .iter_mut()
+            .map(|ip| ip.as_mut_ptr())
+            .collect::<Vec<_>>();
+
+        // Put a null ptr to signal end of the list.
+        aliases_ptrs.push(ptr::null());
+        ips_ptrs.push(ptr::null_mut());
+
+        // Need long-lived values so we can take pointers to them.
+        #[allow(static_mut_refs)]
+        unsafe {
+            GETHOSTBYNAME_HOSTNAME.replace(name);
+            GETHOSTBYNAME_ALIASES_STR.replace(aliases);
+            GETHOSTBYNAME_ALIASES_PTR.replace(aliases_ptrs);
+            GETHOSTBYNAME_ADDRESSES_VAL.replace(addresses);
+            GETHOSTBYNAME_ADDRESSES_PTR.replace(ips_ptrs);
+
+            // Fill the `*mut hostent` that the user will interact with.
+            GETHOSTBYNAME_HOSTENT.h_name = GETHOSTBYNAME_HOSTNAME.as_ref().unwrap().as_ptr() as _;
+            GETHOSTBYNAME_HOSTENT.h_addrtype = h_addrtype;
+            GETHOSTBYNAME_HOSTENT.h_length = h_length;
+            GETHOSTBYNAME_HOSTENT.h_aliases =
+                GETHOSTBYNAME_ALIASES_PTR.as_ref().unwrap().as_ptr() as _;
+            GETHOSTBYNAME_HOSTENT.h_addr_list =
+                GETHOSTBYNAME_ADDRESSES_PTR.as_ref().unwrap().as_ptr() as *mut *mut libc::c_char;
+
+            std::ptr::addr_of_mut!(GETHOSTBYNAME_HOSTENT)
+        }
+    }
+
+    /// Fills `hostent` with pointers into the caller's `buffer`, like the reentrant `*_r`
+    /// functions do.
+    ///
+    /// The pointer arrays go first, so they're properly aligned, followed by the addresses and the
+    /// strings.
+    ///
+    /// Returns [`None`] when `buffer` is too small.
+    #[cfg(target_os = "linux")]
+    fn write_into(&self, hostent: &mut hostent, buffer: &mut [u8]) -> Option<()> {
+        let pointer_size = mem::size_of::<*mut libc::c_char>();
+        let start = buffer
+            .as_ptr()
+            .align_offset(mem::align_of::<*mut libc::c_char>());
+
+        let pointers_length = (self.aliases.len() + self.addresses.len() + 2) * pointer_size;
+        let data_length = self.addresses.iter().map(Vec::len).sum::<usize>()
+            + std::iter::once(&self.name)
+                .chain(&self.aliases)
+                .map(|string| string.as_bytes_with_nul().len())
+                .sum::<usize>();
+        if start + pointers_length + data_length > buffer.len() {
+            return None;
+        }
+
+        let (pointers, data) = buffer[start..].split_at_mut(pointers_length);
+
+        let mut offset = 0;
+        let mut copy = |bytes: &[u8]| {
+            let target = &mut data[offset..offset + bytes.len()];
+            target.copy_from_slice(bytes);
+            offset += bytes.len();
+            target.as_mut_ptr().cast::<libc::c_char>()
+        };
+
+        let addresses = self
+            .addresses
+            .iter()
+            .map(|address| copy(address))
+            .chain(std::iter::once(ptr::null_mut()))
+            .collect::<Vec<_>>();
+        let name = copy(self.name.as_bytes_with_nul());
+        let aliases = self
+            .aliases
+            .iter()
+            .map(|alias| copy(alias.as_bytes_with_nul()))
+            .chain(std::iter::once(ptr::null_mut()))
+            .collect::<Vec<_>>();
+
+        pointers
+            .chunks_exact_mut(pointer_size)
+            .zip(aliases.iter().chain(&addresses))
+            .for_each(|(slot, pointer)| slot.copy_from_slice(&(*pointer as usize).to_ne_bytes()));
+
+        let (h_addrtype, h_length) = self.address_type();
+        hostent.h_name = name;
+        hostent.h_aliases = pointers.as_mut_ptr().cast();
+        hostent.h_addrtype = h_addrtype;
+        hostent.h_length = h_length;
+        hostent.h_addr_list = pointers[aliases.len() * pointer_size..].as_mut_ptr().cast();
+
+        Some(())
+    }
+}
+
+/// Resolves a hostname and set result to static global like the original `gethostbyname` does.
+///
+/// Also used by `gethostbyname2`, which asks for addresses of a specific `family`.
+///
+/// Used by erlang/elixir to resolve DNS.
+///
+/// **Safety**:
+/// See the [`GETHOSTBYNAME_ALIASES_PTR`] docs. If you see this function being called and some weird
+/// issue is going on, assume that you might've triggered the UB.
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(super) fn gethostbyname(
+    raw_name: Option<&CStr>,
+    family: AddressFamily,
+) -> Detour<*mut hostent> {
+    let name = host_name_from_raw(raw_name)?;
+
+    let Some(entry) = HostEntry::resolve(name, family)? else {
         set_errno(errno::Errno(libc::EAI_NODATA));
         return Detour::Success(ptr::null_mut());
+    };
+
+    Detour::Success(entry.into_static())
+}
+
+/// Reentrant version of [`gethostbyname`], fills the caller's `hostent` using `buffer` for the
+/// data it points to.
+///
+/// Returns `0` and sets `result` on success, `ERANGE` when `buffer` is too small. When nothing is
+/// found `result` is set to null and the error goes to `h_errno`.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = "trace", ret, skip(hostent, buffer, result, h_errno))]
+pub(super) fn gethostbyname_r(
+    raw_name: Option<&CStr>,
+    hostent: &mut hostent,
+    buffer: &mut [u8],
+    result: &mut *mut hostent,
+    h_errno: &mut c_int,
+) -> Detour<c_int> {
+    let name = host_name_from_raw(raw_name)?;
+
+    *result = ptr::null_mut();
+    let Some(entry) = HostEntry::resolve(name, AddressFamily::Ipv4Only)? else {
+        *h_errno = HOST_NOT_FOUND;
+        return Detour::Success(0);
+    };
+
+    if entry.write_into(hostent, buffer).is_none() {
+        *h_errno = NETDB_INTERNAL;
+        return Detour::Success(libc::ERANGE);
     }
 
-    // We need `*mut _` at the end, so `ips` has to be `mut`.
-    let (aliases, mut ips) = hosts_and_ips
-        .into_iter()
-        .filter_map(|(host, ip)| match ip {
-            // Only care about ipv4s and hosts that exist.
-            IpAddr::V4(ip) => {
-                let c_host = CString::new(host).ok()?;
-                Some((c_host, ip.octets()))
-            }
-            IpAddr::V6(ip) => {
-                trace!("ipv6 received - ignoring - {ip:?}");
-                None
-            }
-        })
-        .fold(
-            (Vec::default(), Vec::default()),
-            |(mut aliases, mut ips), (host, octets)| {
-                aliases.push(host);
-                ips.push(octets);
-                (aliases, ips)
-            },
-        );
+    *result = hostent;
+    Detour::Success(0)
+}
 
-    let mut aliases_ptrs: Vec<*const i8> = aliases
+/// Resolves the name of `ip` through the agent with a [`ReverseLookupRequest`].
+///
+/// Returns [`None`] when the address has no name. Bypassed when the address should be resolved
+/// locally, or when the agent doesn't support [`ReverseLookupRequest`]s.
+///
+/// # Note
+///
+/// This function updates the mapping in [`REMOTE_DNS_REVERSE_MAPPING`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+fn remote_reverse_lookup(ip: IpAddr, port: u16) -> Detour<Option<String>> {
+    crate::setup()
+        .dns_selector()
+        .check_query(&ip.to_string(), port)?;
+
+    let name = match common::make_proxy_request_with_response(ReverseLookupRequest { ip })?.0 {
+        Ok(names) => names
+            .into_iter()
+            .next()
+            .map(|name| name.trim_end_matches('.').to_string()),
+        // `NotImplemented` error here means that the protocol doesn't support it.
+        Err(ResponseError::NotImplemented) => return Detour::Bypass(Bypass::NotImplemented),
+        Err(ResponseError::DnsLookup(DnsLookupError {
+            kind: ResolveErrorKindInternal::NoRecordsFound(..),
+        })) => None,
+        Err(fail) => return Detour::Error(fail.into()),
+    };
+
+    if let Some(name) = &name {
+        remember_remote_answers(name, [ip])?;
+    }
+
+    Detour::Success(name)
+}
+
+/// Resolves the name of the address in `raw_address` remotely, and writes it to `host`.
+///
+/// Only the host part goes through the agent, the service is left to the original
+/// `getnameinfo`, called with a null `host`.
+///
+/// When the address has no name we fail with `EAI_NONAME` if `NI_NAMEREQD` was requested,
+/// otherwise the original `getnameinfo` writes the numeric address, instead of doing a local
+/// lookup.
+#[mirrord_layer_macro::instrument(level = "trace", ret, skip(raw_address, host, service))]
+pub(super) fn getnameinfo(
+    raw_address: *const sockaddr,
+    address_length: socklen_t,
+    host: Option<&mut [u8]>,
+    service: *mut libc::c_char,
+    service_length: socklen_t,
+    flags: c_int,
+) -> Detour<c_int> {
+    let host = host
+        .filter(|host| !host.is_empty())
+        .bypass(Bypass::NullNode)?;
+    if flags & libc::NI_NUMERICHOST != 0 {
+        return Detour::Bypass(Bypass::NumericHost);
+    }
+
+    let address = SocketAddr::try_from_raw(raw_address, address_length)?;
+    let Some(name) = remote_reverse_lookup(address.ip(), address.port())? else {
+        if flags & libc::NI_NAMEREQD != 0 {
+            return Detour::Success(libc::EAI_NONAME);
+        }
+
+        return Detour::Success(unsafe {
+            FN_GETNAMEINFO(
+                raw_address,
+                address_length,
+                host.as_mut_ptr().cast(),
+                host.len() as socklen_t,
+                service,
+                service_length,
+                flags | libc::NI_NUMERICHOST,
+            )
+        });
+    };
+
+    let name = if flags & libc::NI_NOFQDN != 0 {
+        name.split('.').next().unwrap_or_default()
+    } else {
+        name.as_str()
+    };
+    if name.len() >= host.len() {
+        return Detour::Success(libc::EAI_OVERFLOW);
+    }
+    host[..name.len()].copy_from_slice(name.as_bytes());
+    host[name.len()] = 0;
+
+    if service.is_null() || service_length == 0 {
+        return Detour::Success(0);
+    }
+
+    Detour::Success(unsafe {
+        FN_GETNAMEINFO(
+            raw_address,
+            address_length,
+            ptr::null_mut(),
+            0,
+            service,
+            service_length,
+            flags,
+        )
+    })
+}
+
+/// Resolves the name of `address` remotely for `gethostbyaddr`, and sets the result to the same
+/// static global as [`gethostbyname`].
+///
+/// `address` holds the octets of an address of the given `family`. When nothing is found the
+/// error goes to `h_errno`.
+///
+/// **Safety**:
+/// See the [`GETHOSTBYNAME_ALIASES_PTR`] docs.
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(super) fn gethostbyaddr(address: &[u8], family: c_int) -> Detour<*mut hostent> {
+    let ip = match family {
+        libc::AF_INET => IpAddr::from(
+            <[u8; 4]>::try_from(address)
+                .ok()
+                .bypass(Bypass::AddressConversion)?,
+        ),
+        libc::AF_INET6 => IpAddr::from(
+            <[u8; 16]>::try_from(address)
+                .ok()
+                .bypass(Bypass::AddressConversion)?,
+        ),
+        _ => return Detour::Bypass(Bypass::AddressConversion),
+    };
+
+    let Some(name) = remote_reverse_lookup(ip, 0)? else {
+        set_h_errno(HOST_NOT_FOUND);
+        return Detour::Success(ptr::null_mut());
+    };
+
+    let entry = HostEntry {
+        name: CString::new(name)?,
+        aliases: Vec::new(),
+        addresses: vec![address.to_vec()],
+        ipv6: ip.is_ipv6(),
+    };
+
+    Detour::Success(entry.into_static())
+}
+
+/// TTL of the answers from
+/// [`DnsConfig::hosts`](mirrord_config::feature::network::dns::DnsConfig::hosts), they don't
+/// change while the app runs.
+const STATIC_HOST_TTL: u32 = 3600;
+
+/// Builds the address records for `query` from the static `addresses`.
+///
+/// Queries for other record types get no records, as the name exists only with its addresses.
+fn static_dns_records(query: &Query, addresses: &[IpAddr]) -> Vec<Record> {
+    addresses
         .iter()
-        .map(|alias| alias.as_ptr().cast())
-        .collect::<Vec<_>>();
-    let mut ips_ptrs = ips.iter_mut().map(|ip| ip.as_mut_ptr()).collect::<Vec<_>>();
+        .filter_map(|address| {
+            let rdata = match (query.query_type(), address) {
+                (RecordType::A, IpAddr::V4(ip)) => RData::A(A(*ip)),
+                (RecordType::AAAA, IpAddr::V6(ip)) => RData::AAAA(AAAA(*ip)),
+                _ => return None,
+            };
 
-    // Put a null ptr to signal end of the list.
-    aliases_ptrs.push(ptr::null());
-    ips_ptrs.push(ptr::null_mut());
+            Some(Record::from_rdata(
+                query.name().clone(),
+                STATIC_HOST_TTL,
+                rdata,
+            ))
+        })
+        .collect()
+}
 
-    // Need long-lived values so we can take pointers to them.
-    #[allow(static_mut_refs)]
-    unsafe {
-        GETHOSTBYNAME_HOSTNAME.replace(host_name);
-        GETHOSTBYNAME_ALIASES_STR.replace(aliases);
-        GETHOSTBYNAME_ALIASES_PTR.replace(aliases_ptrs);
-        GETHOSTBYNAME_ADDRESSES_VAL.replace(ips);
-        GETHOSTBYNAME_ADDRESSES_PTR.replace(ips_ptrs);
-
-        // Fill the `*mut hostent` that the user will interact with.
-        GETHOSTBYNAME_HOSTENT.h_name = GETHOSTBYNAME_HOSTNAME.as_ref().unwrap().as_ptr() as _;
-        GETHOSTBYNAME_HOSTENT.h_length = 4;
-        GETHOSTBYNAME_HOSTENT.h_addrtype = libc::AF_INET;
-        GETHOSTBYNAME_HOSTENT.h_aliases = GETHOSTBYNAME_ALIASES_PTR.as_ref().unwrap().as_ptr() as _;
-        GETHOSTBYNAME_HOSTENT.h_addr_list =
-            GETHOSTBYNAME_ADDRESSES_PTR.as_ref().unwrap().as_ptr() as *mut *mut libc::c_char;
+/// Resolves `query` through the agent with a [`DnsQueryRequest`], see [`remote_dns_query`].
+fn remote_dns_records(name: String, query: &Query) -> Detour<(ResponseCode, Vec<Record>)> {
+    crate::setup()
+        .dns_selector()
+        .check_query(name.trim_end_matches('.'), 0)?;
+
+    let request = DnsQueryRequest {
+        name: name.clone(),
+        record_type: query.query_type().into(),
+    };
+    let records = match common::make_proxy_request_with_response(request)?.0 {
+        Ok(records) => {
+            let records = records
+                .into_iter()
+                .filter_map(|record| record.into_record().ok())
+                .collect::<Vec<_>>();
+
+            remember_remote_answers(
+                &name,
+                records.iter().filter_map(|record| record.data()?.ip_addr()),
+            )?;
+
+            (ResponseCode::NoError, records)
+        }
+        // `NotImplemented` error here means that the protocol doesn't support it.
+        Err(ResponseError::NotImplemented) => return Detour::Bypass(Bypass::NotImplemented),
+        Err(ResponseError::DnsLookup(DnsLookupError {
+            kind: ResolveErrorKindInternal::NoRecordsFound(code),
+        })) => (ResponseCode::from(code), Vec::new()),
+        Err(fail) => {
+            warn!(%fail, "Remote DNS query failed");
+            (ResponseCode::ServFail, Vec::new())
+        }
+    };
+
+    Detour::Success(records)
+}
+
+/// Resolves `query` through the agent with a [`DnsQueryRequest`], and builds the DNS response
+/// message with the given `id`.
+///
+/// Names from [`DnsConfig::hosts`](mirrord_config::feature::network::dns::DnsConfig::hosts) are
+/// answered with their static addresses, without asking the agent.
+///
+/// Failed lookups are reported with the response code, e.g. `NXDOMAIN`, like a DNS server would.
+/// Bypassed when the name should be resolved locally, or when the agent doesn't support
+/// [`DnsQueryRequest`]s.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+fn remote_dns_query(id: u16, recursion_desired: bool, query: Query) -> Detour<Message> {
+    let name = query.name().to_utf8();
+    let (response_code, answers) = match crate::setup().dns_selector().static_host(&name) {
+        Some(addresses) => (ResponseCode::NoError, static_dns_records(&query, addresses)),
+        None => remote_dns_records(name, &query)?,
+    };
+
+    let mut response = Message::new();
+    response
+        .set_id(id)
+        .set_message_type(MessageType::Response)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(recursion_desired)
+        .set_recursion_available(true)
+        .set_response_code(response_code)
+        .add_query(query)
+        .add_answers(answers);
+
+    Detour::Success(response)
+}
+
+/// Resolves the records of `name` remotely for `res_query` and `res_search`, and writes the DNS
+/// response message to `answer`.
+///
+/// `res_query` asks for the exact `name`, while `res_search` lets the remote resolver apply the
+/// search domains of the remote `resolv.conf`.
+///
+/// Returns the length of the response, or `-1` with `h_errno` set when nothing was found. Like in
+/// glibc, a response that doesn't fit in `answer` is cut, but its full length is returned, so the
+/// caller can tell that it was cut and retry with a larger buffer.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = "trace", ret, skip(answer))]
+pub(super) fn res_query(
+    raw_name: Option<&CStr>,
+    class: c_int,
+    record_type: c_int,
+    answer: &mut [u8],
+    search: bool,
+) -> Detour<c_int> {
+    let name = raw_name
+        .bypass(Bypass::NullNode)?
+        .to_str()
+        .map_err(|fail| {
+            warn!("Failed converting `name` from `CStr` with {:#?}", fail);
+
+            Bypass::CStrConversion
+        })?;
+
+    // The agent resolves only internet records.
+    if DNSClass::from(class as u16) != DNSClass::IN {
+        return Detour::Bypass(Bypass::NotImplemented);
+    }
+
+    let mut name = Name::from_utf8(name).map_err(|_| Bypass::CStrConversion)?;
+    if !search {
+        name.set_fqdn(true);
+    }
+
+    let query = Query::query(name, RecordType::from(record_type as u16));
+    let response = remote_dns_query(rand::random(), true, query)?;
+
+    let h_errno = match response.response_code() {
+        ResponseCode::NoError if !response.answers().is_empty() => None,
+        ResponseCode::NoError => Some(NO_DATA),
+        ResponseCode::NXDomain => Some(HOST_NOT_FOUND),
+        _ => Some(TRY_AGAIN),
+    };
+    if let Some(h_errno) = h_errno {
+        set_h_errno(h_errno);
+        return Detour::Success(-1);
     }
 
-    Detour::Success(std::ptr::addr_of!(GETHOSTBYNAME_HOSTENT) as _)
+    let response = response.to_vec().map_err(|_| Bypass::NotImplemented)?;
+    answer
+        .iter_mut()
+        .zip(&response)
+        .for_each(|(to, from)| *to = *from);
+
+    Detour::Success(response.len() as c_int)
 }
 
 /// Resolve hostname from remote host with caching for the result
@@ -1202,17 +1841,121 @@ pub(super) fn recv_from(
         .get(&sockfd)
         .and_then(|socket| match &socket.state {
             SocketState::Connected(Connected { remote_address, .. }) => {
-                Some(remote_address.clone())
+                Some((socket.domain, remote_address.clone()))
             }
             _ => None,
         })
-        .map(SocketAddress::try_into)?
+        .map(|(domain, remote_address)| {
+            SockAddr::try_from(remote_address).map(|address| to_domain(address, domain))
+        })?
         .map(|address| fill_address(raw_source, source_length, address))??;
 
     errno::set_errno(errno::Errno(0));
     Detour::Success(recv_from_result)
 }
 
+/// Answers a DNS query sent to port `53` with [`remote_dns_query`], see [`send_to`] and
+/// [`sendmsg`].
+///
+/// The query is sent to a local responder socket instead of the DNS server, and the responder
+/// replies with the response built from the agent's answer.
+#[derive(Debug)]
+struct DnsResponder {
+    socket: std::net::UdpSocket,
+    response: Vec<u8>,
+}
+
+impl DnsResponder {
+    /// Resolves the query in `message`, if it's a single standard query of the `IN` class.
+    ///
+    /// Returns [`None`] for the messages that should be sent to the DNS server as they are.
+    fn intercept(message: &[u8], domain: c_int) -> Option<Self> {
+        match Self::resolve(message, domain) {
+            Detour::Success(responder) => Some(responder),
+            Detour::Bypass(..) => None,
+            Detour::Error(fail) => {
+                warn!(%fail, "Failed to answer a DNS query, sending it to the DNS server");
+                None
+            }
+        }
+    }
+
+    fn resolve(message: &[u8], domain: c_int) -> Detour<Self> {
+        if !crate::setup().remote_dns_enabled() {
+            return Detour::Bypass(Bypass::LocalDns);
+        }
+
+        let query_message = Message::from_vec(message).ok()?;
+        let [query] = query_message.queries() else {
+            return Detour::Bypass(Bypass::NotImplemented);
+        };
+
+        if query_message.message_type() != MessageType::Query
+            || query_message.op_code() != OpCode::Query
+            || query.query_class() != DNSClass::IN
+        {
+            return Detour::Bypass(Bypass::NotImplemented);
+        }
+
+        let response = remote_dns_query(
+            query_message.id(),
+            query_message.recursion_desired(),
+            query.clone(),
+        )?
+        .to_vec()
+        .ok()?;
+
+        let localhost: IpAddr = if domain == AF_INET6 {
+            Ipv6Addr::LOCALHOST.into()
+        } else {
+            Ipv4Addr::LOCALHOST.into()
+        };
+        let socket = std::net::UdpSocket::bind(SocketAddr::new(localhost, 0))?;
+        socket.set_nonblocking(true)?;
+
+        Detour::Success(Self { socket, response })
+    }
+
+    /// Address that the query should be sent to, instead of the DNS server.
+    fn address(&self, domain: c_int) -> Detour<SockAddr> {
+        Detour::Success(to_domain(SockAddr::from(self.socket.local_addr()?), domain))
+    }
+
+    /// Replies to the query that `sockfd` sent to [`Self::address`], and puts `sockfd` in the
+    /// [`Connected`] state, so that [`recv_from`] reports the response as coming from the DNS
+    /// `server`.
+    fn respond(
+        self,
+        sockfd: RawFd,
+        mut user_socket_info: Arc<UserSocket>,
+        server: SocketAddr,
+    ) -> Detour<()> {
+        let local_address = unsafe {
+            SockAddr::try_init(|storage, len| {
+                if FN_GETSOCKNAME(sockfd, storage.cast(), len) == -1 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            })
+        }?
+        .1;
+
+        Arc::get_mut(&mut user_socket_info).unwrap().state = SocketState::Connected(Connected {
+            remote_address: server.into(),
+            local_address: SocketAddress::try_from(local_address)?,
+            layer_address: Some(self.socket.local_addr()?.into()),
+        });
+        SOCKETS.lock()?.insert(sockfd, user_socket_info);
+
+        // The query content doesn't matter, we only need its source address.
+        let (_, querier) = self.socket.recv_from(&mut [0; 512])?;
+        self.socket.send_to(&self.response, querier)?;
+
+        Detour::Success(())
+    }
+}
+
 /// Helps manually resolving DNS on port `53` with UDP, see [`send_to`] and [`sendmsg`].
 #[mirrord_layer_macro::instrument(level = "trace", ret)]
 fn send_dns_patch(
@@ -1298,13 +2041,14 @@ pub(super) fn send_to(
     raw_destination: *const sockaddr,
     destination_length: socklen_t,
 ) -> Detour<isize> {
-    let destination = SockAddr::try_from_raw(raw_destination, destination_length)?;
+    let destination = to_canonical(SockAddr::try_from_raw(raw_destination, destination_length)?);
     trace!("destination {:?}", destination.as_socket());
 
     let user_socket_info = SOCKETS
         .lock()?
         .remove(&sockfd)
         .ok_or(Bypass::LocalFdNotFound(sockfd))?;
+    let domain = user_socket_info.domain;
 
     // we don't support unix sockets which don't use `connect`
     if (destination.is_unix() || user_socket_info.domain == AF_UNIX)
@@ -1325,7 +2069,10 @@ pub(super) fn send_to(
         .as_socket()
         .filter(|destination| destination.port() != 53)
     {
-        let rawish_true_destination = send_dns_patch(sockfd, user_socket_info, destination)?;
+        let rawish_true_destination = to_domain(
+            send_dns_patch(sockfd, user_socket_info, destination)?,
+            domain,
+        );
 
         unsafe {
             FN_SEND_TO(
@@ -1337,6 +2084,30 @@ pub(super) fn send_to(
                 rawish_true_destination.len(),
             )
         }
+    } else if let Some((server, responder)) = destination.as_socket().and_then(|server| {
+        let message = unsafe { slice::from_raw_parts(raw_message.cast(), message_length) };
+        Some((server, DnsResponder::intercept(message, domain)?))
+    }) {
+        let responder_address = responder.address(domain)?;
+
+        let sent_result = unsafe {
+            FN_SEND_TO(
+                sockfd,
+                raw_message,
+                message_length,
+                flags,
+                responder_address.as_ptr(),
+                responder_address.len(),
+            )
+        };
+
+        if sent_result == -1 {
+            SOCKETS.lock()?.insert(sockfd, user_socket_info);
+        } else {
+            responder.respond(sockfd, user_socket_info, server)?;
+        }
+
+        sent_result
     } else {
         connect_outgoing::<false>(
             sockfd,
@@ -1353,6 +2124,7 @@ pub(super) fn send_to(
                 _ => unreachable!(),
             })
             .map(SocketAddress::try_into)??;
+        let layer_address = to_domain(layer_address, domain);
 
         let raw_interceptor_address = layer_address.as_ptr();
         let raw_interceptor_length = layer_address.len();
@@ -1384,7 +2156,7 @@ pub(super) fn sendmsg(
     let destination = (!unsafe { *raw_message_header }.msg_name.is_null()).then(|| {
         let raw_destination = unsafe { *raw_message_header }.msg_name as *const libc::sockaddr;
         let destination_length = unsafe { *raw_message_header }.msg_namelen;
-        SockAddr::try_from_raw(raw_destination, destination_length)
+        SockAddr::try_from_raw(raw_destination, destination_length).map(to_canonical)
     })??;
 
     trace!("destination {:?}", destination.as_socket());
@@ -1394,6 +2166,7 @@ pub(super) fn sendmsg(
         .lock()?
         .remove(&sockfd)
         .ok_or(Bypass::LocalFdNotFound(sockfd))?;
+    let domain = user_socket_info.domain;
 
     // we don't support unix sockets which don't use `connect`
     if (destination.is_unix() || user_socket_info.domain == AF_UNIX)
@@ -1414,7 +2187,10 @@ pub(super) fn sendmsg(
         .as_socket()
         .filter(|destination| destination.port() != 53)
     {
-        let rawish_true_destination = send_dns_patch(sockfd, user_socket_info, destination)?;
+        let rawish_true_destination = to_domain(
+            send_dns_patch(sockfd, user_socket_info, destination)?,
+            domain,
+        );
 
         let mut true_message_header = Box::new(unsafe { *raw_message_header });
 
@@ -1430,6 +2206,33 @@ pub(super) fn sendmsg(
         true_message_header.as_mut().msg_namelen = rawish_true_destination.len();
 
         unsafe { FN_SENDMSG(sockfd, true_message_header.as_ref(), flags) }
+    } else if let Some((server, responder)) = destination.as_socket().and_then(|server| {
+        let message = unsafe { message_bytes(raw_message_header) };
+        Some((server, DnsResponder::intercept(&message, domain)?))
+    }) {
+        let responder_address = responder.address(domain)?;
+        let mut true_message_header = Box::new(unsafe { *raw_message_header });
+
+        unsafe {
+            true_message_header
+                .as_mut()
+                .msg_name
+                .copy_from_nonoverlapping(
+                    responder_address.as_ptr() as *const _,
+                    responder_address.len() as usize,
+                )
+        };
+        true_message_header.as_mut().msg_namelen = responder_address.len();
+
+        let sent_result = unsafe { FN_SENDMSG(sockfd, true_message_header.as_ref(), flags) };
+
+        if sent_result == -1 {
+            SOCKETS.lock()?.insert(sockfd, user_socket_info);
+        } else {
+            responder.respond(sockfd, user_socket_info, server)?;
+        }
+
+        sent_result
     } else {
         connect_outgoing::<false>(
             sockfd,
@@ -1446,6 +2249,7 @@ pub(super) fn sendmsg(
                 _ => unreachable!(),
             })
             .map(SocketAddress::try_into)??;
+        let layer_address = to_domain(layer_address, domain);
 
         let raw_interceptor_address = layer_address.as_ptr() as *const _;
         let raw_interceptor_length = layer_address.len();
@@ -1465,6 +2269,30 @@ pub(super) fn sendmsg(
     Detour::Success(sent_result)
 }
 
+/// Gathers the content of the buffers in `raw_message_header`, for [`DnsResponder::intercept`].
+///
+/// # Safety
+///
+/// `raw_message_header` must be valid, as passed to [`libc::sendmsg`].
+unsafe fn message_bytes(raw_message_header: *const libc::msghdr) -> Vec<u8> {
+    let libc::msghdr {
+        msg_iov,
+        msg_iovlen,
+        ..
+    } = *raw_message_header;
+
+    if msg_iov.is_null() {
+        return Vec::new();
+    }
+
+    slice::from_raw_parts(msg_iov, msg_iovlen as usize)
+        .iter()
+        .filter(|iov| !iov.iov_base.is_null())
+        .flat_map(|iov| slice::from_raw_parts(iov.iov_base.cast::<u8>(), iov.iov_len))
+        .copied()
+        .collect()
+}
+
 /// helper to reconstruct a [`dns_resolver_t`] for [`remote_dns_configuration_copy`]
 /// NOTE: do free any memory "leaked" in this function over at [`free_dns_resolver_t`]
 #[cfg(target_os = "macos")]
@@ -0,0 +1,62 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/sendfile.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+/// Test `copy_file_range`, `sendfile` and `splice` from a remote file, which contains
+/// `"hello remote"`.
+///
+/// The copied bytes must come from the remote file, not from the empty local one backing it, and
+/// `copy_file_range` must reject any `flags`.
+int main() {
+  char buffer[32] = {0};
+
+  int fd = open("/app/source.txt", O_RDONLY);
+  assert(fd >= 0);
+
+  char out_path[] = "/tmp/mirrord-copy-range-XXXXXX";
+  int out_fd = mkstemp(out_path);
+  assert(out_fd >= 0);
+  assert(unlink(out_path) == 0);
+
+  loff_t offset = 0;
+  errno = 0;
+  assert(copy_file_range(fd, &offset, out_fd, NULL, 12, 1) == -1);
+  assert(errno == EINVAL);
+  assert(offset == 0);
+
+  assert(copy_file_range(fd, &offset, out_fd, NULL, 64, 0) == 12);
+  assert(offset == 12);
+  assert(pread(out_fd, buffer, sizeof(buffer), 0) == 12);
+  assert(memcmp(buffer, "hello remote", 12) == 0);
+  assert(close(out_fd) == 0);
+
+  int sockets[2];
+  assert(socketpair(AF_UNIX, SOCK_STREAM, 0, sockets) == 0);
+  assert(sendfile(sockets[0], fd, NULL, 5) == 5);
+  assert(recv(sockets[1], buffer, sizeof(buffer), 0) == 5);
+  assert(memcmp(buffer, "hello", 5) == 0);
+  assert(close(sockets[0]) == 0);
+  assert(close(sockets[1]) == 0);
+
+  int pipe_fds[2];
+  assert(pipe(pipe_fds) == 0);
+  offset = 6;
+  assert(splice(fd, &offset, pipe_fds[1], NULL, 6, 0) == 6);
+  assert(offset == 12);
+  assert(read(pipe_fds[0], buffer, sizeof(buffer)) == 6);
+  assert(memcmp(buffer, "remote", 6) == 0);
+  assert(close(pipe_fds[0]) == 0);
+  assert(close(pipe_fds[1]) == 0);
+
+  assert(close(fd) == 0);
+
+  printf("copy range done\n");
+  return 0;
+}
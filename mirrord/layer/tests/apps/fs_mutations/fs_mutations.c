@@ -0,0 +1,31 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+/// Test the functions that modify the remote file system.
+///
+/// The `*at` functions are called with a descriptor of a remote directory, so the relative paths
+/// should be resolved against it.
+int main() {
+  assert(mkdir("/app/dir", 0755) == 0);
+
+  int dir_fd = open("/app/dir", O_RDONLY | O_DIRECTORY);
+  assert(dir_fd >= 0);
+
+  assert(mkdirat(dir_fd, "child", 0700) == 0);
+  assert(unlinkat(dir_fd, "child", AT_REMOVEDIR) == 0);
+  assert(renameat(dir_fd, "old.txt", AT_FDCWD, "/app/new.txt") == 0);
+  assert(unlinkat(dir_fd, "file.txt", 0) == 0);
+  assert(fchownat(dir_fd, "link", 1000, -1, AT_SYMLINK_NOFOLLOW) == 0);
+  assert(close(dir_fd) == 0);
+
+  assert(rename("/app/new.txt", "/app/renamed.txt") == 0);
+  assert(chown("/app/renamed.txt", -1, 1000) == 0);
+  assert(unlink("/app/renamed.txt") == 0);
+  assert(rmdir("/app/dir") == 0);
+
+  printf("fs mutations done\n");
+  return 0;
+}
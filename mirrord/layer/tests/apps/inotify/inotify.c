@@ -0,0 +1,43 @@
+#include <assert.h>
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/inotify.h>
+#include <unistd.h>
+
+/// Test an inotify instance watching a remote directory.
+///
+/// A read with a buffer too small for the next event should fail with `EINVAL`, without consuming
+/// the event.
+int main() {
+  int fd = inotify_init1(IN_CLOEXEC);
+  assert(fd >= 0);
+
+  int wd = inotify_add_watch(fd, "/app/dir", IN_MODIFY);
+  assert(wd >= 0);
+
+  char small[sizeof(struct inotify_event)];
+  errno = 0;
+  assert(read(fd, small, sizeof(small)) == -1);
+  assert(errno == EINVAL);
+
+  char buffer[4096] __attribute__((aligned(__alignof__(struct inotify_event))));
+  ssize_t received = read(fd, buffer, sizeof(buffer));
+  assert(received == sizeof(struct inotify_event) + 16);
+  struct inotify_event *event = (struct inotify_event *)buffer;
+  assert(event->wd == wd);
+  assert(event->mask == IN_MODIFY);
+  assert(strcmp(event->name, "config.yaml") == 0);
+
+  assert(inotify_rm_watch(fd, wd) == 0);
+
+  received = read(fd, buffer, sizeof(buffer));
+  assert(received == sizeof(struct inotify_event));
+  assert(event->wd == wd);
+  assert(event->mask == IN_IGNORED);
+
+  assert(close(fd) == 0);
+
+  printf("inotify done\n");
+  return 0;
+}
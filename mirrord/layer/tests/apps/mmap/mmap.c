@@ -0,0 +1,46 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+/// Test memory mappings of a remote file.
+///
+/// A mapping replaced by a fixed anonymous mapping should not be written back, remapping a remote
+/// file mapping should fail, and the dirty pages of a shared mapping should be written back on
+/// `msync`.
+int main() {
+  size_t page = sysconf(_SC_PAGESIZE);
+
+  int fd = open("/app/mapped.txt", O_RDWR);
+  assert(fd >= 0);
+
+  char *replaced = mmap(NULL, page, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+  assert(replaced != MAP_FAILED);
+  assert(memcmp(replaced, "hello", 5) == 0);
+
+  errno = 0;
+  assert(mremap(replaced, page, 2 * page, MREMAP_MAYMOVE) == MAP_FAILED);
+  assert(errno == EINVAL);
+
+  replaced[0] = 'H';
+  assert(mmap(replaced, page, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1,
+              0) == replaced);
+  // Not a remote file mapping anymore, so it can be remapped and unmapped without a write back.
+  assert(mremap(replaced, page, page, 0) == replaced);
+  assert(munmap(replaced, page) == 0);
+
+  char *shared = mmap(NULL, page, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+  assert(shared != MAP_FAILED);
+  shared[0] = 'H';
+  assert(msync(shared, page, MS_SYNC) == 0);
+  assert(munmap(shared, page) == 0);
+
+  assert(close(fd) == 0);
+
+  printf("mmap done\n");
+  return 0;
+}
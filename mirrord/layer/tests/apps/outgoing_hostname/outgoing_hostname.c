@@ -0,0 +1,53 @@
+#include <arpa/inet.h>
+#include <assert.h>
+#include <errno.h>
+#include <netdb.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+/// Resolves `name` to a single IPv4 address with `getaddrinfo`, and connects a new TCP socket to
+/// it. Returns the socket, and the result of `connect` in `result`.
+int resolve_and_connect(const char *name, const char *port, int *result) {
+  struct addrinfo hints = {0};
+  hints.ai_family = AF_INET;
+  hints.ai_socktype = SOCK_STREAM;
+
+  struct addrinfo *address = NULL;
+  assert(getaddrinfo(name, port, &hints, &address) == 0);
+  assert(address != NULL);
+
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  assert(fd >= 0);
+  *result = connect(fd, address->ai_addr, address->ai_addrlen);
+
+  freeaddrinfo(address);
+  return fd;
+}
+
+/// Test the `*.payments.svc` outgoing filter, which selects the remote connections by the names
+/// that their addresses were resolved from in the remote pod.
+///
+/// `api.payments.svc` matches, so it's connected through the agent, which echoes the data back.
+/// `db.other.svc` doesn't, so it's connected locally, to a loopback port where nothing listens.
+int main() {
+  int result = -1;
+
+  int remote = resolve_and_connect("api.payments.svc", "80", &result);
+  assert(result == 0);
+  assert(send(remote, "ping", 4, 0) == 4);
+  char buffer[4];
+  assert(recv(remote, buffer, sizeof(buffer), MSG_WAITALL) == 4);
+  assert(memcmp(buffer, "ping", 4) == 0);
+  assert(recv(remote, buffer, sizeof(buffer), 0) == 0);
+  assert(close(remote) == 0);
+
+  int local = resolve_and_connect("db.other.svc", "1", &result);
+  assert(result == -1);
+  assert(errno == ECONNREFUSED);
+  assert(close(local) == 0);
+
+  printf("outgoing hostname done\n");
+  return 0;
+}
@@ -0,0 +1,32 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+/// Test `rename` and `renameat` on paths mapped to local ones with a `local:` rule.
+///
+/// Both the old and the new path must be replaced with the mapped ones, so the files are only
+/// ever found under the local directory.
+int main() {
+  assert(mkdir("/tmp/mirrord-rename-mapping", 0755) == 0 || errno == EEXIST);
+
+  int fd = open("/tmp/mirrord-rename-mapping/old.txt", O_CREAT | O_WRONLY | O_TRUNC, 0644);
+  assert(fd >= 0);
+  assert(close(fd) == 0);
+
+  assert(rename("/app/mapped/old.txt", "/app/mapped/new.txt") == 0);
+  assert(access("/tmp/mirrord-rename-mapping/old.txt", F_OK) == -1);
+  assert(access("/tmp/mirrord-rename-mapping/new.txt", F_OK) == 0);
+
+  assert(renameat(AT_FDCWD, "/app/mapped/new.txt", AT_FDCWD, "/app/mapped/renamed.txt") == 0);
+  assert(access("/tmp/mirrord-rename-mapping/new.txt", F_OK) == -1);
+  assert(access("/tmp/mirrord-rename-mapping/renamed.txt", F_OK) == 0);
+
+  assert(unlink("/tmp/mirrord-rename-mapping/renamed.txt") == 0);
+  assert(rmdir("/tmp/mirrord-rename-mapping") == 0);
+
+  printf("rename mapping done\n");
+  return 0;
+}
@@ -0,0 +1,30 @@
+#include <arpa/nameser.h>
+#include <assert.h>
+#include <netinet/in.h>
+#include <resolv.h>
+#include <stdio.h>
+#include <string.h>
+
+/// Test `res_query` with a response that doesn't fit in the buffer.
+///
+/// Like in glibc, the response is cut at the buffer size, but its full length is returned, so the
+/// query can be retried with a buffer that is large enough.
+int main() {
+  unsigned char small[64 + 1];
+  memset(small, 0xaa, sizeof(small));
+
+  int length = res_query("big.svc", C_IN, T_TXT, small, 64);
+  assert(length > 64);
+  assert(small[64] == 0xaa);
+
+  unsigned char large[512];
+  assert(length <= (int)sizeof(large));
+  assert(res_query("big.svc", C_IN, T_TXT, large, sizeof(large)) == length);
+  assert(memcmp(small + 2, large + 2, 62) == 0);
+
+  // A single answer, the record count is at offset 6 of the header.
+  assert(large[6] == 0 && large[7] == 1);
+
+  printf("res query done\n");
+  return 0;
+}
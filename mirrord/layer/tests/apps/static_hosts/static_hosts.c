@@ -0,0 +1,63 @@
+#include <arpa/inet.h>
+#include <assert.h>
+#include <netdb.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+
+/// Resolves `name` with `getaddrinfo` for the given `family`, and checks that the only address
+/// found is `expected`.
+void expect_single_address(const char *name, int family, const char *expected) {
+  struct addrinfo hints = {0};
+  hints.ai_family = family;
+  hints.ai_socktype = SOCK_STREAM;
+
+  struct addrinfo *result = NULL;
+  assert(getaddrinfo(name, "80", &hints, &result) == 0);
+  assert(result != NULL);
+  assert(result->ai_next == NULL);
+  assert(result->ai_family == family);
+
+  char address[INET6_ADDRSTRLEN];
+  const void *raw = family == AF_INET
+                        ? (const void *)&((struct sockaddr_in *)result->ai_addr)->sin_addr
+                        : (const void *)&((struct sockaddr_in6 *)result->ai_addr)->sin6_addr;
+  assert(inet_ntop(family, raw, address, sizeof(address)) != NULL);
+  assert(strcmp(address, expected) == 0);
+
+  freeaddrinfo(result);
+}
+
+/// Resolves `name` with `gethostbyname`, and checks that the only address found is the IPv4
+/// `expected`.
+void expect_host_entry(const char *name, const char *expected) {
+  struct hostent *entry = gethostbyname(name);
+  assert(entry != NULL);
+  assert(entry->h_addrtype == AF_INET);
+  assert(entry->h_addr_list[0] != NULL);
+  assert(entry->h_addr_list[1] == NULL);
+
+  char address[INET_ADDRSTRLEN];
+  assert(inet_ntop(AF_INET, entry->h_addr_list[0], address, sizeof(address)) != NULL);
+  assert(strcmp(address, expected) == 0);
+}
+
+/// Test `getaddrinfo` and `gethostbyname` for names from `feature.network.dns.hosts`, which
+/// have both an IPv4 and an IPv6 address, and are answered without asking the agent.
+int main() {
+  expect_single_address("payments.static.svc", AF_INET, "10.1.2.3");
+  expect_single_address("payments.static.svc", AF_INET6, "fd00::1");
+
+  struct addrinfo *both = NULL;
+  assert(getaddrinfo("payments.static.svc", NULL, NULL, &both) == 0);
+  assert(both != NULL && both->ai_next != NULL && both->ai_next->ai_next == NULL);
+  assert(both->ai_family != both->ai_next->ai_family);
+  freeaddrinfo(both);
+
+  expect_host_entry("payments.static.svc", "10.1.2.3");
+  // The IPv6 address is listed first in the config.
+  expect_host_entry("orders.ipv6-first.svc", "10.4.5.6");
+
+  printf("static hosts done\n");
+  return 0;
+}
@@ -109,7 +109,14 @@ impl TestIntProxy {
             let agent_conn = AgentConnection::new_for_raw_address(fake_agent_address)
                 .await
                 .unwrap();
-            let intproxy = IntProxy::new_with_connection(agent_conn, listener);
+            let intproxy = IntProxy::new_with_connection(
+                agent_conn,
+                listener,
+                Default::default(),
+                None,
+                None,
+                None,
+            );
             intproxy
                 .run(Duration::from_secs(5), Duration::from_secs(5))
                 .await
@@ -142,6 +149,10 @@ impl TestIntProxy {
                     self.send(DaemonMessage::SwitchProtocolVersionResponse(version))
                         .await;
                 }
+                ClientMessage::SwitchCompression(compression) => {
+                    self.send(DaemonMessage::SwitchCompressionResponse(compression))
+                        .await;
+                }
                 ClientMessage::ReadyForLogs => {}
                 other => break Some(other),
             }
@@ -615,6 +626,17 @@ impl TestIntProxy {
         );
     }
 
+    /// Verify the next message from the layer is the given [`FileRequest`], answer it with the
+    /// given [`FileResponse`].
+    pub async fn expect_file_request(&mut self, request: FileRequest, response: FileResponse) {
+        assert_eq!(self.recv().await, ClientMessage::FileRequest(request));
+
+        self.codec
+            .send(DaemonMessage::File(response))
+            .await
+            .unwrap();
+    }
+
     /// Verify the next message from the layer is an access to the given path with the given mode.
     /// Send back a response.
     pub async fn expect_file_access(&mut self, pathname: PathBuf, mode: u8) {
@@ -742,6 +764,14 @@ pub enum Application {
     RustListenPorts,
     Fork,
     ReadLink,
+    FsMutations,
+    Mmap,
+    Inotify,
+    RenameMapping,
+    CopyRange,
+    ResQuery,
+    StaticHosts,
+    OutgoingHostname,
     OpenFile,
     CIssue2055,
     CIssue2178,
@@ -795,6 +825,16 @@ impl Application {
             Application::PythonFastApiHTTP | Application::PythonIssue864 => String::from("uvicorn"),
             Application::Fork => String::from("tests/apps/fork/out.c_test_app"),
             Application::ReadLink => String::from("tests/apps/readlink/out.c_test_app"),
+            Application::FsMutations => String::from("tests/apps/fs_mutations/out.c_test_app"),
+            Application::Mmap => String::from("tests/apps/mmap/out.c_test_app"),
+            Application::Inotify => String::from("tests/apps/inotify/out.c_test_app"),
+            Application::RenameMapping => String::from("tests/apps/rename_mapping/out.c_test_app"),
+            Application::CopyRange => String::from("tests/apps/copy_range/out.c_test_app"),
+            Application::ResQuery => String::from("tests/apps/res_query/out.c_test_app"),
+            Application::StaticHosts => String::from("tests/apps/static_hosts/out.c_test_app"),
+            Application::OutgoingHostname => {
+                String::from("tests/apps/outgoing_hostname/out.c_test_app")
+            }
             Application::Realpath => String::from("tests/apps/realpath/out.c_test_app"),
             Application::NodeHTTP | Application::NodeIssue2283 | Application::NodeIssue2807 => {
                 String::from("node")
@@ -1025,6 +1065,14 @@ impl Application {
             | Application::Go23FAccessAt
             | Application::Fork
             | Application::ReadLink
+            | Application::FsMutations
+            | Application::Mmap
+            | Application::Inotify
+            | Application::RenameMapping
+            | Application::CopyRange
+            | Application::ResQuery
+            | Application::StaticHosts
+            | Application::OutgoingHostname
             | Application::Realpath
             | Application::RustFileOps
             | Application::RustIssue1123
@@ -1100,6 +1148,14 @@ impl Application {
             | Application::BashShebang
             | Application::Fork
             | Application::ReadLink
+            | Application::FsMutations
+            | Application::Mmap
+            | Application::Inotify
+            | Application::RenameMapping
+            | Application::CopyRange
+            | Application::ResQuery
+            | Application::StaticHosts
+            | Application::OutgoingHostname
             | Application::Realpath
             | Application::Go21Issue834
             | Application::Go22Issue834
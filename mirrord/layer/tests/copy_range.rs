@@ -0,0 +1,100 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{
+        ReadFileResponse, ReadLimitedFileRequest, SeekFileRequest, SeekFileResponse,
+        SeekFromInternal,
+    },
+    FileRequest, FileResponse,
+};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Answers a [`ReadLimitedFileRequest`] for the remote file, which contains `"hello remote"`.
+async fn expect_source_read(intproxy: &mut TestIntProxy, fd: u64, buffer_size: u64, start: u64) {
+    const CONTENTS: &[u8] = b"hello remote";
+
+    let bytes = CONTENTS
+        .iter()
+        .skip(start as usize)
+        .take(buffer_size as usize)
+        .copied()
+        .collect::<Vec<_>>();
+
+    intproxy
+        .expect_file_request(
+            FileRequest::ReadLimited(ReadLimitedFileRequest {
+                remote_fd: fd,
+                buffer_size,
+                start_from: start,
+            }),
+            FileResponse::ReadLimited(Ok(ReadFileResponse {
+                read_amount: bytes.len() as u64,
+                bytes,
+            })),
+        )
+        .await;
+}
+
+/// Test `copy_file_range` to a local file, `sendfile` to a socket and `splice` to a pipe, all from
+/// a remote file. A `copy_file_range` with `flags` fails without reaching the agent.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn copy_range(dylib_path: &Path) {
+    let application = Application::CopyRange;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            vec![
+                ("MIRRORD_FILE_MODE", "localwithoverrides"),
+                ("MIRRORD_FILE_READ_WRITE_PATTERN", "^/app/"),
+            ],
+            None,
+        )
+        .await;
+
+    let fd = 1;
+    intproxy
+        .expect_file_open_for_reading("/app/source.txt", fd)
+        .await;
+
+    // `copy_file_range` with an offset, which is not the file position.
+    expect_source_read(&mut intproxy, fd, 64, 0).await;
+
+    // `sendfile` without an offset, from the file position, which is then moved.
+    intproxy
+        .expect_file_request(
+            FileRequest::Seek(SeekFileRequest {
+                fd,
+                seek_from: SeekFromInternal::Current(0),
+            }),
+            FileResponse::Seek(Ok(SeekFileResponse { result_offset: 0 })),
+        )
+        .await;
+    expect_source_read(&mut intproxy, fd, 5, 0).await;
+    intproxy
+        .expect_file_request(
+            FileRequest::Seek(SeekFileRequest {
+                fd,
+                seek_from: SeekFromInternal::Start(5),
+            }),
+            FileResponse::Seek(Ok(SeekFileResponse { result_offset: 5 })),
+        )
+        .await;
+
+    // `splice` with an offset.
+    expect_source_read(&mut intproxy, fd, 6, 6).await;
+
+    intproxy.expect_file_close(fd).await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_stdout_contains("copy range done").await;
+}
@@ -0,0 +1,138 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{ChownRequest, MakeDirRequest, RemoveDirRequest, RenameRequest, UnlinkRequest},
+    FileRequest, FileResponse,
+};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test `mkdir`, `rmdir`, `unlink`, `rename`, `chown` and their `*at` versions, called with a
+/// descriptor of a remote directory.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn fs_mutations(dylib_path: &Path) {
+    let application = Application::FsMutations;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            vec![
+                ("MIRRORD_FILE_MODE", "localwithoverrides"),
+                ("MIRRORD_FILE_READ_WRITE_PATTERN", "^/app/"),
+            ],
+            None,
+        )
+        .await;
+
+    intproxy
+        .expect_file_request(
+            FileRequest::MakeDir(MakeDirRequest {
+                path: "/app/dir".into(),
+                mode: 0o755,
+            }),
+            FileResponse::MakeDir(Ok(())),
+        )
+        .await;
+
+    let dir_fd = 1;
+    intproxy
+        .expect_file_open_for_reading("/app/dir", dir_fd)
+        .await;
+
+    intproxy
+        .expect_file_request(
+            FileRequest::MakeDir(MakeDirRequest {
+                path: "/app/dir/child".into(),
+                mode: 0o700,
+            }),
+            FileResponse::MakeDir(Ok(())),
+        )
+        .await;
+    intproxy
+        .expect_file_request(
+            FileRequest::RemoveDir(RemoveDirRequest {
+                path: "/app/dir/child".into(),
+            }),
+            FileResponse::RemoveDir(Ok(())),
+        )
+        .await;
+    intproxy
+        .expect_file_request(
+            FileRequest::Rename(RenameRequest {
+                old_path: "/app/dir/old.txt".into(),
+                new_path: "/app/new.txt".into(),
+            }),
+            FileResponse::Rename(Ok(())),
+        )
+        .await;
+    intproxy
+        .expect_file_request(
+            FileRequest::Unlink(UnlinkRequest {
+                path: "/app/dir/file.txt".into(),
+            }),
+            FileResponse::Unlink(Ok(())),
+        )
+        .await;
+    intproxy
+        .expect_file_request(
+            FileRequest::Chown(ChownRequest {
+                path: "/app/dir/link".into(),
+                user_id: Some(1000),
+                group_id: None,
+                follow_symlink: false,
+            }),
+            FileResponse::Chown(Ok(())),
+        )
+        .await;
+    intproxy.expect_file_close(dir_fd).await;
+
+    intproxy
+        .expect_file_request(
+            FileRequest::Rename(RenameRequest {
+                old_path: "/app/new.txt".into(),
+                new_path: "/app/renamed.txt".into(),
+            }),
+            FileResponse::Rename(Ok(())),
+        )
+        .await;
+    intproxy
+        .expect_file_request(
+            FileRequest::Chown(ChownRequest {
+                path: "/app/renamed.txt".into(),
+                user_id: None,
+                group_id: Some(1000),
+                follow_symlink: true,
+            }),
+            FileResponse::Chown(Ok(())),
+        )
+        .await;
+    intproxy
+        .expect_file_request(
+            FileRequest::Unlink(UnlinkRequest {
+                path: "/app/renamed.txt".into(),
+            }),
+            FileResponse::Unlink(Ok(())),
+        )
+        .await;
+    intproxy
+        .expect_file_request(
+            FileRequest::RemoveDir(RemoveDirRequest {
+                path: "/app/dir".into(),
+            }),
+            FileResponse::RemoveDir(Ok(())),
+        )
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process
+        .assert_stdout_contains("fs mutations done")
+        .await;
+}
@@ -0,0 +1,62 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{AddWatchRequest, AddWatchResponse, FileWatchEvent, RemoveWatchRequest},
+    ClientMessage, DaemonMessage, FileRequest, FileResponse,
+};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test an emulated inotify instance watching a remote directory.
+///
+/// The event is pushed by the agent, and read by the application only with a big enough buffer.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn inotify(dylib_path: &Path) {
+    let application = Application::Inotify;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            vec![
+                ("MIRRORD_FILE_MODE", "localwithoverrides"),
+                ("MIRRORD_FILE_READ_WRITE_PATTERN", "^/app/"),
+            ],
+            None,
+        )
+        .await;
+
+    intproxy
+        .expect_file_request(
+            FileRequest::AddWatch(AddWatchRequest {
+                path: "/app/dir".into(),
+                mask: libc::IN_MODIFY,
+            }),
+            FileResponse::AddWatch(Ok(AddWatchResponse { watch_id: 1 })),
+        )
+        .await;
+
+    intproxy
+        .send(DaemonMessage::FileWatchEvent(FileWatchEvent {
+            watch_id: 1,
+            mask: libc::IN_MODIFY,
+            cookie: 0,
+            name: Some("config.yaml".to_string()),
+        }))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::RemoveWatch(RemoveWatchRequest { watch_id: 1 }))
+    );
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_stdout_contains("inotify done").await;
+}
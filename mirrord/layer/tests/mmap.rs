@@ -0,0 +1,89 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{ReadFileResponse, ReadLimitedFileRequest, WriteFileResponse, WriteLimitedFileRequest},
+    ClientMessage, DaemonMessage, FileRequest, FileResponse,
+};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test `mmap`, `mremap`, `msync` and `munmap` on a remote file.
+///
+/// The first mapping is replaced with a fixed anonymous mapping, so its change is never written
+/// back. Only the change in the second mapping should reach the remote file.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn mmap(dylib_path: &Path) {
+    let application = Application::Mmap;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            vec![
+                ("MIRRORD_FILE_MODE", "localwithoverrides"),
+                ("MIRRORD_FILE_READ_WRITE_PATTERN", "^/app/"),
+            ],
+            None,
+        )
+        .await;
+
+    let fd = 1;
+    intproxy
+        .expect_file_open_with_whatever_options("/app/mapped.txt", fd)
+        .await;
+
+    for _ in 0..2 {
+        let page_size = match intproxy.recv().await {
+            ClientMessage::FileRequest(FileRequest::ReadLimited(ReadLimitedFileRequest {
+                remote_fd,
+                buffer_size,
+                start_from: 0,
+            })) if remote_fd == fd => buffer_size,
+            other => panic!("unexpected message: {other:?}"),
+        };
+        intproxy
+            .send(DaemonMessage::File(FileResponse::ReadLimited(Ok(
+                ReadFileResponse {
+                    bytes: b"hello".to_vec(),
+                    read_amount: 5,
+                },
+            ))))
+            .await;
+
+        intproxy
+            .expect_file_request(
+                FileRequest::ReadLimited(ReadLimitedFileRequest {
+                    remote_fd: fd,
+                    buffer_size: page_size - 5,
+                    start_from: 5,
+                }),
+                FileResponse::ReadLimited(Ok(ReadFileResponse {
+                    bytes: vec![],
+                    read_amount: 0,
+                })),
+            )
+            .await;
+    }
+
+    intproxy
+        .expect_file_request(
+            FileRequest::WriteLimited(WriteLimitedFileRequest {
+                remote_fd: fd,
+                start_from: 0,
+                write_bytes: b"Hello".to_vec(),
+            }),
+            FileResponse::WriteLimited(Ok(WriteFileResponse { written_amount: 5 })),
+        )
+        .await;
+    intproxy.expect_file_close(fd).await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_stdout_contains("mmap done").await;
+}
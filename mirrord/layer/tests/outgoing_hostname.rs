@@ -0,0 +1,102 @@
+#![feature(assert_matches)]
+use std::{net::SocketAddr, path::Path, time::Duration};
+
+use mirrord_protocol::{
+    dns::{DnsLookup, GetAddrInfoRequestV2, GetAddrInfoResponse, LookupRecord},
+    outgoing::{
+        tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
+        DaemonConnect, DaemonRead, LayerConnect, LayerWrite, SocketAddress,
+    },
+    ClientMessage, DaemonMessage,
+};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Answers the remote `getaddrinfo` of `name` with the single address `ip`.
+async fn expect_resolve(intproxy: &mut TestIntProxy, name: &str, ip: &str) {
+    let msg = intproxy.recv().await;
+    let ClientMessage::GetAddrInfoRequestV2(GetAddrInfoRequestV2 { node, .. }) = msg else {
+        panic!("Invalid message received from layer: {msg:?}");
+    };
+    assert_eq!(node, name);
+
+    intproxy
+        .send(DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(Ok(
+            DnsLookup(vec![LookupRecord {
+                name: node,
+                ip: ip.parse().unwrap(),
+            }]),
+        ))))
+        .await;
+}
+
+/// Test the `tcp://*.payments.svc` remote outgoing filter, which is matched against the names
+/// that the addresses were resolved from through the agent.
+///
+/// Only the connection to the address of `api.payments.svc` goes through the agent, the one to
+/// the address of `db.other.svc` is made locally.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn outgoing_hostname_filter(dylib_path: &Path, config_dir: &Path) {
+    let application = Application::OutgoingHostname;
+    let config_path = config_dir.join("outgoing_filter_hostname.json");
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![], Some(config_path.to_str().unwrap()))
+        .await;
+
+    expect_resolve(&mut intproxy, "api.payments.svc", "10.9.8.7").await;
+
+    let expected_peer_address = "10.9.8.7:80".parse::<SocketAddr>().unwrap();
+    let msg = intproxy.recv().await;
+    let ClientMessage::TcpOutgoing(LayerTcpOutgoing::Connect(LayerConnect {
+        remote_address: SocketAddress::Ip(addr),
+    })) = msg
+    else {
+        panic!("Invalid message received from layer: {msg:?}");
+    };
+    assert_eq!(addr, expected_peer_address);
+
+    intproxy
+        .send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Connect(Ok(
+            DaemonConnect {
+                connection_id: 0,
+                remote_address: addr.into(),
+                local_address: "10.0.0.5:40000".parse::<SocketAddr>().unwrap().into(),
+            },
+        ))))
+        .await;
+
+    let msg = intproxy.recv().await;
+    let ClientMessage::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+        connection_id: 0,
+        bytes,
+    })) = msg
+    else {
+        panic!("Invalid message received from layer: {msg:?}");
+    };
+    assert_eq!(bytes, b"ping");
+
+    intproxy
+        .send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Read(Ok(
+            DaemonRead {
+                connection_id: 0,
+                bytes,
+            },
+        ))))
+        .await;
+    intproxy
+        .send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Close(0)))
+        .await;
+
+    expect_resolve(&mut intproxy, "db.other.svc", "127.0.0.1").await;
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process
+        .assert_stdout_contains("outgoing hostname done")
+        .await;
+}
@@ -0,0 +1,29 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test `rename` and `renameat` with both paths mapped to local ones by a `local:` rule of
+/// `feature.fs.mapping`. The files are renamed in the local directory, nothing reaches the agent.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn rename_mapping(dylib_path: &Path, config_dir: &Path) {
+    let application = Application::RenameMapping;
+    let config_path = config_dir.join("fs_mapping_rename.json");
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![], Some(config_path.to_str().unwrap()))
+        .await;
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process
+        .assert_stdout_contains("rename mapping done")
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+}
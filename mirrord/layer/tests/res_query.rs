@@ -0,0 +1,58 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    dns::{DnsQueryRequest, DnsQueryResponse, DnsRecord},
+    ClientMessage, DaemonMessage,
+};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Numeric type of the `TXT` records.
+const TXT: u16 = 16;
+
+/// Test `res_query` with a remote response that is larger than the application's buffer. The
+/// application queries twice, first with a buffer that is too small.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn res_query(dylib_path: &Path) {
+    let application = Application::ResQuery;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_REMOTE_DNS", "true")], None)
+        .await;
+
+    // A single string of 200 characters, prefixed with its length.
+    let rdata = std::iter::once(200)
+        .chain(std::iter::repeat(b'a').take(200))
+        .collect::<Vec<u8>>();
+
+    for _ in 0..2 {
+        assert_eq!(
+            intproxy.recv().await,
+            ClientMessage::DnsQuery(DnsQueryRequest {
+                name: "big.svc.".to_string(),
+                record_type: TXT,
+            })
+        );
+        intproxy
+            .send(DaemonMessage::DnsQuery(DnsQueryResponse(Ok(vec![
+                DnsRecord {
+                    name: "big.svc.".to_string(),
+                    record_type: TXT,
+                    ttl: 60,
+                    rdata: rdata.clone(),
+                },
+            ]))))
+            .await;
+    }
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_stdout_contains("res query done").await;
+}
@@ -0,0 +1,29 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test `getaddrinfo`, for each address family, and `gethostbyname` for names from
+/// `feature.network.dns.hosts`. The answers come from the config, nothing reaches the agent.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn static_hosts(dylib_path: &Path, config_dir: &Path) {
+    let application = Application::StaticHosts;
+    let config_path = config_dir.join("dns_static_hosts.json");
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![], Some(config_path.to_str().unwrap()))
+        .await;
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process
+        .assert_stdout_contains("static hosts done")
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+}
@@ -12,7 +12,10 @@ use mirrord_macros::protocol_break;
 use semver::VersionReq;
 
 use crate::{
-    dns::{GetAddrInfoRequest, GetAddrInfoResponse},
+    dns::{
+        DnsCacheStats, DnsQueryRequest, DnsQueryResponse, GetAddrInfoRequest, GetAddrInfoRequestV2,
+        GetAddrInfoResponse, ReverseLookupRequest, ReverseLookupResponse,
+    },
     file::*,
     outgoing::{
         tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
@@ -20,6 +23,7 @@ use crate::{
     },
     pause::DaemonPauseTarget,
     tcp::{DaemonTcp, LayerTcp, LayerTcpSteal},
+    udp::{DaemonUdpIncoming, LayerUdpIncoming},
     vpn::{ClientVpn, ServerVpn},
     ResponseError,
 };
@@ -83,12 +87,55 @@ pub enum FileRequest {
     /// only. [`ReadDirRequest`]s that come from the layer are transformed into this
     /// batched form when the protocol version supports it. See [`READDIR_BATCH_VERSION`].
     ReadDirBatch(ReadDirBatchRequest),
+    MakeDir(MakeDirRequest),
+    RemoveDir(RemoveDirRequest),
+    Unlink(UnlinkRequest),
+    Rename(RenameRequest),
+    Chmod(ChmodRequest),
+    Fchmod(FchmodRequest),
+    Chown(ChownRequest),
+    Truncate(TruncateRequest),
+    Ftruncate(FtruncateRequest),
+    Symlink(SymlinkRequest),
+    Utimes(UtimesRequest),
+    /// Starts watching a remote path, the events are pushed with
+    /// [`DaemonMessage::FileWatchEvent`]. See [`WATCH_VERSION`].
+    AddWatch(AddWatchRequest),
+    /// Not answered.
+    RemoveWatch(RemoveWatchRequest),
+    /// Like [`FileRequest::XstatFs`], but for a path instead of an open file. See
+    /// [`STATFS_VERSION`].
+    StatFs(StatFsRequest),
+    /// Like [`FileRequest::XstatFs`], but the response includes the maximum length of file
+    /// names. See [`STATFS_V2_VERSION`].
+    XstatFsV2(XstatFsRequestV2),
+    /// Like [`FileRequest::StatFs`], but the response includes the maximum length of file names.
+    /// See [`STATFS_V2_VERSION`].
+    StatFsV2(StatFsRequestV2),
 }
 
 /// Minimal mirrord-protocol version that allows `ClientMessage::ReadyForLogs` message.
 pub static CLIENT_READY_FOR_LOGS: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.3.1".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows [`ClientMessage::SwitchCompression`] message.
+pub static COMPRESSION_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.16.0".parse().expect("Bad Identifier"));
+
+/// Compression of the messages sent with the [`ProtocolCodec`].
+///
+/// Negotiated with [`ClientMessage::SwitchCompression`] and
+/// [`DaemonMessage::SwitchCompressionResponse`], see [`SwitchesCompression`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Compression {
+    /// Messages are sent as they are.
+    #[default]
+    None,
+    /// Messages are sent in frames, and the ones larger than
+    /// [`ProtocolCodec::COMPRESSION_THRESHOLD`] are compressed with zstd.
+    Zstd,
+}
+
 /// `-layer` --> `-agent` messages.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub enum ClientMessage {
@@ -106,6 +153,33 @@ pub enum ClientMessage {
     SwitchProtocolVersion(#[bincode(with_serde)] semver::Version),
     ReadyForLogs,
     Vpn(ClientVpn),
+    /// Resolves the given node, possibly to IPv6 addresses.
+    ///
+    /// Answered with [`DaemonMessage::GetAddrInfoResponse`].
+    GetAddrInfoRequestV2(GetAddrInfoRequestV2),
+    /// Incoming UDP traffic, mirrored or stolen.
+    ///
+    /// Answered with [`DaemonMessage::UdpIncoming`].
+    UdpIncoming(LayerUdpIncoming),
+    /// Messages sent after this one use the given [`Compression`].
+    ///
+    /// Answered with [`DaemonMessage::SwitchCompressionResponse`].
+    SwitchCompression(Compression),
+    /// Resolves the given name to records of any type. See
+    /// [`DNS_QUERY_VERSION`](crate::dns::DNS_QUERY_VERSION).
+    ///
+    /// Answered with [`DaemonMessage::DnsQuery`].
+    DnsQuery(DnsQueryRequest),
+    /// Asks for the [`DnsCacheStats`] of the agent. See
+    /// [`DNS_CACHE_STATS_VERSION`](crate::dns::DNS_CACHE_STATS_VERSION).
+    ///
+    /// Answered with [`DaemonMessage::DnsCacheStats`].
+    GetDnsCacheStats,
+    /// Resolves the names of the given address. See
+    /// [`REVERSE_LOOKUP_VERSION`](crate::dns::REVERSE_LOOKUP_VERSION).
+    ///
+    /// Answered with [`DaemonMessage::ReverseLookup`].
+    ReverseLookup(ReverseLookupRequest),
 }
 
 /// Type alias for `Result`s that should be returned from mirrord-agent to mirrord-layer.
@@ -121,12 +195,27 @@ pub enum FileResponse {
     Seek(RemoteResult<SeekFileResponse>),
     Access(RemoteResult<AccessFileResponse>),
     Xstat(RemoteResult<XstatResponse>),
+    /// Response to both [`FileRequest::XstatFs`] and [`FileRequest::StatFs`].
     XstatFs(RemoteResult<XstatFsResponse>),
     ReadDir(RemoteResult<ReadDirResponse>),
     OpenDir(RemoteResult<OpenDirResponse>),
     GetDEnts64(RemoteResult<GetDEnts64Response>),
     ReadLink(RemoteResult<ReadLinkFileResponse>),
     ReadDirBatch(RemoteResult<ReadDirBatchResponse>),
+    MakeDir(RemoteResult<()>),
+    RemoveDir(RemoteResult<()>),
+    Unlink(RemoteResult<()>),
+    Rename(RemoteResult<()>),
+    /// Response to both [`FileRequest::Chmod`] and [`FileRequest::Fchmod`].
+    Chmod(RemoteResult<()>),
+    Chown(RemoteResult<()>),
+    /// Response to both [`FileRequest::Truncate`] and [`FileRequest::Ftruncate`].
+    Truncate(RemoteResult<()>),
+    Symlink(RemoteResult<()>),
+    Utimes(RemoteResult<()>),
+    AddWatch(RemoteResult<AddWatchResponse>),
+    /// Response to both [`FileRequest::XstatFsV2`] and [`FileRequest::StatFsV2`].
+    XstatFsV2(RemoteResult<XstatFsResponseV2>),
 }
 
 /// `-agent` --> `-layer` messages.
@@ -150,10 +239,51 @@ pub enum DaemonMessage {
     PauseTarget(DaemonPauseTarget),
     SwitchProtocolVersionResponse(#[bincode(with_serde)] semver::Version),
     Vpn(ServerVpn),
+    UdpIncoming(DaemonUdpIncoming),
+    /// Messages sent after this one use the given [`Compression`].
+    SwitchCompressionResponse(Compression),
+    /// Pushed for the watches started with [`FileRequest::AddWatch`].
+    FileWatchEvent(FileWatchEvent),
+    DnsQuery(DnsQueryResponse),
+    DnsCacheStats(DnsCacheStats),
+    ReverseLookup(ReverseLookupResponse),
+}
+
+/// Implemented for the messages sent with the [`ProtocolCodec`].
+///
+/// Allows the codec to switch the [`Compression`] exactly after the message that negotiates it,
+/// separately for each direction. This way every hop that uses the codec (e.g. the external proxy)
+/// stays in sync with its peers.
+pub trait SwitchesCompression {
+    /// Returns the [`Compression`] of the messages sent after this one, if this message changes
+    /// it.
+    fn switches_compression(&self) -> Option<Compression>;
+}
+
+impl SwitchesCompression for ClientMessage {
+    fn switches_compression(&self) -> Option<Compression> {
+        match self {
+            Self::SwitchCompression(compression) => Some(*compression),
+            _ => None,
+        }
+    }
+}
+
+impl SwitchesCompression for DaemonMessage {
+    fn switches_compression(&self) -> Option<Compression> {
+        match self {
+            Self::SwitchCompressionResponse(compression) => Some(*compression),
+            _ => None,
+        }
+    }
 }
 
 pub struct ProtocolCodec<I, O> {
     config: bincode::config::Configuration,
+    /// [`Compression`] of the incoming messages.
+    incoming_compression: Compression,
+    /// [`Compression`] of the outgoing messages.
+    outgoing_compression: Compression,
     /// Phantom fields to make this struct generic over message types.
     _phantom_incoming_message: PhantomData<I>,
     _phantom_outgoing_message: PhantomData<O>,
@@ -170,40 +300,149 @@ impl<I, O> Default for ProtocolCodec<I, O> {
     fn default() -> Self {
         Self {
             config: bincode::config::standard(),
+            incoming_compression: Default::default(),
+            outgoing_compression: Default::default(),
             _phantom_incoming_message: Default::default(),
             _phantom_outgoing_message: Default::default(),
         }
     }
 }
 
-impl<I: bincode::Decode, O> Decoder for ProtocolCodec<I, O> {
-    type Item = I;
-    type Error = io::Error;
+impl<I, O> ProtocolCodec<I, O> {
+    /// With [`Compression::Zstd`], only messages at least this large (encoded) are compressed.
+    pub const COMPRESSION_THRESHOLD: usize = 512;
 
-    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+    /// With [`Compression::Zstd`], each message is sent in a frame: the frame kind
+    /// ([`Self::FRAME_RAW`] or [`Self::FRAME_ZSTD`]) and the length of the payload
+    /// as big endian `u32`.
+    const FRAME_HEADER_LEN: usize = 5;
+
+    /// Frame with a plain encoded message.
+    const FRAME_RAW: u8 = 0;
+
+    /// Frame with a zstd compressed encoded message.
+    const FRAME_ZSTD: u8 = 1;
+
+    /// Upper bound for the frame payload and for the decompressed message, so that a peer
+    /// can't make us allocate an arbitrary amount of memory.
+    pub const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024;
+
+    fn invalid_data<E: ToString>(error: E) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+    }
+}
+
+impl<I: bincode::Decode, O> ProtocolCodec<I, O> {
+    /// Decodes a message sent with [`Compression::None`].
+    fn decode_plain(&self, src: &mut BytesMut) -> io::Result<Option<I>> {
         match bincode::decode_from_slice(&src[..], self.config) {
             Ok((message, read)) => {
                 src.advance(read);
                 Ok(Some(message))
             }
             Err(DecodeError::UnexpectedEnd { .. }) => Ok(None),
-            Err(err) => Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+            Err(err) => Err(Self::invalid_data(err)),
+        }
+    }
+
+    /// Decodes a message sent with [`Compression::Zstd`].
+    fn decode_frame(&self, src: &mut BytesMut) -> io::Result<Option<I>> {
+        let Some(mut header) = src.get(..Self::FRAME_HEADER_LEN) else {
+            return Ok(None);
+        };
+        let kind = header.get_u8();
+        let payload_len = header.get_u32() as usize;
+        if payload_len > Self::MAX_MESSAGE_LEN {
+            return Err(Self::invalid_data(format!(
+                "frame length {payload_len} exceeds the maximum of {}",
+                Self::MAX_MESSAGE_LEN
+            )));
+        }
+        let frame_len = Self::FRAME_HEADER_LEN + payload_len;
+
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        let mut payload = src.split_to(frame_len);
+        payload.advance(Self::FRAME_HEADER_LEN);
+
+        let message = match kind {
+            Self::FRAME_RAW => bincode::decode_from_slice(&payload, self.config),
+            Self::FRAME_ZSTD => {
+                let decompressed = zstd::bulk::decompress(&payload, Self::MAX_MESSAGE_LEN)
+                    .map_err(Self::invalid_data)?;
+                bincode::decode_from_slice(&decompressed, self.config)
+            }
+            other => return Err(Self::invalid_data(format!("unknown frame kind {other}"))),
+        };
+
+        message
+            .map(|(message, _)| Some(message))
+            .map_err(Self::invalid_data)
+    }
+}
+
+impl<I: bincode::Decode + SwitchesCompression, O> Decoder for ProtocolCodec<I, O> {
+    type Item = I;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        let message = match self.incoming_compression {
+            Compression::None => self.decode_plain(src)?,
+            Compression::Zstd => self.decode_frame(src)?,
+        };
+
+        if let Some(compression) = message.as_ref().and_then(I::switches_compression) {
+            self.incoming_compression = compression;
         }
+
+        Ok(message)
     }
 }
 
-impl<I, O: bincode::Encode> Encoder<O> for ProtocolCodec<I, O> {
+impl<I, O: bincode::Encode + SwitchesCompression> Encoder<O> for ProtocolCodec<I, O> {
     type Error = io::Error;
 
     fn encode(&mut self, msg: O, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let switch_to = msg.switches_compression();
+
         let encoded = match bincode::encode_to_vec(msg, self.config) {
             Ok(encoded) => encoded,
             Err(err) => {
-                return Err(io::Error::new(io::ErrorKind::Other, err.to_string()));
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string()));
             }
         };
-        dst.reserve(encoded.len());
-        dst.put(&encoded[..]);
+
+        match self.outgoing_compression {
+            Compression::None => {
+                dst.reserve(encoded.len());
+                dst.put(&encoded[..]);
+            }
+
+            Compression::Zstd => {
+                let compressed = (encoded.len() >= Self::COMPRESSION_THRESHOLD)
+                    .then(|| zstd::bulk::compress(&encoded, zstd::DEFAULT_COMPRESSION_LEVEL))
+                    .transpose()?
+                    .filter(|compressed| compressed.len() < encoded.len());
+
+                let (kind, payload) = match &compressed {
+                    Some(compressed) => (Self::FRAME_ZSTD, compressed),
+                    None => (Self::FRAME_RAW, &encoded),
+                };
+                let payload_len = u32::try_from(payload.len()).map_err(Self::invalid_data)?;
+
+                dst.reserve(Self::FRAME_HEADER_LEN + payload.len());
+                dst.put_u8(kind);
+                dst.put_u32(payload_len);
+                dst.put(&payload[..]);
+            }
+        }
+
+        if let Some(compression) = switch_to {
+            self.outgoing_compression = compression;
+        }
 
         Ok(())
     }
@@ -251,6 +490,49 @@ mod tests {
         assert!(buf.is_empty());
     }
 
+    /// Messages after [`ClientMessage::SwitchCompression`] and
+    /// [`DaemonMessage::SwitchCompressionResponse`] are framed, and the large ones are compressed.
+    #[test]
+    fn switch_compression() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec = DaemonCodec::default();
+        let mut buf = BytesMut::new();
+
+        let small = DaemonMessage::Tcp(DaemonTcp::Data(TcpData {
+            connection_id: 1,
+            bytes: vec![1, 2, 3],
+        }));
+        let large = DaemonMessage::Tcp(DaemonTcp::Data(TcpData {
+            connection_id: 1,
+            bytes: vec![7; 64 * 1024],
+        }));
+
+        let switch = ClientMessage::SwitchCompression(Compression::Zstd);
+        client_codec.encode(switch.clone(), &mut buf).unwrap();
+        assert_eq!(daemon_codec.decode(&mut buf).unwrap().unwrap(), switch);
+
+        let response = DaemonMessage::SwitchCompressionResponse(Compression::Zstd);
+        daemon_codec.encode(response.clone(), &mut buf).unwrap();
+        assert_eq!(client_codec.decode(&mut buf).unwrap().unwrap(), response);
+
+        daemon_codec.encode(small.clone(), &mut buf).unwrap();
+        daemon_codec.encode(large.clone(), &mut buf).unwrap();
+        assert!(buf.len() < 1024, "large message was not compressed");
+
+        // Frames split at an arbitrary point are decoded once complete.
+        let mut rest = buf.split_off(buf.len() - 3);
+        assert_eq!(client_codec.decode(&mut buf).unwrap().unwrap(), small);
+        assert!(client_codec.decode(&mut buf).unwrap().is_none());
+        buf.unsplit(rest.split());
+        assert_eq!(client_codec.decode(&mut buf).unwrap().unwrap(), large);
+        assert!(buf.is_empty());
+
+        let msg = ClientMessage::Tcp(LayerTcp::PortSubscribe(1));
+        client_codec.encode(msg.clone(), &mut buf).unwrap();
+        assert_eq!(daemon_codec.decode(&mut buf).unwrap().unwrap(), msg);
+        assert!(buf.is_empty());
+    }
+
     #[test]
     fn decode_client_invalid_data() {
         let mut codec = ClientCodec::default();
@@ -260,7 +542,7 @@ mod tests {
         let res = codec.decode(&mut buf);
         match res {
             Ok(_) => panic!("Should have failed"),
-            Err(err) => assert_eq!(err.kind(), io::ErrorKind::Other),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
         }
     }
 
@@ -282,7 +564,32 @@ mod tests {
         let res = codec.decode(&mut buf);
         match res {
             Ok(_) => panic!("Should have failed"),
-            Err(err) => assert_eq!(err.kind(), io::ErrorKind::Other),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
         }
     }
+
+    /// Frames and decompressed messages above [`ProtocolCodec::MAX_MESSAGE_LEN`] are rejected
+    /// before we allocate for them.
+    #[test]
+    fn decode_frame_too_large() {
+        let mut codec = ClientCodec {
+            incoming_compression: Compression::Zstd,
+            ..Default::default()
+        };
+
+        let mut buf = BytesMut::new();
+        buf.put_u8(ClientCodec::FRAME_RAW);
+        buf.put_u32(ClientCodec::MAX_MESSAGE_LEN as u32 + 1);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(buf.capacity() < ClientCodec::MAX_MESSAGE_LEN);
+
+        let bomb = zstd::bulk::compress(&vec![0; ClientCodec::MAX_MESSAGE_LEN + 1], 1).unwrap();
+        let mut buf = BytesMut::new();
+        buf.put_u8(ClientCodec::FRAME_ZSTD);
+        buf.put_u32(bomb.len() as u32);
+        buf.put(&bomb[..]);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
 }
@@ -1,12 +1,38 @@
 extern crate alloc;
 use core::ops::Deref;
-use std::net::IpAddr;
+use std::{net::IpAddr, sync::LazyLock};
 
 use bincode::{Decode, Encode};
-use hickory_resolver::{lookup_ip::LookupIp, proto::rr::resource::RecordParts};
+use hickory_resolver::{
+    lookup::Lookup,
+    lookup_ip::LookupIp,
+    proto::{
+        error::{ProtoError, ProtoErrorKind},
+        rr::{resource::RecordParts, RData, Record, RecordType},
+        serialize::binary::{BinDecoder, BinEncodable, Restrict},
+    },
+};
+use semver::VersionReq;
 
 use crate::RemoteResult;
 
+/// Minimal mirrord-protocol version that allows [`GetAddrInfoRequestV2`].
+pub static ADDRINFO_V2_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.13.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`DnsQueryRequest`].
+pub static DNS_QUERY_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.20.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::GetDnsCacheStats`](crate::ClientMessage::GetDnsCacheStats).
+pub static DNS_CACHE_STATS_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.21.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ReverseLookupRequest`].
+pub static REVERSE_LOOKUP_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.22.0".parse().expect("Bad Identifier"));
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub struct LookupRecord {
     pub name: String,
@@ -16,6 +42,24 @@ pub struct LookupRecord {
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub struct DnsLookup(pub Vec<LookupRecord>);
 
+/// Counters of a DNS cache, answers to
+/// [`ClientMessage::GetDnsCacheStats`](crate::ClientMessage::GetDnsCacheStats).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct DnsCacheStats {
+    /// Requests answered from the cache.
+    pub hits: u64,
+    /// Requests that had to be resolved.
+    pub misses: u64,
+}
+
+impl DnsCacheStats {
+    /// Percentage of the requests answered from the cache, [`None`] if there were no requests.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f64 * 100.0 / total as f64)
+    }
+}
+
 impl From<LookupIp> for DnsLookup {
     fn from(lookup_ip: LookupIp) -> Self {
         let lookup_records = lookup_ip
@@ -75,3 +119,169 @@ impl Deref for GetAddrInfoResponse {
 pub struct GetAddrInfoRequest {
     pub node: String,
 }
+
+/// Address families that should be resolved for a [`GetAddrInfoRequestV2`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub enum AddressFamily {
+    /// Only IPv4 addresses, the behavior of [`GetAddrInfoRequest`].
+    #[default]
+    Ipv4Only,
+    /// Only IPv6 addresses.
+    Ipv6Only,
+    /// Both IPv4 and IPv6 addresses.
+    Both,
+    /// IPv4 addresses, or IPv6 addresses if the node has no IPv4 address.
+    Any,
+}
+
+/// Triggered by the `mirrord-layer` hook of `getaddrinfo_detour`.
+///
+/// Unlike [`GetAddrInfoRequest`], allows resolving IPv6 addresses.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct GetAddrInfoRequestV2 {
+    pub node: String,
+    pub family: AddressFamily,
+}
+
+impl From<GetAddrInfoRequest> for GetAddrInfoRequestV2 {
+    fn from(GetAddrInfoRequest { node }: GetAddrInfoRequest) -> Self {
+        Self {
+            node,
+            family: AddressFamily::Ipv4Only,
+        }
+    }
+}
+
+impl From<GetAddrInfoRequestV2> for GetAddrInfoRequest {
+    fn from(GetAddrInfoRequestV2 { node, .. }: GetAddrInfoRequestV2) -> Self {
+        Self { node }
+    }
+}
+
+/// Triggered by the `mirrord-layer` hooks of `res_query` and friends, and by the DNS queries sent
+/// to port `53`.
+///
+/// Unlike [`GetAddrInfoRequestV2`], asks for records of any type, e.g. `SRV` or `TXT`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct DnsQueryRequest {
+    /// Queried name, absolute if it ends with a dot, otherwise subject to the search domains of
+    /// the remote `resolv.conf`.
+    pub name: String,
+    /// Numeric type of the queried records, e.g. `33` for `SRV`.
+    pub record_type: u16,
+}
+
+/// A resource record answering a [`DnsQueryRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct DnsRecord {
+    pub name: String,
+    /// Numeric type of the record, may differ from the queried one, e.g. for `CNAME`s.
+    pub record_type: u16,
+    pub ttl: u32,
+    /// Record data in the DNS wire format.
+    pub rdata: Vec<u8>,
+}
+
+impl DnsRecord {
+    /// Converts the records of a [`Lookup`], skipping the ones that can't be encoded.
+    pub fn from_lookup(lookup: &Lookup) -> Vec<Self> {
+        lookup
+            .records()
+            .iter()
+            .filter_map(|record| Self::from_record(record).ok())
+            .collect()
+    }
+
+    pub fn from_record(record: &Record) -> Result<Self, ProtoError> {
+        let rdata = record
+            .data()
+            .ok_or_else(|| ProtoError::from(ProtoErrorKind::Message("record without data")))?
+            .to_bytes()?;
+
+        Ok(Self {
+            name: record.name().to_string(),
+            record_type: record.record_type().into(),
+            ttl: record.ttl(),
+            rdata,
+        })
+    }
+
+    /// Decodes the [`DnsRecord::rdata`], so that the record can be put in a DNS message.
+    pub fn into_record(self) -> Result<Record, ProtoError> {
+        let length = u16::try_from(self.rdata.len())
+            .map_err(|_| ProtoError::from(ProtoErrorKind::Message("record data too long")))?;
+        let rdata = RData::read(
+            &mut BinDecoder::new(&self.rdata),
+            RecordType::from(self.record_type),
+            Restrict::new(length),
+        )?;
+
+        Ok(Record::from_rdata(self.name.parse()?, self.ttl, rdata))
+    }
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct DnsQueryResponse(pub RemoteResult<Vec<DnsRecord>>);
+
+/// Triggered by the `mirrord-layer` hooks of `getnameinfo` and `gethostbyaddr`.
+///
+/// Resolves the names of the given address from its `PTR` records.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct ReverseLookupRequest {
+    pub ip: IpAddr,
+}
+
+/// Names of the address from a [`ReverseLookupRequest`], fully qualified.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct ReverseLookupResponse(pub RemoteResult<Vec<String>>);
+
+impl Deref for DnsQueryResponse {
+    type Target = RemoteResult<Vec<DnsRecord>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for ReverseLookupResponse {
+    type Target = RemoteResult<Vec<String>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hickory_resolver::proto::rr::rdata::{SRV, TXT};
+
+    use super::*;
+
+    #[test]
+    fn record_wire_format() {
+        let records = [
+            Record::from_rdata(
+                "_mongodb._tcp.cluster.local.".parse().unwrap(),
+                30,
+                RData::SRV(SRV::new(
+                    0,
+                    5,
+                    27017,
+                    "db-0.cluster.local.".parse().unwrap(),
+                )),
+            ),
+            Record::from_rdata(
+                "config.cluster.local.".parse().unwrap(),
+                60,
+                RData::TXT(TXT::new(vec!["replicaSet=rs0".to_string()])),
+            ),
+        ];
+
+        for record in records {
+            let converted = DnsRecord::from_record(&record).unwrap();
+            assert_eq!(converted.record_type, u16::from(record.record_type()));
+
+            assert_eq!(converted.into_record().unwrap(), record);
+        }
+    }
+}
@@ -64,6 +64,16 @@ pub enum ResponseError {
 
     #[error("Failed stripping path with `{0}`!")]
     StripPrefix(String),
+
+    /// The connection with the agent was re-established while the operation was in progress, so
+    /// its result is unknown.
+    #[error("Connection with the agent was lost before the operation completed!")]
+    AgentReconnected,
+
+    /// The remote resource was allocated by a previous agent connection, and it did not survive
+    /// the reconnection.
+    #[error("Remote resource `{0}` was lost when the connection with the agent was re-established!")]
+    StaleResource(u64),
 }
 
 impl From<StripPrefixError> for ResponseError {
@@ -107,6 +117,12 @@ impl fmt::Display for BlockedAction {
                     "Stealing traffic from port {port} with http request filter: {filter}"
                 )
             }
+            BlockedAction::Steal(StealType::FilteredHttpPassthrough(port, filter)) => {
+                write!(
+                    f,
+                    "Stealing traffic from port {port} with http request filter: {filter}"
+                )
+            }
         }
     }
 }
@@ -18,6 +18,23 @@ use semver::VersionReq;
 pub static READDIR_BATCH_VERSION: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.9.0".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows the requests that modify the remote file system
+/// structure and metadata, e.g. [`MakeDirRequest`], [`RenameRequest`] or [`ChmodRequest`].
+pub static FS_MUTATIONS_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.17.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`AddWatchRequest`] and [`RemoveWatchRequest`].
+pub static WATCH_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.18.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`StatFsRequest`].
+pub static STATFS_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.19.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`XstatFsRequestV2`] and [`StatFsRequestV2`].
+pub static STATFS_V2_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.23.0".parse().expect("Bad Identifier"));
+
 /// Internal version of Metadata across operating system (macOS, Linux)
 /// Only mutual attributes
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq, Default)]
@@ -104,6 +121,53 @@ impl From<Statfs> for FsMetadataInternal {
     }
 }
 
+/// Like [`FsMetadataInternal`], with the maximum length of file names.
+#[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq, Default)]
+pub struct FsMetadataInternalV2 {
+    /// f_type
+    pub filesystem_type: i64,
+    /// f_bsize
+    pub block_size: i64,
+    /// f_blocks
+    pub blocks: u64,
+    /// f_bfree
+    pub blocks_free: u64,
+    /// f_bavail
+    pub blocks_available: u64,
+    /// f_files
+    pub files: u64,
+    /// f_ffree
+    pub files_free: u64,
+    /// f_namelen
+    pub name_len: u64,
+}
+
+/// Used with agents that don't match [`STATFS_V2_VERSION`], the name length is unknown then.
+impl From<FsMetadataInternal> for FsMetadataInternalV2 {
+    fn from(metadata: FsMetadataInternal) -> Self {
+        FsMetadataInternalV2 {
+            filesystem_type: metadata.filesystem_type,
+            block_size: metadata.block_size,
+            blocks: metadata.blocks,
+            blocks_free: metadata.blocks_free,
+            blocks_available: metadata.blocks_available,
+            files: metadata.files,
+            files_free: metadata.files_free,
+            name_len: 0,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<Statfs> for FsMetadataInternalV2 {
+    fn from(stat: Statfs) -> Self {
+        FsMetadataInternalV2 {
+            name_len: u64::try_from(stat.maximum_name_length()).unwrap_or_default(),
+            ..FsMetadataInternal::from(stat).into()
+        }
+    }
+}
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub struct DirEntryInternal {
     pub inode: u64,
@@ -367,6 +431,28 @@ pub struct XstatFsRequest {
     pub fd: u64,
 }
 
+/// Gets the metadata of the file system that contains `path`, like `statfs`.
+///
+/// Answered with [`FileResponse::XstatFs`](crate::FileResponse::XstatFs).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct StatFsRequest {
+    pub path: PathBuf,
+}
+
+/// Like [`XstatFsRequest`], answered with
+/// [`FileResponse::XstatFsV2`](crate::FileResponse::XstatFsV2).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct XstatFsRequestV2 {
+    pub fd: u64,
+}
+
+/// Like [`StatFsRequest`], answered with
+/// [`FileResponse::XstatFsV2`](crate::FileResponse::XstatFsV2).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct StatFsRequestV2 {
+    pub path: PathBuf,
+}
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub struct XstatResponse {
     pub metadata: MetadataInternal,
@@ -377,6 +463,19 @@ pub struct XstatFsResponse {
     pub metadata: FsMetadataInternal,
 }
 
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct XstatFsResponseV2 {
+    pub metadata: FsMetadataInternalV2,
+}
+
+impl From<XstatFsResponse> for XstatFsResponseV2 {
+    fn from(response: XstatFsResponse) -> Self {
+        XstatFsResponseV2 {
+            metadata: response.metadata.into(),
+        }
+    }
+}
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub struct FdOpenDirRequest {
     pub remote_fd: u64,
@@ -434,3 +533,135 @@ pub struct GetDEnts64Response {
     pub entries: Vec<DirEntryInternal>,
     pub result_size: u64,
 }
+
+/// Creates a new directory at `path`, with permissions `mode` (before the umask is applied).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct MakeDirRequest {
+    pub path: PathBuf,
+    pub mode: u32,
+}
+
+/// Removes the empty directory at `path`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct RemoveDirRequest {
+    pub path: PathBuf,
+}
+
+/// Removes the file (or symbolic link) at `path`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct UnlinkRequest {
+    pub path: PathBuf,
+}
+
+/// Moves `old_path` to `new_path`, replacing `new_path` if it exists.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct RenameRequest {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+}
+
+/// Changes the permissions of the file at `path`, following symbolic links.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct ChmodRequest {
+    pub path: PathBuf,
+    pub mode: u32,
+}
+
+/// Changes the permissions of the open file `fd`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct FchmodRequest {
+    pub fd: u64,
+    pub mode: u32,
+}
+
+/// Changes the owner and group of the file at `path`.
+///
+/// [`None`] leaves the respective id unchanged.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct ChownRequest {
+    pub path: PathBuf,
+    pub user_id: Option<u32>,
+    pub group_id: Option<u32>,
+    /// `false` changes the symbolic link itself, like `lchown`.
+    pub follow_symlink: bool,
+}
+
+/// Truncates (or extends) the file at `path` to exactly `length` bytes.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct TruncateRequest {
+    pub path: PathBuf,
+    pub length: u64,
+}
+
+/// Truncates (or extends) the open file `fd` to exactly `length` bytes.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct FtruncateRequest {
+    pub fd: u64,
+    pub length: u64,
+}
+
+/// Creates a symbolic link at `link_path`, pointing to `target`.
+///
+/// `target` is stored as is, it is not resolved by the agent.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct SymlinkRequest {
+    pub target: PathBuf,
+    pub link_path: PathBuf,
+}
+
+/// New value for one of the timestamps in [`UtimesRequest`], mirrors the `UTIME_*` special values
+/// of `utimensat`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileTimeUpdate {
+    /// Leave the timestamp unchanged (`UTIME_OMIT`).
+    Omit,
+    /// Set the timestamp to the current time of the remote host (`UTIME_NOW`).
+    Now,
+    /// Set the timestamp to the given time since the epoch.
+    Set { seconds: i64, nanoseconds: u32 },
+}
+
+/// Changes the access and modification times of the file at `path`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct UtimesRequest {
+    pub path: PathBuf,
+    pub access_time: FileTimeUpdate,
+    pub modification_time: FileTimeUpdate,
+    /// `false` changes the symbolic link itself, like `AT_SYMLINK_NOFOLLOW`.
+    pub follow_symlink: bool,
+}
+
+/// Starts watching the remote `path` for the events in `mask`, like `inotify_add_watch`.
+///
+/// `mask` uses the values of the `IN_*` inotify flags. Watching the same file again extends the
+/// mask of the existing watch, and gives the same [`AddWatchResponse::watch_id`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct AddWatchRequest {
+    pub path: PathBuf,
+    pub mask: u32,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AddWatchResponse {
+    pub watch_id: u64,
+}
+
+/// Stops the watch started with [`AddWatchRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RemoveWatchRequest {
+    pub watch_id: u64,
+}
+
+/// An inotify event of a remote watch.
+///
+/// When the watch is removed (explicitly, or because the watched file is gone), the last event
+/// carries `IN_IGNORED`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct FileWatchEvent {
+    pub watch_id: u64,
+    pub mask: u32,
+    /// Connects the `IN_MOVED_FROM` and `IN_MOVED_TO` events of a single rename.
+    pub cookie: u32,
+    /// Name of the file inside of the watched directory that the event is about.
+    pub name: Option<String>,
+}
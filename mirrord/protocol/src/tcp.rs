@@ -157,6 +157,53 @@ pub enum HttpFilter {
         /// Filters to use
         filters: Vec<HttpFilter>,
     },
+    /// Filter by method ("POST")
+    Method(Filter),
+    /// Filter by query string ("tenant=alice")
+    Query(Filter),
+    /// Filter by the beginning of the body
+    Body(BodyFilter),
+}
+
+/// Describes how [`HttpFilter::Body`] inspects the request body.
+///
+/// Only the first `max_size` bytes of the body are ever inspected.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub enum BodyFilter {
+    /// Match the body (converted to a lossy UTF-8 string) against the regex.
+    Regex { filter: Filter, max_size: u32 },
+    /// Parse the body as JSON and match every value selected by the JSONPath `query` against the
+    /// regex. Bodies that are not valid JSON (or were truncated to `max_size`) never match.
+    Json {
+        query: String,
+        filter: Filter,
+        max_size: u32,
+    },
+}
+
+impl BodyFilter {
+    /// Default value for `max_size`, in bytes.
+    pub const DEFAULT_MAX_SIZE: u32 = 64 * 1024;
+
+    pub fn max_size(&self) -> u32 {
+        let (Self::Regex { max_size, .. } | Self::Json { max_size, .. }) = self;
+        *max_size
+    }
+}
+
+impl Display for BodyFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyFilter::Regex { filter, max_size } => {
+                write!(f, "{filter} (first {max_size} bytes)")
+            }
+            BodyFilter::Json {
+                query,
+                filter,
+                max_size,
+            } => write!(f, "{query}={filter} (first {max_size} bytes)"),
+        }
+    }
 }
 
 impl Display for HttpFilter {
@@ -164,6 +211,9 @@ impl Display for HttpFilter {
         match self {
             HttpFilter::Header(filter) => write!(f, "header={filter}"),
             HttpFilter::Path(filter) => write!(f, "path={filter}"),
+            HttpFilter::Method(filter) => write!(f, "method={filter}"),
+            HttpFilter::Query(filter) => write!(f, "query={filter}"),
+            HttpFilter::Body(filter) => write!(f, "body={filter}"),
             HttpFilter::Composite { all, filters } => match all {
                 true => {
                     write!(f, "all of ")?;
@@ -207,12 +257,16 @@ pub enum StealType {
     FilteredHttp(Port, Filter),
     /// Steal HTTP traffic matching a given filter - supporting more than once kind of filter
     FilteredHttpEx(Port, HttpFilter),
+    /// Steal HTTP traffic matching a given filter, sending the requests that the client fails to
+    /// handle (no response or a 5xx response) to their original destination.
+    FilteredHttpPassthrough(Port, HttpFilter),
 }
 
 impl StealType {
     pub fn get_port(&self) -> Port {
         let (StealType::All(port)
         | StealType::FilteredHttpEx(port, ..)
+        | StealType::FilteredHttpPassthrough(port, ..)
         | StealType::FilteredHttp(port, ..)) = self;
         *port
     }
@@ -458,6 +512,15 @@ pub static HTTP_FILTERED_UPGRADE_VERSION: LazyLock<VersionReq> =
 pub static HTTP_COMPOSITE_FILTER_VERSION: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.11.0".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows [`HttpFilter::Method`], [`HttpFilter::Query`]
+/// and [`HttpFilter::Body`].
+pub static HTTP_METHOD_QUERY_BODY_FILTER_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.12.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`StealType::FilteredHttpPassthrough`].
+pub static HTTP_FILTER_PASSTHROUGH_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.15.0".parse().expect("Bad Identifier"));
+
 /// Protocol break - on version 2, please add source port, dest/src IP to the message
 /// so we can avoid losing this information.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
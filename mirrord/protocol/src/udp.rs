@@ -0,0 +1,89 @@
+//! Messages related to the incoming UDP traffic.
+//!
+//! Outgoing UDP traffic is handled with [`crate::outgoing::udp`].
+
+use std::{fmt, net::SocketAddr, sync::LazyLock};
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+use crate::{Port, RemoteResult};
+
+/// Minimal mirrord-protocol version that allows [`LayerUdpIncoming`] and [`DaemonUdpIncoming`].
+pub static UDP_INCOMING_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.14.0".parse().expect("Bad Identifier"));
+
+/// How the agent should handle datagrams incoming to a subscribed port.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum UdpIncomingMode {
+    /// Datagrams are copied to the client, the target still receives them.
+    Mirror,
+    /// Datagrams are redirected to the client, the target does not receive them. The client can
+    /// reply with [`LayerUdpIncoming::Reply`].
+    Steal,
+}
+
+/// Subscription for the datagrams incoming to the remote port.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UdpPortSubscription {
+    pub port: Port,
+    pub mode: UdpIncomingMode,
+}
+
+/// Datagram received by the target on a subscribed port.
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+pub struct IncomingDatagram {
+    /// Address of the remote peer that sent the datagram.
+    pub source: SocketAddr,
+    /// Address to which the datagram was sent, its port is the subscribed port.
+    pub destination: SocketAddr,
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Debug for IncomingDatagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncomingDatagram")
+            .field("source", &self.source)
+            .field("destination", &self.destination)
+            .field("bytes (length)", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// Reply to an [`IncomingDatagram`] stolen from the given port.
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+pub struct UdpReply {
+    /// Subscribed port, the reply is sent from this port.
+    pub port: Port,
+    /// Address of the remote peer that should receive the reply.
+    pub peer: SocketAddr,
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Debug for UdpReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpReply")
+            .field("port", &self.port)
+            .field("peer", &self.peer)
+            .field("bytes (length)", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// Messages related to incoming UDP traffic from client.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub enum LayerUdpIncoming {
+    /// Answered with [`DaemonUdpIncoming::SubscribeResult`].
+    PortSubscribe(UdpPortSubscription),
+    PortUnsubscribe(Port),
+    /// Only valid for ports subscribed with [`UdpIncomingMode::Steal`].
+    Reply(UdpReply),
+}
+
+/// Messages related to incoming UDP traffic from server.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub enum DaemonUdpIncoming {
+    /// Result of [`LayerUdpIncoming::PortSubscribe`] for the given port.
+    SubscribeResult(Port, RemoteResult<()>),
+    Datagram(IncomingDatagram),
+}